@@ -0,0 +1,32 @@
+//! Measures how long each example grammar takes to match its corpus.
+//!
+//! Run with `cargo bench --features test-grammars`.
+
+use std::time::{Duration, Instant};
+
+use almora::test_grammars::{self, corpus_for, matches_fully, GRAMMARS};
+
+const ITERATIONS: u32 = 200;
+
+fn main() {
+    for name in GRAMMARS {
+        let grammar = test_grammars::grammar(name).unwrap();
+
+        for file in corpus_for(name) {
+            let start = Instant::now();
+            for _ in 0..ITERATIONS {
+                // Black box to avoid having the result optimized away
+                std::hint::black_box(matches_fully(&grammar, file.source).unwrap());
+            }
+            let elapsed: Duration = start.elapsed() / ITERATIONS;
+
+            println!(
+                "{:>12} {:<28} {:>6} chars {:>10.2?}/iter",
+                name,
+                file.name,
+                file.source.chars().count(),
+                elapsed
+            );
+        }
+    }
+}
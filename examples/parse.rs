@@ -0,0 +1,43 @@
+//! Matches a file against one of the example grammars.
+//!
+//! Usage: `cargo run --features test-grammars --example parse -- <grammar> <file>`
+
+use std::{env, fs, process};
+
+use almora::test_grammars::{self, matches_fully, GRAMMARS};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("Usage: {} <{}> <file>", args[0], GRAMMARS.join("|"));
+        process::exit(2);
+    }
+
+    let grammar = match test_grammars::grammar(&args[1]) {
+        Some(grammar) => grammar,
+        None => {
+            eprintln!("Unknown grammar `{}`. Expected one of: {}", args[1], GRAMMARS.join(", "));
+            process::exit(2);
+        }
+    };
+
+    let source = match fs::read_to_string(&args[2]) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("Could not read {}: {}", args[2], err);
+            process::exit(2);
+        }
+    };
+
+    match matches_fully(&grammar, &source) {
+        Ok(true) => println!("{}: valid {}", args[2], args[1]),
+        Ok(false) => {
+            println!("{}: invalid {}", args[2], args[1]);
+            process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("{}: {}", args[2], err);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,485 @@
+use crate::{
+    almora::{doc::doc_comment, CompileError, Diagnostic},
+    parser_lib::{Location, LocationPolicy, ParseNode, Span},
+};
+
+use super::{
+    BinaryOp, Block, Expr, ExprKind, Function, Ident, Import, Item, Literal, Param, Program, Stmt, StmtKind, StringPart,
+    UnaryOp,
+};
+
+/// Builds the AST of a program from its parse tree, given by the almora grammar.
+///
+/// The tree must come from the grammar: a node that it can't produce makes the lowering panic.
+pub fn lower(tree: &ParseNode, source: &str) -> Result<Program, CompileError> {
+    let lowerer = Lowerer { source };
+    // The doc comments of a function are in the text since the previous item
+    let mut previous_end = tree.span().start().byte_offset();
+    let items = tree
+        .children()
+        .iter()
+        .filter(|node| node.rule() != UNTERMINATED_STMT)
+        .map(|node| {
+            let before = &source[previous_end..node.span().start().byte_offset()];
+            previous_end = node.span().end().byte_offset();
+            match node.rule() {
+                "import_stmt" => Ok(Item::Import(Import {
+                    module: lowerer.ident(required(node, "module_path")),
+                    span: node.span().clone(),
+                })),
+                "function" => lowerer.function(node, doc_comment(before)).map(Item::Function),
+                _ => lowerer.stmt(node).map(Item::Statement),
+            }
+        })
+        .collect::<Result<_, _>>()?;
+
+    let mut errors = Vec::new();
+    unterminated_strings(tree, &mut errors);
+    // An unterminated block comment goes until the end of the input, so it is after the last item
+    let trailing = &source[previous_end..tree.span().end().byte_offset()];
+    if let Some(offset) = unterminated_comment(trailing) {
+        let mut start = *tree.children().last().map_or(tree.span().start(), |item| item.span().end());
+        trailing[..offset].chars().for_each(|c| start.increment_for(c, LocationPolicy::default()));
+        errors.push(Diagnostic::error("unterminated block comment", delimiter(start, "/*")));
+    }
+    Ok(Program { items, span: tree.span().clone(), errors })
+}
+
+/// Rule of the statements left incomplete by an unterminated string. They are skipped, and only the string is reported.
+const UNTERMINATED_STMT: &str = "unterminated_stmt";
+
+/// Reports the strings of the tree that were closed at the end of their line, at their opening quote.
+fn unterminated_strings(node: &ParseNode, errors: &mut Vec<Diagnostic>) {
+    if node.rule() == "unterminated_string" {
+        errors.push(Diagnostic::error("unterminated string", delimiter(*node.span().start(), "\"")));
+    }
+    for child in node.children() {
+        unterminated_strings(child, errors);
+    }
+}
+
+/// Returns the byte offset of the block comment without its `*/` in the text between items, if any.
+///
+/// The text only contains whitespace and comments, since the grammar ignores it.
+fn unterminated_comment(trivia: &str) -> Option<usize> {
+    let mut offset = 0;
+    loop {
+        let rest = &trivia[offset..];
+        let trimmed = rest.trim_start();
+        offset += rest.len() - trimmed.len();
+        if trimmed.starts_with("//") {
+            offset += trimmed.find('\n').map_or(trimmed.len(), |end| end + 1);
+        } else if let Some(comment) = trimmed.strip_prefix("/*") {
+            match comment.find("*/") {
+                Some(end) => offset += end + 4,
+                None => return Some(offset),
+            }
+        } else {
+            return None;
+        }
+    }
+}
+
+/// Returns the span of the delimiter at the given location.
+fn delimiter(start: Location, delimiter: &str) -> Span {
+    let mut end = start;
+    delimiter.chars().for_each(|c| end.increment_for(c, LocationPolicy::default()));
+    Span::new(start, end)
+}
+
+struct Lowerer<'a> {
+    source: &'a str,
+}
+
+impl Lowerer<'_> {
+    fn function(&self, node: &ParseNode, doc: Option<String>) -> Result<Function, CompileError> {
+        let params = required(node, "parameters")
+            .children()
+            .iter()
+            .map(|param| Param {
+                name: self.ident(required(param, "identifier")),
+                ty: self.ident(required(param, "type")),
+                span: param.span().clone(),
+            })
+            .collect();
+
+        Ok(Function {
+            doc,
+            name: self.ident(required(node, "identifier")),
+            params,
+            return_type: child(node, "type").map(|ty| self.ident(ty)),
+            body: self.block(required(node, "block"))?,
+            span: node.span().clone(),
+        })
+    }
+
+    fn block(&self, node: &ParseNode) -> Result<Block, CompileError> {
+        let stmts = node
+            .children()
+            .iter()
+            .filter(|stmt| stmt.rule() != UNTERMINATED_STMT)
+            .map(|stmt| self.stmt(stmt))
+            .collect::<Result<_, _>>()?;
+        Ok(Block { stmts, span: node.span().clone() })
+    }
+
+    fn stmt(&self, node: &ParseNode) -> Result<Stmt, CompileError> {
+        let expr = || self.expr(required(node, "expr"));
+        let kind = match node.rule() {
+            "let_stmt" => StmtKind::Let {
+                name: self.ident(required(node, "identifier")),
+                ty: child(node, "type").map(|ty| self.ident(ty)),
+                value: expr()?,
+            },
+            "assign_stmt" => StmtKind::Assign { target: self.ident(required(node, "identifier")), value: expr()? },
+            "if_stmt" => {
+                // The else branch is the node after the block, if any
+                let else_branch = match node.children().get(2) {
+                    Some(branch) => Some(Box::new(self.stmt(branch)?)),
+                    None => None,
+                };
+                StmtKind::If { condition: expr()?, then_branch: self.block(required(node, "block"))?, else_branch }
+            }
+            "while_stmt" => StmtKind::While { condition: expr()?, body: self.block(required(node, "block"))? },
+            "for_stmt" => StmtKind::For {
+                variable: self.ident(required(node, "identifier")),
+                iterable: expr()?,
+                body: self.block(required(node, "block"))?,
+            },
+            "return_stmt" => StmtKind::Return(child(node, "expr").map(|value| self.expr(value)).transpose()?),
+            "block" => StmtKind::Block(self.block(node)?),
+            "expr_stmt" => StmtKind::Expr(expr()?),
+            rule => panic!("unexpected `{}` node in a block", rule),
+        };
+        Ok(Stmt { kind, span: node.span().clone() })
+    }
+
+    fn expr(&self, node: &ParseNode) -> Result<Expr, CompileError> {
+        let span = node.span().clone();
+        let text = node.text(self.source);
+        let kind = match node.rule() {
+            "expr" => return self.expr(&node.children()[0]),
+            // The parentheses are kept in the span, so that the expressions containing it cover them
+            "group" => return Ok(Expr { span, ..self.expr(&node.children()[0])? }),
+            "integer" => ExprKind::Literal(Literal::Integer(
+                decode_integer(text).ok_or_else(|| CompileError::LiteralOverflow(span.clone()))?,
+            )),
+            "float" => ExprKind::Literal(Literal::Float(
+                decode_float(text).ok_or_else(|| CompileError::LiteralOverflow(span.clone()))?,
+            )),
+            "string" | "unterminated_string" => return self.string(node),
+            "boolean" => ExprKind::Literal(Literal::Boolean(text == "true")),
+            "identifier" => ExprKind::Identifier(text.to_string()),
+            "unary" => {
+                let op = match required(node, "unary_op").text(self.source) {
+                    "-" => UnaryOp::Neg,
+                    _ => UnaryOp::Not,
+                };
+                ExprKind::Unary { op, operand: Box::new(self.expr(&node.children()[1])?) }
+            }
+            "call" => return self.call(node),
+            _ => return self.binary(node),
+        };
+        Ok(Expr { kind, span })
+    }
+
+    /// Lowers a string literal, or an interpolated string if it has expressions.
+    fn string(&self, node: &ParseNode) -> Result<Expr, CompileError> {
+        let span = node.span().clone();
+        let invalid = || CompileError::InvalidLiteral(span.clone());
+
+        // The text parts are between the quotes and the interpolations
+        let mut parts = Vec::new();
+        let mut start = span.start().byte_offset() + 1;
+        for interpolation in node.children() {
+            let text = &self.source[start..interpolation.span().start().byte_offset()];
+            parts.push(StringPart::Text(decode_string(text).ok_or_else(invalid)?));
+            parts.push(StringPart::Expr(self.expr(required(interpolation, "expr"))?));
+            start = interpolation.span().end().byte_offset();
+        }
+        // An unterminated string has no closing quote
+        let end = match node.rule() {
+            "string" => span.end().byte_offset() - 1,
+            _ => span.end().byte_offset(),
+        };
+        let text = &self.source[start..end];
+        parts.push(StringPart::Text(decode_string(text).ok_or_else(invalid)?));
+        parts.retain(|part| !matches!(part, StringPart::Text(text) if text.is_empty()));
+
+        let kind = match parts.as_slice() {
+            [] => ExprKind::Literal(Literal::String(String::new())),
+            [StringPart::Text(text)] => ExprKind::Literal(Literal::String(text.clone())),
+            _ => ExprKind::Interpolation(parts),
+        };
+        Ok(Expr { kind, span })
+    }
+
+    /// Lowers the calls of a callee, like `f(1)(2)`, the first call being the innermost.
+    fn call(&self, node: &ParseNode) -> Result<Expr, CompileError> {
+        let (callee, calls) = node.children().split_first().expect("a call has a callee");
+        let mut expr = self.expr(callee)?;
+        for arguments in calls {
+            let span = Span::new(*expr.span.start(), *arguments.span().end());
+            let arguments = arguments.children().iter().map(|arg| self.expr(arg)).collect::<Result<_, _>>()?;
+            expr = Expr { kind: ExprKind::Call { callee: Box::new(expr), arguments }, span };
+        }
+        Ok(expr)
+    }
+
+    /// Lowers the operations of a level of precedence, like `a - b + c`. They are grouped from the left.
+    fn binary(&self, node: &ParseNode) -> Result<Expr, CompileError> {
+        let (first, operations) = node.children().split_first().expect("an operation has operands");
+        let mut expr = self.expr(first)?;
+        for operation in operations.chunks(2) {
+            let [op, right] = operation else {
+                panic!("unexpected operator without operand in a `{}` node", node.rule());
+            };
+            let op = BinaryOp::from_symbol(op.text(self.source))
+                .unwrap_or_else(|| panic!("unexpected `{}` node in an expression", node.rule()));
+            let right = self.expr(right)?;
+            let span = Span::new(*expr.span.start(), *right.span.end());
+            expr = Expr { kind: ExprKind::Binary { op, left: Box::new(expr), right: Box::new(right) }, span };
+        }
+        Ok(expr)
+    }
+
+    fn ident(&self, node: &ParseNode) -> Ident {
+        Ident { name: node.text(self.source).to_string(), span: node.span().clone() }
+    }
+}
+
+/// Returns the first child of the node matched by the given rule, if any.
+fn child<'a>(node: &'a ParseNode, rule: &str) -> Option<&'a ParseNode> {
+    node.children().iter().find(|child| child.rule() == rule)
+}
+
+/// Returns the first child of the node matched by the given rule, which the grammar always gives.
+fn required<'a>(node: &'a ParseNode, rule: &str) -> &'a ParseNode {
+    child(node, rule).unwrap_or_else(|| panic!("a `{}` node has no `{}` child", node.rule(), rule))
+}
+
+/// Returns the value of an integer literal, which can be in hexadecimal, octal or binary with a prefix. Returns None if
+/// it doesn't fit in 64 bits.
+fn decode_integer(text: &str) -> Option<i64> {
+    let digits = text.replace('_', "");
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x") => (16, &digits[2..]),
+        Some("0o") => (8, &digits[2..]),
+        Some("0b") => (2, &digits[2..]),
+        _ => (10, digits.as_str()),
+    };
+    i64::from_str_radix(digits, radix).ok()
+}
+
+/// Returns the value of a float literal. Returns None if it is too large to be finite.
+fn decode_float(text: &str) -> Option<f64> {
+    text.replace('_', "").parse().ok().filter(|value: &f64| value.is_finite())
+}
+
+/// Returns the value of a part of a string literal, with the escape sequences replaced. Returns None if a `\u{...}`
+/// escape is not a Unicode scalar value.
+fn decode_string(text: &str) -> Option<String> {
+    let mut value = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => value.push('\n'),
+            Some('t') => value.push('\t'),
+            Some('r') => value.push('\r'),
+            Some('0') => value.push('\0'),
+            Some('u') => {
+                let digits: String = chars.by_ref().skip(1).take_while(|c| *c != '}').collect();
+                value.push(u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32)?);
+            }
+            Some(c) => value.push(c),
+            None => {}
+        }
+    }
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        almora::almora,
+        parser_lib::{Location, StringCharReader},
+    };
+
+    use super::*;
+
+    fn parse(source: &str) -> Result<Program, CompileError> {
+        let grammar = almora::define_grammar::<StringCharReader>();
+        let mut reader = StringCharReader::new(source);
+        let tree = grammar.parse_tree(&Location::beginning(), &mut reader).unwrap().unwrap();
+        lower(&tree, source)
+    }
+
+    /// Returns the expression of the first statement.
+    fn parse_expr(source: &str) -> Expr {
+        match parse(&format!("{};", source)).unwrap().items.remove(0) {
+            Item::Statement(Stmt { kind: StmtKind::Expr(expr), .. }) => expr,
+            item => panic!("expected an expression statement, got {:?}", item),
+        }
+    }
+
+    /// Writes the expression as an S-expression, to compare trees in tests.
+    fn sexp(expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Literal(Literal::String(value)) => format!("{:?}", value),
+            ExprKind::Literal(Literal::Integer(value)) => value.to_string(),
+            ExprKind::Literal(Literal::Float(value)) => format!("{:?}", value),
+            ExprKind::Literal(Literal::Boolean(value)) => value.to_string(),
+            ExprKind::Identifier(name) => name.clone(),
+            ExprKind::Unary { op, operand } => format!("({} {})", op.symbol(), sexp(operand)),
+            ExprKind::Binary { op, left, right } => format!("({} {} {})", op.symbol(), sexp(left), sexp(right)),
+            ExprKind::Call { callee, arguments } => {
+                let arguments: Vec<String> = arguments.iter().map(sexp).collect();
+                format!("(call {} [{}])", sexp(callee), arguments.join(" "))
+            }
+            ExprKind::Interpolation(parts) => {
+                let parts: Vec<String> = parts
+                    .iter()
+                    .map(|part| match part {
+                        StringPart::Text(text) => format!("{:?}", text),
+                        StringPart::Expr(value) => sexp(value),
+                    })
+                    .collect();
+                format!("(format {})", parts.join(" "))
+            }
+        }
+    }
+
+    #[test]
+    fn test_lower_expressions() {
+        assert_eq!(sexp(&parse_expr("1 - 2 + 3 * 4")), "(+ (- 1 2) (* 3 4))");
+        assert_eq!(sexp(&parse_expr("!(a || b) && c != 2.5")), "(&& (! (|| a b)) (!= c 2.5))");
+        assert_eq!(sexp(&parse_expr("-f(1, \"a\\tb\")(true)")), "(- (call (call f [1 \"a\\tb\"]) [true]))");
+        assert_eq!(sexp(&parse_expr(r#""say \"hi\"\\""#)), r#""say \"hi\"\\""#);
+
+        // The spans cover the parentheses
+        let expr = parse_expr("(1 + 2) * (3)");
+        assert_eq!(expr.span.start().index()..expr.span.end().index(), 0..13);
+        let ExprKind::Binary { left, right, .. } = expr.kind else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(left.span.start().index()..left.span.end().index(), 0..7);
+        assert_eq!(right.span.start().index()..right.span.end().index(), 10..13);
+
+        // Numbers can have a base and separators
+        assert_eq!(sexp(&parse_expr("0xff + 0o17 + 0b1_01 + 1_000")), "(+ (+ (+ 255 15) 5) 1000)");
+        assert_eq!(sexp(&parse_expr("0x7FFF_FFFF_FFFF_FFFF")), i64::MAX.to_string());
+        assert_eq!(sexp(&parse_expr("1_5.2_5e-1")), "1.525");
+
+        // Integers must fit in 64 bits, and floats must be finite
+        let overflows = [("99999999999999999999;", 0..20), ("x = 0x1_0000_0000_0000_0000;", 4..27), ("1e309;", 0..5)];
+        for (source, range) in overflows {
+            let err = parse(source).unwrap_err();
+            let CompileError::LiteralOverflow(span) = err else {
+                panic!("expected a literal overflow, got {:?}", err);
+            };
+            assert_eq!(span.start().index()..span.end().index(), range);
+        }
+    }
+
+    #[test]
+    fn test_lower_strings() {
+        assert_eq!(sexp(&parse_expr(r#""\u{48}\u{1F600} \${""#)), r#""H😀 ${""#);
+        assert_eq!(sexp(&parse_expr(r#""""#)), r#""""#);
+        let expr = parse_expr(r#""x = ${x}, sum = ${ a + b }\n""#);
+        assert_eq!(sexp(&expr), r#"(format "x = " x ", sum = " (+ a b) "\n")"#);
+        assert_eq!(sexp(&parse_expr(r#""${x}${"${y}!"}""#)), r#"(format x (format y "!"))"#);
+
+        // The expressions have their spans in the source
+        let ExprKind::Interpolation(parts) = parse_expr(r#""a${bc}""#).kind else {
+            panic!("expected an interpolated string");
+        };
+        let StringPart::Expr(bc) = &parts[1] else {
+            panic!("expected an expression, got {:?}", parts[1]);
+        };
+        assert_eq!(bc.span.start().index()..bc.span.end().index(), 4..6);
+
+        // Escapes must be Unicode scalar values
+        assert!(matches!(parse(r#""\u{D800}";"#), Err(CompileError::InvalidLiteral(_))));
+        assert!(matches!(parse(r#""\u{110000}";"#), Err(CompileError::InvalidLiteral(_))));
+    }
+
+    #[test]
+    fn test_lower_unterminated() {
+        let errors = |program: &Program| program.errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+
+        // The string ends at the end of the line
+        let program = parse("let s = \"a ${b}\n;\nprint(\"end\");").unwrap();
+        assert_eq!(errors(&program), ["1:9: error: unterminated string"]);
+        assert_eq!(program.items.len(), 2);
+        let Item::Statement(Stmt { kind: StmtKind::Let { value, .. }, .. }) = &program.items[0] else {
+            panic!("expected a let statement");
+        };
+        assert_eq!(sexp(value), r#"(format "a " b)"#);
+        assert_eq!(sexp(&parse_expr("\"\n")), r#""""#);
+
+        // The statement missing its `;` is skipped, and the next line is lowered
+        let program = parse("let s = \"abc\nlet y = 2;").unwrap();
+        assert_eq!(errors(&program), ["1:9: error: unterminated string"]);
+        let [Item::Statement(Stmt { kind: StmtKind::Let { name, .. }, .. })] = &program.items[..] else {
+            panic!("expected a let statement");
+        };
+        assert_eq!(name.name, "y");
+        let program = parse("fn f() {\n    g(\"a ${x}\n    h();\n}").unwrap();
+        assert_eq!(errors(&program), ["2:7: error: unterminated string"]);
+        let [Item::Function(function)] = &program.items[..] else {
+            panic!("expected a function");
+        };
+        assert_eq!(function.body.stmts.len(), 1);
+
+        // The block comment ends at the end of the input
+        let program = parse("let x = 1; // one\n  /* two */ /* three\nlet y = 2;").unwrap();
+        assert_eq!(errors(&program), ["2:13: error: unterminated block comment"]);
+        assert_eq!(program.items.len(), 1);
+        assert_eq!(errors(&parse("/*/").unwrap()), ["1:1: error: unterminated block comment"]);
+        assert_eq!(errors(&parse("/* a */ x; /* b */").unwrap()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_lower_statements() {
+        let program = parse("fn f(a: int, b: float) -> int {\n    let x: int = a;\n    if x { return; } else if b { x = 1; } else { }\n}\nwhile c { for i in l { } }\n").unwrap();
+        assert_eq!(program.items.len(), 2);
+
+        let Item::Function(function) = &program.items[0] else {
+            panic!("expected a function");
+        };
+        assert_eq!(function.name.name, "f");
+        let params: Vec<(&str, &str)> = function.params.iter().map(|p| (p.name.name.as_str(), p.ty.name.as_str())).collect();
+        assert_eq!(params, [("a", "int"), ("b", "float")]);
+        assert_eq!(function.return_type.as_ref().map(|ty| ty.name.as_str()), Some("int"));
+        assert_eq!(function.span.start().line()..function.span.end().line(), 1..4);
+
+        let [let_stmt, if_stmt] = &function.body.stmts[..] else {
+            panic!("expected two statements, got {:?}", function.body.stmts);
+        };
+        let StmtKind::Let { name, ty: Some(ty), value } = &let_stmt.kind else {
+            panic!("expected a typed let, got {:?}", let_stmt);
+        };
+        assert_eq!((name.name.as_str(), ty.name.as_str(), sexp(value).as_str()), ("x", "int", "a"));
+
+        let StmtKind::If { then_branch, else_branch: Some(else_branch), .. } = &if_stmt.kind else {
+            panic!("expected an if with an else branch, got {:?}", if_stmt);
+        };
+        assert!(matches!(then_branch.stmts[..], [Stmt { kind: StmtKind::Return(None), .. }]));
+        let StmtKind::If { then_branch, else_branch: Some(last), .. } = &else_branch.kind else {
+            panic!("expected an else if, got {:?}", else_branch);
+        };
+        assert!(matches!(&then_branch.stmts[0].kind, StmtKind::Assign { target, .. } if target.name == "x"));
+        assert!(matches!(&last.kind, StmtKind::Block(block) if block.stmts.is_empty()));
+
+        let Item::Statement(Stmt { kind: StmtKind::While { condition, body }, .. }) = &program.items[1] else {
+            panic!("expected a while loop");
+        };
+        assert_eq!(sexp(condition), "c");
+        assert!(matches!(&body.stmts[0].kind, StmtKind::For { variable, iterable, .. }
+            if variable.name == "i" && sexp(iterable) == "l"));
+    }
+}
@@ -0,0 +1,203 @@
+//! Typed tree of an almora program, built from the parse tree of the grammar by `lower`, and written back as source
+//! by `print`.
+
+mod lower;
+mod printer;
+
+pub use lower::lower;
+pub use printer::{print, print_expr, PrintOptions};
+
+use crate::{almora::Diagnostic, parser_lib::Span};
+
+/// Whole source file: the imports, the functions and the statements run at the top level, in source order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub items: Vec<Item>,
+    pub span: Span,
+    /// Syntax errors that the parser recovered from, like an unterminated string: the program can still be analyzed.
+    pub errors: Vec<Diagnostic>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Import(Import),
+    Function(Function),
+    Statement(Stmt),
+}
+
+/// `import a.b;`, which makes the names defined at the top level of the module `a.b` visible in the program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    /// Name of the module, with its parts separated by dots.
+    pub module: Ident,
+    pub span: Span,
+}
+
+/// Name given in the source, like a variable, a function or a type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+/// Function definition: `fn name(a: int) -> int { ... }`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// Text of the `///` comments right before the function, without the `///`.
+    pub doc: Option<String>,
+    pub name: Ident,
+    pub params: Vec<Param>,
+    /// If None, the function returns nothing.
+    pub return_type: Option<Ident>,
+    pub body: Block,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: Ident,
+    pub ty: Ident,
+    pub span: Span,
+}
+
+/// Statements between braces, which have their own scope.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    /// `let name: ty = value;`
+    Let { name: Ident, ty: Option<Ident>, value: Expr },
+    /// `target = value;`
+    Assign { target: Ident, value: Expr },
+    /// `if condition { ... } else ...`, where the else branch is a block or another `if`.
+    If { condition: Expr, then_branch: Block, else_branch: Option<Box<Stmt>> },
+    While { condition: Expr, body: Block },
+    /// `for variable in iterable { ... }`
+    For { variable: Ident, iterable: Expr, body: Block },
+    Return(Option<Expr>),
+    Block(Block),
+    /// Expression evaluated for its effects, like a call.
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Literal(Literal),
+    Identifier(String),
+    Unary { op: UnaryOp, operand: Box<Expr> },
+    Binary { op: BinaryOp, left: Box<Expr>, right: Box<Expr> },
+    Call { callee: Box<Expr>, arguments: Vec<Expr> },
+    /// String with embedded expressions, like `"x = ${x}"`. The strings without them are literals.
+    Interpolation(Vec<StringPart>),
+}
+
+/// Part of an interpolated string, in source order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    /// Decoded text between the expressions
+    Text(String),
+    /// Expression of a `${...}`, whose value is converted to a string
+    Expr(Expr),
+}
+
+/// Value written in the source, decoded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Boolean(bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    /// `-`
+    Neg,
+    /// `!`
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+impl UnaryOp {
+    /// Returns the operator written in the source.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Not => "!",
+        }
+    }
+}
+
+impl BinaryOp {
+    /// Returns the operator written in the source.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        }
+    }
+
+    /// Returns the operator written as the given symbol, if any.
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        let op = match symbol {
+            "+" => BinaryOp::Add,
+            "-" => BinaryOp::Sub,
+            "*" => BinaryOp::Mul,
+            "/" => BinaryOp::Div,
+            "%" => BinaryOp::Rem,
+            "<" => BinaryOp::Lt,
+            "<=" => BinaryOp::Le,
+            ">" => BinaryOp::Gt,
+            ">=" => BinaryOp::Ge,
+            "==" => BinaryOp::Eq,
+            "!=" => BinaryOp::Ne,
+            "&&" => BinaryOp::And,
+            "||" => BinaryOp::Or,
+            _ => return None,
+        };
+        Some(op)
+    }
+}
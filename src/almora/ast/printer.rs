@@ -0,0 +1,444 @@
+use super::{BinaryOp, Block, Expr, ExprKind, Function, Item, Literal, Program, Stmt, StmtKind, StringPart};
+
+/// Tells how `print` lays out the source.
+///
+/// The default options use lines of 100 chars, indented by 4 spaces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrintOptions {
+    /// Maximum length of a line, which can be exceeded if there is nothing to break.
+    width: usize,
+    /// Number of spaces added at each level of nesting.
+    indent: usize,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self { width: 100, indent: 4 }
+    }
+}
+
+impl PrintOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn with_indent(mut self, indent: usize) -> Self {
+        self.indent = indent;
+        self
+    }
+}
+
+/// Writes the program as source text, which parses back to the same program.
+///
+/// The parentheses are only kept where the precedence requires them, and the comments are lost. A call or a function
+/// whose line would be longer than the width is written with one argument or parameter per line.
+pub fn print(program: &Program, options: &PrintOptions) -> String {
+    let mut printer = Printer::new(options.width, options.indent);
+    for (index, item) in program.items.iter().enumerate() {
+        // Functions are separated from the other items by a blank line
+        let previous = index.checked_sub(1).map(|index| &program.items[index]);
+        if matches!(item, Item::Function(_)) && previous.is_some() || matches!(previous, Some(Item::Function(_))) {
+            printer.out.push('\n');
+        }
+        match item {
+            Item::Import(import) => printer.out.push_str(&format!("import {};", import.module.name)),
+            Item::Function(function) => printer.function(function),
+            Item::Statement(stmt) => printer.stmt(stmt),
+        }
+        printer.out.push('\n');
+    }
+    printer.out
+}
+
+/// Writes the expression as source text, on one line.
+pub fn print_expr(expr: &Expr) -> String {
+    let mut printer = Printer::new(usize::MAX, 0);
+    printer.expr(expr, Precedence::Lowest);
+    printer.out
+}
+
+/// How tightly an expression binds its operands: an operand binding less tightly than its operator needs parentheses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Lowest,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Sum,
+    Product,
+    Unary,
+    /// Calls and primary expressions
+    Postfix,
+}
+
+impl Precedence {
+    fn of_operator(op: BinaryOp) -> Self {
+        match op {
+            BinaryOp::Or => Precedence::Or,
+            BinaryOp::And => Precedence::And,
+            BinaryOp::Eq | BinaryOp::Ne => Precedence::Equality,
+            BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => Precedence::Comparison,
+            BinaryOp::Add | BinaryOp::Sub => Precedence::Sum,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => Precedence::Product,
+        }
+    }
+
+    fn of(expr: &Expr) -> Self {
+        match &expr.kind {
+            ExprKind::Binary { op, .. } => Precedence::of_operator(*op),
+            ExprKind::Unary { .. } => Precedence::Unary,
+            // Negative numbers are written with a minus, like a unary expression
+            ExprKind::Literal(Literal::Integer(value)) if *value < 0 => Precedence::Unary,
+            ExprKind::Literal(Literal::Float(value)) if value.is_sign_negative() => Precedence::Unary,
+            _ => Precedence::Postfix,
+        }
+    }
+}
+
+struct Printer {
+    width: usize,
+    indent: usize,
+    out: String,
+    /// Nesting level of the current line.
+    level: usize,
+}
+
+impl Printer {
+    fn new(width: usize, indent: usize) -> Self {
+        Self { width, indent, out: String::new(), level: 0 }
+    }
+
+    fn column(&self) -> usize {
+        self.out.len() - self.out.rfind('\n').map_or(0, |index| index + 1)
+    }
+
+    fn new_line(&mut self) {
+        self.out.push('\n');
+        self.out.extend(std::iter::repeat_n(' ', self.level * self.indent));
+    }
+
+    /// Writes the items separated by commas, on the line if they fit, or else one per line.
+    fn list<T>(&mut self, open: &str, items: &[T], close: &str, mut write: impl FnMut(&mut Self, &T)) {
+        let mut flat = Printer::new(usize::MAX, 0);
+        for (index, item) in items.iter().enumerate() {
+            if index > 0 {
+                flat.out.push_str(", ");
+            }
+            write(&mut flat, item);
+        }
+
+        if items.is_empty() || self.column() + open.len() + flat.out.len() + close.len() <= self.width {
+            self.out.push_str(open);
+            self.out.push_str(&flat.out);
+            self.out.push_str(close);
+            return;
+        }
+
+        self.out.push_str(open);
+        self.level += 1;
+        for item in items {
+            self.new_line();
+            write(self, item);
+            self.out.push(',');
+        }
+        self.level -= 1;
+        self.new_line();
+        self.out.push_str(close);
+    }
+
+    fn function(&mut self, function: &Function) {
+        for line in function.doc.iter().flat_map(|doc| doc.split('\n')) {
+            self.out.push_str("///");
+            if !line.is_empty() {
+                self.out.push(' ');
+                self.out.push_str(line);
+            }
+            self.new_line();
+        }
+        self.out.push_str("fn ");
+        self.out.push_str(&function.name.name);
+        let close = match &function.return_type {
+            Some(ty) => format!(") -> {} {{", ty.name),
+            None => ") {".to_string(),
+        };
+        self.list("(", &function.params, &close, |printer, param| {
+            printer.out.push_str(&format!("{}: {}", param.name.name, param.ty.name));
+        });
+        self.block_content(&function.body);
+    }
+
+    /// Writes the statements of the block and its closing brace, after its opening brace.
+    fn block_content(&mut self, block: &Block) {
+        if block.stmts.is_empty() {
+            self.out.push('}');
+            return;
+        }
+        self.level += 1;
+        for stmt in &block.stmts {
+            self.new_line();
+            self.stmt(stmt);
+        }
+        self.level -= 1;
+        self.new_line();
+        self.out.push('}');
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.out.push('{');
+        self.block_content(block);
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Let { name, ty, value } => {
+                self.out.push_str("let ");
+                self.out.push_str(&name.name);
+                if let Some(ty) = ty {
+                    self.out.push_str(": ");
+                    self.out.push_str(&ty.name);
+                }
+                self.out.push_str(" = ");
+                self.expr(value, Precedence::Lowest);
+                self.out.push(';');
+            }
+            StmtKind::Assign { target, value } => {
+                self.out.push_str(&target.name);
+                self.out.push_str(" = ");
+                self.expr(value, Precedence::Lowest);
+                self.out.push(';');
+            }
+            StmtKind::If { condition, then_branch, else_branch } => {
+                self.out.push_str("if ");
+                self.expr(condition, Precedence::Lowest);
+                self.out.push(' ');
+                self.block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.out.push_str(" else ");
+                    self.stmt(else_branch);
+                }
+            }
+            StmtKind::While { condition, body } => {
+                self.out.push_str("while ");
+                self.expr(condition, Precedence::Lowest);
+                self.out.push(' ');
+                self.block(body);
+            }
+            StmtKind::For { variable, iterable, body } => {
+                self.out.push_str("for ");
+                self.out.push_str(&variable.name);
+                self.out.push_str(" in ");
+                self.expr(iterable, Precedence::Lowest);
+                self.out.push(' ');
+                self.block(body);
+            }
+            StmtKind::Return(value) => {
+                self.out.push_str("return");
+                if let Some(value) = value {
+                    self.out.push(' ');
+                    self.expr(value, Precedence::Lowest);
+                }
+                self.out.push(';');
+            }
+            StmtKind::Block(block) => self.block(block),
+            StmtKind::Expr(expr) => {
+                self.expr(expr, Precedence::Lowest);
+                self.out.push(';');
+            }
+        }
+    }
+
+    /// Writes the expression, in parentheses if it binds less tightly than `min`.
+    fn expr(&mut self, expr: &Expr, min: Precedence) {
+        let precedence = Precedence::of(expr);
+        if precedence < min {
+            self.out.push('(');
+            self.expr(expr, Precedence::Lowest);
+            self.out.push(')');
+            return;
+        }
+
+        match &expr.kind {
+            ExprKind::Literal(literal) => self.literal(literal),
+            ExprKind::Identifier(name) => self.out.push_str(name),
+            ExprKind::Unary { op, operand } => {
+                self.out.push_str(op.symbol());
+                self.expr(operand, Precedence::Unary);
+            }
+            ExprKind::Binary { op, left, right } => {
+                // The operators are left associative, and the comparisons can't be chained
+                let (left_min, right_min) = match precedence {
+                    Precedence::Comparison | Precedence::Equality => (next(precedence), next(precedence)),
+                    _ => (precedence, next(precedence)),
+                };
+                self.expr(left, left_min);
+                self.out.push(' ');
+                self.out.push_str(op.symbol());
+                self.out.push(' ');
+                self.expr(right, right_min);
+            }
+            ExprKind::Call { callee, arguments } => {
+                self.expr(callee, Precedence::Postfix);
+                self.list("(", arguments, ")", |printer, argument| printer.expr(argument, Precedence::Lowest));
+            }
+            ExprKind::Interpolation(parts) => {
+                self.out.push('"');
+                for part in parts {
+                    match part {
+                        StringPart::Text(text) => self.string_text(text),
+                        StringPart::Expr(part) => {
+                            self.out.push_str("${");
+                            self.expr(part, Precedence::Lowest);
+                            self.out.push('}');
+                        }
+                    }
+                }
+                self.out.push('"');
+            }
+        }
+    }
+
+    fn literal(&mut self, literal: &Literal) {
+        match literal {
+            Literal::Integer(i64::MIN) => self.out.push_str("(-9223372036854775807 - 1)"),
+            Literal::Integer(value) => self.out.push_str(&value.to_string()),
+            // There are no literals for the infinities and NaN
+            Literal::Float(value) if value.is_nan() => self.out.push_str("(0.0 / 0.0)"),
+            Literal::Float(value) if value.is_infinite() => {
+                self.out.push_str(if *value > 0.0 { "(1.0 / 0.0)" } else { "(-1.0 / 0.0)" })
+            }
+            // The debug format always has a fraction or an exponent
+            Literal::Float(value) => self.out.push_str(&format!("{:?}", value)),
+            Literal::Boolean(value) => self.out.push_str(&value.to_string()),
+            Literal::String(value) => {
+                self.out.push('"');
+                self.string_text(value);
+                self.out.push('"');
+            }
+        }
+    }
+
+    /// Writes the text of a string with the escape sequences, including `\$` where it would start an interpolation.
+    fn string_text(&mut self, text: &str) {
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                '\n' => self.out.push_str("\\n"),
+                '\t' => self.out.push_str("\\t"),
+                '\r' => self.out.push_str("\\r"),
+                '\0' => self.out.push_str("\\0"),
+                '$' if chars.peek() == Some(&'{') => self.out.push_str("\\$"),
+                c => self.out.push(c),
+            }
+        }
+    }
+}
+
+/// Returns the precedence binding just more tightly.
+fn next(precedence: Precedence) -> Precedence {
+    match precedence {
+        Precedence::Lowest => Precedence::Or,
+        Precedence::Or => Precedence::And,
+        Precedence::And => Precedence::Equality,
+        Precedence::Equality => Precedence::Comparison,
+        Precedence::Comparison => Precedence::Sum,
+        Precedence::Sum => Precedence::Product,
+        Precedence::Product => Precedence::Unary,
+        Precedence::Unary | Precedence::Postfix => Precedence::Postfix,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::almora::{compile, optimizer::optimize};
+
+    use super::*;
+
+    /// Parses and prints the source, and checks that the printed source gives the same program.
+    fn round_trip(source: &str, options: &PrintOptions) -> String {
+        let printed = print(&compile(source).unwrap(), options);
+        let reprinted = print(&compile(&printed).unwrap(), options);
+        assert_eq!(printed, reprinted);
+        printed
+    }
+
+    fn expr(source: &str) -> String {
+        let Item::Statement(Stmt { kind: StmtKind::Expr(expr), .. }) = &compile(source).unwrap().items[0] else {
+            panic!("expected an expression statement");
+        };
+        print_expr(expr)
+    }
+
+    #[test]
+    fn test_print_expressions() {
+        assert_eq!(expr("1+2*3;"), "1 + 2 * 3");
+        assert_eq!(expr("((1 + 2)) * (3);"), "(1 + 2) * 3");
+        assert_eq!(expr("(a - b) - (c - d);"), "a - b - (c - d)");
+        assert_eq!(expr("(a < b) == (c < d);"), "a < b == c < d");
+        assert_eq!(expr("(a == b) != c;"), "(a == b) != c");
+        assert_eq!(expr("!(a && b) || -(-x);"), "!(a && b) || --x");
+        assert_eq!(expr("(f)(1,2)(g(x),);"), "f(1, 2)(g(x))");
+        assert_eq!(expr("(a + b)(c);"), "(a + b)(c)");
+        assert_eq!(expr(r#""a\"b\\c\n\t\0";"#), r#""a\"b\\c\n\t\0""#);
+        assert_eq!(expr("1.5e300 * 1.0;"), "1.5e300 * 1.0");
+
+        // Strings are written back with the escapes needed to read them again
+        assert_eq!(expr(r#""\u{e9}\${x} $y";"#), r#""é\${x} $y""#);
+        assert_eq!(expr(r#""${ a+1 }\n${f("}")}";"#), r#""${a + 1}\n${f("}")}""#);
+    }
+
+    #[test]
+    fn test_print_program() {
+        let source = "import lib.util;let x:int=1;///  Doc\n///\n\
+                      fn f(a:int,b:float)->int{if a>b{return a;}else if a==b{}else{return -b;}}\
+                      for i in 3{while x<10{x=x+f(i,x);}}";
+        assert_eq!(
+            round_trip(source, &PrintOptions::new()),
+            "import lib.util;\n\
+             let x: int = 1;\n\
+             \n\
+             /// \x20Doc\n\
+             ///\n\
+             fn f(a: int, b: float) -> int {\n    \
+                 if a > b {\n        \
+                     return a;\n    \
+                 } else if a == b {} else {\n        \
+                     return -b;\n    \
+                 }\n\
+             }\n\
+             \n\
+             for i in 3 {\n    \
+                 while x < 10 {\n        \
+                     x = x + f(i, x);\n    \
+                 }\n\
+             }\n"
+        );
+
+        // Lists are broken when they don't fit
+        let source = "fn long(first: int, second: int) { long(first * 1000, second * 1000); }";
+        let options = PrintOptions::new().with_width(30).with_indent(2);
+        assert_eq!(
+            round_trip(source, &options),
+            "fn long(\n  first: int,\n  second: int,\n) {\n  long(\n    first * 1000,\n    second * 1000,\n  );\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_print_optimized() {
+        // Folded literals can be negative or out of the range of the source literals
+        let mut program = compile("let x = 0 - 5;\nlet y = -1.5 * 2;\nlet z = 0 - 9223372036854775807 - 1;\nx - x;").unwrap();
+        optimize(&mut program);
+        let printed = print(&program, &PrintOptions::new());
+        assert_eq!(printed, "let x = -5;\nlet y = -3.0;\nlet z = (-9223372036854775807 - 1);\nx - x;\n");
+        let mut reparsed = compile(&printed).unwrap();
+        optimize(&mut reparsed);
+        assert_eq!(print(&reparsed, &PrintOptions::new()), printed);
+    }
+}
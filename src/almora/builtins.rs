@@ -0,0 +1,249 @@
+use std::{
+    fmt::{Debug, Formatter},
+    io::Write,
+    rc::Rc,
+};
+
+use super::bytecode::Value;
+
+/// Native function called by the VM. It returns an error message if the arguments are not valid.
+pub type BuiltinFunction = dyn Fn(&mut CallContext, &[Value]) -> Result<Value, String>;
+
+/// What a builtin can use besides its arguments.
+pub struct CallContext<'a> {
+    /// Where `print` writes, which is the output of the VM.
+    pub output: &'a mut dyn Write,
+}
+
+/// Function of the host application, visible in every almora program.
+#[derive(Clone)]
+pub struct Builtin {
+    name: String,
+    arity: Option<u8>,
+    function: Rc<BuiltinFunction>,
+}
+
+impl Builtin {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Number of arguments of the function, or None if it takes any number of them.
+    pub fn arity(&self) -> Option<u8> {
+        self.arity
+    }
+
+    pub fn call(&self, context: &mut CallContext, arguments: &[Value]) -> Result<Value, String> {
+        (self.function)(context, arguments)
+    }
+}
+
+impl Debug for Builtin {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_struct("Builtin").field("name", &self.name).field("arity", &self.arity).finish_non_exhaustive()
+    }
+}
+
+/// Registry of the builtins, given to the resolver and to the VM.
+///
+/// `Builtins::standard` has the functions of the standard library, and host applications can register their own:
+///
+/// ```ignore
+/// let mut builtins = Builtins::standard();
+/// builtins.register("double", Some(1), |_, arguments| match arguments {
+///     [Value::Integer(value)] => Ok(Value::Integer(value * 2)),
+///     _ => Err("expected an int".to_string()),
+/// });
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Builtins {
+    builtins: Vec<Builtin>,
+}
+
+impl Builtins {
+    /// Returns an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the standard library:
+    ///
+    /// - `print(values...)` writes the values separated by spaces, followed by a new line
+    /// - `len(s)` is the number of characters of a string
+    /// - `str(value)` converts a value to a string, and `int(s)` parses an integer
+    /// - `abs(x)`, `min(a, b)`, `max(a, b)`, `sqrt(x)` and `floor(x)`
+    /// - `upper(s)`, `lower(s)`, `contains(s, part)` and `substring(s, start, end)`, which counts characters
+    pub fn standard() -> Self {
+        let mut builtins = Self::new();
+        builtins.register("print", None, |context, arguments| {
+            let line: Vec<String> = arguments.iter().map(Value::to_string).collect();
+            writeln!(context.output, "{}", line.join(" ")).map_err(|err| err.to_string())?;
+            Ok(Value::Nil)
+        });
+        builtins.register("len", Some(1), |_, arguments| {
+            let length = string(&arguments[0])?.chars().count();
+            Ok(Value::Integer(length as i64))
+        });
+        builtins.register("str", Some(1), |_, arguments| Ok(Value::String(Rc::from(arguments[0].to_string()))));
+        builtins.register("int", Some(1), |_, arguments| {
+            let text = string(&arguments[0])?;
+            text.trim().parse().map(Value::Integer).map_err(|_| format!("`{}` is not an integer", text))
+        });
+
+        builtins.register("abs", Some(1), |_, arguments| match &arguments[0] {
+            Value::Integer(value) => {
+                value.checked_abs().map(Value::Integer).ok_or_else(|| "integer overflow".to_string())
+            }
+            Value::Float(value) => Ok(Value::Float(value.abs())),
+            other => Err(expected("a number", other)),
+        });
+        builtins.register("min", Some(2), |_, arguments| extremum(arguments, f64::min, std::cmp::min));
+        builtins.register("max", Some(2), |_, arguments| extremum(arguments, f64::max, std::cmp::max));
+        builtins.register("sqrt", Some(1), |_, arguments| Ok(Value::Float(number(&arguments[0])?.sqrt())));
+        builtins.register("floor", Some(1), |_, arguments| {
+            let value = number(&arguments[0])?.floor();
+            // The bounds are exact powers of two, so the comparisons are exact
+            if value >= -(i64::MIN as f64) || value < i64::MIN as f64 || value.is_nan() {
+                return Err(format!("{} doesn't fit in an int", value));
+            }
+            Ok(Value::Integer(value as i64))
+        });
+
+        builtins.register("upper", Some(1), |_, arguments| {
+            Ok(Value::String(Rc::from(string(&arguments[0])?.to_uppercase())))
+        });
+        builtins.register("lower", Some(1), |_, arguments| {
+            Ok(Value::String(Rc::from(string(&arguments[0])?.to_lowercase())))
+        });
+        builtins.register("contains", Some(2), |_, arguments| {
+            Ok(Value::Boolean(string(&arguments[0])?.contains(string(&arguments[1])?)))
+        });
+        builtins.register("substring", Some(3), |_, arguments| {
+            let text = string(&arguments[0])?;
+            let (start, end) = (integer(&arguments[1])?, integer(&arguments[2])?);
+            let length = text.chars().count() as i64;
+            if start < 0 || start > end || end > length {
+                return Err(format!("invalid range {}..{} for a string of length {}", start, end, length));
+            }
+            let part: String = text.chars().skip(start as usize).take((end - start) as usize).collect();
+            Ok(Value::String(Rc::from(part)))
+        });
+        builtins
+    }
+
+    /// Adds a builtin, or replaces the one with the same name.
+    ///
+    /// The VM checks the number of arguments before calling the function, unless the arity is None.
+    pub fn register<F>(&mut self, name: &str, arity: Option<u8>, function: F)
+    where
+        F: Fn(&mut CallContext, &[Value]) -> Result<Value, String> + 'static,
+    {
+        let builtin = Builtin { name: name.to_string(), arity, function: Rc::new(function) };
+        match self.builtins.iter_mut().find(|existing| existing.name == name) {
+            Some(existing) => *existing = builtin,
+            None => self.builtins.push(builtin),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Builtin> {
+        self.builtins.iter().find(|builtin| builtin.name == name)
+    }
+
+    /// Returns the builtins, in registration order.
+    pub fn iter(&self) -> impl Iterator<Item = &Builtin> {
+        self.builtins.iter()
+    }
+}
+
+fn expected(expected: &str, found: &Value) -> String {
+    format!("expected {}, found {}", expected, found.type_name())
+}
+
+fn string(value: &Value) -> Result<&str, String> {
+    match value {
+        Value::String(value) => Ok(value),
+        other => Err(expected("a string", other)),
+    }
+}
+
+fn integer(value: &Value) -> Result<i64, String> {
+    match value {
+        Value::Integer(value) => Ok(*value),
+        other => Err(expected("an int", other)),
+    }
+}
+
+fn number(value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Integer(value) => Ok(*value as f64),
+        Value::Float(value) => Ok(*value),
+        other => Err(expected("a number", other)),
+    }
+}
+
+/// Returns an int if both arguments are ints, and a float otherwise.
+fn extremum(arguments: &[Value], float: fn(f64, f64) -> f64, int: fn(i64, i64) -> i64) -> Result<Value, String> {
+    match arguments {
+        [Value::Integer(a), Value::Integer(b)] => Ok(Value::Integer(int(*a, *b))),
+        [a, b] => Ok(Value::Float(float(number(a)?, number(b)?))),
+        _ => unreachable!("the arity is checked by the VM"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Calls the standard builtin, and returns its result and what it printed.
+    fn call(name: &str, arguments: &[Value]) -> (Result<Value, String>, String) {
+        let mut output = Vec::new();
+        let result = Builtins::standard().get(name).unwrap().call(&mut CallContext { output: &mut output }, arguments);
+        (result, String::from_utf8(output).unwrap())
+    }
+
+    fn string(value: &str) -> Value {
+        Value::String(Rc::from(value))
+    }
+
+    #[test]
+    fn test_standard_builtins() {
+        let printed = call("print", &[string("a"), Value::Integer(1), Value::Float(2.0), Value::Nil]);
+        assert_eq!(printed, (Ok(Value::Nil), "a 1 2.0 nil\n".to_string()));
+        assert_eq!(call("print", &[]).1, "\n");
+
+        assert_eq!(call("len", &[string("héllo")]).0, Ok(Value::Integer(5)));
+        assert_eq!(call("len", &[Value::Integer(1)]).0, Err("expected a string, found int".to_string()));
+        assert_eq!(call("str", &[Value::Boolean(true)]).0, Ok(string("true")));
+        assert_eq!(call("int", &[string(" -12 ")]).0, Ok(Value::Integer(-12)));
+        assert_eq!(call("int", &[string("1.5")]).0, Err("`1.5` is not an integer".to_string()));
+
+        assert_eq!(call("abs", &[Value::Integer(-3)]).0, Ok(Value::Integer(3)));
+        assert_eq!(call("abs", &[Value::Integer(i64::MIN)]).0, Err("integer overflow".to_string()));
+        assert_eq!(call("min", &[Value::Integer(3), Value::Integer(2)]).0, Ok(Value::Integer(2)));
+        assert_eq!(call("max", &[Value::Integer(3), Value::Float(3.5)]).0, Ok(Value::Float(3.5)));
+        assert_eq!(call("sqrt", &[Value::Integer(9)]).0, Ok(Value::Float(3.0)));
+        assert_eq!(call("floor", &[Value::Float(-1.5)]).0, Ok(Value::Integer(-2)));
+        assert!(call("floor", &[Value::Float(1e19)]).0.is_err());
+
+        assert_eq!(call("upper", &[string("abc")]).0, Ok(string("ABC")));
+        assert_eq!(call("contains", &[string("abc"), string("bc")]).0, Ok(Value::Boolean(true)));
+        let substring = |start, end| {
+            call("substring", &[string("héllo"), Value::Integer(start), Value::Integer(end)]).0
+        };
+        assert_eq!(substring(1, 3), Ok(string("él")));
+        assert!(substring(3, 6).is_err());
+    }
+
+    #[test]
+    fn test_register_builtins() {
+        let mut builtins = Builtins::new();
+        builtins.register("answer", Some(0), |_, _| Ok(Value::Integer(42)));
+        builtins.register("answer", Some(0), |_, _| Ok(Value::Integer(43)));
+        assert_eq!(builtins.iter().count(), 1);
+
+        let answer = builtins.get("answer").unwrap();
+        assert_eq!(answer.arity(), Some(0));
+        assert_eq!(answer.call(&mut CallContext { output: &mut Vec::new() }, &[]), Ok(Value::Integer(43)));
+        assert!(builtins.get("print").is_none());
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt::Write;
+
+use super::{Constant, Instruction, Module};
+
+/// Returns a readable listing of the module: its constants, its globals and builtins, and the instructions of its
+/// functions.
+///
+/// Each instruction is written with its offset, and the instructions using a constant, a global or a builtin are
+/// followed by it in a comment. If the code of a function is not valid, its listing stops at the invalid instruction.
+pub fn disassemble(module: &Module) -> String {
+    let mut listing = String::new();
+    if !module.constants.is_empty() {
+        listing.push_str("constants:\n");
+        for (index, constant) in module.constants.iter().enumerate() {
+            writeln!(listing, "  {}: {}", index, constant_text(module, constant)).unwrap();
+        }
+    }
+    if !module.globals.is_empty() {
+        writeln!(listing, "globals: {}", module.globals.join(", ")).unwrap();
+    }
+    if !module.builtins.is_empty() {
+        writeln!(listing, "builtins: {}", module.builtins.join(", ")).unwrap();
+    }
+
+    for function in &module.functions {
+        writeln!(listing, "fn {} (arity {}, {} locals):", function.name, function.arity, function.locals).unwrap();
+        let mut offset = 0;
+        while offset < function.code.len() {
+            let Some((instruction, next)) = Instruction::decode(&function.code, offset) else {
+                writeln!(listing, "  {:04}  <invalid {}>", offset, function.code[offset]).unwrap();
+                break;
+            };
+            write!(listing, "  {:04}  {}", offset, instruction).unwrap();
+            match instruction {
+                Instruction::Constant(index) => {
+                    if let Some(constant) = module.constants.get(index as usize) {
+                        write!(listing, "  ; {}", constant_text(module, constant)).unwrap();
+                    }
+                }
+                Instruction::GetGlobal(index) | Instruction::SetGlobal(index) => {
+                    if let Some(global) = module.globals.get(index as usize) {
+                        write!(listing, "  ; {}", global).unwrap();
+                    }
+                }
+                Instruction::GetBuiltin(index) => {
+                    if let Some(builtin) = module.builtins.get(index as usize) {
+                        write!(listing, "  ; {}", builtin).unwrap();
+                    }
+                }
+                _ => {}
+            }
+            listing.push('\n');
+            offset = next;
+        }
+    }
+    listing
+}
+
+fn constant_text(module: &Module, constant: &Constant) -> String {
+    match constant {
+        Constant::Integer(value) => value.to_string(),
+        Constant::Float(value) => format!("{:?}", value),
+        Constant::String(value) => format!("{:?}", value),
+        Constant::Function(index) => match module.functions.get(*index as usize) {
+            Some(function) => format!("<fn {}>", function.name),
+            None => format!("<fn {}>", index),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::almora::{bytecode::emit, compile};
+
+    use super::*;
+
+    #[test]
+    fn test_disassemble() {
+        let module = emit(&compile("fn f(a: int) { return a; }\nlet x = f(\"s\");").unwrap()).unwrap();
+        assert_eq!(
+            disassemble(&module),
+            "constants:\n  0: <fn f>\n  1: \"s\"\nglobals: f, x\n\
+             fn <main> (arity 0, 0 locals):\n\
+             \x20 0000  Constant 0  ; <fn f>\n\
+             \x20 0003  SetGlobal 0  ; f\n\
+             \x20 0006  GetGlobal 0  ; f\n\
+             \x20 0009  Constant 1  ; \"s\"\n\
+             \x20 0012  Call 1\n\
+             \x20 0014  SetGlobal 1  ; x\n\
+             \x20 0017  Nil\n\
+             \x20 0018  Return\n\
+             fn f (arity 1, 1 locals):\n\
+             \x20 0000  GetLocal 0\n\
+             \x20 0003  Return\n\
+             \x20 0004  Nil\n\
+             \x20 0005  Return\n"
+        );
+
+        let mut module = Module::default();
+        module.functions.push(super::super::FunctionCode { name: "g".to_string(), arity: 0, locals: 0, code: vec![4, 99] });
+        assert_eq!(disassemble(&module), "fn g (arity 0, 0 locals):\n  0000  Pop\n  0001  <invalid 99>\n");
+    }
+}
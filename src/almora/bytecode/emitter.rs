@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+
+use crate::{
+    almora::{
+        ast::{BinaryOp, Block, Expr, ExprKind, Function, Item, Literal, Program, Stmt, StmtKind, StringPart, UnaryOp},
+        resolve_with, Diagnostic, ResolveOptions,
+    },
+    parser_lib::Span,
+};
+
+use super::{Constant, FunctionCode, Instruction, Module};
+
+/// Compiles the program to bytecode.
+///
+/// The program is resolved first: if it has errors, they are returned instead. The variables defined at the top level
+/// of the program are globals, like the functions. The ones defined in blocks and functions are in local slots.
+///
+/// The program can use the standard builtins: see `emit_with` for other ones.
+pub fn emit(program: &Program) -> Result<Module, Vec<Diagnostic>> {
+    emit_with(program, &ResolveOptions::new())
+}
+
+/// Compiles a program resolved with the given options. The builtins that the program uses are in the module by name,
+/// so the VM running it must have them.
+pub fn emit_with(program: &Program, options: &ResolveOptions) -> Result<Module, Vec<Diagnostic>> {
+    let (_, diagnostics) = resolve_with(program, options);
+    let errors: Vec<Diagnostic> = diagnostics.into_iter().filter(Diagnostic::is_error).collect();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut emitter = Emitter { module: Module::default(), globals: HashMap::new() };
+    emitter.emit(program).map_err(|err| vec![*err])?;
+    Ok(emitter.module)
+}
+
+/// The errors of the emitter are boxed, since they are rare and diagnostics are large.
+type EmitResult<T> = Result<T, Box<Diagnostic>>;
+
+struct Emitter {
+    module: Module,
+    globals: HashMap<String, u16>,
+}
+
+impl Emitter {
+    fn emit(&mut self, program: &Program) -> EmitResult<()> {
+        // The globals are known before the code is emitted, since the functions can use them before their definition
+        let functions: Vec<&Function> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Function(function) => Some(function),
+                Item::Import(_) | Item::Statement(_) => None,
+            })
+            .collect();
+        for function in &functions {
+            self.global(&function.name.name, &function.name.span)?;
+        }
+        for item in &program.items {
+            if let Item::Statement(Stmt { kind: StmtKind::Let { name, .. }, .. }) = item {
+                self.global(&name.name, &name.span)?;
+            }
+        }
+
+        // The top-level code stores the functions in their globals first
+        let mut main = FunctionEmitter::new(self);
+        for (index, function) in functions.iter().enumerate() {
+            let constant = main.emitter.constant(Constant::Function(index as u16 + 1), &function.span)?;
+            main.instruction(Instruction::Constant(constant));
+            main.instruction(Instruction::SetGlobal(main.emitter.globals[&function.name.name]));
+        }
+        for item in &program.items {
+            if let Item::Statement(stmt) = item {
+                main.stmt(stmt)?;
+            }
+        }
+        let main = main.finish("<main>", 0);
+        self.module.functions.push(main);
+
+        for function in functions {
+            let mut emitter = FunctionEmitter::new(self);
+            emitter.scopes.push(Vec::new());
+            for param in &function.params {
+                emitter.define(&param.name.name, &param.span)?;
+            }
+            emitter.stmts(&function.body)?;
+            let arity = function.params.len() as u8;
+            let code = emitter.finish(&function.name.name, arity);
+            self.module.functions.push(code);
+        }
+        Ok(())
+    }
+
+    fn global(&mut self, name: &str, span: &Span) -> EmitResult<u16> {
+        if let Some(index) = self.globals.get(name) {
+            return Ok(*index);
+        }
+        let index = u16::try_from(self.module.globals.len())
+            .map_err(|_| Box::new(Diagnostic::error("too many globals in the program", span.clone())))?;
+        self.module.globals.push(name.to_string());
+        self.globals.insert(name.to_string(), index);
+        Ok(index)
+    }
+
+    /// Returns the index of the builtin in the module, adding it the first time it is used.
+    fn builtin(&mut self, name: &str, span: &Span) -> EmitResult<u16> {
+        let index = match self.module.builtins.iter().position(|builtin| builtin == name) {
+            Some(index) => index,
+            None => {
+                self.module.builtins.push(name.to_string());
+                self.module.builtins.len() - 1
+            }
+        };
+        u16::try_from(index).map_err(|_| Box::new(Diagnostic::error("too many builtins in the program", span.clone())))
+    }
+
+    /// Returns the index of the constant in the pool, adding it if it is not there yet.
+    fn constant(&mut self, constant: Constant, span: &Span) -> EmitResult<u16> {
+        let index = match self.module.constants.iter().position(|existing| *existing == constant) {
+            Some(index) => index,
+            None => {
+                self.module.constants.push(constant);
+                self.module.constants.len() - 1
+            }
+        };
+        u16::try_from(index).map_err(|_| Box::new(Diagnostic::error("too many constants in the program", span.clone())))
+    }
+}
+
+/// Emits the code of a function, and allocates the local slots of its variables.
+struct FunctionEmitter<'e> {
+    emitter: &'e mut Emitter,
+    code: Vec<u8>,
+    /// Local variables of the enclosing blocks, with their slots. At the top level, variables are globals.
+    scopes: Vec<Vec<(String, u16)>>,
+    next_slot: u16,
+    locals: u16,
+}
+
+impl<'e> FunctionEmitter<'e> {
+    fn new(emitter: &'e mut Emitter) -> Self {
+        Self { emitter, code: Vec::new(), scopes: Vec::new(), next_slot: 0, locals: 0 }
+    }
+
+    /// Returns the code, which returns nil if the end is reached.
+    fn finish(mut self, name: &str, arity: u8) -> FunctionCode {
+        self.instruction(Instruction::Nil);
+        self.instruction(Instruction::Return);
+        FunctionCode { name: name.to_string(), arity, locals: self.locals, code: self.code }
+    }
+
+    fn instruction(&mut self, instruction: Instruction) {
+        instruction.encode(&mut self.code);
+    }
+
+    /// Emits a jump to a target not known yet, and returns the offset of the target to patch with `patch`.
+    fn jump(&mut self, instruction: Instruction) -> usize {
+        self.instruction(instruction);
+        self.code.len() - 4
+    }
+
+    /// Makes the jump go to the current end of the code.
+    fn patch(&mut self, at: usize) {
+        let target = self.code.len() as u32;
+        self.code[at..at + 4].copy_from_slice(&target.to_le_bytes());
+    }
+
+    /// Allocates a slot for the variable in the innermost scope.
+    fn define(&mut self, name: &str, span: &Span) -> EmitResult<u16> {
+        let slot = self.next_slot;
+        self.next_slot = slot
+            .checked_add(1)
+            .ok_or_else(|| Box::new(Diagnostic::error("too many local variables in the function", span.clone())))?;
+        self.locals = self.locals.max(self.next_slot);
+        self.scopes.last_mut().expect("locals are defined in a scope").push((name.to_string(), slot));
+        Ok(slot)
+    }
+
+    /// Runs the function in a new scope, whose slots are reused after it.
+    fn scoped<T>(&mut self, f: impl FnOnce(&mut Self) -> EmitResult<T>) -> EmitResult<T> {
+        let next_slot = self.next_slot;
+        self.scopes.push(Vec::new());
+        let result = f(self);
+        self.scopes.pop();
+        self.next_slot = next_slot;
+        result
+    }
+
+    fn local(&self, name: &str) -> Option<u16> {
+        self.scopes.iter().rev().find_map(|scope| scope.iter().rev().find(|(n, _)| n == name).map(|(_, slot)| *slot))
+    }
+
+    /// The program is resolved, so a name which is not a variable or a function is a builtin.
+    fn get(&mut self, name: &str, span: &Span) -> EmitResult<()> {
+        let instruction = match (self.local(name), self.emitter.globals.get(name)) {
+            (Some(slot), _) => Instruction::GetLocal(slot),
+            (None, Some(index)) => Instruction::GetGlobal(*index),
+            (None, None) => Instruction::GetBuiltin(self.emitter.builtin(name, span)?),
+        };
+        self.instruction(instruction);
+        Ok(())
+    }
+
+    fn set(&mut self, name: &str) {
+        match self.local(name) {
+            Some(slot) => self.instruction(Instruction::SetLocal(slot)),
+            None => self.instruction(Instruction::SetGlobal(self.emitter.globals[name])),
+        }
+    }
+
+    fn block(&mut self, block: &Block) -> EmitResult<()> {
+        self.scoped(|emitter| emitter.stmts(block))
+    }
+
+    fn stmts(&mut self, block: &Block) -> EmitResult<()> {
+        for stmt in &block.stmts {
+            self.stmt(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> EmitResult<()> {
+        match &stmt.kind {
+            StmtKind::Let { name, value, .. } => {
+                self.expr(value)?;
+                if self.scopes.is_empty() {
+                    self.instruction(Instruction::SetGlobal(self.emitter.globals[&name.name]));
+                } else {
+                    let slot = self.define(&name.name, &name.span)?;
+                    self.instruction(Instruction::SetLocal(slot));
+                }
+            }
+            StmtKind::Assign { target, value } => {
+                self.expr(value)?;
+                self.set(&target.name);
+            }
+            StmtKind::If { condition, then_branch, else_branch } => {
+                self.expr(condition)?;
+                let to_else = self.jump(Instruction::JumpIfFalse(0));
+                self.block(then_branch)?;
+                match else_branch {
+                    Some(else_branch) => {
+                        let to_end = self.jump(Instruction::Jump(0));
+                        self.patch(to_else);
+                        self.stmt(else_branch)?;
+                        self.patch(to_end);
+                    }
+                    None => self.patch(to_else),
+                }
+            }
+            StmtKind::While { condition, body } => {
+                let start = self.code.len() as u32;
+                self.expr(condition)?;
+                let to_end = self.jump(Instruction::JumpIfFalse(0));
+                self.block(body)?;
+                self.instruction(Instruction::Jump(start));
+                self.patch(to_end);
+            }
+            // The iterable and the index of the next element are kept in two hidden slots
+            StmtKind::For { variable, iterable, body } => {
+                self.expr(iterable)?;
+                self.scoped(|emitter| {
+                    let iterator = emitter.define("", &stmt.span)?;
+                    emitter.define("", &stmt.span)?;
+                    emitter.instruction(Instruction::SetLocal(iterator));
+                    let zero = emitter.emitter.constant(Constant::Integer(0), &stmt.span)?;
+                    emitter.instruction(Instruction::Constant(zero));
+                    emitter.instruction(Instruction::SetLocal(iterator + 1));
+
+                    let start = emitter.code.len() as u32;
+                    let to_end = emitter.jump(Instruction::IterNext(iterator, 0));
+                    let slot = emitter.define(&variable.name, &variable.span)?;
+                    emitter.instruction(Instruction::SetLocal(slot));
+                    emitter.block(body)?;
+                    emitter.instruction(Instruction::Jump(start));
+                    emitter.patch(to_end);
+                    Ok(())
+                })?;
+            }
+            StmtKind::Return(value) => {
+                match value {
+                    Some(value) => self.expr(value)?,
+                    None => self.instruction(Instruction::Nil),
+                }
+                self.instruction(Instruction::Return);
+            }
+            StmtKind::Block(block) => self.block(block)?,
+            StmtKind::Expr(expr) => {
+                self.expr(expr)?;
+                self.instruction(Instruction::Pop);
+            }
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &Expr) -> EmitResult<()> {
+        match &expr.kind {
+            ExprKind::Literal(Literal::Boolean(true)) => self.instruction(Instruction::True),
+            ExprKind::Literal(Literal::Boolean(false)) => self.instruction(Instruction::False),
+            ExprKind::Literal(literal) => {
+                let constant = match literal {
+                    Literal::Integer(value) => Constant::Integer(*value),
+                    Literal::Float(value) => Constant::Float(*value),
+                    Literal::String(value) => Constant::String(value.clone()),
+                    Literal::Boolean(_) => unreachable!("booleans have their own instructions"),
+                };
+                let index = self.emitter.constant(constant, &expr.span)?;
+                self.instruction(Instruction::Constant(index));
+            }
+            ExprKind::Identifier(name) => self.get(name, &expr.span)?,
+            ExprKind::Unary { op, operand } => {
+                self.expr(operand)?;
+                self.instruction(match op {
+                    UnaryOp::Neg => Instruction::Neg,
+                    UnaryOp::Not => Instruction::Not,
+                });
+            }
+            // The right operand is only evaluated if the left one doesn't give the result
+            ExprKind::Binary { op: op @ (BinaryOp::And | BinaryOp::Or), left, right } => {
+                self.expr(left)?;
+                self.instruction(Instruction::Dup);
+                let to_end = match op {
+                    BinaryOp::And => self.jump(Instruction::JumpIfFalse(0)),
+                    _ => self.jump(Instruction::JumpIfTrue(0)),
+                };
+                self.instruction(Instruction::Pop);
+                self.expr(right)?;
+                self.patch(to_end);
+            }
+            ExprKind::Binary { op, left, right } => {
+                self.expr(left)?;
+                self.expr(right)?;
+                self.instruction(match op {
+                    BinaryOp::Add => Instruction::Add,
+                    BinaryOp::Sub => Instruction::Sub,
+                    BinaryOp::Mul => Instruction::Mul,
+                    BinaryOp::Div => Instruction::Div,
+                    BinaryOp::Rem => Instruction::Rem,
+                    BinaryOp::Lt => Instruction::Lt,
+                    BinaryOp::Le => Instruction::Le,
+                    BinaryOp::Gt => Instruction::Gt,
+                    BinaryOp::Ge => Instruction::Ge,
+                    BinaryOp::Eq => Instruction::Eq,
+                    BinaryOp::Ne => Instruction::Ne,
+                    BinaryOp::And | BinaryOp::Or => unreachable!("logical operators are emitted as jumps"),
+                });
+            }
+            ExprKind::Call { callee, arguments } => {
+                self.expr(callee)?;
+                for argument in arguments {
+                    self.expr(argument)?;
+                }
+                let count = u8::try_from(arguments.len())
+                    .map_err(|_| Box::new(Diagnostic::error("too many arguments in the call", expr.span.clone())))?;
+                self.instruction(Instruction::Call(count));
+            }
+            ExprKind::Interpolation(parts) => {
+                for part in parts {
+                    match part {
+                        StringPart::Text(text) => {
+                            let index = self.emitter.constant(Constant::String(text.clone()), &expr.span)?;
+                            self.instruction(Instruction::Constant(index));
+                        }
+                        StringPart::Expr(part) => self.expr(part)?,
+                    }
+                }
+                let count = u16::try_from(parts.len())
+                    .map_err(|_| Box::new(Diagnostic::error("too many parts in the string", expr.span.clone())))?;
+                self.instruction(Instruction::Format(count));
+            }
+        }
+        Ok(())
+    }
+}
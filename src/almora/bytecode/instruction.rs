@@ -0,0 +1,233 @@
+use std::fmt::{Display, Formatter};
+
+/// Instruction of the VM, encoded as an opcode byte followed by its operands in little endian.
+///
+/// The jumps are absolute offsets in the code of the function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// Pushes the constant with the given index
+    Constant(u16),
+    Nil,
+    True,
+    False,
+    Pop,
+    /// Pushes the value on top of the stack again
+    Dup,
+    GetLocal(u16),
+    /// Pops the value and stores it in the local slot
+    SetLocal(u16),
+    GetGlobal(u16),
+    /// Pops the value and stores it in the global
+    SetGlobal(u16),
+    /// Pushes the builtin with the given index in the builtins of the module
+    GetBuiltin(u16),
+    /// Pops the given number of values, and pushes the concatenation of their texts
+    Format(u16),
+    Neg,
+    Not,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    Jump(u32),
+    /// Pops the condition, and jumps if it is false
+    JumpIfFalse(u32),
+    /// Pops the condition, and jumps if it is true
+    JumpIfTrue(u32),
+    /// Calls the value below the given number of arguments
+    Call(u8),
+    /// Returns the value on top of the stack
+    Return,
+    /// Pushes the next element of the iterable stored in the local slot, whose index is in the next slot.
+    /// If there is none, jumps to the given offset.
+    IterNext(u16, u32),
+}
+
+impl Instruction {
+    /// Appends the encoded instruction to the code.
+    pub fn encode(&self, code: &mut Vec<u8>) {
+        code.push(self.opcode());
+        match *self {
+            Instruction::Constant(index)
+            | Instruction::GetLocal(index)
+            | Instruction::SetLocal(index)
+            | Instruction::GetGlobal(index)
+            | Instruction::SetGlobal(index)
+            | Instruction::GetBuiltin(index)
+            | Instruction::Format(index) => code.extend_from_slice(&index.to_le_bytes()),
+            Instruction::Jump(target) | Instruction::JumpIfFalse(target) | Instruction::JumpIfTrue(target) => {
+                code.extend_from_slice(&target.to_le_bytes())
+            }
+            Instruction::Call(arguments) => code.push(arguments),
+            Instruction::IterNext(slot, exit) => {
+                code.extend_from_slice(&slot.to_le_bytes());
+                code.extend_from_slice(&exit.to_le_bytes());
+            }
+            _ => {}
+        }
+    }
+
+    /// Decodes the instruction at the offset, and returns it with the offset of the next one.
+    ///
+    /// Returns None if the code ends in the middle of the instruction, or if the opcode is unknown.
+    pub fn decode(code: &[u8], offset: usize) -> Option<(Self, usize)> {
+        let u16_at = |at: usize| Some(u16::from_le_bytes(code.get(at..at + 2)?.try_into().ok()?));
+        let u32_at = |at: usize| Some(u32::from_le_bytes(code.get(at..at + 4)?.try_into().ok()?));
+        let operand = offset + 1;
+
+        let instruction = match *code.get(offset)? {
+            0 => Instruction::Constant(u16_at(operand)?),
+            1 => Instruction::Nil,
+            2 => Instruction::True,
+            3 => Instruction::False,
+            4 => Instruction::Pop,
+            5 => Instruction::Dup,
+            6 => Instruction::GetLocal(u16_at(operand)?),
+            7 => Instruction::SetLocal(u16_at(operand)?),
+            8 => Instruction::GetGlobal(u16_at(operand)?),
+            9 => Instruction::SetGlobal(u16_at(operand)?),
+            10 => Instruction::Neg,
+            11 => Instruction::Not,
+            12 => Instruction::Add,
+            13 => Instruction::Sub,
+            14 => Instruction::Mul,
+            15 => Instruction::Div,
+            16 => Instruction::Rem,
+            17 => Instruction::Lt,
+            18 => Instruction::Le,
+            19 => Instruction::Gt,
+            20 => Instruction::Ge,
+            21 => Instruction::Eq,
+            22 => Instruction::Ne,
+            23 => Instruction::Jump(u32_at(operand)?),
+            24 => Instruction::JumpIfFalse(u32_at(operand)?),
+            25 => Instruction::JumpIfTrue(u32_at(operand)?),
+            26 => Instruction::Call(*code.get(operand)?),
+            27 => Instruction::Return,
+            28 => Instruction::IterNext(u16_at(operand)?, u32_at(operand + 2)?),
+            29 => Instruction::GetBuiltin(u16_at(operand)?),
+            30 => Instruction::Format(u16_at(operand)?),
+            _ => return None,
+        };
+        Some((instruction, offset + instruction.size()))
+    }
+
+    /// Returns the number of bytes of the encoded instruction.
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::Constant(_)
+            | Instruction::GetLocal(_)
+            | Instruction::SetLocal(_)
+            | Instruction::GetGlobal(_)
+            | Instruction::SetGlobal(_)
+            | Instruction::GetBuiltin(_)
+            | Instruction::Format(_) => 3,
+            Instruction::Jump(_) | Instruction::JumpIfFalse(_) | Instruction::JumpIfTrue(_) => 5,
+            Instruction::Call(_) => 2,
+            Instruction::IterNext(_, _) => 7,
+            _ => 1,
+        }
+    }
+
+    fn opcode(&self) -> u8 {
+        match self {
+            Instruction::Constant(_) => 0,
+            Instruction::Nil => 1,
+            Instruction::True => 2,
+            Instruction::False => 3,
+            Instruction::Pop => 4,
+            Instruction::Dup => 5,
+            Instruction::GetLocal(_) => 6,
+            Instruction::SetLocal(_) => 7,
+            Instruction::GetGlobal(_) => 8,
+            Instruction::SetGlobal(_) => 9,
+            Instruction::Neg => 10,
+            Instruction::Not => 11,
+            Instruction::Add => 12,
+            Instruction::Sub => 13,
+            Instruction::Mul => 14,
+            Instruction::Div => 15,
+            Instruction::Rem => 16,
+            Instruction::Lt => 17,
+            Instruction::Le => 18,
+            Instruction::Gt => 19,
+            Instruction::Ge => 20,
+            Instruction::Eq => 21,
+            Instruction::Ne => 22,
+            Instruction::Jump(_) => 23,
+            Instruction::JumpIfFalse(_) => 24,
+            Instruction::JumpIfTrue(_) => 25,
+            Instruction::Call(_) => 26,
+            Instruction::Return => 27,
+            Instruction::IterNext(_, _) => 28,
+            Instruction::GetBuiltin(_) => 29,
+            Instruction::Format(_) => 30,
+        }
+    }
+}
+
+/// Writes the instruction like `Constant 3` or `IterNext 2 40`.
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Instruction::Constant(index) => write!(f, "Constant {}", index),
+            Instruction::GetLocal(slot) => write!(f, "GetLocal {}", slot),
+            Instruction::SetLocal(slot) => write!(f, "SetLocal {}", slot),
+            Instruction::GetGlobal(index) => write!(f, "GetGlobal {}", index),
+            Instruction::SetGlobal(index) => write!(f, "SetGlobal {}", index),
+            Instruction::GetBuiltin(index) => write!(f, "GetBuiltin {}", index),
+            Instruction::Format(count) => write!(f, "Format {}", count),
+            Instruction::Jump(target) => write!(f, "Jump {}", target),
+            Instruction::JumpIfFalse(target) => write!(f, "JumpIfFalse {}", target),
+            Instruction::JumpIfTrue(target) => write!(f, "JumpIfTrue {}", target),
+            Instruction::Call(arguments) => write!(f, "Call {}", arguments),
+            Instruction::IterNext(slot, exit) => write!(f, "IterNext {} {}", slot, exit),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instruction_encoding() {
+        let instructions = [
+            Instruction::Constant(300),
+            Instruction::Add,
+            Instruction::JumpIfFalse(70000),
+            Instruction::Call(2),
+            Instruction::IterNext(1, 12),
+            Instruction::GetBuiltin(3),
+            Instruction::Return,
+        ];
+        let mut code = Vec::new();
+        for instruction in &instructions {
+            instruction.encode(&mut code);
+        }
+        assert_eq!(code.len(), instructions.iter().map(Instruction::size).sum::<usize>());
+        assert_eq!(&code[..4], [0, 44, 1, 12]);
+
+        let mut decoded = Vec::new();
+        let mut offset = 0;
+        while let Some((instruction, next)) = Instruction::decode(&code, offset) {
+            decoded.push(instruction);
+            offset = next;
+        }
+        assert_eq!(decoded, instructions);
+        assert_eq!(offset, code.len());
+
+        // Truncated operands and unknown opcodes
+        assert_eq!(Instruction::decode(&[0, 1], 0), None);
+        assert_eq!(Instruction::decode(&[200], 0), None);
+        assert_eq!(Instruction::IterNext(1, 12).to_string(), "IterNext 1 12");
+    }
+}
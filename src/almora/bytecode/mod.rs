@@ -0,0 +1,252 @@
+//! Bytecode of the almora programs: the format, the emitter from the AST, and the VM that runs it.
+//!
+//! A compiled module can be saved in an `.almc` file with `Module::to_bytes`, and loaded back with `Module::from_bytes`.
+
+mod disassembler;
+mod emitter;
+mod instruction;
+mod vm;
+
+pub use disassembler::disassemble;
+pub use emitter::{emit, emit_with};
+pub use instruction::Instruction;
+pub use vm::{RuntimeError, Value, Vm};
+
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+};
+
+/// First bytes of an `.almc` file.
+pub const MAGIC: &[u8; 4] = b"ALMC";
+/// Version of the format written by `Module::to_bytes`. Other versions are rejected.
+pub const FORMAT_VERSION: u16 = 2;
+
+/// Value known at compile time, stored in the constants pool of a module.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Integer(i64),
+    Float(f64),
+    String(String),
+    /// Function of the module, by index
+    Function(u16),
+}
+
+/// Code of a function, with the number of local slots it needs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionCode {
+    pub name: String,
+    /// Number of parameters, which are the first local slots.
+    pub arity: u8,
+    pub locals: u16,
+    pub code: Vec<u8>,
+}
+
+/// Compiled program: its functions, the constants they use, and the names of the globals and of the builtins.
+///
+/// The first function is the top-level code of the program, run by `Vm::run`. The builtins are found by name when the
+/// VM is created.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Module {
+    pub constants: Vec<Constant>,
+    pub globals: Vec<String>,
+    pub builtins: Vec<String>,
+    pub functions: Vec<FunctionCode>,
+}
+
+/// Error while loading a module from bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BytecodeError {
+    /// The bytes don't start with `MAGIC`
+    InvalidMagic,
+    /// The module was written with another version of the format
+    UnsupportedVersion(u16),
+    /// The bytes end before the module
+    Truncated,
+    /// Unknown tag of a constant
+    InvalidConstant(u8),
+    /// A string is not valid UTF-8
+    InvalidString,
+}
+
+impl Module {
+    /// Encodes the module, to write it in an `.almc` file.
+    ///
+    /// The numbers are in little endian, and the lists and strings are prefixed by their length on 4 bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+
+        write_len(&mut bytes, self.constants.len());
+        for constant in &self.constants {
+            match constant {
+                Constant::Integer(value) => {
+                    bytes.push(0);
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+                Constant::Float(value) => {
+                    bytes.push(1);
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+                Constant::String(value) => {
+                    bytes.push(2);
+                    write_bytes(&mut bytes, value.as_bytes());
+                }
+                Constant::Function(index) => {
+                    bytes.push(3);
+                    bytes.extend_from_slice(&index.to_le_bytes());
+                }
+            }
+        }
+
+        write_len(&mut bytes, self.globals.len());
+        for global in &self.globals {
+            write_bytes(&mut bytes, global.as_bytes());
+        }
+
+        write_len(&mut bytes, self.builtins.len());
+        for builtin in &self.builtins {
+            write_bytes(&mut bytes, builtin.as_bytes());
+        }
+
+        write_len(&mut bytes, self.functions.len());
+        for function in &self.functions {
+            write_bytes(&mut bytes, function.name.as_bytes());
+            bytes.push(function.arity);
+            bytes.extend_from_slice(&function.locals.to_le_bytes());
+            write_bytes(&mut bytes, &function.code);
+        }
+        bytes
+    }
+
+    /// Decodes a module written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytecodeError> {
+        let mut reader = ByteReader { bytes, offset: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(BytecodeError::InvalidMagic);
+        }
+        let version = u16::from_le_bytes(reader.array()?);
+        if version != FORMAT_VERSION {
+            return Err(BytecodeError::UnsupportedVersion(version));
+        }
+
+        let mut module = Module::default();
+        for _ in 0..reader.len()? {
+            let constant = match reader.take(1)?[0] {
+                0 => Constant::Integer(i64::from_le_bytes(reader.array()?)),
+                1 => Constant::Float(f64::from_le_bytes(reader.array()?)),
+                2 => Constant::String(reader.string()?),
+                3 => Constant::Function(u16::from_le_bytes(reader.array()?)),
+                tag => return Err(BytecodeError::InvalidConstant(tag)),
+            };
+            module.constants.push(constant);
+        }
+        for _ in 0..reader.len()? {
+            module.globals.push(reader.string()?);
+        }
+        for _ in 0..reader.len()? {
+            module.builtins.push(reader.string()?);
+        }
+        for _ in 0..reader.len()? {
+            let name = reader.string()?;
+            let arity = reader.take(1)?[0];
+            let locals = u16::from_le_bytes(reader.array()?);
+            let len = reader.len()?;
+            let code = reader.take(len)?.to_vec();
+            module.functions.push(FunctionCode { name, arity, locals, code });
+        }
+        Ok(module)
+    }
+}
+
+fn write_len(bytes: &mut Vec<u8>, len: usize) {
+    bytes.extend_from_slice(&(len as u32).to_le_bytes());
+}
+
+fn write_bytes(bytes: &mut Vec<u8>, value: &[u8]) {
+    write_len(bytes, value.len());
+    bytes.extend_from_slice(value);
+}
+
+/// Reads the parts of an encoded module in order.
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], BytecodeError> {
+        let end = self.offset.checked_add(len).ok_or(BytecodeError::Truncated)?;
+        let taken = self.bytes.get(self.offset..end).ok_or(BytecodeError::Truncated)?;
+        self.offset = end;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], BytecodeError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn len(&mut self) -> Result<usize, BytecodeError> {
+        Ok(u32::from_le_bytes(self.array()?) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, BytecodeError> {
+        let len = self.len()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| BytecodeError::InvalidString)
+    }
+}
+
+impl Display for BytecodeError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            BytecodeError::InvalidMagic
+                => write!(f, "Not an almora module: the magic number is missing."),
+            BytecodeError::UnsupportedVersion(version)
+                => write!(f, "Unsupported module format version {} (expected {}).", version, FORMAT_VERSION),
+            BytecodeError::Truncated
+                => write!(f, "The module is truncated."),
+            BytecodeError::InvalidConstant(tag)
+                => write!(f, "Invalid constant tag {}.", tag),
+            BytecodeError::InvalidString
+                => write!(f, "A string of the module is not valid UTF-8."),
+        }
+    }
+}
+
+impl Error for BytecodeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_bytes() {
+        let module = Module {
+            constants: vec![
+                Constant::Integer(-3),
+                Constant::Float(0.5),
+                Constant::String("héllo".to_string()),
+                Constant::Function(1),
+            ],
+            globals: vec!["x".to_string()],
+            builtins: vec!["print".to_string()],
+            functions: vec![
+                FunctionCode { name: "main".to_string(), arity: 0, locals: 0, code: vec![1, 27] },
+                FunctionCode { name: "f".to_string(), arity: 2, locals: 3, code: vec![6, 0, 0, 27] },
+            ],
+        };
+
+        let bytes = module.to_bytes();
+        assert_eq!(&bytes[..6], b"ALMC\x02\x00");
+        assert_eq!(Module::from_bytes(&bytes), Ok(module));
+
+        assert_eq!(Module::from_bytes(b"ELF\x7f"), Err(BytecodeError::InvalidMagic));
+        assert_eq!(Module::from_bytes(b"ALMC\x01\x00"), Err(BytecodeError::UnsupportedVersion(1)));
+        assert_eq!(Module::from_bytes(&bytes[..bytes.len() - 1]), Err(BytecodeError::Truncated));
+        assert_eq!(
+            Module::from_bytes(b"ALMC\x02\x00\x01\x00\x00\x00\x09"),
+            Err(BytecodeError::InvalidConstant(9))
+        );
+    }
+}
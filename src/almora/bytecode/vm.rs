@@ -0,0 +1,589 @@
+use std::{
+    cmp::Ordering,
+    error::Error,
+    fmt::{Display, Formatter},
+    io::{self, Write},
+    rc::Rc,
+};
+
+use crate::almora::{Builtin, Builtins, CallContext};
+
+use super::{Constant, Instruction, Module};
+
+/// Maximum number of nested calls, to stop infinite recursions.
+const MAX_FRAMES: usize = 256;
+
+/// Value handled by the VM.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Nil,
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    String(Rc<str>),
+    /// Function of the module, by index
+    Function(u16),
+    /// Builtin of the module, by index
+    Builtin(u16),
+}
+
+impl Value {
+    /// Name of the type of the value, used in the errors.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Integer(_) => "int",
+            Value::Float(_) => "float",
+            Value::Boolean(_) => "bool",
+            Value::String(_) => "string",
+            Value::Function(_) | Value::Builtin(_) => "function",
+        }
+    }
+
+    fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Integer(value) => Some(*value as f64),
+            Value::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl From<&Constant> for Value {
+    fn from(constant: &Constant) -> Self {
+        match constant {
+            Constant::Integer(value) => Value::Integer(*value),
+            Constant::Float(value) => Value::Float(*value),
+            Constant::String(value) => Value::String(Rc::from(value.as_str())),
+            Constant::Function(index) => Value::Function(*index),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{:?}", value),
+            Value::Boolean(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Function(index) => write!(f, "<function {}>", index),
+            Value::Builtin(index) => write!(f, "<builtin {}>", index),
+        }
+    }
+}
+
+/// Error while running a module.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    /// The operator can't be applied to values of these types
+    TypeMismatch { operator: &'static str, operands: Vec<&'static str> },
+    /// The condition of a jump is not a boolean
+    NotABoolean(&'static str),
+    DivisionByZero,
+    /// The result of an integer operation doesn't fit in 64 bits
+    IntegerOverflow,
+    NotCallable(&'static str),
+    ArityMismatch { function: String, expected: u8, found: u8 },
+    /// A `for` loop iterates on a value which is not an integer or a string
+    NotIterable(&'static str),
+    /// Too many nested calls
+    StackOverflow,
+    /// The module uses a builtin that the VM doesn't have
+    UnknownBuiltin(String),
+    /// A builtin rejected its arguments
+    Builtin { function: String, message: String },
+    /// The code is not valid, in the given function at the given offset
+    InvalidCode { function: String, offset: usize },
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            RuntimeError::TypeMismatch { operator, operands }
+                => write!(f, "Cannot apply `{}` to {}.", operator, operands.join(" and ")),
+            RuntimeError::NotABoolean(found)
+                => write!(f, "Expected a bool condition, found {}.", found),
+            RuntimeError::DivisionByZero
+                => write!(f, "Division by zero."),
+            RuntimeError::IntegerOverflow
+                => write!(f, "Integer overflow."),
+            RuntimeError::NotCallable(found)
+                => write!(f, "Cannot call a value of type {}.", found),
+            RuntimeError::ArityMismatch { function, expected, found }
+                => write!(f, "`{}` expects {} arguments, but {} were given.", function, expected, found),
+            RuntimeError::NotIterable(found)
+                => write!(f, "Cannot iterate on a value of type {}.", found),
+            RuntimeError::StackOverflow
+                => write!(f, "Stack overflow: too many nested calls."),
+            RuntimeError::UnknownBuiltin(name)
+                => write!(f, "Unknown builtin `{}`.", name),
+            RuntimeError::Builtin { function, message }
+                => write!(f, "`{}`: {}.", function, message),
+            RuntimeError::InvalidCode { function, offset }
+                => write!(f, "Invalid bytecode in `{}` at offset {}.", function, offset),
+        }
+    }
+}
+
+impl Error for RuntimeError {}
+
+/// Call of a function being run.
+struct Frame {
+    function: usize,
+    ip: usize,
+    /// Index in the stack of the first local slot, which is the first argument.
+    base: usize,
+}
+
+/// Stack machine running the code of a module.
+///
+/// The values of a call are on the stack: the function, then its local slots starting with the arguments, then the
+/// operands of its instructions. The builtins are called directly, without a frame.
+pub struct Vm<'m> {
+    module: &'m Module,
+    constants: Vec<Value>,
+    globals: Vec<Value>,
+    /// Builtins of the module, or None for the ones that were not given to the VM.
+    builtins: Vec<Option<Builtin>>,
+    /// Where the builtins write, or None for the standard output.
+    output: Option<&'m mut dyn Write>,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+}
+
+impl<'m> Vm<'m> {
+    /// Creates a VM with the standard builtins.
+    pub fn new(module: &'m Module) -> Self {
+        Self::with_builtins(module, &Builtins::standard())
+    }
+
+    /// Creates a VM with the given builtins. Using a builtin of the module which is not one of them fails at runtime.
+    pub fn with_builtins(module: &'m Module, builtins: &Builtins) -> Self {
+        Self {
+            module,
+            constants: module.constants.iter().map(Value::from).collect(),
+            globals: vec![Value::Nil; module.globals.len()],
+            builtins: module.builtins.iter().map(|name| builtins.get(name).cloned()).collect(),
+            output: None,
+            stack: Vec::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Makes the builtins like `print` write to the output instead of the standard output.
+    pub fn with_output(mut self, output: &'m mut dyn Write) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    /// Returns the value of the global with the given name, if the module has one.
+    pub fn global(&self, name: &str) -> Option<&Value> {
+        let index = self.module.globals.iter().position(|global| global == name)?;
+        self.globals.get(index)
+    }
+
+    /// Runs the top-level code of the module, and returns the value it returns.
+    pub fn run(&mut self) -> Result<Value, RuntimeError> {
+        self.stack.clear();
+        self.frames.clear();
+        let main = self.module.functions.first().ok_or_else(|| self.invalid_code())?;
+        self.stack.resize(main.locals as usize, Value::Nil);
+        self.frames.push(Frame { function: 0, ip: 0, base: 0 });
+
+        loop {
+            let frame = self.frames.last_mut().expect("a function is running");
+            let code = &self.module.functions[frame.function].code;
+            let Some((instruction, next)) = Instruction::decode(code, frame.ip) else {
+                return Err(self.invalid_code());
+            };
+            frame.ip = next;
+            let base = frame.base;
+
+            match instruction {
+                Instruction::Constant(index) => {
+                    let value = self.constants.get(index as usize).cloned().ok_or_else(|| self.invalid_code())?;
+                    self.stack.push(value);
+                }
+                Instruction::Nil => self.stack.push(Value::Nil),
+                Instruction::True => self.stack.push(Value::Boolean(true)),
+                Instruction::False => self.stack.push(Value::Boolean(false)),
+                Instruction::Pop => {
+                    self.pop()?;
+                }
+                Instruction::Dup => {
+                    let value = self.peek()?.clone();
+                    self.stack.push(value);
+                }
+                Instruction::GetLocal(slot) => {
+                    let value = self.stack.get(base + slot as usize).cloned().ok_or_else(|| self.invalid_code())?;
+                    self.stack.push(value);
+                }
+                Instruction::SetLocal(slot) => {
+                    let value = self.pop()?;
+                    let slot = base + slot as usize;
+                    if slot >= self.stack.len() {
+                        return Err(self.invalid_code());
+                    }
+                    self.stack[slot] = value;
+                }
+                Instruction::GetGlobal(index) => {
+                    let value = self.globals.get(index as usize).cloned().ok_or_else(|| self.invalid_code())?;
+                    self.stack.push(value);
+                }
+                Instruction::SetGlobal(index) => {
+                    let value = self.pop()?;
+                    if index as usize >= self.globals.len() {
+                        return Err(self.invalid_code());
+                    }
+                    self.globals[index as usize] = value;
+                }
+                Instruction::GetBuiltin(index) => match self.builtins.get(index as usize) {
+                    Some(Some(_)) => self.stack.push(Value::Builtin(index)),
+                    Some(None) => {
+                        return Err(RuntimeError::UnknownBuiltin(self.module.builtins[index as usize].clone()))
+                    }
+                    None => return Err(self.invalid_code()),
+                },
+                Instruction::Format(count) => {
+                    let start = self.stack.len().checked_sub(count as usize).ok_or_else(|| self.invalid_code())?;
+                    let text: String = self.stack.drain(start..).map(|value| value.to_string()).collect();
+                    self.stack.push(Value::String(Rc::from(text)));
+                }
+                Instruction::Neg => {
+                    let value = match self.pop()? {
+                        Value::Integer(value) => Value::Integer(value.checked_neg().ok_or(RuntimeError::IntegerOverflow)?),
+                        Value::Float(value) => Value::Float(-value),
+                        other => return Err(mismatch("-", &[&other])),
+                    };
+                    self.stack.push(value);
+                }
+                Instruction::Not => {
+                    let value = match self.pop()? {
+                        Value::Boolean(value) => Value::Boolean(!value),
+                        other => return Err(mismatch("!", &[&other])),
+                    };
+                    self.stack.push(value);
+                }
+                Instruction::Add
+                | Instruction::Sub
+                | Instruction::Mul
+                | Instruction::Div
+                | Instruction::Rem
+                | Instruction::Lt
+                | Instruction::Le
+                | Instruction::Gt
+                | Instruction::Ge
+                | Instruction::Eq
+                | Instruction::Ne => {
+                    let right = self.pop()?;
+                    let left = self.pop()?;
+                    self.stack.push(binary(instruction, left, right)?);
+                }
+                Instruction::Jump(target) => self.jump(target),
+                Instruction::JumpIfFalse(target) | Instruction::JumpIfTrue(target) => {
+                    let condition = match self.pop()? {
+                        Value::Boolean(condition) => condition,
+                        other => return Err(RuntimeError::NotABoolean(other.type_name())),
+                    };
+                    if condition == matches!(instruction, Instruction::JumpIfTrue(_)) {
+                        self.jump(target);
+                    }
+                }
+                Instruction::Call(count) => self.call(count)?,
+                Instruction::Return => {
+                    let value = self.pop()?;
+                    let frame = self.frames.pop().expect("a function is running");
+                    if self.frames.is_empty() {
+                        return Ok(value);
+                    }
+                    // Removes the function and its slots
+                    self.stack.truncate(frame.base - 1);
+                    self.stack.push(value);
+                }
+                Instruction::IterNext(slot, exit) => {
+                    let slot = base + slot as usize;
+                    let (Some(iterable), Some(Value::Integer(index))) = (self.stack.get(slot), self.stack.get(slot + 1))
+                    else {
+                        return Err(self.invalid_code());
+                    };
+                    let index = *index;
+                    let next = match iterable {
+                        Value::Integer(end) => (index < *end).then_some(Value::Integer(index)),
+                        Value::String(string) => {
+                            string.chars().nth(index as usize).map(|c| Value::String(Rc::from(c.to_string())))
+                        }
+                        other => return Err(RuntimeError::NotIterable(other.type_name())),
+                    };
+                    match next {
+                        Some(value) => {
+                            self.stack[slot + 1] = Value::Integer(index + 1);
+                            self.stack.push(value);
+                        }
+                        None => self.jump(exit),
+                    }
+                }
+            }
+        }
+    }
+
+    fn call(&mut self, count: u8) -> Result<(), RuntimeError> {
+        let base = self.stack.len().checked_sub(count as usize).ok_or_else(|| self.invalid_code())?;
+        let callee = base.checked_sub(1).and_then(|index| self.stack.get(index)).ok_or_else(|| self.invalid_code())?;
+        let index = match callee {
+            Value::Function(index) => *index as usize,
+            Value::Builtin(index) => return self.call_builtin(*index as usize, count),
+            other => return Err(RuntimeError::NotCallable(other.type_name())),
+        };
+        let function = self.module.functions.get(index).ok_or_else(|| self.invalid_code())?;
+        if function.arity != count {
+            return Err(RuntimeError::ArityMismatch {
+                function: function.name.clone(),
+                expected: function.arity,
+                found: count,
+            });
+        }
+        if self.frames.len() >= MAX_FRAMES {
+            return Err(RuntimeError::StackOverflow);
+        }
+
+        self.stack.resize(base + (function.locals as usize).max(count as usize), Value::Nil);
+        self.frames.push(Frame { function: index, ip: 0, base });
+        Ok(())
+    }
+
+    /// Calls the builtin with the arguments on top of the stack, and replaces them and the builtin by its result.
+    fn call_builtin(&mut self, index: usize, count: u8) -> Result<(), RuntimeError> {
+        let Some(Some(builtin)) = self.builtins.get(index).cloned() else {
+            return Err(self.invalid_code());
+        };
+        if let Some(arity) = builtin.arity().filter(|arity| *arity != count) {
+            let function = builtin.name().to_string();
+            return Err(RuntimeError::ArityMismatch { function, expected: arity, found: count });
+        }
+
+        let arguments = self.stack.split_off(self.stack.len() - count as usize);
+        self.stack.pop();
+        let mut stdout = io::stdout();
+        let output: &mut dyn Write = match &mut self.output {
+            Some(output) => &mut **output,
+            None => &mut stdout,
+        };
+        let value = builtin
+            .call(&mut CallContext { output }, &arguments)
+            .map_err(|message| RuntimeError::Builtin { function: builtin.name().to_string(), message })?;
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn jump(&mut self, target: u32) {
+        self.frames.last_mut().expect("a function is running").ip = target as usize;
+    }
+
+    fn pop(&mut self) -> Result<Value, RuntimeError> {
+        self.stack.pop().ok_or_else(|| self.invalid_code())
+    }
+
+    fn peek(&self) -> Result<&Value, RuntimeError> {
+        self.stack.last().ok_or_else(|| self.invalid_code())
+    }
+
+    /// Error for the instruction being run.
+    fn invalid_code(&self) -> RuntimeError {
+        match self.frames.last() {
+            Some(frame) => RuntimeError::InvalidCode {
+                function: self.module.functions[frame.function].name.clone(),
+                offset: frame.ip,
+            },
+            None => RuntimeError::InvalidCode { function: String::new(), offset: 0 },
+        }
+    }
+}
+
+fn mismatch(operator: &'static str, operands: &[&Value]) -> RuntimeError {
+    RuntimeError::TypeMismatch { operator, operands: operands.iter().map(|value| value.type_name()).collect() }
+}
+
+/// Applies a binary operator. Integers stay integers, but are converted to floats when mixed with floats.
+fn binary(instruction: Instruction, left: Value, right: Value) -> Result<Value, RuntimeError> {
+    let operator = match instruction {
+        Instruction::Add => "+",
+        Instruction::Sub => "-",
+        Instruction::Mul => "*",
+        Instruction::Div => "/",
+        Instruction::Rem => "%",
+        Instruction::Lt => "<",
+        Instruction::Le => "<=",
+        Instruction::Gt => ">",
+        Instruction::Ge => ">=",
+        Instruction::Eq => "==",
+        Instruction::Ne => "!=",
+        _ => unreachable!("not a binary operator"),
+    };
+
+    match instruction {
+        Instruction::Eq => return Ok(Value::Boolean(equals(&left, &right))),
+        Instruction::Ne => return Ok(Value::Boolean(!equals(&left, &right))),
+        Instruction::Lt | Instruction::Le | Instruction::Gt | Instruction::Ge => {
+            let ordering = match (&left, &right) {
+                (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+                (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+                _ => match (left.as_float(), right.as_float()) {
+                    (Some(a), Some(b)) => a.partial_cmp(&b),
+                    _ => return Err(mismatch(operator, &[&left, &right])),
+                },
+            };
+            let result = match instruction {
+                Instruction::Lt => ordering == Some(Ordering::Less),
+                Instruction::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                Instruction::Gt => ordering == Some(Ordering::Greater),
+                _ => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+            };
+            return Ok(Value::Boolean(result));
+        }
+        _ => {}
+    }
+
+    match (&left, &right) {
+        (Value::Integer(a), Value::Integer(b)) => {
+            let result = match instruction {
+                Instruction::Add => a.checked_add(*b),
+                Instruction::Sub => a.checked_sub(*b),
+                Instruction::Mul => a.checked_mul(*b),
+                _ if *b == 0 => return Err(RuntimeError::DivisionByZero),
+                Instruction::Div => a.checked_div(*b),
+                _ => a.checked_rem(*b),
+            };
+            result.map(Value::Integer).ok_or(RuntimeError::IntegerOverflow)
+        }
+        (Value::String(a), Value::String(b)) if instruction == Instruction::Add => {
+            Ok(Value::String(Rc::from(format!("{}{}", a, b))))
+        }
+        _ => match (left.as_float(), right.as_float()) {
+            (Some(a), Some(b)) => Ok(Value::Float(match instruction {
+                Instruction::Add => a + b,
+                Instruction::Sub => a - b,
+                Instruction::Mul => a * b,
+                Instruction::Div => a / b,
+                _ => a % b,
+            })),
+            _ => Err(mismatch(operator, &[&left, &right])),
+        },
+    }
+}
+
+/// Values of different types are not equal, except integers and floats with the same value.
+fn equals(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Integer(a), Value::Float(b)) | (Value::Float(b), Value::Integer(a)) => *a as f64 == *b,
+        _ => left == right,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::almora::{
+        bytecode::{emit, emit_with},
+        compile, ResolveOptions,
+    };
+
+    use super::*;
+
+    /// Compiles and runs the source, and returns the value of the global `result`.
+    fn run(source: &str) -> Result<Value, RuntimeError> {
+        let module = emit(&compile(source).unwrap()).unwrap();
+        let mut vm = Vm::new(&module);
+        vm.run()?;
+        Ok(vm.global("result").unwrap().clone())
+    }
+
+    #[test]
+    fn test_vm_expressions() {
+        assert_eq!(run("let result = 1 + 2 * 3 - 4;"), Ok(Value::Integer(3)));
+        assert_eq!(run("let result = 7 / 2 + 7 % 2;"), Ok(Value::Integer(4)));
+        assert_eq!(run("let result = 1 + 0.5;"), Ok(Value::Float(1.5)));
+        assert_eq!(run("let result = -(2 - 5);"), Ok(Value::Integer(3)));
+        assert_eq!(run("let result = \"ab\" + \"c\";"), Ok(Value::String(Rc::from("abc"))));
+        assert_eq!(run("let result = 1 < 2 && 2.0 == 2 && !(\"a\" >= \"b\");"), Ok(Value::Boolean(true)));
+        assert_eq!(run("let result = 1 != 1 || 3 <= 2;"), Ok(Value::Boolean(false)));
+        assert_eq!(
+            run("let x = 2;\nlet result = \"${x} * 1.5 = ${x * 1.5}, ${x > 1}${\"!\"}\";"),
+            Ok(Value::String(Rc::from("2 * 1.5 = 3.0, true!")))
+        );
+    }
+
+    #[test]
+    fn test_vm_statements() {
+        let source = "let result = 0;\nlet i = 0;\nwhile i < 5 { if i % 2 == 0 { result = result + i; } else { } i = i + 1; }";
+        assert_eq!(run(source), Ok(Value::Integer(6)));
+
+        assert_eq!(run("let result = 0;\nfor i in 4 { let j = i * i; result = result + j; }"), Ok(Value::Integer(14)));
+        assert_eq!(run("let result = \"\";\nfor c in \"abc\" { result = c + result; }"), Ok(Value::String(Rc::from("cba"))));
+    }
+
+    #[test]
+    fn test_vm_functions() {
+        let source = "let result = fib(10);\nfn fib(n: int) -> int { if n < 2 { return n; } return fib(n - 1) + fib(n - 2); }";
+        assert_eq!(run(source), Ok(Value::Integer(55)));
+
+        // Functions return nil by default, and can read globals
+        assert_eq!(run("let x = 2;\nfn f() { x = x * 10; }\nlet result = f();"), Ok(Value::Nil));
+        assert_eq!(run("let result = 1;\nfn f(a: int) { let b = a + 1; result = b; }\nf(4);"), Ok(Value::Integer(5)));
+    }
+
+    #[test]
+    fn test_vm_errors() {
+        assert_eq!(run("let result = 1 / 0;"), Err(RuntimeError::DivisionByZero));
+        assert_eq!(run("let result = 1 + true;"), Err(mismatch("+", &[&Value::Integer(1), &Value::Boolean(true)])));
+        assert_eq!(run("let result = 9223372036854775807 + 1;"), Err(RuntimeError::IntegerOverflow));
+        assert_eq!(run("if 1 { }\nlet result = 0;"), Err(RuntimeError::NotABoolean("int")));
+        assert_eq!(run("let result = 1;\nresult();"), Err(RuntimeError::NotCallable("int")));
+        assert_eq!(run("fn f() {}\nlet result = 1;\nfor x in f { }"), Err(RuntimeError::NotIterable("function")));
+        assert_eq!(
+            run("fn f(a: int) {}\nlet result = f();"),
+            Err(RuntimeError::ArityMismatch { function: "f".to_string(), expected: 1, found: 0 })
+        );
+        assert_eq!(run("fn f() { f(); }\nlet result = f();"), Err(RuntimeError::StackOverflow));
+        assert_eq!(
+            RuntimeError::TypeMismatch { operator: "-", operands: vec!["string"] }.to_string(),
+            "Cannot apply `-` to string."
+        );
+    }
+
+    #[test]
+    fn test_vm_builtins() {
+        let source = "fn f(s: string) { print(s, len(s)); }\nf(\"ab\");\nprint(max(1, 2.5));";
+        let module = emit(&compile(source).unwrap()).unwrap();
+        let mut output = Vec::new();
+        assert_eq!(Vm::new(&module).with_output(&mut output).run(), Ok(Value::Nil));
+        assert_eq!(String::from_utf8(output).unwrap(), "ab 2\n2.5\n");
+        assert_eq!(module.builtins, ["print", "max", "len"]);
+
+        assert_eq!(run("let result = upper(substring(\"hello\", 1, 3));"), Ok(Value::String(Rc::from("EL"))));
+        assert_eq!(
+            run("let result = len(1);"),
+            Err(RuntimeError::Builtin {
+                function: "len".to_string(),
+                message: "expected a string, found int".to_string()
+            })
+        );
+        assert_eq!(
+            run("let result = sqrt();"),
+            Err(RuntimeError::ArityMismatch { function: "sqrt".to_string(), expected: 1, found: 0 })
+        );
+
+        // Host applications give their own builtins to the resolver and to the VM
+        let mut builtins = Builtins::new();
+        builtins.register("twice", Some(1), |_, arguments| match arguments {
+            [Value::Integer(value)] => Ok(Value::Integer(value * 2)),
+            _ => Err("expected an int".to_string()),
+        });
+        let options = ResolveOptions::new().with_builtins(&builtins);
+        let module = emit_with(&compile("let result = twice(21);").unwrap(), &options).unwrap();
+        let mut vm = Vm::with_builtins(&module, &builtins);
+        vm.run().unwrap();
+        assert_eq!(vm.global("result"), Some(&Value::Integer(42)));
+        assert_eq!(Vm::new(&module).run(), Err(RuntimeError::UnknownBuiltin("twice".to_string())));
+    }
+}
@@ -0,0 +1,213 @@
+use std::fmt::{Display, Formatter};
+
+use crate::parser_lib::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The program is invalid, and the pass that found it stopped there: the next problems are not reported
+    Fatal,
+    /// The program is invalid
+    Error,
+    /// The program is valid, but probably not what was meant
+    Warning,
+}
+
+/// Problem found in a program, at the given span.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    /// More information, like where a conflicting name was defined.
+    pub notes: Vec<Note>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Self { severity: Severity::Error, message: message.into(), span, notes: Vec::new() }
+    }
+
+    pub fn fatal(message: impl Into<String>, span: Span) -> Self {
+        Self { severity: Severity::Fatal, message: message.into(), span, notes: Vec::new() }
+    }
+
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Self { severity: Severity::Warning, message: message.into(), span, notes: Vec::new() }
+    }
+
+    /// Adds a note, at the given span if any.
+    pub fn with_note(mut self, message: impl Into<String>, span: Option<Span>) -> Self {
+        self.notes.push(Note { message: message.into(), span });
+        self
+    }
+
+    /// Returns true if the program is invalid: the diagnostic is an error, or a fatal one.
+    pub fn is_error(&self) -> bool {
+        matches!(self.severity, Severity::Fatal | Severity::Error)
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Severity::Fatal => write!(f, "fatal error"),
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Keeps the diagnostics, in order, until the first fatal one or until there are more errors than the limit.
+///
+/// The error past the limit is replaced by a fatal diagnostic at its span, which tells that the pass stopped there.
+pub(crate) fn limit_errors(diagnostics: &mut Vec<Diagnostic>, limit: usize) {
+    let mut errors = 0;
+    for i in 0..diagnostics.len() {
+        if diagnostics[i].severity == Severity::Fatal {
+            diagnostics.truncate(i + 1);
+            return;
+        }
+        if diagnostics[i].is_error() {
+            errors += 1;
+            if errors > limit {
+                let message = format!("too many errors, stopped after {}", limit);
+                diagnostics[i] = Diagnostic::fatal(message, diagnostics[i].span.clone());
+                diagnostics.truncate(i + 1);
+                return;
+            }
+        }
+    }
+}
+
+/// Returns the candidate closest to a misspelled name, if one is close enough to be what was meant.
+///
+/// A candidate is close enough if it is at most one edit away for every three chars of the name, and at least one, but
+/// fewer edits than the name has chars. The first of the closest candidates is returned.
+pub(crate) fn suggest<'c>(name: &str, candidates: impl IntoIterator<Item = &'c str>) -> Option<&'c str> {
+    let length = name.chars().count();
+    let max_distance = (length / 3).max(1).min(length.saturating_sub(1));
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Returns the optimal string alignment distance between the texts: the number of chars to insert, remove or replace,
+/// or of adjacent chars to swap, to go from one to the other. A substring is never edited twice.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // Distances from the start of `a` read so far to each start of `b`, for the last two chars of `a` and the current
+    let mut before: Vec<usize> = Vec::new();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let replace = previous[j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            row[j] = replace.min(previous[j] + 1).min(row[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut previous, row);
+    }
+    previous[b.len()]
+}
+
+/// Writes the diagnostic like `3:5: error: message`, followed by its notes on the next lines.
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}: {}", self.span.start(), self.severity, self.message)?;
+        for note in &self.notes {
+            match &note.span {
+                Some(span) => write!(f, "\n{}: note: {}", span.start(), note.message)?,
+                None => write!(f, "\nnote: {}", note.message)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::Location;
+
+    use super::*;
+
+    #[test]
+    fn test_diagnostic_display() {
+        let span = |line| Span::new(Location::new(line, 5, 0), Location::new(line, 6, 1));
+        let diagnostic = Diagnostic::error("`x` is already defined", span(3))
+            .with_note("previous definition", Some(span(1)))
+            .with_note("names must be unique in a scope", None);
+
+        assert!(diagnostic.is_error());
+        assert_eq!(
+            diagnostic.to_string(),
+            "3:5: error: `x` is already defined\n1:5: note: previous definition\nnote: names must be unique in a scope"
+        );
+        assert_eq!(Diagnostic::warning("unused", span(2)).to_string(), "2:5: warning: unused");
+        assert!(!Diagnostic::warning("unused", span(2)).is_error());
+        let fatal = Diagnostic::fatal("unexpected input", span(4));
+        assert!(fatal.is_error());
+        assert_eq!(fatal.to_string(), "4:5: fatal error: unexpected input");
+    }
+
+    #[test]
+    fn test_limit_errors() {
+        let span = |line| Span::new(Location::new(line, 1, 0), Location::new(line, 2, 1));
+        let limited = |diagnostics: &[Diagnostic], limit| {
+            let mut diagnostics = diagnostics.to_vec();
+            limit_errors(&mut diagnostics, limit);
+            diagnostics.iter().map(ToString::to_string).collect::<Vec<_>>()
+        };
+
+        let diagnostics = [
+            Diagnostic::error("a", span(1)),
+            Diagnostic::warning("b", span(2)),
+            Diagnostic::error("c", span(3)),
+            Diagnostic::error("d", span(4)),
+        ];
+        assert_eq!(limited(&diagnostics, 3).len(), 4);
+        assert_eq!(
+            limited(&diagnostics, 1),
+            ["1:1: error: a", "2:1: warning: b", "3:1: fatal error: too many errors, stopped after 1"]
+        );
+
+        // Nothing is reported after a fatal diagnostic
+        let diagnostics = [Diagnostic::fatal("a", span(1)), Diagnostic::error("b", span(2))];
+        assert_eq!(limited(&diagnostics, 5), ["1:1: fatal error: a"]);
+    }
+
+    #[test]
+    fn test_suggest() {
+        assert_eq!(edit_distance("whle", "while"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        // A swap of two adjacent chars is a single edit
+        assert_eq!(edit_distance("cuont", "count"), 1);
+        assert_eq!(edit_distance("whlie", "while"), 1);
+        assert_eq!(edit_distance("ab", "ba"), 1);
+        // The swapped chars are not edited again
+        assert_eq!(edit_distance("ca", "abc"), 3);
+
+        let candidates = ["while", "return", "print", "len"];
+        assert_eq!(suggest("whle", candidates), Some("while"));
+        assert_eq!(suggest("retrun", candidates), Some("return"));
+        assert_eq!(suggest("prnt", candidates), Some("print"));
+        assert_eq!(suggest("x", ["y"]), None);
+        assert_eq!(suggest("lne", candidates), Some("len"));
+        assert_eq!(suggest("whlie", candidates), Some("while"));
+        // The exact name is not a suggestion
+        assert_eq!(suggest("len", candidates), None);
+    }
+}
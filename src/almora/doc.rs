@@ -0,0 +1,193 @@
+//! Documentation of almora modules, from the `///` comments written before their functions and global variables.
+
+use std::fmt::Write;
+
+use crate::parser_lib::{SyntaxKind, SyntaxNode};
+
+use super::{compile, main::grammar, CompileError};
+
+/// Kind of a documented item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocItemKind {
+    Function,
+    /// Variable defined at the top level
+    Variable,
+}
+
+/// Function or global variable of a module, with its documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocItem {
+    pub kind: DocItemKind,
+    pub name: String,
+    /// Definition without the body or the value, like `fn area(r: float) -> float` or `let pi: float`.
+    pub signature: String,
+    /// Text of the doc comments, without the `///`. None if the item is not documented.
+    pub doc: Option<String>,
+    /// Line of the definition, starting at 1.
+    pub line: usize,
+}
+
+/// Documentation of a module: its functions and global variables, in source order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModuleDoc {
+    pub items: Vec<DocItem>,
+}
+
+impl ModuleDoc {
+    /// Writes the documentation as Markdown, with a section for each item.
+    pub fn to_markdown(&self, title: &str) -> String {
+        let mut markdown = format!("# {}\n", title);
+        for item in &self.items {
+            write!(markdown, "\n## `{}`\n", item.signature).unwrap();
+            if let Some(doc) = &item.doc {
+                write!(markdown, "\n{}\n", doc).unwrap();
+            }
+        }
+        markdown
+    }
+}
+
+/// Extracts the documentation of a module from its lossless syntax tree, where the doc comments are trivia.
+///
+/// The doc comments of an item are the `///` lines right before it. The ones before the other statements are ignored.
+pub fn document(source: &str) -> Result<ModuleDoc, CompileError> {
+    // The program is compiled first, to report the errors of the source
+    compile(source)?;
+    let root = grammar()
+        .parse_lossless(source)?
+        .expect("the program matches the empty input");
+
+    let mut module = ModuleDoc::default();
+    let mut trivia = String::new();
+    for element in elements(&root) {
+        let SyntaxKind::Rule(rule) = element.kind() else {
+            if *element.kind() == SyntaxKind::Trivia {
+                trivia.push_str(&element.text());
+            }
+            continue;
+        };
+        let (kind, end) = match rule.as_str() {
+            "function" => (DocItemKind::Function, "block"),
+            "let_stmt" => (DocItemKind::Variable, "expr"),
+            _ => {
+                trivia.clear();
+                continue;
+            }
+        };
+
+        let children = element.children();
+        let name = children
+            .iter()
+            .find(|child| *child.kind() == SyntaxKind::Rule("identifier".to_string()))
+            .map(SyntaxNode::text)
+            .unwrap_or_default();
+        let header: String = children
+            .iter()
+            .take_while(|child| *child.kind() != SyntaxKind::Rule(end.to_string()))
+            .map(text_without_comments)
+            .collect();
+        let signature = header.split_whitespace().collect::<Vec<_>>().join(" ");
+        module.items.push(DocItem {
+            kind,
+            name,
+            signature: signature.trim_end_matches(" =").to_string(),
+            doc: doc_comment(&trivia),
+            line: source[..element.range().start].matches('\n').count() + 1,
+        });
+        trivia.clear();
+    }
+    Ok(module)
+}
+
+/// Returns the text of the doc comments at the end of the text before an item, without their `///`.
+///
+/// The doc comments must be on the lines right before the item: an empty line or another comment between them stops
+/// them.
+pub(crate) fn doc_comment(before: &str) -> Option<String> {
+    let mut lines: Vec<&str> = before
+        .lines()
+        .rev()
+        // The last line is the indentation of the item
+        .skip(usize::from(!before.ends_with('\n')))
+        .map(str::trim)
+        .take_while(|line| line.starts_with("///") && !line.starts_with("////"))
+        .map(|line| {
+            let line = &line[3..];
+            line.strip_prefix(' ').unwrap_or(line)
+        })
+        .collect();
+    lines.reverse();
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+/// Returns the top-level elements of the tree, looking inside the nodes of the program.
+fn elements(root: &SyntaxNode) -> Vec<SyntaxNode> {
+    root.children()
+        .into_iter()
+        .flat_map(|element| match element.kind() {
+            SyntaxKind::Rule(rule) if rule == "program" => elements(&element),
+            _ => vec![element],
+        })
+        .collect()
+}
+
+/// Returns the text of the element, with its comments replaced by spaces.
+fn text_without_comments(element: &SyntaxNode) -> String {
+    match element.kind() {
+        SyntaxKind::Trivia => " ".to_string(),
+        SyntaxKind::Token => element.text(),
+        SyntaxKind::Rule(_) => element.children().iter().map(text_without_comments).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_doc_comment() {
+        assert_eq!(doc_comment("/// Adds.\n///\n///  Twice.\n    "), Some("Adds.\n\n Twice.".to_string()));
+        assert_eq!(doc_comment("/// Stopped.\n\n/// Kept.\n"), Some("Kept.".to_string()));
+        assert_eq!(doc_comment("/// Stopped.\n// Not a doc.\n"), None);
+        assert_eq!(doc_comment("//// Not a doc.\n"), None);
+        assert_eq!(doc_comment(""), None);
+    }
+
+    #[test]
+    fn test_document() {
+        let source = "/// The circle constant.\nlet pi: float = 3.14;\n\n\
+                      /// Area of a circle.\n///\n/// The radius must be positive.\n\
+                      fn area(r: float /* radius */) -> float {\n    /// Not an item.\n    let a = pi * r * r;\n\
+                      \x20   return a;\n}\n\
+                      // Not a doc.\nfn undocumented() {}\n/// Not an item.\nprint(pi);\nlet x = 1;\n";
+        let module = document(source).unwrap();
+        let items: Vec<(DocItemKind, &str, &str, Option<&str>, usize)> = module
+            .items
+            .iter()
+            .map(|item| (item.kind, item.name.as_str(), item.signature.as_str(), item.doc.as_deref(), item.line))
+            .collect();
+        assert_eq!(
+            items,
+            [
+                (DocItemKind::Variable, "pi", "let pi: float", Some("The circle constant."), 2),
+                (
+                    DocItemKind::Function,
+                    "area",
+                    "fn area(r: float ) -> float",
+                    Some("Area of a circle.\n\nThe radius must be positive."),
+                    7
+                ),
+                (DocItemKind::Function, "undocumented", "fn undocumented()", None, 13),
+                (DocItemKind::Variable, "x", "let x", None, 16),
+            ]
+        );
+
+        assert_eq!(
+            module.to_markdown("geometry"),
+            "# geometry\n\n## `let pi: float`\n\nThe circle constant.\n\n\
+             ## `fn area(r: float ) -> float`\n\nArea of a circle.\n\nThe radius must be positive.\n\n\
+             ## `fn undocumented()`\n\n## `let x`\n"
+        );
+        assert!(document("fn (").is_err());
+    }
+}
@@ -0,0 +1,42 @@
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+};
+
+use crate::parser_lib::{Location, ParserError, Span};
+
+/// Error that stops the compilation of an almora source.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompileError {
+    /// The parser could not match the source
+    Parser(ParserError),
+    /// The source doesn't follow the grammar from the given location
+    UnexpectedInput(Location),
+    /// The literal can't be decoded, like a string with an escape which is not a Unicode scalar value
+    InvalidLiteral(Span),
+    /// The number is too large, like an integer that doesn't fit in 64 bits
+    LiteralOverflow(Span),
+}
+
+impl From<ParserError> for CompileError {
+    fn from(err: ParserError) -> Self {
+        Self::Parser(err)
+    }
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            CompileError::Parser(err)
+                => write!(f, "{}", err),
+            CompileError::UnexpectedInput(location)
+                => write!(f, "{}: unexpected input.", location),
+            CompileError::InvalidLiteral(span)
+                => write!(f, "{}: invalid literal.", span),
+            CompileError::LiteralOverflow(span)
+                => write!(f, "{}: number literal out of range.", span),
+        }
+    }
+}
+
+impl Error for CompileError {}
@@ -1,31 +1,204 @@
-use crate::{choice, define_grammar, seq, until, word};
+use crate::{and, choice, define_grammar, not, opt, parser_lib::ParseOptions, range, seq, until, word};
 
 define_grammar!(almora, |grammar: &mut GrammarBuilder<R>| {
     // ===== Config ignore list =====
+    // Doc comments are trivia too: they are attached to the next item from the text before it
+    let doc_comment = seq!(word!("///"), until!(word!("\n"), 0), word!("\n"));
     let line_comment = seq!(word!("//"), until!(word!("\n"), 0), word!("\n"));
-    let block_comment = seq!(word!("/*"), until!(word!("*/"), 0), word!("*/"));
+    // An unterminated block comment ends at the end of the input: the lowering reports it
+    let end_of_input = not!(range!('\0', char::MAX));
+    let block_comment = seq!(word!("/*"), until!(word!("*/"), 0), choice![word!("*/"), end_of_input.clone()]);
     let whitespace = choice![word!(" "), word!("\t"), word!("\n"), word!("\r")];
-    let ignore = choice![line_comment, block_comment, whitespace];
-    // grammar.ignore(ignore);
+    let ignore = choice![doc_comment, line_comment, block_comment, whitespace];
+    grammar.ignore(ignore.clone());
+    let ws = ignore.at_least(0);
 
-    
+    // The levels of the expressions are tried with an operator first, then without it: the results of the named rules
+    // are cached so that the operands are not matched again
+    grammar.options(ParseOptions::new().with_memoization());
+
+    // ===== Literals =====
+    // The digits can be separated by underscores, like `1_000`
+    let separated = |digit: Rule<R>| seq!(digit, seq!(opt!(word!("_")), digit).at_least(0));
+    let digits = separated(range!('0', '9'));
+    let hex_digit = choice![range!('0', '9'), range!('a', 'f'), range!('A', 'F')];
+    let exponent = seq!(choice![word!("e"), word!("E")], opt!(choice![word!("+"), word!("-")]), digits);
+    let float = grammar.define(
+        "float",
+        choice![seq!(digits, word!("."), digits, opt!(exponent)), seq!(digits, exponent)],
+    );
+    let integer = grammar.define(
+        "integer",
+        choice![
+            seq!(word!("0x"), separated(hex_digit.clone())),
+            seq!(word!("0o"), separated(range!('0', '7'))),
+            seq!(word!("0b"), separated(range!('0', '1'))),
+            digits.clone()
+        ],
+    );
+
+    // The strings can contain expressions, in `${...}`
+    let expr = grammar.rule("expr");
+    let unicode_escape = seq!(word!("\\u{"), hex_digit.at_least(1), word!("}"));
+    let escape = choice![Rule::keywords(&["\\\"", "\\\\", "\\n", "\\t", "\\r", "\\0", "\\$"]), unicode_escape];
+    let interpolation = grammar.define("interpolation", seq!(word!("${"), ws, expr, ws, word!("}")));
+    let string_char = until!(choice![word!("\""), word!("\\"), word!("\n"), word!("${")], 1);
+    let string_parts = choice![interpolation, escape, string_char].at_least(0);
+    let string = grammar.define("string", seq!(word!("\""), string_parts, word!("\"")));
+    // A string without its closing quote ends at the end of the line: the lowering reports it, and the parse goes on
+    let line_end = choice![word!("\n"), end_of_input];
+    let unterminated_string = grammar.define("unterminated_string", seq!(word!("\""), string_parts, and!(line_end)));
+
+    // ===== Keywords =====
+    // Words are only keywords if they are not the start of a longer identifier
+    let letter = choice![range!('a', 'z'), range!('A', 'Z'), word!("_")];
+    let identifier_char = choice![letter, range!('0', '9')];
+    let mut keyword = |word| seq!(grammar.reserved(word), not!(identifier_char));
+    let let_kw = keyword("let");
+    let fn_kw = keyword("fn");
+    let if_kw = keyword("if");
+    let else_kw = keyword("else");
+    let while_kw = keyword("while");
+    let for_kw = keyword("for");
+    let in_kw = keyword("in");
+    let return_kw = keyword("return");
+    let true_kw = keyword("true");
+    let false_kw = keyword("false");
+    let import_kw = keyword("import");
+
+    let boolean = grammar.define("boolean", choice![true_kw, false_kw]);
+    let literal = choice![float, integer, string, unterminated_string, boolean];
+
+    let name = seq!(not!(seq!(grammar.any_reserved(), not!(identifier_char))), letter, identifier_char.at_least(0));
+    let identifier = grammar.define("identifier", name.clone());
+    let ty = grammar.define("type", name.clone());
+    let module_path = grammar.define("module_path", seq!(name.clone(), seq!(word!("."), name).at_least(0)));
+
+    // ===== Expressions =====
+    let group = grammar.rule("group");
+    let primary = choice![literal, identifier, group];
+
+    let separator = seq!(ws, word!(","), ws);
+    let argument_list = seq!(expr, seq!(separator, expr).at_least(0), opt!(seq!(ws, word!(","))));
+    let arguments = grammar.define("arguments", seq!(word!("("), ws, opt!(argument_list), ws, word!(")")));
+    let call = grammar.define("call", seq!(primary, seq!(ws, arguments).at_least(1)));
+    let postfix = choice![call, primary];
+
+    let unary = grammar.rule("unary");
+    let unary_op = grammar.define("unary_op", choice![word!("-"), word!("!")]);
+    let unary = grammar.define("unary", seq!(unary_op, ws, choice![unary, postfix]));
+    let mut operand = choice![unary, postfix];
+
+    // Binary operators, from the tightest to the loosest. The longest operators are tried first.
+    let levels: [(&str, &str, &[&str]); 6] = [
+        ("product", "product_op", &["*", "/", "%"]),
+        ("sum", "sum_op", &["+", "-"]),
+        ("comparison", "comparison_op", &["<=", ">=", "<", ">"]),
+        ("equality", "equality_op", &["==", "!="]),
+        ("and", "and_op", &["&&"]),
+        ("or", "or_op", &["||"]),
+    ];
+    for (name, op_name, operators) in levels {
+        let op = grammar.define(op_name, Rule::keywords(operators));
+        let operation = seq!(ws, op, ws, operand);
+        // Comparisons can't be chained: `a < b < c` is an error
+        let operations = match name {
+            "comparison" | "equality" => operation,
+            _ => operation.at_least(1),
+        };
+        let level = grammar.define(name, seq!(operand, operations));
+        operand = choice![level, operand];
+    }
+    grammar.define("group", seq!(word!("("), ws, operand, ws, word!(")")));
+    let expr = grammar.define("expr", operand);
+
+    // ===== Statements =====
+    let semicolon = seq!(ws, word!(";"));
+    let type_annotation = seq!(ws, word!(":"), ws, ty);
+    let let_stmt = grammar.define(
+        "let_stmt",
+        seq!(let_kw, ws, identifier, opt!(type_annotation), ws, word!("="), ws, expr, semicolon),
+    );
+    // `==` is an expression statement, not an assignment
+    let assign_stmt = grammar.define(
+        "assign_stmt",
+        seq!(identifier, ws, word!("="), not!(word!("=")), ws, expr, semicolon),
+    );
+    let return_stmt = grammar.define("return_stmt", seq!(return_kw, opt!(seq!(ws, expr)), semicolon));
+    let expr_stmt = grammar.define("expr_stmt", seq!(expr, semicolon));
+
+    let block = grammar.rule("block");
+    let if_stmt = grammar.rule("if_stmt");
+    let else_branch = seq!(ws, else_kw, ws, choice![if_stmt, block]);
+    let if_stmt = grammar.define("if_stmt", seq!(if_kw, ws, expr, ws, block, opt!(else_branch)));
+    let while_stmt = grammar.define("while_stmt", seq!(while_kw, ws, expr, ws, block));
+    let for_stmt = grammar.define("for_stmt", seq!(for_kw, ws, identifier, ws, in_kw, ws, expr, ws, block));
+    // A statement left incomplete by an unterminated string, like `let s = "abc` before the next statement: the string
+    // takes the end of the line as its closing quote, and the `;` or `)` missing there are ignored with the rest of the
+    // statement, so that the parse goes on at the next line. The quotes of a line comment don't start a string.
+    let line_char = seq!(not!(choice![word!("\""), word!("//"), line_end]), range!('\0', char::MAX));
+    let unterminated_stmt = grammar.define(
+        "unterminated_stmt",
+        seq!(choice![string, line_char].at_least(0), unterminated_string.clone()),
+    );
+    let statement = choice![
+        let_stmt,
+        if_stmt,
+        while_stmt,
+        for_stmt,
+        return_stmt,
+        block,
+        assign_stmt,
+        expr_stmt,
+        unterminated_stmt
+    ];
+    let block = grammar.define("block", seq!(word!("{"), ws, seq!(statement, ws).at_least(0), word!("}")));
+
+    // ===== Declarations =====
+    let parameter = grammar.define("parameter", seq!(identifier, type_annotation));
+    let parameter_list = seq!(parameter, seq!(separator, parameter).at_least(0), opt!(seq!(ws, word!(","))));
+    let parameters = grammar.define("parameters", seq!(word!("("), ws, opt!(parameter_list), ws, word!(")")));
+    let return_type = seq!(ws, word!("->"), ws, ty);
+    let function = grammar.define(
+        "function",
+        seq!(fn_kw, ws, identifier, ws, parameters, opt!(return_type), ws, block),
+    );
+
+    // `import a.b;` loads the module in `a/b.alm`, relative to the root of the project
+    let import_stmt = grammar.define("import_stmt", seq!(import_kw, ws, module_path, semicolon));
 
     // Save the root rule.
-    ignore
+    grammar.define("program", seq!(ws, seq!(choice![import_stmt, function, statement], ws).at_least(0)))
 });
 
-
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
-    use crate::parser_lib::{StringCharReader, MatchToken, Location};
+    use crate::{
+        assert_parse_tree, assert_parses, assert_rejects,
+        parser_lib::{Location, MatchToken, ParseNode, StringCharReader},
+    };
 
     use super::*;
 
+    /// Checks the tree of the expression, parsed as an expression statement.
+    fn assert_expr(source: &str, expected: &str) {
+        let grammar = almora::define_grammar::<StringCharReader>();
+        let expected = format!("(program (expr_stmt (expr {})))", ParseNode::normalize_sexp(expected));
+        assert_parse_tree!(grammar, &format!("{};", source), &expected);
+    }
+
+    /// Checks that the expression is not valid, parsed as an expression statement.
+    fn reject_expr(source: &str) {
+        let grammar = almora::define_grammar::<StringCharReader>();
+        assert_rejects!(grammar, &format!("{};", source));
+    }
+
     #[test]
     fn test_compile() {
         let almora_grammar = almora::define_grammar();
 
-        let mut matcher = StringCharReader::new("/* hey */a");
+        let mut matcher = StringCharReader::new("/* hey */a;");
 
         // Parse the input.
         let loc = Location::beginning();
@@ -34,4 +207,328 @@ mod tests {
 
         println!("{:?}", result);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_literals() {
+        assert_expr("42", r#"(integer "42")"#);
+        assert_expr("4.25", r#"(float "4.25")"#);
+        assert_expr("1e10", r#"(float "1e10")"#);
+        assert_expr("2.5E-3", r#"(float "2.5E-3")"#);
+        assert_expr("1_000_000", r#"(integer "1_000_000")"#);
+        assert_expr("0xFF_ff", r#"(integer "0xFF_ff")"#);
+        assert_expr("0o17", r#"(integer "0o17")"#);
+        assert_expr("0b1010_0101", r#"(integer "0b1010_0101")"#);
+        assert_expr("1_0.2_5e+1_0", r#"(float "1_0.2_5e+1_0")"#);
+        assert_expr(r#""hello""#, r#"(string "\"hello\"")"#);
+        assert_expr(r#""a \"b\"\n""#, r#"(string "\"a \\\"b\\\"\\n\"")"#);
+        assert_expr("true", r#"(boolean "true")"#);
+        assert_expr("false ", r#"(boolean "false")"#);
+
+        reject_expr("1.");
+        reject_expr(".5");
+        reject_expr("1e");
+        reject_expr("1__0");
+        reject_expr("10_");
+        reject_expr("1_.5");
+        reject_expr("0x");
+        reject_expr("0b102");
+        reject_expr("0o8");
+        reject_expr(r#""\q""#);
+    }
+
+    #[test]
+    fn test_strings() {
+        assert_expr(r#""\u{1F600}\$""#, r#"(string "\"\\u{1F600}\\$\"")"#);
+        assert_expr(r#""$a ${x}""#, r#"(string (interpolation (expr (identifier "x"))))"#);
+        assert_expr(
+            r#""${ "n=${n}" }!""#,
+            r#"(string (interpolation (expr (string (interpolation (expr (identifier "n")))))))"#,
+        );
+
+        reject_expr(r#""\u{}""#);
+        reject_expr(r#""\u{zz}""#);
+        reject_expr(r#""${}""#);
+        reject_expr(r#""${x""#);
+
+        // A string without its closing quote ends at the end of the line, and the parse goes on
+        let grammar = almora::define_grammar::<StringCharReader>();
+        assert_parse_tree!(
+            grammar,
+            "f(\"a ${x}\n);\ng();",
+            r#"
+            (program
+                (expr_stmt
+                    (expr
+                        (call
+                            (identifier "f")
+                            (arguments (expr (unterminated_string (interpolation (expr (identifier "x")))))))))
+                (expr_stmt (expr (call (identifier "g") (arguments "()")))))
+            "#
+        );
+        assert_parses!(grammar, "let x = 1; /* never closed\nlet y = 2;");
+
+        // The `;` and `)` missing at the end of the line are ignored with the rest of the statement
+        assert_parse_tree!(
+            grammar,
+            "let s = \"abc\nlet y = 2;",
+            r#"
+            (program
+                (unterminated_stmt (unterminated_string "\"abc"))
+                (let_stmt (identifier "y") (expr (integer "2"))))
+            "#
+        );
+        assert_parses!(grammar, "fn f() {\n    g(\"a\", \"b ${x}\n}");
+        assert_parse_tree!(
+            grammar,
+            "\"two\nlines\";",
+            r#"
+            (program
+                (unterminated_stmt (unterminated_string "\"two"))
+                (unterminated_stmt (unterminated_string "\";")))
+            "#
+        );
+        assert_rejects!(grammar, "let s = 1 // \"abc\nlet y = 2;");
+    }
+
+    #[test]
+    fn test_identifiers() {
+        assert_expr("x", r#"(identifier "x")"#);
+        assert_expr("_tmp2", r#"(identifier "_tmp2")"#);
+        assert_expr("trueish", r#"(identifier "trueish")"#);
+        assert_expr("lettuce", r#"(identifier "lettuce")"#);
+        reject_expr("2x");
+
+        // Keywords are reserved
+        reject_expr("while");
+        reject_expr("f(return)");
+    }
+
+    #[test]
+    fn test_operators() {
+        // Precedence
+        assert_expr(
+            "1 + 2 * 3",
+            r#"
+            (sum
+                (integer "1")
+                (sum_op "+")
+                (product (integer "2") (product_op "*") (integer "3")))
+            "#,
+        );
+        assert_expr(
+            "a || b && c == d < e",
+            r#"
+            (or
+                (identifier "a")
+                (or_op "||")
+                (and
+                    (identifier "b")
+                    (and_op "&&")
+                    (equality
+                        (identifier "c")
+                        (equality_op "==")
+                        (comparison (identifier "d") (comparison_op "<") (identifier "e")))))
+            "#,
+        );
+
+        // The operands of the same level are siblings, in order
+        assert_expr(
+            "8 / 4 % 3",
+            r#"(product (integer "8") (product_op "/") (integer "4") (product_op "%") (integer "3"))"#,
+        );
+        assert_expr("a <= b", r#"(comparison (identifier "a") (comparison_op "<=") (identifier "b"))"#);
+        assert_expr("a != b", r#"(equality (identifier "a") (equality_op "!=") (identifier "b"))"#);
+        assert_expr("a>=b", r#"(comparison (identifier "a") (comparison_op ">=") (identifier "b"))"#);
+        reject_expr("a < b < c");
+        reject_expr("a == b == c");
+        reject_expr("1 +");
+        reject_expr("* 2");
+    }
+
+    #[test]
+    fn test_unary() {
+        assert_expr(
+            "-x * 2",
+            r#"(product (unary (unary_op "-") (identifier "x")) (product_op "*") (integer "2"))"#,
+        );
+        assert_expr("!!done", r#"(unary (unary_op "!") (unary (unary_op "!") (identifier "done")))"#);
+        assert_expr("1 - -1", r#"(sum (integer "1") (sum_op "-") (unary (unary_op "-") (integer "1")))"#);
+        reject_expr("-");
+    }
+
+    #[test]
+    fn test_parentheses() {
+        assert_expr(
+            "(1 + 2) * 3",
+            r#"
+            (product
+                (group (sum (integer "1") (sum_op "+") (integer "2")))
+                (product_op "*")
+                (integer "3"))
+            "#,
+        );
+        assert_expr("((( x )))", r#"(group (group (group (identifier "x"))))"#);
+        assert_expr("( /* one */ 1 // comment\n)", r#"(group (integer "1"))"#);
+        reject_expr("(1 + 2");
+        reject_expr("()");
+    }
+
+    #[test]
+    fn test_calls() {
+        assert_expr(
+            "max(a, 2 + b)",
+            r#"
+            (call
+                (identifier "max")
+                (arguments
+                    (expr (identifier "a"))
+                    (expr (sum (integer "2") (sum_op "+") (identifier "b")))))
+            "#,
+        );
+        assert_expr("f()", r#"(call (identifier "f") (arguments "()"))"#);
+        assert_expr(
+            "make()(1,)",
+            r#"(call (identifier "make") (arguments "()") (arguments (expr (integer "1"))))"#,
+        );
+        assert_expr(
+            "-f(x)",
+            r#"(unary (unary_op "-") (call (identifier "f") (arguments (expr (identifier "x")))))"#,
+        );
+        reject_expr("f(,)");
+        reject_expr("f(a b)");
+        reject_expr("f(a");
+    }
+
+    #[test]
+    fn test_bindings() {
+        let grammar = almora::define_grammar::<StringCharReader>();
+
+        assert_parse_tree!(
+            grammar,
+            "let x = 1;\nlet y: float = x;\nx = y;",
+            r#"
+            (program
+                (let_stmt (identifier "x") (expr (integer "1")))
+                (let_stmt (identifier "y") (type "float") (expr (identifier "x")))
+                (assign_stmt (identifier "x") (expr (identifier "y"))))
+            "#
+        );
+        assert_parse_tree!(
+            grammar,
+            "x == y;",
+            r#"(program (expr_stmt (expr (equality (identifier "x") (equality_op "==") (identifier "y")))))"#
+        );
+        assert_parses!(grammar, "letx = 1;");
+        assert_rejects!(grammar, "let x = 1");
+        assert_rejects!(grammar, "let 1 = x;");
+        assert_rejects!(grammar, "let if = 1;");
+        assert_rejects!(grammar, "let x: = 1;");
+        assert_rejects!(grammar, "1 = x;");
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let grammar = almora::define_grammar::<StringCharReader>();
+
+        assert_parse_tree!(
+            grammar,
+            "if a { f(); } else if b { } else { return; }",
+            r#"
+            (program
+                (if_stmt
+                    (expr (identifier "a"))
+                    (block (expr_stmt (expr (call (identifier "f") (arguments "()")))))
+                    (if_stmt
+                        (expr (identifier "b"))
+                        (block "{ }")
+                        (block (return_stmt "return;")))))
+            "#
+        );
+        assert_parse_tree!(
+            grammar,
+            "while x > 0 { x = x - 1; }",
+            r#"
+            (program
+                (while_stmt
+                    (expr (comparison (identifier "x") (comparison_op ">") (integer "0")))
+                    (block
+                        (assign_stmt
+                            (identifier "x")
+                            (expr (sum (identifier "x") (sum_op "-") (integer "1")))))))
+            "#
+        );
+        assert_parse_tree!(
+            grammar,
+            "for item in items { { print(item); } }",
+            r#"
+            (program
+                (for_stmt
+                    (identifier "item")
+                    (expr (identifier "items"))
+                    (block
+                        (block (expr_stmt (expr (call (identifier "print") (arguments (expr (identifier "item"))))))))))
+            "#
+        );
+        assert_parses!(grammar, "ifx = 1;");
+        assert_rejects!(grammar, "if x { ");
+        assert_rejects!(grammar, "if x return;");
+        assert_rejects!(grammar, "while { }");
+        assert_rejects!(grammar, "for in in x { }");
+        assert_rejects!(grammar, "else { }");
+    }
+
+    #[test]
+    fn test_functions() {
+        let grammar = almora::define_grammar::<StringCharReader>();
+
+        assert_parse_tree!(
+            grammar,
+            "fn add(a: int, b: int) -> int {\n    return a + b;\n}\n\nadd(1, 2);",
+            r#"
+            (program
+                (function
+                    (identifier "add")
+                    (parameters
+                        (parameter (identifier "a") (type "int"))
+                        (parameter (identifier "b") (type "int")))
+                    (type "int")
+                    (block
+                        (return_stmt (expr (sum (identifier "a") (sum_op "+") (identifier "b"))))))
+                (expr_stmt
+                    (expr
+                        (call
+                            (identifier "add")
+                            (arguments (expr (integer "1")) (expr (integer "2")))))))
+            "#
+        );
+        assert_parse_tree!(
+            grammar,
+            "fn main() {}",
+            r#"(program (function (identifier "main") (parameters "()") (block "{}")))"#
+        );
+        assert_parses!(grammar, "// entry point\nfn f(x: int,) -> bool { return; }\n");
+        assert_rejects!(grammar, "fn f(x) {}");
+        assert_rejects!(grammar, "fn f(x: int) -> {}");
+        assert_rejects!(grammar, "fn f(x: int)");
+        assert_rejects!(grammar, "fn fn() {}");
+        assert_rejects!(grammar, "fn f(x: let) {}");
+    }
+
+    #[test]
+    fn test_imports() {
+        let grammar = almora::define_grammar::<StringCharReader>();
+
+        assert_parse_tree!(
+            grammar,
+            "import math;\nimport geometry.shapes ;",
+            r#"(program (import_stmt (module_path "math")) (import_stmt (module_path "geometry.shapes")))"#
+        );
+        assert_parses!(grammar, "fn f() {}\nimport a;");
+        assert_rejects!(grammar, "import;");
+        assert_rejects!(grammar, "import a.;");
+        assert_rejects!(grammar, "import a . b;");
+        assert_rejects!(grammar, "import a");
+        assert_rejects!(grammar, "fn f() { import a; }");
+        assert_rejects!(grammar, "let import = 1;");
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    almora::{
+        ast::{Block, Expr, ExprKind, Function, Item, Literal, Program, Stmt, StmtKind},
+        main::{grammar, lower_tree},
+        module_loader::compile_error,
+        resolve_with, CompileError, Diagnostic, ModuleLoader, Namespace, ResolveOptions, SymbolKind, SymbolTable,
+    },
+    parser_lib::{IncrementalTree, Location, ParserError, SourceId, Span, TextEdit},
+};
+
+/// Source opened in the editor, with the result of its analysis, which is updated after each edit.
+#[derive(Debug)]
+pub struct Document {
+    source: String,
+    version: i64,
+    /// Parse tree, reused by the next edit. None if the parser failed, then the next edit parses the whole source.
+    tree: Option<IncrementalTree>,
+    /// Root of the project, where the imported modules are loaded from. Without it, imports are unknown modules.
+    root: Option<PathBuf>,
+    program: Result<Program, CompileError>,
+    symbols: SymbolTable,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Document {
+    pub fn new(source: &str, version: i64, root: Option<&Path>) -> Self {
+        let empty = Span::new(Location::beginning(), Location::beginning());
+        let mut document = Self {
+            source: source.to_string(),
+            version,
+            tree: None,
+            root: root.map(Path::to_path_buf),
+            program: Ok(Program { items: Vec::new(), span: empty, errors: Vec::new() }),
+            symbols: SymbolTable::default(),
+            diagnostics: Vec::new(),
+        };
+        document.update(grammar().parse_incremental(source));
+        document
+    }
+
+    /// Applies an edit of the source, and analyzes it again.
+    ///
+    /// Panics if the range of the edit is not in the source, or not on char boundaries.
+    pub fn edit(&mut self, edit: &TextEdit, version: i64) {
+        self.source = edit.apply(&self.source);
+        self.version = version;
+        let tree = match &self.tree {
+            Some(tree) => grammar().reparse(tree, edit),
+            None => grammar().parse_incremental(&self.source),
+        };
+        self.update(tree);
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn version(&self) -> i64 {
+        self.version
+    }
+
+    /// Returns the AST of the source, or None if it doesn't compile.
+    pub fn program(&self) -> Option<&Program> {
+        self.program.as_ref().ok()
+    }
+
+    /// Returns the error of the compilation, or the ones of the resolver.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Returns the span of the name at the given byte offset, and a description of its symbol in Markdown.
+    pub fn hover(&self, offset: usize) -> Option<(Span, String)> {
+        let contains = |span: &Span| span.start().byte_offset() <= offset && offset <= span.end().byte_offset();
+        // The definitions of the imports and of the builtins are not in the source
+        let is_defined = |kind| matches!(kind, SymbolKind::Function | SymbolKind::Parameter | SymbolKind::Variable);
+        let (span, id) = match self.symbols.references().iter().find(|reference| contains(&reference.span)) {
+            Some(reference) => (reference.span.clone(), reference.symbol),
+            None => self
+                .symbols
+                .symbols()
+                .find(|(_, symbol)| is_defined(symbol.kind) && contains(&symbol.span))
+                .map(|(id, symbol)| (symbol.span.clone(), id))?,
+        };
+
+        let symbol = self.symbols.symbol(id);
+        let details = self.program().map(describe).unwrap_or_default();
+        let (detail, doc) = match symbol.kind {
+            SymbolKind::Builtin => (format!("(builtin) {}", symbol.name), None),
+            SymbolKind::Import => (format!("(imported) {}", symbol.name), None),
+            _ => details.get(&symbol.span.start().byte_offset()).cloned()?,
+        };
+        let mut markdown = format!("```almora\n{}\n```", detail);
+        if let Some(doc) = doc {
+            markdown.push_str("\n\n");
+            markdown.push_str(&doc);
+        }
+        Some((span, markdown))
+    }
+
+    fn update(&mut self, tree: Result<IncrementalTree, ParserError>) {
+        self.program = match &tree {
+            Ok(tree) => lower_tree(tree.tree(), tree.source()),
+            Err(err) => Err(err.clone().into()),
+        };
+        self.tree = tree.ok();
+        self.analyze();
+    }
+
+    /// Resolves the program, with the namespaces of the modules it imports.
+    fn analyze(&mut self) {
+        let program = match &self.program {
+            Ok(program) => program,
+            Err(err) => {
+                self.symbols = SymbolTable::default();
+                self.diagnostics = vec![compile_error(err.clone(), SourceId::default(), &self.source)];
+                return;
+            }
+        };
+
+        let mut options = ResolveOptions::new();
+        let mut diagnostics = Vec::new();
+        if let Some(root) = &self.root {
+            let mut loader = ModuleLoader::new(root);
+            for item in &program.items {
+                let Item::Import(import) = item else {
+                    continue;
+                };
+                let name = &import.module.name;
+                let namespace = match loader.load(name) {
+                    Ok(id) => Namespace::of(&loader.module(id).symbols),
+                    Err(err) => {
+                        let message = format!("cannot load module `{}`: {}", name, err);
+                        diagnostics.push(Diagnostic::error(message, import.module.span.clone()));
+                        Namespace::default()
+                    }
+                };
+                options = options.with_import(name, namespace);
+            }
+        }
+
+        let (symbols, resolve_diagnostics) = resolve_with(program, &options);
+        diagnostics.extend(resolve_diagnostics);
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span.start().index());
+        self.symbols = symbols;
+        self.diagnostics = diagnostics;
+    }
+}
+
+/// Returns the definition of a function without its body, like `fn area(r: float) -> float`.
+pub(super) fn signature(function: &Function) -> String {
+    let params: Vec<String> =
+        function.params.iter().map(|param| format!("{}: {}", param.name.name, param.ty.name)).collect();
+    match &function.return_type {
+        Some(ty) => format!("fn {}({}) -> {}", function.name.name, params.join(", "), ty.name),
+        None => format!("fn {}({})", function.name.name, params.join(", ")),
+    }
+}
+
+/// Details of the names defined in a program: their definition with their type, and their doc comments.
+type Details = HashMap<usize, (String, Option<String>)>;
+
+/// Returns the details of the functions, parameters and variables of the program, by byte offset of their name.
+///
+/// The type of a variable is its annotation, or the type of its value if it is a literal.
+fn describe(program: &Program) -> Details {
+    let mut details = Details::new();
+    for item in &program.items {
+        match item {
+            Item::Import(_) => {}
+            Item::Function(function) => {
+                details.insert(function.name.span.start().byte_offset(), (signature(function), function.doc.clone()));
+                for param in &function.params {
+                    let detail = format!("(parameter) {}: {}", param.name.name, param.ty.name);
+                    details.insert(param.name.span.start().byte_offset(), (detail, None));
+                }
+                describe_block(&function.body, &mut details);
+            }
+            Item::Statement(stmt) => describe_stmt(stmt, &mut details),
+        }
+    }
+    details
+}
+
+fn describe_block(block: &Block, details: &mut Details) {
+    for stmt in &block.stmts {
+        describe_stmt(stmt, details);
+    }
+}
+
+fn describe_stmt(stmt: &Stmt, details: &mut Details) {
+    match &stmt.kind {
+        StmtKind::Let { name, ty, value } => {
+            let detail = match ty.as_ref().map(|ty| ty.name.as_str()).or_else(|| literal_type(value)) {
+                Some(ty) => format!("let {}: {}", name.name, ty),
+                None => format!("let {}", name.name),
+            };
+            details.insert(name.span.start().byte_offset(), (detail, None));
+        }
+        StmtKind::If { then_branch, else_branch, .. } => {
+            describe_block(then_branch, details);
+            if let Some(else_branch) = else_branch {
+                describe_stmt(else_branch, details);
+            }
+        }
+        StmtKind::While { body, .. } | StmtKind::Block(body) => describe_block(body, details),
+        StmtKind::For { variable, body, .. } => {
+            details.insert(variable.span.start().byte_offset(), (format!("(loop variable) {}", variable.name), None));
+            describe_block(body, details);
+        }
+        StmtKind::Assign { .. } | StmtKind::Return(_) | StmtKind::Expr(_) => {}
+    }
+}
+
+fn literal_type(expr: &Expr) -> Option<&'static str> {
+    match &expr.kind {
+        ExprKind::Literal(Literal::Integer(_)) => Some("int"),
+        ExprKind::Literal(Literal::Float(_)) => Some("float"),
+        ExprKind::Literal(Literal::String(_)) | ExprKind::Interpolation(_) => Some("string"),
+        ExprKind::Literal(Literal::Boolean(_)) => Some("bool"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document() {
+        let source = "/// Doubles.\nfn double(x: int) -> int {\n    return x * 2;\n}\nlet a = 1;\nprint(double(a));\n";
+        let mut document = Document::new(source, 1, None);
+        assert!(document.diagnostics().is_empty());
+        assert_eq!(document.program().unwrap().items.len(), 3);
+
+        let offset = |text: &str| source.find(text).unwrap();
+        let hover = |document: &Document, offset| document.hover(offset).map(|(_, text)| text);
+        assert_eq!(
+            hover(&document, offset("double(a)") + 2),
+            Some("```almora\nfn double(x: int) -> int\n```\n\nDoubles.".to_string())
+        );
+        assert_eq!(hover(&document, offset("x * 2")), Some("```almora\n(parameter) x: int\n```".to_string()));
+        assert_eq!(hover(&document, offset("a = 1")), Some("```almora\nlet a: int\n```".to_string()));
+        assert_eq!(hover(&document, offset("print")), Some("```almora\n(builtin) print\n```".to_string()));
+        assert_eq!(hover(&document, offset("return")), None);
+        let (span, _) = document.hover(offset("double(a)")).unwrap();
+        assert_eq!(span.start().byte_offset()..span.end().byte_offset(), offset("double(a)")..offset("(a)"));
+
+        // The edits reuse the tree of the previous version
+        let start = offset("1;");
+        document.edit(&TextEdit::new(start..start + 1, "\"one\""), 2);
+        assert_eq!(document.version(), 2);
+        assert_eq!(hover(&document, offset("a = ")), Some("```almora\nlet a: string\n```".to_string()));
+        document.edit(&TextEdit::new(0..0, "let a = b;\n"), 3);
+        let messages: Vec<String> = document.diagnostics().iter().map(ToString::to_string).collect();
+        assert_eq!(messages[0], "1:9: error: unknown name `b`");
+        assert!(messages[1].starts_with("6:5: error: `a` is already defined"));
+
+        document.edit(&TextEdit::new(0..0, "fn ("), 4);
+        assert!(document.program().is_none());
+        assert_eq!(document.diagnostics()[0].to_string(), "1:1: fatal error: unexpected input");
+        assert_eq!(document.hover(offset("print")), None);
+    }
+}
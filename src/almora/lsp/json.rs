@@ -0,0 +1,362 @@
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+};
+
+/// JSON value of the messages of the language server protocol.
+///
+/// The fields of an object keep their order, so that the messages are written in a predictable way.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+/// Invalid JSON text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonError {
+    /// Byte offset where the text stops being valid.
+    pub offset: usize,
+    pub message: &'static str,
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "invalid JSON at byte {}: {}.", self.offset, self.message)
+    }
+}
+
+impl Error for JsonError {}
+
+impl Json {
+    /// Parses a JSON text, which must contain a single value, surrounded by whitespace.
+    pub fn parse(text: &str) -> Result<Json, JsonError> {
+        let mut parser = JsonParser { text, offset: 0 };
+        let value = parser.value()?;
+        parser.whitespace();
+        match parser.offset < text.len() {
+            true => Err(parser.error("unexpected text after the value")),
+            false => Ok(value),
+        }
+    }
+
+    /// Returns an object with the given fields.
+    pub fn object<'a>(fields: impl IntoIterator<Item = (&'a str, Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    /// Returns the field of an object, if it is an object with this field.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value at the end of the path of fields, like `["params", "textDocument", "uri"]`.
+    pub fn path(&self, keys: &[&str]) -> Option<&Json> {
+        keys.iter().try_fold(self, |value, key| value.get(key))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the number if it is a non-negative integer.
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(value) if *value >= 0.0 && value.fract() == 0.0 && *value <= usize::MAX as f64 => {
+                Some(*value as usize)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(value: i64) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(values: Vec<Json>) -> Self {
+        Json::Array(values)
+    }
+}
+
+/// Writes the value as compact JSON text. The integers are written without a fraction.
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            // The integers that a float represents exactly are written as integers
+            Json::Number(value) if value.fract() == 0.0 && value.abs() < 1e15 => write!(f, "{}", *value as i64),
+            Json::Number(value) if value.is_finite() => write!(f, "{}", value),
+            Json::Number(_) => write!(f, "null"),
+            Json::String(value) => write_string(f, value),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut Formatter, value: &str) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+struct JsonParser<'a> {
+    text: &'a str,
+    offset: usize,
+}
+
+impl JsonParser<'_> {
+    fn error(&self, message: &'static str) -> JsonError {
+        JsonError { offset: self.offset, message }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.offset).copied()
+    }
+
+    fn whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.offset += 1;
+        }
+    }
+
+    /// Skips the given word if the text continues with it.
+    fn eat(&mut self, word: &str) -> bool {
+        let found = self.text[self.offset..].starts_with(word);
+        if found {
+            self.offset += word.len();
+        }
+        found
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError> {
+        self.whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ if self.eat("null") => Ok(Json::Null),
+            _ if self.eat("true") => Ok(Json::Bool(true)),
+            _ if self.eat("false") => Ok(Json::Bool(false)),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, JsonError> {
+        self.offset += 1;
+        let mut fields = Vec::new();
+        self.whitespace();
+        if self.eat("}") {
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected the name of a field"));
+            }
+            let key = self.string()?;
+            self.whitespace();
+            if !self.eat(":") {
+                return Err(self.error("expected `:`"));
+            }
+            fields.push((key, self.value()?));
+            self.whitespace();
+            if self.eat("}") {
+                return Ok(Json::Object(fields));
+            }
+            if !self.eat(",") {
+                return Err(self.error("expected `,` or `}`"));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, JsonError> {
+        self.offset += 1;
+        let mut values = Vec::new();
+        self.whitespace();
+        if self.eat("]") {
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.whitespace();
+            if self.eat("]") {
+                return Ok(Json::Array(values));
+            }
+            if !self.eat(",") {
+                return Err(self.error("expected `,` or `]`"));
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.offset += 1;
+        let mut value = String::new();
+        loop {
+            let Some(c) = self.text[self.offset..].chars().next() else {
+                return Err(self.error("unterminated string"));
+            };
+            self.offset += c.len_utf8();
+            match c {
+                '"' => return Ok(value),
+                '\\' => value.push(self.escape()?),
+                c if (c as u32) < 0x20 => return Err(self.error("control character in a string")),
+                c => value.push(c),
+            }
+        }
+    }
+
+    /// Decodes the escape after a `\`. The `\u` escape of a high surrogate must be followed by the one of a low one.
+    fn escape(&mut self) -> Result<char, JsonError> {
+        let c = match self.peek() {
+            Some(b'"') => '"',
+            Some(b'\\') => '\\',
+            Some(b'/') => '/',
+            Some(b'b') => '\u{8}',
+            Some(b'f') => '\u{c}',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'u') => {
+                self.offset += 1;
+                let high = self.hex()?;
+                let code = match high {
+                    0xd800..=0xdbff if self.eat("\\u") => match self.hex()? {
+                        low @ 0xdc00..=0xdfff => 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00),
+                        _ => return Err(self.error("invalid surrogate pair")),
+                    },
+                    code => code,
+                };
+                return char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"));
+            }
+            _ => return Err(self.error("invalid escape")),
+        };
+        self.offset += 1;
+        Ok(c)
+    }
+
+    fn hex(&mut self) -> Result<u32, JsonError> {
+        let digits = self.text.get(self.offset..self.offset + 4).ok_or_else(|| self.error("expected 4 hex digits"))?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| self.error("expected 4 hex digits"))?;
+        self.offset += 4;
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.offset;
+        while matches!(self.peek(), Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+            self.offset += 1;
+        }
+        self.text[start..self.offset].parse().map(Json::Number).map_err(|_| JsonError {
+            offset: start,
+            message: "invalid number",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json() {
+        let text = r#" {"id": 1, "params": {"text": "a\"\\\n\u00e9\ud83d\ude00", "list": [true, null, -1.5e2, []]}} "#;
+        let json = Json::parse(text).unwrap();
+        assert_eq!(json.get("id").and_then(Json::as_usize), Some(1));
+        assert_eq!(json.path(&["params", "text"]).and_then(Json::as_str), Some("a\"\\\né😀"));
+        assert_eq!(
+            json.path(&["params", "list"]).and_then(Json::as_array),
+            Some(&[Json::Bool(true), Json::Null, Json::Number(-150.0), Json::Array(vec![])][..])
+        );
+        assert_eq!(json.path(&["params", "missing"]), None);
+        assert_eq!(
+            json.to_string(),
+            "{\"id\":1,\"params\":{\"text\":\"a\\\"\\\\\\né😀\",\"list\":[true,null,-150,[]]}}"
+        );
+        assert_eq!(Json::Number(0.5).to_string(), "0.5");
+        assert_eq!(Json::from("\u{1}").to_string(), "\"\\u0001\"");
+
+        let error = |text| Json::parse(text).unwrap_err();
+        assert_eq!(error("{\"a\" 1}"), JsonError { offset: 5, message: "expected `:`" });
+        assert_eq!(error("[1, 2"), JsonError { offset: 5, message: "expected `,` or `]`" });
+        assert_eq!(error("\"abc"), JsonError { offset: 4, message: "unterminated string" });
+        assert_eq!(error("1 2").to_string(), "invalid JSON at byte 2: unexpected text after the value.");
+        assert_eq!(error("\"\\ud800\\u0041\"").message, "invalid surrogate pair");
+    }
+}
@@ -0,0 +1,570 @@
+//! Language server of almora, which talks to editors with the language server protocol over stdio.
+//!
+//! It publishes the diagnostics of the compiler and of the resolver, lists the functions and global variables of a
+//! document, describes the names under the cursor with their type, and gives the semantic tokens of a document.
+//! The documents are synced by incremental edits, which reuse the parse tree of the previous version.
+
+mod document;
+mod json;
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+};
+
+pub use document::Document;
+pub use json::{Json, JsonError};
+
+use crate::{
+    almora::{
+        ast::{Item, StmtKind},
+        semantic_tokens, Diagnostic, Modifiers, Severity, TokenCategory,
+    },
+    parser_lib::{Location, LocationPolicy, Span, TextEdit, Utf16Position},
+};
+
+// Error codes of the protocol
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+// Kinds of the document symbols
+const MODULE_SYMBOL: usize = 2;
+const FUNCTION_SYMBOL: usize = 12;
+const VARIABLE_SYMBOL: usize = 13;
+
+/// Maximum length of the content of a message. A longer one is rejected instead of being allocated.
+const MAX_MESSAGE_LENGTH: usize = 64 * 1024 * 1024;
+
+/// Runs the server until the client sends `exit`, or closes the input.
+///
+/// Returns true if the client asked the server to shut down before exiting, which is a clean exit.
+pub fn run(input: &mut impl BufRead, output: &mut impl Write) -> io::Result<bool> {
+    let mut server = Server::new();
+    while let Some(content) = read_message(input)? {
+        let replies = match Json::parse(&content) {
+            Ok(message) => server.handle(&message),
+            Err(err) => vec![error_response(Json::Null, PARSE_ERROR, &err.to_string())],
+        };
+        for reply in replies {
+            write_message(output, &reply)?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    Ok(server.shut_down)
+}
+
+/// Reads the content of the next message, after its headers. Returns None at the end of the input.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header"))?;
+    if length > MAX_MESSAGE_LENGTH {
+        let message = format!("Content-Length of {} bytes is over the limit of {} bytes", length, MAX_MESSAGE_LENGTH);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    let mut content = vec![0; length];
+    input.read_exact(&mut content)?;
+    String::from_utf8(content).map(Some).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Writes a message, preceded by its `Content-Length` header.
+pub fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let content = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", content.len(), content)?;
+    output.flush()
+}
+
+/// State of the language server: the open documents, by URI.
+#[derive(Debug, Default)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+    /// Directory of the workspace, from which the imported modules are loaded.
+    root: Option<PathBuf>,
+    shut_down: bool,
+    exited: bool,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn document(&self, uri: &str) -> Option<&Document> {
+        self.documents.get(uri)
+    }
+
+    /// Handles a request or a notification, and returns the messages to send back: the response of a request, and
+    /// the notifications of the diagnostics of the edited documents.
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        if !matches!(message, Json::Object(_)) {
+            return vec![error_response(Json::Null, INVALID_REQUEST, "the message is not an object")];
+        }
+        let method = message.get("method").and_then(Json::as_str).unwrap_or_default();
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let Some(id) = message.get("id").cloned() else {
+            return self.notification(method, params);
+        };
+
+        let result = match method {
+            _ if self.shut_down => Err((INVALID_REQUEST, "the server is shut down".to_string())),
+            "initialize" => Ok(self.initialize(params)),
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Json::Null)
+            }
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/semanticTokens/full" => self.semantic_tokens(params),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method `{}`", method))),
+        };
+        vec![match result {
+            Ok(result) => Json::object([("jsonrpc", "2.0".into()), ("id", id), ("result", result)]),
+            Err((code, message)) => error_response(id, code, &message),
+        }]
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let uri = params.path(&["textDocument", "uri"]).and_then(Json::as_str).unwrap_or_default().to_string();
+        let version = params.path(&["textDocument", "version"]).and_then(|version| match version {
+            Json::Number(version) => Some(*version as i64),
+            _ => None,
+        });
+
+        match method {
+            "exit" => self.exited = true,
+            "textDocument/didOpen" => {
+                let text = params.path(&["textDocument", "text"]).and_then(Json::as_str).unwrap_or_default();
+                let document = Document::new(text, version.unwrap_or_default(), self.root.as_deref());
+                self.documents.insert(uri.clone(), document);
+            }
+            "textDocument/didChange" => {
+                let Some(document) = self.documents.get_mut(&uri) else {
+                    return Vec::new();
+                };
+                let version = version.unwrap_or(document.version() + 1);
+                let changes = params.get("contentChanges").and_then(Json::as_array).unwrap_or_default();
+                for change in changes {
+                    let text = change.get("text").and_then(Json::as_str).unwrap_or_default();
+                    // A change without a range replaces the whole source
+                    let range = match change.get("range") {
+                        Some(range) => offset(document.source(), range.get("start"))
+                            ..offset(document.source(), range.get("end")),
+                        None => 0..document.source().len(),
+                    };
+                    if range.start <= range.end {
+                        document.edit(&TextEdit::new(range, text), version);
+                    }
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                let params = Json::object([("uri", uri.into()), ("diagnostics", Json::Array(Vec::new()))]);
+                return vec![notification("textDocument/publishDiagnostics", params)];
+            }
+            // Like `initialized`, the other notifications are ignored
+            _ => return Vec::new(),
+        }
+
+        match self.documents.get(&uri) {
+            Some(document) => vec![publish_diagnostics(&uri, document)],
+            None => Vec::new(),
+        }
+    }
+
+    fn initialize(&mut self, params: &Json) -> Json {
+        self.root = params
+            .get("rootUri")
+            .and_then(Json::as_str)
+            .and_then(|uri| uri.strip_prefix("file://"))
+            .map(|path| PathBuf::from(decode_uri(path)));
+
+        // The documents are synced incrementally
+        let sync = Json::object([("openClose", true.into()), ("change", 2_usize.into())]);
+        let capabilities = Json::object([
+            ("textDocumentSync", sync),
+            ("documentSymbolProvider", true.into()),
+            ("hoverProvider", true.into()),
+            ("semanticTokensProvider", semantic_tokens_options()),
+        ]);
+        let info = Json::object([("name", "almora".into()), ("version", env!("CARGO_PKG_VERSION").into())]);
+        Json::object([("capabilities", capabilities), ("serverInfo", info)])
+    }
+
+    /// Returns the imports, functions and global variables of the document.
+    fn document_symbols(&self, params: &Json) -> Result<Json, (i64, String)> {
+        let document = self.requested_document(params)?;
+        let Some(program) = document.program() else {
+            return Ok(Json::Array(Vec::new()));
+        };
+
+        let source = document.source();
+        let symbol = |name: &str, kind: usize, detail: Option<String>, span: &Span, name_span: &Span| {
+            let mut fields = vec![("name", name.into())];
+            fields.extend(detail.map(|detail| ("detail", detail.into())));
+            fields.extend([
+                ("kind", kind.into()),
+                ("range", range(source, span)),
+                ("selectionRange", range(source, name_span)),
+            ]);
+            Json::object(fields)
+        };
+        let symbols = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Import(import) => {
+                    let name = &import.module;
+                    Some(symbol(&name.name, MODULE_SYMBOL, None, &import.span, &name.span))
+                }
+                Item::Function(function) => {
+                    let detail = Some(document::signature(function));
+                    Some(symbol(&function.name.name, FUNCTION_SYMBOL, detail, &function.span, &function.name.span))
+                }
+                Item::Statement(stmt) => match &stmt.kind {
+                    StmtKind::Let { name, ty, .. } => {
+                        let detail = ty.as_ref().map(|ty| ty.name.clone());
+                        Some(symbol(&name.name, VARIABLE_SYMBOL, detail, &stmt.span, &name.span))
+                    }
+                    _ => None,
+                },
+            })
+            .collect();
+        Ok(Json::Array(symbols))
+    }
+
+    fn hover(&self, params: &Json) -> Result<Json, (i64, String)> {
+        let document = self.requested_document(params)?;
+        let source = document.source();
+        let Some((span, markdown)) = document.hover(offset(source, params.get("position"))) else {
+            return Ok(Json::Null);
+        };
+        let contents = Json::object([("kind", "markdown".into()), ("value", markdown.into())]);
+        Ok(Json::object([("contents", contents), ("range", range(source, &span))]))
+    }
+
+    /// Returns the semantic tokens of the document, encoded relative to each other as the protocol requires.
+    ///
+    /// The tokens of the protocol can't span several lines, so the multiline ones are split at the line breaks.
+    fn semantic_tokens(&self, params: &Json) -> Result<Json, (i64, String)> {
+        let document = self.requested_document(params)?;
+        let source = document.source();
+        let tokens = semantic_tokens(source).unwrap_or_default();
+
+        let mut data = Vec::new();
+        let mut previous = Utf16Position { line: 0, character: 0 };
+        for (span, category, modifiers) in tokens {
+            let category = TokenCategory::ALL.iter().position(|known| *known == category).unwrap_or_default();
+            let (Some(mut position), Some(text)) = (
+                span.start().to_utf16_position(source, LocationPolicy::default()),
+                source.get(span.start().byte_offset()..span.end().byte_offset()),
+            ) else {
+                continue;
+            };
+            for line in text.split_inclusive('\n') {
+                let length: usize = line.trim_end_matches(['\n', '\r']).chars().map(char::len_utf16).sum();
+                if length > 0 {
+                    let start = match position.line == previous.line {
+                        true => position.character - previous.character,
+                        false => position.character,
+                    };
+                    data.extend([position.line - previous.line, start, length, category, modifiers.bits() as usize]);
+                    previous = position;
+                }
+                position = Utf16Position { line: position.line + 1, character: 0 };
+            }
+        }
+        let data: Vec<Json> = data.into_iter().map(Json::from).collect();
+        Ok(Json::object([("data", data.into())]))
+    }
+
+    fn requested_document(&self, params: &Json) -> Result<&Document, (i64, String)> {
+        let uri = params.path(&["textDocument", "uri"]).and_then(Json::as_str).unwrap_or_default();
+        self.documents.get(uri).ok_or_else(|| (INVALID_PARAMS, format!("unknown document `{}`", uri)))
+    }
+}
+
+/// Returns the capability of the semantic tokens, with the names of their categories and of their modifiers.
+fn semantic_tokens_options() -> Json {
+    let categories: Vec<Json> = TokenCategory::ALL.iter().map(|category| category.name().into()).collect();
+    let modifiers: Vec<Json> = Modifiers::NAMES.iter().map(|&name| name.into()).collect();
+    let legend = Json::object([("tokenTypes", categories.into()), ("tokenModifiers", modifiers.into())]);
+    Json::object([("legend", legend), ("full", true.into())])
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    Json::object([("jsonrpc", "2.0".into()), ("method", method.into()), ("params", params)])
+}
+
+fn error_response(id: Json, code: i64, message: &str) -> Json {
+    let error = Json::object([("code", code.into()), ("message", message.into())]);
+    Json::object([("jsonrpc", "2.0".into()), ("id", id), ("error", error)])
+}
+
+fn publish_diagnostics(uri: &str, document: &Document) -> Json {
+    let source = document.source();
+    let diagnostic = |diagnostic: &Diagnostic| {
+        let severity: usize = match diagnostic.severity {
+            Severity::Fatal | Severity::Error => 1,
+            Severity::Warning => 2,
+        };
+        let related: Vec<Json> = diagnostic
+            .notes
+            .iter()
+            .filter_map(|note| {
+                let location = Json::object([("uri", uri.into()), ("range", range(source, note.span.as_ref()?))]);
+                Some(Json::object([("location", location), ("message", note.message.as_str().into())]))
+            })
+            .collect();
+        Json::object([
+            ("range", range(source, &diagnostic.span)),
+            ("severity", severity.into()),
+            ("source", "almora".into()),
+            ("message", diagnostic.message.as_str().into()),
+            ("relatedInformation", related.into()),
+        ])
+    };
+    let diagnostics: Vec<Json> = document.diagnostics().iter().map(diagnostic).collect();
+    let params = Json::object([
+        ("uri", uri.into()),
+        ("version", document.version().into()),
+        ("diagnostics", diagnostics.into()),
+    ]);
+    notification("textDocument/publishDiagnostics", params)
+}
+
+/// Converts a span of the source to a range of the protocol, whose columns are in UTF-16 code units.
+fn range(source: &str, span: &Span) -> Json {
+    Json::object([("start", position(source, span.start())), ("end", position(source, span.end()))])
+}
+
+/// Converts a location of the source to a position of the protocol. A location that is not in the source, like one
+/// computed before an edit, is moved to the end of the source.
+fn position(source: &str, location: &Location) -> Json {
+    let position = location.to_utf16_position(source, LocationPolicy::default()).unwrap_or_else(|| {
+        let line_start = source.rfind('\n').map_or(0, |i| i + 1);
+        Utf16Position {
+            line: source.matches('\n').count(),
+            character: source[line_start..].chars().map(char::len_utf16).sum(),
+        }
+    });
+    Json::object([("line", position.line.into()), ("character", position.character.into())])
+}
+
+/// Converts a position of the protocol to a byte offset in the source. Positions past the end of a line or of the
+/// source are moved back to the end, and missing ones are at the start.
+fn offset(source: &str, position: Option<&Json>) -> usize {
+    let field = |name| position.and_then(|position| position.get(name)).and_then(Json::as_usize).unwrap_or(0);
+    let (line, character) = (field("line"), field("character"));
+
+    let mut line_start = 0;
+    for _ in 0..line {
+        match source[line_start..].find('\n') {
+            Some(end) => line_start += end + 1,
+            None => return source.len(),
+        }
+    }
+
+    let mut units = 0;
+    for (i, c) in source[line_start..].char_indices() {
+        if c == '\n' || units >= character {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    source.len()
+}
+
+/// Decodes the `%XX` escapes of the path of a URI.
+fn decode_uri(path: &str) -> String {
+    let mut bytes = Vec::new();
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail.get(..2).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (byte, escaped) {
+            (b'%', Some(escaped)) => {
+                bytes.push(escaped);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the messages of the client with their headers, like the input of the server.
+    fn input(messages: &[Json]) -> Vec<u8> {
+        let mut input = Vec::new();
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+        input
+    }
+
+    /// Returns the messages written by the server.
+    fn messages(output: &[u8]) -> Vec<Json> {
+        let mut output = output;
+        let mut messages = Vec::new();
+        while let Some(content) = read_message(&mut output).unwrap() {
+            messages.push(Json::parse(&content).unwrap());
+        }
+        messages
+    }
+
+    fn request(id: usize, method: &str, params: Json) -> Json {
+        Json::object([("jsonrpc", "2.0".into()), ("id", id.into()), ("method", method.into()), ("params", params)])
+    }
+
+    fn position(line: usize, character: usize) -> Json {
+        Json::object([("line", line.into()), ("character", character.into())])
+    }
+
+    fn range(start: Json, end: Json) -> Json {
+        Json::object([("start", start), ("end", end)])
+    }
+
+    #[test]
+    fn test_offset() {
+        let source = "ab\né😀x\n";
+        let offset = |line, character| offset(source, Some(&position(line, character)));
+        assert_eq!(offset(0, 1), 1);
+        assert_eq!(offset(0, 5), 2);
+        assert_eq!(offset(1, 1), 5);
+        // The emoji is 2 UTF-16 code units, and 4 bytes
+        assert_eq!(offset(1, 3), 9);
+        assert_eq!(offset(2, 0), source.len());
+        assert_eq!(offset(7, 0), source.len());
+        assert_eq!(decode_uri("/home/a%20b/%C3%A9%"), "/home/a b/é%");
+    }
+
+    #[test]
+    fn test_range() {
+        let source = "ab\né😀x\n";
+        let span = Span::new(Location::with_byte_offset(2, 2, 4, 5), Location::with_byte_offset(2, 3, 5, 9));
+        assert_eq!(super::range(source, &span), range(position(1, 1), position(1, 3)));
+
+        // A span computed before an edit that shortened the source is moved to its end
+        let stale = Span::new(Location::with_byte_offset(2, 3, 5, 6), Location::with_byte_offset(4, 1, 30, 40));
+        assert_eq!(super::range(source, &stale), range(position(2, 0), position(2, 0)));
+    }
+
+    #[test]
+    fn test_read_message() {
+        let mut input = "Content-Length: 2\r\n\r\n{}".as_bytes();
+        assert_eq!(read_message(&mut input).unwrap(), Some("{}".to_string()));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+
+        // A huge length is rejected before allocating anything
+        let mut input = "Content-Length: 18446744073709551615\r\n\r\n{}".as_bytes();
+        assert_eq!(read_message(&mut input).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_server() {
+        let uri = "file:///project/main.alm";
+        let document = |fields: &[(&'static str, Json)]| {
+            Json::object([("uri", uri.into())].into_iter().chain(fields.iter().cloned()))
+        };
+        let text = "fn f(x: int) -> int { return x; }\nlet y = f(1);\n";
+        let open = document(&[("version", 1_usize.into()), ("text", text.into())]);
+        let change = Json::object([("range", range(position(1, 10), position(1, 11))), ("text", "z".into())]);
+        let input = input(&[
+            request(1, "initialize", Json::object([("rootUri", Json::Null)])),
+            notification("initialized", Json::object([])),
+            notification("textDocument/didOpen", Json::object([("textDocument", open)])),
+            notification(
+                "textDocument/didChange",
+                Json::object([
+                    ("textDocument", document(&[("version", 2_usize.into())])),
+                    ("contentChanges", vec![change].into()),
+                ]),
+            ),
+            request(
+                3,
+                "textDocument/hover",
+                Json::object([("textDocument", document(&[])), ("position", position(1, 8))]),
+            ),
+            request(4, "textDocument/documentSymbol", Json::object([("textDocument", document(&[]))])),
+            request(5, "unknown", Json::Null),
+            Json::String("not an object".to_string()),
+            request(6, "shutdown", Json::Null),
+            notification("exit", Json::Null),
+            request(7, "shutdown", Json::Null),
+        ]);
+        let mut output = Vec::new();
+        assert!(run(&mut input.as_slice(), &mut output).unwrap());
+        let messages = messages(&output);
+        let field = |index: usize, path: &[&str]| messages[index].path(path).map(ToString::to_string);
+
+        assert_eq!(messages.len(), 8);
+        assert_eq!(field(0, &["result", "capabilities", "textDocumentSync", "change"]), Some("2".to_string()));
+        assert_eq!(field(1, &["method"]), Some("\"textDocument/publishDiagnostics\"".to_string()));
+        assert_eq!(field(1, &["params", "diagnostics"]), Some("[]".to_string()));
+        // The change replaced the argument of the call
+        let diagnostics = messages[2].path(&["params", "diagnostics"]).and_then(Json::as_array).unwrap();
+        assert_eq!(field(2, &["params", "version"]), Some("2".to_string()));
+        assert_eq!(diagnostics[0].get("range"), Some(&range(position(1, 10), position(1, 11))));
+        assert_eq!(diagnostics[0].get("message").and_then(Json::as_str), Some("unknown name `z`"));
+
+        assert_eq!(
+            field(3, &["result", "contents", "value"]),
+            Some("\"```almora\\nfn f(x: int) -> int\\n```\"".to_string())
+        );
+        assert_eq!(messages[3].path(&["result", "range"]), Some(&range(position(1, 8), position(1, 9))));
+        let symbols = messages[4].get("result").and_then(Json::as_array).unwrap();
+        let symbol = |symbol: &Json| ["name", "detail", "kind"].map(|field| symbol.get(field).cloned());
+        assert_eq!(
+            symbols.iter().map(symbol).collect::<Vec<_>>(),
+            [
+                [Some("f".into()), Some("fn f(x: int) -> int".into()), Some(12_usize.into())],
+                [Some("y".into()), None, Some(13_usize.into())],
+            ]
+        );
+
+        assert_eq!(field(5, &["error", "code"]), Some("-32601".to_string()));
+        assert_eq!(field(6, &["id"]), Some("null".to_string()));
+        assert_eq!(field(6, &["error", "code"]), Some("-32600".to_string()));
+        // The server stops at `exit`
+        assert_eq!(field(7, &["result"]), Some("null".to_string()));
+    }
+
+    #[test]
+    fn test_semantic_tokens() {
+        let mut server = Server::new();
+        let document = Json::object([("uri", "a.alm".into()), ("text", "/* a\nb */ let x = 1;\n".into())]);
+        server.handle(&notification("textDocument/didOpen", Json::object([("textDocument", document)])));
+        let params = Json::object([("textDocument", Json::object([("uri", "a.alm".into())]))]);
+        let response = server.handle(&request(1, "textDocument/semanticTokens/full", params));
+
+        // The comment is split in 2 lines, and the variable is a global declaration
+        let data = response[0].path(&["result", "data"]).and_then(Json::as_array).unwrap();
+        let data: Vec<usize> = data.iter().filter_map(Json::as_usize).collect();
+        assert_eq!(data, [0, 0, 4, 3, 0, 1, 0, 4, 3, 0, 0, 5, 3, 0, 0, 0, 4, 1, 7, 0b11, 0, 4, 1, 2, 0]);
+    }
+}
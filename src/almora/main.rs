@@ -1,19 +1,58 @@
-use crate::parser_lib::{MatchStr, Stream};
+use std::sync::OnceLock;
 
-use super::grammar::*;
+use crate::parser_lib::{Grammar, Location, ParseNode, SourceId, StringCharReader};
 
-pub fn compile<R: 'static + MatchStr >() {
-    let grammar = almora::define_grammar::<R>();
-    println!("{}", grammar);
+use super::{ast, ast::Program, grammar::*, CompileError};
+
+/// Grammar shared by the compilations, since it doesn't change.
+static GRAMMAR: OnceLock<Grammar<StringCharReader>> = OnceLock::new();
+
+/// Returns the almora grammar, which is defined the first time it is needed.
+pub(crate) fn grammar() -> &'static Grammar<StringCharReader> {
+    GRAMMAR.get_or_init(almora::define_grammar)
+}
+
+/// Parses the source, and returns the AST of the program.
+pub fn compile(source: &str) -> Result<Program, CompileError> {
+    compile_source(source, SourceId::default())
+}
+
+/// Parses the source with the given id, which is given to the locations of the AST and of the error.
+pub fn compile_source(source: &str, id: SourceId) -> Result<Program, CompileError> {
+    let grammar = grammar();
+    let mut reader = StringCharReader::new(source);
+    let tree = grammar.parse_tree(&Location::beginning().with_source(id), &mut reader)?;
+    lower_tree(tree.as_ref(), source)
+}
+
+/// Returns the AST of a parse tree of the grammar, which must match the whole source.
+pub(crate) fn lower_tree(tree: Option<&ParseNode>, source: &str) -> Result<Program, CompileError> {
+    // The program matches at least the empty input, so the end of the match is where the source is wrong
+    let tree = tree.expect("the program matches the empty input");
+    if tree.span().end().byte_offset() < source.len() {
+        return Err(CompileError::UnexpectedInput(*tree.span().end()));
+    }
+    ast::lower(tree, source)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::parser_lib::{Location, MatchToken, ParseInfo, Span, StringCharReader};
+    use crate::almora::ast::{Item, StmtKind};
 
     use super::*;
 
     #[test]
     fn test_compile() {
+        let program = compile("fn main() {\n    print(1);\n}\nmain();\n").unwrap();
+        assert_eq!(program.items.len(), 2);
+        assert!(matches!(&program.items[0], Item::Function(function) if function.name.name == "main"));
+        assert!(matches!(&program.items[1], Item::Statement(stmt) if matches!(stmt.kind, StmtKind::Expr(_))));
+
+        assert_eq!(compile("").unwrap().items, []);
+
+        // The error is where the program stops matching
+        let err = compile("let x = 1;\nlet = 2;").unwrap_err();
+        assert_eq!(err, CompileError::UnexpectedInput(Location::new(2, 1, 11)));
+        assert_eq!(err.to_string(), "2:1: unexpected input.");
     }
 }
@@ -1,5 +1,25 @@
+pub mod ast;
+pub mod bytecode;
+mod builtins;
+mod diagnostic;
+pub mod doc;
+mod error;
 mod grammar;
+pub mod lsp;
 mod main;
+mod module_loader;
+pub mod optimizer;
 pub mod parser;
+mod resolver;
+mod semantic_tokens;
+mod symbol_table;
 
+pub use builtins::{Builtin, BuiltinFunction, Builtins, CallContext};
+pub use diagnostic::{Diagnostic, Note, Severity};
+pub use error::CompileError;
 pub use grammar::almora;
+pub use main::{compile, compile_source};
+pub use module_loader::{LoadedModule, ModuleId, ModuleLoader, MODULE_EXTENSION};
+pub use resolver::{resolve, resolve_with, ResolveOptions};
+pub use semantic_tokens::{semantic_tokens, Modifiers, TokenCategory};
+pub use symbol_table::{Namespace, Reference, Scope, ScopeId, Symbol, SymbolId, SymbolKind, SymbolTable};
@@ -0,0 +1,363 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::parser_lib::{Location, MultiFileCharReader, SourceId, Span};
+
+use super::{
+    ast::{Item, Program},
+    compile_source,
+    diagnostic::suggest,
+    main::grammar, resolve_with, Builtins, CompileError, Diagnostic, Namespace, ResolveOptions, SymbolTable,
+};
+
+/// Extension of the files of the almora modules.
+pub const MODULE_EXTENSION: &str = "alm";
+
+/// Index of a module in a `ModuleLoader`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ModuleId(usize);
+
+/// Module loaded by a `ModuleLoader`, with its program resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadedModule {
+    /// Name of the module, like `geometry.shapes`.
+    pub name: String,
+    /// File of the module, relative to the root of the project.
+    pub path: PathBuf,
+    /// Source of the module in the source map of the loader, which is in the locations of its AST.
+    pub source: SourceId,
+    /// AST of the module, or None if it could not be parsed.
+    pub program: Option<Program>,
+    pub symbols: SymbolTable,
+    /// Modules imported by this one that could be loaded, in import order.
+    pub imports: Vec<ModuleId>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Loads the modules of a project, and the modules they import.
+///
+/// The module `a.b` is in the file `a/b.alm` under the root of the project. The sources of the modules are kept in a
+/// source map, where the `SourceId` of a location gives the module it belongs to. Each module is resolved with the
+/// namespaces of the modules it imports, which are loaded first. Import cycles are errors.
+#[derive(Debug)]
+pub struct ModuleLoader {
+    root: PathBuf,
+    /// Sources given in memory, by module name. They are used instead of the files.
+    sources: HashMap<String, String>,
+    source_map: MultiFileCharReader,
+    modules: Vec<LoadedModule>,
+    by_name: HashMap<String, ModuleId>,
+    builtins: Builtins,
+}
+
+impl ModuleLoader {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            sources: HashMap::new(),
+            source_map: MultiFileCharReader::new(),
+            modules: Vec::new(),
+            by_name: HashMap::new(),
+            builtins: Builtins::standard(),
+        }
+    }
+
+    /// Replaces the builtins visible in the modules, which are the standard ones by default.
+    pub fn with_builtins(mut self, builtins: Builtins) -> Self {
+        self.builtins = builtins;
+        self
+    }
+
+    /// Gives the source of a module, which is used instead of its file.
+    pub fn add_source(&mut self, module: &str, source: &str) {
+        self.sources.insert(module.to_string(), source.to_string());
+    }
+
+    /// Returns the file of a module, relative to the root of the project.
+    pub fn path_of(module: &str) -> PathBuf {
+        let mut path: PathBuf = module.split('.').collect();
+        path.set_extension(MODULE_EXTENSION);
+        path
+    }
+
+    /// Loads the module and the modules it imports, unless it is already loaded.
+    ///
+    /// Fails if the source of the module can't be read. The errors in the module and in the modules it imports are
+    /// in their diagnostics: a module that can't be read is reported at its import.
+    pub fn load(&mut self, module: &str) -> io::Result<ModuleId> {
+        self.load_module(module, &mut Vec::new())
+    }
+
+    /// Loads a module, while the modules of the stack are being loaded.
+    fn load_module(&mut self, module: &str, stack: &mut Vec<String>) -> io::Result<ModuleId> {
+        if let Some(id) = self.by_name.get(module) {
+            return Ok(*id);
+        }
+
+        let path = Self::path_of(module);
+        let source = match self.sources.get(module) {
+            Some(source) => source.clone(),
+            None => fs::read_to_string(self.root.join(&path))?,
+        };
+        let source_id = self.source_map.add_source(&path.to_string_lossy(), &source);
+        let id = ModuleId(self.modules.len());
+        self.by_name.insert(module.to_string(), id);
+        self.modules.push(LoadedModule {
+            name: module.to_string(),
+            path,
+            source: source_id,
+            program: None,
+            symbols: SymbolTable::default(),
+            imports: Vec::new(),
+            diagnostics: Vec::new(),
+        });
+
+        let program = match compile_source(&source, source_id) {
+            Ok(program) => program,
+            Err(err) => {
+                self.modules[id.0].diagnostics.push(compile_error(err, source_id, &source));
+                return Ok(id);
+            }
+        };
+
+        // The imported modules are loaded first, to resolve the module with their namespaces
+        let mut options = ResolveOptions::new().with_builtins(&self.builtins);
+        let mut imports = Vec::new();
+        let mut diagnostics = Vec::new();
+        stack.push(module.to_string());
+        for item in &program.items {
+            let Item::Import(import) = item else {
+                continue;
+            };
+            let name = &import.module.name;
+            let span = import.module.span.clone();
+
+            if let Some(start) = stack.iter().position(|loading| loading == name) {
+                let cycle = stack[start..].iter().chain([name]).map(String::as_str).collect::<Vec<_>>().join(" -> ");
+                diagnostics.push(Diagnostic::error(format!("import cycle: {}", cycle), span));
+                options = options.with_import(name, Namespace::default());
+                continue;
+            }
+            match self.load_module(name, stack) {
+                Ok(imported) => {
+                    imports.push(imported);
+                    options = options.with_import(name, Namespace::of(&self.modules[imported.0].symbols));
+                }
+                Err(err) => {
+                    let path = self.root.join(Self::path_of(name));
+                    let message = format!("cannot load module `{}` from `{}`: {}", name, path.display(), err);
+                    diagnostics.push(Diagnostic::error(message, span));
+                    options = options.with_import(name, Namespace::default());
+                }
+            }
+        }
+        stack.pop();
+
+        let (symbols, resolve_diagnostics) = resolve_with(&program, &options);
+        diagnostics.extend(resolve_diagnostics);
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span.start().index());
+
+        let loaded = &mut self.modules[id.0];
+        loaded.program = Some(program);
+        loaded.symbols = symbols;
+        loaded.imports = imports;
+        loaded.diagnostics = diagnostics;
+        Ok(id)
+    }
+
+    /// Panics if the module was not loaded by this loader.
+    pub fn module(&self, id: ModuleId) -> &LoadedModule {
+        &self.modules[id.0]
+    }
+
+    /// Returns the loaded module with the given name, if any.
+    pub fn lookup(&self, module: &str) -> Option<ModuleId> {
+        self.by_name.get(module).copied()
+    }
+
+    /// Returns the loaded modules, in the order they were found: a module comes before the ones it imports first.
+    pub fn modules(&self) -> impl Iterator<Item = (ModuleId, &LoadedModule)> {
+        self.modules.iter().enumerate().map(|(index, module)| (ModuleId(index), module))
+    }
+
+    /// Returns the module of a location of the AST, from its `SourceId`.
+    pub fn module_of(&self, source: SourceId) -> Option<ModuleId> {
+        self.modules.iter().position(|module| module.source == source).map(ModuleId)
+    }
+
+    /// Returns the name of the file of a source, to report locations.
+    pub fn source_name(&self, source: SourceId) -> Option<&str> {
+        self.source_map.source_name(source)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the diagnostics of all the modules.
+    pub fn diagnostics(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.modules.iter().flat_map(|module| &module.diagnostics)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics().any(Diagnostic::is_error)
+    }
+}
+
+/// Reports an error of the parser in the given text as a fatal diagnostic, since the parse stops there.
+///
+/// If the unexpected input starts with a word close to a keyword, like `whle`, the keyword is suggested.
+pub(crate) fn compile_error(err: CompileError, source: SourceId, text: &str) -> Diagnostic {
+    let suggestion = match &err {
+        CompileError::UnexpectedInput(location) => {
+            let rest = &text[location.byte_offset()..];
+            let word = &rest[..rest.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(rest.len())];
+            let keywords = grammar().reserved_words();
+            match keywords.iter().any(|keyword| keyword == word) {
+                true => None,
+                false => suggest(word, keywords.iter().map(String::as_str)),
+            }
+        }
+        _ => None,
+    };
+    let span = match &err {
+        CompileError::UnexpectedInput(location) => Span::new(*location, *location),
+        CompileError::InvalidLiteral(span) | CompileError::LiteralOverflow(span) => span.clone(),
+        CompileError::Parser(_) => {
+            let start = Location::beginning().with_source(source);
+            Span::new(start, start)
+        }
+    };
+    let message = match err {
+        CompileError::UnexpectedInput(_) => "unexpected input".to_string(),
+        CompileError::InvalidLiteral(_) => "invalid literal".to_string(),
+        CompileError::LiteralOverflow(_) => "number literal out of range".to_string(),
+        CompileError::Parser(err) => err.to_string(),
+    };
+    match suggestion {
+        Some(keyword) => Diagnostic::fatal(message, span).with_note(format!("did you mean `{}`?", keyword), None),
+        None => Diagnostic::fatal(message, span),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::almora::SymbolKind;
+
+    use super::*;
+
+    /// Returns the diagnostics of the loader like `shapes.alm:3:5: error: message`.
+    fn diagnostics(loader: &ModuleLoader) -> Vec<String> {
+        loader
+            .diagnostics()
+            .map(|diagnostic| {
+                let source = loader.source_name(diagnostic.span.start().source()).unwrap();
+                format!("{}:{}: {}: {}", source, diagnostic.span.start(), diagnostic.severity, diagnostic.message)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_load_modules() {
+        let mut loader = ModuleLoader::new("project");
+        loader.add_source("main", "import geometry.shapes;\nimport util;\nlet a = area(2) + twice(1);\n");
+        loader.add_source("geometry.shapes", "import util;\nfn area(side: int) -> int { return twice(side * side); }\n");
+        loader.add_source("util", "fn twice(x: int) -> int { return x * 2; }\nlet unused = 0;\n");
+
+        let main = loader.load("main").unwrap();
+        assert_eq!(diagnostics(&loader), Vec::<String>::new());
+
+        // The imported modules are loaded first, and only once
+        let names: Vec<&str> = loader.modules().map(|(_, module)| module.name.as_str()).collect();
+        assert_eq!(names, ["main", "geometry.shapes", "util"]);
+        let shapes = loader.lookup("geometry.shapes").unwrap();
+        let util = loader.lookup("util").unwrap();
+        assert_eq!(loader.module(main).imports, [shapes, util]);
+        assert_eq!(loader.module(shapes).imports, [util]);
+        assert_eq!(loader.module(shapes).path, Path::new("geometry").join("shapes.alm"));
+        assert_eq!(loader.load("util").unwrap(), util);
+
+        // The namespace of a module doesn't contain the names it imports
+        let symbols = &loader.module(main).symbols;
+        let imported: Vec<(&str, SymbolKind)> = symbols.symbols().map(|(_, s)| (s.name.as_str(), s.kind)).collect();
+        assert_eq!(
+            imported,
+            [
+                ("area", SymbolKind::Import),
+                ("twice", SymbolKind::Import),
+                ("unused", SymbolKind::Import),
+                ("a", SymbolKind::Variable),
+            ]
+        );
+        let namespace = Namespace::of(&loader.module(shapes).symbols);
+        assert_eq!(namespace.symbols().map(|symbol| symbol.name.as_str()).collect::<Vec<_>>(), ["area"]);
+
+        // The locations tell the module they belong to
+        let program = loader.module(shapes).program.as_ref().unwrap();
+        let Item::Function(function) = &program.items[1] else {
+            panic!("expected a function");
+        };
+        assert_eq!(loader.module_of(function.span.start().source()), Some(shapes));
+        assert_eq!(loader.source_name(loader.module(shapes).source), Some("geometry/shapes.alm"));
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let mut loader = ModuleLoader::new("project");
+        loader.add_source("a", "import b;\nimport missing;\nimport broken;\nfn f() { g(); h(); }\n");
+        loader.add_source("b", "import a;\nfn g() {}\n");
+        loader.add_source("broken", "let x = 1;\nwhle x < 2 { x = x + 1; }\n");
+
+        loader.load("a").unwrap();
+        assert!(loader.has_errors());
+        let errors = diagnostics(&loader);
+        let missing = Path::new("project").join("missing.alm");
+        assert!(errors[0].starts_with(&format!(
+            "a.alm:2:8: error: cannot load module `missing` from `{}`: ",
+            missing.display()
+        )));
+        assert_eq!(
+            errors[1..],
+            [
+                "a.alm:4:15: error: unknown name `h`",
+                "b.alm:1:8: error: import cycle: a -> b -> a",
+                "broken.alm:2:1: fatal error: unexpected input",
+            ]
+        );
+        let broken = loader.module(loader.lookup("broken").unwrap());
+        assert_eq!(broken.program, None);
+        assert_eq!(broken.diagnostics[0].notes[0].message, "did you mean `while`?");
+
+        // The module given to `load` must exist
+        assert!(loader.load("missing").is_err());
+        assert_eq!(loader.lookup("missing"), None);
+    }
+
+    #[test]
+    fn test_compile_error() {
+        // A keyword with two swapped chars is suggested
+        let source = "whlie true {}";
+        let err = compile_source(source, SourceId::default()).unwrap_err();
+        let diagnostic = compile_error(err, SourceId::default(), source);
+        assert_eq!(diagnostic.to_string(), "1:1: fatal error: unexpected input\nnote: did you mean `while`?");
+    }
+
+    #[test]
+    fn test_load_files() {
+        let root = std::env::temp_dir().join(format!("almora_modules_{}", std::process::id()));
+        fs::create_dir_all(root.join("lib")).unwrap();
+        fs::write(root.join("main.alm"), "import lib.math;\nlet x = square(3);\n").unwrap();
+        fs::write(root.join("lib").join("math.alm"), "fn square(x: int) -> int { return x * x; }\n").unwrap();
+
+        let mut loader = ModuleLoader::new(&root);
+        let main = loader.load("main");
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(diagnostics(&loader), Vec::<String>::new());
+        assert_eq!(loader.module(main.unwrap()).imports, [loader.lookup("lib.math").unwrap()]);
+        assert_eq!(loader.root(), root);
+    }
+}
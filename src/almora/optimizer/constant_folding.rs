@@ -0,0 +1,158 @@
+use std::cmp::Ordering;
+
+use crate::almora::ast::{BinaryOp, Expr, ExprKind, Literal, Program, UnaryOp};
+
+use super::{rewrite_program, Pass, Rewrite};
+
+/// Computes the operations whose operands are literals, like the VM would.
+///
+/// The operations that would fail at runtime, like a division by zero or an integer overflow, are kept so that they
+/// still fail. The logical operators are folded as soon as their left operand is a literal: `true && x` is `x`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConstantFolding;
+
+impl Pass for ConstantFolding {
+    fn name(&self) -> &'static str {
+        "constant folding"
+    }
+
+    fn run(&self, program: &mut Program) -> bool {
+        rewrite_program(program, &mut ConstantFolding)
+    }
+}
+
+impl Rewrite for ConstantFolding {
+    fn expr(&mut self, expr: &mut Expr) -> bool {
+        let folded = match &expr.kind {
+            ExprKind::Unary { op, operand } => match &operand.kind {
+                ExprKind::Literal(value) => unary(*op, value).map(ExprKind::Literal),
+                _ => None,
+            },
+            ExprKind::Binary { op: op @ (BinaryOp::And | BinaryOp::Or), left, right } => match left.kind {
+                ExprKind::Literal(Literal::Boolean(value)) if value == (*op == BinaryOp::And) => {
+                    Some(right.kind.clone())
+                }
+                ExprKind::Literal(Literal::Boolean(value)) => Some(ExprKind::Literal(Literal::Boolean(value))),
+                _ => None,
+            },
+            ExprKind::Binary { op, left, right } => match (&left.kind, &right.kind) {
+                (ExprKind::Literal(left), ExprKind::Literal(right)) => binary(*op, left, right).map(ExprKind::Literal),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        match folded {
+            Some(kind) => {
+                expr.kind = kind;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn unary(op: UnaryOp, value: &Literal) -> Option<Literal> {
+    match (op, value) {
+        (UnaryOp::Neg, Literal::Integer(value)) => value.checked_neg().map(Literal::Integer),
+        (UnaryOp::Neg, Literal::Float(value)) => Some(Literal::Float(-value)),
+        (UnaryOp::Not, Literal::Boolean(value)) => Some(Literal::Boolean(!value)),
+        _ => None,
+    }
+}
+
+fn binary(op: BinaryOp, left: &Literal, right: &Literal) -> Option<Literal> {
+    match op {
+        BinaryOp::Eq => return Some(Literal::Boolean(equals(left, right))),
+        BinaryOp::Ne => return Some(Literal::Boolean(!equals(left, right))),
+        BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+            let ordering = match (left, right) {
+                (Literal::Integer(a), Literal::Integer(b)) => Some(a.cmp(b)),
+                (Literal::String(a), Literal::String(b)) => Some(a.cmp(b)),
+                _ => as_float(left)?.partial_cmp(&as_float(right)?),
+            };
+            let result = match op {
+                BinaryOp::Lt => ordering == Some(Ordering::Less),
+                BinaryOp::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                BinaryOp::Gt => ordering == Some(Ordering::Greater),
+                _ => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+            };
+            return Some(Literal::Boolean(result));
+        }
+        _ => {}
+    }
+
+    match (left, right) {
+        (Literal::Integer(a), Literal::Integer(b)) => {
+            let result = match op {
+                BinaryOp::Add => a.checked_add(*b),
+                BinaryOp::Sub => a.checked_sub(*b),
+                BinaryOp::Mul => a.checked_mul(*b),
+                BinaryOp::Div => a.checked_div(*b),
+                BinaryOp::Rem => a.checked_rem(*b),
+                _ => None,
+            };
+            result.map(Literal::Integer)
+        }
+        (Literal::String(a), Literal::String(b)) if op == BinaryOp::Add => Some(Literal::String(format!("{}{}", a, b))),
+        _ => {
+            let (a, b) = (as_float(left)?, as_float(right)?);
+            let result = match op {
+                BinaryOp::Add => a + b,
+                BinaryOp::Sub => a - b,
+                BinaryOp::Mul => a * b,
+                BinaryOp::Div => a / b,
+                BinaryOp::Rem => a % b,
+                _ => return None,
+            };
+            // Infinities and NaN can't be written as literals
+            result.is_finite().then_some(Literal::Float(result))
+        }
+    }
+}
+
+fn as_float(value: &Literal) -> Option<f64> {
+    match value {
+        Literal::Integer(value) => Some(*value as f64),
+        Literal::Float(value) => Some(*value),
+        _ => None,
+    }
+}
+
+/// Values of different types are not equal, except integers and floats with the same value.
+fn equals(left: &Literal, right: &Literal) -> bool {
+    match (left, right) {
+        (Literal::Integer(a), Literal::Float(b)) | (Literal::Float(b), Literal::Integer(a)) => *a as f64 == *b,
+        _ => left == right,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::almora::optimizer::tests::optimized;
+
+    use super::*;
+
+    #[test]
+    fn test_constant_folding() {
+        let fold = |source: &str| optimized(&ConstantFolding, source);
+        assert_eq!(fold("1 + 2 * 3;"), "7;");
+        assert_eq!(fold("-(7 / 2) + 7 % 2 * 1.5;"), "-1.5;");
+        assert_eq!(fold("\"a\" + \"b\" + \"c\";"), "\"abc\";");
+        assert_eq!(fold("(1 < 2 == (2.0 >= 2)) != !(\"a\" > \"b\");"), "false;");
+        assert_eq!(fold("1 == true;"), "false;");
+        assert_eq!(fold("let x = 1;\nx + 2 * 3;"), "let x = 1; (+ x 6);");
+
+        // Logical operators only need their left operand
+        assert_eq!(fold("let x = 1;\ntrue && x;"), "let x = 1; x;");
+        assert_eq!(fold("let x = 1;\nfalse && x;"), "let x = 1; false;");
+        assert_eq!(fold("let x = 1;\n1 < 2 || x;"), "let x = 1; true;");
+        assert_eq!(fold("let x = 1;\nx || true;"), "let x = 1; (|| x true);");
+
+        // Operations failing at runtime are kept
+        assert_eq!(fold("1 / 0;"), "(/ 1 0);");
+        assert_eq!(fold("9223372036854775807 + 1;"), "(+ 9223372036854775807 1);");
+        assert_eq!(fold("1.0 / 0;"), "(/ 1.0 0);");
+        assert_eq!(fold("-true + 1;"), "(+ (- true) 1);");
+    }
+}
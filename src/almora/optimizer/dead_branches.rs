@@ -0,0 +1,92 @@
+use crate::almora::ast::{Block, ExprKind, Literal, Program, Stmt, StmtKind};
+
+use super::{rewrite_program, Pass, Rewrite};
+
+/// Removes the branches that can't run because their condition is a literal.
+///
+/// An `if` with a literal condition is replaced by the branch that runs, which stays a block to keep its scope. A
+/// `while false` loop is removed, like the empty blocks and the empty `else` branches.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeadBranchElimination;
+
+impl Pass for DeadBranchElimination {
+    fn name(&self) -> &'static str {
+        "dead branch elimination"
+    }
+
+    fn run(&self, program: &mut Program) -> bool {
+        rewrite_program(program, &mut DeadBranchElimination)
+    }
+}
+
+impl Rewrite for DeadBranchElimination {
+    fn stmt(&mut self, stmt: &mut Stmt) -> bool {
+        let span = stmt.span.clone();
+        let empty = || StmtKind::Block(Block { stmts: Vec::new(), span: span.clone() });
+
+        let replacement = match &mut stmt.kind {
+            StmtKind::If { condition, then_branch, else_branch } => match condition.kind {
+                ExprKind::Literal(Literal::Boolean(true)) => Some(StmtKind::Block(then_branch.clone())),
+                ExprKind::Literal(Literal::Boolean(false)) => match else_branch.take() {
+                    Some(else_branch) => Some(else_branch.kind),
+                    None => Some(empty()),
+                },
+                _ if else_branch.as_deref().is_some_and(is_empty) => {
+                    *else_branch = None;
+                    return true;
+                }
+                _ => None,
+            },
+            StmtKind::While { condition, .. } if condition.kind == ExprKind::Literal(Literal::Boolean(false)) => {
+                Some(empty())
+            }
+            _ => None,
+        };
+
+        match replacement {
+            Some(kind) => {
+                stmt.kind = kind;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn keep(&mut self, stmt: &Stmt) -> bool {
+        !is_empty(stmt)
+    }
+}
+
+fn is_empty(stmt: &Stmt) -> bool {
+    matches!(&stmt.kind, StmtKind::Block(block) if block.stmts.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::almora::optimizer::tests::optimized;
+
+    use super::*;
+
+    #[test]
+    fn test_dead_branch_elimination() {
+        let eliminate = |source: &str| optimized(&DeadBranchElimination, source);
+        assert_eq!(eliminate("fn f() {}\nif true { f(); } else { f(1); }"), "fn f {} { (call f []); }");
+        assert_eq!(eliminate("fn f() {}\nif false { f(); } else { f(1); }"), "fn f {} { (call f [1]); }");
+        assert_eq!(eliminate("fn f() {}\nif false { f(); }\nf(2);"), "fn f {} (call f [2]);");
+        assert_eq!(
+            eliminate("let x = 1;\nif x > 0 { x = 2; } else if false { x = 3; } else if true { x = 4; }"),
+            "let x = 1; if (> x 0) { x = 2; } else { x = 4; }"
+        );
+        assert_eq!(
+            eliminate("let x = 1;\nif x > 0 { x = 2; } else if false { x = 3; }"),
+            "let x = 1; if (> x 0) { x = 2; }"
+        );
+
+        // Loops
+        assert_eq!(eliminate("fn f() { while false { f(); } return; }"), "fn f { return; }");
+        assert_eq!(eliminate("fn f() { while true { { } } }"), "fn f { while true {} }");
+
+        // Conditions which are not literals are kept
+        assert_eq!(eliminate("let x = true;\nif !x { x = false; }"), "let x = true; if (! x) { x = false; }");
+    }
+}
@@ -0,0 +1,328 @@
+//! Passes that transform the AST of a program into a simpler one that does the same, run by a `PassManager`.
+
+mod constant_folding;
+mod dead_branches;
+mod simplification;
+
+pub use constant_folding::ConstantFolding;
+pub use dead_branches::DeadBranchElimination;
+pub use simplification::AlgebraicSimplification;
+
+use super::ast::{Block, Expr, ExprKind, Item, Program, Stmt, StmtKind, StringPart};
+
+/// Transformation of a program.
+pub trait Pass {
+    /// Name of the pass, to tell it apart from the others.
+    fn name(&self) -> &'static str;
+
+    /// Transforms the program, and returns whether it changed.
+    fn run(&self, program: &mut Program) -> bool;
+}
+
+/// Runs passes on a program, in order, until none of them changes it.
+///
+/// A pass can enable another one: for example, folding a condition lets the dead branch be removed. The passes are run
+/// again while one of them changes the program, up to a maximum number of rounds.
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+    max_rounds: usize,
+}
+
+impl PassManager {
+    /// Creates a manager without passes.
+    pub fn new() -> Self {
+        Self { passes: Vec::new(), max_rounds: 8 }
+    }
+
+    /// Adds a pass, run after the ones already added.
+    pub fn with_pass(mut self, pass: impl Pass + 'static) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// Sets the maximum number of times the passes are run. The default is 8.
+    pub fn with_max_rounds(mut self, max_rounds: usize) -> Self {
+        self.max_rounds = max_rounds;
+        self
+    }
+
+    /// Returns the names of the passes, in order.
+    pub fn passes(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.passes.iter().map(|pass| pass.name())
+    }
+
+    /// Runs the passes on the program, and returns whether it changed.
+    pub fn run(&self, program: &mut Program) -> bool {
+        let mut changed = false;
+        for _ in 0..self.max_rounds {
+            let mut round_changed = false;
+            for pass in &self.passes {
+                round_changed |= pass.run(program);
+            }
+            if !round_changed {
+                break;
+            }
+            changed = true;
+        }
+        changed
+    }
+}
+
+/// A manager is a pass that runs its passes, so it can be nested in another manager.
+impl Pass for PassManager {
+    fn name(&self) -> &'static str {
+        "pass manager"
+    }
+
+    fn run(&self, program: &mut Program) -> bool {
+        PassManager::run(self, program)
+    }
+}
+
+/// The standard passes: algebraic simplification, constant folding and dead branch elimination.
+impl Default for PassManager {
+    fn default() -> Self {
+        Self::new()
+            .with_pass(AlgebraicSimplification)
+            .with_pass(ConstantFolding)
+            .with_pass(DeadBranchElimination)
+    }
+}
+
+/// Runs the standard passes on the program.
+pub fn optimize(program: &mut Program) {
+    PassManager::default().run(program);
+}
+
+/// Rewrites the nodes of a program bottom up: the children of a node are rewritten before it.
+///
+/// Each method returns whether it changed the node.
+trait Rewrite {
+    fn expr(&mut self, _expr: &mut Expr) -> bool {
+        false
+    }
+
+    fn stmt(&mut self, _stmt: &mut Stmt) -> bool {
+        false
+    }
+
+    /// Returns whether the statement is kept in its block.
+    fn keep(&mut self, _stmt: &Stmt) -> bool {
+        true
+    }
+}
+
+fn rewrite_program(program: &mut Program, rewriter: &mut impl Rewrite) -> bool {
+    let mut changed = false;
+    for item in &mut program.items {
+        changed |= match item {
+            Item::Import(_) => false,
+            Item::Function(function) => rewrite_block(&mut function.body, rewriter),
+            Item::Statement(stmt) => rewrite_stmt(stmt, rewriter),
+        };
+    }
+    let len = program.items.len();
+    program.items.retain(|item| match item {
+        Item::Import(_) | Item::Function(_) => true,
+        Item::Statement(stmt) => rewriter.keep(stmt),
+    });
+    changed || program.items.len() != len
+}
+
+fn rewrite_block(block: &mut Block, rewriter: &mut impl Rewrite) -> bool {
+    let mut changed = false;
+    for stmt in &mut block.stmts {
+        changed |= rewrite_stmt(stmt, rewriter);
+    }
+    let len = block.stmts.len();
+    block.stmts.retain(|stmt| rewriter.keep(stmt));
+    changed || block.stmts.len() != len
+}
+
+fn rewrite_stmt(stmt: &mut Stmt, rewriter: &mut impl Rewrite) -> bool {
+    let changed = match &mut stmt.kind {
+        StmtKind::Let { value, .. } | StmtKind::Assign { value, .. } => rewrite_expr(value, rewriter),
+        StmtKind::If { condition, then_branch, else_branch } => {
+            let mut changed = rewrite_expr(condition, rewriter);
+            changed |= rewrite_block(then_branch, rewriter);
+            if let Some(else_branch) = else_branch {
+                changed |= rewrite_stmt(else_branch, rewriter);
+            }
+            changed
+        }
+        StmtKind::While { condition: expr, body } | StmtKind::For { iterable: expr, body, .. } => {
+            rewrite_expr(expr, rewriter) | rewrite_block(body, rewriter)
+        }
+        StmtKind::Return(value) => value.as_mut().is_some_and(|value| rewrite_expr(value, rewriter)),
+        StmtKind::Block(block) => rewrite_block(block, rewriter),
+        StmtKind::Expr(expr) => rewrite_expr(expr, rewriter),
+    };
+    rewriter.stmt(stmt) || changed
+}
+
+fn rewrite_expr(expr: &mut Expr, rewriter: &mut impl Rewrite) -> bool {
+    let changed = match &mut expr.kind {
+        ExprKind::Literal(_) | ExprKind::Identifier(_) => false,
+        ExprKind::Unary { operand, .. } => rewrite_expr(operand, rewriter),
+        ExprKind::Binary { left, right, .. } => rewrite_expr(left, rewriter) | rewrite_expr(right, rewriter),
+        ExprKind::Call { callee, arguments } => {
+            let mut changed = rewrite_expr(callee, rewriter);
+            for argument in arguments {
+                changed |= rewrite_expr(argument, rewriter);
+            }
+            changed
+        }
+        ExprKind::Interpolation(parts) => {
+            let mut changed = false;
+            for part in parts {
+                if let StringPart::Expr(part) = part {
+                    changed |= rewrite_expr(part, rewriter);
+                }
+            }
+            changed
+        }
+    };
+    rewriter.expr(expr) || changed
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use crate::almora::{
+        ast::{Literal, Program},
+        bytecode::{emit, Vm},
+        compile,
+    };
+
+    use super::*;
+
+    /// Compiles the source, runs the pass on it, and writes the program back on one line, with the expressions as
+    /// s-expressions.
+    pub fn optimized(pass: &dyn Pass, source: &str) -> String {
+        let mut program = compile(source).unwrap();
+        pass.run(&mut program);
+        let items: Vec<String> = program
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Import(import) => format!("import {};", import.module.name),
+                Item::Function(function) => format!("fn {} {}", function.name.name, block(&function.body)),
+                Item::Statement(stmt) => self::stmt(stmt),
+            })
+            .collect();
+        items.join(" ")
+    }
+
+    fn block(block: &Block) -> String {
+        if block.stmts.is_empty() {
+            return "{}".to_string();
+        }
+        let stmts: Vec<String> = block.stmts.iter().map(stmt).collect();
+        format!("{{ {} }}", stmts.join(" "))
+    }
+
+    fn stmt(stmt: &Stmt) -> String {
+        match &stmt.kind {
+            StmtKind::Let { name, value, .. } => format!("let {} = {};", name.name, expr(value)),
+            StmtKind::Assign { target, value } => format!("{} = {};", target.name, expr(value)),
+            StmtKind::If { condition, then_branch, else_branch: Some(else_branch) } => {
+                format!("if {} {} else {}", expr(condition), block(then_branch), self::stmt(else_branch))
+            }
+            StmtKind::If { condition, then_branch, else_branch: None } => {
+                format!("if {} {}", expr(condition), block(then_branch))
+            }
+            StmtKind::While { condition, body } => format!("while {} {}", expr(condition), block(body)),
+            StmtKind::For { variable, iterable, body } => {
+                format!("for {} in {} {}", variable.name, expr(iterable), block(body))
+            }
+            StmtKind::Return(Some(value)) => format!("return {};", expr(value)),
+            StmtKind::Return(None) => "return;".to_string(),
+            StmtKind::Block(body) => block(body),
+            StmtKind::Expr(value) => format!("{};", expr(value)),
+        }
+    }
+
+    fn expr(expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Literal(Literal::String(value)) => format!("{:?}", value),
+            ExprKind::Literal(Literal::Integer(value)) => value.to_string(),
+            ExprKind::Literal(Literal::Float(value)) => format!("{:?}", value),
+            ExprKind::Literal(Literal::Boolean(value)) => value.to_string(),
+            ExprKind::Identifier(name) => name.clone(),
+            ExprKind::Unary { op, operand } => format!("({} {})", op.symbol(), self::expr(operand)),
+            ExprKind::Binary { op, left, right } => {
+                format!("({} {} {})", op.symbol(), self::expr(left), self::expr(right))
+            }
+            ExprKind::Call { callee, arguments } => {
+                let arguments: Vec<String> = arguments.iter().map(self::expr).collect();
+                format!("(call {} [{}])", self::expr(callee), arguments.join(" "))
+            }
+            ExprKind::Interpolation(parts) => {
+                let parts: Vec<String> = parts
+                    .iter()
+                    .map(|part| match part {
+                        StringPart::Text(text) => format!("{:?}", text),
+                        StringPart::Expr(value) => self::expr(value),
+                    })
+                    .collect();
+                format!("(format {})", parts.join(" "))
+            }
+        }
+    }
+
+    /// Runs the program, and returns the value of the global `result`, or the runtime error.
+    fn result(program: &Program) -> String {
+        let module = emit(program).unwrap();
+        let mut vm = Vm::new(&module);
+        match vm.run() {
+            Ok(_) => vm.global("result").unwrap().to_string(),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_pass_manager() {
+        let manager = PassManager::default();
+        assert_eq!(
+            manager.passes().collect::<Vec<_>>(),
+            ["algebraic simplification", "constant folding", "dead branch elimination"]
+        );
+
+        // Folding the condition lets the branch be removed
+        let source = "let x = 1;\nif 2 * 3 > 5 && !false { x = x * 1 + 0; } else { x = 2; }";
+        assert_eq!(optimized(&manager, source), "let x = 1; { x = (+ (* x 1) 0); }");
+        let mut program = compile("let x = 1;\nx = x + 1;").unwrap();
+        assert!(!manager.run(&mut program));
+
+        // The passes stop after the maximum number of rounds
+        let once = PassManager::new().with_pass(DeadBranchElimination).with_pass(ConstantFolding).with_max_rounds(1);
+        assert_eq!(optimized(&once, "if !true { f(); }\nfn f() {}"), "if false { (call f []); } fn f {}");
+        assert_eq!(optimized(&PassManager::new().with_pass(once), "if !true { f(); }\nfn f() {}"), "fn f {}");
+    }
+
+    #[test]
+    fn test_optimized_programs_run_the_same() {
+        let sources = [
+            "let result = 1 + 2 * 3 - 10 / 4 % 3;",
+            "let result = 1 + 0.5 * 2;",
+            "let result = \"a\" + \"b\" == \"ab\" && 2.0 == 2 && 1 != true;",
+            "let result = 1 / 0;",
+            "let result = 9223372036854775807 + 1;",
+            "let result = -(1 < 2.5) ;",
+            "let result = 0;\nif 1 >= 2 || \"b\" < \"a\" { result = 1; } else if !false { result = 2; }",
+            "let result = 0;\nwhile 1 > 2 { result = 1; }\nfor i in 3 + 1 { result = result + i * 1; }",
+            "fn f(x: int) -> int { return --x - 0; }\nlet result = f(3) * 1;",
+            // Ill-typed programs fail the same way
+            "let result = !!1.5;",
+            "let result = !!9223372036854775807;",
+            "let result = 0;\nif --false { result = 1; }",
+            "let result = \"a\" * 1 + 0;",
+            "let x = \"a\";\nlet result = 0 + x - 0;",
+        ];
+        for source in sources {
+            let program = compile(source).unwrap();
+            let mut optimized = program.clone();
+            optimize(&mut optimized);
+            assert_eq!(result(&optimized), result(&program), "{}", source);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::almora::ast::{BinaryOp, Expr, ExprKind, Literal, Program, UnaryOp};
+
+use super::{rewrite_program, Pass, Rewrite};
+
+/// Removes the operations that give back their operand, like `x + 0`, `x * 1` or `--x`, and turns `!(a == b)` into
+/// `a != b`.
+///
+/// There is no type checker, so an operation is only removed when the type of its operand is known: `x + 0` fails at
+/// runtime if `x` is a string, and `!!x` if it is a float, so they are kept. The operands are never removed, since they
+/// can have effects: `f() * 0` is kept.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AlgebraicSimplification;
+
+impl Pass for AlgebraicSimplification {
+    fn name(&self) -> &'static str {
+        "algebraic simplification"
+    }
+
+    fn run(&self, program: &mut Program) -> bool {
+        rewrite_program(program, &mut AlgebraicSimplification)
+    }
+}
+
+impl Rewrite for AlgebraicSimplification {
+    fn expr(&mut self, expr: &mut Expr) -> bool {
+        let simplified = match &mut expr.kind {
+            ExprKind::Unary { op, operand } => match &mut operand.kind {
+                ExprKind::Unary { op: inner, operand } if inner == op && has_type(*op, operand) => {
+                    Some(std::mem::replace(&mut operand.kind, placeholder()))
+                }
+                ExprKind::Binary { op: inner @ (BinaryOp::Eq | BinaryOp::Ne), .. } if *op == UnaryOp::Not => {
+                    *inner = if *inner == BinaryOp::Eq { BinaryOp::Ne } else { BinaryOp::Eq };
+                    Some(std::mem::replace(&mut operand.kind, placeholder()))
+                }
+                _ => None,
+            },
+            ExprKind::Binary { op, left, right } => {
+                let identity = match op {
+                    BinaryOp::Add | BinaryOp::Sub => Some(0),
+                    BinaryOp::Mul | BinaryOp::Div => Some(1),
+                    _ => None,
+                };
+                let is_identity = |expr: &Expr| identity.is_some_and(|value| expr.kind == integer(value));
+                let is_integer = |expr: &Expr| matches!(expr.kind, ExprKind::Literal(Literal::Integer(_)));
+                // Subtraction and division are not commutative
+                if is_identity(right) && is_integer(left) {
+                    Some(std::mem::replace(&mut left.kind, placeholder()))
+                } else if matches!(op, BinaryOp::Add | BinaryOp::Mul) && is_identity(left) && is_integer(right) {
+                    Some(std::mem::replace(&mut right.kind, placeholder()))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        match simplified {
+            Some(kind) => {
+                expr.kind = kind;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Returns whether the operand is known to be of the type of the operator, or to fail before the operator is applied.
+///
+/// `!` gives a boolean, or fails, so `!!!x` is `!x` whatever `x` is.
+fn has_type(op: UnaryOp, operand: &Expr) -> bool {
+    match op {
+        UnaryOp::Not => matches!(
+            operand.kind,
+            ExprKind::Literal(Literal::Boolean(_))
+                | ExprKind::Unary { op: UnaryOp::Not, .. }
+                | ExprKind::Binary {
+                    op: BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge,
+                    ..
+                }
+        ),
+        UnaryOp::Neg => matches!(
+            operand.kind,
+            ExprKind::Literal(Literal::Integer(_) | Literal::Float(_)) | ExprKind::Unary { op: UnaryOp::Neg, .. }
+        ),
+    }
+}
+
+fn integer(value: i64) -> ExprKind {
+    ExprKind::Literal(Literal::Integer(value))
+}
+
+/// Kind left in a node moved out of the tree, which is dropped right after.
+fn placeholder() -> ExprKind {
+    integer(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::almora::optimizer::tests::optimized;
+
+    use super::*;
+
+    #[test]
+    fn test_algebraic_simplification() {
+        let simplify = |source: &str| optimized(&AlgebraicSimplification, &format!("let x = 1;\n{}", source));
+        assert_eq!(simplify("2 + 0 - 0;"), "let x = 1; 2;");
+        assert_eq!(simplify("1 * (0 + 2) / 1;"), "let x = 1; 2;");
+        assert_eq!(simplify("--2.5;"), "let x = 1; 2.5;");
+        assert_eq!(simplify("---x;"), "let x = 1; (- x);");
+        assert_eq!(simplify("!!!(x == 2);"), "let x = 1; (!= x 2);");
+        assert_eq!(simplify("!!(x < 2);"), "let x = 1; (< x 2);");
+        assert_eq!(simplify("!(x != 2);"), "let x = 1; (== x 2);");
+
+        // The type of a variable is not known: it could be a string, or a float
+        assert_eq!(simplify("x + 0;"), "let x = 1; (+ x 0);");
+        assert_eq!(simplify("1 * x;"), "let x = 1; (* 1 x);");
+        assert_eq!(simplify("--x;"), "let x = 1; (- (- x));");
+        assert_eq!(simplify("!!x;"), "let x = 1; (! (! x));");
+        assert_eq!(simplify("!!1.5;"), "let x = 1; (! (! 1.5));");
+
+        // Only the integer identities, on the right side for the non-commutative operators
+        assert_eq!(simplify("0 - 2;"), "let x = 1; (- 0 2);");
+        assert_eq!(simplify("1 / 2;"), "let x = 1; (/ 1 2);");
+        assert_eq!(simplify("2 + 0.0;"), "let x = 1; (+ 2 0.0);");
+        assert_eq!(simplify("2 * 0;"), "let x = 1; (* 2 0);");
+        assert_eq!(simplify("-!x;"), "let x = 1; (- (! x));");
+    }
+}
@@ -0,0 +1,479 @@
+use std::collections::HashMap;
+
+use crate::parser_lib::Span;
+
+use super::{
+    ast::{Block, Expr, ExprKind, Function, Ident, Item, Program, Stmt, StmtKind, StringPart},
+    diagnostic::{limit_errors, suggest},
+    main::grammar,
+    Builtins, Diagnostic, Namespace, ScopeId, SymbolId, SymbolKind, SymbolTable,
+};
+
+/// Names defined outside of the program to resolve: the modules it can import, and the builtins.
+#[derive(Debug, Clone)]
+pub struct ResolveOptions {
+    imports: HashMap<String, Namespace>,
+    builtins: Vec<String>,
+    /// Maximum number of errors to report. If None, there is no limit.
+    max_errors: Option<usize>,
+}
+
+impl ResolveOptions {
+    /// Options without modules to import, with the standard builtins.
+    pub fn new() -> Self {
+        Self { imports: HashMap::new(), builtins: Vec::new(), max_errors: None }.with_builtins(&Builtins::standard())
+    }
+
+    /// Makes the module importable, given its namespace.
+    pub fn with_import(mut self, module: &str, namespace: Namespace) -> Self {
+        self.imports.insert(module.to_string(), namespace);
+        self
+    }
+
+    /// Replaces the builtins visible in the program.
+    pub fn with_builtins(mut self, builtins: &Builtins) -> Self {
+        self.builtins = builtins.iter().map(|builtin| builtin.name().to_string()).collect();
+        self
+    }
+
+    /// Reports at most the given number of errors, in source order. The next one is replaced by a fatal diagnostic
+    /// telling that the resolver stopped there, and the diagnostics after it are dropped.
+    pub fn with_max_errors(mut self, max_errors: usize) -> Self {
+        self.max_errors = Some(max_errors);
+        self
+    }
+}
+
+impl Default for ResolveOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Finds the symbols that the names of the program refer to, and reports the names that are wrongly defined or used.
+///
+/// The functions are visible in the whole program, even before their definition, but the variables are only visible
+/// after it. Defining a name twice in a scope is an error, and hiding a name of an enclosing scope is a warning.
+///
+/// The standard builtins are visible, but the program can't import modules: see `resolve_with`.
+pub fn resolve(program: &Program) -> (SymbolTable, Vec<Diagnostic>) {
+    resolve_with(program, &ResolveOptions::new())
+}
+
+/// Resolves a program that imports modules or uses other builtins.
+///
+/// The names of an imported namespace are defined in the global scope of the program, at the span of the import, like
+/// the functions: they are visible in the whole program. Importing an unknown module is an error.
+///
+/// The builtins are visible everywhere, unless a name of the program hides them, which is a warning. The ones that the
+/// program uses are added to a scope of their own, with an empty span at the start of the program. Assigning a builtin
+/// is an error.
+///
+/// The syntax errors that the parser recovered from are reported with the other diagnostics.
+pub fn resolve_with(program: &Program, options: &ResolveOptions) -> (SymbolTable, Vec<Diagnostic>) {
+    let mut resolver = Resolver {
+        table: SymbolTable::default(),
+        diagnostics: program.errors.clone(),
+        unresolved: Vec::new(),
+        builtins: &options.builtins,
+        builtin_scope: None,
+        start: Span::new(*program.span.start(), *program.span.start()),
+    };
+    let global = resolver.table.add_scope(None, program.span.clone());
+
+    for item in &program.items {
+        if let Item::Import(import) = item {
+            let Some(namespace) = options.imports.get(&import.module.name) else {
+                let message = format!("unknown module `{}`", import.module.name);
+                resolver.diagnostics.push(Diagnostic::error(message, import.module.span.clone()));
+                continue;
+            };
+            for symbol in namespace.symbols() {
+                let name = Ident { name: symbol.name.clone(), span: import.module.span.clone() };
+                resolver.define(&name, SymbolKind::Import, global);
+            }
+        }
+    }
+    for item in &program.items {
+        if let Item::Function(function) = item {
+            resolver.define(&function.name, SymbolKind::Function, global);
+        }
+    }
+    for item in &program.items {
+        match item {
+            Item::Import(_) => {}
+            Item::Function(function) => resolver.function(function, global),
+            Item::Statement(stmt) => resolver.stmt(stmt, global),
+        }
+    }
+
+    resolver.report_unresolved();
+    if let Some(limit) = options.max_errors {
+        limit_errors(&mut resolver.diagnostics, limit);
+    }
+    (resolver.table, resolver.diagnostics)
+}
+
+struct Resolver<'o> {
+    table: SymbolTable,
+    diagnostics: Vec<Diagnostic>,
+    /// Names that were not visible where they were used, with that span and scope.
+    unresolved: Vec<(String, Span, ScopeId)>,
+    builtins: &'o [String],
+    /// Scope of the builtins used by the program, added with the first one.
+    builtin_scope: Option<ScopeId>,
+    /// Empty span at the start of the program, where the builtins are defined.
+    start: Span,
+}
+
+impl Resolver<'_> {
+    /// Adds a symbol to the scope, unless a symbol with the same name is already defined in it.
+    fn define(&mut self, name: &Ident, kind: SymbolKind, scope: ScopeId) {
+        if let Some(existing) = self.table.lookup_in(scope, &name.name) {
+            let previous = self.table.symbol(existing).span.clone();
+            self.diagnostics.push(
+                Diagnostic::error(format!("`{}` is already defined in this scope", name.name), name.span.clone())
+                    .with_note("previous definition here", Some(previous)),
+            );
+            return;
+        }
+
+        let enclosing = self.table.scope(scope).parent.and_then(|parent| self.table.lookup(parent, &name.name));
+        if let Some(shadowed) = enclosing {
+            let previous = self.table.symbol(shadowed).span.clone();
+            self.diagnostics.push(
+                Diagnostic::warning(format!("`{}` shadows a name of an enclosing scope", name.name), name.span.clone())
+                    .with_note("shadowed definition here", Some(previous)),
+            );
+        } else if self.builtins.contains(&name.name) {
+            let message = format!("`{}` shadows a builtin", name.name);
+            self.diagnostics.push(Diagnostic::warning(message, name.span.clone()));
+        }
+        self.table.add_symbol(&name.name, kind, name.span.clone(), scope);
+    }
+
+    /// Resolves a use of a name, or records it to report it at the end.
+    fn use_name(&mut self, name: &str, span: &Span, scope: ScopeId) {
+        match self.table.lookup(scope, name).or_else(|| self.builtin(name)) {
+            Some(symbol) => self.table.add_reference(span.clone(), symbol),
+            None => self.unresolved.push((name.to_string(), span.clone(), scope)),
+        }
+    }
+
+    /// Returns the symbol of the builtin with the given name, adding it the first time it is used.
+    fn builtin(&mut self, name: &str) -> Option<SymbolId> {
+        if !self.builtins.iter().any(|builtin| builtin == name) {
+            return None;
+        }
+        let scope = match self.builtin_scope {
+            Some(scope) => scope,
+            None => *self.builtin_scope.insert(self.table.add_scope(None, self.start.clone())),
+        };
+        let symbol = self.table.lookup_in(scope, name);
+        Some(symbol.unwrap_or_else(|| self.table.add_symbol(name, SymbolKind::Builtin, self.start.clone(), scope)))
+    }
+
+    /// The parameters and the body of the function are in the same scope.
+    fn function(&mut self, function: &Function, scope: ScopeId) {
+        let scope = self.table.add_scope(Some(scope), function.span.clone());
+        for param in &function.params {
+            self.define(&param.name, SymbolKind::Parameter, scope);
+        }
+        self.stmts(&function.body, scope);
+    }
+
+    fn block(&mut self, block: &Block, scope: ScopeId) {
+        let scope = self.table.add_scope(Some(scope), block.span.clone());
+        self.stmts(block, scope);
+    }
+
+    fn stmts(&mut self, block: &Block, scope: ScopeId) {
+        for stmt in &block.stmts {
+            self.stmt(stmt, scope);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, scope: ScopeId) {
+        match &stmt.kind {
+            // The value can't use the variable being defined
+            StmtKind::Let { name, value, .. } => {
+                self.expr(value, scope);
+                self.define(name, SymbolKind::Variable, scope);
+            }
+            StmtKind::Assign { target, value } => {
+                self.use_name(&target.name, &target.span, scope);
+                if self.table.lookup(scope, &target.name).is_none() && self.builtins.contains(&target.name) {
+                    let message = format!("cannot assign the builtin `{}`", target.name);
+                    self.diagnostics.push(Diagnostic::error(message, target.span.clone()));
+                }
+                self.expr(value, scope);
+            }
+            StmtKind::If { condition, then_branch, else_branch } => {
+                self.expr(condition, scope);
+                self.block(then_branch, scope);
+                if let Some(else_branch) = else_branch {
+                    self.stmt(else_branch, scope);
+                }
+            }
+            StmtKind::While { condition, body } => {
+                self.expr(condition, scope);
+                self.block(body, scope);
+            }
+            // The variable is only visible in the body
+            StmtKind::For { variable, iterable, body } => {
+                self.expr(iterable, scope);
+                let scope = self.table.add_scope(Some(scope), stmt.span.clone());
+                self.define(variable, SymbolKind::Variable, scope);
+                self.block(body, scope);
+            }
+            StmtKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value, scope);
+                }
+            }
+            StmtKind::Block(block) => self.block(block, scope),
+            StmtKind::Expr(expr) => self.expr(expr, scope),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr, scope: ScopeId) {
+        match &expr.kind {
+            ExprKind::Literal(_) => {}
+            ExprKind::Identifier(name) => self.use_name(name, &expr.span, scope),
+            ExprKind::Unary { operand, .. } => self.expr(operand, scope),
+            ExprKind::Binary { left, right, .. } => {
+                self.expr(left, scope);
+                self.expr(right, scope);
+            }
+            ExprKind::Call { callee, arguments } => {
+                self.expr(callee, scope);
+                for argument in arguments {
+                    self.expr(argument, scope);
+                }
+            }
+            ExprKind::Interpolation(parts) => {
+                for part in parts {
+                    if let StringPart::Expr(part) = part {
+                        self.expr(part, scope);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reports the names that were not visible where they were used.
+    ///
+    /// If a variable with that name is defined later in the scope of the use or an enclosing one, it is used before its
+    /// definition. Otherwise, the name is unknown, and the closest visible name, builtin or keyword is suggested.
+    fn report_unresolved(&mut self) {
+        for (name, span, scope) in std::mem::take(&mut self.unresolved) {
+            let later = self.table.ancestors(scope).find_map(|scope| {
+                let symbol = self.table.symbol(self.table.lookup_in(scope, &name)?);
+                (symbol.span.start().index() > span.start().index()).then(|| symbol.span.clone())
+            });
+
+            let diagnostic = match later {
+                Some(definition) => Diagnostic::error(format!("`{}` is used before its definition", name), span)
+                    .with_note("defined here", Some(definition)),
+                None => {
+                    let diagnostic = Diagnostic::error(format!("unknown name `{}`", name), span.clone());
+                    match self.suggest(&name, &span, scope) {
+                        Some(suggestion) => diagnostic.with_note(format!("did you mean `{}`?", suggestion), None),
+                        None => diagnostic,
+                    }
+                }
+            };
+            self.diagnostics.push(diagnostic);
+        }
+        self.diagnostics.sort_by_key(|diagnostic| diagnostic.span.start().index());
+    }
+
+    /// Returns the name closest to an unknown one used at the span: a symbol visible there, a builtin or a keyword.
+    fn suggest(&self, name: &str, span: &Span, scope: ScopeId) -> Option<String> {
+        let visible = self.table.ancestors(scope).flat_map(|scope| &self.table.scope(scope).symbols).filter_map(|id| {
+            let symbol = self.table.symbol(*id);
+            // The variables defined after the use are not visible there
+            let visible = symbol.kind != SymbolKind::Variable || symbol.span.start().index() < span.start().index();
+            visible.then_some(symbol.name.as_str())
+        });
+        let builtins = self.builtins.iter().map(String::as_str);
+        let keywords = grammar().reserved_words().iter().map(String::as_str);
+        suggest(name, visible.chain(builtins).chain(keywords)).map(str::to_string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::almora::{compile, Severity};
+
+    use super::*;
+
+    /// Resolves the source, and returns the diagnostics like `3:5: error: message`, without their notes.
+    fn diagnostics(source: &str) -> Vec<String> {
+        let (_, diagnostics) = resolve(&compile(source).unwrap());
+        diagnostics
+            .iter()
+            .map(|diagnostic| format!("{}: {}: {}", diagnostic.span.start(), diagnostic.severity, diagnostic.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_symbols() {
+        let source = "fn f(a: int) -> int { return g(a); }\nfn g(b: int) -> int { let c = b; return c; }\nlet x = f(1);\n";
+        let (table, diagnostics) = resolve(&compile(source).unwrap());
+        assert_eq!(diagnostics, []);
+
+        let symbols: Vec<(&str, SymbolKind)> = table.symbols().map(|(_, s)| (s.name.as_str(), s.kind)).collect();
+        assert_eq!(
+            symbols,
+            [
+                ("f", SymbolKind::Function),
+                ("g", SymbolKind::Function),
+                ("a", SymbolKind::Parameter),
+                ("b", SymbolKind::Parameter),
+                ("c", SymbolKind::Variable),
+                ("x", SymbolKind::Variable),
+            ]
+        );
+
+        // Functions can be used before their definition
+        let references: Vec<(usize, &str)> = table
+            .references()
+            .iter()
+            .map(|reference| (reference.span.start().index(), table.symbol(reference.symbol).name.as_str()))
+            .collect();
+        assert_eq!(references, [(29, "g"), (31, "a"), (67, "b"), (77, "c"), (90, "f")]);
+
+        let (c, _) = table.symbols().find(|(_, symbol)| symbol.name == "c").unwrap();
+        assert_eq!(table.references_to(c).count(), 1);
+        let scope = table.symbol(c).scope;
+        assert_eq!(table.ancestors(scope).count(), 2);
+        assert_eq!(table.lookup(scope, "f"), table.lookup_in(ScopeId::GLOBAL, "f"));
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        assert_eq!(diagnostics("let x = y;"), ["1:9: error: unknown name `y`"]);
+        assert_eq!(diagnostics("x = 1;"), ["1:1: error: unknown name `x`"]);
+        assert_eq!(diagnostics("f(x);\nlet x = 1;\nfn f(a: int) {}"), ["1:3: error: `x` is used before its definition"]);
+        assert_eq!(diagnostics("let x = x;"), ["1:9: error: unknown name `x`"]);
+        assert_eq!(diagnostics("{ let x = 1; }\nx;"), ["2:1: error: unknown name `x`"]);
+        assert_eq!(diagnostics("for i in l { }"), ["1:10: error: unknown name `l`"]);
+        assert_eq!(diagnostics("let s = \"${s}\";"), ["1:12: error: unknown name `s`"]);
+        // The syntax errors that the parser recovered from are reported too
+        assert_eq!(
+            diagnostics("let s = \"${t}\n;"),
+            ["1:9: error: unterminated string", "1:12: error: unknown name `t`"]
+        );
+
+        assert_eq!(
+            diagnostics("fn f() {}\nfn f(a: int, a: int) { let a = 1; }"),
+            [
+                "2:4: error: `f` is already defined in this scope",
+                "2:14: error: `a` is already defined in this scope",
+                "2:28: error: `a` is already defined in this scope",
+            ]
+        );
+
+        // The notes point to the other definition
+        let (_, diagnostics) = resolve(&compile("let x = 1;\nlet x = 2;").unwrap());
+        assert_eq!(diagnostics[0].to_string(), "2:5: error: `x` is already defined in this scope\n1:5: note: previous definition here");
+        let (_, diagnostics) = resolve(&compile("f();\nlet f = 1;").unwrap());
+        assert_eq!(diagnostics[0].notes[0].span.as_ref().map(|span| span.start().line()), Some(2));
+
+        // The errors past the limit are not reported
+        let options = ResolveOptions::new().with_max_errors(2);
+        let (_, diagnostics) = resolve_with(&compile("a;\nlet x = 1;\nlet x = 2;\nb;\nc;").unwrap(), &options);
+        let messages: Vec<String> = diagnostics.iter().map(|d| format!("{}: {}", d.span.start(), d.message)).collect();
+        assert_eq!(
+            messages,
+            ["1:1: unknown name `a`", "3:5: `x` is already defined in this scope", "4:1: too many errors, stopped after 2"]
+        );
+        assert_eq!(diagnostics[2].severity, Severity::Fatal);
+
+        // The closest visible name, builtin or keyword is suggested
+        let notes = |source| {
+            let (_, diagnostics) = resolve(&compile(source).unwrap());
+            diagnostics.iter().flat_map(|d| d.notes.iter().map(|note| note.message.clone())).collect::<Vec<_>>()
+        };
+        assert_eq!(notes("let total = 1;
+print(totl);"), ["did you mean `total`?"]);
+        assert_eq!(notes("prnt(1);"), ["did you mean `print`?"]);
+        assert_eq!(notes("let count = 1;\nprint(cuont);"), ["did you mean `count`?"]);
+        assert_eq!(notes("fn f() { retrun; }"), ["did you mean `return`?"]);
+        assert_eq!(notes("print(count);
+let counts = 1;"), Vec::<String>::new());
+        assert_eq!(notes("{ let value = 1; }
+valu;"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_resolve_shadowing() {
+        let source = "let x = 1;\nfn f(x: int) {\n    for x in x { let y = x; }\n}";
+        let (table, diagnostics) = resolve(&compile(source).unwrap());
+        let warnings: Vec<String> = diagnostics.iter().map(|d| format!("{}: {}", d.span.start(), d.message)).collect();
+        assert_eq!(
+            warnings,
+            ["2:6: `x` shadows a name of an enclosing scope", "3:9: `x` shadows a name of an enclosing scope"]
+        );
+        assert!(diagnostics.iter().all(|diagnostic| diagnostic.severity == Severity::Warning));
+
+        // The iterable is the parameter, the use in the body is the loop variable
+        let uses: Vec<SymbolKind> = table.references().iter().map(|r| table.symbol(r.symbol).kind).collect();
+        assert_eq!(uses, [SymbolKind::Parameter, SymbolKind::Variable]);
+    }
+
+    #[test]
+    fn test_resolve_imports() {
+        let (math, _) = resolve(&compile("fn square(x: int) -> int { return x * x; }\nlet pi = 3.14;").unwrap());
+        let options = ResolveOptions::new().with_import("math", Namespace::of(&math));
+
+        let program = compile("let area = pi * square(2);\nimport math;").unwrap();
+        let (table, diagnostics) = resolve_with(&program, &options);
+        assert_eq!(diagnostics, []);
+        let uses: Vec<(&str, usize)> = table
+            .references()
+            .iter()
+            .map(|reference| table.symbol(reference.symbol))
+            .map(|symbol| (symbol.name.as_str(), symbol.span.start().index()))
+            .collect();
+        assert_eq!(uses, [("pi", 34), ("square", 34)]);
+
+        let program = compile("import math;\nimport physics;\nfn square() {}").unwrap();
+        let diagnostics: Vec<String> = resolve_with(&program, &options)
+            .1
+            .iter()
+            .map(|diagnostic| format!("{}: {}", diagnostic.span.start(), diagnostic.message))
+            .collect();
+        assert_eq!(diagnostics, ["2:8: unknown module `physics`", "3:4: `square` is already defined in this scope"]);
+        assert!(resolve(&compile("import math;").unwrap()).1[0].is_error());
+    }
+
+    #[test]
+    fn test_resolve_builtins() {
+        let (table, errors) = resolve(&compile("print(len(\"ab\"));\nfn f() { print(); }").unwrap());
+        assert_eq!(errors, []);
+        let uses: Vec<(&str, SymbolKind)> =
+            table.references().iter().map(|r| table.symbol(r.symbol)).map(|s| (s.name.as_str(), s.kind)).collect();
+        let builtin = |name| (name, SymbolKind::Builtin);
+        assert_eq!(uses, [builtin("print"), builtin("len"), builtin("print")]);
+
+        // The builtins are only in their own scope
+        let (print, _) = table.symbols().find(|(_, symbol)| symbol.name == "print").unwrap();
+        assert_eq!(table.scope(table.symbol(print).scope).parent, None);
+        assert_eq!(table.lookup(ScopeId::GLOBAL, "print"), None);
+        assert_eq!(table.scope(ScopeId::GLOBAL).parent, None);
+
+        // The names of the program hide the builtins
+        assert_eq!(diagnostics("fn len(s: string) {}\nlen(1);"), ["1:4: warning: `len` shadows a builtin"]);
+        assert_eq!(diagnostics("len = 2;"), ["1:1: error: cannot assign the builtin `len`"]);
+
+        // Host applications choose the builtins
+        let mut builtins = Builtins::new();
+        builtins.register("answer", Some(0), |_, _| Ok(crate::almora::bytecode::Value::Integer(42)));
+        let options = ResolveOptions::new().with_builtins(&builtins);
+        let (_, errors) = resolve_with(&compile("answer();\nprint();").unwrap(), &options);
+        let messages: Vec<&str> = errors.iter().map(|diagnostic| diagnostic.message.as_str()).collect();
+        assert_eq!(messages, ["unknown name `print`"]);
+    }
+}
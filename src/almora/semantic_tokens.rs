@@ -0,0 +1,266 @@
+//! Semantic tokens of almora sources: the highlighting of the grammar, refined with the symbols of the resolver.
+
+use std::{collections::BTreeMap, ops::Range};
+
+use crate::parser_lib::{HighlightCategory, Location, LocationPolicy, Span};
+
+use super::{
+    ast::{Block, Ident, Item, Program, Stmt, StmtKind},
+    compile,
+    main::grammar,
+    resolve, CompileError, ScopeId, Symbol, SymbolKind,
+};
+
+/// Category of a semantic token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenCategory {
+    Keyword,
+    String,
+    Number,
+    Comment,
+    Operator,
+    Function,
+    Parameter,
+    /// Variable defined by `let` or by a `for` loop, or name imported from another module
+    Variable,
+    Type,
+    /// Module of an import
+    Namespace,
+}
+
+impl TokenCategory {
+    /// Every category, in the order of their index in the legend of the language server.
+    pub const ALL: [TokenCategory; 10] = [
+        TokenCategory::Keyword,
+        TokenCategory::String,
+        TokenCategory::Number,
+        TokenCategory::Comment,
+        TokenCategory::Operator,
+        TokenCategory::Function,
+        TokenCategory::Parameter,
+        TokenCategory::Variable,
+        TokenCategory::Type,
+        TokenCategory::Namespace,
+    ];
+
+    /// Name of the category in the language server protocol.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenCategory::Keyword => "keyword",
+            TokenCategory::String => "string",
+            TokenCategory::Number => "number",
+            TokenCategory::Comment => "comment",
+            TokenCategory::Operator => "operator",
+            TokenCategory::Function => "function",
+            TokenCategory::Parameter => "parameter",
+            TokenCategory::Variable => "variable",
+            TokenCategory::Type => "type",
+            TokenCategory::Namespace => "namespace",
+        }
+    }
+}
+
+/// What else is known about the name of a token.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Modifiers {
+    /// The token is the definition of the name
+    pub declaration: bool,
+    /// The name is defined at the top level of the program, or imported
+    pub global: bool,
+    /// The name is a builtin of the host application
+    pub builtin: bool,
+    /// The name is defined in another module
+    pub imported: bool,
+}
+
+impl Modifiers {
+    /// Names of the modifiers in the language server protocol, in the order of their bits.
+    pub const NAMES: [&'static str; 4] = ["declaration", "global", "defaultLibrary", "imported"];
+
+    /// Returns the set of modifiers as bits, where the bit `i` is the modifier `NAMES[i]`.
+    pub fn bits(&self) -> u32 {
+        [self.declaration, self.global, self.builtin, self.imported]
+            .iter()
+            .enumerate()
+            .filter(|(_, set)| **set)
+            .map(|(i, _)| 1 << i)
+            .sum()
+    }
+}
+
+/// Returns the tokens of the source to highlight, in source order.
+///
+/// The keywords, literals, comments and operators come from the highlighting of the grammar. The names are classified
+/// with the symbols they resolve to, so that the parameters, the local and global variables and the functions can be
+/// told apart. The names that don't resolve have no token. A token spans several lines if it is a block comment, or
+/// comments that follow each other.
+pub fn semantic_tokens(source: &str) -> Result<Vec<(Span, TokenCategory, Modifiers)>, CompileError> {
+    let program = compile(source)?;
+    let (symbols, _) = resolve(&program);
+
+    // Names, by byte offset
+    let mut names = BTreeMap::new();
+    let mut add_name = |span: &Span, category, modifiers| {
+        names.insert(span.start().byte_offset(), (span.clone(), category, modifiers));
+    };
+    for (_, symbol) in symbols.symbols() {
+        // The imports and the builtins are not defined in the source
+        if !matches!(symbol.kind, SymbolKind::Import | SymbolKind::Builtin) {
+            let (category, modifiers) = classify(symbol);
+            add_name(&symbol.span, category, Modifiers { declaration: true, ..modifiers });
+        }
+    }
+    for reference in symbols.references() {
+        let (category, modifiers) = classify(symbols.symbol(reference.symbol));
+        add_name(&reference.span, category, modifiers);
+    }
+    for item in &program.items {
+        if let Item::Import(import) = item {
+            add_name(&import.module.span, TokenCategory::Namespace, Modifiers::default());
+        }
+    }
+    for ty in types(&program) {
+        add_name(&ty.span, TokenCategory::Type, Modifiers::default());
+    }
+
+    let mut locator = Locator { source, location: Location::beginning() };
+    let mut tokens = Vec::new();
+    for (range, category) in grammar().highlight(source)?.spans() {
+        let category = match category {
+            HighlightCategory::Keyword => TokenCategory::Keyword,
+            HighlightCategory::String => TokenCategory::String,
+            HighlightCategory::Number => TokenCategory::Number,
+            HighlightCategory::Comment => TokenCategory::Comment,
+            HighlightCategory::Operator => TokenCategory::Operator,
+            HighlightCategory::Identifier | HighlightCategory::Plain => continue,
+        };
+        tokens.push((locator.span(range.clone()), category, Modifiers::default()));
+    }
+    tokens.extend(names.into_values());
+    tokens.sort_by_key(|(span, _, _)| span.start().byte_offset());
+    Ok(tokens)
+}
+
+fn classify(symbol: &Symbol) -> (TokenCategory, Modifiers) {
+    let global = Modifiers { global: true, ..Modifiers::default() };
+    match symbol.kind {
+        SymbolKind::Function => (TokenCategory::Function, global),
+        SymbolKind::Parameter => (TokenCategory::Parameter, Modifiers::default()),
+        SymbolKind::Variable if symbol.scope == ScopeId::GLOBAL => (TokenCategory::Variable, global),
+        SymbolKind::Variable => (TokenCategory::Variable, Modifiers::default()),
+        SymbolKind::Import => (TokenCategory::Variable, Modifiers { imported: true, ..global }),
+        SymbolKind::Builtin => (TokenCategory::Function, Modifiers { builtin: true, ..global }),
+    }
+}
+
+/// Returns the names of the types written in the program: the types of the parameters, of the results and of the
+/// variables.
+fn types(program: &Program) -> Vec<&Ident> {
+    let mut types = Vec::new();
+    for item in &program.items {
+        match item {
+            Item::Import(_) => {}
+            Item::Function(function) => {
+                types.extend(function.params.iter().map(|param| &param.ty));
+                types.extend(&function.return_type);
+                block_types(&function.body, &mut types);
+            }
+            Item::Statement(stmt) => stmt_types(stmt, &mut types),
+        }
+    }
+    types
+}
+
+fn block_types<'p>(block: &'p Block, types: &mut Vec<&'p Ident>) {
+    for stmt in &block.stmts {
+        stmt_types(stmt, types);
+    }
+}
+
+fn stmt_types<'p>(stmt: &'p Stmt, types: &mut Vec<&'p Ident>) {
+    match &stmt.kind {
+        StmtKind::Let { ty, .. } => types.extend(ty),
+        StmtKind::If { then_branch, else_branch, .. } => {
+            block_types(then_branch, types);
+            if let Some(else_branch) = else_branch {
+                stmt_types(else_branch, types);
+            }
+        }
+        StmtKind::While { body, .. } | StmtKind::For { body, .. } | StmtKind::Block(body) => block_types(body, types),
+        StmtKind::Assign { .. } | StmtKind::Return(_) | StmtKind::Expr(_) => {}
+    }
+}
+
+/// Computes the locations of increasing byte offsets of a source.
+struct Locator<'s> {
+    source: &'s str,
+    location: Location,
+}
+
+impl Locator<'_> {
+    fn span(&mut self, range: Range<usize>) -> Span {
+        let start = self.at(range.start);
+        Span::new(start, self.at(range.end))
+    }
+
+    fn at(&mut self, offset: usize) -> Location {
+        for c in self.source[self.location.byte_offset()..offset].chars() {
+            self.location.increment_for(c, LocationPolicy::default());
+        }
+        self.location
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modifiers() {
+        assert_eq!(Modifiers::default().bits(), 0);
+        assert_eq!(Modifiers { declaration: true, builtin: true, ..Modifiers::default() }.bits(), 0b101);
+    }
+
+    #[test]
+    fn test_semantic_tokens() {
+        let source = "/// Scales.\nfn scale(x: int) -> int {\n    let k = 2;\n    return x * k;\n}\n\
+                      let total = scale(len(\"ab\"));\nprint(total, unknown);\n";
+        let tokens: Vec<(&str, TokenCategory, u32)> = semantic_tokens(source)
+            .unwrap()
+            .iter()
+            .map(|(span, category, modifiers)| {
+                (&source[span.start().byte_offset()..span.end().byte_offset()], *category, modifiers.bits())
+            })
+            .collect();
+        use TokenCategory::*;
+        assert_eq!(
+            tokens,
+            [
+                ("/// Scales.\n", Comment, 0),
+                ("fn", Keyword, 0),
+                ("scale", Function, 0b11),
+                ("x", Parameter, 0b1),
+                ("int", Type, 0),
+                ("int", Type, 0),
+                ("let", Keyword, 0),
+                ("k", Variable, 0b1),
+                ("2", Number, 0),
+                ("return", Keyword, 0),
+                ("x", Parameter, 0),
+                ("*", Operator, 0),
+                ("k", Variable, 0),
+                ("let", Keyword, 0),
+                ("total", Variable, 0b11),
+                ("scale", Function, 0b10),
+                ("len", Function, 0b110),
+                ("\"ab\"", String, 0),
+                ("print", Function, 0b110),
+                ("total", Variable, 0b10),
+            ]
+        );
+
+        let tokens = semantic_tokens("import geometry;\n").unwrap();
+        assert_eq!(tokens[1].1, TokenCategory::Namespace);
+        assert!(semantic_tokens("let = ;").is_err());
+    }
+}
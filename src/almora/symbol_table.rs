@@ -0,0 +1,156 @@
+use crate::parser_lib::Span;
+
+/// Index of a symbol in a `SymbolTable`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SymbolId(usize);
+
+/// Index of a scope in a `SymbolTable`. The global scope is the first one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ScopeId(usize);
+
+impl ScopeId {
+    /// Scope of the whole program.
+    pub const GLOBAL: ScopeId = ScopeId(0);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Parameter,
+    /// Defined by `let`, or by a `for` loop
+    Variable,
+    /// Defined at the top level of another module, and made visible by `import`
+    Import,
+    /// Function of the host application. The builtins used by a program are in a scope of their own, which is not
+    /// an ancestor of the global scope: `SymbolTable::lookup` doesn't find them.
+    Builtin,
+}
+
+/// Name defined in a program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Span of the name in its definition.
+    pub span: Span,
+    pub scope: ScopeId,
+}
+
+/// Part of a program where the names defined in it are visible: the program, a function or a block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scope {
+    /// Enclosing scope, or None for the global scope and the scope of the builtins.
+    pub parent: Option<ScopeId>,
+    pub span: Span,
+    /// Symbols defined in the scope, in definition order.
+    pub symbols: Vec<SymbolId>,
+}
+
+/// Use of a symbol in a program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub span: Span,
+    pub symbol: SymbolId,
+}
+
+/// Names defined at the top level of a module, visible in the modules importing it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Namespace {
+    symbols: Vec<Symbol>,
+}
+
+impl Namespace {
+    /// Returns the functions and the variables of the global scope of a resolved module. The names that the module
+    /// imports are not part of it. A table without scopes, like the one of a module that could not be parsed, has an
+    /// empty namespace.
+    pub fn of(table: &SymbolTable) -> Self {
+        let Some(global) = table.scopes.first() else {
+            return Self::default();
+        };
+        let symbols = global
+            .symbols
+            .iter()
+            .map(|id| table.symbol(*id))
+            .filter(|symbol| symbol.kind != SymbolKind::Import)
+            .cloned()
+            .collect();
+        Self { symbols }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    /// Returns the symbols, in definition order.
+    pub fn symbols(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter()
+    }
+}
+
+/// Symbols of a program with their scopes, and the symbols that the names used in it refer to.
+///
+/// It is built by `resolve`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+    scopes: Vec<Scope>,
+    references: Vec<Reference>,
+}
+
+impl SymbolTable {
+    /// Adds a scope, in the given one if any.
+    pub(crate) fn add_scope(&mut self, parent: Option<ScopeId>, span: Span) -> ScopeId {
+        self.scopes.push(Scope { parent, span, symbols: Vec::new() });
+        ScopeId(self.scopes.len() - 1)
+    }
+
+    pub(crate) fn add_symbol(&mut self, name: &str, kind: SymbolKind, span: Span, scope: ScopeId) -> SymbolId {
+        let id = SymbolId(self.symbols.len());
+        self.symbols.push(Symbol { name: name.to_string(), kind, span, scope });
+        self.scopes[scope.0].symbols.push(id);
+        id
+    }
+
+    pub(crate) fn add_reference(&mut self, span: Span, symbol: SymbolId) {
+        self.references.push(Reference { span, symbol });
+    }
+
+    /// Returns the symbol with the given name defined directly in the scope, if any.
+    pub fn lookup_in(&self, scope: ScopeId, name: &str) -> Option<SymbolId> {
+        self.scopes[scope.0].symbols.iter().copied().find(|id| self.symbols[id.0].name == name)
+    }
+
+    /// Returns the symbol with the given name visible in the scope: defined in it, or else in the closest enclosing scope.
+    pub fn lookup(&self, scope: ScopeId, name: &str) -> Option<SymbolId> {
+        self.ancestors(scope).find_map(|scope| self.lookup_in(scope, name))
+    }
+
+    /// Returns the scope and the scopes enclosing it, from the innermost to the global scope.
+    pub fn ancestors(&self, scope: ScopeId) -> impl Iterator<Item = ScopeId> + '_ {
+        std::iter::successors(Some(scope), |scope| self.scopes[scope.0].parent)
+    }
+
+    /// Panics if the symbol is not in the table, like the other accessors.
+    pub fn symbol(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id.0]
+    }
+
+    pub fn scope(&self, id: ScopeId) -> &Scope {
+        &self.scopes[id.0]
+    }
+
+    /// Returns the symbols, in definition order.
+    pub fn symbols(&self) -> impl Iterator<Item = (SymbolId, &Symbol)> {
+        self.symbols.iter().enumerate().map(|(index, symbol)| (SymbolId(index), symbol))
+    }
+
+    /// Returns the uses of the symbols, in source order.
+    pub fn references(&self) -> &[Reference] {
+        &self.references
+    }
+
+    /// Returns the uses of the given symbol, in source order.
+    pub fn references_to(&self, symbol: SymbolId) -> impl Iterator<Item = &Reference> {
+        self.references.iter().filter(move |reference| reference.symbol == symbol)
+    }
+}
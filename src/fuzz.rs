@@ -0,0 +1,119 @@
+//! Entry points for fuzzers like cargo-fuzz (libFuzzer) or AFL, which call them with arbitrary bytes:
+//!
+//! ```ignore
+//! fuzz_target!(|data: &[u8]| almora::fuzz::fuzz_parse_bytes(data));
+//! ```
+//!
+//! They return normally whatever the bytes are, the invalid inputs are simply rejected by the readers or the grammar.
+//! They only panic when they find a bug: a reader or a matcher panicking, or readers disagreeing on the same input.
+
+use std::{io::Cursor, sync::OnceLock};
+
+use crate::{
+    almora::almora,
+    parser_lib::{
+        BytesCharReader, Grammar, InvalidUtf8Policy, Location, MatchToken, ParseResult, ReadCharReader, Stream, StringCharReader,
+    },
+};
+
+/// Bytes starting an UTF-8 input with a BOM, which the byte readers skip.
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Decodes the bytes with every byte reader, and checks that they read the same chars.
+///
+/// The first byte sets the size of the buffer of the streaming reader, so that the fuzzer explores the refills.
+pub fn fuzz_read_bytes(data: &[u8]) {
+    let buffer_size = data.first().map_or(1, |first| usize::from(first % 16) + 1);
+
+    for policy in [InvalidUtf8Policy::Replace, InvalidUtf8Policy::Error] {
+        let mut bytes_reader = BytesCharReader::new(data);
+        bytes_reader.set_invalid_utf8_policy(policy);
+        let mut read_reader = ReadCharReader::new(data, buffer_size);
+        read_reader.set_invalid_utf8_policy(policy);
+
+        let expected = read_all(&mut bytes_reader);
+        assert_eq!(read_all(&mut read_reader), expected, "{:?} with {:?}", data, policy);
+    }
+
+    // Out of range positions are not found, instead of overflowing
+    let mut reader = ReadCharReader::new(data, buffer_size);
+    assert_eq!(reader.load_chars(usize::MAX).ok(), Some(0));
+    assert_eq!(reader.peek_nth(usize::MAX), None);
+}
+
+/// Parses the bytes with the Almora grammar, and checks that the readers give the same result.
+pub fn fuzz_parse_bytes(data: &[u8]) {
+    static BYTES_GRAMMAR: OnceLock<Grammar<BytesCharReader<Vec<u8>>>> = OnceLock::new();
+    static READ_GRAMMAR: OnceLock<Grammar<ReadCharReader<Cursor<Vec<u8>>>>> = OnceLock::new();
+    static STRING_GRAMMAR: OnceLock<Grammar<StringCharReader>> = OnceLock::new();
+
+    let loc = Location::beginning();
+    let mut reader = BytesCharReader::new(data.to_vec());
+    let expected = BYTES_GRAMMAR.get_or_init(almora::define_grammar).test(&loc, &mut reader);
+
+    // The streaming reader holds the whole input, so that it can look as far as the other ones
+    let mut reader = ReadCharReader::new(Cursor::new(data.to_vec()), data.len() + 1);
+    let result = READ_GRAMMAR.get_or_init(almora::define_grammar).test(&loc, &mut reader);
+    assert_same(&result, &expected, data);
+
+    // The string reader doesn't skip the BOM, nor replace invalid chars
+    if let Ok(source) = std::str::from_utf8(data) {
+        if !data.starts_with(UTF8_BOM) {
+            let mut reader = StringCharReader::new(source);
+            let result = STRING_GRAMMAR.get_or_init(almora::define_grammar).test(&loc, &mut reader);
+            assert_same(&result, &expected, data);
+        }
+    }
+}
+
+/// Consumes every char of the reader.
+fn read_all(reader: &mut impl Stream<char>) -> Vec<char> {
+    let mut chars = Vec::new();
+    while let Some(c) = reader.consume() {
+        chars.push(c);
+    }
+    chars
+}
+
+/// Checks that two readers gave the same result. The errors can differ, since the readers don't fail the same way.
+fn assert_same(result: &ParseResult, expected: &ParseResult, data: &[u8]) {
+    if let (Ok(result), Ok(expected)) = (result, expected) {
+        assert_eq!(result, expected, "{:?}", data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUTS: &[&[u8]] = &[
+        b"",
+        b"/* hey */a",
+        b"// comment",
+        b"/* unterminated",
+        b"\r\n\t ",
+        b"\xef\xbb\xbf/**/",
+        b"\xff\xfe/\x00*\x00",
+        b"\xfe\xff\x00/",
+        b"\xff\xfe",
+        b"/*\xc3*/",
+        b"\xe2\x82",
+        b"\xf4\x90\x80\x80",
+        b"\xed\xa0\x80",
+        b"\x0f\xf0\x9f\x98",
+    ];
+
+    #[test]
+    fn test_fuzz_read_bytes() {
+        for input in INPUTS {
+            fuzz_read_bytes(input);
+        }
+    }
+
+    #[test]
+    fn test_fuzz_parse_bytes() {
+        for input in INPUTS {
+            fuzz_parse_bytes(input);
+        }
+    }
+}
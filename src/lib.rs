@@ -1 +1,8 @@
 
+pub mod almora;
+pub mod fuzz;
+pub mod parser_lib;
+pub mod utils;
+
+#[cfg(any(test, feature = "test-grammars"))]
+pub mod test_grammars;
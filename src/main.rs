@@ -1,5 +1,25 @@
-mod almora;
-mod parser_lib;
-mod utils;
+use std::{
+    env,
+    io::{self, BufReader},
+    process::ExitCode,
+};
 
-fn main() {}
+use almora::almora::lsp;
+
+fn main() -> ExitCode {
+    match env::args().nth(1).as_deref() {
+        Some("lsp") => match lsp::run(&mut BufReader::new(io::stdin()), &mut io::stdout()) {
+            Ok(true) => ExitCode::SUCCESS,
+            // The client exited without asking the server to shut down
+            Ok(false) => ExitCode::FAILURE,
+            Err(err) => {
+                eprintln!("almora lsp: {}", err);
+                ExitCode::FAILURE
+            }
+        },
+        _ => {
+            eprintln!("usage: almora lsp");
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,3 @@
+mod slice_byte_reader;
+
+pub use slice_byte_reader::SliceByteReader;
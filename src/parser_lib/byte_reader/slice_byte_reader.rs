@@ -0,0 +1,106 @@
+use std::fmt::Debug;
+
+use crate::parser_lib::{Checkpoint, LexError, MatchBytes, ParserError, Rewind, Stream};
+
+/// Byte reader that streams the bytes of a slice, to parse binary formats.
+///
+/// Since the whole input is in memory, doesn't use a buffer, and any byte can be accessed in constant time.
+#[derive(Debug)]
+pub struct SliceByteReader<B: AsRef<[u8]>> {
+    bytes: B,
+    /// The current position in the bytes.
+    cursor_index: usize,
+}
+
+impl<B: AsRef<[u8]>> SliceByteReader<B> {
+    /// Creates a reader over the given bytes (a slice, a `Vec<u8>`, the content of a file...).
+    pub fn new(bytes: B) -> Self {
+        Self { bytes, cursor_index: 0 }
+    }
+
+    /// Returns the wrapped bytes.
+    pub fn into_inner(self) -> B {
+        self.bytes
+    }
+}
+
+impl<B: AsRef<[u8]>> Stream<u8> for SliceByteReader<B> {
+    fn peek(&mut self) -> Option<u8> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&mut self, n: usize) -> Option<u8> {
+        self.bytes.as_ref().get(self.cursor_index + n).copied()
+    }
+
+    fn consume(&mut self) -> Option<u8> {
+        self.consume_nth(0)
+    }
+
+    fn consume_nth(&mut self, n: usize) -> Option<u8> {
+        let b = self.peek_nth(n)?;
+        self.cursor_index += n + 1;
+        Some(b)
+    }
+
+    fn is_eof(&mut self) -> bool {
+        self.cursor_index >= self.bytes.as_ref().len()
+    }
+
+    fn index(&self) -> usize {
+        self.cursor_index
+    }
+}
+
+impl<B: AsRef<[u8]>> Rewind for SliceByteReader<B> {
+    fn checkpoint(&mut self) -> Checkpoint {
+        Checkpoint::new(self.cursor_index, self.cursor_index)
+    }
+
+    fn rewind(&mut self, checkpoint: Checkpoint) -> Result<(), ParserError> {
+        // All the bytes are kept, so it is always possible
+        self.cursor_index = checkpoint.index();
+        Ok(())
+    }
+}
+
+impl<B: AsRef<[u8]> + Debug> MatchBytes for SliceByteReader<B> {
+    fn match_bytes(&mut self, pos: usize, bytes: &[u8]) -> Result<bool, ParserError> {
+        if pos < self.cursor_index {
+            return Err(LexError::NoLookBehind(pos).into());
+        }
+
+        // Compare the whole slice at once
+        Ok(self.bytes.as_ref().get(pos..pos + bytes.len()) == Some(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::Endianness;
+
+    use super::*;
+
+    #[test]
+    fn test_slice_byte_reader() {
+        let mut reader = SliceByteReader::new(vec![0x89, b'P', b'N', b'G', 0x00, 0x00, 0x01, 0x02]);
+
+        assert_eq!(reader.match_bytes(0, b"\x89PNG"), Ok(true));
+        assert_eq!(reader.match_bytes(1, b"PNG\0"), Ok(true));
+        assert_eq!(reader.match_bytes(6, b"\x01\x02\x03"), Ok(false));
+        assert_eq!(reader.match_byte_range(1, b'A', b'Z', 0), Ok(3));
+        assert_eq!(reader.match_byte_range(1, b'A', b'Z', 2), Ok(2));
+
+        // Integer fields
+        assert_eq!(reader.read_uint(4, 4, Endianness::Big), Ok(Some(0x0102)));
+        assert_eq!(reader.read_uint(6, 2, Endianness::Little), Ok(Some(0x0201)));
+        assert_eq!(reader.read_uint(6, 4, Endianness::Little), Ok(None));
+        assert_eq!(reader.is_end_of_input(8), Ok(true));
+
+        // Consumed bytes can't be read anymore
+        reader.consume_nth(3);
+        assert_eq!(reader.byte_at(0), Err(LexError::NoLookBehind(0).into()));
+        assert_eq!(reader.match_bytes(0, b"\x89"), Err(LexError::NoLookBehind(0).into()));
+        assert_eq!(reader.peek(), Some(0x00));
+    }
+}
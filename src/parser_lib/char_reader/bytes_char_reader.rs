@@ -0,0 +1,279 @@
+use std::{collections::VecDeque, fmt::Debug};
+
+use crate::parser_lib::{Checkpoint, IoError, LocationPolicy, MatchStr, ParserError, Rewind, Stream};
+
+use super::{
+    utils::{Decoded, InvalidUtf8Policy},
+    Encoding,
+};
+
+/// Char reader that streams characters from bytes already in memory
+/// (embedded sources, network payloads...), without building a `String` first.
+///
+/// The bytes are decoded lazily: only the chars that are peeked are decoded.
+/// By default, the bytes are UTF-8, unless a BOM tells otherwise, and invalid sequences are replaced by U+FFFD.
+#[derive(Debug)]
+pub struct BytesCharReader<B: AsRef<[u8]>> {
+    bytes: B,
+    /// Offset of the first byte that was not decoded yet.
+    byte_offset: usize,
+    /// Chars that were decoded but not consumed yet, with their width in bytes.
+    decoded: VecDeque<(char, usize)>,
+    /// The current position in the input, counted in chars.
+    cursor_index: usize,
+    /// How locations are computed.
+    policy: LocationPolicy,
+    /// Encoding of the bytes. None until it is detected from the first bytes.
+    encoding: Option<Encoding>,
+    /// What to do with invalid UTF-8.
+    utf8_policy: InvalidUtf8Policy,
+    /// If true, `\r\n` is read as a single `\n`.
+    normalize_newlines: bool,
+    /// Invalid UTF-8 error, if the policy asked for it.
+    /// Once it happened, no more chars are decoded and it is returned by the matching functions.
+    error: Option<ParserError>,
+}
+
+impl<B: AsRef<[u8]>> BytesCharReader<B> {
+    /// Creates a new reader over the given bytes, which can be borrowed (`&'static [u8]`) or owned (`Vec<u8>`).
+    pub fn new(bytes: B) -> Self {
+        Self {
+            bytes,
+            byte_offset: 0,
+            decoded: VecDeque::new(),
+            cursor_index: 0,
+            policy: LocationPolicy::default(),
+            encoding: None,
+            utf8_policy: InvalidUtf8Policy::default(),
+            normalize_newlines: false,
+            error: None,
+        }
+    }
+
+    /// Sets the encoding of the bytes, instead of detecting it from their BOM.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = Some(encoding);
+    }
+
+    /// Sets what to do when the input is not valid UTF-8.
+    pub fn set_invalid_utf8_policy(&mut self, policy: InvalidUtf8Policy) {
+        self.utf8_policy = policy;
+    }
+
+    /// If true, `\r\n` is read as a single `\n`, so that grammars matching `\n` work on Windows-authored inputs.
+    /// Locations then refer to the normalized input.
+    pub fn set_normalize_newlines(&mut self, normalize: bool) {
+        self.normalize_newlines = normalize;
+    }
+
+    /// Decodes the next char of the input into the buffer.
+    /// Returns false if the end of the input is reached.
+    fn decode_next(&mut self) -> bool {
+        // The encoding is decided with the first bytes, and the BOM is skipped
+        if self.byte_offset == 0 {
+            // All the bytes are there, so there is always enough of them to decide
+            if let Some((encoding, bom_len)) = Encoding::resolve(self.encoding, self.bytes.as_ref(), true) {
+                self.encoding = Some(encoding);
+                self.byte_offset = bom_len;
+            }
+        }
+
+        let remaining = &self.bytes.as_ref()[self.byte_offset..];
+        if remaining.is_empty() || self.error.is_some() {
+            return false;
+        }
+
+        // All the bytes are there, so a cut char is invalid
+        let encoding = self.encoding.unwrap_or(Encoding::Utf8);
+        let (c, width) = match encoding.decode(remaining, true) {
+            Decoded::Char('\r', width) if self.normalize_newlines => {
+                // With normalized new lines, "\r\n" is decoded as a single '\n'
+                match encoding.decode(&remaining[width..], true) {
+                    Decoded::Char('\n', lf_width) => ('\n', width + lf_width),
+                    _ => ('\r', width),
+                }
+            }
+            Decoded::Char(c, width) => (c, width),
+            Decoded::Invalid(width) => match self.utf8_policy {
+                // Skip the invalid bytes, the next ones may start a valid char
+                InvalidUtf8Policy::Replace => (char::REPLACEMENT_CHARACTER, width),
+                InvalidUtf8Policy::Error => {
+                    self.error = Some(ParserError::Io(IoError::InvalidUtf8 {
+                        byte_offset: self.byte_offset,
+                    }));
+                    return false;
+                }
+            },
+            Decoded::Incomplete => unreachable!("all the bytes are decoded at once"),
+        };
+
+        self.decoded.push_back((c, width));
+        self.byte_offset += width;
+        true
+    }
+
+    /// Decodes chars until the nth next char is in the buffer.
+    /// Returns false if the end of the input is reached before.
+    fn decode_until(&mut self, n: usize) -> bool {
+        while self.decoded.len() <= n {
+            if !self.decode_next() {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl<B: AsRef<[u8]>> Stream<char> for BytesCharReader<B> {
+    fn peek(&mut self) -> Option<char> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&mut self, n: usize) -> Option<char> {
+        self.decode_until(n);
+        self.decoded.get(n).map(|(c, _)| *c)
+    }
+
+    fn consume(&mut self) -> Option<char> {
+        self.consume_nth(0)
+    }
+
+    fn consume_nth(&mut self, n: usize) -> Option<char> {
+        if !self.decode_until(n) {
+            return None;
+        }
+
+        // Discard the chars before the nth
+        self.decoded.drain(..n);
+        self.cursor_index += n + 1;
+        self.decoded.pop_front().map(|(c, _)| c)
+    }
+
+    fn is_eof(&mut self) -> bool {
+        self.peek().is_none()
+    }
+
+    fn index(&self) -> usize {
+        self.cursor_index
+    }
+}
+
+impl<B: AsRef<[u8]>> Rewind for BytesCharReader<B> {
+    fn checkpoint(&mut self) -> Checkpoint {
+        // The cursor is before the decoded chars
+        let decoded_bytes: usize = self.decoded.iter().map(|(_, width)| width).sum();
+        Checkpoint::new(self.cursor_index, self.byte_offset - decoded_bytes)
+    }
+
+    fn rewind(&mut self, checkpoint: Checkpoint) -> Result<(), ParserError> {
+        // All the bytes are kept, so the chars can simply be decoded again from there
+        self.decoded.clear();
+        self.error = None;
+        self.cursor_index = checkpoint.index();
+        self.byte_offset = checkpoint.position();
+        Ok(())
+    }
+}
+
+impl<B: AsRef<[u8]> + Debug> MatchStr for BytesCharReader<B> {
+    fn check_error(&self) -> Result<(), ParserError> {
+        match &self.error {
+            Some(err) => Err(err.clone()),
+            None => Ok(()),
+        }
+    }
+
+    fn location_policy(&self) -> LocationPolicy {
+        self.policy
+    }
+
+    fn set_location_policy(&mut self, policy: LocationPolicy) {
+        self.policy = policy;
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use crate::parser_lib::LexError;
+
+    use super::*;
+
+    #[test]
+    fn test_bytes_char_reader() {
+        let mut reader = BytesCharReader::new("😎 hello".as_bytes());
+
+        assert_eq!(reader.peek(), Some('😎'));
+        assert_eq!(reader.peek_nth(2), Some('h'));
+
+        // Only the peeked chars were decoded
+        assert_eq!(reader.byte_offset, 6);
+
+        assert_eq!(reader.match_str(2, "hello"), Ok(true));
+        assert_eq!(reader.consume_nth(1), Some(' '));
+        assert_eq!(reader.match_str(0, "😎"), Err(LexError::NoLookBehind(0).into()));
+        assert_eq!(reader.match_range(2, 'a', 'z', 0).unwrap().len(), 5);
+        assert_eq!(reader.is_end_of_input(6), Ok(false));
+        assert_eq!(reader.is_end_of_input(7), Ok(true));
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        // 0xff can never appear in UTF-8, and 0xe2 starts an unfinished 3 bytes char
+        let mut reader = BytesCharReader::new(vec![b'a', 0xff, b'b', 0xe2, 0x82]);
+
+        assert_eq!(reader.consume(), Some('a'));
+        assert_eq!(reader.consume(), Some(char::REPLACEMENT_CHARACTER));
+        assert_eq!(reader.consume(), Some('b'));
+        // The unfinished char is replaced as a whole
+        assert_eq!(reader.consume(), Some(char::REPLACEMENT_CHARACTER));
+        assert_eq!(reader.is_eof(), true);
+    }
+
+    #[test]
+    fn test_invalid_utf8_error() {
+        let mut reader = BytesCharReader::new(vec![b'a', b'b', 0xe2, 0x82]);
+        reader.set_invalid_utf8_policy(InvalidUtf8Policy::Error);
+
+        // The valid start can be matched, then the error is reported with its position
+        assert_eq!(reader.match_str(0, "ab"), Ok(true));
+        assert_eq!(reader.match_range(0, 'a', 'z', 0), Err(IoError::InvalidUtf8 { byte_offset: 2 }.into()));
+        assert_eq!(reader.is_end_of_input(2), Err(IoError::InvalidUtf8 { byte_offset: 2 }.into()));
+    }
+
+    #[test]
+    fn test_encodings() {
+        // UTF-16 is detected from the BOM
+        let mut reader = BytesCharReader::new(b"\xfe\xff\0h\xd8\x3d\xde\x0e");
+        assert_eq!(reader.match_str(0, "h😎"), Ok(true));
+        assert_eq!(reader.is_end_of_input(2), Ok(true));
+
+        // Latin-1 must be set
+        let mut reader = BytesCharReader::new(b"caf\xe9");
+        reader.set_encoding(Encoding::Latin1);
+        assert_eq!(reader.match_str(0, "café"), Ok(true));
+    }
+
+    #[test]
+    fn test_normalize_newlines() {
+        let mut reader = BytesCharReader::new(b"a\r\nb\r");
+        reader.set_normalize_newlines(true);
+        assert_eq!(reader.match_str(0, "a\nb\r"), Ok(true));
+        assert_eq!(reader.is_end_of_input(4), Ok(true));
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut reader = BytesCharReader::new("\u{feff}é😎!".as_bytes());
+        let start = reader.checkpoint();
+        assert_eq!(reader.consume(), Some('é'));
+        let middle = reader.checkpoint();
+        assert_eq!(reader.consume_nth(1), Some('!'));
+
+        // Everything can be decoded again
+        assert_eq!(reader.rewind(middle), Ok(()));
+        assert_eq!(reader.match_str(1, "😎!"), Ok(true));
+        assert_eq!(reader.rewind(start), Ok(()));
+        assert_eq!(reader.consume(), Some('é'));
+    }
+}
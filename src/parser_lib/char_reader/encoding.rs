@@ -0,0 +1,140 @@
+use super::utils::{decode_utf8, Decoded};
+
+/// Encoding of the bytes given to a char reader.
+///
+/// By default, readers detect it from the byte order mark (BOM) at the start of the input,
+/// and fall back to UTF-8 when there is none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// ISO-8859-1: each byte is the code point of the char.
+    Latin1,
+    /// Like Latin-1, except for the 0x80-0x9f bytes that are used for printable chars.
+    Windows1252,
+}
+
+/// Chars of the 0x80-0x9f bytes in Windows-1252.
+/// The unassigned bytes are kept as their C1 control chars, like browsers do.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+impl Encoding {
+    /// Detects the encoding from the byte order mark at the start of the given bytes.
+    /// Returns the encoding and the length of the BOM, or None if there is no BOM.
+    pub fn from_bom(bytes: &[u8]) -> Option<(Encoding, usize)> {
+        match bytes {
+            [0xef, 0xbb, 0xbf, ..] => Some((Encoding::Utf8, 3)),
+            [0xff, 0xfe, ..] => Some((Encoding::Utf16Le, 2)),
+            [0xfe, 0xff, ..] => Some((Encoding::Utf16Be, 2)),
+            _ => None,
+        }
+    }
+
+    /// Decides which encoding to use for an input starting with the given bytes, and how many BOM bytes to skip.
+    /// The `forced` encoding is used if there is one, but its BOM is still skipped.
+    ///
+    /// Returns None if more bytes are needed to decide.
+    pub fn resolve(forced: Option<Encoding>, bytes: &[u8], at_end: bool) -> Option<(Encoding, usize)> {
+        // The longest BOM takes 3 bytes
+        if bytes.len() < 3 && !at_end {
+            return None;
+        }
+
+        match (Encoding::from_bom(bytes), forced) {
+            (Some((detected, len)), None) => Some((detected, len)),
+            (Some((detected, len)), Some(forced)) if detected == forced => Some((forced, len)),
+            (_, forced) => Some((forced.unwrap_or(Encoding::Utf8), 0)),
+        }
+    }
+
+    /// Decodes the first char of the given bytes.
+    /// If `at_end` is true, no more bytes will follow, so a cut char is invalid.
+    pub fn decode(self, bytes: &[u8], at_end: bool) -> Decoded {
+        match self {
+            Encoding::Utf8 => decode_utf8(bytes, at_end),
+            Encoding::Utf16Le => decode_utf16(bytes, at_end, u16::from_le_bytes),
+            Encoding::Utf16Be => decode_utf16(bytes, at_end, u16::from_be_bytes),
+            Encoding::Latin1 | Encoding::Windows1252 => match bytes.first() {
+                Some(0x80..=0x9f) if self == Encoding::Windows1252 => {
+                    Decoded::Char(WINDOWS_1252_HIGH[(bytes[0] - 0x80) as usize], 1)
+                }
+                // Each byte is exactly one char, so there is no invalid input
+                Some(b) => Decoded::Char(*b as char, 1),
+                None if at_end => Decoded::Invalid(0),
+                None => Decoded::Incomplete,
+            },
+        }
+    }
+}
+
+/// Decodes the first char of UTF-16 bytes, with the given byte order.
+fn decode_utf16(bytes: &[u8], at_end: bool, to_unit: fn([u8; 2]) -> u16) -> Decoded {
+    // Chars take one unit, or two for surrogate pairs
+    let unit_at = |i: usize| bytes.get(i..i + 2).map(|b| to_unit([b[0], b[1]]));
+
+    let first = match unit_at(0) {
+        Some(unit) => unit,
+        None if at_end => return Decoded::Invalid(bytes.len()),
+        None => return Decoded::Incomplete,
+    };
+
+    let units = match first {
+        0xd800..=0xdbff => match unit_at(2) {
+            Some(second) => vec![first, second],
+            None if at_end => return Decoded::Invalid(2),
+            None => return Decoded::Incomplete,
+        },
+        _ => vec![first],
+    };
+
+    match char::decode_utf16(units.iter().copied()).next() {
+        Some(Ok(c)) => Decoded::Char(c, c.len_utf16() * 2),
+        // Skip the first unit, the next one may start a valid char
+        _ => Decoded::Invalid(2),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_bom() {
+        assert_eq!(Encoding::from_bom(b"\xef\xbb\xbfhi"), Some((Encoding::Utf8, 3)));
+        assert_eq!(Encoding::from_bom(b"\xff\xfeh\0"), Some((Encoding::Utf16Le, 2)));
+        assert_eq!(Encoding::from_bom(b"\xfe\xff\0h"), Some((Encoding::Utf16Be, 2)));
+        assert_eq!(Encoding::from_bom(b"hi"), None);
+
+        // A forced encoding is kept, but its own BOM is skipped
+        assert_eq!(Encoding::resolve(Some(Encoding::Latin1), b"\xff\xfeh", false), Some((Encoding::Latin1, 0)));
+        assert_eq!(Encoding::resolve(Some(Encoding::Utf8), b"\xef\xbb\xbf", false), Some((Encoding::Utf8, 3)));
+
+        // Short inputs wait for more bytes, unless there are none
+        assert_eq!(Encoding::resolve(None, b"\xff\xfe", false), None);
+        assert_eq!(Encoding::resolve(None, b"h", true), Some((Encoding::Utf8, 0)));
+    }
+
+    #[test]
+    fn test_decode() {
+        // Latin-1 and Windows-1252 only differ on 0x80-0x9f
+        assert_eq!(Encoding::Latin1.decode(&[0xe9], false), Decoded::Char('é', 1));
+        assert_eq!(Encoding::Windows1252.decode(&[0xe9], false), Decoded::Char('é', 1));
+        assert_eq!(Encoding::Latin1.decode(&[0x80], false), Decoded::Char('\u{80}', 1));
+        assert_eq!(Encoding::Windows1252.decode(&[0x80], false), Decoded::Char('€', 1));
+
+        // UTF-16, with a surrogate pair
+        assert_eq!(Encoding::Utf16Le.decode(&[0xe9, 0x00], false), Decoded::Char('é', 2));
+        assert_eq!(Encoding::Utf16Be.decode(&[0x00, 0xe9], false), Decoded::Char('é', 2));
+        assert_eq!(Encoding::Utf16Be.decode(&[0xd8, 0x3d, 0xde, 0x0e], false), Decoded::Char('😎', 4));
+        assert_eq!(Encoding::Utf16Be.decode(&[0xd8, 0x3d], false), Decoded::Incomplete);
+
+        // Lone surrogates and cut units are invalid
+        assert_eq!(Encoding::Utf16Be.decode(&[0xde, 0x0e, 0x00, 0x61], false), Decoded::Invalid(2));
+        assert_eq!(Encoding::Utf16Be.decode(&[0xd8, 0x3d, 0x00, 0x61], false), Decoded::Invalid(2));
+        assert_eq!(Encoding::Utf16Le.decode(&[0x61], true), Decoded::Invalid(1));
+    }
+}
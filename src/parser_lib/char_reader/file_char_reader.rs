@@ -1,11 +1,12 @@
-use std::{error::Error, fs::File, io::Read};
-
-use crate::{
-    parser_lib::{MatchStr, ParserError, Stream},
-    utils::RingBuffer,
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, Read},
 };
 
-use super::utils::TryIntoChar;
+use crate::parser_lib::{Checkpoint, Encoding, InvalidUtf8Policy, LocationDelta, LocationPolicy, MatchStr, ParserError, Rewind, Stream};
+
+use super::{Prefetcher, ReadCharReader};
 
 /// Char reader that streams characters from a file.
 /// Doesn't load the whole file into memory.
@@ -13,14 +14,23 @@ use super::utils::TryIntoChar;
 /// Maintains a buffer for peaked characters.
 #[derive(Debug)]
 pub struct FileCharReader {
-    /// The file to read from.
-    file: File,
-    /// The buffer of characters.
-    buffer: RingBuffer<char>,
-    /// Number of UTF-8 characters read from the buffer (head).
-    nb_read_from_buffer: usize,
-    /// Number of UTF-8 characters read from the file (tail).
-    nb_read_from_file: usize,
+    reader: ReadCharReader<FileInput>,
+}
+
+/// The file, read directly or in the background.
+#[derive(Debug)]
+enum FileInput {
+    Direct(File),
+    Prefetched(Prefetcher),
+}
+
+impl Read for FileInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            FileInput::Direct(file) => file.read(buf),
+            FileInput::Prefetched(prefetcher) => prefetcher.read(buf),
+        }
+    }
 }
 
 impl FileCharReader {
@@ -28,172 +38,89 @@ impl FileCharReader {
     #[allow(unused)]
     pub fn new(filepath: &str, buffer_size: usize) -> Result<Self, Box<dyn Error>> {
         Ok(FileCharReader {
-            file: File::open(filepath)?,
-            buffer: RingBuffer::new(buffer_size),
-            nb_read_from_file: 0,
-            nb_read_from_buffer: 0,
+            reader: ReadCharReader::new(FileInput::Direct(File::open(filepath)?), buffer_size),
         })
     }
 
-    /// Try to load the next n utf8 chars into the buffer.
-    /// Returns the number of actually loaded chars.
-    /// 0 means either EOF, or not enough space in the buffer.
-    pub fn load_chars(&mut self, n: usize) -> usize {
-        // Check if there is enough space in the buffer, we don't want to override chars that weren't consumed
-        if self.buffer.size() + n > self.buffer.capacity() {
-            return 0;
-        }
+    /// Creates a new file char reader that reads the file in a background thread, by chunks of the given size.
+    /// Useful for big files, so that parsing isn't stalled by the reads.
+    pub fn with_prefetch(filepath: &str, buffer_size: usize, chunk_size: usize) -> Result<Self, Box<dyn Error>> {
+        let input = Prefetcher::new(File::open(filepath)?, chunk_size);
+        Ok(FileCharReader {
+            reader: ReadCharReader::new(FileInput::Prefetched(input), buffer_size),
+        })
+    }
 
-        // We want to load the next n bytes
-        // An utf8 char takes up to 4 bytes
-
-        // We can safely read n bytes at once, they count how many true utf8 chars there are
-        // Then repeat with the number of remaining chars to read
-        // This way, we can potentially avoid having to read each char individually
-
-        // Buffer for the char we are reading
-        let mut char_i = 0;
-        let mut char_buf = [0u8; 4];
-
-        // Buffer for read bytes
-        let mut buf: Vec<u8> = Vec::with_capacity(n);
-
-        // Stats
-        let mut bytes_read = 1;
-        let mut chars_to_read = n;
-
-        while chars_to_read > 0 && bytes_read > 0 {
-            // Create buffer
-            buf.resize(chars_to_read, 0);
-
-            // Try to read the next bytes
-            bytes_read = self.file.read(&mut buf).unwrap();
-
-            // Try to find utf8 chars in the buffer
-            for i in 0..bytes_read {
-                char_buf[char_i] = buf[i];
-
-                // Check that it is a valid char
-                match char_buf.try_into_char() {
-                    Ok(c) => {
-                        self.buffer.push(c).expect("Buffer overflow");
-                        // We can start the next char
-                        char_i = 0;
-                        char_buf = [0u8; 4];
-                        chars_to_read -= 1;
-                        // Increment cursor
-                        self.nb_read_from_file += 1;
-                    }
-                    // If it's not a valid char, we try by taking one more byte
-                    Err(_) => {
-                        char_i += 1;
-                    }
-                }
-            }
-        }
+    /// Sets the encoding of the file, instead of detecting it from its BOM.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.reader.set_encoding(encoding);
+    }
 
-        // Return the number of chars read
-        n - chars_to_read
+    /// If true, `\r\n` is read as a single `\n`. Locations then refer to the normalized file.
+    pub fn set_normalize_newlines(&mut self, normalize: bool) {
+        self.reader.set_normalize_newlines(normalize);
     }
 
-    /// Load chars in the buffer until the i is <= tail
-    fn load_until(&mut self, index: usize) -> bool {
-        if index >= self.nb_read_from_file {
-            self.load_chars(index - self.nb_read_from_file + 1);
+    /// Keeps the last `window` consumed chars in the buffer, so that they can still be matched,
+    /// or shown in error messages. The buffer space they take can't be used to look ahead anymore.
+    ///
+    /// Panics if the window doesn't leave any space in the buffer.
+    pub fn set_look_behind(&mut self, window: usize) {
+        self.reader.set_look_behind(window);
+    }
 
-            if index >= self.nb_read_from_file {
-                return false;
-            }
-        }
+    /// Returns the nth consumed char, starting from the last one (n = 1).
+    /// Returns None if it is not kept anymore.
+    pub fn peek_behind(&self, n: usize) -> Option<char> {
+        self.reader.peek_behind(n)
+    }
 
-        true
+    /// Sets what to do when the file is not valid UTF-8.
+    pub fn set_invalid_utf8_policy(&mut self, policy: InvalidUtf8Policy) {
+        self.reader.set_invalid_utf8_policy(policy);
     }
 }
 
+// Everything is done by the generic reader
 impl Stream<char> for FileCharReader {
     fn peek(&mut self) -> Option<char> {
-        // Ensure that the next char is loaded
-        self.load_until(self.nb_read_from_buffer);
-
-        self.buffer.peek()
+        self.reader.peek()
     }
 
     fn peek_nth(&mut self, n: usize) -> Option<char> {
-        // Ensure that the nth char is loaded
-        self.load_until(self.nb_read_from_buffer + n);
-
-        self.buffer.peek_nth(n)
+        self.reader.peek_nth(n)
     }
 
     fn consume(&mut self) -> Option<char> {
-        // Ensure that the next char is loaded
-        self.load_until(self.nb_read_from_buffer);
-
-        let res = self.buffer.pop();
-
-        if let Some(_) = res {
-            self.nb_read_from_buffer += 1;
-        }
-
-        res
+        self.reader.consume()
     }
 
     fn consume_nth(&mut self, n: usize) -> Option<char> {
-        // Ensure that the nth char is loaded
-        self.load_until(self.nb_read_from_buffer + n);
+        self.reader.consume_nth(n)
+    }
 
-        // Discard the chars before the nth
-        for _ in 0..n {
-            self.buffer.pop();
-        }
+    fn is_eof(&mut self) -> bool {
+        self.reader.is_eof()
+    }
 
-        let res = self.buffer.pop();
-        if res.is_some() {
-            self.nb_read_from_buffer += n + 1;
-        }
+    fn index(&self) -> usize {
+        self.reader.index()
+    }
+}
 
-        res
+impl Rewind for FileCharReader {
+    fn checkpoint(&mut self) -> Checkpoint {
+        self.reader.checkpoint()
     }
 
-    fn is_eof(&mut self) -> bool {
-        // EOF = enable to load next char
-        self.load_until(self.nb_read_from_buffer) == false
+    fn rewind(&mut self, checkpoint: Checkpoint) -> Result<(), ParserError> {
+        self.reader.rewind(checkpoint)
     }
 }
 
 impl MatchStr for FileCharReader {
     fn match_str(&mut self, pos: usize, s: &str) -> Result<bool, ParserError> {
-        // Get the pos starting from the current position of the cursor
-
-        // This is a stream: we can look ahead, but we can't look behind chars that were already consumed
-        if pos < self.nb_read_from_buffer {
-            return Err(ParserError::NoLookBehind(pos));
-        }
-
-        // This is the amount by which we will need to look ahead for the start of the stream
-        let relative_pos = pos - self.nb_read_from_buffer;
-
-        // If the string is to far away or to big to fit in the buffer, we won't be able to look it ahead
-        if relative_pos + s.len() >= self.buffer.capacity() {
-            return Err(ParserError::LookAheadBufferOverflow(relative_pos + s.len()));
-        }
-
-        // Compare each char
-        let mut i = relative_pos;
-        for str_c in s.chars() {
-            if let Some(file_c) = self.peek_nth(i) {
-                if file_c != str_c {
-                    // If a difference is found, it's not equal
-                    return Ok(false);
-                }
-            } else {
-                // If EOF is reached before the end of the string to compare, it's not equal
-                return Ok(false);
-            }
-            i += 1;
-        }
-
-        Ok(true)
+        self.reader.match_str(pos, s)
     }
 
     fn match_range(
@@ -201,104 +128,42 @@ impl MatchStr for FileCharReader {
         pos: usize,
         start: char,
         end: char,
-        max: u8,
-    ) -> Result<u32, ParserError> {
-        // This is a stream: we can look ahead, but we can't look behind chars that were already consumed
-        if pos < self.nb_read_from_buffer {
-            return Err(ParserError::NoLookBehind(pos));
-        }
-
-        // This is the amount by which we will need to look ahead for the start of the stream
-        let relative_pos = pos - self.nb_read_from_buffer;
-
-        let mut matched = 0;
-
-        let mut i = relative_pos;
-        while let Some(c) = self.peek_nth(i) {
-            // If a difference is found, or if we already have matched the max, we stop here
-            if c < start || c > end {
-                break;
-            }
-
-            // If there is a max and it is reached, we stop here
-            if max != 0 && matched >= max.into() {
-                break;
-            }
-
-            matched += 1;
-            i += 1;
-        }
-
-        Ok(matched)
+        max: usize,
+    ) -> Result<LocationDelta, ParserError> {
+        self.reader.match_range(pos, start, end, max)
     }
 
     fn is_newline(&mut self, pos: usize) -> Result<bool, ParserError> {
-        // This is a stream: we can look ahead, but we can't look behind chars that were already consumed
-        if pos < self.nb_read_from_buffer {
-            return Err(ParserError::NoLookBehind(pos));
-        }
-
-        // This is the amount by which we will need to look ahead for the start of the stream
-        let relative_pos = pos - self.nb_read_from_buffer;
-
-        // If the string is to far away or to big to fit in the buffer, we won't be able to look it ahead
-        if relative_pos + 1 >= self.buffer.capacity() {
-            return Err(ParserError::LookAheadBufferOverflow(relative_pos + 1));
-        }
-
-        // Compare the char
-        match self.peek_nth(relative_pos) {
-            Some('\n') => Ok(true),
-            _ => Ok(false),
-        }
+        self.reader.is_newline(pos)
     }
 
     fn is_end_of_input(&mut self, pos: usize) -> Result<bool, ParserError> {
-        // This is a stream: we can look ahead, but we can't look behind chars that were already consumed
-        if pos < self.nb_read_from_buffer {
-            return Err(ParserError::NoLookBehind(pos));
-        }
+        self.reader.is_end_of_input(pos)
+    }
 
-        // This is the amount by which we will need to look ahead for the start of the stream
-        let relative_pos = pos - self.nb_read_from_buffer;
+    fn refills(&self) -> usize {
+        self.reader.refills()
+    }
 
-        // If the string is to far away or to big to fit in the buffer, we won't be able to look it ahead
-        if relative_pos + 1 >= self.buffer.capacity() {
-            return Err(ParserError::LookAheadBufferOverflow(relative_pos + 1));
-        }
+    fn reserve_look_ahead(&mut self, n: usize) {
+        self.reader.reserve_look_ahead(n);
+    }
 
-        // Compare the char
-        match self.peek_nth(relative_pos) {
-            None => Ok(true),
-            _ => Ok(false),
-        }
+    fn location_policy(&self) -> LocationPolicy {
+        self.reader.location_policy()
+    }
+
+    fn set_location_policy(&mut self, policy: LocationPolicy) {
+        self.reader.set_location_policy(policy);
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
-    use super::*;
-
-    #[test]
-    fn test_load_chars() {
-        let mut reader = FileCharReader::new("resources/test_files/test.txt", 10).unwrap();
+    use crate::parser_lib::LexError;
 
-        let res = reader.load_chars(10);
-        assert_eq!(res, 10);
-
-        // Check that the buffer was filled accordingly
-        assert_eq!(reader.buffer.pop(), Some('😎'));
-        assert_eq!(reader.buffer.pop(), Some(' '));
-        assert_eq!(reader.buffer.pop(), Some('h'));
-        assert_eq!(reader.buffer.pop(), Some('e'));
-        assert_eq!(reader.buffer.pop(), Some('l'));
-        assert_eq!(reader.buffer.pop(), Some('l'));
-        assert_eq!(reader.buffer.pop(), Some('o'));
-        assert_eq!(reader.buffer.pop(), Some(' '));
-        assert_eq!(reader.buffer.pop(), Some('t'));
-        assert_eq!(reader.buffer.pop(), Some('h'));
-        assert_eq!(reader.buffer.pop(), None);
-    }
+    use super::*;
 
     #[test]
     fn test_char_reader() {
@@ -405,7 +270,7 @@ mod tests {
         assert!(reader.match_str(39, "important").is_err());
         assert_eq!(
             reader.match_str(39, "important").unwrap_err(),
-            ParserError::LookAheadBufferOverflow(48)
+            ParserError::Lex(LexError::LookAheadBufferOverflow(48))
         );
 
         // We can still compare words at the beginning, since the cursor hasn't moved
@@ -419,7 +284,7 @@ mod tests {
         assert!(reader.match_str(2, "hello").is_err());
         assert_eq!(
             reader.match_str(2, "hello").unwrap_err(),
-            ParserError::NoLookBehind(2)
+            ParserError::Lex(LexError::NoLookBehind(2))
         );
     }
 
@@ -429,33 +294,33 @@ mod tests {
 
         // Look ahead check should work
         assert!(reader.match_range(9, 'a', 'z', 1).is_ok());
-        assert_eq!(reader.match_range(9, 'a', 'z', 1).unwrap(), 1);
+        assert_eq!(reader.match_range(9, 'a', 'z', 1).unwrap().len(), 1);
 
         // But not capital
         assert!(reader.match_range(9, 'A', 'Z', 1).is_ok());
-        assert_eq!(reader.match_range(9, 'A', 'Z', 1).unwrap(), 0);
+        assert_eq!(reader.match_range(9, 'A', 'Z', 1).unwrap().len(), 0);
 
         // But not numbers
         assert!(reader.match_range(9, '0', '9', 1).is_ok());
-        assert_eq!(reader.match_range(9, '0', '9', 1).unwrap(), 0);
+        assert_eq!(reader.match_range(9, '0', '9', 1).unwrap().len(), 0);
 
         // Space is no alpha numeric
         assert!(reader.match_range(7, 'a', 'z', 1).is_ok());
-        assert_eq!(reader.match_range(7, 'a', 'z', 1).unwrap(), 0);
+        assert_eq!(reader.match_range(7, 'a', 'z', 1).unwrap().len(), 0);
 
         assert!(reader.match_range(7, 'A', 'Z', 1).is_ok());
-        assert_eq!(reader.match_range(7, 'A', 'Z', 1).unwrap(), 0);
+        assert_eq!(reader.match_range(7, 'A', 'Z', 1).unwrap().len(), 0);
 
         assert!(reader.match_range(7, '0', '9', 1).is_ok());
-        assert_eq!(reader.match_range(7, '0', '9', 1).unwrap(), 0);
+        assert_eq!(reader.match_range(7, '0', '9', 1).unwrap().len(), 0);
 
         // Should also work for longer matches
         // Here it can get words up to 10 chars, but it stops at the space so it only finds 4 chars
         assert!(reader.match_range(8, 'a', 'z', 10).is_ok());
-        assert_eq!(reader.match_range(8, 'a', 'z', 10).unwrap(), 4);
+        assert_eq!(reader.match_range(8, 'a', 'z', 10).unwrap().len(), 4);
 
         // 0 is infinite max
         assert!(reader.match_range(39, 'a', 'z', 0).is_ok());
-        assert_eq!(reader.match_range(39, 'a', 'z', 0).unwrap(), 9);
+        assert_eq!(reader.match_range(39, 'a', 'z', 0).unwrap().len(), 9);
     }
 }
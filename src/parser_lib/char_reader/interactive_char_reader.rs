@@ -0,0 +1,151 @@
+use std::fmt::{Debug, Formatter};
+
+use crate::parser_lib::{Checkpoint, LocationPolicy, MatchStr, ParserError, Rewind, Stream};
+
+/// Char reader for interactive inputs, like a REPL.
+///
+/// When the matchers need chars after the end of the current input, it asks for more with a callback
+/// (which can show another prompt), then resumes the matching. That way, multi-line constructs can be typed
+/// line by line. The input really ends when the callback returns None.
+///
+/// All the input is kept in memory.
+pub struct InteractiveCharReader {
+    chars: Vec<char>,
+    /// Asks for more input.
+    more: Box<dyn FnMut() -> Option<String>>,
+    /// True when the callback returned None.
+    finished: bool,
+    /// The current position in the input.
+    cursor_index: usize,
+    /// How locations are computed.
+    policy: LocationPolicy,
+}
+
+impl InteractiveCharReader {
+    /// Creates a reader that asks for its input with the given callback.
+    pub fn new<F: FnMut() -> Option<String> + 'static>(more: F) -> Self {
+        Self {
+            chars: Vec::new(),
+            more: Box::new(more),
+            finished: false,
+            cursor_index: 0,
+            policy: LocationPolicy::default(),
+        }
+    }
+
+    /// Adds some input without calling the callback.
+    pub fn push_str(&mut self, s: &str) {
+        self.chars.extend(s.chars());
+    }
+
+    /// Returns true if the callback said there was no more input.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Asks for more input until the char at the given index is available.
+    /// Returns false if the input ends before.
+    fn load_until(&mut self, index: usize) -> bool {
+        while index >= self.chars.len() {
+            if self.finished {
+                return false;
+            }
+
+            match (self.more)() {
+                Some(s) => self.chars.extend(s.chars()),
+                None => self.finished = true,
+            }
+        }
+        true
+    }
+}
+
+impl Debug for InteractiveCharReader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // The callback can't be shown
+        f.debug_struct("InteractiveCharReader")
+            .field("chars", &self.chars)
+            .field("finished", &self.finished)
+            .field("cursor_index", &self.cursor_index)
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+impl Stream<char> for InteractiveCharReader {
+    fn peek(&mut self) -> Option<char> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&mut self, n: usize) -> Option<char> {
+        self.load_until(self.cursor_index + n);
+        self.chars.get(self.cursor_index + n).copied()
+    }
+
+    fn consume(&mut self) -> Option<char> {
+        self.consume_nth(0)
+    }
+
+    fn consume_nth(&mut self, n: usize) -> Option<char> {
+        let c = self.peek_nth(n)?;
+        self.cursor_index += n + 1;
+        Some(c)
+    }
+
+    fn is_eof(&mut self) -> bool {
+        self.peek().is_none()
+    }
+
+    fn index(&self) -> usize {
+        self.cursor_index
+    }
+}
+
+impl Rewind for InteractiveCharReader {
+    fn checkpoint(&mut self) -> Checkpoint {
+        Checkpoint::new(self.cursor_index, 0)
+    }
+
+    fn rewind(&mut self, checkpoint: Checkpoint) -> Result<(), ParserError> {
+        // All the input is kept, so it is always possible
+        self.cursor_index = checkpoint.index();
+        Ok(())
+    }
+}
+
+impl MatchStr for InteractiveCharReader {
+    fn location_policy(&self) -> LocationPolicy {
+        self.policy
+    }
+
+    fn set_location_policy(&mut self, policy: LocationPolicy) {
+        self.policy = policy;
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use crate::parser_lib::{Location, MatchToken};
+    use crate::{seq, until, word};
+
+    use super::*;
+
+    #[test]
+    fn test_interactive_char_reader() {
+        // Lines typed by the user, one per prompt
+        let mut lines = vec!["}\n", "  x = 2;\n"];
+        let mut reader = InteractiveCharReader::new(move || lines.pop().map(String::from));
+        reader.push_str("fn f() {\n");
+
+        // The block is only finished on the third line
+        let block = seq!(word!("fn f() {\n"), until!(word!("}"), 0), word!("}"));
+        let res = block.test(&Location::beginning(), &mut reader).unwrap().unwrap();
+        assert_eq!(res.end(), &Location::new(3, 2, 19));
+
+        // Then, the user stops typing
+        assert_eq!(reader.is_finished(), false);
+        assert_eq!(reader.is_end_of_input(20), Ok(true));
+        assert_eq!(reader.is_finished(), true);
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    collections::VecDeque,
+    fmt::{Debug, Formatter},
+};
+
+use crate::parser_lib::{Checkpoint, LexError, LocationPolicy, MatchStr, ParserError, Rewind, Stream};
+
+/// Char reader that streams characters from any iterator of chars.
+///
+/// Useful to plug custom sources (generated input, decrypted streams...) without writing a new reader.
+/// The chars peeked by the matchers are buffered until they are consumed, then they are dropped:
+/// like the other streaming readers, it can't look behind the cursor.
+pub struct IterCharReader<I: Iterator<Item = char>> {
+    iter: I,
+    /// Chars taken from the iterator but not consumed yet.
+    buffer: VecDeque<char>,
+    /// Number of consumed chars.
+    nb_consumed: usize,
+    /// How locations are computed.
+    policy: LocationPolicy,
+}
+
+impl<I: Iterator<Item = char>> IterCharReader<I> {
+    /// Creates a reader reading the chars of the given iterator.
+    pub fn new<T: IntoIterator<IntoIter = I>>(iter: T) -> Self {
+        Self {
+            iter: iter.into_iter(),
+            buffer: VecDeque::new(),
+            nb_consumed: 0,
+            policy: LocationPolicy::default(),
+        }
+    }
+
+    /// Returns the wrapped iterator. The buffered chars are lost.
+    pub fn into_inner(self) -> I {
+        self.iter
+    }
+
+    /// Takes chars from the iterator until the buffer contains at least n chars.
+    /// Returns false if the iterator ends before.
+    fn load_until(&mut self, n: usize) -> bool {
+        while self.buffer.len() < n {
+            match self.iter.next() {
+                Some(c) => self.buffer.push_back(c),
+                None => return false,
+            }
+        }
+        true
+    }
+}
+
+impl<I: Iterator<Item = char>> Debug for IterCharReader<I> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // Most iterators (closures, adapters) can't be shown
+        f.debug_struct("IterCharReader")
+            .field("buffer", &self.buffer)
+            .field("nb_consumed", &self.nb_consumed)
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+impl<I: Iterator<Item = char>> Stream<char> for IterCharReader<I> {
+    fn peek(&mut self) -> Option<char> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&mut self, n: usize) -> Option<char> {
+        self.load_until(n + 1);
+        self.buffer.get(n).copied()
+    }
+
+    fn consume(&mut self) -> Option<char> {
+        self.consume_nth(0)
+    }
+
+    fn consume_nth(&mut self, n: usize) -> Option<char> {
+        let c = self.peek_nth(n)?;
+        self.buffer.drain(..=n);
+        self.nb_consumed += n + 1;
+        Some(c)
+    }
+
+    fn is_eof(&mut self) -> bool {
+        self.peek().is_none()
+    }
+
+    fn index(&self) -> usize {
+        self.nb_consumed
+    }
+}
+
+impl<I: Iterator<Item = char>> Rewind for IterCharReader<I> {
+    fn checkpoint(&mut self) -> Checkpoint {
+        Checkpoint::new(self.nb_consumed, 0)
+    }
+
+    fn rewind(&mut self, checkpoint: Checkpoint) -> Result<(), ParserError> {
+        // The consumed chars are dropped, so it is only possible to move forward
+        if checkpoint.index() < self.nb_consumed {
+            return Err(LexError::NoLookBehind(checkpoint.index()).into());
+        }
+
+        if checkpoint.index() > self.nb_consumed {
+            self.consume_nth(checkpoint.index() - self.nb_consumed - 1);
+        }
+        Ok(())
+    }
+}
+
+impl<I: Iterator<Item = char>> MatchStr for IterCharReader<I> {
+    fn location_policy(&self) -> LocationPolicy {
+        self.policy
+    }
+
+    fn set_location_policy(&mut self, policy: LocationPolicy) {
+        self.policy = policy;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iter_char_reader() {
+        // Generated input
+        let mut reader = IterCharReader::new("abc".chars().cycle().take(7));
+
+        assert_eq!(reader.match_str(3, "abca"), Ok(true));
+        assert_eq!(reader.match_range(6, 'a', 'c', 0).unwrap().len(), 1);
+        assert_eq!(reader.is_end_of_input(7), Ok(true));
+
+        // Consumed chars are dropped
+        assert_eq!(reader.consume_nth(2), Some('c'));
+        assert_eq!(reader.match_str(0, "a"), Err(LexError::NoLookBehind(0).into()));
+        assert_eq!(reader.rewind(Checkpoint::new(1, 0)), Err(LexError::NoLookBehind(1).into()));
+
+        // But it is possible to move forward
+        assert_eq!(reader.rewind(Checkpoint::new(5, 0)), Ok(()));
+        assert_eq!(reader.peek(), Some('c'));
+    }
+}
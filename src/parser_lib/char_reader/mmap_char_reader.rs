@@ -0,0 +1,244 @@
+use std::{error::Error, fs::File, io};
+
+use memmap2::Mmap;
+
+use crate::parser_lib::{Checkpoint, LexError, LocationDelta, LocationPolicy, MatchStr, ParserError, Rewind, Stream};
+
+/// Char reader over a memory-mapped file.
+///
+/// The file is validated once when opened, then every comparison is done directly
+/// on slices of the mapping, without copying chars into a buffer.
+/// Useful for big files, for which the look ahead of a `FileCharReader` would need a huge buffer.
+///
+/// The mapping is shared with the file: see `new` for what the caller must guarantee.
+#[derive(Debug)]
+pub struct MmapCharReader {
+    map: Mmap,
+    /// The current position in the input, counted in chars.
+    cursor_index: usize,
+    /// Byte offset of the cursor.
+    cursor_byte: usize,
+    /// Last position looked up (char index, byte offset), to avoid walking from the cursor every time.
+    last_lookup: (usize, usize),
+    /// How locations are computed.
+    policy: LocationPolicy,
+}
+
+impl MmapCharReader {
+    /// Maps the given file into memory.
+    ///
+    /// Returns an error if the file can't be mapped, or if it is not valid UTF-8.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified, truncated or replaced in place, by this process or by another one,
+    /// for as long as the reader lives. The content is only validated here, then read as a `str`:
+    /// a modified file is undefined behavior, and a truncated one can kill the process with `SIGBUS`.
+    #[allow(unused)]
+    pub unsafe fn new(filepath: &str) -> Result<Self, Box<dyn Error>> {
+        // Safety: the caller guarantees that the file is not modified while it is mapped
+        let map = Mmap::map(&File::open(filepath)?)?;
+
+        // Validate once, so that the mapping can then be used as a str
+        if let Err(err) = std::str::from_utf8(&map) {
+            return Err(Box::new(io::Error::new(io::ErrorKind::InvalidData, err)));
+        }
+
+        Ok(Self {
+            map,
+            cursor_index: 0,
+            cursor_byte: 0,
+            last_lookup: (0, 0),
+            policy: LocationPolicy::default(),
+        })
+    }
+
+    fn text(&self) -> &str {
+        // Safety: the content was validated in the constructor, and the caller of the constructor
+        // guarantees that it is not modified afterwards
+        unsafe { std::str::from_utf8_unchecked(&self.map) }
+    }
+
+    /// Returns the byte offset of the char at the given absolute index.
+    /// The end of the input is a valid position, after it None is returned.
+    fn byte_of(&mut self, index: usize) -> Option<usize> {
+        // Start from the last lookup if it is not too far
+        let (mut i, mut byte) = if self.last_lookup.0 >= self.cursor_index && self.last_lookup.0 <= index {
+            self.last_lookup
+        } else {
+            (self.cursor_index, self.cursor_byte)
+        };
+
+        let text = self.text();
+        let mut chars = text[byte..].chars();
+        while i < index {
+            byte += chars.next()?.len_utf8();
+            i += 1;
+        }
+
+        self.last_lookup = (i, byte);
+        Some(byte)
+    }
+}
+
+impl Stream<char> for MmapCharReader {
+    fn peek(&mut self) -> Option<char> {
+        self.text()[self.cursor_byte..].chars().next()
+    }
+
+    fn peek_nth(&mut self, n: usize) -> Option<char> {
+        let byte = self.byte_of(self.cursor_index + n)?;
+        self.text()[byte..].chars().next()
+    }
+
+    fn consume(&mut self) -> Option<char> {
+        self.consume_nth(0)
+    }
+
+    fn consume_nth(&mut self, n: usize) -> Option<char> {
+        let c = self.peek_nth(n)?;
+
+        // The byte of the nth char was just looked up
+        self.cursor_byte = self.last_lookup.1 + c.len_utf8();
+        self.cursor_index += n + 1;
+        Some(c)
+    }
+
+    fn is_eof(&mut self) -> bool {
+        self.cursor_byte >= self.map.len()
+    }
+
+    fn index(&self) -> usize {
+        self.cursor_index
+    }
+}
+
+impl Rewind for MmapCharReader {
+    fn checkpoint(&mut self) -> Checkpoint {
+        Checkpoint::new(self.cursor_index, self.cursor_byte)
+    }
+
+    fn rewind(&mut self, checkpoint: Checkpoint) -> Result<(), ParserError> {
+        // The whole file is mapped, so it is always possible
+        self.cursor_index = checkpoint.index();
+        self.cursor_byte = checkpoint.position();
+        self.last_lookup = (self.cursor_index, self.cursor_byte);
+        Ok(())
+    }
+}
+
+impl MatchStr for MmapCharReader {
+    fn match_str(&mut self, pos: usize, s: &str) -> Result<bool, ParserError> {
+        if pos < self.cursor_index {
+            return Err(LexError::NoLookBehind(pos).into());
+        }
+
+        // Compare the whole slice at once
+        match self.byte_of(pos) {
+            Some(byte) => Ok(self.text()[byte..].starts_with(s)),
+            None => Ok(false),
+        }
+    }
+
+    fn match_range(
+        &mut self,
+        pos: usize,
+        start: char,
+        end: char,
+        max: usize,
+    ) -> Result<LocationDelta, ParserError> {
+        if pos < self.cursor_index {
+            return Err(LexError::NoLookBehind(pos).into());
+        }
+
+        let mut matched = LocationDelta::with_policy(self.policy);
+        let byte = match self.byte_of(pos) {
+            Some(byte) => byte,
+            None => return Ok(matched),
+        };
+
+        for c in self.text()[byte..].chars() {
+            // If a difference is found, or if we already have matched the max, we stop here
+            if c < start || c > end || (max != 0 && matched.len() >= max) {
+                break;
+            }
+            matched.push(c);
+        }
+
+        Ok(matched)
+    }
+
+    fn find_char(&mut self, pos: usize, c: char) -> Result<Option<usize>, ParserError> {
+        if pos < self.cursor_index {
+            return Err(LexError::NoLookBehind(pos).into());
+        }
+
+        let Some(byte) = self.byte_of(pos) else {
+            return Ok(None);
+        };
+
+        // The bytes are searched at once, then the chars before the match are counted
+        let Some(offset) = self.text()[byte..].find(c) else {
+            return Ok(None);
+        };
+        let index = pos + self.text()[byte..byte + offset].chars().count();
+        self.last_lookup = (index, byte + offset);
+        Ok(Some(index))
+    }
+
+    fn is_newline(&mut self, pos: usize) -> Result<bool, ParserError> {
+        self.match_str(pos, "\n")
+    }
+
+    fn is_end_of_input(&mut self, pos: usize) -> Result<bool, ParserError> {
+        if pos < self.cursor_index {
+            return Err(LexError::NoLookBehind(pos).into());
+        }
+
+        Ok(!matches!(self.byte_of(pos), Some(byte) if byte < self.map.len()))
+    }
+
+    fn location_policy(&self) -> LocationPolicy {
+        self.policy
+    }
+
+    fn set_location_policy(&mut self, policy: LocationPolicy) {
+        self.policy = policy;
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mmap_char_reader() {
+        // Safety: the test files are never modified
+        let mut reader = unsafe { MmapCharReader::new("resources/test_files/test.txt") }.unwrap();
+
+        assert_eq!(reader.peek(), Some('😎'));
+        assert_eq!(reader.peek_nth(2), Some('h'));
+
+        // No buffer, so even far words can be compared
+        assert_eq!(reader.match_str(8, "this"), Ok(true));
+        assert_eq!(reader.match_str(39, "important"), Ok(true));
+        assert_eq!(reader.match_str(10, "this"), Ok(false));
+        assert_eq!(reader.match_range(39, 'a', 'z', 0).unwrap().len(), 9);
+
+        // The chars are counted after the multi-byte one
+        assert_eq!(reader.find_char(0, 'w'), Ok(Some(23)));
+        assert_eq!(reader.find_char(3, 'h'), Ok(Some(9)));
+        assert_eq!(reader.match_str(23, "which"), Ok(true));
+        assert_eq!(reader.find_char(0, 'z'), Ok(None));
+
+        assert_eq!(reader.consume_nth(6), Some('o'));
+        assert_eq!(reader.match_str(2, "hello"), Err(LexError::NoLookBehind(2).into()));
+        assert_eq!(reader.consume(), Some(' '));
+        assert_eq!(reader.peek(), Some('t'));
+
+        assert_eq!(reader.is_end_of_input(58), Ok(false));
+        assert_eq!(reader.is_end_of_input(59), Ok(true));
+        assert_eq!(reader.is_eof(), false);
+    }
+}
@@ -1,6 +1,45 @@
+//! Input subsystem of the parser library.
+//!
+//! All the inputs share one trait hierarchy: `Stream<char>` for the cursor, and `MatchStr` on top of it for the
+//! matching functions used by the matchers (binary inputs use `Stream<u8>` and `MatchBytes` the same way).
+//!
+//! - In-memory readers: `StringCharReader`, `BytesCharReader`, `MmapCharReader` (`mmap` feature), `MultiFileCharReader`
+//! - Streaming readers, with a `RingBuffer` loaded in bulk: `ReadCharReader`, `FileCharReader`
+//! - Other sources: `IterCharReader`, `InteractiveCharReader`
+//! - Wrappers: `NfcCharReader`, `StatsCharReader`
+//!
+//! Read errors are reported as `ParserError`s by all of them.
+
+mod bytes_char_reader;
+mod encoding;
 mod file_char_reader;
+mod interactive_char_reader;
+mod iter_char_reader;
+#[cfg(feature = "mmap")]
+mod mmap_char_reader;
+mod multi_file_char_reader;
+#[cfg(feature = "nfc")]
+mod nfc_char_reader;
+mod prefetcher;
+mod read_char_reader;
+mod stats_char_reader;
 mod string_char_reader;
+mod utf8_decoder;
 mod utils;
 
+pub use bytes_char_reader::BytesCharReader;
+pub use encoding::Encoding;
 pub use file_char_reader::FileCharReader;
+pub use interactive_char_reader::InteractiveCharReader;
+pub use iter_char_reader::IterCharReader;
+#[cfg(feature = "mmap")]
+pub use mmap_char_reader::MmapCharReader;
+pub use multi_file_char_reader::MultiFileCharReader;
+#[cfg(feature = "nfc")]
+pub use nfc_char_reader::NfcCharReader;
+pub use prefetcher::Prefetcher;
+pub use read_char_reader::ReadCharReader;
+pub use stats_char_reader::StatsCharReader;
 pub use string_char_reader::StringCharReader;
+pub use utf8_decoder::{Utf8Decoder, Utf8Error};
+pub use utils::InvalidUtf8Policy;
@@ -0,0 +1,195 @@
+use std::{error::Error, fs};
+
+use crate::parser_lib::{Checkpoint, Location, LocationPolicy, MatchStr, ParserError, Rewind, SourceId, Stream};
+
+/// Char reader that chains several sources (files, or include-expanded strings) into one input.
+///
+/// The matchers see a single input, so the locations they compute are relative to the start of the chain.
+/// `locate` converts them back to a location in the right source, with its `SourceId`.
+///
+/// Like the string reader, all the sources are loaded in memory.
+#[derive(Debug, Default)]
+pub struct MultiFileCharReader {
+    chars: Vec<char>,
+    /// The chained sources, in order.
+    sources: Vec<Source>,
+    /// Total number of bytes of the UTF-8 encoded sources.
+    bytes: usize,
+    /// Total number of new lines in the sources.
+    newlines: usize,
+    /// The current position in the chain.
+    cursor_index: usize,
+    /// How locations are computed.
+    policy: LocationPolicy,
+}
+
+/// Where a source starts in the chain.
+#[derive(Debug)]
+struct Source {
+    name: String,
+    index: usize,
+    byte_offset: usize,
+    /// Number of new lines before the source.
+    newlines: usize,
+}
+
+impl MultiFileCharReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the content of the given file at the end of the chain.
+    /// Returns the id of the new source, which is named after the path of the file.
+    pub fn add_file(&mut self, filepath: &str) -> Result<SourceId, Box<dyn Error>> {
+        let content = fs::read_to_string(filepath)?;
+        Ok(self.add_source(filepath, &content))
+    }
+
+    /// Adds the given text at the end of the chain, under the given name.
+    /// Returns the id of the new source.
+    pub fn add_source(&mut self, name: &str, text: &str) -> SourceId {
+        self.sources.push(Source {
+            name: String::from(name),
+            index: self.chars.len(),
+            byte_offset: self.bytes,
+            newlines: self.newlines,
+        });
+
+        self.chars.extend(text.chars());
+        self.bytes += text.len();
+        self.newlines += text.matches('\n').count();
+
+        SourceId::new(self.sources.len() - 1)
+    }
+
+    /// Returns the name of the given source.
+    pub fn source_name(&self, source: SourceId) -> Option<&str> {
+        self.sources.get(source.id()).map(|s| s.name.as_str())
+    }
+
+    /// Returns the source containing the char at the given index of the chain.
+    /// The end of the chain belongs to the last source.
+    pub fn source_of(&self, index: usize) -> SourceId {
+        // Empty sources start at the same index as the next one, the last one wins
+        let id = self.sources.partition_point(|s| s.index <= index);
+        SourceId::new(id.saturating_sub(1))
+    }
+
+    /// Converts a location computed on the chain to a location in the source containing it.
+    pub fn locate(&self, loc: &Location) -> Location {
+        let id = self.source_of(loc.index());
+        let source = match self.sources.get(id.id()) {
+            Some(source) => source,
+            None => return *loc,
+        };
+
+        let base = self.policy.base();
+        let lines_before = source.newlines;
+        let line = loc.line() - lines_before;
+
+        // On the first line of the source, the columns of the end of the previous source are removed
+        let column = if line == base {
+            let line_start = self.chars[..source.index].iter().rposition(|c| *c == '\n').map_or(0, |i| i + 1);
+            let offset: usize = self.chars[line_start..source.index]
+                .iter()
+                .map(|c| self.policy.width_of(*c))
+                .sum();
+            loc.column() - offset
+        } else {
+            loc.column()
+        };
+
+        Location::with_byte_offset(line, column, loc.index() - source.index, loc.byte_offset() - source.byte_offset)
+            .with_source(id)
+    }
+}
+
+impl Stream<char> for MultiFileCharReader {
+    fn peek(&mut self) -> Option<char> {
+        self.chars.get(self.cursor_index).copied()
+    }
+
+    fn peek_nth(&mut self, n: usize) -> Option<char> {
+        self.chars.get(self.cursor_index + n).copied()
+    }
+
+    fn consume(&mut self) -> Option<char> {
+        self.consume_nth(0)
+    }
+
+    fn consume_nth(&mut self, n: usize) -> Option<char> {
+        let c = self.peek_nth(n)?;
+        self.cursor_index += n + 1;
+        Some(c)
+    }
+
+    fn is_eof(&mut self) -> bool {
+        self.cursor_index >= self.chars.len()
+    }
+
+    fn index(&self) -> usize {
+        self.cursor_index
+    }
+}
+
+impl Rewind for MultiFileCharReader {
+    fn checkpoint(&mut self) -> Checkpoint {
+        Checkpoint::new(self.cursor_index, 0)
+    }
+
+    fn rewind(&mut self, checkpoint: Checkpoint) -> Result<(), ParserError> {
+        // All the sources are kept, so it is always possible
+        self.cursor_index = checkpoint.index();
+        Ok(())
+    }
+}
+
+impl MatchStr for MultiFileCharReader {
+    fn location_policy(&self) -> LocationPolicy {
+        self.policy
+    }
+
+    fn set_location_policy(&mut self, policy: LocationPolicy) {
+        self.policy = policy;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multi_file_char_reader() {
+        let mut reader = MultiFileCharReader::new();
+        let main = reader.add_source("main.alm", "use lib\n");
+        let lib = reader.add_file("resources/test_files/test.txt").unwrap();
+
+        assert_eq!(reader.source_name(main), Some("main.alm"));
+        assert_eq!(reader.source_name(lib), Some("resources/test_files/test.txt"));
+
+        // The sources are read as one input
+        assert_eq!(reader.match_str(4, "lib\n😎 hello"), Ok(true));
+        assert_eq!(reader.match_str(4, "lib\nhello"), Ok(false));
+        assert_eq!(reader.source_of(7), main);
+        assert_eq!(reader.source_of(8), lib);
+    }
+
+    #[test]
+    fn test_locate() {
+        let mut reader = MultiFileCharReader::new();
+        let a = reader.add_source("a", "x\nab");
+        let b = reader.add_source("b", "cd\né");
+
+        // A location on the first line of the source loses the columns of the previous source
+        let loc = Location::new(2, 3, 4);
+        assert_eq!(reader.locate(&loc), Location::new(1, 1, 0).with_source(b));
+
+        // After a new line, only the line changes
+        let loc = Location::with_byte_offset(3, 2, 8, 9);
+        assert_eq!(reader.locate(&loc), Location::with_byte_offset(2, 2, 4, 5).with_source(b));
+
+        // Locations in the first source stay the same
+        let loc = Location::new(2, 2, 3);
+        assert_eq!(reader.locate(&loc), loc.with_source(a));
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+
+use unicode_normalization::{char::canonical_combining_class, is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+use crate::parser_lib::{Checkpoint, LexError, LocationPolicy, MatchStr, ParserError, Rewind, Stream};
+
+/// Wrapper around a char reader that normalizes its input to the Unicode NFC form.
+///
+/// That way, an `é` written as `e` followed by a combining acute accent matches a grammar using the pre-composed `é`.
+/// The locations refer to the normalized chars.
+///
+/// The chars are normalized by segments that can't be composed with each other, which are buffered until consumed.
+/// Like the other streaming readers, it can't look behind the cursor.
+#[derive(Debug)]
+pub struct NfcCharReader<R: MatchStr> {
+    inner: R,
+    /// Normalized chars that are not consumed yet.
+    buffer: VecDeque<char>,
+    /// Chars read from the inner reader but not normalized yet, because the next ones may compose with them.
+    segment: String,
+    /// Number of consumed normalized chars.
+    nb_consumed: usize,
+    /// How locations are computed.
+    policy: LocationPolicy,
+}
+
+impl<R: MatchStr> NfcCharReader<R> {
+    /// Wraps the given reader. It should not have been read yet.
+    pub fn new(inner: R) -> Self {
+        Self {
+            policy: inner.location_policy(),
+            inner,
+            buffer: VecDeque::new(),
+            segment: String::new(),
+            nb_consumed: 0,
+        }
+    }
+
+    /// Returns the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Normalizes chars of the inner reader until the buffer contains at least n chars.
+    /// Returns false if the input ends before.
+    fn load_until(&mut self, n: usize) -> bool {
+        while self.buffer.len() < n {
+            match self.inner.consume() {
+                Some(c) => {
+                    self.segment.push(c);
+
+                    // The segment can be normalized when the next char can't compose with it
+                    if self.inner.peek().is_none_or(is_boundary) {
+                        self.flush_segment();
+                    }
+                }
+                None => {
+                    self.flush_segment();
+                    return self.buffer.len() >= n;
+                }
+            }
+        }
+        true
+    }
+
+    /// Normalizes the pending segment and moves it into the buffer.
+    fn flush_segment(&mut self) {
+        self.buffer.extend(self.segment.nfc());
+        self.segment.clear();
+    }
+}
+
+/// Returns true if nothing before the given char can compose with it or be reordered after it.
+fn is_boundary(c: char) -> bool {
+    canonical_combining_class(c) == 0 && is_nfc_quick(std::iter::once(c)) == IsNormalized::Yes
+}
+
+impl<R: MatchStr> Stream<char> for NfcCharReader<R> {
+    fn peek(&mut self) -> Option<char> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&mut self, n: usize) -> Option<char> {
+        self.load_until(n + 1);
+        self.buffer.get(n).copied()
+    }
+
+    fn consume(&mut self) -> Option<char> {
+        self.consume_nth(0)
+    }
+
+    fn consume_nth(&mut self, n: usize) -> Option<char> {
+        let c = self.peek_nth(n)?;
+        self.buffer.drain(..=n);
+        self.nb_consumed += n + 1;
+        Some(c)
+    }
+
+    fn is_eof(&mut self) -> bool {
+        self.peek().is_none()
+    }
+
+    fn index(&self) -> usize {
+        self.nb_consumed
+    }
+}
+
+impl<R: MatchStr> Rewind for NfcCharReader<R> {
+    fn checkpoint(&mut self) -> Checkpoint {
+        Checkpoint::new(self.nb_consumed, 0)
+    }
+
+    fn rewind(&mut self, checkpoint: Checkpoint) -> Result<(), ParserError> {
+        // The consumed chars are dropped, so it is only possible to move forward
+        if checkpoint.index() < self.nb_consumed {
+            return Err(LexError::NoLookBehind(checkpoint.index()).into());
+        }
+
+        if checkpoint.index() > self.nb_consumed {
+            self.consume_nth(checkpoint.index() - self.nb_consumed - 1);
+        }
+        Ok(())
+    }
+}
+
+impl<R: MatchStr> MatchStr for NfcCharReader<R> {
+    fn location_policy(&self) -> LocationPolicy {
+        self.policy
+    }
+
+    fn set_location_policy(&mut self, policy: LocationPolicy) {
+        self.policy = policy;
+    }
+
+    fn check_error(&self) -> Result<(), ParserError> {
+        self.inner.check_error()
+    }
+
+    fn refills(&self) -> usize {
+        self.inner.refills()
+    }
+
+    fn reserve_look_ahead(&mut self, n: usize) {
+        self.inner.reserve_look_ahead(n);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use crate::parser_lib::StringCharReader;
+
+    use super::*;
+
+    #[test]
+    fn test_nfc_char_reader() {
+        // Decomposed é, then a pre-composed one
+        let mut reader = NfcCharReader::new(StringCharReader::new("cafe\u{301} et caf\u{e9}"));
+
+        assert_eq!(reader.match_str(0, "café"), Ok(true));
+        assert_eq!(reader.match_str(8, "café"), Ok(true));
+        assert_eq!(reader.is_end_of_input(12), Ok(true));
+
+        // Combining marks are reordered and composed
+        let mut reader = NfcCharReader::new(StringCharReader::new("a\u{323}\u{302}"));
+        assert_eq!(reader.match_str(0, "\u{1ead}"), Ok(true));
+        let mut reader = NfcCharReader::new(StringCharReader::new("a\u{302}\u{323}"));
+        assert_eq!(reader.match_str(0, "\u{1ead}"), Ok(true));
+
+        // Hangul jamos are composed into syllables
+        let mut reader = NfcCharReader::new(StringCharReader::new("\u{1100}\u{1161}"));
+        assert_eq!(reader.consume(), Some('가'));
+        assert_eq!(reader.is_eof(), true);
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    io::{self, ErrorKind, Read},
+    sync::mpsc::{sync_channel, Receiver},
+    thread,
+};
+
+/// Number of chunks that can be read in advance.
+const PREFETCH_DEPTH: usize = 4;
+
+/// Input that reads another one in a background thread, so that the reads are done while matching.
+///
+/// Wrap an input with it to give it to a `ReadCharReader`: the small synchronous reads done when
+/// loading chars are then served from chunks that were already read.
+///
+/// The thread stops when the end of the input is reached, when it fails, or when the prefetcher is dropped.
+#[derive(Debug)]
+pub struct Prefetcher {
+    /// Chunks read by the thread. An empty chunk means the end of the input.
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    /// Chunk being read.
+    chunk: Vec<u8>,
+    /// Position of the next byte to read in the chunk.
+    chunk_pos: usize,
+    /// True when the thread doesn't send anything anymore.
+    done: bool,
+}
+
+impl Prefetcher {
+    /// Starts reading the given input in the background, by chunks of the given size.
+    pub fn new<R: Read + Send + 'static>(mut input: R, chunk_size: usize) -> Self {
+        let (sender, receiver) = sync_channel(PREFETCH_DEPTH);
+
+        thread::spawn(move || loop {
+            let mut chunk = vec![0u8; chunk_size.max(1)];
+            let result = match input.read(&mut chunk) {
+                Ok(bytes_read) => {
+                    chunk.truncate(bytes_read);
+                    Ok(chunk)
+                }
+                // The read can simply be retried
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => Err(err),
+            };
+
+            // Stop at the end of the input, on error, or if nobody listens anymore
+            let last = !matches!(&result, Ok(chunk) if !chunk.is_empty());
+            if sender.send(result).is_err() || last {
+                break;
+            }
+        });
+
+        Self {
+            receiver,
+            chunk: Vec::new(),
+            chunk_pos: 0,
+            done: false,
+        }
+    }
+}
+
+impl Read for Prefetcher {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Wait for the next chunk if the current one is finished
+        if self.chunk_pos >= self.chunk.len() {
+            if self.done {
+                return Ok(0);
+            }
+
+            match self.receiver.recv() {
+                Ok(Ok(chunk)) => {
+                    self.done = chunk.is_empty();
+                    self.chunk = chunk;
+                    self.chunk_pos = 0;
+                }
+                Ok(Err(err)) => {
+                    self.done = true;
+                    return Err(err);
+                }
+                Err(_) => {
+                    self.done = true;
+                    return Err(io::Error::other("The prefetch thread stopped unexpectedly"));
+                }
+            }
+        }
+
+        let remaining = &self.chunk[self.chunk_pos..];
+        let n = remaining.len().min(buf.len());
+        buf[..n].copy_from_slice(&remaining[..n]);
+        self.chunk_pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::parser_lib::{MatchStr, ParserError, ReadCharReader};
+
+    use super::*;
+
+    #[test]
+    fn test_prefetcher() {
+        let mut input = Prefetcher::new(Cursor::new("hello world"), 4);
+
+        // The chunks are read back in order
+        let mut content = String::new();
+        input.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "hello world");
+        assert_eq!(input.read(&mut [0u8; 4]).unwrap(), 0);
+
+        // It can be given to a reader
+        let mut reader = ReadCharReader::new(Prefetcher::new(Cursor::new("let x = 2;"), 3), 16);
+        assert_eq!(reader.match_str(4, "x = 2;"), Ok(true));
+        assert_eq!(reader.is_end_of_input(10), Ok(true));
+    }
+
+    #[test]
+    fn test_prefetcher_error() {
+        struct FailingInput;
+
+        impl Read for FailingInput {
+            fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::new(ErrorKind::PermissionDenied, "denied"))
+            }
+        }
+
+        // The error of the thread is returned by the reader
+        let mut reader = ReadCharReader::new(Prefetcher::new(FailingInput, 4), 16);
+        let err = ParserError::from(io::Error::from(ErrorKind::PermissionDenied));
+        assert_eq!(reader.match_str(0, "a"), Err(err));
+    }
+}
@@ -0,0 +1,615 @@
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    io::{ErrorKind, Read},
+};
+
+use crate::{
+    parser_lib::{Checkpoint, IoError, LexError, LocationPolicy, MatchStr, ParserError, Rewind, Stream},
+    utils::RingBuffer,
+};
+
+use super::{
+    utils::{Decoded, InvalidUtf8Policy},
+    Encoding,
+};
+
+/// Char reader that streams characters from any input implementing `Read`
+/// (files, sockets, decoders, in-memory cursors...).
+/// Doesn't load the whole input into memory.
+///
+/// Decodes the input (UTF-8 by default) and maintains a buffer for peaked characters.
+#[derive(Debug)]
+pub struct ReadCharReader<R: Read> {
+    /// The input to read from.
+    input: R,
+    /// The buffer of characters.
+    buffer: RingBuffer<char>,
+    /// Number of UTF-8 characters read from the buffer (head).
+    nb_read_from_buffer: usize,
+    /// Number of UTF-8 characters read from the input (tail).
+    nb_read_from_input: usize,
+    /// Bytes read from the input but not decoded yet.
+    pending: VecDeque<u8>,
+    /// Number of bytes decoded from the input.
+    byte_offset: usize,
+    /// How locations are computed.
+    policy: LocationPolicy,
+    /// Encoding of the input. None until it is detected from the first bytes.
+    encoding: Option<Encoding>,
+    /// What to do with invalid UTF-8.
+    utf8_policy: InvalidUtf8Policy,
+    /// If true, `\r\n` is read as a single `\n`.
+    normalize_newlines: bool,
+    /// Error that happened while reading the input, if any.
+    /// Once it happened, no more chars are read and it is returned by the matching functions.
+    error: Option<ParserError>,
+    /// Number of consumed chars that are kept in the buffer, to be able to look behind the cursor.
+    look_behind: usize,
+    /// Number of calls to `load_chars` that loaded chars.
+    refills: usize,
+}
+
+impl<R: Read> ReadCharReader<R> {
+    /// Creates a new char reader over the given input with the given buffer size
+    pub fn new(input: R, buffer_size: usize) -> Self {
+        ReadCharReader {
+            input,
+            buffer: RingBuffer::new(buffer_size),
+            nb_read_from_input: 0,
+            nb_read_from_buffer: 0,
+            pending: VecDeque::new(),
+            byte_offset: 0,
+            policy: LocationPolicy::default(),
+            encoding: None,
+            utf8_policy: InvalidUtf8Policy::default(),
+            normalize_newlines: false,
+            error: None,
+            look_behind: 0,
+            refills: 0,
+        }
+    }
+
+    /// Keeps the last `window` consumed chars in the buffer, so that they can still be matched,
+    /// or shown in error messages. The buffer space they take can't be used to look ahead anymore.
+    ///
+    /// Panics if the window doesn't leave any space in the buffer.
+    pub fn set_look_behind(&mut self, window: usize) {
+        assert!(window < self.buffer.capacity(), "The look behind window must be smaller than the buffer");
+        self.look_behind = window;
+    }
+
+    /// Returns the nth consumed char, starting from the last one (n = 1).
+    /// Returns None if it is not in the look behind window.
+    pub fn peek_behind(&self, n: usize) -> Option<char> {
+        if n > self.look_behind {
+            return None;
+        }
+
+        self.buffer.peek_back(n)
+    }
+
+    /// Sets the encoding of the input, instead of detecting it from its BOM.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = Some(encoding);
+    }
+
+    /// Sets what to do when the input is not valid UTF-8.
+    pub fn set_invalid_utf8_policy(&mut self, policy: InvalidUtf8Policy) {
+        self.utf8_policy = policy;
+    }
+
+    /// If true, `\r\n` is read as a single `\n`, so that grammars matching `\n` work on Windows-authored inputs.
+    /// Locations then refer to the normalized input.
+    pub fn set_normalize_newlines(&mut self, normalize: bool) {
+        self.normalize_newlines = normalize;
+    }
+
+    /// Returns the error that happened while reading the input, if any.
+    pub fn error(&self) -> Option<&ParserError> {
+        self.error.as_ref()
+    }
+
+    /// Returns true if the input had no bytes for now when the matchers needed more (see `IoError::NeedMoreInput`).
+    pub fn needs_more_input(&self) -> bool {
+        matches!(self.error, Some(ParserError::Io(IoError::NeedMoreInput { .. })))
+    }
+
+    /// Reads the input again after a `NeedMoreInput` error, once more bytes arrived. Other errors are kept.
+    pub fn resume(&mut self) {
+        if self.needs_more_input() {
+            self.error = None;
+        }
+    }
+
+    /// Returns the wrapped input.
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+
+    /// Try to load the next n chars into the buffer.
+    /// Returns the number of actually loaded chars.
+    /// 0 means either EOF, or not enough space in the buffer.
+    ///
+    /// Returns an error if the input could not be read, or if it is not valid UTF-8 and the policy
+    /// is `InvalidUtf8Policy::Error`. The chars loaded before the error stay in the buffer.
+    pub fn load_chars(&mut self, n: usize) -> Result<usize, ParserError> {
+        // Check if there is enough space in the buffer, we don't want to override chars that weren't consumed,
+        // nor the chars of the look behind window
+        if self.buffer.size().saturating_add(n).saturating_add(self.look_behind) > self.buffer.capacity() {
+            return Ok(0);
+        }
+
+        // A char takes at least 1 byte, so we can safely read n bytes at once.
+        // The bytes that are not decoded yet are kept for the next call.
+        let mut buf = vec![0u8; n.max(4)];
+        let mut at_end = false;
+        let mut loaded = 0;
+
+        while loaded < n {
+            // The encoding is decided with the first bytes, and the BOM is skipped
+            if self.byte_offset == 0 {
+                match Encoding::resolve(self.encoding, self.pending.make_contiguous(), at_end) {
+                    Some((encoding, bom_len)) => {
+                        self.pending.drain(..bom_len);
+                        self.byte_offset += bom_len;
+                        self.encoding = Some(encoding);
+                    }
+                    None => {
+                        at_end = self.read_more(&mut buf)?;
+                        continue;
+                    }
+                }
+            }
+
+            // Checked after the BOM, which may be the whole input
+            if at_end && self.pending.is_empty() {
+                break;
+            }
+
+            let encoding = self.encoding.unwrap_or(Encoding::Utf8);
+            let c = match encoding.decode(self.pending.make_contiguous(), at_end) {
+                Decoded::Char(c, width) => {
+                    // With normalized new lines, "\r\n" is decoded as a single '\n'
+                    let (c, width) = if c == '\r' && self.normalize_newlines {
+                        match encoding.decode(&self.pending.make_contiguous()[width..], at_end) {
+                            Decoded::Char('\n', lf_width) => ('\n', width + lf_width),
+                            Decoded::Incomplete => {
+                                at_end = self.read_more(&mut buf)?;
+                                continue;
+                            }
+                            _ => (c, width),
+                        }
+                    } else {
+                        (c, width)
+                    };
+
+                    self.pending.drain(..width);
+                    self.byte_offset += width;
+                    c
+                }
+                Decoded::Invalid(width) => match self.utf8_policy {
+                    // Skip the invalid bytes, the next ones may start a valid char
+                    InvalidUtf8Policy::Replace => {
+                        self.pending.drain(..width);
+                        self.byte_offset += width;
+                        char::REPLACEMENT_CHARACTER
+                    }
+                    InvalidUtf8Policy::Error => {
+                        return Err(IoError::InvalidUtf8 { byte_offset: self.byte_offset }.into())
+                    }
+                },
+                // Read the next bytes, then try again
+                Decoded::Incomplete => {
+                    at_end = self.read_more(&mut buf)?;
+                    continue;
+                }
+            };
+
+            self.buffer.push_back(c)?;
+            self.nb_read_from_input += 1;
+            loaded += 1;
+        }
+
+        if loaded > 0 {
+            self.refills += 1;
+        }
+        Ok(loaded)
+    }
+
+    /// Reads the next bytes of the input after the pending ones, using the given buffer.
+    /// Returns true if the end of the input is reached.
+    fn read_more(&mut self, buf: &mut [u8]) -> Result<bool, ParserError> {
+        loop {
+            match self.input.read(buf) {
+                Ok(bytes_read) => {
+                    self.pending.extend(&buf[..bytes_read]);
+                    return Ok(bytes_read == 0);
+                }
+                // The read can simply be retried
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                // The bytes read so far are kept, so that the reading can be resumed when more arrive
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    let byte_offset = self.byte_offset + self.pending.len();
+                    return Err(IoError::NeedMoreInput { byte_offset }.into());
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Load chars in the buffer until the i is <= tail
+    ///
+    /// If the input fails, the error is saved and nothing more will be loaded.
+    fn load_until(&mut self, index: usize) -> bool {
+        if index >= self.nb_read_from_input {
+            if self.error.is_some() {
+                return false;
+            }
+
+            if let Err(err) = self.load_chars((index - self.nb_read_from_input).saturating_add(1)) {
+                self.error = Some(err);
+                return false;
+            }
+
+            if index >= self.nb_read_from_input {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+impl<R: Read> Stream<char> for ReadCharReader<R> {
+    fn peek(&mut self) -> Option<char> {
+        // Ensure that the next char is loaded
+        self.load_until(self.nb_read_from_buffer);
+
+        self.buffer.peek()
+    }
+
+    fn peek_nth(&mut self, n: usize) -> Option<char> {
+        // Ensure that the nth char is loaded
+        self.load_until(self.nb_read_from_buffer.saturating_add(n));
+
+        self.buffer.peek_nth(n)
+    }
+
+    fn consume(&mut self) -> Option<char> {
+        // Ensure that the next char is loaded
+        self.load_until(self.nb_read_from_buffer);
+
+        let res = self.buffer.pop_front();
+
+        if res.is_some() {
+            self.nb_read_from_buffer += 1;
+        }
+
+        res
+    }
+
+    fn consume_nth(&mut self, n: usize) -> Option<char> {
+        // Ensure that the nth char is loaded, otherwise nothing is consumed
+        if !self.load_until(self.nb_read_from_buffer.saturating_add(n)) {
+            return None;
+        }
+
+        // Discard the chars before the nth
+        for _ in 0..n {
+            self.buffer.pop_front();
+        }
+
+        let res = self.buffer.pop_front();
+        if res.is_some() {
+            self.nb_read_from_buffer += n + 1;
+        }
+
+        res
+    }
+
+    fn is_eof(&mut self) -> bool {
+        // EOF = enable to load next char
+        !self.load_until(self.nb_read_from_buffer)
+    }
+
+    fn index(&self) -> usize {
+        self.nb_read_from_buffer
+    }
+}
+
+impl<R: Read> Rewind for ReadCharReader<R> {
+    fn checkpoint(&mut self) -> Checkpoint {
+        Checkpoint::new(self.nb_read_from_buffer, 0)
+    }
+
+    fn rewind(&mut self, checkpoint: Checkpoint) -> Result<(), ParserError> {
+        let index = checkpoint.index();
+
+        if index > self.nb_read_from_buffer {
+            // Going forward is just consuming
+            self.consume_nth(index - self.nb_read_from_buffer - 1);
+        } else if self.buffer.unpop(self.nb_read_from_buffer - index) {
+            // The consumed chars are still in the buffer as long as they were not overwritten by new ones
+            self.nb_read_from_buffer = index;
+        } else {
+            return Err(LexError::NoLookBehind(index).into());
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read + Debug> MatchStr for ReadCharReader<R> {
+    fn char_at(&mut self, pos: usize) -> Result<Option<char>, ParserError> {
+        // This is a stream: we can look ahead, but we can only look behind the chars of the look behind window
+        if pos < self.nb_read_from_buffer {
+            return match self.peek_behind(self.nb_read_from_buffer - pos) {
+                Some(c) => Ok(Some(c)),
+                None => Err(LexError::NoLookBehind(pos).into()),
+            };
+        }
+
+        // This is the amount by which we will need to look ahead for the start of the stream
+        let relative_pos = pos - self.nb_read_from_buffer;
+
+        // If the char is to far away to fit in the buffer, we won't be able to look it ahead
+        if relative_pos.saturating_add(1).saturating_add(self.look_behind) >= self.buffer.capacity() {
+            return Err(LexError::LookAheadBufferOverflow(relative_pos.saturating_add(1)).into());
+        }
+
+        Ok(self.peek_nth(relative_pos))
+    }
+
+    fn check_error(&self) -> Result<(), ParserError> {
+        match &self.error {
+            Some(err) => Err(err.clone()),
+            None => Ok(()),
+        }
+    }
+
+    fn match_str(&mut self, pos: usize, s: &str) -> Result<bool, ParserError> {
+        let len = s.chars().count();
+        if len == 0 {
+            return Ok(true);
+        }
+
+        // Chars behind the cursor are rare, compare them one by one
+        if pos < self.nb_read_from_buffer {
+            for (i, str_c) in (pos..).zip(s.chars()) {
+                if self.char_at(i)? != Some(str_c) {
+                    return self.check_error().map(|_| false);
+                }
+            }
+            return Ok(true);
+        }
+
+        // Load all the chars at once, checking that the end of the string fits in the buffer
+        self.char_at(pos.saturating_add(len - 1))?;
+
+        // Then compare them directly in the buffer
+        let relative_pos = pos - self.nb_read_from_buffer;
+        let (first, second) = self.buffer.as_slices();
+        let mut input = first.iter().chain(second).flatten().skip(relative_pos);
+        for str_c in s.chars() {
+            match input.next() {
+                Some(c) if *c == str_c => (),
+                // If a difference is found, it's not equal
+                Some(_) => return Ok(false),
+                // If EOF is reached before the end of the string to compare, it's not equal,
+                // unless it is caused by a read error
+                None => return self.check_error().map(|_| false),
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn refills(&self) -> usize {
+        self.refills
+    }
+
+    fn reserve_look_ahead(&mut self, n: usize) {
+        // The buffer must be strictly larger than the chars looked ahead and behind, see `char_at`
+        let needed = n.saturating_add(1).saturating_add(self.look_behind);
+        if needed > self.buffer.capacity() {
+            self.buffer.grow(needed - self.buffer.capacity());
+        }
+    }
+
+    fn location_policy(&self) -> LocationPolicy {
+        self.policy
+    }
+
+    fn set_location_policy(&mut self, policy: LocationPolicy) {
+        self.policy = policy;
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_load_chars() {
+        let input = Cursor::new("😎 hello this is a file");
+        let mut reader = ReadCharReader::new(input, 10);
+
+        let res = reader.load_chars(10);
+        assert_eq!(res.unwrap(), 10);
+
+        // Check that the buffer was filled accordingly
+        assert_eq!(reader.buffer.pop_front(), Some('😎'));
+        assert_eq!(reader.buffer.pop_front(), Some(' '));
+        assert_eq!(reader.buffer.pop_front(), Some('h'));
+        assert_eq!(reader.buffer.pop_front(), Some('e'));
+        assert_eq!(reader.buffer.pop_front(), Some('l'));
+        assert_eq!(reader.buffer.pop_front(), Some('l'));
+        assert_eq!(reader.buffer.pop_front(), Some('o'));
+        assert_eq!(reader.buffer.pop_front(), Some(' '));
+        assert_eq!(reader.buffer.pop_front(), Some('t'));
+        assert_eq!(reader.buffer.pop_front(), Some('h'));
+        assert_eq!(reader.buffer.pop_front(), None);
+
+        // Too many chars to fit in the buffer
+        assert_eq!(reader.load_chars(usize::MAX), Ok(0));
+        assert_eq!(reader.peek_nth(usize::MAX), None);
+        assert_eq!(reader.consume_nth(usize::MAX), None);
+        assert_eq!(reader.char_at(usize::MAX), Err(LexError::LookAheadBufferOverflow(usize::MAX).into()));
+    }
+
+    #[test]
+    fn test_in_memory_input() {
+        let mut reader = ReadCharReader::new(Cursor::new(b"let x = 2;".to_vec()), 16);
+
+        assert_eq!(reader.match_str(0, "let"), Ok(true));
+        assert_eq!(reader.match_range(4, 'a', 'z', 0).unwrap().len(), 1);
+        assert_eq!(reader.consume_nth(3), Some(' '));
+        assert_eq!(reader.peek(), Some('x'));
+        assert_eq!(reader.is_end_of_input(10), Ok(true));
+
+        // The input can be taken back
+        let input = reader.into_inner();
+        assert_eq!(input.position(), 10);
+    }
+
+    /// Input that returns a few bytes, then fails
+    #[derive(Debug)]
+    struct FailingInput {
+        bytes: &'static [u8],
+    }
+
+    impl Read for FailingInput {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if let Some((first, rest)) = self.bytes.split_first() {
+                buf[0] = *first;
+                self.bytes = rest;
+                Ok(1)
+            } else {
+                Err(std::io::Error::other("disk unplugged"))
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_error() {
+        let mut reader = ReadCharReader::new(FailingInput { bytes: b"let" }, 16);
+
+        // What was read before the error can be matched
+        assert_eq!(reader.match_str(0, "le"), Ok(true));
+        assert_eq!(reader.error().is_none(), true);
+
+        // Then the error is returned instead of being taken for the end of the input
+        let err = ParserError::from(std::io::Error::other(""));
+        assert_eq!(reader.match_str(0, "let x"), Err(err.clone()));
+        assert_eq!(reader.is_end_of_input(3), Err(err.clone()));
+        assert_eq!(reader.match_range(0, 'a', 'z', 0), Err(err));
+        assert_eq!(reader.error().unwrap().to_string(), "Could not read the input: disk unplugged");
+
+        // The stream just stops
+        assert_eq!(reader.peek_nth(3), None);
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let input = [b'a', 0xff, b'b', 0xe2, 0x82];
+
+        // By default, invalid bytes are replaced
+        let mut reader = ReadCharReader::new(Cursor::new(input), 16);
+        assert_eq!(reader.match_str(0, "a\u{fffd}b\u{fffd}"), Ok(true));
+        assert_eq!(reader.is_end_of_input(4), Ok(true));
+
+        // But they can be reported instead
+        let mut reader = ReadCharReader::new(Cursor::new(input), 16);
+        reader.set_invalid_utf8_policy(InvalidUtf8Policy::Error);
+        assert_eq!(reader.match_str(0, "a"), Ok(true));
+        assert_eq!(reader.match_str(0, "ab"), Err(IoError::InvalidUtf8 { byte_offset: 1 }.into()));
+        assert_eq!(reader.peek_nth(1), None);
+    }
+
+    #[test]
+    fn test_encodings() {
+        // UTF-16 is detected from the BOM, which is not part of the chars
+        let mut reader = ReadCharReader::new(Cursor::new(b"\xff\xfeh\0\xe9\0".to_vec()), 16);
+        assert_eq!(reader.match_str(0, "hé"), Ok(true));
+        assert_eq!(reader.is_end_of_input(2), Ok(true));
+
+        // The BOM can be the whole input
+        let mut reader = ReadCharReader::new(Cursor::new(b"\xff\xfe".to_vec()), 16);
+        assert_eq!(reader.peek(), None);
+
+        // Legacy encodings can't be detected, they are set instead
+        let mut reader = ReadCharReader::new(Cursor::new(b"\x80 \xe9t\xe9".to_vec()), 16);
+        reader.set_encoding(Encoding::Windows1252);
+        assert_eq!(reader.match_str(0, "€ été"), Ok(true));
+    }
+
+    #[test]
+    fn test_normalize_newlines() {
+        let mut reader = ReadCharReader::new(Cursor::new("a\r\nb\rc\r"), 16);
+        reader.set_normalize_newlines(true);
+
+        // Only the \r followed by a \n are removed
+        assert_eq!(reader.match_str(0, "a\nb\rc\r"), Ok(true));
+        assert_eq!(reader.is_end_of_input(6), Ok(true));
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut reader = ReadCharReader::new(Cursor::new("hello world"), 8);
+
+        let start = reader.checkpoint();
+        assert_eq!(reader.consume_nth(5), Some(' '));
+        let middle = reader.checkpoint();
+
+        // The consumed chars are read again
+        assert_eq!(reader.rewind(start), Ok(()));
+        assert_eq!(reader.match_str(0, "hello"), Ok(true));
+        assert_eq!(reader.rewind(middle), Ok(()));
+        assert_eq!(reader.peek(), Some('w'));
+
+        // Until the buffer needs their space
+        assert_eq!(reader.match_str(6, "world"), Ok(true));
+        assert_eq!(reader.rewind(start), Err(LexError::NoLookBehind(0).into()));
+        assert_eq!(reader.peek(), Some('w'));
+    }
+
+    #[test]
+    fn test_look_behind() {
+        let mut reader = ReadCharReader::new(Cursor::new("let x = 2;"), 8);
+        reader.set_look_behind(3);
+
+        // The last consumed chars can still be matched
+        assert_eq!(reader.consume_nth(4), Some('x'));
+        assert_eq!(reader.peek_behind(1), Some('x'));
+        assert_eq!(reader.match_str(2, "t x ="), Ok(true));
+        assert_eq!(reader.is_newline(3), Ok(false));
+
+        // But not the older ones
+        assert_eq!(reader.match_str(1, "e"), Err(LexError::NoLookBehind(1).into()));
+        assert_eq!(reader.peek_behind(4), None);
+
+        // Since the window takes a part of the buffer, less can be looked ahead
+        assert_eq!(reader.match_str(5, " = 2;"), Err(LexError::LookAheadBufferOverflow(5).into()));
+        assert_eq!(reader.match_str(5, " = 2"), Ok(true));
+
+        // Unless the buffer is grown
+        reader.reserve_look_ahead(5);
+        assert_eq!(reader.match_str(5, " = 2;"), Ok(true));
+    }
+
+    #[test]
+    fn test_match_str_across_wrap() {
+        let mut reader = ReadCharReader::new(Cursor::new("abcdefghijkl"), 8);
+
+        // Move the start of the buffer, so that the next chars wrap around its end
+        assert_eq!(reader.match_str(0, "abcdef"), Ok(true));
+        assert_eq!(reader.consume_nth(5), Some('f'));
+        assert_eq!(reader.match_str(6, "ghijkl"), Ok(true));
+        assert_eq!(reader.match_str(7, "hijkx"), Ok(false));
+        assert_eq!(reader.match_str(10, "klm"), Ok(false));
+    }
+}
@@ -0,0 +1,208 @@
+use crate::parser_lib::{Checkpoint, LocationDelta, LocationPolicy, MatchStr, ParserError, ReaderStats, Rewind, Stream};
+
+/// Wrapper around a char reader that counts how it is used, see `ReaderStats`.
+///
+/// The stats can be retrieved after a parse to tune the buffer size of the reader,
+/// or to find the rules of a grammar that cause a lot of backtracking.
+#[derive(Debug)]
+pub struct StatsCharReader<R: MatchStr> {
+    inner: R,
+    stats: ReaderStats,
+    /// End of the furthest match since the last backtrack.
+    furthest: usize,
+}
+
+impl<R: MatchStr> StatsCharReader<R> {
+    /// Wraps the given reader.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            stats: ReaderStats::default(),
+            furthest: 0,
+        }
+    }
+
+    /// Returns the stats collected since the creation of the wrapper, or since the last reset.
+    pub fn stats(&self) -> ReaderStats {
+        ReaderStats {
+            refills: self.inner.refills(),
+            ..self.stats
+        }
+    }
+
+    /// Sets all the counters back to 0, except the refills that are counted by the wrapped reader.
+    pub fn reset_stats(&mut self) {
+        self.stats = ReaderStats::default();
+        self.furthest = self.inner.index();
+    }
+
+    /// Returns the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Records that the chars from `start` to `end` (exclusive) were read,
+    /// and that they matched if `matched` is true.
+    fn record(&mut self, start: usize, end: usize, matched: bool) {
+        let lookahead = end.saturating_sub(self.inner.index());
+        self.stats.peak_lookahead = self.stats.peak_lookahead.max(lookahead);
+
+        // Going before the end of a match means that it is being discarded
+        if start < self.furthest {
+            self.stats.backtracks += 1;
+            self.furthest = start;
+        }
+
+        if matched {
+            self.furthest = self.furthest.max(end);
+        }
+    }
+}
+
+impl<R: MatchStr> Stream<char> for StatsCharReader<R> {
+    fn peek(&mut self) -> Option<char> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&mut self, n: usize) -> Option<char> {
+        self.stats.peak_lookahead = self.stats.peak_lookahead.max(n + 1);
+        self.inner.peek_nth(n)
+    }
+
+    fn consume(&mut self) -> Option<char> {
+        self.consume_nth(0)
+    }
+
+    fn consume_nth(&mut self, n: usize) -> Option<char> {
+        let c = self.inner.consume_nth(n)?;
+        self.stats.consumed += n + 1;
+        Some(c)
+    }
+
+    fn is_eof(&mut self) -> bool {
+        self.inner.is_eof()
+    }
+
+    fn index(&self) -> usize {
+        self.inner.index()
+    }
+}
+
+impl<R: MatchStr> Rewind for StatsCharReader<R> {
+    fn checkpoint(&mut self) -> Checkpoint {
+        self.inner.checkpoint()
+    }
+
+    fn rewind(&mut self, checkpoint: Checkpoint) -> Result<(), ParserError> {
+        let index = self.inner.index();
+        self.inner.rewind(checkpoint)?;
+
+        if checkpoint.index() < index {
+            self.stats.backtracks += 1;
+            self.furthest = checkpoint.index();
+        } else {
+            self.stats.consumed += checkpoint.index() - index;
+        }
+        Ok(())
+    }
+}
+
+impl<R: MatchStr> MatchStr for StatsCharReader<R> {
+    fn char_at(&mut self, pos: usize) -> Result<Option<char>, ParserError> {
+        self.record(pos, pos + 1, false);
+        self.inner.char_at(pos)
+    }
+
+    fn check_error(&self) -> Result<(), ParserError> {
+        self.inner.check_error()
+    }
+
+    fn match_str(&mut self, pos: usize, s: &str) -> Result<bool, ParserError> {
+        let matched = self.inner.match_str(pos, s)?;
+        self.record(pos, pos + s.chars().count(), matched);
+        Ok(matched)
+    }
+
+    fn match_range(&mut self, pos: usize, start: char, end: char, max: usize) -> Result<LocationDelta, ParserError> {
+        let delta = self.inner.match_range(pos, start, end, max)?;
+
+        // The char after the range was also read, to know that it doesn't match
+        self.record(pos, pos + delta.len() + 1, false);
+        if !delta.is_empty() {
+            self.record(pos, pos + delta.len(), true);
+        }
+        Ok(delta)
+    }
+
+    fn is_newline(&mut self, pos: usize) -> Result<bool, ParserError> {
+        let matched = self.inner.is_newline(pos)?;
+        self.record(pos, pos + 1, matched);
+        Ok(matched)
+    }
+
+    fn is_end_of_input(&mut self, pos: usize) -> Result<bool, ParserError> {
+        self.record(pos, pos + 1, false);
+        self.inner.is_end_of_input(pos)
+    }
+
+    fn refills(&self) -> usize {
+        self.inner.refills()
+    }
+
+    fn reserve_look_ahead(&mut self, n: usize) {
+        self.inner.reserve_look_ahead(n);
+    }
+
+    fn location_policy(&self) -> LocationPolicy {
+        self.inner.location_policy()
+    }
+
+    fn set_location_policy(&mut self, policy: LocationPolicy) {
+        self.inner.set_location_policy(policy);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::parser_lib::{Location, MatchToken, ReadCharReader, StringCharReader};
+    use crate::{choice, seq, word};
+
+    use super::*;
+
+    #[test]
+    fn test_stats_char_reader() {
+        let mut reader = StatsCharReader::new(StringCharReader::new("hello world"));
+
+        // The first choice matches "hello " before failing, so the second one backtracks
+        let rule = choice!(seq!(word!("hello "), word!("there")), word!("hello world"));
+        let res = rule.test(&Location::beginning(), &mut reader).unwrap();
+        assert_eq!(res.unwrap().len(), 11);
+
+        reader.consume_nth(10);
+        let stats = reader.stats();
+        assert_eq!(stats.consumed, 11);
+        assert_eq!(stats.peak_lookahead, 11);
+        assert_eq!(stats.refills, 0);
+        assert_eq!(stats.backtracks, 1);
+
+        reader.reset_stats();
+        assert_eq!(reader.stats(), ReaderStats::default());
+    }
+
+    #[test]
+    fn test_refills() {
+        let mut reader = StatsCharReader::new(ReadCharReader::new(Cursor::new("hello world"), 4));
+
+        // Each consumed char that isn't in the buffer yet is loaded
+        assert_eq!(reader.match_str(0, "hel"), Ok(true));
+        reader.consume_nth(2);
+        assert_eq!(reader.match_str(3, "lo"), Ok(true));
+
+        let stats = reader.stats();
+        assert_eq!(stats.refills, 2);
+        assert_eq!(stats.peak_lookahead, 3);
+        assert_eq!(stats.to_string(), "3 chars consumed, peak lookahead of 3 chars, 2 buffer refills, 0 backtracks");
+    }
+}
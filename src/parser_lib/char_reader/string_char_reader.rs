@@ -1,17 +1,20 @@
-use crate::parser_lib::{MatchStr, ParserError, Stream};
+use crate::parser_lib::{Checkpoint, LexError, LocationPolicy, MatchStr, ParseContext, ParserError, Rewind, Stream};
 
 /// Char reader that streams characters from a string.
 ///
 /// Since the whole string is loaded in memory, doesn't use a buffer.
-/// Since Rust peekable iterator do not support look ahead of more than 1 char, this doesn't use it.
-/// Thus, it is not at all as optimized as the file reader .
+/// The string is decoded once into chars, so that any char can be accessed in constant time.
 ///
-/// Useful for testing. In real life situations, prefer a FileCharReader.
+/// Useful for testing and for small inputs. For big files, prefer a FileCharReader.
 #[derive(Debug)]
 pub struct StringCharReader {
-    string: String,
+    chars: Vec<char>,
     /// The current position in the string.
     cursor_index: usize,
+    /// How locations are computed.
+    policy: LocationPolicy,
+    /// If true, the chars read are reported to the incremental parse.
+    track_reads: bool,
 }
 
 impl StringCharReader {
@@ -19,19 +22,43 @@ impl StringCharReader {
     #[allow(unused)]
     pub fn new(s: &str) -> Self {
         Self {
-            string: String::from(s),
+            chars: s.chars().collect(),
             cursor_index: 0,
+            policy: LocationPolicy::default(),
+            track_reads: false,
+        }
+    }
+
+    /// Reports the chars read to the incremental parse running on the current thread, see `Grammar::reparse`.
+    pub(crate) fn track_reads(&mut self) {
+        self.track_reads = true;
+    }
+
+    /// If true, `\r\n` is read as a single `\n`, so that grammars matching `\n` work on Windows-authored strings.
+    /// Locations then refer to the normalized string, so it should be set before reading.
+    pub fn set_normalize_newlines(&mut self, normalize: bool) {
+        if normalize {
+            let mut chars = Vec::with_capacity(self.chars.len());
+            for (i, c) in self.chars.iter().enumerate() {
+                if *c != '\r' || self.chars.get(i + 1) != Some(&'\n') {
+                    chars.push(*c);
+                }
+            }
+            self.chars = chars;
         }
     }
 }
 
 impl Stream<char> for StringCharReader {
     fn peek(&mut self) -> Option<char> {
-        self.string.chars().nth(self.cursor_index)
+        self.peek_nth(0)
     }
 
     fn peek_nth(&mut self, n: usize) -> Option<char> {
-        self.string.chars().nth(self.cursor_index + n)
+        if self.track_reads {
+            ParseContext::read(self.cursor_index + n + 1);
+        }
+        self.chars.get(self.cursor_index + n).copied()
     }
 
     fn consume(&mut self) -> Option<char> {
@@ -51,103 +78,79 @@ impl Stream<char> for StringCharReader {
     }
 
     fn is_eof(&mut self) -> bool {
-        self.string.chars().nth(self.cursor_index) == None
+        self.cursor_index >= self.chars.len()
     }
-}
-
-impl MatchStr for StringCharReader {
-    fn match_str(&mut self, pos: usize, s: &str) -> Result<bool, ParserError> {
-        if pos < self.cursor_index {
-            return Err(ParserError::NoLookBehind(pos));
-        }
 
-        // This is the amount by which we will need to look ahead for the start of the stream
-        let relative_pos = pos - self.cursor_index;
+    fn index(&self) -> usize {
+        self.cursor_index
+    }
+}
 
-        // Compare each char
-        let mut i = relative_pos;
-        for str_c in s.chars() {
-            if let Some(file_c) = self.peek_nth(i) {
-                if file_c != str_c {
-                    // If a difference is found, it's not equal
-                    return Ok(false);
-                }
-            } else {
-                // If EOF is reached before the end of the string to compare, it's not equal
-                return Ok(false);
-            }
-            i += 1;
-        }
+impl Rewind for StringCharReader {
+    fn checkpoint(&mut self) -> Checkpoint {
+        Checkpoint::new(self.cursor_index, 0)
+    }
 
-        Ok(true)
+    fn rewind(&mut self, checkpoint: Checkpoint) -> Result<(), ParserError> {
+        // The whole string is kept, so it is always possible
+        self.cursor_index = checkpoint.index();
+        Ok(())
     }
+}
 
-    fn match_range(
-        &mut self,
-        pos: usize,
-        start: char,
-        end: char,
-        max: u8,
-    ) -> Result<u32, ParserError> {
+impl MatchStr for StringCharReader {
+    fn find_char(&mut self, pos: usize, c: char) -> Result<Option<usize>, ParserError> {
         if pos < self.cursor_index {
-            return Err(ParserError::NoLookBehind(pos));
+            return Err(LexError::NoLookBehind(pos).into());
         }
 
-        // This is the amount by which we will need to look ahead for the start of the stream
-        let relative_pos = pos - self.cursor_index;
-
-        let mut matched = 0;
+        let chars = self.chars.get(pos..).unwrap_or_default();
+        let found = position(chars, c).map(|i| pos + i);
 
-        // Compare each char
-        let mut i = relative_pos;
-        while let Some(c) = self.peek_nth(i) {
-            // If a difference is found, or if we already have matched the max, we stop here
-            if c < start || c > end {
-                break;
-            }
-
-            // If there is a max and it is reached, we stop here
-            if max != 0 && matched >= max.into() {
-                break;
-            }
-
-            matched += 1;
-            i += 1;
+        if self.track_reads {
+            ParseContext::read(found.unwrap_or(pos + chars.len()) + 1);
         }
-
-        Ok(matched)
+        Ok(found)
     }
 
-    fn is_newline(&mut self, pos: usize) -> Result<bool, ParserError> {
-        if pos < self.cursor_index {
-            return Err(ParserError::NoLookBehind(pos));
-        }
-
-        // This is the amount by which we will need to look ahead for the start of the stream
-        let relative_pos = pos - self.cursor_index;
+    fn location_policy(&self) -> LocationPolicy {
+        self.policy
+    }
 
-        // Compare the char
-        match self.peek_nth(relative_pos) {
-            Some('\n') => Ok(true),
-            _ => Ok(false),
-        }
+    fn set_location_policy(&mut self, policy: LocationPolicy) {
+        self.policy = policy;
     }
+}
 
-    fn is_end_of_input(&mut self, pos: usize) -> Result<bool, ParserError> {
-        if pos < self.cursor_index {
-            return Err(ParserError::NoLookBehind(pos));
-        }
+/// Returns the position of the first occurrence of the char, searched with memchr.
+#[cfg(feature = "memchr")]
+fn position(chars: &[char], c: char) -> Option<usize> {
+    // SAFETY: the chars are initialized 4-byte values, and bytes don't need any alignment
+    let bytes = unsafe { std::slice::from_raw_parts(chars.as_ptr().cast::<u8>(), std::mem::size_of_val(chars)) };
+    // Only the occurrences aligned on a char are the char, the other ones overlap two chars
+    let needle = u32::from(c).to_ne_bytes();
+    memchr::memmem::find_iter(bytes, &needle).find(|i| i % 4 == 0).map(|i| i / 4)
+}
 
-        if pos >= self.string.len() {
-            return Ok(true);
+/// Returns the position of the first occurrence of the char.
+#[cfg(not(feature = "memchr"))]
+fn position(chars: &[char], c: char) -> Option<usize> {
+    let mut offset = 0;
+    for chunk in chars.chunks(32) {
+        // The chars of a chunk are compared without branches, so that the comparisons can be vectorized
+        if chunk.iter().fold(false, |found, input_c| found | (*input_c == c)) {
+            return chunk.iter().position(|input_c| *input_c == c).map(|i| offset + i);
         }
-
-        Ok(false)
+        offset += chunk.len();
     }
+    None
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
+    use crate::parser_lib::LexError;
+
     use super::*;
 
     #[test]
@@ -261,7 +264,7 @@ mod tests {
         assert!(reader.match_str(2, "hello").is_err());
         assert_eq!(
             reader.match_str(2, "hello").unwrap_err(),
-            ParserError::NoLookBehind(2)
+            ParserError::Lex(LexError::NoLookBehind(2))
         );
     }
 
@@ -272,33 +275,63 @@ mod tests {
 
         // Look ahead check should work
         assert!(reader.match_range(9, 'a', 'z', 1).is_ok());
-        assert_eq!(reader.match_range(9, 'a', 'z', 1).unwrap(), 1);
+        assert_eq!(reader.match_range(9, 'a', 'z', 1).unwrap().len(), 1);
 
         // But not capital
         assert!(reader.match_range(9, 'A', 'Z', 1).is_ok());
-        assert_eq!(reader.match_range(9, 'A', 'Z', 1).unwrap(), 0);
+        assert_eq!(reader.match_range(9, 'A', 'Z', 1).unwrap().len(), 0);
 
         // But not numbers
         assert!(reader.match_range(9, '0', '9', 1).is_ok());
-        assert_eq!(reader.match_range(9, '0', '9', 1).unwrap(), 0);
+        assert_eq!(reader.match_range(9, '0', '9', 1).unwrap().len(), 0);
 
         // Space is no alpha numeric
         assert!(reader.match_range(7, 'a', 'z', 1).is_ok());
-        assert_eq!(reader.match_range(7, 'a', 'z', 1).unwrap(), 0);
+        assert_eq!(reader.match_range(7, 'a', 'z', 1).unwrap().len(), 0);
 
         assert!(reader.match_range(7, 'A', 'Z', 1).is_ok());
-        assert_eq!(reader.match_range(7, 'A', 'Z', 1).unwrap(), 0);
+        assert_eq!(reader.match_range(7, 'A', 'Z', 1).unwrap().len(), 0);
 
         assert!(reader.match_range(7, '0', '9', 1).is_ok());
-        assert_eq!(reader.match_range(7, '0', '9', 1).unwrap(), 0);
+        assert_eq!(reader.match_range(7, '0', '9', 1).unwrap().len(), 0);
 
         // Should also work for longer matches
         // Here it can get words up to 10 chars, but it stops at the space so it only finds 4 chars
         assert!(reader.match_range(8, 'a', 'z', 10).is_ok());
-        assert_eq!(reader.match_range(8, 'a', 'z', 10).unwrap(), 4);
+        assert_eq!(reader.match_range(8, 'a', 'z', 10).unwrap().len(), 4);
 
         // 0 is infinite max
         assert!(reader.match_range(39, 'a', 'z', 0).is_ok());
-        assert_eq!(reader.match_range(39, 'a', 'z', 0).unwrap(), 9);
+        assert_eq!(reader.match_range(39, 'a', 'z', 0).unwrap().len(), 9);
+    }
+
+    #[test]
+    fn test_normalize_newlines() {
+        let mut reader = StringCharReader::new("a\r\nb");
+        assert_eq!(reader.match_str(0, "a\nb"), Ok(false));
+
+        // Once normalized, a matcher looking for \n works the same on Windows-authored strings
+        reader.set_normalize_newlines(true);
+        assert_eq!(reader.match_str(0, "a\nb"), Ok(true));
+        assert_eq!(reader.is_newline(1), Ok(true));
+    }
+
+    #[test]
+    fn test_find_char() {
+        // Longer than a chunk of the search
+        let input = "a".repeat(40) + "b" + &"a".repeat(40) + "b";
+        let mut reader = StringCharReader::new(&input);
+        assert_eq!(reader.find_char(0, 'b'), Ok(Some(40)));
+        assert_eq!(reader.find_char(40, 'b'), Ok(Some(40)));
+        assert_eq!(reader.find_char(41, 'b'), Ok(Some(81)));
+        assert_eq!(reader.find_char(0, 'c'), Ok(None));
+        assert_eq!(reader.find_char(100, 'b'), Ok(None));
+
+        reader.consume_nth(1);
+        assert_eq!(reader.find_char(0, 'b'), Err(LexError::NoLookBehind(0).into()));
+
+        // The bytes of the first two chars contain the ones of the last char, but across them
+        let mut reader = StringCharReader::new("\u{e941}\0\u{e9}");
+        assert_eq!(reader.find_char(0, '\u{e9}'), Ok(Some(2)));
     }
 }
@@ -0,0 +1,143 @@
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+};
+
+use crate::parser_lib::{IoError, ParserError};
+
+use super::utils::{decode_utf8, Decoded};
+
+/// Invalid UTF-8 sequence found by a `Utf8Decoder`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Utf8Error {
+    /// Offset of the sequence from the start of the input, in bytes.
+    byte_offset: usize,
+    /// Number of bytes of the sequence.
+    len: usize,
+}
+
+impl Utf8Error {
+    pub fn byte_offset(&self) -> usize {
+        self.byte_offset
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Display for Utf8Error {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "Invalid UTF-8 sequence of {} bytes at byte {}.", self.len, self.byte_offset)
+    }
+}
+
+impl Error for Utf8Error {}
+
+impl From<Utf8Error> for ParserError {
+    fn from(err: Utf8Error) -> Self {
+        ParserError::Io(IoError::InvalidUtf8 {
+            byte_offset: err.byte_offset,
+        })
+    }
+}
+
+/// Incremental UTF-8 decoder, for inputs that arrive by chunks (like network packets).
+///
+/// A char cut between two chunks is kept until the next ones complete it.
+/// It uses the same decoding as the readers, so an invalid sequence gives a single error.
+#[derive(Debug, Default)]
+pub struct Utf8Decoder {
+    /// Bytes of a cut char, waiting for the next chunk.
+    pending: Vec<u8>,
+    /// Number of bytes decoded since the start of the input.
+    byte_offset: usize,
+}
+
+impl Utf8Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the given chunk, after the bytes of the previous ones that were not decoded yet.
+    /// Returns the decoded chars, or an error for each invalid sequence.
+    pub fn feed(&mut self, bytes: &[u8]) -> impl Iterator<Item = Result<char, Utf8Error>> {
+        self.pending.extend_from_slice(bytes);
+        self.decode(false)
+    }
+
+    /// Tells the decoder that the input ended.
+    /// Returns an error if the last chunk ended with a cut char.
+    pub fn finish(&mut self) -> Option<Utf8Error> {
+        // Only one cut char can be pending
+        self.decode(true).next().and_then(Result::err)
+    }
+
+    /// Returns the number of bytes decoded since the start of the input.
+    pub fn byte_offset(&self) -> usize {
+        self.byte_offset
+    }
+
+    fn decode(&mut self, at_end: bool) -> std::vec::IntoIter<Result<char, Utf8Error>> {
+        let mut decoded = Vec::new();
+        let mut pos = 0;
+
+        while pos < self.pending.len() {
+            match decode_utf8(&self.pending[pos..], at_end) {
+                Decoded::Char(c, width) => {
+                    decoded.push(Ok(c));
+                    pos += width;
+                }
+                Decoded::Invalid(len) => {
+                    decoded.push(Err(Utf8Error {
+                        byte_offset: self.byte_offset + pos,
+                        len,
+                    }));
+                    pos += len;
+                }
+                Decoded::Incomplete => break,
+            }
+        }
+
+        self.pending.drain(..pos);
+        self.byte_offset += pos;
+        decoded.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utf8_decoder() {
+        let mut decoder = Utf8Decoder::new();
+
+        // The emoji is cut between the chunks
+        let bytes = "a😎".as_bytes();
+        assert_eq!(decoder.feed(&bytes[..3]).collect::<Vec<_>>(), vec![Ok('a')]);
+        assert_eq!(decoder.feed(&bytes[3..]).collect::<Vec<_>>(), vec![Ok('😎')]);
+        assert_eq!(decoder.byte_offset(), 5);
+
+        // Invalid sequences are reported with their offset
+        let res: Vec<_> = decoder.feed(&[0xff, b'b', 0xe2, 0x82, b'c']).collect();
+        assert_eq!(
+            res,
+            vec![
+                Err(Utf8Error { byte_offset: 5, len: 1 }),
+                Ok('b'),
+                Err(Utf8Error { byte_offset: 7, len: 2 }),
+                Ok('c')
+            ]
+        );
+
+        // A cut char at the end is invalid
+        assert_eq!(decoder.feed(&[0xe2]).count(), 0);
+        assert_eq!(decoder.finish(), Some(Utf8Error { byte_offset: 10, len: 1 }));
+        assert_eq!(decoder.finish(), None);
+    }
+}
@@ -1,18 +1,77 @@
-pub trait TryIntoChar {
-    type Error;
+/// Returns the number of bytes of the UTF-8 char starting with the given byte.
+/// Returns 0 if the byte can't start a char.
+pub fn utf8_width(first: u8) -> usize {
+    match first {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 0,
+    }
+}
+
+/// What a reader does when the input is not valid UTF-8 (or not valid in the encoding of the reader).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidUtf8Policy {
+    /// Each invalid byte is replaced by U+FFFD, and the reading continues.
+    #[default]
+    Replace,
+    /// The reading stops, and the matching functions return an `IoError::InvalidUtf8`.
+    Error,
+}
 
-    fn try_into_char(self) -> Result<char, Self::Error>;
+/// Result of the decoding of the first char of some bytes.
+#[derive(Debug, PartialEq)]
+pub enum Decoded {
+    /// A valid char, with its width in bytes.
+    Char(char, usize),
+    /// The bytes don't start with a valid char. The given number of bytes should be skipped.
+    Invalid(usize),
+    /// The char is cut: more bytes are needed to decode it.
+    Incomplete,
 }
 
-impl TryIntoChar for [u8; 4] {
-    type Error = ();
+/// Decodes the first char of the given bytes.
+/// If `at_end` is true, no more bytes will follow, so a cut char is invalid.
+///
+/// An invalid sequence is skipped as a whole, up to the first byte that can't continue it,
+/// so that it is replaced by a single U+FFFD (like `String::from_utf8_lossy`).
+pub fn decode_utf8(bytes: &[u8], at_end: bool) -> Decoded {
+    let first = match bytes.first() {
+        Some(first) => *first,
+        None if at_end => return Decoded::Invalid(0),
+        None => return Decoded::Incomplete,
+    };
+
+    // Some leading bytes would only start overlong encodings, surrogates, or chars after U+10FFFF.
+    // The range of their second byte is restricted to avoid them.
+    let second = match first {
+        0xc0 | 0xc1 | 0xf5..=0xff => return Decoded::Invalid(1),
+        0xe0 => 0xa0..=0xbf,
+        0xed => 0x80..=0x9f,
+        0xf0 => 0x90..=0xbf,
+        0xf4 => 0x80..=0x8f,
+        _ => 0x80..=0xbf,
+    };
+
+    let width = utf8_width(first);
+    if width == 0 {
+        return Decoded::Invalid(1);
+    }
 
-    fn try_into_char(self) -> Result<char, Self::Error> {
-        match std::str::from_utf8(&self) {
-            Ok(s) => Ok(s.chars().next().unwrap()),
-            Err(_) => Err(()),
+    // Check the continuation bytes that are already there, even if the char is cut
+    for i in 1..width {
+        let valid = if i == 1 { second.clone() } else { 0x80..=0xbf };
+        match bytes.get(i) {
+            Some(b) if valid.contains(b) => {}
+            Some(_) => return Decoded::Invalid(i),
+            None if at_end => return Decoded::Invalid(i),
+            None => return Decoded::Incomplete,
         }
     }
+
+    let s = std::str::from_utf8(&bytes[..width]).expect("the bytes were validated");
+    Decoded::Char(s.chars().next().unwrap(), width)
 }
 
 #[cfg(test)]
@@ -20,15 +79,33 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_try_into_char() {
-        let mut char_buf = [0u8; 4];
-        char_buf[0] = 240;
-        char_buf[1] = 159;
-        char_buf[2] = 152;
-        char_buf[3] = 142;
+    fn test_utf8_width() {
+        assert_eq!(utf8_width(b'a'), 1);
+        assert_eq!(utf8_width("é".as_bytes()[0]), 2);
+        assert_eq!(utf8_width("€".as_bytes()[0]), 3);
+        assert_eq!(utf8_width("😎".as_bytes()[0]), 4);
+
+        // Continuation bytes
+        assert_eq!(utf8_width("é".as_bytes()[1]), 0);
+    }
+
+    #[test]
+    fn test_decode_utf8() {
+        assert_eq!(decode_utf8("é!".as_bytes(), false), Decoded::Char('é', 2));
+        assert_eq!(decode_utf8(&[0xff, b'a'], false), Decoded::Invalid(1));
+
+        // A cut char is only invalid if nothing can follow
+        assert_eq!(decode_utf8(&[0xe2, 0x82], false), Decoded::Incomplete);
+        assert_eq!(decode_utf8(&[0xe2, 0x82], true), Decoded::Invalid(2));
 
-        let c = char_buf.try_into_char().unwrap();
+        // An invalid byte is detected without waiting for the rest of the char
+        assert_eq!(decode_utf8(&[0xe2, b'a'], false), Decoded::Invalid(1));
+        assert_eq!(decode_utf8(&[0xf0, 0x9f, b'a'], false), Decoded::Invalid(2));
 
-        assert_eq!(c, '😎');
+        // Surrogates, overlong encodings and chars after U+10FFFF are not valid chars
+        assert_eq!(decode_utf8(&[0xed, 0xa0, 0x80], false), Decoded::Invalid(1));
+        assert_eq!(decode_utf8(&[0xc0, 0xaf], false), Decoded::Invalid(1));
+        assert_eq!(decode_utf8(&[0xe0, 0x80, 0xaf], false), Decoded::Invalid(1));
+        assert_eq!(decode_utf8(&[0xf4, 0x90, 0x80, 0x80], false), Decoded::Invalid(1));
     }
 }
@@ -0,0 +1,131 @@
+use std::collections::VecDeque;
+
+use crate::parser_lib::{MatchStr, ParserError};
+
+/// Automaton finding the first position where one of several strings starts, reading the input once.
+///
+/// It is a trie of the strings, where each node also links to the longest suffix of its text that is in the trie.
+/// When a char doesn't continue the current node, the search follows these links instead of starting over.
+#[derive(Debug)]
+pub(crate) struct AhoCorasick {
+    /// Nodes of the trie. The first one is the root.
+    nodes: Vec<AcNode>,
+    /// Length of the longest string, in chars.
+    max_len: usize,
+}
+
+#[derive(Debug, Default)]
+struct AcNode {
+    /// Next nodes, sorted by char.
+    children: Vec<(char, usize)>,
+    /// Node of the longest proper suffix of the text of this node that is in the trie.
+    fail: usize,
+    /// Length of the longest string ending at this node, as its text or a suffix of it. 0 if there is none.
+    longest: usize,
+}
+
+impl AhoCorasick {
+    /// Builds the automaton of the strings. They must not be empty.
+    pub fn new(strings: &[&str]) -> Self {
+        let mut nodes = vec![AcNode::default()];
+        for string in strings {
+            let mut node = 0;
+            for c in string.chars() {
+                node = match nodes[node].children.binary_search_by_key(&c, |(child_c, _)| *child_c) {
+                    Ok(i) => nodes[node].children[i].1,
+                    Err(i) => {
+                        let child = nodes.len();
+                        nodes[node].children.insert(i, (c, child));
+                        nodes.push(AcNode::default());
+                        child
+                    }
+                };
+            }
+            nodes[node].longest = string.chars().count();
+        }
+
+        // The links of a node use the ones of shorter texts, so the nodes are visited by depth
+        let mut automaton = Self {
+            nodes,
+            max_len: strings.iter().map(|string| string.chars().count()).max().unwrap_or(0),
+        };
+        let mut queue: VecDeque<usize> = automaton.nodes[0].children.iter().map(|(_, child)| *child).collect();
+        while let Some(node) = queue.pop_front() {
+            for (c, child) in automaton.nodes[node].children.clone() {
+                let fail = automaton.next(automaton.nodes[node].fail, c);
+                automaton.nodes[child].fail = fail;
+                if automaton.nodes[child].longest == 0 {
+                    automaton.nodes[child].longest = automaton.nodes[fail].longest;
+                }
+                queue.push_back(child);
+            }
+        }
+        automaton
+    }
+
+    /// Returns the node reached from the given one with the char.
+    fn next(&self, mut node: usize, c: char) -> usize {
+        loop {
+            let children = &self.nodes[node].children;
+            if let Ok(i) = children.binary_search_by_key(&c, |(child_c, _)| *child_c) {
+                return children[i].1;
+            }
+            if node == 0 {
+                return 0;
+            }
+            node = self.nodes[node].fail;
+        }
+    }
+
+    /// Returns the first position at `pos` or after it where one of the strings starts, or None if there is none.
+    pub fn find<R: MatchStr>(&self, pos: usize, reader: &mut R) -> Result<Option<usize>, ParserError> {
+        let mut node = 0;
+        let mut found: Option<usize> = None;
+
+        for i in pos.. {
+            // A string ending after this char would start after the one found
+            if found.is_some_and(|start| i + 1 >= start + self.max_len) {
+                break;
+            }
+
+            let Some(c) = reader.char_at(i)? else {
+                // The end of the chars may also be caused by a read error
+                reader.check_error()?;
+                break;
+            };
+            node = self.next(node, c);
+
+            let longest = self.nodes[node].longest;
+            if longest > 0 {
+                let start = i + 1 - longest;
+                found = Some(found.map_or(start, |found| found.min(start)));
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::StringCharReader;
+
+    use super::*;
+
+    #[test]
+    fn test_aho_corasick() {
+        let automaton = AhoCorasick::new(&["he", "she", "hers", "abcd", "c"]);
+        let find = |input: &str, pos: usize| automaton.find(pos, &mut StringCharReader::new(input)).unwrap();
+
+        assert_eq!(find("ushers", 0), Some(1));
+        assert_eq!(find("xxhe", 0), Some(2));
+        assert_eq!(find("xxhe", 3), None);
+        // The string starting first is found, even if another one ends before it
+        assert_eq!(find("abcd", 0), Some(0));
+        assert_eq!(find("abce", 0), Some(2));
+        // After a failure, the suffix links keep the start of the next string
+        assert_eq!(find("shhe", 0), Some(2));
+        assert_eq!(find("abcabcd", 0), Some(2));
+        assert_eq!(find("", 0), None);
+    }
+}
@@ -0,0 +1,72 @@
+use std::{
+    fmt::{Debug, Display, Formatter},
+    sync::Arc,
+};
+
+use crate::parser_lib::{CreateParseResult, Location, MatchToken, MatcherShape, Nesting, Notation, ParseContext, ParseResult, Rewind};
+
+/// Matcher that returns true if the given matcher matches the string, without taking it (positive lookahead)
+#[derive(Debug)]
+pub struct AndMatcher<R: Debug> {
+    value: Arc<dyn MatchToken<R>>,
+}
+
+impl<R: Debug> AndMatcher<R> {
+    pub fn new(value: Arc<dyn MatchToken<R>>) -> Self {
+        Self { value }
+    }
+}
+
+impl<R: Debug + Rewind> MatchToken<R> for AndMatcher<R> {
+    fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
+        let mark = ParseContext::mark();
+        let matched = self.value.test(loc, reader)?.is_some();
+        ParseContext::rollback(mark, reader)?;
+
+        if matched {
+            // The value is only checked, so the span is of length 0 and its nodes are not kept
+            ParseResult::empty(*loc)
+        } else {
+            ParseResult::no_match()
+        }
+    }
+
+    fn fmt_notation(&self, f: &mut Formatter, notation: Notation, nesting: Nesting) -> std::fmt::Result {
+        Notation::write_group(f, nesting >= Nesting::Operand, |f| {
+            write!(f, "&")?;
+            self.value.fmt_notation(f, notation, Nesting::Operand)
+        })
+    }
+
+    fn shape(&self) -> MatcherShape<'_, R> {
+        MatcherShape::And(&self.value)
+    }
+}
+
+impl<R: Debug> Display for AndMatcher<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "(&{})", self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::{ParseInfo, Span, StrMatcher, StringCharReader};
+
+    use super::*;
+
+    #[test]
+    fn test_and_matcher() {
+        let rule = AndMatcher::new(Arc::new(StrMatcher::new("hello")));
+
+        let mut reader = StringCharReader::new("hello world");
+
+        // It matches, but takes nothing
+        let loc = Location::beginning();
+        let info = ParseInfo::new(Span::new(loc, loc), 0);
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
+        assert_eq!(rule.test(&(loc + 1), &mut reader).unwrap(), None);
+
+        assert_eq!(rule.to_string(), "(&\"hello\")");
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt::{Display, Formatter};
+
+use crate::parser_lib::{
+    CreateParseResult, Location, LocationDelta, MatchStr, MatchToken, MatcherShape, Nesting, Notation, ParseContext,
+    ParseResult,
+};
+
+/// Matcher that matches the exact text of the last child labeled with the given name, earlier in the same rule
+/// (like the closing tag of an XML element, or the terminator of a heredoc).
+///
+/// The labeled child must be in a sequence of the rule being matched, see `LabelMatcher`. The rules used by the rule
+/// don't see its labels. If nothing was captured with the label, it doesn't match.
+///
+/// Only the span of the child is captured: its text is read again from the input. If the reader doesn't keep it, like
+/// after a `TokenMatcher` consumed it from a buffered reader, the match fails with a `NoLookBehind` error. Readers that
+/// hold the whole input always keep it, and `ReadCharReader::set_look_behind` keeps the last consumed chars.
+#[derive(Debug)]
+pub struct BackReferenceMatcher {
+    label: String,
+}
+
+impl BackReferenceMatcher {
+    pub fn new(label: &str) -> Self {
+        Self { label: label.to_string() }
+    }
+}
+
+impl<R: MatchStr> MatchToken<R> for BackReferenceMatcher {
+    fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
+        let Some(captured) = ParseContext::captured(&self.label) else {
+            return ParseResult::no_match();
+        };
+
+        // The captured text is read again from the input, and compared char by char
+        let mut delta = LocationDelta::with_policy(reader.location_policy());
+        for pos in captured.start().index()..captured.end().index() {
+            let expected = reader.char_at(pos)?;
+            match (expected, reader.char_at(loc.index() + delta.len())?) {
+                (Some(expected), Some(c)) if c == expected => delta.push(c),
+                _ => {
+                    // The end of the chars may also be caused by a read error
+                    reader.check_error()?;
+                    return ParseResult::no_match();
+                }
+            }
+        }
+
+        ParseResult::matches(*loc, delta.apply_to(loc))
+    }
+
+    /// Writes the label as a special sequence, since the notations have no back-references.
+    fn fmt_notation(&self, f: &mut Formatter, _notation: Notation, _nesting: Nesting) -> std::fmt::Result {
+        write!(f, "? same as {} ?", self.label)
+    }
+
+    fn shape(&self) -> MatcherShape<'_, R> {
+        MatcherShape::Terminal
+    }
+}
+
+impl Display for BackReferenceMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "={}", self.label)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        parser_lib::{
+            GrammarBuilder, InNotation, LexError, ParseOptions, ParserError, ReadCharReader, Rule, StringCharReader,
+        },
+        choice, range, seq, word,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_back_reference_matcher() {
+        // Without capture, it doesn't match
+        let rule = BackReferenceMatcher::new("name");
+        let mut reader = StringCharReader::new("a");
+        assert_eq!(MatchToken::<StringCharReader>::test(&rule, &Location::beginning(), &mut reader).unwrap(), None);
+
+        let name = range!('a', 'z').at_least(1);
+        let element: Rule<StringCharReader> = seq!(
+            word!("<"),
+            name: name,
+            word!(">"),
+            range!('0', '9').at_least(0),
+            word!("</"),
+            Rule::back_reference("name"),
+            word!(">")
+        );
+        let matches = |input: &str| {
+            let mut reader = StringCharReader::new(input);
+            element.test(&Location::beginning(), &mut reader).unwrap().map(|info| info.len())
+        };
+
+        assert_eq!(matches("<a>12</a>"), Some(9));
+        assert_eq!(matches("<abc></abc>"), Some(11));
+        assert_eq!(matches("<abc></ab>"), None);
+        assert_eq!(matches("<ab></abc>"), None);
+        assert_eq!(matches("<a></"), None);
+
+        assert_eq!(InNotation::<StringCharReader>(&rule, Notation::Ebnf).to_string(), "? same as name ?");
+        assert_eq!(rule.to_string(), "=name");
+    }
+
+    #[test]
+    fn test_back_reference_in_rules() {
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let name = builder.define("name", range!('a', 'z').at_least(1));
+        let close = builder.define("close", seq!(word!("</"), Rule::back_reference("tag"), word!(">")));
+        let open = seq!(word!("<"), tag: name.clone(), word!(">"));
+        // The captures of a failed alternative are forgotten
+        let element = choice!(
+            seq!(open.clone(), word!("/")),
+            seq!(word!("<"), name, word!(">"), Rule::back_reference("tag")),
+            seq!(open.clone(), word!("</"), Rule::back_reference("tag"), word!(">"))
+        );
+        // A rule doesn't see the captures of its parents
+        let nested = seq!(open, close);
+        let grammar = builder.save_root(choice!(seq!(word!("e"), element), seq!(word!("n"), nested)));
+
+        let len = |input: &str| {
+            let mut reader = StringCharReader::new(input);
+            grammar.parse(&Location::beginning(), &mut reader, &ParseOptions::new()).unwrap().map(|info| info.len())
+        };
+        assert_eq!(len("e<a>/"), Some(5));
+        assert_eq!(len("e<ab>ab"), None);
+        assert_eq!(len("e<ab></ab>"), Some(10));
+        assert_eq!(len("e<ab></a>"), None);
+        assert_eq!(len("n<ab></ab>"), None);
+    }
+
+    #[test]
+    fn test_back_reference_after_token() {
+        let rule: Rule<ReadCharReader<Cursor<Vec<u8>>>> =
+            seq!(tag: range!('a', 'z').at_least(1).finish_token(), word!("-"), Rule::back_reference("tag"));
+        let reader = || ReadCharReader::new(Cursor::new(b"ab-ab".to_vec()), 16);
+
+        // The token consumed the captured text, and the reader dropped it
+        let err = rule.test(&Location::beginning(), &mut reader()).unwrap_err();
+        assert_eq!(err, ParserError::Lex(LexError::NoLookBehind(0)));
+
+        // Unless the reader keeps the last consumed chars
+        let mut reader = reader();
+        reader.set_look_behind(4);
+        assert_eq!(rule.test(&Location::beginning(), &mut reader).unwrap().map(|info| info.len()), Some(5));
+    }
+}
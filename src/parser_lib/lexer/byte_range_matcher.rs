@@ -0,0 +1,76 @@
+use std::fmt::Display;
+
+use crate::parser_lib::{CreateParseResult, Location, LocationDelta, MatchBytes, MatchToken, ParseResult};
+
+/// Matcher that returns true if the next bytes are in the given range.
+///
+/// - start: inclusive start of the range
+/// - end: inclusive end of the range
+#[derive(Debug)]
+pub struct ByteRangeMatcher {
+    start: u8,
+    end: u8,
+    /// Min number of matching bytes
+    min: usize,
+    /// Max number of matching bytes
+    /// If 0, considered as infinite
+    max: usize,
+}
+
+impl ByteRangeMatcher {
+    /// Create matcher for a single byte in range
+    pub fn new(start: u8, end: u8) -> Self {
+        Self::repeat_between(start, end, 1, 1)
+    }
+
+    /// Create matcher for a range of bytes, with a minimum number of matching bytes and infinite max
+    pub fn at_least_n(start: u8, end: u8, min: usize) -> Self {
+        Self::repeat_between(start, end, min, 0)
+    }
+
+    /// Create matcher for a range of bytes, with a minimum and maximum number of matching bytes
+    pub fn repeat_between(start: u8, end: u8, min: usize, max: usize) -> Self {
+        Self { start, end, min, max }
+    }
+}
+
+impl<R: MatchBytes> MatchToken<R> for ByteRangeMatcher {
+    fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
+        let count = reader.match_byte_range(loc.index(), self.start, self.end, self.max)?;
+
+        if count >= self.min {
+            return ParseResult::matches(*loc, LocationDelta::of_bytes(count).apply_to(loc));
+        }
+
+        ParseResult::no_match()
+    }
+}
+
+impl Display for ByteRangeMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "[{:#04x}-{:#04x}]", self.start, self.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::{ParseInfo, SliceByteReader, Span};
+
+    use super::*;
+
+    #[test]
+    fn test_byte_range_matcher() {
+        let mut reader = SliceByteReader::new(b"abc\x00");
+        let loc = Location::beginning();
+
+        let rule = ByteRangeMatcher::at_least_n(b'a', b'z', 2);
+        let info = ParseInfo::new(Span::new(loc, loc + 3), 3);
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
+        assert_eq!(rule.test(&(loc + 2), &mut reader).unwrap(), None);
+
+        let rule = ByteRangeMatcher::new(0x00, 0x1f);
+        let info = ParseInfo::new(Span::new(loc + 3, loc + 4), 1);
+        assert_eq!(rule.test(&(loc + 3), &mut reader).unwrap(), Some(info));
+        assert_eq!(rule.to_string(), "[0x00-0x1f]");
+    }
+}
@@ -0,0 +1,55 @@
+use std::fmt::Display;
+
+use crate::parser_lib::{CreateParseResult, Location, LocationDelta, MatchBytes, MatchToken, ParseResult};
+
+/// Matcher that tries to match exact bytes (like the magic number of a binary format).
+#[derive(Debug)]
+pub struct BytesMatcher {
+    value: &'static [u8],
+}
+
+impl BytesMatcher {
+    pub fn new(value: &'static [u8]) -> Self {
+        Self { value }
+    }
+}
+
+impl<R: MatchBytes> MatchToken<R> for BytesMatcher {
+    fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
+        if reader.match_bytes(loc.index(), self.value)? {
+            // In binary inputs, the location simply moves by the number of bytes
+            return ParseResult::matches(*loc, LocationDelta::of_bytes(self.value.len()).apply_to(loc));
+        }
+
+        ParseResult::no_match()
+    }
+}
+
+impl Display for BytesMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "b\"{}\"", self.value.escape_ascii())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::{ParseInfo, SliceByteReader, Span};
+
+    use super::*;
+
+    #[test]
+    fn test_bytes_matcher() {
+        let rule = BytesMatcher::new(b"\x89PNG");
+        let mut reader = SliceByteReader::new(b"\x89PNG\r\n");
+
+        let loc = Location::beginning();
+        let info = ParseInfo::new(Span::new(loc, Location::new(1, 5, 4)), 4);
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
+        assert_eq!(rule.test(&(loc + 1), &mut reader).unwrap(), None);
+
+        // The input may be too short
+        assert_eq!(rule.test(&(loc + 4), &mut reader).unwrap(), None);
+
+        assert_eq!(rule.to_string(), "b\"\\x89PNG\"");
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt::{Display, Formatter};
+
+use crate::parser_lib::{
+    CreateParseResult, Location, LocationDelta, LocationPolicy, MatchStr, MatchToken, MatcherShape, Nesting, Notation,
+    ParseResult, Span,
+};
+
+/// Matcher that tries to match a single char (like a punctuation sign).
+///
+/// It matches like a `StrMatcher` of one char, but it only reads one char and has no string to iterate.
+#[derive(Debug)]
+pub struct CharMatcher {
+    value: char,
+    /// UTF-8 encoding of the value, to describe it as a string.
+    utf8: [u8; 4],
+    /// Size of the value, computed with the default location policy.
+    delta: LocationDelta,
+}
+
+impl CharMatcher {
+    pub fn new(value: char) -> Self {
+        let mut utf8 = [0; 4];
+        value.encode_utf8(&mut utf8);
+        let mut delta = LocationDelta::new();
+        delta.push(value);
+        Self { value, utf8, delta }
+    }
+
+    /// Returns the value as a string.
+    fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.utf8[..self.value.len_utf8()]).expect("the bytes are encoded from a char")
+    }
+
+    /// Computes the delta of the value for the given policy.
+    fn delta_for(&self, policy: LocationPolicy) -> LocationDelta {
+        if policy == LocationPolicy::default() {
+            return self.delta;
+        }
+
+        let mut delta = LocationDelta::with_policy(policy);
+        delta.push(self.value);
+        delta
+    }
+}
+
+impl<R: MatchStr> MatchToken<R> for CharMatcher {
+    fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
+        match reader.char_at(loc.index())? {
+            Some(c) if c == self.value => {
+                let delta = self.delta_for(reader.location_policy());
+                ParseResult::new(Span::new(*loc, delta.apply_to(loc)), 1)
+            }
+            Some(_) => ParseResult::no_match(),
+            None => {
+                // The end of the chars may also be caused by a read error
+                reader.check_error()?;
+                ParseResult::no_match()
+            }
+        }
+    }
+
+    fn fmt_notation(&self, f: &mut Formatter, _notation: Notation, _nesting: Nesting) -> std::fmt::Result {
+        Notation::write_str(f, self.as_str())
+    }
+
+    fn shape(&self) -> MatcherShape<'_, R> {
+        MatcherShape::Literal(self.as_str())
+    }
+}
+
+impl Display for CharMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "\"{}\"", match self.value {
+            '\n' => "\\n",
+            '\r' => "\\r",
+            '\t' => "\\t",
+            '\0' => "\\0",
+            '"' => "\\\"",
+            '\\' => "\\\\",
+            _ => self.as_str(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::{ParseInfo, StrMatcher, StringCharReader};
+
+    use super::*;
+
+    #[test]
+    fn test_char_matcher() {
+        let rule = CharMatcher::new('(');
+        let mut reader = StringCharReader::new("(é\n");
+
+        let loc = Location::beginning();
+        let info = ParseInfo::new(Span::new(loc, loc + 1), 1);
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
+        assert_eq!(rule.test(&(loc + 1), &mut reader).unwrap(), None);
+        assert_eq!(rule.test(&(loc + 3), &mut reader).unwrap(), None);
+
+        // The locations are the same as with a string matcher
+        for (value, start) in [('é', loc + 1), ('\n', Location::with_byte_offset(1, 3, 2, 3))] {
+            let info = StrMatcher::new(value.to_string()).test(&start, &mut reader).unwrap();
+            assert_eq!(CharMatcher::new(value).test(&start, &mut reader).unwrap(), info);
+        }
+
+        assert_eq!(rule.to_string(), "\"(\"");
+        assert_eq!(CharMatcher::new('\n').to_string(), "\"\\n\"");
+        assert!(matches!(MatchToken::<StringCharReader>::shape(&rule), MatcherShape::Literal("(")));
+    }
+}
@@ -1,33 +1,58 @@
-use std::{fmt::Display, rc::Rc};
+use std::{
+    fmt::{Debug, Display, Formatter},
+    sync::Arc,
+};
 
-use crate::parser_lib::{CreateParseResult, Location, MatchStr, MatchToken, ParseResult};
+use crate::parser_lib::{Location, MatchToken, MatcherShape, Nesting, Notation, ParseContext, ParseInfo, ParseResult, Span};
 
 /// Matcher that tries to match one of the given matchers
+///
+/// The result gives the index of the matched alternative, see `ParseInfo::alternative`.
 #[derive(Debug)]
-pub struct ChoiceMatcher<R: MatchStr> {
-    children: Vec<Rc<dyn MatchToken<R>>>,
+pub struct ChoiceMatcher<R: Debug> {
+    children: Vec<Arc<dyn MatchToken<R>>>,
 }
 
-impl<R: MatchStr> ChoiceMatcher<R> {
-    pub fn new(children: Vec<Rc<dyn MatchToken<R>>>) -> Self {
+impl<R: Debug> ChoiceMatcher<R> {
+    pub fn new(children: Vec<Arc<dyn MatchToken<R>>>) -> Self {
         Self { children }
     }
 }
 
-impl<R: MatchStr> MatchToken<R> for ChoiceMatcher<R> {
+impl<R: Debug> MatchToken<R> for ChoiceMatcher<R> {
     fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
         // Try to match the first child. If it doesn't work, start from the beginning and try the second, and so on.
-        for child in &self.children {
+        for (i, child) in self.children.iter().enumerate() {
+            if i > 0 {
+                ParseContext::backtrack(loc)?;
+            }
             if let Some(res) = child.test(loc, reader)? {
-                return ParseResult::matches(*loc, *res.span().end());
+                let end = *res.span().end();
+                return Ok(Some(ParseInfo::new(Span::new(*loc, end), end.index() - loc.index()).with_alternative(i)));
             }
         }
 
-        ParseResult::no_match()
+        Ok(None)
+    }
+
+    fn fmt_notation(&self, f: &mut Formatter, notation: Notation, nesting: Nesting) -> std::fmt::Result {
+        Notation::write_group(f, nesting >= Nesting::Item, |f| {
+            for (i, child) in self.children.iter().enumerate() {
+                if i > 0 {
+                    write!(f, "{}", notation.choice_separator())?;
+                }
+                child.fmt_notation(f, notation, Nesting::Alternative)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn shape(&self) -> MatcherShape<'_, R> {
+        MatcherShape::Choice(&self.children)
     }
 }
 
-impl<R: MatchStr> Display for ChoiceMatcher<R> {
+impl<R: Debug> Display for ChoiceMatcher<R> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         // Write children seperated by "|"
         write!(
@@ -43,22 +68,23 @@ impl<R: MatchStr> Display for ChoiceMatcher<R> {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
-    use crate::parser_lib::{ParseInfo, Span, StrMatcher, StringCharReader};
+    use crate::parser_lib::{StrMatcher, StringCharReader};
 
     use super::*;
 
     #[test]
     fn test_choice_matcher() {
         let rule = ChoiceMatcher::new(vec![
-            Rc::new(StrMatcher::new("hey ")),
-            Rc::new(StrMatcher::new("world")),
+            Arc::new(StrMatcher::new("hey ")),
+            Arc::new(StrMatcher::new("world")),
         ]);
 
         // First matches but not the second
         let mut reader = StringCharReader::new("hey you");
 
-        let info = ParseInfo::new(Span::new(Location::beginning(), Location::new(1, 5, 4)), 4);
+        let info = ParseInfo::new(Span::new(Location::beginning(), Location::new(1, 5, 4)), 4).with_alternative(0);
         let loc = Location::beginning();
         assert_eq!(rule.test(&loc, &mut reader).is_ok(), true);
         assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
@@ -66,7 +92,7 @@ mod tests {
         // Second matches but not the first
         reader = StringCharReader::new("world you");
 
-        let info = ParseInfo::new(Span::new(Location::beginning(), Location::new(1, 6, 5)), 5);
+        let info = ParseInfo::new(Span::new(Location::beginning(), Location::new(1, 6, 5)), 5).with_alternative(1);
         let loc = Location::beginning();
         assert_eq!(rule.test(&loc, &mut reader).is_ok(), true);
         assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
@@ -80,7 +106,7 @@ mod tests {
         // If both are one after the other, it should only match the first (its not a repetition, just a choice)
         reader = StringCharReader::new("hey world");
 
-        let info = ParseInfo::new(Span::new(Location::beginning(), Location::new(1, 5, 4)), 4);
+        let info = ParseInfo::new(Span::new(Location::beginning(), Location::new(1, 5, 4)), 4).with_alternative(0);
         let loc = Location::beginning();
         assert_eq!(rule.test(&loc, &mut reader).is_ok(), true);
         assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
@@ -0,0 +1,432 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{Debug, Display, Formatter},
+    sync::Arc,
+};
+
+use crate::parser_lib::{
+    CreateParseResult, InNotation, Location, LocationDelta, MatchStr, MatchToken, MatcherShape, Nesting, Notation,
+    ParseResult, ParserError, Span, TokenType,
+};
+
+/// Maximum number of states of a compiled automaton, since the compilation can grow exponentially.
+const MAX_STATES: usize = 10_000;
+
+/// Error returned when a matcher can't be compiled to a DFA.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DfaError {
+    /// The matcher is not regular (like a reference or a lookahead). Contains its notation.
+    NotRegular(String),
+    /// The automaton would have more than the given number of states.
+    TooManyStates(usize),
+}
+
+impl Display for DfaError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            DfaError::NotRegular(matcher) => write!(f, "`{}` can't be compiled to a DFA.", matcher),
+            DfaError::TooManyStates(max) => write!(f, "The DFA would have more than {} states.", max),
+        }
+    }
+}
+
+impl Error for DfaError {}
+
+/// Deterministic finite automaton compiled from the regular matchers (strings, ranges, sequences, choices,
+/// repetitions), to match tokens by reading each char once.
+///
+/// Like a lexer generator, it finds the longest match, and the first token wins when several match the same text.
+/// This differs from the combinators, which never give back what a repetition or a choice matched:
+/// `seq!(range!('a', 'z').at_least(0), word!("s"))` never matches, but its DFA matches `cats`.
+#[derive(Debug, Clone)]
+pub struct Dfa {
+    /// States of the automaton. The first one is the start.
+    states: Vec<DfaState>,
+}
+
+#[derive(Debug, Clone)]
+struct DfaState {
+    /// Transitions for the chars between start and end (inclusive, as u32), sorted and disjoint.
+    transitions: Vec<(u32, u32, usize)>,
+    /// Index of the token matched when the automaton stops here.
+    accept: Option<usize>,
+    /// Other tokens matching the same text, which lose against the accepted one.
+    ties: Vec<usize>,
+}
+
+impl Dfa {
+    /// Compiles the given matchers into a single automaton, where the token index is the index of the matcher.
+    pub fn compile<R>(matchers: &[&dyn MatchToken<R>]) -> Result<Self, DfaError> {
+        let mut nfa = Nfa::default();
+        let root = nfa.add();
+        for (token, matcher) in matchers.iter().enumerate() {
+            let start = nfa.add();
+            nfa.states[root].epsilon.push(start);
+            let end = nfa.build(*matcher, start)?;
+            nfa.states[end].accept.get_or_insert(token);
+        }
+        nfa.to_dfa()
+    }
+
+    /// Compiles the matchers of the given token types.
+    pub fn for_tokens<R: MatchStr>(token_types: &[TokenType<R>]) -> Result<Self, DfaError> {
+        let matchers: Vec<&dyn MatchToken<R>> = token_types.iter().map(|t| t.matcher().as_ref()).collect();
+        Self::compile(&matchers)
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Returns the pairs of tokens that can match the same non-empty text, sorted. The first token of a pair wins.
+    pub fn overlaps(&self) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        // The start state is only reached by the empty text
+        for state in &self.states[1..] {
+            let tokens: Vec<usize> = state.accept.into_iter().chain(state.ties.iter().copied()).collect();
+            for (i, first) in tokens.iter().enumerate() {
+                pairs.extend(tokens[i + 1..].iter().map(|second| (*first, *second)));
+            }
+        }
+        pairs.sort_unstable();
+        pairs.dedup();
+        pairs
+    }
+
+    /// Returns the token matching the longest input at the position, with the delta covered by its chars.
+    pub fn longest_match<R: MatchStr>(
+        &self,
+        pos: usize,
+        reader: &mut R,
+    ) -> Result<Option<(usize, LocationDelta)>, ParserError> {
+        let mut state = &self.states[0];
+        let mut delta = LocationDelta::with_policy(reader.location_policy());
+        let mut longest = state.accept.map(|token| (token, delta));
+
+        for i in pos.. {
+            let Some(c) = reader.char_at(i)? else {
+                // The end of the chars may also be caused by a read error
+                reader.check_error()?;
+                break;
+            };
+
+            let code = c as u32;
+            let next = state
+                .transitions
+                .binary_search_by(|(start, end, _)| {
+                    if *end < code {
+                        std::cmp::Ordering::Less
+                    } else if *start > code {
+                        std::cmp::Ordering::Greater
+                    } else {
+                        std::cmp::Ordering::Equal
+                    }
+                })
+                .map(|t| state.transitions[t].2);
+            let Ok(next) = next else {
+                break;
+            };
+
+            state = &self.states[next];
+            delta.push(c);
+            if let Some(token) = state.accept {
+                longest = Some((token, delta));
+            }
+        }
+
+        Ok(longest)
+    }
+}
+
+/// Automaton with epsilon transitions, built from the matchers before being made deterministic.
+#[derive(Debug, Default)]
+struct Nfa {
+    states: Vec<NfaState>,
+}
+
+#[derive(Debug, Default)]
+struct NfaState {
+    epsilon: Vec<usize>,
+    transitions: Vec<(u32, u32, usize)>,
+    accept: Option<usize>,
+}
+
+impl Nfa {
+    fn add(&mut self) -> usize {
+        self.states.push(NfaState::default());
+        self.states.len() - 1
+    }
+
+    fn add_transition(&mut self, from: usize, start: char, end: char) -> usize {
+        let to = self.add();
+        self.states[from].transitions.push((start as u32, end as u32, to));
+        to
+    }
+
+    /// Adds the states matching the matcher from the start state. Returns the state reached at the end.
+    fn build<R>(&mut self, matcher: &dyn MatchToken<R>, start: usize) -> Result<usize, DfaError> {
+        match matcher.shape() {
+            MatcherShape::Literal(value) => Ok(value.chars().fold(start, |state, c| self.add_transition(state, c, c))),
+            MatcherShape::Keywords(words) => {
+                let end = self.add();
+                for word in words {
+                    let alternative = self.add();
+                    self.states[start].epsilon.push(alternative);
+                    let word_end = word.chars().fold(alternative, |state, c| self.add_transition(state, c, c));
+                    self.states[word_end].epsilon.push(end);
+                }
+                Ok(end)
+            }
+            MatcherShape::Range { start: first, end: last, min, max } => {
+                self.repeat(start, min, max, &mut |nfa, state| Ok(nfa.add_transition(state, first, last)))
+            }
+            MatcherShape::Sequence(children) => children
+                .iter()
+                .try_fold(start, |state, child| self.build(child.as_ref(), state)),
+            MatcherShape::Choice(children) => {
+                let end = self.add();
+                for child in children {
+                    let alternative = self.add();
+                    self.states[start].epsilon.push(alternative);
+                    let child_end = self.build(child.as_ref(), alternative)?;
+                    self.states[child_end].epsilon.push(end);
+                }
+                Ok(end)
+            }
+            MatcherShape::Repetition { value, min, max } => {
+                self.repeat(start, min, max, &mut |nfa, state| nfa.build(value.as_ref(), state))
+            }
+            MatcherShape::Optional(value) => self.repeat(start, 0, 1, &mut |nfa, state| nfa.build(value.as_ref(), state)),
+            MatcherShape::Wrapper(value) | MatcherShape::Label { value, .. } => self.build(value.as_ref(), start),
+            MatcherShape::Terminal
+            | MatcherShape::Reference(_)
+            | MatcherShape::Not(_)
+            | MatcherShape::And(_)
+            | MatcherShape::Until { .. }
+            | MatcherShape::Error(_) => Err(DfaError::NotRegular(InNotation(matcher, Notation::Ebnf).to_string())),
+        }
+    }
+
+    /// Adds an item repeated between min and max times (if max is 0, there is no limit).
+    fn repeat(
+        &mut self,
+        start: usize,
+        min: usize,
+        max: usize,
+        item: &mut dyn FnMut(&mut Self, usize) -> Result<usize, DfaError>,
+    ) -> Result<usize, DfaError> {
+        let mut state = start;
+        for _ in 0..min {
+            state = item(self, state)?;
+        }
+
+        if max == 0 {
+            // Loop on a new state, so that the loop doesn't go back before the required items
+            let head = self.add();
+            self.states[state].epsilon.push(head);
+            let end = item(self, head)?;
+            self.states[end].epsilon.push(head);
+            return Ok(head);
+        }
+
+        for _ in min..max {
+            let skip = self.add();
+            let end = item(self, state)?;
+            self.states[state].epsilon.push(skip);
+            self.states[end].epsilon.push(skip);
+            state = skip;
+        }
+        Ok(state)
+    }
+
+    /// Returns the states reachable from the given ones with epsilon transitions, sorted.
+    fn closure(&self, states: impl IntoIterator<Item = usize>) -> Vec<usize> {
+        let mut reached = vec![false; self.states.len()];
+        let mut stack: Vec<usize> = states.into_iter().collect();
+        while let Some(state) = stack.pop() {
+            if !reached[state] {
+                reached[state] = true;
+                stack.extend(&self.states[state].epsilon);
+            }
+        }
+        (0..self.states.len()).filter(|s| reached[*s]).collect()
+    }
+
+    /// Makes the automaton deterministic with the subset construction.
+    fn to_dfa(&self) -> Result<Dfa, DfaError> {
+        let start = self.closure([0]);
+        let mut ids: HashMap<Vec<usize>, usize> = HashMap::from([(start.clone(), 0)]);
+        let mut subsets = vec![start];
+        let mut states = Vec::new();
+
+        while let Some(subset) = subsets.get(states.len()).cloned() {
+            let transitions: Vec<(u32, u32, usize)> = subset
+                .iter()
+                .flat_map(|s| self.states[*s].transitions.iter().copied())
+                .collect();
+
+            // Split the chars into intervals where the same transitions apply
+            let mut bounds: Vec<u32> = transitions.iter().flat_map(|(start, end, _)| [*start, end + 1]).collect();
+            bounds.sort_unstable();
+            bounds.dedup();
+
+            let mut dfa_transitions: Vec<(u32, u32, usize)> = Vec::new();
+            for window in bounds.windows(2) {
+                let (start, end) = (window[0], window[1] - 1);
+                let targets = transitions
+                    .iter()
+                    .filter(|(t_start, t_end, _)| *t_start <= start && end <= *t_end)
+                    .map(|(_, _, target)| *target);
+                let target = self.closure(targets);
+                if target.is_empty() {
+                    continue;
+                }
+
+                let id = match ids.get(&target) {
+                    Some(id) => *id,
+                    None => {
+                        if subsets.len() >= MAX_STATES {
+                            return Err(DfaError::TooManyStates(MAX_STATES));
+                        }
+                        ids.insert(target.clone(), subsets.len());
+                        subsets.push(target);
+                        subsets.len() - 1
+                    }
+                };
+
+                // Merge with the previous interval if it is contiguous and goes to the same state
+                match dfa_transitions.last_mut() {
+                    Some((_, last_end, last_id)) if *last_end + 1 == start && *last_id == id => *last_end = end,
+                    _ => dfa_transitions.push((start, end, id)),
+                }
+            }
+
+            let mut accepts: Vec<usize> = subset.iter().filter_map(|s| self.states[*s].accept).collect();
+            accepts.sort_unstable();
+            accepts.dedup();
+            states.push(DfaState {
+                transitions: dfa_transitions,
+                accept: accepts.first().copied(),
+                ties: accepts.into_iter().skip(1).collect(),
+            });
+        }
+
+        Ok(Dfa { states })
+    }
+}
+
+/// Matcher using a DFA compiled from another matcher, see `Dfa`.
+#[derive(Debug)]
+pub struct DfaMatcher<R: Debug> {
+    dfa: Dfa,
+    /// Matcher the DFA was compiled from, used to display it.
+    ///
+    /// It is not given as the shape, since rebuilding the matcher from it would change how it matches.
+    source: Arc<dyn MatchToken<R>>,
+}
+
+impl<R: Debug> DfaMatcher<R> {
+    pub fn new(source: Arc<dyn MatchToken<R>>) -> Result<Self, DfaError> {
+        let dfa = Dfa::compile(&[source.as_ref()])?;
+        Ok(Self { dfa, source })
+    }
+
+    pub fn dfa(&self) -> &Dfa {
+        &self.dfa
+    }
+}
+
+impl<R: MatchStr> MatchToken<R> for DfaMatcher<R> {
+    fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
+        match self.dfa.longest_match(loc.index(), reader)? {
+            Some((_, delta)) => ParseResult::new(Span::new(*loc, delta.apply_to(loc)), delta.len()),
+            None => ParseResult::no_match(),
+        }
+    }
+
+    fn fmt_notation(&self, f: &mut Formatter, notation: Notation, nesting: Nesting) -> std::fmt::Result {
+        self.source.fmt_notation(f, notation, nesting)
+    }
+}
+
+impl<R: Debug> Display for DfaMatcher<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::{Rule, StringCharReader};
+    use crate::{choice, range, seq, word};
+
+    use super::*;
+
+    #[test]
+    fn test_dfa() {
+        let ident: Rule<StringCharReader> = seq!(
+            choice!(range!('a', 'z'), word!("_")),
+            choice!(range!('a', 'z'), range!('0', '9'), word!("_")).at_least(0)
+        );
+        let number = choice!(word!("0x"), word!("0b")).optional() + range!('0', '9').at_least(1);
+        let keyword = choice!(word!("if"), word!("else"));
+        let dfa = Dfa::compile(&[&keyword, &ident, &number, &word!("=="), &word!("=")]).unwrap();
+
+        let longest = |input: &str| {
+            let mut reader = StringCharReader::new(input);
+            dfa.longest_match(0, &mut reader).unwrap().map(|(token, delta)| (token, delta.len()))
+        };
+        assert_eq!(longest("if x"), Some((0, 2)));
+        // The longest match wins over the first token
+        assert_eq!(longest("iffy"), Some((1, 4)));
+        assert_eq!(longest("_a1 b"), Some((1, 3)));
+        assert_eq!(longest("0x12"), Some((2, 4)));
+        assert_eq!(longest("0y"), Some((2, 1)));
+        assert_eq!(longest("==="), Some((3, 2)));
+        assert_eq!(longest("+"), None);
+        assert_eq!(longest(""), None);
+        // The keywords are also identifiers
+        assert_eq!(dfa.overlaps(), [(0, 1)]);
+
+        // Bounded repetitions
+        let dfa = Dfa::compile::<StringCharReader>(&[&range!('a', 'b').repeat_between(2, 3)]).unwrap();
+        let mut reader = StringCharReader::new("abab");
+        assert_eq!(dfa.longest_match(0, &mut reader).unwrap().map(|(_, d)| d.len()), Some(3));
+        let mut reader = StringCharReader::new("a");
+        assert_eq!(dfa.longest_match(0, &mut reader).unwrap(), None);
+
+        // Keyword sets are choices between their strings
+        let keywords: Rule<StringCharReader> = Rule::keywords(&["in", "int"]);
+        let dfa = Dfa::compile(&[&keywords]).unwrap();
+        let mut reader = StringCharReader::new("into");
+        assert_eq!(dfa.longest_match(0, &mut reader).unwrap().map(|(_, d)| d.len()), Some(3));
+
+        // Only regular matchers can be compiled
+        let not_regular: Rule<StringCharReader> = seq!(word!("a"), word!("b").not());
+        assert_eq!(
+            Dfa::compile(&[&not_regular]).unwrap_err(),
+            DfaError::NotRegular("!\"b\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_dfa_matcher() {
+        // The DFA gives back chars to the end of the sequence, unlike the combinators
+        let plural: Rule<StringCharReader> = seq!(range!('a', 'z').at_least(0), word!("s"));
+        let matcher = DfaMatcher::new(plural.matcher().clone()).unwrap();
+        let loc = Location::beginning();
+
+        let mut reader = StringCharReader::new("cats!");
+        assert_eq!(plural.test(&loc, &mut reader).unwrap(), None);
+        assert_eq!(matcher.test(&loc, &mut reader).unwrap().unwrap().len(), 4);
+        assert_eq!(matcher.to_string(), plural.to_string());
+
+        // Newlines move the end location
+        let lines: Rule<StringCharReader> = choice!(word!("\n"), word!("x")).at_least(1);
+        let matcher = DfaMatcher::new(lines.matcher().clone()).unwrap();
+        let mut reader = StringCharReader::new("x\nx");
+        let info = matcher.test(&loc, &mut reader).unwrap().unwrap();
+        assert_eq!(*info.span().end(), Location::new(2, 2, 3));
+    }
+}
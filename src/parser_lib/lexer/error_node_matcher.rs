@@ -0,0 +1,108 @@
+use std::{
+    fmt::{Debug, Display, Formatter},
+    sync::Arc,
+};
+
+use crate::parser_lib::{
+    Location, MatchStr, MatchToken, MatcherShape, Nesting, Notation, ParseContext, ParseResult, UntilMatcher,
+};
+
+/// Matcher that consumes garbled input up to a sync point, like the `;` ending a statement, as an error node.
+///
+/// It matches at least one char, and stops before the sync point, or at the end of the input. The text is put in a
+/// node of the rule `ERROR_RULE` when a tree is built, so that the passes using the tree can skip it and go on. The
+/// sync point is not consumed: the rule after the error usually matches it. The number of errors can be limited, see
+/// `ParseOptions::with_max_errors`. It doesn't match if the recovery is disabled, see `ParseOptions::with_recovery`.
+#[derive(Debug)]
+pub struct ErrorNodeMatcher<R: MatchStr> {
+    sync: Arc<dyn MatchToken<R>>,
+    garbled: UntilMatcher<R>,
+}
+
+/// Name of the rule of the error nodes.
+pub const ERROR_RULE: &str = "error";
+
+impl<R: MatchStr> ErrorNodeMatcher<R> {
+    pub fn new(sync: Arc<dyn MatchToken<R>>) -> Self {
+        Self { garbled: UntilMatcher::new(sync.clone(), 1), sync }
+    }
+}
+
+impl<R: MatchStr> MatchToken<R> for ErrorNodeMatcher<R> {
+    fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
+        if !ParseContext::recovers() {
+            return Ok(None);
+        }
+        ParseContext::rule(ERROR_RULE, loc, || {
+            let result = self.garbled.test(loc, reader)?;
+            if result.is_some() {
+                ParseContext::error(loc)?;
+            }
+            Ok(result)
+        })
+    }
+
+    fn fmt_notation(&self, f: &mut Formatter, notation: Notation, nesting: Nesting) -> std::fmt::Result {
+        self.garbled.fmt_notation(f, notation, nesting)
+    }
+
+    fn shape(&self) -> MatcherShape<'_, R> {
+        MatcherShape::Error(&self.sync)
+    }
+}
+
+impl<R: MatchStr> Display for ErrorNodeMatcher<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "error({})", self.garbled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser_lib::{Grammar, GrammarBuilder, ParseInfo, Rule, Span, StrMatcher, StringCharReader},
+        range, seq, word,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_error_node_matcher() {
+        let rule = ErrorNodeMatcher::new(Arc::new(StrMatcher::new(";")));
+        assert_eq!(rule.to_string(), "error((!\";\")+)");
+
+        // Stops before the sync point, or at the end of the input
+        let loc = Location::beginning();
+        let mut reader = StringCharReader::new("a b;c");
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(ParseInfo::new(Span::new(loc, loc + 3), 3)));
+        let mut reader = StringCharReader::new("abc");
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(ParseInfo::new(Span::new(loc, loc + 3), 3)));
+
+        // An error has at least one char
+        let mut reader = StringCharReader::new(";");
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_recover_with() {
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let number = builder.define("number", range!('0', '9').at_least(1));
+        let statement = builder.define("statement", seq!(number, word!(";")));
+        // A statement that doesn't parse is skipped until its `;`
+        let recovered = seq!(statement.recover_with(&word!(";")), word!(";").optional());
+        let grammar: Grammar<StringCharReader> = builder.save_root(recovered.at_least(0));
+
+        let source = "1;x 2;3;?";
+        let tree = grammar.parse_tree(&Location::beginning(), &mut StringCharReader::new(source)).unwrap().unwrap();
+        assert_eq!(
+            tree.to_sexp(source),
+            r#"(root (statement (number "1")) (error "x 2") (statement (number "3")) (error "?"))"#
+        );
+        let errors: Vec<&str> = tree.errors().iter().map(|error| error.text(source)).collect();
+        assert_eq!(errors, ["x 2", "?"]);
+        assert!(!tree.children()[0].is_error());
+
+        // The notation shows the text that an error can take
+        assert_eq!(Rule::<StringCharReader>::error(&word!(";")).to_string(), "error((!\";\")+)");
+    }
+}
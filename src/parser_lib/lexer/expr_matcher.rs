@@ -0,0 +1,378 @@
+use std::{
+    fmt::{Debug, Display, Formatter},
+    sync::Arc,
+};
+
+use crate::parser_lib::{
+    ChoiceMatcher, CreateParseResult, Location, MatchToken, Nesting, Notation, OptionalMatcher,
+    ParseContext, ParseResult, ParserError, RepetitionMatcher, Rewind, SequentialMatcher, Span,
+};
+
+/// Side on which consecutive operators of the same precedence are grouped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    /// `a - b - c` is `(a - b) - c`
+    Left,
+    /// `a ^ b ^ c` is `a ^ (b ^ c)`
+    Right,
+}
+
+/// Position of an operator relative to its operands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fixity {
+    /// Before its operand, like `-a`
+    Prefix,
+    /// Between its operands, like `a + b`
+    Infix(Associativity),
+    /// After its operand, like `a!`
+    Postfix,
+}
+
+/// Operator of an expression.
+#[derive(Debug)]
+pub struct Operator<R: Debug> {
+    matcher: Arc<dyn MatchToken<R>>,
+    fixity: Fixity,
+    /// Operators with a higher precedence bind tighter.
+    precedence: u32,
+}
+
+impl<R: Debug> Operator<R> {
+    pub fn new(matcher: Arc<dyn MatchToken<R>>, fixity: Fixity, precedence: u32) -> Self {
+        Self { matcher, fixity, precedence }
+    }
+
+    pub fn fixity(&self) -> Fixity {
+        self.fixity
+    }
+
+    pub fn precedence(&self) -> u32 {
+        self.precedence
+    }
+}
+
+/// Tree of an expression matched by an `ExprMatcher`.
+///
+/// Operators are identified by their index in the list given to the matcher, and the span of the matched operator.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprTree {
+    Operand(Span),
+    Prefix {
+        operator: usize,
+        span: Span,
+        operand: Box<ExprTree>,
+    },
+    Infix {
+        operator: usize,
+        span: Span,
+        left: Box<ExprTree>,
+        right: Box<ExprTree>,
+    },
+    Postfix {
+        operator: usize,
+        span: Span,
+        operand: Box<ExprTree>,
+    },
+}
+
+impl ExprTree {
+    /// Returns the span of the whole expression.
+    pub fn span(&self) -> Span {
+        match self {
+            ExprTree::Operand(span) => span.clone(),
+            ExprTree::Prefix { span, operand, .. } => Span::new(*span.start(), *operand.span().end()),
+            ExprTree::Infix { left, right, .. } => Span::new(*left.span().start(), *right.span().end()),
+            ExprTree::Postfix { span, operand, .. } => Span::new(*operand.span().start(), *span.end()),
+        }
+    }
+
+    /// Writes the tree as a S-expression, using the text of the source: `1 + 2 * 3` is `(+ 1 (* 2 3))`.
+    pub fn to_sexp(&self, source: &str) -> String {
+        let text = |span: &Span| -> String {
+            let start = span.start().index();
+            source.chars().skip(start).take(span.end().index() - start).collect()
+        };
+
+        match self {
+            ExprTree::Operand(span) => text(span),
+            ExprTree::Prefix { span, operand, .. } | ExprTree::Postfix { span, operand, .. } => {
+                format!("({} {})", text(span), operand.to_sexp(source))
+            }
+            ExprTree::Infix { span, left, right, .. } => {
+                format!("({} {} {})", text(span), left.to_sexp(source), right.to_sexp(source))
+            }
+        }
+    }
+}
+
+/// Matcher of expressions made of operands and operators, with precedence and associativity.
+///
+/// It uses precedence climbing (a Pratt parser): this avoids writing one rule per precedence level, and
+/// `parse` returns the tree of the expression with the operators correctly nested.
+/// Operators are tried in the order they are given, so longer ones should come first (`**` before `*`).
+#[derive(Debug)]
+pub struct ExprMatcher<R: Debug> {
+    operand: Arc<dyn MatchToken<R>>,
+    operators: Vec<Operator<R>>,
+    /// Ignored between operands and operators (whitespace, for example).
+    padding: Option<Arc<dyn MatchToken<R>>>,
+    /// Same language, without the precedence. Used to export the matcher.
+    ///
+    /// It is not given as its shape, otherwise the matcher tree could be rebuilt without the precedence.
+    flat: Arc<dyn MatchToken<R>>,
+}
+
+impl<R: 'static + Debug + Rewind> ExprMatcher<R> {
+    pub fn new(operand: Arc<dyn MatchToken<R>>, operators: Vec<Operator<R>>, padding: Option<Arc<dyn MatchToken<R>>>) -> Self {
+        let flat = Self::flatten(&operand, &operators, &padding);
+        Self { operand, operators, padding, flat }
+    }
+
+    /// Builds `(prefix pad)* operand (pad postfix)* (pad infix pad (prefix pad)* operand (pad postfix)*)*`.
+    fn flatten(
+        operand: &Arc<dyn MatchToken<R>>,
+        operators: &[Operator<R>],
+        padding: &Option<Arc<dyn MatchToken<R>>>,
+    ) -> Arc<dyn MatchToken<R>> {
+        let pad: Option<Arc<dyn MatchToken<R>>> = padding
+            .as_ref()
+            .map(|padding| Arc::new(OptionalMatcher::new(padding.clone())) as Arc<dyn MatchToken<R>>);
+        let seq = |items: Vec<Option<Arc<dyn MatchToken<R>>>>| -> Arc<dyn MatchToken<R>> {
+            let mut items: Vec<_> = items.into_iter().flatten().collect();
+            if items.len() == 1 {
+                items.remove(0)
+            } else {
+                Arc::new(SequentialMatcher::new(items))
+            }
+        };
+        let choice = |is_fixity: &dyn Fn(Fixity) -> bool| -> Option<Arc<dyn MatchToken<R>>> {
+            let mut children: Vec<_> = operators
+                .iter()
+                .filter(|op| is_fixity(op.fixity))
+                .map(|op| op.matcher.clone())
+                .collect();
+            match children.len() {
+                0 => None,
+                1 => Some(children.remove(0)),
+                _ => Some(Arc::new(ChoiceMatcher::new(children))),
+            }
+        };
+        let many = |value: Arc<dyn MatchToken<R>>| Some(Arc::new(RepetitionMatcher::new(value, 0)) as Arc<dyn MatchToken<R>>);
+
+        let prefixes = choice(&|fixity| fixity == Fixity::Prefix).and_then(|op| many(seq(vec![Some(op), pad.clone()])));
+        let postfixes = choice(&|fixity| fixity == Fixity::Postfix).and_then(|op| many(seq(vec![pad.clone(), Some(op)])));
+        let unit = seq(vec![prefixes, Some(operand.clone()), postfixes]);
+
+        match choice(&|fixity| matches!(fixity, Fixity::Infix(_))) {
+            Some(infix) => {
+                let rest = seq(vec![pad.clone(), Some(infix), pad.clone(), Some(unit.clone())]);
+                seq(vec![Some(unit), many(rest)])
+            }
+            None => unit,
+        }
+    }
+}
+
+impl<R: Debug + Rewind> ExprMatcher<R> {
+    /// Matches an expression and returns its tree.
+    pub fn parse(&self, loc: &Location, reader: &mut R) -> Result<Option<ExprTree>, ParserError> {
+        self.parse_min(loc, reader, 0)
+    }
+
+    /// Matches an expression containing only operators of at least the given precedence, except in prefixes.
+    fn parse_min(&self, loc: &Location, reader: &mut R, min: u32) -> Result<Option<ExprTree>, ParserError> {
+        // Operands of operators are nested like rules
+        ParseContext::nested(loc, || self.climb(loc, reader, min))
+    }
+
+    /// Matches an operand, and the operators following it while they have at least the given precedence.
+    fn climb(&self, loc: &Location, reader: &mut R, min: u32) -> Result<Option<ExprTree>, ParserError> {
+        let Some(mut left) = self.parse_unary(loc, reader)? else {
+            return Ok(None);
+        };
+
+        'climb: loop {
+            // The padding and the operators are only kept if an operator matches, with its operand if it needs one
+            let mark = ParseContext::mark();
+            let loc = self.skip(left.span().end(), reader)?;
+            let operator_mark = ParseContext::mark();
+
+            for (i, op) in self.operators.iter().enumerate() {
+                if op.precedence < min {
+                    continue;
+                }
+                match op.fixity {
+                    Fixity::Prefix => {}
+                    Fixity::Postfix => {
+                        if let Some(info) = op.matcher.test(&loc, reader)? {
+                            left = ExprTree::Postfix { operator: i, span: info.span().clone(), operand: Box::new(left) };
+                            continue 'climb;
+                        }
+                    }
+                    Fixity::Infix(associativity) => {
+                        let Some(info) = op.matcher.test(&loc, reader)? else {
+                            continue;
+                        };
+                        // A left-associative operator can't take an operator of the same precedence on its right
+                        let right_min = match associativity {
+                            Associativity::Left => op.precedence.saturating_add(1),
+                            Associativity::Right => op.precedence,
+                        };
+                        let right_loc = self.skip(info.span().end(), reader)?;
+                        // Without right operand, the operator is not part of the expression
+                        if let Some(right) = self.parse_min(&right_loc, reader, right_min)? {
+                            left = ExprTree::Infix {
+                                operator: i,
+                                span: info.span().clone(),
+                                left: Box::new(left),
+                                right: Box::new(right),
+                            };
+                            continue 'climb;
+                        }
+                        ParseContext::rollback(operator_mark, reader)?;
+                    }
+                }
+            }
+
+            ParseContext::rollback(mark, reader)?;
+            return Ok(Some(left));
+        }
+    }
+
+    /// Matches an operand, optionally preceded by prefix operators.
+    fn parse_unary(&self, loc: &Location, reader: &mut R) -> Result<Option<ExprTree>, ParserError> {
+        for (i, op) in self.operators.iter().enumerate() {
+            if op.fixity != Fixity::Prefix {
+                continue;
+            }
+            let mark = ParseContext::mark();
+            if let Some(info) = op.matcher.test(loc, reader)? {
+                let operand_loc = self.skip(info.span().end(), reader)?;
+                if let Some(operand) = self.parse_min(&operand_loc, reader, op.precedence)? {
+                    return Ok(Some(ExprTree::Prefix { operator: i, span: info.span().clone(), operand: Box::new(operand) }));
+                }
+            }
+            ParseContext::rollback(mark, reader)?;
+        }
+
+        Ok(self.operand.test(loc, reader)?.map(|info| ExprTree::Operand(info.span().clone())))
+    }
+
+    /// Returns the location after the padding.
+    fn skip(&self, loc: &Location, reader: &mut R) -> Result<Location, ParserError> {
+        let Some(padding) = &self.padding else {
+            return Ok(*loc);
+        };
+        Ok(padding.test(loc, reader)?.map_or(*loc, |info| *info.span().end()))
+    }
+}
+
+impl<R: Debug + Rewind> MatchToken<R> for ExprMatcher<R> {
+    fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
+        match self.parse(loc, reader)? {
+            Some(tree) => ParseResult::matches(*loc, *tree.span().end()),
+            None => ParseResult::no_match(),
+        }
+    }
+
+    fn fmt_notation(&self, f: &mut Formatter, notation: Notation, nesting: Nesting) -> std::fmt::Result {
+        self.flat.fmt_notation(f, notation, nesting)
+    }
+}
+
+impl<R: Debug> Display for ExprMatcher<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.flat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::{RangeMatcher, StrMatcher, StringCharReader, SyntaxError};
+
+    use super::*;
+
+    fn op(word: &'static str, fixity: Fixity, precedence: u32) -> Operator<StringCharReader> {
+        Operator::new(Arc::new(StrMatcher::new(word)), fixity, precedence)
+    }
+
+    fn calculator() -> ExprMatcher<StringCharReader> {
+        ExprMatcher::new(
+            Arc::new(RangeMatcher::new('0', '9')),
+            vec![
+                op("+", Fixity::Infix(Associativity::Left), 10),
+                op("-", Fixity::Infix(Associativity::Left), 10),
+                op("*", Fixity::Infix(Associativity::Left), 20),
+                op("^", Fixity::Infix(Associativity::Right), 30),
+                op("-", Fixity::Prefix, 25),
+                op("!", Fixity::Postfix, 40),
+            ],
+            Some(Arc::new(RepetitionMatcher::new(Arc::new(StrMatcher::new(" ")), 1))),
+        )
+    }
+
+    #[test]
+    fn test_expr_matcher() {
+        let expr = calculator();
+        let loc = Location::beginning();
+        let sexp = |source: &str| {
+            let mut reader = StringCharReader::new(source);
+            expr.parse(&loc, &mut reader).unwrap().map(|tree| tree.to_sexp(source))
+        };
+
+        assert_eq!(sexp("1 + 2 * 3"), Some("(+ 1 (* 2 3))".to_string()));
+        assert_eq!(sexp("1*2+3"), Some("(+ (* 1 2) 3)".to_string()));
+        assert_eq!(sexp("1 - 2 - 3"), Some("(- (- 1 2) 3)".to_string()));
+        assert_eq!(sexp("2 ^ 3 ^ 4"), Some("(^ 2 (^ 3 4))".to_string()));
+        assert_eq!(sexp("-2 ^ 2 * 3"), Some("(* (- (^ 2 2)) 3)".to_string()));
+        assert_eq!(sexp("- - 3!"), Some("(- (- (! 3)))".to_string()));
+        assert_eq!(sexp("x"), None);
+
+        // A trailing operator without operand is not matched
+        let mut reader = StringCharReader::new("1 + 2 * ");
+        assert_eq!(expr.test(&loc, &mut reader).unwrap().unwrap().len(), 5);
+
+        let mut reader = StringCharReader::new("1 + 2");
+        let tree = expr.parse(&loc, &mut reader).unwrap().unwrap();
+        assert_eq!(
+            tree,
+            ExprTree::Infix {
+                operator: 0,
+                span: Span::new(Location::new(1, 3, 2), Location::new(1, 4, 3)),
+                left: Box::new(ExprTree::Operand(Span::new(loc, Location::new(1, 2, 1)))),
+                right: Box::new(ExprTree::Operand(Span::new(Location::new(1, 5, 4), Location::new(1, 6, 5)))),
+            }
+        );
+    }
+
+    #[test]
+    fn test_expr_matcher_recursion_limit() {
+        let expr = calculator();
+        let input = "-".repeat(100_000) + "1";
+
+        // The limit is meant for the stack of a main thread, which is larger than the one of the test threads
+        let result = std::thread::scope(|scope| {
+            let parse = || expr.parse(&Location::beginning(), &mut StringCharReader::new(&input));
+            std::thread::Builder::new().stack_size(8 * 1024 * 1024).spawn_scoped(scope, parse).unwrap().join().unwrap()
+        });
+        assert!(matches!(result, Err(ParserError::Syntax(SyntaxError::RecursionLimit { .. }))));
+    }
+
+    #[test]
+    fn test_expr_matcher_notation() {
+        let expr = ExprMatcher::<StringCharReader>::new(
+            Arc::new(RangeMatcher::new('0', '9')),
+            vec![
+                op("+", Fixity::Infix(Associativity::Left), 10),
+                op("*", Fixity::Infix(Associativity::Left), 20),
+                op("-", Fixity::Prefix, 30),
+            ],
+            None,
+        );
+
+        assert_eq!(
+            crate::parser_lib::InNotation(&expr, Notation::Peg).to_string(),
+            "\"-\"* [0-9] ((\"+\" / \"*\") \"-\"* [0-9])*"
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    fmt::{Debug, Display, Formatter},
+    marker::PhantomData,
+};
+
+use crate::parser_lib::{Location, MatchToken, MatcherShape, Nesting, Notation, ParseResult, SyntaxError};
+
+/// Matcher that stops the parse with a `SyntaxError::Fatal` error when it is reached.
+///
+/// It marks the places where the input can't be recovered from, like the last alternative of a choice that must match:
+/// the parse stops there with a message, instead of backtracking and failing later at a less helpful location.
+#[derive(Debug)]
+pub struct FatalMatcher<R> {
+    message: String,
+    reader: PhantomData<fn(&mut R)>,
+}
+
+impl<R> FatalMatcher<R> {
+    pub fn new(message: &str) -> Self {
+        Self { message: message.to_string(), reader: PhantomData }
+    }
+}
+
+impl<R: Debug> MatchToken<R> for FatalMatcher<R> {
+    fn test(&self, loc: &Location, _reader: &mut R) -> ParseResult {
+        Err(SyntaxError::Fatal { message: self.message.clone(), location: *loc }.into())
+    }
+
+    /// Writes the message as a special sequence, which the notations use for what they can't express.
+    fn fmt_notation(&self, f: &mut Formatter, _notation: Notation, _nesting: Nesting) -> std::fmt::Result {
+        write!(f, "? {} ?", self.message)
+    }
+
+    fn shape(&self) -> MatcherShape<'_, R> {
+        MatcherShape::Terminal
+    }
+}
+
+impl<R> Display for FatalMatcher<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "fatal({:?})", self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser_lib::{GrammarBuilder, ParseOptions, ParserError, Recovery, Rule, StringCharReader},
+        choice, range, seq, word,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_fatal_matcher() {
+        let rule = FatalMatcher::<StringCharReader>::new("expected a value");
+        let mut reader = StringCharReader::new("x");
+        let err = rule.test(&Location::beginning(), &mut reader).unwrap_err();
+        assert_eq!(err.to_string(), "1:1: expected a value.");
+        assert_eq!(err.location(), Some(Location::beginning()));
+        assert_eq!(Rule::<StringCharReader>::fatal("oops").to_string(), "fatal(\"oops\")");
+    }
+
+    #[test]
+    fn test_error_limits() {
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let number = builder.define("number", range!('0', '9').at_least(1));
+        let statement = seq!(number, word!(";")).recover_with(&word!(";"));
+        // A `!` can't be recovered from
+        let bang = word!("!").then(&Rule::fatal("unexpected `!`"));
+        let program = choice![bang, seq!(statement, word!(";").optional())].at_least(0);
+        builder.options(ParseOptions::new().with_max_errors(2));
+        let grammar = builder.save_root(program);
+
+        let parse = |source: &str| grammar.parse_tree(&Location::beginning(), &mut StringCharReader::new(source));
+        assert_eq!(parse("1;x;2;y;").unwrap().unwrap().errors().len(), 2);
+        let err = parse("x;y;3;z;").unwrap_err();
+        assert!(matches!(err.cause(), ParserError::Syntax(SyntaxError::TooManyErrors { limit: 2, .. })));
+        assert_eq!(err.location().map(|location| location.index()), Some(6));
+        let err = parse("1;!2;").unwrap_err();
+        assert_eq!(err.to_string(), "1:4: unexpected `!`.");
+
+        // Without recovery, the parse stops before the first error
+        let options = ParseOptions::new().with_recovery(Recovery::Disabled);
+        let mut reader = StringCharReader::new("1;x;2;");
+        let info = grammar.parse(&Location::beginning(), &mut reader, &options).unwrap().unwrap();
+        assert_eq!(info.span().end().index(), 2);
+    }
+}
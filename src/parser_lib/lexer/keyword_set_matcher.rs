@@ -0,0 +1,192 @@
+use std::fmt::{Display, Formatter};
+
+use crate::parser_lib::{
+    CreateParseResult, Location, LocationDelta, LocationPolicy, MatchStr, MatchToken, MatcherShape, Nesting, Notation,
+    ParseResult, ParserError, Span,
+};
+
+/// Matcher that tries to match one of the given strings (like a list of keywords).
+///
+/// It matches the same as a choice between the strings: the first one that matches in the list is chosen.
+/// The strings are stored in a trie, so the input is read only once instead of once per string.
+#[derive(Debug)]
+pub struct KeywordSetMatcher {
+    keywords: Vec<String>,
+    /// Deltas of the keywords, computed with the default location policy.
+    deltas: Vec<LocationDelta>,
+    /// Nodes of the trie. The first one is the root.
+    nodes: Vec<TrieNode>,
+}
+
+#[derive(Debug, Default)]
+struct TrieNode {
+    /// Next nodes, sorted by char.
+    children: Vec<(char, usize)>,
+    /// Index of the first keyword ending at this node.
+    keyword: Option<usize>,
+    /// Smallest index of the keywords ending at this node or after it.
+    first_below: usize,
+}
+
+impl KeywordSetMatcher {
+    pub fn new(keywords: &[&str]) -> Self {
+        let mut nodes = vec![TrieNode {
+            first_below: usize::MAX,
+            ..TrieNode::default()
+        }];
+
+        for (index, keyword) in keywords.iter().enumerate() {
+            let mut node = 0;
+            for c in keyword.chars() {
+                nodes[node].first_below = nodes[node].first_below.min(index);
+                node = match nodes[node].children.binary_search_by_key(&c, |(child_c, _)| *child_c) {
+                    Ok(i) => nodes[node].children[i].1,
+                    Err(i) => {
+                        let child = nodes.len();
+                        nodes[node].children.insert(i, (c, child));
+                        nodes.push(TrieNode {
+                            first_below: index,
+                            ..TrieNode::default()
+                        });
+                        child
+                    }
+                };
+            }
+            // With duplicates, the first keyword is kept
+            nodes[node].first_below = nodes[node].first_below.min(index);
+            nodes[node].keyword.get_or_insert(index);
+        }
+
+        Self {
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+            deltas: keywords.iter().map(|k| Self::delta_of(k, LocationPolicy::default())).collect(),
+            nodes,
+        }
+    }
+
+    /// Returns the keywords, in the order they are tried.
+    pub fn keywords(&self) -> &[String] {
+        &self.keywords
+    }
+
+    fn delta_of(keyword: &str, policy: LocationPolicy) -> LocationDelta {
+        let mut delta = LocationDelta::with_policy(policy);
+        for c in keyword.chars() {
+            delta.push(c);
+        }
+        delta
+    }
+
+    /// Returns the index of the first keyword found at the position.
+    fn find<R: MatchStr>(&self, pos: usize, reader: &mut R) -> Result<Option<usize>, ParserError> {
+        let mut node = &self.nodes[0];
+        let mut found = node.keyword;
+
+        for i in pos.. {
+            // No keyword after this one comes before the one already found
+            if found.is_some_and(|k| k <= node.first_below) {
+                break;
+            }
+
+            let Some(c) = reader.char_at(i)? else {
+                // The end of the chars may also be caused by a read error
+                reader.check_error()?;
+                break;
+            };
+            let Ok(child) = node.children.binary_search_by_key(&c, |(child_c, _)| *child_c) else {
+                break;
+            };
+
+            node = &self.nodes[node.children[child].1];
+            if let Some(k) = node.keyword {
+                found = Some(found.map_or(k, |f| f.min(k)));
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+impl<R: MatchStr> MatchToken<R> for KeywordSetMatcher {
+    fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
+        let Some(k) = self.find(loc.index(), reader)? else {
+            return ParseResult::no_match();
+        };
+
+        let policy = reader.location_policy();
+        let delta = if policy == LocationPolicy::default() {
+            self.deltas[k]
+        } else {
+            Self::delta_of(&self.keywords[k], policy)
+        };
+        ParseResult::new(Span::new(*loc, delta.apply_to(loc)), delta.len())
+    }
+
+    fn fmt_notation(&self, f: &mut Formatter, notation: Notation, nesting: Nesting) -> std::fmt::Result {
+        Notation::write_group(f, nesting >= Nesting::Item && self.keywords.len() > 1, |f| {
+            for (i, keyword) in self.keywords.iter().enumerate() {
+                if i > 0 {
+                    write!(f, "{}", notation.choice_separator())?;
+                }
+                Notation::write_str(f, keyword)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn shape(&self) -> MatcherShape<'_, R> {
+        MatcherShape::Keywords(&self.keywords)
+    }
+}
+
+impl Display for KeywordSetMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "({})",
+            self.keywords
+                .iter()
+                .map(|k| format!("{:?}", k))
+                .collect::<Vec<_>>()
+                .join(" | ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::{InNotation, ParseInfo, StringCharReader};
+
+    use super::*;
+
+    #[test]
+    fn test_keyword_set_matcher() {
+        let rule = KeywordSetMatcher::new(&["if", "in", "int", "i", "else", "elif", "in"]);
+        let loc = Location::beginning();
+        let len = |input: &str| {
+            let mut reader = StringCharReader::new(input);
+            rule.test(&loc, &mut reader).unwrap().map(|info| info.len())
+        };
+
+        assert_eq!(len("if x"), Some(2));
+        assert_eq!(len("else"), Some(4));
+        assert_eq!(len("elif"), Some(4));
+        // Like a choice, the first keyword that matches is chosen, even if a later one is longer
+        assert_eq!(len("int"), Some(2));
+        assert_eq!(len("ix"), Some(1));
+        assert_eq!(len("el"), None);
+        assert_eq!(len(""), None);
+
+        let mut reader = StringCharReader::new("\nelse");
+        let start = Location::new(1, 2, 1);
+        let info = ParseInfo::new(Span::new(start, Location::new(1, 6, 5)), 4);
+        assert_eq!(rule.test(&start, &mut reader).unwrap(), Some(info));
+
+        assert_eq!(rule.keywords().len(), 7);
+        assert!(matches!(MatchToken::<StringCharReader>::shape(&rule), MatcherShape::Keywords(words) if words.len() == 7));
+        assert_eq!(
+            InNotation::<StringCharReader>(&KeywordSetMatcher::new(&["a", "b"]), Notation::Peg).to_string(),
+            "\"a\" / \"b\""
+        );
+    }
+}
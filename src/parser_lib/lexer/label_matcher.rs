@@ -0,0 +1,73 @@
+use std::{
+    fmt::{Debug, Display, Formatter},
+    sync::Arc,
+};
+
+use crate::parser_lib::{Location, MatchToken, MatcherShape, Nesting, Notation, ParseResult};
+
+/// Matcher that names the given matcher in a sequence, so that its span is a field of the result of the sequence
+///
+/// It matches like the given matcher, see `ParseInfo::field`.
+#[derive(Debug)]
+pub struct LabelMatcher<R: Debug> {
+    /// Shared with the results and the captures, so that matching doesn't copy it.
+    label: Arc<str>,
+    value: Arc<dyn MatchToken<R>>,
+}
+
+impl<R: Debug> LabelMatcher<R> {
+    pub fn new(label: &str, value: Arc<dyn MatchToken<R>>) -> Self {
+        Self {
+            label: Arc::from(label),
+            value,
+        }
+    }
+}
+
+impl<R: Debug> MatchToken<R> for LabelMatcher<R> {
+    fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
+        self.value.test(loc, reader)
+    }
+
+    fn fmt_notation(&self, f: &mut Formatter, notation: Notation, nesting: Nesting) -> std::fmt::Result {
+        // The notations don't have labels
+        self.value.fmt_notation(f, notation, nesting)
+    }
+
+    fn shape(&self) -> MatcherShape<'_, R> {
+        MatcherShape::Label {
+            label: &self.label,
+            value: &self.value,
+        }
+    }
+}
+
+impl<R: Debug> Display for LabelMatcher<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.label, self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::{ParseInfo, Span, StrMatcher, StringCharReader};
+
+    use super::*;
+
+    #[test]
+    fn test_label_matcher() {
+        let rule = LabelMatcher::new("greeting", Arc::new(StrMatcher::new("hello")));
+
+        // It matches like its value
+        let mut reader = StringCharReader::new("hello world");
+        let loc = Location::beginning();
+        let info = ParseInfo::new(Span::new(loc, loc + 5), 5);
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
+
+        let mut reader = StringCharReader::new("world");
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), None);
+
+        assert_eq!(rule.to_string(), "greeting:\"hello\"");
+        assert_eq!(crate::parser_lib::InNotation(&rule, Notation::Ebnf).to_string(), "\"hello\"");
+    }
+}
@@ -1,19 +1,46 @@
+mod aho_corasick;
+mod and_matcher;
+mod back_reference_matcher;
+mod byte_range_matcher;
+mod bytes_matcher;
+mod char_matcher;
 mod choice_matcher;
+mod dfa_matcher;
+mod error_node_matcher;
+mod fatal_matcher;
+mod expr_matcher;
+mod keyword_set_matcher;
+mod label_matcher;
 mod optional_matcher;
 mod range_matcher;
+mod reference_matcher;
 mod repetition_matcher;
 mod sequential_matcher;
 mod str_matcher;
 mod not_matcher;
 mod until_matcher;
 mod token_matcher;
+mod uint_matcher;
 
+pub use and_matcher::AndMatcher;
+pub use back_reference_matcher::BackReferenceMatcher;
+pub use byte_range_matcher::ByteRangeMatcher;
+pub use bytes_matcher::BytesMatcher;
+pub use char_matcher::CharMatcher;
 pub use choice_matcher::ChoiceMatcher;
+pub use dfa_matcher::{Dfa, DfaError, DfaMatcher};
+pub use error_node_matcher::{ErrorNodeMatcher, ERROR_RULE};
+pub use fatal_matcher::FatalMatcher;
+pub use expr_matcher::{Associativity, ExprMatcher, ExprTree, Fixity, Operator};
+pub use keyword_set_matcher::KeywordSetMatcher;
+pub use label_matcher::LabelMatcher;
 pub use optional_matcher::OptionalMatcher;
 pub use range_matcher::RangeMatcher;
+pub use reference_matcher::ReferenceMatcher;
 pub use repetition_matcher::RepetitionMatcher;
 pub use sequential_matcher::SequentialMatcher;
 pub use str_matcher::StrMatcher;
 pub use not_matcher::NotMatcher;
 pub use until_matcher::UntilMatcher;
 pub use token_matcher::TokenMatcher;
+pub use uint_matcher::UintMatcher;
@@ -1,22 +1,30 @@
-use std::{fmt::Display, rc::Rc};
+use std::{
+    fmt::{Debug, Display, Formatter},
+    sync::Arc,
+};
 
-use crate::parser_lib::{CreateParseResult, Location, MatchStr, MatchToken, ParseResult};
+use crate::parser_lib::{CreateParseResult, Location, MatchToken, MatcherShape, Nesting, Notation, ParseContext, ParseResult, Rewind};
 
 /// Matcher that returns true if the given matcher doesn't match the string
 #[derive(Debug)]
-pub struct NotMatcher<R: MatchStr> {
-    value: Rc<dyn MatchToken<R>>,
+pub struct NotMatcher<R: Debug> {
+    value: Arc<dyn MatchToken<R>>,
 }
 
-impl<R: MatchStr> NotMatcher<R> {
-    pub fn new(value: Rc<dyn MatchToken<R>>) -> Self {
+impl<R: Debug> NotMatcher<R> {
+    pub fn new(value: Arc<dyn MatchToken<R>>) -> Self {
         Self { value }
     }
 }
 
-impl<R: MatchStr> MatchToken<R> for NotMatcher<R> {
+impl<R: Debug + Rewind> MatchToken<R> for NotMatcher<R> {
     fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
-        if let Some(_) = self.value.test(loc, reader)? {
+        // The value is only checked, so its nodes are not kept
+        let mark = ParseContext::mark();
+        let matched = self.value.test(loc, reader)?.is_some();
+        ParseContext::rollback(mark, reader)?;
+
+        if matched {
             // If the value matched, this is not a match
             ParseResult::no_match()
         } else {
@@ -25,15 +33,27 @@ impl<R: MatchStr> MatchToken<R> for NotMatcher<R> {
             ParseResult::empty(*loc)
         }
     }
+
+    fn fmt_notation(&self, f: &mut Formatter, notation: Notation, nesting: Nesting) -> std::fmt::Result {
+        Notation::write_group(f, nesting >= Nesting::Operand, |f| {
+            write!(f, "!")?;
+            self.value.fmt_notation(f, notation, Nesting::Operand)
+        })
+    }
+
+    fn shape(&self) -> MatcherShape<'_, R> {
+        MatcherShape::Not(&self.value)
+    }
 }
 
-impl<R: MatchStr> Display for NotMatcher<R> {
+impl<R: Debug> Display for NotMatcher<R> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "(!{})", self.value)
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use crate::parser_lib::{ParseInfo, Span, StrMatcher, StringCharReader};
 
@@ -41,7 +61,7 @@ mod tests {
 
     #[test]
     fn test_not_matcher() {
-        let rule = NotMatcher::new(Rc::new(StrMatcher::new("hello")));
+        let rule = NotMatcher::new(Arc::new(StrMatcher::new("hello")));
 
         let mut reader = StringCharReader::new("hello world");
 
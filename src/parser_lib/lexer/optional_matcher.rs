@@ -1,22 +1,25 @@
-use std::{fmt::Display, rc::Rc};
+use std::{
+    fmt::{Debug, Display, Formatter},
+    sync::Arc,
+};
 
-use crate::parser_lib::{CreateParseResult, Location, MatchStr, MatchToken, ParseResult};
+use crate::parser_lib::{CreateParseResult, Location, MatchToken, MatcherShape, Nesting, Notation, ParseResult};
 
 /// Matcher that returns true if the given matcher matches the string, or not
 #[derive(Debug)]
-pub struct OptionalMatcher<R: MatchStr> {
-    value: Rc<dyn MatchToken<R>>,
+pub struct OptionalMatcher<R: Debug> {
+    value: Arc<dyn MatchToken<R>>,
 }
 
-impl<R: MatchStr> OptionalMatcher<R> {
-    pub fn new(value: Rc<dyn MatchToken<R>>) -> Self {
+impl<R: Debug> OptionalMatcher<R> {
+    pub fn new(value: Arc<dyn MatchToken<R>>) -> Self {
         Self { value }
     }
 }
 
-impl<R: MatchStr> MatchToken<R> for OptionalMatcher<R> {
+impl<R: Debug> MatchToken<R> for OptionalMatcher<R> {
     fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
-        if let Ok(Some(res)) = self.value.test(loc, reader) {
+        if let Some(res) = self.value.test(loc, reader)? {
             // If the value matched, the result is the same as the inner rule
             Ok(Some(res))
         }
@@ -27,15 +30,24 @@ impl<R: MatchStr> MatchToken<R> for OptionalMatcher<R> {
             ParseResult::empty(*loc)
         }
     }
+
+    fn fmt_notation(&self, f: &mut Formatter, notation: Notation, nesting: Nesting) -> std::fmt::Result {
+        notation.write_repetition(f, nesting, 0, 1, &|f, nesting| self.value.fmt_notation(f, notation, nesting))
+    }
+
+    fn shape(&self) -> MatcherShape<'_, R> {
+        MatcherShape::Optional(&self.value)
+    }
 }
 
-impl<R: MatchStr> Display for OptionalMatcher<R> {
+impl<R: Debug> Display for OptionalMatcher<R> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}?", self.value)
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use crate::parser_lib::{ParseInfo, Span, StrMatcher, StringCharReader};
 
@@ -43,7 +55,7 @@ mod tests {
 
     #[test]
     fn test_optional_matcher() {
-        let rule = OptionalMatcher::new(Rc::new(StrMatcher::new("hello")));
+        let rule = OptionalMatcher::new(Arc::new(StrMatcher::new("hello")));
 
         let mut reader = StringCharReader::new("hello world");
 
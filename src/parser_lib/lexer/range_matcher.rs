@@ -1,6 +1,11 @@
-use std::{fmt::Display};
+use std::{
+    collections::BTreeSet,
+    fmt::{Display, Formatter},
+};
 
-use crate::parser_lib::{CreateParseResult, Location, MatchStr, MatchToken, ParseResult};
+use crate::parser_lib::{
+    CreateParseResult, Location, LocationDelta, MatchStr, MatchToken, MatcherShape, Nesting, Notation, ParseResult,
+};
 
 /// Matcher that returns true if the next char is in the given range
 /// Avoids to check individually every possibility if the binary range is continuous.
@@ -8,17 +13,20 @@ use crate::parser_lib::{CreateParseResult, Location, MatchStr, MatchToken, Parse
 /// - start: inclusive start of the range
 /// - end: inclusive end of the range
 ///
-/// Note: does not support new lines in the range (won't update the location accordingly)
-/// They are not supported because they are not in an useful range anyway. Use a choice matcher instead.
+/// New lines in the range are supported: the end location is moved to the right line and column.
+///
+/// With `with_ignore_case`, the lowercase and uppercase versions of the chars of the range also match.
 #[derive(Debug)]
 pub struct RangeMatcher {
     start: char,
     end: char,
     /// Min number of matching chars
-    min: u8,
+    min: usize,
     /// Max number of matching chars
     /// If 0, considered as infinite
-    max: u8,
+    max: usize,
+    /// Sorted ranges of the chars matching when the case is ignored, or None if the case matters.
+    folded: Option<Box<[(char, char)]>>,
 }
 
 impl RangeMatcher {
@@ -29,57 +37,163 @@ impl RangeMatcher {
             end,
             min: 1,
             max: 1,
+            folded: None,
         }
     }
 
     /// Create matcher for a range of chars, with a minimum number of matching chars and infinite max
     #[allow(unused)]
-    pub fn at_least_n(start: char, end: char, min: u8) -> Self {
+    pub fn at_least_n(start: char, end: char, min: usize) -> Self {
         Self {
             start,
             end,
             min,
             max: 0,
+            folded: None,
         }
     }
 
     /// Create matcher for a range of chars, with a minimum and maximum number of matching chars
     #[allow(unused)]
-    pub fn repeat_between(start: char, end: char, min: u8, max: u8) -> Self {
+    pub fn repeat_between(start: char, end: char, min: usize, max: usize) -> Self {
         Self {
             start,
             end,
             min,
             max,
+            folded: None,
+        }
+    }
+
+    /// Also matches the other cases of the chars of the range (simple case mappings, which give a single char).
+    pub fn with_ignore_case(mut self) -> Self {
+        let mut chars = BTreeSet::new();
+        for c in self.start..=self.end {
+            let lower = single(c.to_lowercase());
+            let upper = single(c.to_uppercase());
+            let lower_of_upper = upper.and_then(|upper| single(upper.to_lowercase()));
+            let upper_of_lower = lower.and_then(|lower| single(lower.to_uppercase()));
+            chars.extend([lower, upper, lower_of_upper, upper_of_lower].into_iter().flatten());
+        }
+
+        // Consecutive chars are grouped in ranges
+        let mut ranges: Vec<(char, char)> = Vec::new();
+        let others = chars.into_iter().filter(|c| !(self.start..=self.end).contains(c));
+        let mut bounds: Vec<(char, char)> = others.map(|c| (c, c)).chain([(self.start, self.end)]).collect();
+        bounds.sort();
+        for (start, end) in bounds {
+            match ranges.last_mut() {
+                Some(last) if last.1 as u32 + 1 >= start as u32 => last.1 = last.1.max(end),
+                _ => ranges.push((start, end)),
+            }
         }
+        self.folded = Some(ranges.into_boxed_slice());
+        self
+    }
+}
+
+/// Returns the char of a case mapping, if it gives a single one.
+fn single(mut chars: impl Iterator<Item = char>) -> Option<char> {
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
     }
 }
 
 impl<R: MatchStr> MatchToken<R> for RangeMatcher {
     fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
+        if let Some(ranges) = &self.folded {
+            // The chars are tested one by one, since they are in several ranges
+            let mut delta = LocationDelta::with_policy(reader.location_policy());
+            while self.max == 0 || delta.len() < self.max {
+                let Some(c) = reader.char_at(loc.index() + delta.len())? else {
+                    // The end of the chars may also be caused by a read error
+                    reader.check_error()?;
+                    break;
+                };
+                let i = ranges.partition_point(|(start, _)| *start <= c);
+                if i == 0 || ranges[i - 1].1 < c {
+                    break;
+                }
+                delta.push(c);
+            }
+
+            if delta.len() >= self.min {
+                return ParseResult::matches(*loc, delta.apply_to(loc));
+            }
+            return ParseResult::no_match();
+        }
+
         // Test to see if the string is in the input at the given location
-        let nb = reader.match_range(loc.index(), self.start, self.end, self.max)?;
+        let delta = reader.match_range(loc.index(), self.start, self.end, self.max)?;
 
-        if nb >= self.min.into() {
+        if delta.len() >= self.min {
             // If it worked, compute the span
-            return ParseResult::matches(*loc, *loc + nb.try_into().unwrap());
+            return ParseResult::matches(*loc, delta.apply_to(loc));
         }
 
         ParseResult::no_match()
     }
+
+    fn fmt_notation(&self, f: &mut Formatter, notation: Notation, nesting: Nesting) -> std::fmt::Result {
+        let Some(ranges) = &self.folded else {
+            return notation.write_repetition(f, nesting, self.min, self.max, &|f, _| {
+                Notation::write_range(f, self.start, self.end)
+            });
+        };
+
+        // The notations have no case folding, so the ranges are written as a choice
+        notation.write_repetition(f, nesting, self.min, self.max, &|f, nesting| {
+            Notation::write_group(f, nesting >= Nesting::Item && ranges.len() > 1, |f| {
+                for (i, (start, end)) in ranges.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "{}", notation.choice_separator())?;
+                    }
+                    Notation::write_range(f, *start, *end)?;
+                }
+                Ok(())
+            })
+        })
+    }
+
+    fn shape(&self) -> MatcherShape<'_, R> {
+        // A range shape would lose the other cases
+        if self.folded.is_some() {
+            return MatcherShape::Terminal;
+        }
+
+        MatcherShape::Range {
+            start: self.start,
+            end: self.end,
+            min: self.min,
+            max: self.max,
+        }
+    }
 }
 
 impl Display for RangeMatcher {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "[{}-{}]", self.start, self.end)
+        match &self.folded {
+            Some(ranges) => write!(
+                f,
+                "({})",
+                ranges
+                    .iter()
+                    .map(|(start, end)| format!("[{}-{}]", start, end))
+                    .collect::<Vec<_>>()
+                    .join(" | ")
+            ),
+            None => write!(f, "[{}-{}]", self.start, self.end),
+        }
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use std::cmp::min;
 
-    use crate::parser_lib::{ParseInfo, Span, StringCharReader};
+    use crate::parser_lib::{InNotation, ParseInfo, Span, StringCharReader};
 
     use super::*;
 
@@ -157,4 +271,63 @@ mod tests {
         assert_eq!(rule.test(&loc, &mut reader).is_ok(), true);
         assert_eq!(rule.test(&loc, &mut reader).unwrap(), None);
     }
+
+    #[test]
+    fn test_long_range() {
+        // Counts are not limited to 255 chars
+        let identifier = "a".repeat(300);
+        let mut reader = StringCharReader::new(&identifier);
+
+        let loc = Location::beginning();
+        let rule = RangeMatcher::at_least_n('a', 'z', 1);
+        let info = ParseInfo::new(Span::new(loc, loc + 300), 300);
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
+
+        // The min and max can also be bigger than 255
+        let rule = RangeMatcher::repeat_between('a', 'z', 256, 299);
+        let info = ParseInfo::new(Span::new(loc, loc + 299), 299);
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
+
+        let rule = RangeMatcher::at_least_n('a', 'z', 301);
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_range_with_new_lines() {
+        // The range contains '\n', so the end location must be on the next line
+        let rule = RangeMatcher::at_least_n('\0', '\u{7f}', 1);
+        let mut reader = StringCharReader::new("ab\ncd");
+
+        let loc = Location::beginning();
+        let info = ParseInfo::new(Span::new(loc, Location::new(2, 3, 5)), 5);
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
+    }
+
+    #[test]
+    fn test_range_ignore_case() {
+        let rule = RangeMatcher::at_least_n('a', 'f', 1).with_ignore_case();
+        let loc = Location::beginning();
+        let len = |input: &str| {
+            let mut reader = StringCharReader::new(input);
+            rule.test(&loc, &mut reader).unwrap().map(|info| info.len())
+        };
+
+        assert_eq!(len("aBcDeFg"), Some(6));
+        assert_eq!(len("CAFE\n"), Some(4));
+        assert_eq!(len("G"), None);
+        assert_eq!(len(""), None);
+
+        // Non-ASCII chars are folded too, and the location counts chars
+        let rule = RangeMatcher::repeat_between('à', 'é', 1, 3).with_ignore_case();
+        let mut reader = StringCharReader::new("ÉéÀa");
+        let info = ParseInfo::new(Span::new(loc, Location::with_byte_offset(1, 4, 3, 6)), 3);
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
+
+        // The notation lists the ranges of both cases
+        let rule = RangeMatcher::new('a', 'z').with_ignore_case();
+        assert_eq!(InNotation::<StringCharReader>(&rule, Notation::Ebnf).to_string(), "[A-Z] | [a-z]");
+        assert_eq!(rule.to_string(), "([A-Z] | [a-z])");
+        let rule = RangeMatcher::at_least_n('0', '9', 1).with_ignore_case();
+        assert_eq!(InNotation::<StringCharReader>(&rule, Notation::Ebnf).to_string(), "[0-9]+");
+    }
 }
@@ -0,0 +1,247 @@
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display},
+    sync::{Arc, Mutex, MutexGuard, OnceLock, Weak},
+    thread::{self, ThreadId},
+};
+
+use crate::parser_lib::{
+    CreateParseResult, Location, Mark, MatchToken, MatcherShape, NodeId, ParseContext, ParseInfo, ParseResult,
+    PendingToken, Rewind, SourceId, SyntaxError,
+};
+
+/// Matcher that refers to a named rule, which may be defined after it.
+///
+/// It allows recursive rules (like an expression containing expressions between parentheses).
+/// The reference is weak to avoid cycles: the grammar owns the named rules, so it has to be kept alive while matching.
+/// It can be shared between threads: each thread matching the rule has its own seeds.
+///
+/// Left-recursive rules (like `expr = expr "-" num | num`) are supported by growing a seed: when the rule is reached
+/// again at the same position, the previous result is used instead. The first one is a failure, so only the other
+/// alternatives can match. Then, the rule is matched again with that result, as long as it gets longer.
+#[derive(Debug)]
+pub struct ReferenceMatcher<R: Debug> {
+    name: String,
+    target: OnceLock<Weak<dyn MatchToken<R>>>,
+    /// Seeds of the positions where the rule is being matched, by thread.
+    seeds: Mutex<HashMap<SeedKey, Seed>>,
+}
+
+/// Position where a thread is matching the rule.
+type SeedKey = (ThreadId, SourceId, usize);
+
+/// Current result of a rule at a position where it is being matched.
+#[derive(Debug)]
+struct Seed {
+    result: Option<ParseInfo>,
+    /// Node of the result, if a tree is built.
+    node: Option<NodeId>,
+    /// Tokens finished by the result.
+    tokens: Vec<PendingToken>,
+    /// True if the rule was reached again at the same position.
+    left_recursive: bool,
+}
+
+impl<R: Debug + Rewind> ReferenceMatcher<R> {
+    /// Creates an unresolved reference to the given rule.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            target: OnceLock::new(),
+            seeds: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the name of the referenced rule.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sets the matcher of the referenced rule. Returns false if it was already resolved.
+    pub fn resolve(&self, target: &Arc<dyn MatchToken<R>>) -> bool {
+        self.target.set(Arc::downgrade(target)).is_ok()
+    }
+
+    /// Returns true if the referenced rule is defined.
+    pub fn is_resolved(&self) -> bool {
+        self.target.get().is_some()
+    }
+
+    /// Locks the seeds. The lock is never held while matching, so it can't be poisoned by a panicking matcher.
+    fn seeds(&self) -> MutexGuard<'_, HashMap<SeedKey, Seed>> {
+        self.seeds.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Matches the rule at a position where its seed is planted, and grows it if the rule is left-recursive.
+    ///
+    /// The nodes of the last iteration are kept: they are the children of the rule.
+    fn grow(&self, target: &Arc<dyn MatchToken<R>>, key: SeedKey, loc: &Location, reader: &mut R) -> ParseResult {
+        let start = ParseContext::mark();
+        let result = target.test(loc, reader)?;
+
+        // Without left recursion, the first result is the right one
+        if !self.seeds()[&key].left_recursive {
+            return Ok(result);
+        }
+        self.grow_left_recursive(target, key, loc, reader, result, start)
+    }
+
+    /// Matches the left-recursive rule again with the last result as seed, as long as it gets longer.
+    ///
+    /// It is kept apart from `grow` so that the rules that are not left-recursive use less stack.
+    fn grow_left_recursive(
+        &self,
+        target: &Arc<dyn MatchToken<R>>,
+        key: SeedKey,
+        loc: &Location,
+        reader: &mut R,
+        mut result: Option<ParseInfo>,
+        start: Mark,
+    ) -> ParseResult {
+        loop {
+            let Some(end) = result.as_ref().map(|info| info.end().index()) else {
+                return Ok(None);
+            };
+            self.update_seed(key, &result, start);
+
+            // The rule is matched again from its start, so the input consumed by the seed is read again
+            ParseContext::rewind(start, reader)?;
+            let next_start = ParseContext::mark();
+            match target.test(loc, reader)? {
+                Some(next) if next.end().index() > end => {
+                    ParseContext::discard(start, next_start);
+                    result = Some(next);
+                }
+                _ => {
+                    ParseContext::rollback(next_start, reader)?;
+                    return Ok(result);
+                }
+            }
+        }
+    }
+
+    /// Returns the result of the seed planted at the position, if any, and marks the rule as left-recursive.
+    fn use_seed(&self, key: SeedKey) -> Option<Option<ParseInfo>> {
+        let mut seeds = self.seeds();
+        let seed = seeds.get_mut(&key)?;
+        seed.left_recursive = true;
+        ParseContext::seed_used();
+        ParseContext::replay(seed.node);
+        ParseContext::replay_tokens(seed.tokens.clone());
+        Some(seed.result.clone())
+    }
+
+    /// Replaces the seed with the result of the last iteration, its node made of the nodes pushed since the mark, and
+    /// its tokens.
+    fn update_seed(&self, key: SeedKey, result: &Option<ParseInfo>, mark: Mark) {
+        let node = result.as_ref().and_then(|info| ParseContext::node_since(&self.name, info, mark));
+        let tokens = ParseContext::tokens_since(mark);
+        let mut seeds = self.seeds();
+        let seed = seeds.get_mut(&key).unwrap();
+        seed.result = result.clone();
+        seed.node = node;
+        seed.tokens = tokens;
+    }
+}
+
+impl<R: Debug + Rewind> MatchToken<R> for ReferenceMatcher<R> {
+    fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
+        // Either the rule was never defined, or its grammar was dropped
+        let Some(target) = self.target.get().and_then(Weak::upgrade) else {
+            return ParseResult::error(SyntaxError::UnresolvedRule(self.name.clone()).into());
+        };
+        ParseContext::step(loc)?;
+
+        ParseContext::memoized(Arc::as_ptr(&target) as *const u8 as usize, loc, || {
+            // The rule is already being matched at this position: it is left-recursive
+            let key = (thread::current().id(), loc.source(), loc.index());
+            if let Some(result) = self.use_seed(key) {
+                return Ok(result);
+            }
+
+            self.seeds().insert(key, Seed { result: None, node: None, tokens: Vec::new(), left_recursive: false });
+            let result = ParseContext::rule(&self.name, loc, || {
+                ParseContext::nested(loc, || self.grow(&target, key, loc, reader))
+            })
+            .map_err(|err| err.in_rule(&self.name, *loc));
+            self.seeds().remove(&key);
+            result
+        })
+    }
+
+    fn shape(&self) -> MatcherShape<'_, R> {
+        MatcherShape::Reference(&self.name)
+    }
+}
+
+impl<R: Debug> Display for ReferenceMatcher<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // Only the name is shown, otherwise recursive rules would be displayed forever
+        write!(f, "{}", self.name)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use crate::parser_lib::{ChoiceMatcher, RangeMatcher, SequentialMatcher, Span, StrMatcher, StringCharReader};
+
+    use super::*;
+
+    #[test]
+    fn test_reference_matcher() {
+        let rule = ReferenceMatcher::new("greeting");
+        let mut reader = StringCharReader::new("hello world");
+        let loc = Location::beginning();
+
+        // Not defined yet
+        assert_eq!(rule.is_resolved(), false);
+        assert_eq!(rule.test(&loc, &mut reader), Err(SyntaxError::UnresolvedRule("greeting".to_string()).into()));
+
+        let target: Arc<dyn MatchToken<StringCharReader>> = Arc::new(StrMatcher::new("hello"));
+        assert_eq!(rule.resolve(&target), true);
+        assert_eq!(rule.resolve(&target), false);
+
+        let info = ParseInfo::new(Span::new(loc, Location::new(1, 6, 5)), 5);
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
+        assert_eq!(rule.to_string(), "greeting");
+
+        // The reference doesn't keep the rule alive
+        drop(target);
+        assert_eq!(rule.test(&loc, &mut reader), Err(SyntaxError::UnresolvedRule("greeting".to_string()).into()));
+    }
+
+    #[test]
+    fn test_left_recursion() {
+        let loc = Location::beginning();
+
+        // expr = expr "-" num | num
+        let expr = Arc::new(ReferenceMatcher::new("expr"));
+        let num: Arc<dyn MatchToken<StringCharReader>> = Arc::new(RangeMatcher::new('0', '9'));
+        let minus = Arc::new(StrMatcher::new("-"));
+        let target: Arc<dyn MatchToken<StringCharReader>> = Arc::new(ChoiceMatcher::new(vec![
+            Arc::new(SequentialMatcher::new(vec![expr.clone(), minus, num.clone()])),
+            num,
+        ]));
+        expr.resolve(&target);
+
+        let mut reader = StringCharReader::new("5-3-1+2");
+        assert_eq!(expr.test(&loc, &mut reader).unwrap().unwrap().len(), 5);
+        let mut reader = StringCharReader::new("x");
+        assert_eq!(expr.test(&loc, &mut reader).unwrap(), None);
+
+        // Indirect: a = b "x" | "y" ; b = a
+        let a = Arc::new(ReferenceMatcher::new("a"));
+        let b = Arc::new(ReferenceMatcher::new("b"));
+        let a_target: Arc<dyn MatchToken<StringCharReader>> = Arc::new(ChoiceMatcher::new(vec![
+            Arc::new(SequentialMatcher::new(vec![b.clone(), Arc::new(StrMatcher::new("x"))])),
+            Arc::new(StrMatcher::new("y")),
+        ]));
+        let b_target: Arc<dyn MatchToken<StringCharReader>> = a.clone();
+        a.resolve(&a_target);
+        b.resolve(&b_target);
+
+        let mut reader = StringCharReader::new("yxxx");
+        assert_eq!(a.test(&loc, &mut reader).unwrap().unwrap().len(), 4);
+    }
+}
@@ -1,74 +1,117 @@
-use std::{fmt::Display, rc::Rc};
+use std::{
+    fmt::{Debug, Display, Formatter},
+    sync::Arc,
+};
 
-use crate::parser_lib::{CreateParseResult, Location, MatchStr, MatchToken, ParseResult};
+use crate::parser_lib::{Location, MatchToken, MatcherShape, Nesting, Notation, ParseContext, ParseInfo, ParseResult, Rewind, Span};
 
 /// Matcher that returns true if the given matcher matches the string min times, or more
+///
+/// The result gives the span of each repetition, see `ParseInfo::repetitions`.
 #[derive(Debug)]
-pub struct RepetitionMatcher<R: MatchStr> {
-    value: Rc<dyn MatchToken<R>>,
-    min: u8,
+pub struct RepetitionMatcher<R: Debug> {
+    value: Arc<dyn MatchToken<R>>,
+    min: usize,
+    /// Max number of matches
+    /// If 0, considered as infinite
+    max: usize,
 }
 
-impl<R: MatchStr> RepetitionMatcher<R> {
-    pub fn new(value: Rc<dyn MatchToken<R>>, min: u8) -> Self {
-        Self { value, min }
+impl<R: Debug> RepetitionMatcher<R> {
+    pub fn new(value: Arc<dyn MatchToken<R>>, min: usize) -> Self {
+        Self { value, min, max: 0 }
+    }
+
+    /// Create matcher with a minimum and maximum number of matches
+    pub fn between(value: Arc<dyn MatchToken<R>>, min: usize, max: usize) -> Self {
+        Self { value, min, max }
     }
 }
 
-impl<R: MatchStr> MatchToken<R> for RepetitionMatcher<R> {
+impl<R: Debug + Rewind> MatchToken<R> for RepetitionMatcher<R> {
     fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
-        let mut count = 0;
+        let mut repetitions = Vec::new();
         let mut end_loc = *loc;
+        let mark = ParseContext::mark();
+
+        // Try to match the matcher at the end until it doesn't work, or until the max is reached
+        while self.max == 0 || repetitions.len() < self.max {
+            let Some(res) = self.value.test(&end_loc, reader)? else {
+                break;
+            };
 
-        // Try to match the matcher at the end until it doesn't work
-        while let Ok(Some(res)) = self.value.test(&end_loc, reader) {
             // We got one more match
-            count += 1;
+            repetitions.push(res.span().clone());
 
             // The end location is thus further
             end_loc = *res.end();
         }
 
         // If we got at least min matches, we have a match
-        if count >= self.min {
-            ParseResult::matches(*loc, end_loc)
+        if repetitions.len() >= self.min {
+            let span = Span::new(*loc, end_loc);
+            Ok(Some(ParseInfo::new(span, end_loc.index() - loc.index()).with_repetitions(repetitions)))
         } else {
-            ParseResult::no_match()
+            ParseContext::rollback(mark, reader)?;
+            Ok(None)
+        }
+    }
+
+    fn fmt_notation(&self, f: &mut Formatter, notation: Notation, nesting: Nesting) -> std::fmt::Result {
+        notation.write_repetition(f, nesting, self.min, self.max, &|f, nesting| {
+            self.value.fmt_notation(f, notation, nesting)
+        })
+    }
+
+    fn shape(&self) -> MatcherShape<'_, R> {
+        MatcherShape::Repetition {
+            value: &self.value,
+            min: self.min,
+            max: self.max,
         }
     }
 }
 
-impl<R: MatchStr> Display for RepetitionMatcher<R> {
+impl<R: Debug> Display for RepetitionMatcher<R> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self.min {
-            0 => write!(f, "{}*", self.value),
-            1 => write!(f, "{}+", self.value),
-            _ => write!(f, "{}{{{},...}}", self.value, self.min),
+        match (self.min, self.max) {
+            (0, 0) => write!(f, "{}*", self.value),
+            (1, 0) => write!(f, "{}+", self.value),
+            (min, 0) => write!(f, "{}{{{},...}}", self.value, min),
+            (min, max) if min == max => write!(f, "{}{{{}}}", self.value, min),
+            (min, max) => write!(f, "{}{{{},{}}}", self.value, min, max),
         }
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
-    use crate::parser_lib::{ParseInfo, SequentialMatcher, Span, StrMatcher, StringCharReader};
+    use crate::parser_lib::{ChoiceMatcher, SequentialMatcher, StrMatcher, StringCharReader};
 
     use super::*;
 
+    /// Returns the spans of `count` repetitions of `len` chars.
+    fn repetitions(start: Location, count: usize, len: usize) -> Vec<Span> {
+        (0..count).map(|i| Span::new(start + i * len, start + (i + 1) * len)).collect()
+    }
+
     #[test]
     fn test_repetition_matcher() {
-        let rule = RepetitionMatcher::new(Rc::new(StrMatcher::new("a")), 1);
+        let rule = RepetitionMatcher::new(Arc::new(StrMatcher::new("a")), 1);
 
         let mut reader = StringCharReader::new("aaaallo");
 
         // Test rule
         let loc = Location::beginning();
-        let info = ParseInfo::new(Span::new(loc, Location::new(1, 5, 4)), 4);
+        let info = ParseInfo::new(Span::new(loc, Location::new(1, 5, 4)), 4).with_repetitions(repetitions(loc, 4, 1));
         assert_eq!(rule.test(&loc, &mut reader).is_ok(), true);
         assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
 
         // It should match less if it starts later
         let loc2 = loc + 1;
         let info2 = ParseInfo::new(Span::new(loc2, Location::new(1, 5, 4)), 3);
+        let info2 = info2.with_repetitions(repetitions(loc2, 3, 1));
         assert_eq!(rule.test(&loc2, &mut reader).is_ok(), true);
         assert_eq!(rule.test(&loc2, &mut reader).unwrap(), Some(info2));
 
@@ -77,19 +120,19 @@ mod tests {
         assert_eq!(rule.test(&loc, &mut reader).is_ok(), true);
         assert_eq!(rule.test(&loc, &mut reader).unwrap(), None);
 
-        let rule = RepetitionMatcher::new(Rc::new(StrMatcher::new("a")), 0);
+        let rule = RepetitionMatcher::new(Arc::new(StrMatcher::new("a")), 0);
 
         // If we modify the rule to have a min 0, it should match
-        let info2 = ParseInfo::new(Span::new(loc, loc), 0);
+        let info2 = ParseInfo::new(Span::new(loc, loc), 0).with_repetitions(vec![]);
         assert_eq!(rule.test(&loc, &mut reader).is_ok(), true);
         assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info2));
 
-        let rule = RepetitionMatcher::new(Rc::new(StrMatcher::new("aa")), 2);
+        let rule = RepetitionMatcher::new(Arc::new(StrMatcher::new("aa")), 2);
 
         let mut reader = StringCharReader::new("aaaaallo");
 
         // Min can also be greater than 1, and string matcher can be greater as well. Here, we should match the same as first time
-        let info3 = ParseInfo::new(Span::new(loc, Location::new(1, 5, 4)), 4);
+        let info3 = ParseInfo::new(Span::new(loc, Location::new(1, 5, 4)), 4).with_repetitions(repetitions(loc, 2, 2));
         assert_eq!(rule.test(&loc, &mut reader).is_ok(), true);
         assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info3));
 
@@ -103,15 +146,15 @@ mod tests {
     #[test]
     fn test_list() {
         // Some fancy grammar can already be defined:
-        let x = Rc::new(StrMatcher::new("X"));
-        let space = Rc::new(StrMatcher::new(" "));
-        let comma = Rc::new(StrMatcher::new(","));
-        let ws = Rc::new(RepetitionMatcher::new(space, 0));
-        let param = Rc::new(SequentialMatcher::new(vec![x, ws.clone()]));
-        let comma_ws = Rc::new(SequentialMatcher::new(vec![comma, ws]));
-        let second_param = Rc::new(SequentialMatcher::new(vec![comma_ws, param.clone()]));
-        let second_params = Rc::new(RepetitionMatcher::new(second_param, 0));
-        let params = Rc::new(SequentialMatcher::new(vec![param, second_params]));
+        let x = Arc::new(StrMatcher::new("X"));
+        let space = Arc::new(StrMatcher::new(" "));
+        let comma = Arc::new(StrMatcher::new(","));
+        let ws = Arc::new(RepetitionMatcher::new(space, 0));
+        let param = Arc::new(SequentialMatcher::new(vec![x, ws.clone()]));
+        let comma_ws = Arc::new(SequentialMatcher::new(vec![comma, ws]));
+        let second_param = Arc::new(SequentialMatcher::new(vec![comma_ws, param.clone()]));
+        let second_params = Arc::new(RepetitionMatcher::new(second_param, 0));
+        let params = Arc::new(SequentialMatcher::new(vec![param, second_params]));
 
         let mut reader = StringCharReader::new("X, X, X");
 
@@ -149,19 +192,59 @@ mod tests {
 
     #[test]
     fn test_string_representation() {
-        let a = RepetitionMatcher::<StringCharReader>::new(Rc::new(StrMatcher::new("a")), 0);
+        let a = RepetitionMatcher::<StringCharReader>::new(Arc::new(StrMatcher::new("a")), 0);
 
         // String representation should be "a*"
         assert_eq!(a.to_string(), "\"a\"*");
 
-        let a = RepetitionMatcher::<StringCharReader>::new(Rc::new(StrMatcher::new("a")), 1);
+        let a = RepetitionMatcher::<StringCharReader>::new(Arc::new(StrMatcher::new("a")), 1);
 
         // String representation should be "a+"
         assert_eq!(a.to_string(), "\"a\"+");
 
-        let a = RepetitionMatcher::<StringCharReader>::new(Rc::new(StrMatcher::new("a")), 2);
+        let a = RepetitionMatcher::<StringCharReader>::new(Arc::new(StrMatcher::new("a")), 2);
 
         // String representation should be "a{2}"
         assert_eq!(a.to_string(), "\"a\"{2,...}");
+
+        let a = RepetitionMatcher::<StringCharReader>::between(Arc::new(StrMatcher::new("a")), 2, 4);
+        assert_eq!(a.to_string(), "\"a\"{2,4}");
+
+        let a = RepetitionMatcher::<StringCharReader>::between(Arc::new(StrMatcher::new("a")), 3, 3);
+        assert_eq!(a.to_string(), "\"a\"{3}");
+    }
+
+    #[test]
+    fn test_bounded_repetition() {
+        let rule = RepetitionMatcher::between(Arc::new(StrMatcher::new("a")), 2, 3);
+        let loc = Location::beginning();
+
+        // It stops at the max
+        let mut reader = StringCharReader::new("aaaa");
+        let info = ParseInfo::new(Span::new(loc, loc + 3), 3).with_repetitions(repetitions(loc, 3, 1));
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
+
+        // But the min must still be reached
+        let mut reader = StringCharReader::new("ab");
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_repetition_spans() {
+        let item = Arc::new(ChoiceMatcher::new(vec![
+            Arc::new(StrMatcher::new("a")),
+            Arc::new(StrMatcher::new("bbb")),
+        ]));
+        let rule = RepetitionMatcher::new(item, 0);
+        let loc = Location::beginning();
+
+        // Each repetition has its own span
+        let mut reader = StringCharReader::new("abbbac");
+        let info = rule.test(&loc, &mut reader).unwrap().unwrap();
+        let expected = [Span::new(loc, loc + 1), Span::new(loc + 1, loc + 4), Span::new(loc + 4, loc + 5)];
+        assert_eq!(info.repetitions(), Some(&expected[..]));
+
+        // The other matchers don't have repetitions
+        assert_eq!(StrMatcher::new("a").test(&loc, &mut reader).unwrap().unwrap().repetitions(), None);
     }
 }
@@ -1,41 +1,78 @@
-use std::{fmt::Display, rc::Rc};
+use std::{
+    fmt::{Debug, Display, Formatter},
+    sync::Arc,
+};
 
-use crate::parser_lib::{CreateParseResult, Location, MatchStr, MatchToken, ParseResult};
+use crate::parser_lib::{Location, MatchToken, MatcherShape, Nesting, Notation, ParseContext, ParseInfo, ParseResult, Rewind, Span};
 
 /// Matcher that returns true if the given matcher matches the string, or not
+///
+/// The result gives the span of the labeled children, see `LabelMatcher`. Their text can be matched again by the next
+/// matchers of the rule, see `BackReferenceMatcher`.
 #[derive(Debug)]
-pub struct SequentialMatcher<R: MatchStr> {
-    children: Vec<Rc<dyn MatchToken<R>>>,
+pub struct SequentialMatcher<R: Debug> {
+    children: Vec<Arc<dyn MatchToken<R>>>,
 }
 
-impl<R: MatchStr> SequentialMatcher<R> {
-    pub fn new(children: Vec<Rc<dyn MatchToken<R>>>) -> Self {
+impl<R: Debug> SequentialMatcher<R> {
+    pub fn new(children: Vec<Arc<dyn MatchToken<R>>>) -> Self {
         Self { children }
     }
 }
 
-impl<R: MatchStr> MatchToken<R> for SequentialMatcher<R> {
+impl<R: Debug + Rewind> MatchToken<R> for SequentialMatcher<R> {
     fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
         let mut end_loc = *loc;
+        let mut fields = Vec::new();
+        let mark = ParseContext::mark();
+        let captures = ParseContext::captures_mark();
 
         // Try to match each child
         for child in &self.children {
             if let Some(res) = child.test(&end_loc, reader)? {
+                if let MatcherShape::Label { label, .. } = child.shape() {
+                    fields.push((label.clone(), res.span().clone()));
+                    ParseContext::capture(label, res.span());
+                }
+
                 // If the child matched, update the end location
                 end_loc = *res.span().end();
             } else {
                 // None: one of the children didn't match, thus the whole sequence doesn't match
-                // We can stop here
-                return ParseResult::no_match();
+                // We can stop here, without the nodes of the children that matched
+                ParseContext::rollback(mark, reader)?;
+                ParseContext::release_captures(captures);
+                return Ok(None);
             }
         }
 
         // If we get here, we have either a full match, or an empty match (if there is no children)
-        ParseResult::matches(*loc, end_loc)
+        let info = ParseInfo::new(Span::new(*loc, end_loc), end_loc.index() - loc.index());
+        if fields.is_empty() {
+            Ok(Some(info))
+        } else {
+            Ok(Some(info.with_fields(fields)))
+        }
+    }
+
+    fn fmt_notation(&self, f: &mut Formatter, notation: Notation, nesting: Nesting) -> std::fmt::Result {
+        Notation::write_group(f, nesting >= Nesting::Operand, |f| {
+            for (i, child) in self.children.iter().enumerate() {
+                if i > 0 {
+                    write!(f, "{}", notation.sequence_separator())?;
+                }
+                child.fmt_notation(f, notation, Nesting::Item)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn shape(&self) -> MatcherShape<'_, R> {
+        MatcherShape::Sequence(&self.children)
     }
 }
 
-impl<R: MatchStr> Display for SequentialMatcher<R> {
+impl<R: Debug> Display for SequentialMatcher<R> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         // Simply write children one after another
         write!(
@@ -51,16 +88,19 @@ impl<R: MatchStr> Display for SequentialMatcher<R> {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
-    use crate::parser_lib::{OptionalMatcher, ParseInfo, Span, StrMatcher, StringCharReader};
+    use crate::parser_lib::{
+        LabelMatcher, OptionalMatcher, RangeMatcher, RepetitionMatcher, StrMatcher, StringCharReader,
+    };
 
     use super::*;
 
     #[test]
     fn test_sequential_matcher() {
         let rule = SequentialMatcher::new(vec![
-            Rc::new(StrMatcher::new("hello ")),
-            Rc::new(StrMatcher::new("world")),
+            Arc::new(StrMatcher::new("hello ")),
+            Arc::new(StrMatcher::new("world")),
         ]);
 
         let mut reader = StringCharReader::new("hello world");
@@ -85,8 +125,8 @@ mod tests {
 
         // Let's try to combine it with optional
         let rule2 = SequentialMatcher::new(vec![
-            Rc::new(OptionalMatcher::new(Rc::new(StrMatcher::new("hello ")))),
-            Rc::new(StrMatcher::new("world")),
+            Arc::new(OptionalMatcher::new(Arc::new(StrMatcher::new("hello ")))),
+            Arc::new(StrMatcher::new("world")),
         ]);
 
         let mut reader = StringCharReader::new("hello world");
@@ -115,4 +155,33 @@ mod tests {
         assert_eq!(rule.test(&loc, &mut reader).is_ok(), true);
         assert_eq!(rule.test(&loc, &mut reader).unwrap(), None);
     }
+
+    #[test]
+    fn test_labeled_fields() {
+        let name = Arc::new(RepetitionMatcher::new(Arc::new(RangeMatcher::new('a', 'z')), 1));
+        let value = Arc::new(RepetitionMatcher::new(Arc::new(RangeMatcher::new('0', '9')), 1));
+        let rule = SequentialMatcher::new(vec![
+            Arc::new(LabelMatcher::new("name", name)),
+            Arc::new(StrMatcher::new("=")),
+            Arc::new(LabelMatcher::new("value", value)),
+        ]);
+
+        // The labeled children are given by name, in order
+        let mut reader = StringCharReader::new("abc=42");
+        let loc = Location::beginning();
+        let info = rule.test(&loc, &mut reader).unwrap().unwrap();
+        assert_eq!(info.field("name"), Some(&Span::new(loc, loc + 3)));
+        assert_eq!(info.field("value"), Some(&Span::new(loc + 4, loc + 6)));
+        assert_eq!(info.field("other"), None);
+        let labels: Vec<&str> = info.fields().unwrap().iter().map(|(label, _)| &**label).collect();
+        assert_eq!(labels, ["name", "value"]);
+
+        let mut reader = StringCharReader::new("abc=");
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), None);
+
+        // Without labels, there are no fields
+        let rule = SequentialMatcher::new(vec![Arc::new(StrMatcher::new("abc"))]);
+        let mut reader = StringCharReader::new("abc");
+        assert_eq!(rule.test(&loc, &mut reader).unwrap().unwrap().fields(), None);
+    }
 }
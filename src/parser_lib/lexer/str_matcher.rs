@@ -1,64 +1,85 @@
-use std::fmt::Display;
+use std::{
+    borrow::Cow,
+    fmt::{Display, Formatter},
+};
 
-use crate::parser_lib::{CreateParseResult, Location, MatchStr, MatchToken, ParseResult, Span, Stream};
+use crate::parser_lib::{
+    CreateParseResult, Location, LocationDelta, LocationPolicy, MatchStr, MatchToken, MatcherShape, Nesting, Notation, ParseResult, Span,
+};
 
 /// Matcher that tries to match an exact string (like a keyword).
+///
+/// The string is either static or owned, to build matchers from runtime data (like a loaded grammar).
 #[derive(Debug)]
 pub struct StrMatcher {
-    value: &'static str,
+    value: Cow<'static, str>,
 
     // Information about the size of the value
-    // When the value is matched, delta_lines will be added to the number of lines
-    // And delta_columns will be added to the number of columns.
-    // If a new line occurs, the columns will be reset to 1 before adding delta_columns.
-    delta_lines: usize,
-    delta_columns: usize,
+    // When the value is matched, the delta is applied to the start location.
+    // It counts lines, columns, chars and bytes separately, since they differ for non-ASCII values.
+    // It is computed with the default location policy.
+    delta: LocationDelta,
 }
 
 impl StrMatcher {
-    pub fn new(value: &'static str) -> Self {
+    pub fn new(value: impl Into<Cow<'static, str>>) -> Self {
+        let value = value.into();
+
         // Measure delta lines and delta column only once
         // Then we will be able to use those at each match instead
         // of having to recompute it again
-        let mut delta_lines = 0;
-        let mut delta_columns = 0;
+        let mut delta = LocationDelta::new();
         for c in value.chars() {
-            if c == '\n' {
-                delta_lines += 1;
-                delta_columns = 0;
-            } else {
-                delta_columns += 1;
-            }
+            delta.push(c);
         }
 
         // Save the information
-        Self {
-            value,
-            delta_lines,
-            delta_columns,
+        Self { value, delta }
+    }
+
+    /// Computes the delta of the value for the given policy.
+    fn delta_for(&self, policy: LocationPolicy) -> LocationDelta {
+        if policy == LocationPolicy::default() {
+            return self.delta;
+        }
+
+        // Other policies are less common, so they are not cached
+        let mut delta = LocationDelta::with_policy(policy);
+        for c in self.value.chars() {
+            delta.push(c);
         }
+        delta
     }
 }
 
 impl<R: MatchStr > MatchToken<R> for StrMatcher {
     fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
         // Test to see if the string is in the input at the given location
-        let success = reader.match_str(loc.index(), self.value)?;
+        let success = reader.match_str(loc.index(), &self.value)?;
 
         if success {
             // If it worked, compute the span
-            let end_loc = loc.add_delta(self.delta_lines, self.delta_columns, self.value.len());
+            let delta = self.delta_for(reader.location_policy());
+            let end_loc = delta.apply_to(loc);
             let span = Span::new(*loc, end_loc);
-            return ParseResult::new(span, self.value.len());
+            return ParseResult::new(span, delta.len());
         }
 
         ParseResult::no_match()
     }
+
+    fn fmt_notation(&self, f: &mut Formatter, _notation: Notation, _nesting: Nesting) -> std::fmt::Result {
+        Notation::write_str(f, &self.value)
+    }
+
+    fn shape(&self) -> MatcherShape<'_, R> {
+        MatcherShape::Literal(&self.value)
+    }
 }
 
 impl Display for StrMatcher {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "\"{}\"", match self.value {
+        write!(f, "\"{}\"", match self.value.as_ref() {
             "\n" => "\\n",
             "\r" => "\\r",
             "\t" => "\\t",
@@ -71,6 +92,7 @@ impl Display for StrMatcher {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use crate::parser_lib::{ParseInfo, StringCharReader};
 
@@ -80,28 +102,28 @@ mod tests {
     fn test_deltas() {
         // No new line
         let rule = StrMatcher::new("hello");
-        assert_eq!(rule.delta_lines, 0);
-        assert_eq!(rule.delta_columns, 5);
+        assert_eq!(rule.delta.lines(), 0);
+        assert_eq!(rule.delta.columns(), 5);
 
         // New line
         let rule = StrMatcher::new("\nhello");
-        assert_eq!(rule.delta_lines, 1);
-        assert_eq!(rule.delta_columns, 5);
+        assert_eq!(rule.delta.lines(), 1);
+        assert_eq!(rule.delta.columns(), 5);
 
         // New line in the middle
         let rule = StrMatcher::new("hello\nworld");
-        assert_eq!(rule.delta_lines, 1);
-        assert_eq!(rule.delta_columns, 5);
+        assert_eq!(rule.delta.lines(), 1);
+        assert_eq!(rule.delta.columns(), 5);
 
         // New line at the end
         let rule = StrMatcher::new("hello\n");
-        assert_eq!(rule.delta_lines, 1);
-        assert_eq!(rule.delta_columns, 0);
+        assert_eq!(rule.delta.lines(), 1);
+        assert_eq!(rule.delta.columns(), 0);
 
         // Empty string
         let rule = StrMatcher::new("");
-        assert_eq!(rule.delta_lines, 0);
-        assert_eq!(rule.delta_columns, 0);
+        assert_eq!(rule.delta.lines(), 0);
+        assert_eq!(rule.delta.columns(), 0);
 
         // String representation should be "\"hello\""
         let rule = StrMatcher::new("hello");
@@ -137,4 +159,49 @@ mod tests {
         assert_eq!(rule2.test(&loc3, &mut reader).is_ok(), true);
         assert_eq!(rule2.test(&loc3, &mut reader).unwrap(), Some(info2));
     }
+
+    #[test]
+    fn test_owned_str_matcher() {
+        let words = String::from("hello world");
+        let rule = StrMatcher::new(words[6..].to_string());
+
+        let mut reader = StringCharReader::new("world");
+        let loc = Location::beginning();
+        let info = ParseInfo::new(Span::new(loc, Location::new(1, 6, 5)), 5);
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
+        assert_eq!(rule.to_string(), "\"world\"");
+    }
+
+    #[test]
+    fn test_unicode_str_matcher() {
+        let rule = StrMatcher::new("éléphant");
+        assert_eq!(rule.delta.len(), 8);
+        assert_eq!(rule.delta.bytes(), 10);
+
+        let mut reader = StringCharReader::new("éléphant rose");
+
+        // The index counts chars, the byte offset counts bytes
+        let loc = Location::beginning();
+        let info = ParseInfo::new(Span::new(loc, Location::with_byte_offset(1, 9, 8, 10)), 8);
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
+
+        // The next word starts right after it
+        let rule2 = StrMatcher::new(" rose");
+        let loc2 = Location::with_byte_offset(1, 9, 8, 10);
+        let info2 = ParseInfo::new(Span::new(loc2, Location::with_byte_offset(1, 14, 13, 15)), 5);
+        assert_eq!(rule2.test(&loc2, &mut reader).unwrap(), Some(info2));
+    }
+
+    #[test]
+    fn test_str_matcher_with_policy() {
+        let rule = StrMatcher::new("\tif\t");
+
+        let mut reader = StringCharReader::new("\tif\tx");
+        reader.set_location_policy(LocationPolicy::new().with_tab_width(4));
+
+        // Each tab takes 4 columns
+        let loc = Location::beginning();
+        let info = ParseInfo::new(Span::new(loc, Location::new(1, 11, 4)), 4);
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
+    }
 }
@@ -1,48 +1,98 @@
-use std::{fmt::Display, rc::Rc};
+use std::{
+    fmt::{Debug, Display, Formatter},
+    sync::Arc,
+};
 
-use crate::parser_lib::{CreateParseResult, Location, MatchStr, MatchToken, ParseResult, Stream};
+use crate::parser_lib::{
+    Emitter, Location, MatchStr, MatchToken, MatcherShape, Nesting, Notation, ParseContext, ParseResult, Span, Token,
+};
 
-/// In case of match, consumes the input to finish a token.
-#[derive(Debug)]
-pub struct TokenMatcher<R: MatchStr > {
-    value: Rc<dyn MatchToken<R>>,
+/// In case of match, consumes the input to finish a token, so that a streaming reader can drop it.
+///
+/// It can also emit a `Token` for each match, see `TokenMatcher::emitting`.
+///
+/// During a parse, the token can still be undone by a backtrack: when the sequence or lookahead containing it fails,
+/// the reader is rewound to before the token (see `Rewind`), and the token is removed. So the tokens are only given
+/// to the sink at the end of the parse, if it matches. Outside of a parse, the token is given right away and the
+/// input can't be given back.
+pub struct TokenMatcher<R: MatchStr> {
+    value: Arc<dyn MatchToken<R>>,
+    emit: Option<Emitter>,
 }
 
-impl<R: MatchStr > TokenMatcher<R> {
-    pub fn new(value: Rc<dyn MatchToken<R>>) -> Self {
-        Self { value }
+impl<R: MatchStr> TokenMatcher<R> {
+    pub fn new(value: Arc<dyn MatchToken<R>>) -> Self {
+        Self { value, emit: None }
+    }
+
+    /// Creates a matcher that also gives a token of the given type to the sink, with the span of each match.
+    pub fn emitting<T>(
+        value: Arc<dyn MatchToken<R>>,
+        token_type: T,
+        sink: impl Fn(Token<T>) + Send + Sync + 'static,
+    ) -> Self
+    where
+        T: Clone + PartialEq + Send + Sync + 'static,
+    {
+        let emit = move |span: &Span| sink(Token::new(span.clone(), token_type.clone()));
+        Self { value, emit: Some(Arc::new(emit)) }
     }
 }
 
-impl<R: MatchStr > MatchToken<R> for TokenMatcher<R> {
+impl<R: MatchStr> MatchToken<R> for TokenMatcher<R> {
     fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
-        if let Some(res) = self.value.test(loc, reader)? {
-            // Consume the input
-            reader.consume_nth(res.end().index() - 1);
-            Ok(Some(res))
-        } else {
-            // If the value didn't match, the result is valid
-            // Though, the span will be of length 0
-            ParseResult::empty(*loc)
+        let Some(res) = self.value.test(loc, reader)? else {
+            return Ok(None);
+        };
+
+        // Consume up to the end of the match, from the cursor: an inner token may already have consumed a part of it
+        let end = res.end().index();
+        let mut checkpoint = None;
+        if end > reader.index() {
+            checkpoint = Some(reader.checkpoint());
+            reader.consume_nth(end - reader.index() - 1);
         }
+        ParseContext::finish_token(res.span(), checkpoint, self.emit.as_ref());
+        Ok(Some(res))
+    }
+
+    fn fmt_notation(&self, f: &mut Formatter, notation: Notation, nesting: Nesting) -> std::fmt::Result {
+        self.value.fmt_notation(f, notation, nesting)
+    }
+
+    fn shape(&self) -> MatcherShape<'_, R> {
+        MatcherShape::Wrapper(&self.value)
+    }
+}
+
+/// The emitter is written as its address, so that the matchers emitting to different sinks are told apart.
+impl<R: MatchStr> Debug for TokenMatcher<R> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let emit = self.emit.as_ref().map(|emit| format!("{:p}", Arc::as_ptr(emit)));
+        f.debug_struct("TokenMatcher").field("value", &self.value).field("emit", &emit).finish()
     }
 }
 
-impl<R: MatchStr > Display for TokenMatcher<R> {
+impl<R: MatchStr> Display for TokenMatcher<R> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}", self.value)
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
-    use crate::parser_lib::{ParseInfo, Span, StrMatcher, StringCharReader, Stream};
+    use std::sync::Mutex;
+
+    use crate::{
+        choice, and, parser_lib::{GrammarBuilder, ParseInfo, Rule, StrMatcher, StringCharReader, Stream}, seq, word,
+    };
 
     use super::*;
 
     #[test]
     fn test_token_matcher() {
-        let rule = TokenMatcher::new(Rc::new(StrMatcher::new("hello")));
+        let rule = TokenMatcher::new(Arc::new(StrMatcher::new("hello")));
 
         let mut reader = StringCharReader::new("hello world");
 
@@ -55,6 +105,100 @@ mod tests {
 
         // Reader should now be at " world"
         assert_eq!(reader.peek(), Some(' '));
-        
+
+        // A failure is not an empty token
+        assert_eq!(rule.test(&(loc + 5), &mut reader).unwrap(), None);
+        assert_eq!(reader.index(), 5);
+    }
+
+    #[test]
+    fn test_token_matcher_cursor() {
+        let rule = TokenMatcher::new(Arc::new(StrMatcher::new("hello")));
+
+        // The match is consumed from the cursor, which is not always at the beginning of the input
+        let mut reader = StringCharReader::new("say hello world");
+        reader.consume_nth(3);
+        let loc = Location::beginning() + 4;
+        let res = rule.test(&loc, &mut reader).unwrap();
+        assert_eq!(res, Some(ParseInfo::new(Span::new(loc, loc + 5), 5)));
+        assert_eq!(reader.index(), 9);
+        assert_eq!(reader.peek(), Some(' '));
+
+        // The chars consumed by an inner token are not consumed twice
+        let inner = TokenMatcher::new(Arc::new(StrMatcher::new("he")));
+        let rule = TokenMatcher::new(Arc::new(Rule::seq(vec![&Rule::new(Arc::new(inner)), &Rule::word("llo")])));
+        let mut reader = StringCharReader::new("hello world");
+        rule.test(&Location::beginning(), &mut reader).unwrap();
+        assert_eq!(reader.peek(), Some(' '));
+    }
+
+    #[test]
+    fn test_token_matcher_emitting() {
+        let tokens = Arc::new(Mutex::new(Vec::new()));
+        let sink = tokens.clone();
+        let rule = TokenMatcher::emitting(Arc::new(StrMatcher::new("ab")), "word", move |token| {
+            sink.lock().unwrap().push(token)
+        });
+
+        let mut reader = StringCharReader::new("abab!");
+        let loc = Location::beginning();
+        rule.test(&loc, &mut reader).unwrap();
+        rule.test(&(loc + 2), &mut reader).unwrap();
+        rule.test(&(loc + 4), &mut reader).unwrap();
+        let tokens = tokens.lock().unwrap();
+        let spans: Vec<(usize, usize)> =
+            tokens.iter().map(|token| (token.span().start().index(), token.span().end().index())).collect();
+        assert_eq!(spans, [(0, 2), (2, 4)]);
+        assert_eq!(tokens[0].token_type(), &"word");
+    }
+
+    #[test]
+    fn test_token_matcher_backtrack() {
+        let tokens = Arc::new(Mutex::new(Vec::new()));
+        let sink = tokens.clone();
+        let ab = word!("ab").emit_token("ab", move |token| sink.lock().unwrap().push(token.span().clone()));
+        let loc = Location::beginning();
+
+        // The first alternative consumes "ab" then fails: the input is given back to the second one
+        let grammar = GrammarBuilder::new().save_root(choice!(seq!(ab, word!("!")), word!("abc")));
+        let mut reader = StringCharReader::new("abc");
+        assert_eq!(grammar.test(&loc, &mut reader).unwrap().unwrap().len(), 3);
+        assert_eq!(reader.index(), 0);
+        assert_eq!(tokens.lock().unwrap().len(), 0);
+
+        // Same in a lookahead, which never keeps what it matched
+        let grammar = GrammarBuilder::new().save_root(seq!(and!(ab), word!("abc")));
+        let mut reader = StringCharReader::new("abc");
+        assert_eq!(grammar.test(&loc, &mut reader).unwrap().unwrap().len(), 3);
+        assert_eq!(tokens.lock().unwrap().len(), 0);
+
+        // The tokens are only given to the sink when the whole parse matches
+        let grammar = GrammarBuilder::new().save_root(seq!(ab, word!("c")));
+        let mut reader = StringCharReader::new("abd");
+        assert_eq!(grammar.test(&loc, &mut reader).unwrap(), None);
+        assert_eq!(tokens.lock().unwrap().len(), 0);
+        let mut reader = StringCharReader::new("abc");
+        assert_eq!(grammar.test(&loc, &mut reader).unwrap().unwrap().len(), 3);
+        assert_eq!(reader.index(), 2);
+        assert_eq!(*tokens.lock().unwrap(), [Span::new(loc, loc + 2)]);
+    }
+
+    #[test]
+    fn test_token_matcher_left_recursion() {
+        let tokens = Arc::new(Mutex::new(Vec::new()));
+        let sink = tokens.clone();
+        let num = Rule::range('0', '9').emit_token("num", move |token| sink.lock().unwrap().push(token.span().clone()));
+
+        // list = list "," num | num: the rule is matched again from its start, after its seed consumed the input
+        let mut builder = GrammarBuilder::new();
+        let list = builder.rule("list");
+        let list = builder.define("list", choice!(seq!(list, word!(","), num), num));
+        let grammar = builder.save_root(list);
+
+        let mut reader = StringCharReader::new("1,2,3");
+        let loc = Location::beginning();
+        assert_eq!(grammar.test(&loc, &mut reader).unwrap().unwrap().len(), 5);
+        let starts: Vec<usize> = tokens.lock().unwrap().iter().map(|span| span.start().index()).collect();
+        assert_eq!(starts, [0, 2, 4]);
     }
 }
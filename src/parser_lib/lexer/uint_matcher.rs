@@ -0,0 +1,117 @@
+use std::fmt::Display;
+
+use crate::parser_lib::{CreateParseResult, Endianness, Location, LocationDelta, MatchBytes, MatchToken, ParseResult};
+
+/// Matcher for an unsigned integer field of a binary format, like a length or a version number.
+///
+/// The field takes `size` bytes (at most 8) in the given byte order.
+/// Its value must be in the inclusive range `min..=max`, which accepts any value by default.
+#[derive(Debug)]
+pub struct UintMatcher {
+    size: usize,
+    endianness: Endianness,
+    min: u64,
+    max: u64,
+}
+
+impl UintMatcher {
+    /// Create matcher for any value of the field
+    pub fn new(size: usize, endianness: Endianness) -> Self {
+        Self::between(size, endianness, 0, u64::MAX)
+    }
+
+    /// Create matcher for a field with an exact value
+    pub fn equal(size: usize, endianness: Endianness, value: u64) -> Self {
+        Self::between(size, endianness, value, value)
+    }
+
+    /// Create matcher for a field with a value in the given inclusive range
+    pub fn between(size: usize, endianness: Endianness, min: u64, max: u64) -> Self {
+        assert!(size <= 8, "Integer fields can't be longer than 8 bytes");
+        Self {
+            size,
+            endianness,
+            min,
+            max,
+        }
+    }
+}
+
+impl<R: MatchBytes> MatchToken<R> for UintMatcher {
+    fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
+        match reader.read_uint(loc.index(), self.size, self.endianness)? {
+            Some(value) if (self.min..=self.max).contains(&value) => {
+                ParseResult::matches(*loc, LocationDelta::of_bytes(self.size).apply_to(loc))
+            }
+            _ => ParseResult::no_match(),
+        }
+    }
+}
+
+impl Display for UintMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let order = match self.endianness {
+            Endianness::Little => "le",
+            Endianness::Big => "be",
+        };
+        write!(f, "u{}{}", self.size * 8, order)?;
+
+        // Only show the range if it is restricted
+        match (self.min, self.max) {
+            (0, u64::MAX) => Ok(()),
+            (min, max) if min == max => write!(f, "({})", min),
+            (min, max) => write!(f, "({}-{})", min, max),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::parser_lib::{BytesMatcher, ParseInfo, SequentialMatcher, SliceByteReader, Span};
+
+    use super::*;
+
+    #[test]
+    fn test_uint_matcher() {
+        let mut reader = SliceByteReader::new([0x01, 0x00, 0x00, 0x02]);
+        let loc = Location::beginning();
+
+        let rule = UintMatcher::equal(2, Endianness::Little, 1);
+        let info = ParseInfo::new(Span::new(loc, loc + 2), 2);
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
+        assert_eq!(rule.to_string(), "u16le(1)");
+
+        // Same bytes, other byte order
+        let rule = UintMatcher::equal(2, Endianness::Big, 1);
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), None);
+
+        let rule = UintMatcher::between(4, Endianness::Big, 0, 0x0100_0000);
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), None);
+        let rule = UintMatcher::new(4, Endianness::Big);
+        assert_eq!(rule.test(&loc, &mut reader).unwrap().unwrap().len(), 4);
+        assert_eq!(rule.to_string(), "u32be");
+
+        // The field doesn't fit in the input
+        assert_eq!(rule.test(&(loc + 1), &mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_binary_format() {
+        // Magic number, version 1 or 2, then a length
+        let header: SequentialMatcher<SliceByteReader<&[u8]>> = SequentialMatcher::new(vec![
+            Arc::new(BytesMatcher::new(b"ALM\0")),
+            Arc::new(UintMatcher::between(1, Endianness::Big, 1, 2)),
+            Arc::new(UintMatcher::new(4, Endianness::Little)),
+        ]);
+
+        let loc = Location::beginning();
+        let mut reader = SliceByteReader::new(&b"ALM\0\x02\x10\x00\x00\x00"[..]);
+        assert_eq!(header.test(&loc, &mut reader).unwrap().unwrap().len(), 9);
+        assert_eq!(reader.read_uint(5, 4, Endianness::Little), Ok(Some(16)));
+
+        let mut reader = SliceByteReader::new(&b"ALM\0\x03\x10\x00\x00\x00"[..]);
+        assert_eq!(header.test(&loc, &mut reader).unwrap(), None);
+    }
+}
@@ -1,29 +1,114 @@
-use std::{fmt::Display, rc::Rc};
+use std::{
+    fmt::{Display, Formatter},
+    sync::Arc,
+};
 
-use crate::parser_lib::{CreateParseResult, Location, MatchStr, MatchToken, ParseResult};
+use super::aho_corasick::AhoCorasick;
+use crate::parser_lib::{
+    CreateParseResult, Location, MatchStr, MatchToken, MatcherShape, Nesting, Notation, ParseContext, ParseResult,
+    ParserError,
+};
 
 /// Matcher that tries to match as many characters as possible until the given matcher matches
+///
+/// If the terminator is a string, the reader searches its first char, instead of testing the terminator at each char.
+/// If it is a choice between strings, they are all searched in a single pass, see `AhoCorasick`.
 #[derive(Debug)]
 pub struct UntilMatcher<R: MatchStr> {
-    until: Rc<dyn MatchToken<R>>,
+    until: Arc<dyn MatchToken<R>>,
     min: usize,
+    /// How to find the terminator without testing it at each char, if it is made of strings.
+    search: Option<Search>,
+}
+
+/// Search of a terminator made of strings.
+#[derive(Debug)]
+enum Search {
+    /// The terminator is a string starting with this char: only its occurrences are tested.
+    FirstChar(char),
+    /// The terminator is a choice between these strings: it matches where one of them starts.
+    Strings(AhoCorasick),
 }
 
 impl<R: MatchStr> UntilMatcher<R> {
-    pub fn new(until: Rc<dyn MatchToken<R>>, min: usize) -> Self {
-        Self { until, min }
+    pub fn new(until: Arc<dyn MatchToken<R>>, min: usize) -> Self {
+        let search = Self::search_of(until.as_ref());
+        Self { until, min, search }
+    }
+
+    /// Returns how to search the terminator, if it is made of strings.
+    fn search_of(until: &dyn MatchToken<R>) -> Option<Search> {
+        let strings: Vec<&str> = match until.shape() {
+            MatcherShape::Literal(terminator) => return terminator.chars().next().map(Search::FirstChar),
+            MatcherShape::Keywords(words) => words.iter().map(String::as_str).collect(),
+            MatcherShape::Choice(children) => children
+                .iter()
+                .map(|child| match child.shape() {
+                    MatcherShape::Literal(value) => Some(value),
+                    _ => None,
+                })
+                .collect::<Option<_>>()?,
+            _ => return None,
+        };
+
+        // An empty string matches everywhere
+        if strings.is_empty() || strings.iter().any(|string| string.is_empty()) {
+            return None;
+        }
+        Some(Search::Strings(AhoCorasick::new(&strings)))
+    }
+
+    /// Returns the end of the match, jumping from one possible start of the terminator to the next one.
+    fn scan(&self, search: &Search, loc: &Location, reader: &mut R) -> Result<Location, ParserError> {
+        let mut end_loc = *loc;
+        loop {
+            // The chars before the next candidate can't start the terminator
+            let next = match search {
+                Search::FirstChar(first) => reader.find_char(end_loc.index(), *first)?,
+                Search::Strings(strings) => strings.find(end_loc.index(), reader)?,
+            };
+            let skipped = match next {
+                Some(next) if next == end_loc.index() => None,
+                Some(next) => Some(next - end_loc.index()),
+                // Without candidate, the match takes the rest of the input (a max of 0 is no limit)
+                None => Some(0),
+            };
+            if let Some(max) = skipped {
+                end_loc = reader.match_range(end_loc.index(), '\0', char::MAX, max)?.apply_to(&end_loc);
+            }
+            // Where one of the strings starts, the choice between them matches
+            if next.is_none() || matches!(search, Search::Strings(_)) || self.until.test(&end_loc, reader)?.is_some() {
+                return Ok(end_loc);
+            }
+
+            // Only the start of the terminator is there: take its first char
+            end_loc = reader.match_range(end_loc.index(), '\0', char::MAX, 1)?.apply_to(&end_loc);
+        }
     }
 }
 
 impl<R: MatchStr> MatchToken<R> for UntilMatcher<R> {
     fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
+        if let Some(search) = &self.search {
+            let end_loc = self.scan(search, loc, reader)?;
+            if end_loc.index() - loc.index() >= self.min {
+                return ParseResult::matches(*loc, end_loc);
+            }
+            return ParseResult::no_match();
+        }
+
         let mut count = 0;
         let mut end_loc = *loc;
+        let mark = ParseContext::mark();
 
         // Try to match the matcher at the end until it works
-        while let Ok(None) = self.until.test(&end_loc, reader) {
-            // If the EOF is reached, stop the match there
-            if reader.is_end_of_input(end_loc.index())? {
+        loop {
+            // It is not consumed, so its nodes are not kept
+            let found = self.until.test(&end_loc, reader)?.is_some();
+            ParseContext::rollback(mark, reader)?;
+
+            // If the EOF is reached, stop the match there too
+            if found || reader.is_end_of_input(end_loc.index())? {
                 break;
             }
 
@@ -31,12 +116,9 @@ impl<R: MatchStr> MatchToken<R> for UntilMatcher<R> {
             count += 1;
 
             // The end location is thus further
-            // We have to check if we are at a new line or not to increment the location
-            if reader.is_newline(end_loc.index())? {
-                end_loc.add_line();
-            } else {
-                end_loc = end_loc + 1;
-            }
+            // Match any single char to know how to increment the location (new line, multi-byte char...)
+            let delta = reader.match_range(end_loc.index(), '\0', char::MAX, 1)?;
+            end_loc = delta.apply_to(&end_loc);
         }
 
         // If we got at least min matches, we have a match
@@ -46,6 +128,22 @@ impl<R: MatchStr> MatchToken<R> for UntilMatcher<R> {
             ParseResult::no_match()
         }
     }
+
+    fn fmt_notation(&self, f: &mut Formatter, notation: Notation, nesting: Nesting) -> std::fmt::Result {
+        // Any char that doesn't start the terminator
+        notation.write_repetition(f, nesting, self.min, 0, &|f, _| {
+            write!(f, "(!")?;
+            self.until.fmt_notation(f, notation, Nesting::Operand)?;
+            write!(f, "{}.)", notation.sequence_separator())
+        })
+    }
+
+    fn shape(&self) -> MatcherShape<'_, R> {
+        MatcherShape::Until {
+            until: &self.until,
+            min: self.min,
+        }
+    }
 }
 
 impl<R: MatchStr> Display for UntilMatcher<R> {
@@ -59,14 +157,17 @@ impl<R: MatchStr> Display for UntilMatcher<R> {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
-    use crate::parser_lib::{ParseInfo, Span, StrMatcher, StringCharReader};
+    use crate::parser_lib::{
+        ChoiceMatcher, KeywordSetMatcher, ParseInfo, SequentialMatcher, Span, StrMatcher, StringCharReader,
+    };
 
     use super::*;
 
     #[test]
     fn test_until_matcher() {
-        let rule = UntilMatcher::new(Rc::new(StrMatcher::new("a")), 1);
+        let rule = UntilMatcher::new(Arc::new(StrMatcher::new("a")), 1);
 
         let mut reader = StringCharReader::new("hello, a world");
 
@@ -92,7 +193,7 @@ mod tests {
         assert_eq!(rule.test(&loc, &mut reader).unwrap(), None);
 
         // Should match empty string
-        let rule2 = UntilMatcher::new(Rc::new(StrMatcher::new("a")), 0);
+        let rule2 = UntilMatcher::new(Arc::new(StrMatcher::new("a")), 0);
         let loc = Location::beginning();
         let info = ParseInfo::new(Span::new(loc, loc), 0);
         assert_eq!(rule2.test(&loc, &mut reader).is_ok(), true);
@@ -106,4 +207,65 @@ mod tests {
         assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
 
     }
+
+    #[test]
+    fn test_until_with_new_lines() {
+        let rule = UntilMatcher::new(Arc::new(StrMatcher::new("*/")), 0);
+        let mut reader = StringCharReader::new("a\nbc\n*/");
+
+        // The new lines are crossed and the location follows them
+        let loc = Location::beginning();
+        let info = ParseInfo::new(Span::new(loc, Location::new(3, 1, 5)), 5);
+        assert_eq!(rule.test(&loc, &mut reader).unwrap(), Some(info));
+    }
+
+    #[test]
+    fn test_until_string_scan() {
+        // A string terminator is searched, other terminators are tested at each char
+        let terminator = Arc::new(StrMatcher::new("*/"));
+        let scanned = UntilMatcher::new(terminator.clone(), 1);
+        let tested = UntilMatcher::new(Arc::new(SequentialMatcher::new(vec![terminator])), 1);
+
+        let inputs = ["a * b\n**/ c", "no end", "*/", "é*\n*", "x*/*/", ""];
+        let loc = Location::beginning();
+        for input in inputs {
+            for start in [loc, loc + 1] {
+                let expected = tested.test(&start, &mut StringCharReader::new(input)).unwrap();
+                assert_eq!(scanned.test(&start, &mut StringCharReader::new(input)).unwrap(), expected, "{:?}", input);
+            }
+        }
+
+        let mut reader = StringCharReader::new("a * b\n**/ c");
+        let info = ParseInfo::new(Span::new(loc, Location::with_byte_offset(2, 2, 7, 7)), 7);
+        assert_eq!(scanned.test(&loc, &mut reader).unwrap(), Some(info));
+    }
+
+    #[test]
+    fn test_until_strings_scan() {
+        // A choice between strings is searched in a single pass
+        let words = ["*/", "-->", "end", "ending"];
+        let choice = Arc::new(ChoiceMatcher::new(words.iter().map(|w| Arc::new(StrMatcher::new(*w)) as _).collect()));
+        let scanned: [UntilMatcher<StringCharReader>; 2] = [
+            UntilMatcher::new(choice.clone(), 1),
+            UntilMatcher::new(Arc::new(KeywordSetMatcher::new(&words)), 1),
+        ];
+        assert!(scanned.iter().all(|rule| rule.search.is_some()));
+        let tested = UntilMatcher::new(Arc::new(SequentialMatcher::new(vec![choice])), 1);
+        assert!(tested.search.is_none());
+
+        let inputs = ["a -- b -*-->", "no en\nd", "pending */", "*/", "--->", "é-\n->", ""];
+        let loc = Location::beginning();
+        for input in inputs {
+            for start in [loc, loc + 1] {
+                let expected = tested.test(&start, &mut StringCharReader::new(input)).unwrap();
+                for rule in &scanned {
+                    assert_eq!(rule.test(&start, &mut StringCharReader::new(input)).unwrap(), expected, "{:?}", input);
+                }
+            }
+        }
+
+        let mut reader = StringCharReader::new("x\n-*-->");
+        let info = ParseInfo::new(Span::new(loc, Location::with_byte_offset(2, 3, 4, 4)), 4);
+        assert_eq!(scanned[0].test(&loc, &mut reader).unwrap(), Some(info));
+    }
 }
@@ -1,8 +1,10 @@
+mod byte_reader;
 mod char_reader;
 mod lexer;
 mod parser;
 mod types;
 
+pub use byte_reader::*;
 pub use char_reader::*;
 pub use lexer::*;
 pub use types::*;
@@ -0,0 +1,140 @@
+use std::fmt::Write;
+
+use crate::parser_lib::{Grammar, InNotation, MatchStr, MatchToken, MatcherShape, Notation};
+
+impl<R: 'static + MatchStr> Grammar<R> {
+    /// Writes the matcher tree of the grammar as a Graphviz graph (DOT language), to visualize it.
+    ///
+    /// Each named rule is a bold box pointing to its matchers. The references to named rules point back to them
+    /// with dashed edges, instead of repeating their matchers.
+    pub fn to_dot(&self) -> String {
+        let mut graph = DotGraph { out: String::new(), next_id: 0 };
+
+        graph.out.push_str("digraph grammar {\n");
+        graph.out.push_str("    node [fontname=\"monospace\"];\n");
+
+        for (name, rule) in self.productions() {
+            graph.line(format_args!("{} [label=\"{}\", shape=box, style=bold];", rule_id(name), escape(name)));
+            let child = graph.add(rule);
+            graph.line(format_args!("{} -> {};", rule_id(name), child));
+        }
+
+        graph.out.push_str("}\n");
+        graph.out
+    }
+}
+
+/// Graph being written.
+struct DotGraph {
+    out: String,
+    /// Identifier of the next matcher node.
+    next_id: usize,
+}
+
+impl DotGraph {
+    fn line(&mut self, line: std::fmt::Arguments) {
+        // Writing in a string can't fail
+        let _ = writeln!(self.out, "    {}", line);
+    }
+
+    /// Writes the node of a matcher and its children. Returns its identifier.
+    fn add<R>(&mut self, matcher: &dyn MatchToken<R>) -> String {
+        let id = format!("n{}", self.next_id);
+        self.next_id += 1;
+
+        let (label, children) = match matcher.shape() {
+            MatcherShape::Terminal
+            | MatcherShape::Literal(_)
+            | MatcherShape::Keywords(_)
+            | MatcherShape::Range { .. } => {
+                let label = InNotation(matcher, Notation::Ebnf).to_string();
+                self.line(format_args!("{} [label=\"{}\", shape=box, style=rounded];", id, escape(&label)));
+                return id;
+            }
+            MatcherShape::Reference(name) => {
+                self.line(format_args!("{} [label=\"{}\", shape=box];", id, escape(name)));
+                self.line(format_args!("{} -> {} [style=dashed];", id, rule_id(name)));
+                return id;
+            }
+            MatcherShape::Sequence(children) => ("seq".to_string(), children.iter().collect()),
+            MatcherShape::Choice(children) => ("choice".to_string(), children.iter().collect()),
+            MatcherShape::Repetition { value, min, max } => {
+                let label = match (min, max) {
+                    (0, 0) => "*".to_string(),
+                    (1, 0) => "+".to_string(),
+                    (min, 0) => format!("{{{},}}", min),
+                    (min, max) if min == max => format!("{{{}}}", min),
+                    (min, max) => format!("{{{},{}}}", min, max),
+                };
+                (label, vec![value])
+            }
+            MatcherShape::Optional(value) => ("?".to_string(), vec![value]),
+            MatcherShape::Not(value) => ("!".to_string(), vec![value]),
+            MatcherShape::And(value) => ("&".to_string(), vec![value]),
+            MatcherShape::Until { until, min } => (format!("until (min {})", min), vec![until]),
+            MatcherShape::Error(sync) => ("error until".to_string(), vec![sync]),
+            MatcherShape::Wrapper(value) => ("token".to_string(), vec![value]),
+            MatcherShape::Label { label, value } => (format!("{}:", label), vec![value]),
+        };
+
+        self.line(format_args!("{} [label=\"{}\", shape=ellipse];", id, escape(&label)));
+        for child in children {
+            let child_id = self.add(child.as_ref());
+            self.line(format_args!("{} -> {};", id, child_id));
+        }
+        id
+    }
+}
+
+/// Returns the identifier of the node of a named rule.
+fn rule_id(name: &str) -> String {
+    format!("\"rule {}\"", escape(name))
+}
+
+/// Escapes a label for a quoted DOT string.
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::StringCharReader;
+
+    use super::*;
+
+    #[test]
+    fn test_to_dot() {
+        let grammar = Grammar::<StringCharReader>::from_ebnf("list = \"(\" item* \")\" ; item = list | [a-z]+ ;").unwrap();
+
+        assert_eq!(
+            grammar.to_dot(),
+            concat!(
+                "digraph grammar {\n",
+                "    node [fontname=\"monospace\"];\n",
+                "    \"rule list\" [label=\"list\", shape=box, style=bold];\n",
+                "    n0 [label=\"seq\", shape=ellipse];\n",
+                "    n1 [label=\"\\\"(\\\"\", shape=box, style=rounded];\n",
+                "    n0 -> n1;\n",
+                "    n2 [label=\"*\", shape=ellipse];\n",
+                "    n3 [label=\"item\", shape=box];\n",
+                "    n3 -> \"rule item\" [style=dashed];\n",
+                "    n2 -> n3;\n",
+                "    n0 -> n2;\n",
+                "    n4 [label=\"\\\")\\\"\", shape=box, style=rounded];\n",
+                "    n0 -> n4;\n",
+                "    \"rule list\" -> n0;\n",
+                "    \"rule item\" [label=\"item\", shape=box, style=bold];\n",
+                "    n5 [label=\"choice\", shape=ellipse];\n",
+                "    n6 [label=\"list\", shape=box];\n",
+                "    n6 -> \"rule list\" [style=dashed];\n",
+                "    n5 -> n6;\n",
+                "    n7 [label=\"+\", shape=ellipse];\n",
+                "    n8 [label=\"[a-z]\", shape=box, style=rounded];\n",
+                "    n7 -> n8;\n",
+                "    n5 -> n7;\n",
+                "    \"rule item\" -> n5;\n",
+                "}\n",
+            )
+        );
+    }
+}
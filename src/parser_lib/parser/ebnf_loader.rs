@@ -0,0 +1,610 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    fmt::{Display, Formatter},
+    fs, io,
+    path::Path,
+    sync::Arc,
+};
+
+use crate::parser_lib::{Grammar, GrammarBuilder, Location, LocationPolicy, MatchStr, Rule};
+
+/// Error returned when a grammar text can't be loaded.
+#[derive(Debug, Clone)]
+pub enum GrammarLoadError {
+    /// The text doesn't follow the grammar syntax. `found` is None at the end of the text.
+    Syntax {
+        expected: &'static str,
+        found: Option<char>,
+        location: Location,
+    },
+    /// A rule is used but never defined. The location is the one of its first use.
+    UndefinedRule { name: String, location: Location },
+    /// A rule is defined twice.
+    DuplicateRule { name: String, location: Location },
+    /// The text doesn't define any rule.
+    NoRules,
+    /// The grammar file could not be read.
+    Io(Arc<io::Error>),
+}
+
+impl From<io::Error> for GrammarLoadError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(Arc::new(err))
+    }
+}
+
+impl Display for GrammarLoadError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            GrammarLoadError::Syntax { expected, found: Some(c), location }
+                => write!(f, "{}: expected {}, found {:?}.", location, expected, c),
+            GrammarLoadError::Syntax { expected, found: None, location }
+                => write!(f, "{}: expected {}, found the end of the grammar.", location, expected),
+            GrammarLoadError::UndefinedRule { name, location }
+                => write!(f, "{}: the rule `{}` is not defined.", location, name),
+            GrammarLoadError::DuplicateRule { name, location }
+                => write!(f, "{}: the rule `{}` is already defined.", location, name),
+            GrammarLoadError::NoRules
+                => write!(f, "The grammar doesn't define any rule."),
+            GrammarLoadError::Io(err)
+                => write!(f, "Could not read the grammar: {}", err),
+        }
+    }
+}
+
+impl Error for GrammarLoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GrammarLoadError::Io(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl<R: 'static + MatchStr> Grammar<R> {
+    /// Loads a grammar written in EBNF or PEG notation, so that it can be changed without recompiling.
+    ///
+    /// The grammar is a list of rules: `name = expression ;` (the `;` is optional, and `::=` or `<-` can be used
+    /// instead of `=`). The first rule is the root. Expressions are made of:
+    /// - `"text"` or `'text'`: an exact string, with `\n`, `\t`, `\u{...}`... escapes
+    /// - `[a-z_]`: a char in a class, or not in it with `[^...]`
+    /// - `.`: any char
+    /// - `name`: another rule, possibly defined later or recursive (even left-recursive)
+    /// - `a b` or `a, b`: a sequence
+    /// - `a | b` or `a / b`: a choice
+    /// - `a*`, `a+`, `a?`, `a{n}`, `a{n,}`, `a{n,m}`: repetitions
+    /// - `!a`, `&a`: negative and positive lookahead
+    /// - `( ... )`: a group
+    ///
+    /// Comments start with `#` or `//` until the end of the line, or are written between `(*` and `*)`.
+    pub fn from_ebnf(source: &str) -> Result<Self, GrammarLoadError> {
+        EbnfParser::new(source).parse()
+    }
+
+    /// Loads a grammar from a file written in EBNF or PEG notation. See `from_ebnf` for the syntax.
+    pub fn from_ebnf_file<P: AsRef<Path>>(path: P) -> Result<Self, GrammarLoadError> {
+        let source = fs::read_to_string(path)?;
+        Self::from_ebnf(&source)
+    }
+}
+
+/// Parser of the grammar text.
+///
+/// The rules can't build syntax trees yet, so the meta-grammar is parsed by hand (recursive descent).
+struct EbnfParser<R: MatchStr> {
+    chars: Vec<char>,
+    pos: usize,
+    loc: Location,
+    builder: GrammarBuilder<R>,
+    defined: HashSet<String>,
+    /// Rules used in the grammar, with the location of their first use.
+    used: Vec<(String, Location)>,
+}
+
+impl<R: 'static + MatchStr> EbnfParser<R> {
+    fn new(source: &str) -> Self {
+        Self {
+            chars: source.chars().collect(),
+            pos: 0,
+            loc: Location::beginning(),
+            builder: GrammarBuilder::new(),
+            defined: HashSet::new(),
+            used: Vec::new(),
+        }
+    }
+
+    fn parse(mut self) -> Result<Grammar<R>, GrammarLoadError> {
+        let mut root = None;
+
+        self.skip_blanks()?;
+        while self.peek().is_some() {
+            let rule = self.parse_production()?;
+            root.get_or_insert(rule);
+            self.skip_blanks()?;
+        }
+
+        let root = root.ok_or(GrammarLoadError::NoRules)?;
+
+        // All the used rules must be defined
+        if let Some((name, location)) = self.used.iter().find(|(name, _)| !self.defined.contains(name)) {
+            return Err(GrammarLoadError::UndefinedRule {
+                name: name.clone(),
+                location: *location,
+            });
+        }
+
+        Ok(self.builder.save_root(root))
+    }
+
+    // --- Productions and expressions ---
+
+    /// production = name ("=" | "::=" | "<-") expression ";"?
+    fn parse_production(&mut self) -> Result<Rule<R>, GrammarLoadError> {
+        let location = self.loc;
+        let name = self.parse_name()?;
+        if !self.defined.insert(name.clone()) {
+            return Err(GrammarLoadError::DuplicateRule { name, location });
+        }
+
+        self.skip_blanks()?;
+        if !self.eat_definition() {
+            return Err(self.error("`=`, `::=` or `<-`"));
+        }
+
+        let rule = self.parse_choice()?;
+        self.skip_blanks()?;
+        self.eat(';');
+
+        Ok(self.builder.define(&name, rule))
+    }
+
+    /// choice = sequence (("|" | "/") sequence)*
+    fn parse_choice(&mut self) -> Result<Rule<R>, GrammarLoadError> {
+        let mut alternatives = vec![self.parse_sequence()?];
+
+        loop {
+            self.skip_blanks()?;
+            if !self.eat('|') && !self.eat('/') {
+                break;
+            }
+            alternatives.push(self.parse_sequence()?);
+        }
+
+        Ok(Self::combine(alternatives, Rule::choice))
+    }
+
+    /// sequence = prefix ("," ? prefix)*
+    fn parse_sequence(&mut self) -> Result<Rule<R>, GrammarLoadError> {
+        let mut items = vec![self.parse_prefix()?];
+
+        loop {
+            self.skip_blanks()?;
+            // The commas of ISO EBNF are optional
+            if !self.eat(',') && !self.starts_item() {
+                break;
+            }
+            items.push(self.parse_prefix()?);
+        }
+
+        Ok(Self::combine(items, Rule::seq))
+    }
+
+    /// prefix = ("!" | "&")? postfix
+    fn parse_prefix(&mut self) -> Result<Rule<R>, GrammarLoadError> {
+        self.skip_blanks()?;
+        if self.eat('!') {
+            Ok(self.parse_prefix()?.not())
+        } else if self.eat('&') {
+            Ok(self.parse_prefix()?.and())
+        } else {
+            self.parse_postfix()
+        }
+    }
+
+    /// postfix = primary ("*" | "+" | "?" | "{" n ("," m?)? "}")*
+    fn parse_postfix(&mut self) -> Result<Rule<R>, GrammarLoadError> {
+        let mut rule = self.parse_primary()?;
+
+        loop {
+            // The operators must directly follow the expression
+            rule = match self.peek() {
+                Some('*') => {
+                    self.advance();
+                    rule.at_least(0)
+                }
+                Some('+') => {
+                    self.advance();
+                    rule.at_least(1)
+                }
+                Some('?') => {
+                    self.advance();
+                    rule.optional()
+                }
+                Some('{') => {
+                    self.advance();
+                    let min = self.parse_number()?;
+                    let max = if self.eat(',') {
+                        match self.peek() {
+                            Some('}') => None,
+                            _ => Some(self.parse_number()?),
+                        }
+                    } else {
+                        Some(min)
+                    };
+                    self.expect('}', "`}`")?;
+
+                    match max {
+                        Some(max) => rule.repeat_between(min, max),
+                        None => rule.at_least(min),
+                    }
+                }
+                _ => return Ok(rule),
+            };
+        }
+    }
+
+    /// primary = name | string | class | "." | "(" choice ")"
+    fn parse_primary(&mut self) -> Result<Rule<R>, GrammarLoadError> {
+        match self.peek() {
+            Some('"') | Some('\'') => self.parse_string(),
+            Some('[') => self.parse_class(),
+            Some('.') => {
+                self.advance();
+                Ok(any_char())
+            }
+            Some('(') => {
+                self.advance();
+                let rule = self.parse_choice()?;
+                self.skip_blanks()?;
+                self.expect(')', "`)`")?;
+                Ok(rule)
+            }
+            Some(c) if is_name_start(c) => {
+                let location = self.loc;
+                let name = self.parse_name()?;
+                if !self.used.iter().any(|(n, _)| *n == name) {
+                    self.used.push((name.clone(), location));
+                }
+                Ok(self.builder.rule(&name))
+            }
+            _ => Err(self.error("an expression")),
+        }
+    }
+
+    /// Returns true if the next char starts another item of a sequence.
+    ///
+    /// A name followed by a definition starts the next production, which is not part of the sequence.
+    fn starts_item(&mut self) -> bool {
+        match self.peek() {
+            Some('"' | '\'' | '[' | '.' | '(' | '!' | '&') => true,
+            Some(c) if is_name_start(c) => {
+                let (pos, loc) = (self.pos, self.loc);
+                let is_definition = self.parse_name().is_ok()
+                    && self.skip_blanks().is_ok()
+                    && self.eat_definition();
+                self.pos = pos;
+                self.loc = loc;
+                !is_definition
+            }
+            _ => false,
+        }
+    }
+
+    /// Builds a rule from several ones, or returns the rule itself if there is only one.
+    fn combine(mut rules: Vec<Rule<R>>, combinator: fn(Vec<&Rule<R>>) -> Rule<R>) -> Rule<R> {
+        if rules.len() == 1 {
+            return rules.remove(0);
+        }
+        combinator(rules.iter().collect())
+    }
+
+    // --- Terminals ---
+
+    fn parse_name(&mut self) -> Result<String, GrammarLoadError> {
+        match self.peek() {
+            Some(c) if is_name_start(c) => {}
+            _ => return Err(self.error("a rule name")),
+        }
+
+        let mut name = String::new();
+        while let Some(c) = self.peek().filter(|c| c.is_alphanumeric() || *c == '_') {
+            name.push(c);
+            self.advance();
+        }
+        Ok(name)
+    }
+
+    fn parse_number(&mut self) -> Result<usize, GrammarLoadError> {
+        let mut number: Option<usize> = None;
+        while let Some(digit) = self.peek().and_then(|c| c.to_digit(10)) {
+            number = Some(number.unwrap_or(0) * 10 + digit as usize);
+            self.advance();
+        }
+        number.ok_or_else(|| self.error("a number"))
+    }
+
+    fn parse_string(&mut self) -> Result<Rule<R>, GrammarLoadError> {
+        let quote = self.advance().unwrap();
+        let mut value = String::new();
+
+        loop {
+            match self.peek() {
+                Some(c) if c == quote => {
+                    self.advance();
+                    break;
+                }
+                Some('\\') => value.push(self.parse_escape()?),
+                Some(c) => {
+                    value.push(c);
+                    self.advance();
+                }
+                None => return Err(self.error("the end of the string")),
+            }
+        }
+
+        Ok(Rule::word(value))
+    }
+
+    /// class = "[" "^"? (char ("-" char)?)+ "]"
+    fn parse_class(&mut self) -> Result<Rule<R>, GrammarLoadError> {
+        self.advance();
+        let negated = self.eat('^');
+        let mut ranges = Vec::new();
+
+        while !self.eat(']') {
+            let start = self.parse_class_char()?;
+            let end = if self.peek() == Some('-') && self.peek_nth(1) != Some(']') {
+                self.advance();
+                self.parse_class_char()?
+            } else {
+                start
+            };
+            ranges.push(Rule::range(start, end));
+        }
+
+        if ranges.is_empty() {
+            return Err(self.error("a char in the class"));
+        }
+
+        let class = Self::combine(ranges, Rule::choice);
+        if negated {
+            Ok(Rule::seq(vec![&class.not(), &any_char()]))
+        } else {
+            Ok(class)
+        }
+    }
+
+    fn parse_class_char(&mut self) -> Result<char, GrammarLoadError> {
+        match self.peek() {
+            Some('\\') => self.parse_escape(),
+            Some(c) => {
+                self.advance();
+                Ok(c)
+            }
+            None => Err(self.error("`]`")),
+        }
+    }
+
+    /// Parses an escape sequence, starting at the backslash.
+    fn parse_escape(&mut self) -> Result<char, GrammarLoadError> {
+        self.advance();
+        let c = match self.advance() {
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('0') => '\0',
+            Some('u') => {
+                self.expect('{', "`{`")?;
+                let mut code = 0;
+                while let Some(digit) = self.peek().and_then(|c| c.to_digit(16)) {
+                    code = code * 16 + digit;
+                    self.advance();
+                }
+                let c = char::from_u32(code).ok_or_else(|| self.error("a valid char code"))?;
+                self.expect('}', "`}`")?;
+                c
+            }
+            // Quotes, backslashes, brackets...
+            Some(c) => c,
+            None => return Err(self.error("an escaped char")),
+        };
+        Ok(c)
+    }
+
+    // --- Cursor ---
+
+    fn peek(&self) -> Option<char> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.chars.get(self.pos + n).copied()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        self.loc.increment_for(c, LocationPolicy::default());
+        Some(c)
+    }
+
+    /// Consumes the given char if it is the next one.
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.advance();
+            return true;
+        }
+        false
+    }
+
+    fn eat_str(&mut self, expected: &str) -> bool {
+        if !expected.chars().enumerate().all(|(i, c)| self.peek_nth(i) == Some(c)) {
+            return false;
+        }
+        expected.chars().for_each(|_| {
+            self.advance();
+        });
+        true
+    }
+
+    fn eat_definition(&mut self) -> bool {
+        self.eat_str("::=") || self.eat_str("<-") || self.eat('=')
+    }
+
+    fn expect(&mut self, expected: char, description: &'static str) -> Result<(), GrammarLoadError> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.error(description))
+        }
+    }
+
+    /// Skips whitespaces and comments.
+    fn skip_blanks(&mut self) -> Result<(), GrammarLoadError> {
+        loop {
+            match (self.peek(), self.peek_nth(1)) {
+                (Some(c), _) if c.is_whitespace() => {
+                    self.advance();
+                }
+                (Some('#'), _) | (Some('/'), Some('/')) => {
+                    while self.advance().is_some_and(|c| c != '\n') {}
+                }
+                (Some('('), Some('*')) => {
+                    self.advance();
+                    self.advance();
+                    while !self.eat_str("*)") {
+                        if self.advance().is_none() {
+                            return Err(self.error("the end of the comment `*)`"));
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn error(&self, expected: &'static str) -> GrammarLoadError {
+        GrammarLoadError::Syntax {
+            expected,
+            found: self.peek(),
+            location: self.loc,
+        }
+    }
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+/// Matches any char.
+fn any_char<R: 'static + MatchStr>() -> Rule<R> {
+    Rule::range('\0', char::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::{MatchToken, StringCharReader, SyntaxError};
+
+    use super::*;
+
+    /// Returns the number of chars matched by the grammar at the beginning of the input.
+    fn match_len(grammar: &Grammar<StringCharReader>, input: &str) -> Option<usize> {
+        let mut reader = StringCharReader::new(input);
+        grammar.test(&Location::beginning(), &mut reader).unwrap().map(|info| info.len())
+    }
+
+    #[test]
+    fn test_load_ebnf() {
+        let grammar = Grammar::from_ebnf(
+            r#"
+            # Arithmetic expressions
+            expr   = term, (("+" | "-"), term)* ;
+            term   = factor (('*' | '/') factor)* ;
+            factor = number | "(" expr ")" ;   (* recursive *)
+            number ::= [0-9]+ ;
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(match_len(&grammar, "2*(3+4)"), Some(7));
+        assert_eq!(match_len(&grammar, "((1))-22/3"), Some(10));
+        assert_eq!(match_len(&grammar, "(1+2"), None);
+        assert_eq!(grammar.rule("expr").unwrap().to_string(), "(term ((\"+\" | \"-\") term)*)");
+        assert_eq!(grammar.rule("number").unwrap().to_string(), "[0-9]+");
+    }
+
+    #[test]
+    fn test_load_peg() {
+        let grammar = Grammar::from_ebnf(
+            r#"
+            // Identifiers that are not keywords
+            ident   <- !keyword [a-zA-Z_] [a-zA-Z0-9_]*
+            keyword <- ("if" / "else") ![a-zA-Z0-9_]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(match_len(&grammar, "iffy"), Some(4));
+        assert_eq!(match_len(&grammar, "if x"), None);
+
+        // Strings, escapes, negated classes and bounded repetitions
+        let grammar = Grammar::from_ebnf(r#"s = '"' ([^"\\] | '\\' .){0,3} "\u{22}""#).unwrap();
+        assert_eq!(match_len(&grammar, r#""a\"b" "#), Some(6));
+        assert_eq!(match_len(&grammar, r#""abcd""#), None);
+    }
+
+    #[test]
+    fn test_load_left_recursive() {
+        let grammar = Grammar::from_ebnf(
+            r#"
+            expr    = expr "-" primary | primary ;
+            primary = call | [0-9]+ ;
+            call    = primary "()" ;
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(match_len(&grammar, "10-2-3"), Some(6));
+        assert_eq!(match_len(&grammar, "1()()-2()"), Some(9));
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let result = Grammar::<StringCharReader>::from_ebnf("a = b ;\nb = c | 'x' ;");
+        assert!(matches!(
+            result,
+            Err(GrammarLoadError::UndefinedRule { name, location }) if name == "c" && location == Location::new(2, 5, 12)
+        ));
+
+        let result = Grammar::<StringCharReader>::from_ebnf("a = 'x' ;\na = 'y' ;");
+        assert!(matches!(result, Err(GrammarLoadError::DuplicateRule { name, .. }) if name == "a"));
+
+        let result = Grammar::<StringCharReader>::from_ebnf("a = ('x' ;");
+        assert!(matches!(
+            result,
+            Err(GrammarLoadError::Syntax { expected: "`)`", found: Some(';'), location }) if location == Location::new(1, 10, 9)
+        ));
+
+        let result = Grammar::<StringCharReader>::from_ebnf("# Nothing here");
+        assert!(matches!(result, Err(GrammarLoadError::NoRules)));
+
+        let result = Grammar::<StringCharReader>::from_ebnf_file("resources/test_files/missing.ebnf");
+        assert!(matches!(result, Err(GrammarLoadError::Io(_))));
+    }
+
+    #[test]
+    fn test_load_ebnf_file() {
+        let grammar = Grammar::from_ebnf_file("resources/test_files/list.ebnf").unwrap();
+        assert_eq!(match_len(&grammar, "[1, [2, 3], []]"), Some(15));
+
+        // The grammar owns its rules: a copy of the root can't be used without it
+        let root = grammar.rule("list").unwrap().clone();
+        drop(grammar);
+        let mut reader = StringCharReader::new("[]");
+        assert_eq!(
+            root.test(&Location::beginning(), &mut reader),
+            Err(SyntaxError::UnresolvedRule("ws".to_string()).into())
+        );
+    }
+}
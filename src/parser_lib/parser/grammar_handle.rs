@@ -0,0 +1,187 @@
+use std::{
+    fmt::{Debug, Formatter},
+    path::Path,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use crate::parser_lib::{Grammar, MatchStr};
+
+use super::GrammarLoadError;
+
+/// Change of the grammar of a `GrammarHandle`, sent to its listeners.
+#[derive(Debug, Clone)]
+pub enum GrammarChange {
+    /// The grammar was replaced, and has the given version.
+    Swapped { version: u64 },
+    /// A new grammar could not be loaded: the handle keeps the grammar of the given version.
+    ReloadFailed { version: u64, error: GrammarLoadError },
+}
+
+type Listener = Arc<dyn Fn(&GrammarChange) + Send + Sync>;
+
+/// Grammar shared by the parses of an application, that can be replaced by a reloaded one while it is used.
+///
+/// The parses use the grammar returned by `current`: a parse running when the grammar is swapped finishes with the
+/// previous one, which is dropped when the last of them ends. The version starts at 0, and increases at each swap.
+///
+/// ```ignore
+/// let handle = GrammarHandle::<StringCharReader>::from_ebnf_file("dsl.ebnf")?;
+/// handle.on_change(|change| eprintln!("{:?}", change));
+/// // When the file changes:
+/// handle.reload_ebnf_file("dsl.ebnf")?;
+/// ```
+pub struct GrammarHandle<R: MatchStr> {
+    current: RwLock<(Arc<Grammar<R>>, u64)>,
+    listeners: Mutex<Vec<Listener>>,
+}
+
+impl<R: 'static + MatchStr> GrammarHandle<R> {
+    pub fn new(grammar: Grammar<R>) -> Self {
+        Self {
+            current: RwLock::new((Arc::new(grammar), 0)),
+            listeners: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Loads the first grammar of the handle from a text in EBNF or PEG notation, see `Grammar::from_ebnf`.
+    pub fn from_ebnf(source: &str) -> Result<Self, GrammarLoadError> {
+        Grammar::from_ebnf(source).map(Self::new)
+    }
+
+    /// Loads the first grammar of the handle from a file in EBNF or PEG notation.
+    pub fn from_ebnf_file<P: AsRef<Path>>(path: P) -> Result<Self, GrammarLoadError> {
+        Grammar::from_ebnf_file(path).map(Self::new)
+    }
+
+    /// Returns the grammar to use for a parse. It stays valid even if the grammar of the handle is swapped.
+    pub fn current(&self) -> Arc<Grammar<R>> {
+        self.current.read().unwrap().0.clone()
+    }
+
+    pub fn version(&self) -> u64 {
+        self.current.read().unwrap().1
+    }
+
+    /// Replaces the grammar, and returns the previous one. The listeners are told about the change.
+    pub fn swap(&self, grammar: Grammar<R>) -> Arc<Grammar<R>> {
+        self.replace(grammar).0
+    }
+
+    /// Loads a grammar from a text in EBNF or PEG notation, and swaps it with the current one. Returns its version.
+    ///
+    /// If the text can't be loaded, the current grammar is kept, and the listeners are told about the error.
+    pub fn reload_ebnf(&self, source: &str) -> Result<u64, GrammarLoadError> {
+        self.reload(Grammar::from_ebnf(source))
+    }
+
+    /// Loads a grammar from a file in EBNF or PEG notation, and swaps it with the current one, like `reload_ebnf`.
+    pub fn reload_ebnf_file<P: AsRef<Path>>(&self, path: P) -> Result<u64, GrammarLoadError> {
+        self.reload(Grammar::from_ebnf_file(path))
+    }
+
+    /// Calls the function after each change of the grammar, on the thread that made the change.
+    pub fn on_change(&self, listener: impl Fn(&GrammarChange) + Send + Sync + 'static) {
+        self.listeners.lock().unwrap().push(Arc::new(listener));
+    }
+
+    fn reload(&self, grammar: Result<Grammar<R>, GrammarLoadError>) -> Result<u64, GrammarLoadError> {
+        match grammar {
+            Ok(grammar) => Ok(self.replace(grammar).1),
+            Err(error) => {
+                self.notify(&GrammarChange::ReloadFailed { version: self.version(), error: error.clone() });
+                Err(error)
+            }
+        }
+    }
+
+    /// Replaces the grammar, and returns the previous one and the version of the new one.
+    fn replace(&self, grammar: Grammar<R>) -> (Arc<Grammar<R>>, u64) {
+        let (previous, version) = {
+            let mut current = self.current.write().unwrap();
+            let version = current.1 + 1;
+            (std::mem::replace(&mut *current, (Arc::new(grammar), version)).0, version)
+        };
+        self.notify(&GrammarChange::Swapped { version });
+        (previous, version)
+    }
+
+    /// Calls the listeners, without holding the lock: they can use the handle.
+    fn notify(&self, change: &GrammarChange) {
+        let listeners = self.listeners.lock().unwrap().clone();
+        for listener in listeners {
+            listener(change);
+        }
+    }
+}
+
+impl<R: MatchStr> Debug for GrammarHandle<R> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let version = self.current.read().map(|current| current.1).unwrap_or_default();
+        f.debug_struct("GrammarHandle").field("version", &version).finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser_lib::{Location, MatchToken, StringCharReader};
+
+    fn matches(grammar: &Grammar<StringCharReader>, input: &str) -> bool {
+        let mut reader = StringCharReader::new(input);
+        grammar.test(&Location::beginning(), &mut reader).unwrap().is_some_and(|info| info.len() == input.len())
+    }
+
+    #[test]
+    fn test_grammar_handle() {
+        let handle = GrammarHandle::<StringCharReader>::from_ebnf("greeting = \"hello\" ;").unwrap();
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = changes.clone();
+        handle.on_change(move |change| recorded.lock().unwrap().push(format!("{:?}", change)));
+
+        // A parse keeps the grammar it started with
+        let running = handle.current();
+        assert_eq!(handle.reload_ebnf("greeting = \"hi\" ;").unwrap(), 1);
+        assert!(matches(&running, "hello"));
+        assert!(matches(&handle.current(), "hi"));
+        assert!(!matches(&handle.current(), "hello"));
+        drop(running);
+
+        // A grammar that doesn't load is not swapped
+        assert!(matches!(handle.reload_ebnf("greeting = ;"), Err(GrammarLoadError::Syntax { .. })));
+        assert!(handle.reload_ebnf_file("/missing/grammar.ebnf").is_err());
+        assert_eq!(handle.version(), 1);
+        assert!(matches(&handle.current(), "hi"));
+
+        let previous = handle.swap(Grammar::from_ebnf("greeting = \"hey\" ;").unwrap());
+        assert!(matches(&previous, "hi"));
+        let changes = changes.lock().unwrap();
+        assert_eq!(changes.len(), 4);
+        assert_eq!(changes[0], "Swapped { version: 1 }");
+        assert!(changes[1].starts_with("ReloadFailed { version: 1, error: Syntax"));
+        assert!(changes[2].starts_with("ReloadFailed { version: 1, error: Io"));
+        assert_eq!(changes[3], "Swapped { version: 2 }");
+    }
+
+    #[test]
+    fn test_grammar_handle_threads() {
+        let handle = GrammarHandle::<StringCharReader>::from_ebnf("digits = [0-9]+ ;").unwrap();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..50 {
+                        // Either grammar accepts the digits
+                        assert!(matches(&handle.current(), "123"));
+                    }
+                });
+            }
+            for i in 0..20 {
+                let source = match i % 2 {
+                    0 => "digits = [0-9]* ;",
+                    _ => "digits = [0-9]+ ;",
+                };
+                handle.reload_ebnf(source).unwrap();
+            }
+        });
+        assert_eq!(handle.version(), 20);
+    }
+}
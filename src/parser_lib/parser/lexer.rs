@@ -0,0 +1,1140 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt::{Debug, Display, Formatter},
+    mem,
+    ops::Range,
+    sync::Arc,
+};
+
+use crate::parser_lib::{
+    Checkpoint, Dfa, DfaError, Interner, LexError, Location, LocationDelta, MatchStr, MatchToken, ParseInfo,
+    ParserError, Rewind, Rule, Span, Stream, Symbol, SyntaxError, TextEdit, Token, TokenEvent, TokenSink, TokenType,
+    TokenValue,
+};
+use crate::utils::RingBuffer;
+
+/// Where a lexer puts the trivia (like whitespace or comments) between the tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TriviaPolicy {
+    /// The trivia are given to the sink on their own, between the tokens.
+    #[default]
+    Detached,
+    /// The trivia are the leading trivia of the token after them, like in rustc where a comment documents the item
+    /// after it.
+    Leading,
+    /// Like in Roslyn, the trivia on the line of a token, up to the one that ends the line, are its trailing trivia.
+    /// The next ones are the leading trivia of the next token.
+    ///
+    /// The trivia are not split: a trivia starting on the line of the token is trailing even if it spans several
+    /// lines. Give line breaks their own rule to split the indentation of the next line from them.
+    Trailing,
+}
+
+/// What a rule of the lexer produces.
+#[derive(Debug)]
+enum Output<T> {
+    Token(T),
+    Trivia,
+}
+
+impl<T: Clone> Output<T> {
+    /// Returns the token type, or None for trivia.
+    fn token_type(&self) -> Option<T> {
+        match self {
+            Output::Token(token_type) => Some(token_type.clone()),
+            Output::Trivia => None,
+        }
+    }
+}
+
+/// Token or trivia matched by a lexer.
+enum Lexed<T: PartialEq> {
+    Token(Token<T>),
+    Trivia(Span),
+}
+
+impl<T: PartialEq> Lexed<T> {
+    fn span(&self) -> &Span {
+        match self {
+            Lexed::Token(token) => token.span(),
+            Lexed::Trivia(span) => span,
+        }
+    }
+}
+
+type DecodeFn = dyn Fn(&str) -> Option<TokenValue> + Send + Sync;
+
+/// Function giving the value of a token from its text, see `Lexer::decoding`.
+#[derive(Clone)]
+struct Decoder(Arc<DecodeFn>);
+
+impl Debug for Decoder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Decoder")
+    }
+}
+
+/// Change of the state of the lexer when a rule matches.
+#[derive(Debug, Clone, Copy)]
+enum Action {
+    None,
+    Push(&'static str),
+    Pop,
+}
+
+#[derive(Debug)]
+struct LexRule<R: MatchStr, T> {
+    output: Output<T>,
+    matcher: Arc<dyn MatchToken<R>>,
+    /// State in which the rule is used.
+    state: &'static str,
+    action: Action,
+    priority: i32,
+    decoder: Option<Decoder>,
+}
+
+/// Two rules of a lexer with the same priority that can match the same text, see `Lexer::ambiguities`.
+///
+/// The outputs are None for trivia. The first rule wins the ties, since it was added first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenAmbiguity<T> {
+    pub state: &'static str,
+    pub first: Option<T>,
+    pub second: Option<T>,
+    pub priority: i32,
+}
+
+impl<T: Debug> Display for TokenAmbiguity<T> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let name = |output: &Option<T>| match output {
+            Some(token_type) => format!("{:?}", token_type),
+            None => "trivia".to_string(),
+        };
+        write!(
+            f,
+            "The tokens `{}` and `{}` can match the same text with the priority {} in the state `{}`.",
+            name(&self.first),
+            name(&self.second),
+            self.priority,
+            self.state
+        )
+    }
+}
+
+/// Tokens of an edited source, see `Lexer::relex`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relexed<T: PartialEq> {
+    pub tokens: Vec<Token<T>>,
+    /// Indices of the tokens that were lexed again. The other ones are the previous tokens, moved by the edit.
+    pub relexed: Range<usize>,
+}
+
+/// Splits an input into tokens, with the rules given by `with_token` and `with_trivia`.
+///
+/// At each position, the rule with the longest match is used. If several match as much, the one with the highest
+/// priority wins, and then the first one given. Use `ambiguities` to find the ties decided by the order.
+/// The results are written into a `TokenSink`: a `Vec` to get all the tokens at once, a channel to send them to
+/// another thread, or the `Tokens` iterator to read them as they are lexed. The lexer stops at the first position
+/// that no rule matches, with a `SyntaxError::NoToken` error, unless it has an error token: the chars up to the next
+/// position where a rule matches are then an error token, the error is kept in `errors`, and the lexer goes on.
+///
+/// The rules belong to a state, to tokenize the parts of the input that have their own tokens, like the inside of a
+/// template string. The lexer starts in the `INITIAL_STATE`, and only uses the rules of the state on top of its stack.
+/// The rules added after `in_state` belong to that state, and `pushing` and `popping` make the last added rule enter
+/// or leave a state when it matches.
+///
+/// Instead of a rule for each keyword, the keywords can be matched by the rule of the identifiers, and then found in
+/// a table of keywords to change the type of their tokens, see `with_keywords`.
+///
+/// The lexer is also an `Iterator` and a `Stream` of its tokens, for the parsers reading tokens: it lexes them as they
+/// are read, and only keeps the ones peeked. Don't mix them with the sinks, which don't see the tokens peeked.
+///
+/// The trivia are given to the sink between the tokens, or attached to them, see `TriviaPolicy`. When they are
+/// attached, each token is given to the sink once its trailing trivia are known, and the trivia after the last token
+/// are its trailing trivia.
+///
+/// ```ignore
+/// let mut lexer = Lexer::new(StringCharReader::new("let x"))
+///     .with_token(Kind::Let, &word!("let"))
+///     .with_token(Kind::Name, &range!('a', 'z').at_least(1))
+///     .with_trivia(&word!(" "));
+/// let mut tokens = Vec::new();
+/// lexer.tokenize(&mut tokens)?;
+///
+/// // Nested comments
+/// let lexer = Lexer::new(reader)
+///     .with_trivia(&word!("/*")).pushing("comment")
+///     .in_state("comment")
+///     .with_trivia(&word!("/*")).pushing("comment")
+///     .with_trivia(&word!("*/")).popping()
+///     .with_trivia(&until!(choice![word!("/*"), word!("*/")], 1));
+/// ```
+#[derive(Debug)]
+pub struct Lexer<R: MatchStr, T: Clone + PartialEq> {
+    rules: Vec<LexRule<R, T>>,
+    reader: R,
+    location: Location,
+    /// State of the rules added next.
+    defined_state: &'static str,
+    states: Vec<&'static str>,
+    trivia_policy: TriviaPolicy,
+    /// Last token, kept until its trailing trivia are known when the trivia are attached.
+    pending: Option<Token<T>>,
+    /// Trivia after the pending token that lead the next one.
+    leading_trivia: Vec<Span>,
+    /// Type of the tokens of the chars that no rule matches.
+    error_token: Option<T>,
+    errors: Vec<ParserError>,
+    /// Strings of the keywords.
+    interner: Interner,
+    /// Token types whose tokens can be keywords, with the type of each keyword.
+    keywords: Vec<(T, HashMap<Symbol, T>)>,
+    /// Tokens lexed but not read yet by the iterator or the stream. It grows when it is full.
+    ///
+    /// The tokens consumed since the oldest live checkpoint stay behind in it, so that the stream can be rewound.
+    lookahead: RingBuffer<Token<T>>,
+    /// Number of tokens read by the iterator or the stream.
+    consumed: usize,
+    /// Number of live checkpoints at each index, until they are released.
+    checkpoints: BTreeMap<usize, usize>,
+    /// Error that stopped the iterator and the stream, and whether the iterator gave it.
+    error: Option<ParserError>,
+    error_given: bool,
+    stopped: bool,
+}
+
+/// Number of tokens the lookahead of a lexer can hold before it grows.
+const LOOKAHEAD_CAPACITY: usize = 16;
+
+/// State in which a lexer starts.
+pub const INITIAL_STATE: &str = "initial";
+
+impl<R: 'static + MatchStr, T: Clone + PartialEq> Lexer<R, T> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            rules: Vec::new(),
+            reader,
+            location: Location::beginning(),
+            defined_state: INITIAL_STATE,
+            states: vec![INITIAL_STATE],
+            trivia_policy: TriviaPolicy::default(),
+            pending: None,
+            leading_trivia: Vec::new(),
+            error_token: None,
+            errors: Vec::new(),
+            interner: Interner::new(),
+            keywords: Vec::new(),
+            lookahead: RingBuffer::new(LOOKAHEAD_CAPACITY),
+            consumed: 0,
+            checkpoints: BTreeMap::new(),
+            error: None,
+            error_given: false,
+            stopped: false,
+        }
+    }
+
+    /// Makes the chars that no rule matches a token of the given type, instead of stopping the lexer.
+    pub fn with_error_token(mut self, token_type: T) -> Self {
+        self.error_token = Some(token_type);
+        self
+    }
+
+    /// Makes the last added rule give the tokens it matches the value returned by the function for their text.
+    ///
+    /// Like the numeric value of an integer literal, or the text of a string literal without its escape sequences.
+    pub fn decoding(mut self, decoder: impl Fn(&str) -> Option<TokenValue> + Send + Sync + 'static) -> Self {
+        if let Some(rule) = self.rules.last_mut() {
+            rule.decoder = Some(Decoder(Arc::new(decoder)));
+        }
+        self
+    }
+
+    /// Makes the tokens of the given type whose text is one of the keywords tokens of the type of the keyword.
+    ///
+    /// The keywords are interned like the lexemes, so each token of the given type is looked up by its lexeme.
+    pub fn with_keywords(mut self, token_type: T, keywords: &[(&str, T)]) -> Self {
+        let table: HashMap<Symbol, T> = keywords
+            .iter()
+            .map(|(keyword, keyword_type)| (self.interner.intern(keyword), keyword_type.clone()))
+            .collect();
+        match self.keywords.iter_mut().find(|(t, _)| *t == token_type) {
+            Some((_, existing)) => existing.extend(table),
+            None => self.keywords.push((token_type, table)),
+        }
+        self
+    }
+
+    /// Returns the interner of the lexemes of the tokens.
+    pub fn interner(&self) -> &Interner {
+        &self.interner
+    }
+
+    /// Interns the lexemes in the given interner, like the one of a previous lexer, so that they have the same symbols.
+    pub fn with_interner(mut self, interner: Interner) -> Self {
+        let previous = mem::replace(&mut self.interner, interner);
+        for (_, table) in &mut self.keywords {
+            let keywords: Vec<(Symbol, T)> = table.drain().collect();
+            for (symbol, keyword_type) in keywords {
+                table.insert(self.interner.intern(previous.resolve(symbol).unwrap_or_default()), keyword_type);
+            }
+        }
+        self
+    }
+
+    /// Returns the errors found so far, for the chars that are in error tokens.
+    pub fn errors(&self) -> &[ParserError] {
+        &self.errors
+    }
+
+    /// Returns the error that stopped the iterator and the stream, if any.
+    pub fn error(&self) -> Option<&ParserError> {
+        self.error.as_ref()
+    }
+
+    /// Lexes tokens until the lookahead has more than n of them, or the end of the input.
+    fn fill(&mut self, n: usize) {
+        let mut lexed = Vec::new();
+        while self.lookahead.size() <= n && !self.stopped {
+            match self.next_into(&mut lexed) {
+                Ok(more) => self.stopped = !more,
+                Err(error) => {
+                    self.error = Some(error);
+                    self.stopped = true;
+                }
+            }
+            for token in lexed.drain(..) {
+                // The buffer grows instead of overwriting the consumed tokens that can still be rewound to
+                let retained = self.checkpoints.keys().next().map_or(0, |oldest| self.consumed.saturating_sub(*oldest));
+                let capacity = self.lookahead.capacity();
+                if self.lookahead.size() + retained >= capacity {
+                    self.lookahead.grow(capacity.max(1));
+                }
+                // There is always space after growing
+                let _ = self.lookahead.push_back(token);
+            }
+        }
+    }
+
+    /// Sets where the trivia are put.
+    pub fn with_trivia_policy(mut self, policy: TriviaPolicy) -> Self {
+        self.trivia_policy = policy;
+        self
+    }
+
+    /// Adds a rule producing tokens of the given type.
+    pub fn with_token(self, token_type: T, rule: &Rule<R>) -> Self {
+        self.with_rule(Output::Token(token_type), rule)
+    }
+
+    /// Adds a rule producing trivia, like whitespace or comments.
+    pub fn with_trivia(self, rule: &Rule<R>) -> Self {
+        self.with_rule(Output::Trivia, rule)
+    }
+
+    fn with_rule(self, output: Output<T>, rule: &Rule<R>) -> Self {
+        self.with_matcher(output, rule.matcher().clone(), 0)
+    }
+
+    fn with_matcher(mut self, output: Output<T>, matcher: Arc<dyn MatchToken<R>>, priority: i32) -> Self {
+        let state = self.defined_state;
+        self.rules.push(LexRule { output, matcher, state, action: Action::None, priority, decoder: None });
+        self
+    }
+
+    /// Sets the priority of the last added rule (0 by default), which wins the ties with the rules matching as much.
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        if let Some(rule) = self.rules.last_mut() {
+            rule.priority = priority;
+        }
+        self
+    }
+
+    /// Makes the rules added next belong to the given state.
+    pub fn in_state(mut self, state: &'static str) -> Self {
+        self.defined_state = state;
+        self
+    }
+
+    /// Makes the last added rule enter the given state when it matches.
+    pub fn pushing(self, state: &'static str) -> Self {
+        self.with_action(Action::Push(state))
+    }
+
+    /// Makes the last added rule go back to the previous state when it matches. The initial state is never left.
+    pub fn popping(self) -> Self {
+        self.with_action(Action::Pop)
+    }
+
+    fn with_action(mut self, action: Action) -> Self {
+        if let Some(rule) = self.rules.last_mut() {
+            rule.action = action;
+        }
+        self
+    }
+
+    /// Lexes an edited source, reusing the tokens of the previous source that the edit can't change.
+    ///
+    /// The lexer must read the edited source, and must not have lexed anything yet. The tokens ending before the edit
+    /// are kept, and the lexer starts after them: it stops at the first token that is the same as a previous token
+    /// after the edit, and the previous tokens from there are moved by the edit. The tokens are compared with their
+    /// trivia, and the errors of the reused error tokens are not in `errors`.
+    ///
+    /// The rules must not read the input after the end of their match, like with a lookahead. Since the tokens don't
+    /// keep the state of the lexer, the lexers with several states lex the whole source again. Since the lexemes are
+    /// compared, the lexer must have a clone of the interner of the previous lexer, see `with_interner`.
+    pub fn relex(&mut self, previous: &[Token<T>], edit: &TextEdit) -> Result<Relexed<T>, ParserError> {
+        let mut kept = previous.iter().take_while(|token| token.full_end().byte_offset() < edit.range.start).count();
+        if self.trivia_policy != TriviaPolicy::Detached {
+            // The trivia after the last token may be attached to it
+            kept = kept.saturating_sub(1);
+        }
+        if self.rules.iter().any(|rule| rule.state != INITIAL_STATE) {
+            kept = 0;
+        }
+
+        // Start after the kept tokens
+        let mut tokens = previous[..kept].to_vec();
+        let start = tokens.last().map_or(self.location, |token| *token.full_end());
+        if start.index() > self.reader.index() {
+            self.reader.consume_nth(start.index() - self.reader.index() - 1);
+        }
+        self.location = start;
+
+        // Previous tokens that the edit didn't change, by their start in the edited source
+        let moved = |token: &Token<T>| {
+            token.full_start().byte_offset() + edit.text.len() + edit.range.start - edit.range.end
+        };
+        let mut reusable = (kept..previous.len())
+            .filter(|i| previous[*i].full_start().byte_offset() >= edit.range.end)
+            .peekable();
+
+        let mut lexed = Vec::new();
+        loop {
+            let more = self.next_into(&mut lexed)?;
+            for token in lexed.drain(..) {
+                let start = token.full_start().byte_offset();
+                while reusable.next_if(|i| moved(&previous[*i]) < start).is_some() {}
+
+                if let Some(i) = reusable.next_if(|i| moved(&previous[*i]) == start) {
+                    let (old, new) = (*previous[i].full_start(), *token.full_start());
+                    let reused: Vec<Token<T>> = previous[i..]
+                        .iter()
+                        .map(|token| {
+                            let mut token = token.clone();
+                            token.map_locations(&|loc| move_location(loc, &old, &new));
+                            token
+                        })
+                        .collect();
+                    if reused[0] == token {
+                        let relexed = kept..tokens.len();
+                        tokens.extend(reused);
+                        return Ok(Relexed { tokens, relexed });
+                    }
+                }
+                tokens.push(token);
+            }
+            if !more {
+                let relexed = kept..tokens.len();
+                return Ok(Relexed { tokens, relexed });
+            }
+        }
+    }
+
+    /// Finds the rules with the same priority that can match the same text, where the order decides which one wins.
+    ///
+    /// The rules are compiled to a `Dfa`, so they must be regular. Like the DFA, it may find texts that the rules
+    /// don't match, because a repetition never gives back chars.
+    pub fn ambiguities(&self) -> Result<Vec<TokenAmbiguity<T>>, DfaError> {
+        let mut states: Vec<&'static str> = Vec::new();
+        for rule in &self.rules {
+            if !states.contains(&rule.state) {
+                states.push(rule.state);
+            }
+        }
+
+        let mut ambiguities = Vec::new();
+        for state in states {
+            let rules: Vec<&LexRule<R, T>> = self.rules.iter().filter(|rule| rule.state == state).collect();
+            let matchers: Vec<&dyn MatchToken<R>> = rules.iter().map(|rule| rule.matcher.as_ref()).collect();
+            for (first, second) in Dfa::compile(&matchers)?.overlaps() {
+                let (first, second) = (rules[first], rules[second]);
+                if first.priority == second.priority {
+                    ambiguities.push(TokenAmbiguity {
+                        state,
+                        first: first.output.token_type(),
+                        second: second.output.token_type(),
+                        priority: first.priority,
+                    });
+                }
+            }
+        }
+        Ok(ambiguities)
+    }
+
+    /// Returns the current state.
+    pub fn state(&self) -> &'static str {
+        self.states.last().copied().unwrap_or(INITIAL_STATE)
+    }
+
+    /// Returns the location of the next token.
+    pub fn location(&self) -> &Location {
+        &self.location
+    }
+
+    /// Lexes the whole input into the sink.
+    pub fn tokenize(&mut self, sink: &mut impl TokenSink<T>) -> Result<(), ParserError> {
+        while self.next_into(sink)? {}
+        Ok(())
+    }
+
+    /// Returns an iterator lexing the input one token or trivia at a time. It ends after an error.
+    pub fn tokens(&mut self) -> Tokens<'_, R, T> {
+        Tokens { lexer: self, queue: VecDeque::new(), done: false }
+    }
+
+    /// Lexes the next token or trivia into the sink. Returns false at the end of the input.
+    pub fn next_into(&mut self, sink: &mut impl TokenSink<T>) -> Result<bool, ParserError> {
+        let (lexed, action) = match self.next_match() {
+            Ok(Some(found)) => found,
+            Ok(None) => {
+                self.flush(sink);
+                return Ok(false);
+            }
+            Err(error) => {
+                self.flush(sink);
+                sink.push_error(&error);
+                return Err(error);
+            }
+        };
+
+        // Consume the token, the next one starts at its end
+        let end = *lexed.span().end();
+        if end.index() > self.reader.index() {
+            self.reader.consume_nth(end.index() - self.reader.index() - 1);
+        }
+        self.location = end;
+        match action {
+            Action::None => (),
+            Action::Push(state) => self.states.push(state),
+            Action::Pop if self.states.len() > 1 => {
+                self.states.pop();
+            }
+            Action::Pop => (),
+        }
+
+        match lexed {
+            Lexed::Token(token) => self.push_token(token, sink),
+            Lexed::Trivia(span) => self.push_trivia(span, sink),
+        }
+        Ok(true)
+    }
+
+    /// Matches the next token, trivia or error token, with the change of state of its rule.
+    fn next_match(&mut self) -> Result<Option<(Lexed<T>, Action)>, ParserError> {
+        let (rule, info) = match self.longest_match() {
+            Ok(Some((rule, info))) => (Some(rule), info),
+            Ok(None) => return Ok(None),
+            Err(ParserError::Syntax(SyntaxError::NoToken { location })) if self.error_token.is_some() => {
+                self.errors.push(SyntaxError::NoToken { location }.into());
+                (None, self.skip_error()?)
+            }
+            Err(error) => return Err(error),
+        };
+        let (output, action, decoder) = match rule {
+            Some(rule) => {
+                let rule = &self.rules[rule];
+                (rule.output.token_type(), rule.action, rule.decoder.clone())
+            }
+            None => (self.error_token.clone(), Action::None, None),
+        };
+        let Some(token_type) = output else {
+            return Ok(Some((Lexed::Trivia(info.span().clone()), action)));
+        };
+
+        let mut text = String::new();
+        for i in info.start().index()..info.end().index() {
+            text.extend(self.reader.char_at(i)?);
+        }
+        let lexeme = self.interner.intern(&text);
+        let mut token = Token::new(info.span().clone(), self.keyword_type(token_type, lexeme)).with_lexeme(lexeme);
+        if let Some(value) = decoder.and_then(|decoder| (decoder.0)(&text)) {
+            token = token.with_value(value);
+        }
+        Ok(Some((Lexed::Token(token), action)))
+    }
+
+    /// Returns the type of the keyword of the lexeme if the tokens of the type can be keywords, or else the type.
+    fn keyword_type(&self, token_type: T, lexeme: Symbol) -> T {
+        let keyword = self.keywords.iter().find(|(t, _)| *t == token_type);
+        keyword.and_then(|(_, table)| table.get(&lexeme).cloned()).unwrap_or(token_type)
+    }
+
+    /// Finds the chars from the location up to the next position where a rule matches, or the end of the input.
+    fn skip_error(&mut self) -> Result<ParseInfo, ParserError> {
+        let mut delta = LocationDelta::with_policy(self.reader.location_policy());
+        let mut end = self.location;
+        while let Some(c) = self.reader.char_at(end.index())? {
+            delta.push(c);
+            end = delta.apply_to(&self.location);
+            if self.matches_at(&end)? {
+                break;
+            }
+        }
+        Ok(ParseInfo::new(Span::new(self.location, end), delta.len()))
+    }
+
+    /// Checks whether a rule of the state matches some chars at the location.
+    fn matches_at(&mut self, loc: &Location) -> Result<bool, ParserError> {
+        let state = self.state();
+        for rule in self.rules.iter().filter(|rule| rule.state == state) {
+            if let Some(info) = rule.matcher.test(loc, &mut self.reader)? {
+                if info.end().index() > loc.index() {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn push_token(&mut self, token: Token<T>, sink: &mut impl TokenSink<T>) {
+        if self.trivia_policy == TriviaPolicy::Detached {
+            sink.push_token(token);
+            return;
+        }
+
+        let token = token.with_trivia(mem::take(&mut self.leading_trivia), Vec::new());
+        if let Some(previous) = self.pending.replace(token) {
+            sink.push_token(previous);
+        }
+    }
+
+    fn push_trivia(&mut self, span: Span, sink: &mut impl TokenSink<T>) {
+        match (self.trivia_policy, &mut self.pending) {
+            (TriviaPolicy::Detached, _) => sink.push_trivia(span),
+            // The line of the token is not ended yet
+            (TriviaPolicy::Trailing, Some(token))
+                if self.leading_trivia.is_empty() && span.start().line() == token.span().end().line() =>
+            {
+                token.add_trailing_trivia(span)
+            }
+            _ => self.leading_trivia.push(span),
+        }
+    }
+
+    /// Gives the pending token to the sink, with the trivia after it.
+    fn flush(&mut self, sink: &mut impl TokenSink<T>) {
+        let trivia = mem::take(&mut self.leading_trivia);
+        match self.pending.take() {
+            Some(mut token) => {
+                trivia.into_iter().for_each(|span| token.add_trailing_trivia(span));
+                sink.push_token(token);
+            }
+            None => trivia.into_iter().for_each(|span| sink.push_trivia(span)),
+        }
+    }
+
+    /// Finds the rule of the state matching the most chars at the location, with its index.
+    fn longest_match(&mut self) -> Result<Option<(usize, ParseInfo)>, ParserError> {
+        if self.reader.is_eof() {
+            self.reader.check_error()?;
+            return Ok(None);
+        }
+
+        let state = self.state();
+        let mut best: Option<(usize, &LexRule<R, T>, ParseInfo)> = None;
+        for (i, rule) in self.rules.iter().enumerate().filter(|(_, rule)| rule.state == state) {
+            let Some(info) = rule.matcher.test(&self.location, &mut self.reader)? else {
+                continue;
+            };
+            // An empty match would not move the lexer
+            let better = match &best {
+                Some((_, best_rule, best)) => {
+                    let (end, best_end) = (info.end().index(), best.end().index());
+                    end > best_end || (end == best_end && rule.priority > best_rule.priority)
+                }
+                None => info.end().index() > self.location.index(),
+            };
+            if better {
+                best = Some((i, rule, info));
+            }
+        }
+
+        match best {
+            Some((i, _, info)) => Ok(Some((i, info))),
+            None => Err(SyntaxError::NoToken { location: self.location }.into()),
+        }
+    }
+}
+
+/// Gives the tokens as they are lexed, and then the error that stopped the lexer, if any.
+impl<R: 'static + MatchStr, T: Clone + PartialEq> Iterator for Lexer<R, T> {
+    type Item = Result<Token<T>, ParserError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.fill(0);
+        if let Some(token) = self.lookahead.pop_front() {
+            self.consumed += 1;
+            return Some(Ok(token));
+        }
+        if self.error_given {
+            return None;
+        }
+        self.error_given = true;
+        self.error.clone().map(Err)
+    }
+}
+
+/// Reads the tokens as they are lexed. The stream ends at the first error, see `Lexer::error`.
+impl<R: 'static + MatchStr, T: Clone + PartialEq> Stream<Token<T>> for Lexer<R, T> {
+    fn peek(&mut self) -> Option<Token<T>> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&mut self, n: usize) -> Option<Token<T>> {
+        self.fill(n);
+        self.lookahead.peek_nth(n)
+    }
+
+    fn consume(&mut self) -> Option<Token<T>> {
+        self.consume_nth(0)
+    }
+
+    fn consume_nth(&mut self, n: usize) -> Option<Token<T>> {
+        self.fill(n);
+        if self.lookahead.size() <= n {
+            return None;
+        }
+        self.consumed += n + 1;
+        (0..=n).filter_map(|_| self.lookahead.pop_front()).last()
+    }
+
+    fn is_eof(&mut self) -> bool {
+        self.fill(0);
+        self.lookahead.size() == 0
+    }
+
+    fn index(&self) -> usize {
+        self.consumed
+    }
+}
+
+impl<R: 'static + MatchStr, T: Clone + PartialEq> Rewind for Lexer<R, T> {
+    fn checkpoint(&mut self) -> Checkpoint {
+        *self.checkpoints.entry(self.consumed).or_default() += 1;
+        Checkpoint::new(self.consumed, 0)
+    }
+
+    fn rewind(&mut self, checkpoint: Checkpoint) -> Result<(), ParserError> {
+        let index = checkpoint.index();
+
+        if index > self.consumed {
+            // Going forward is just consuming, as long as the tokens up to the checkpoint can be lexed
+            if self.consume_nth(index - self.consumed - 1).is_none() {
+                return Err(self.error.clone().unwrap_or_else(|| LexError::NoLookBehind(index).into()));
+            }
+        } else if self.lookahead.unpop(self.consumed - index) {
+            // The consumed tokens are still in the buffer as long as they were not overwritten by new ones
+            self.consumed = index;
+        } else {
+            return Err(LexError::NoLookBehind(index).into());
+        }
+        Ok(())
+    }
+
+    fn release(&mut self, checkpoint: Checkpoint) {
+        if let Some(count) = self.checkpoints.get_mut(&checkpoint.index()) {
+            *count -= 1;
+            if *count == 0 {
+                self.checkpoints.remove(&checkpoint.index());
+            }
+        }
+    }
+}
+
+/// Moves a location after `old` like `old` is moved to `new`.
+fn move_location(loc: &Location, old: &Location, new: &Location) -> Location {
+    let column = if loc.line() == old.line() {
+        loc.column() - old.column() + new.column()
+    } else {
+        loc.column()
+    };
+    Location::with_byte_offset(
+        loc.line() - old.line() + new.line(),
+        column,
+        loc.index() - old.index() + new.index(),
+        loc.byte_offset() - old.byte_offset() + new.byte_offset(),
+    )
+    .with_source(loc.source())
+}
+
+impl<R: 'static + MatchStr> Lexer<R, &'static str> {
+    /// Adds a token rule for each token type, with its name as type and its priority. See `define_tokens!`.
+    pub fn with_token_types(self, token_types: &[TokenType<R>]) -> Self {
+        token_types.iter().fold(self, |lexer, token_type| {
+            let output = Output::Token(token_type.name());
+            lexer.with_matcher(output, token_type.matcher().clone(), token_type.priority())
+        })
+    }
+}
+
+/// Iterator over the tokens, trivia and errors of a lexer, lexed when they are read. See `Lexer::tokens`.
+pub struct Tokens<'l, R: MatchStr, T: Clone + PartialEq> {
+    lexer: &'l mut Lexer<R, T>,
+    queue: VecDeque<TokenEvent<T>>,
+    done: bool,
+}
+
+impl<R: 'static + MatchStr, T: Clone + PartialEq> Iterator for Tokens<'_, R, T> {
+    type Item = TokenEvent<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.queue.is_empty() && !self.done {
+            self.done = !matches!(self.lexer.next_into(&mut self.queue), Ok(true));
+        }
+        self.queue.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use crate::{
+        parser_lib::{Span, StringCharReader, TextEdit},
+        choice, define_tokens, range, seq, until, word,
+    };
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Kind {
+        Error,
+        Let,
+        Name,
+        Quote,
+        Text,
+        Open,
+        Close,
+    }
+
+    fn lexer(source: &str) -> Lexer<StringCharReader, Kind> {
+        Lexer::new(StringCharReader::new(source))
+            .with_token(Kind::Let, &word!("let"))
+            .with_token(Kind::Name, &range!('a', 'z').at_least(1))
+            .with_trivia(&word!(" "))
+    }
+
+    fn spans<T: PartialEq>(tokens: &[Token<T>]) -> Vec<(usize, usize)> {
+        tokens.iter().map(|token| (token.span().start().index(), token.span().end().index())).collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let mut tokens = Vec::new();
+        lexer("let letter x").tokenize(&mut tokens).unwrap();
+        let types: Vec<&Kind> = tokens.iter().map(Token::token_type).collect();
+        // The longest match wins, and the first rule wins a tie
+        assert_eq!(types, [&Kind::Let, &Kind::Name, &Kind::Name]);
+        assert_eq!(spans(&tokens), [(0, 3), (4, 10), (11, 12)]);
+
+        // The tokens before the error are kept
+        let mut tokens = Vec::new();
+        let err = lexer("let 1").tokenize(&mut tokens).unwrap_err();
+        assert_eq!(err.to_string(), "1:5: no token matches the input.");
+        assert_eq!(err.location().map(|location| location.index()), Some(4));
+        assert_eq!(spans(&tokens), [(0, 3)]);
+    }
+
+    #[test]
+    fn test_tokens() {
+        let mut lexer = lexer("x y!");
+        let events: Vec<TokenEvent<Kind>> = lexer.tokens().collect();
+        assert_eq!(events.len(), 4);
+        assert!(matches!(&events[0], TokenEvent::Token(token) if token.token_type() == &Kind::Name));
+        let trivia = Span::new(Location::beginning() + 1, Location::beginning() + 2);
+        assert_eq!(events[1], TokenEvent::Trivia(trivia));
+        assert!(matches!(&events[3], TokenEvent::Error(ParserError::Syntax(SyntaxError::NoToken { .. }))));
+        assert_eq!(lexer.location().index(), 3);
+    }
+
+    #[test]
+    fn test_channel() {
+        let (sender, receiver) = mpsc::channel();
+        let worker = std::thread::spawn(move || {
+            let mut sender = sender;
+            lexer("let x").tokenize(&mut sender)
+        });
+        worker.join().unwrap().unwrap();
+        let events: Vec<TokenEvent<Kind>> = receiver.iter().collect();
+        assert_eq!(events.len(), 3);
+        assert!(matches!(&events[2], TokenEvent::Token(token) if token.token_type() == &Kind::Name));
+    }
+
+    #[test]
+    fn test_states() {
+        // Template strings, with interpolated names
+        let lexer = |source: &str| {
+            Lexer::new(StringCharReader::new(source))
+                .with_token(Kind::Name, &range!('a', 'z').at_least(1))
+                .with_token(Kind::Quote, &word!("`"))
+                .pushing("template")
+                .with_token(Kind::Close, &word!("}"))
+                .popping()
+                .in_state("template")
+                .with_token(Kind::Text, &until!(choice![word!("`"), word!("${")], 1))
+                .with_token(Kind::Open, &word!("${"))
+                .pushing(INITIAL_STATE)
+                .with_token(Kind::Quote, &word!("`"))
+                .popping()
+        };
+        let mut tokens = Vec::new();
+        let mut template = lexer("`a ${b}c`d");
+        template.tokenize(&mut tokens).unwrap();
+        let types: Vec<Kind> = tokens.iter().map(|token| token.token_type().clone()).collect();
+        use Kind::*;
+        assert_eq!(types, [Quote, Text, Open, Name, Close, Text, Quote, Name]);
+        assert_eq!(template.state(), INITIAL_STATE);
+
+        // The input can end in any state, and the initial state is never left
+        let mut template = lexer("`a");
+        template.tokenize(&mut Vec::new()).unwrap();
+        assert_eq!(template.state(), "template");
+        let mut close = lexer("}}a");
+        close.tokenize(&mut Vec::new()).unwrap();
+        assert_eq!(close.state(), INITIAL_STATE);
+
+        // Nested comments
+        let mut comments = Lexer::<_, Kind>::new(StringCharReader::new("/* a /* b */ c */"))
+            .with_trivia(&word!("/*"))
+            .pushing("comment")
+            .in_state("comment")
+            .with_trivia(&word!("/*"))
+            .pushing("comment")
+            .with_trivia(&word!("*/"))
+            .popping()
+            .with_trivia(&until!(choice![word!("/*"), word!("*/")], 1));
+        let events: Vec<TokenEvent<Kind>> = comments.tokens().collect();
+        assert_eq!(events.len(), 7);
+        assert_eq!(comments.state(), INITIAL_STATE);
+    }
+
+    #[test]
+    fn test_trivia_policy() {
+        let source = "a // x\n// y\nb ";
+        let lexer = |policy| {
+            Lexer::new(StringCharReader::new(source))
+                .with_token(Kind::Name, &range!('a', 'z').at_least(1))
+                .with_trivia(&seq!(word!("//"), until!(word!("\n"), 0)))
+                .with_trivia(&word!("\n"))
+                .with_trivia(&word!(" "))
+                .with_trivia_policy(policy)
+        };
+        let texts = |spans: &[Span]| -> Vec<&str> {
+            spans.iter().map(|span| &source[span.start().byte_offset()..span.end().byte_offset()]).collect()
+        };
+
+        let mut tokens = Vec::new();
+        lexer(TriviaPolicy::Leading).tokenize(&mut tokens).unwrap();
+        assert_eq!(tokens.len(), 2);
+        assert!(tokens[0].leading_trivia().is_empty() && tokens[0].trailing_trivia().is_empty());
+        assert_eq!(texts(tokens[1].leading_trivia()), [" ", "// x", "\n", "// y", "\n"]);
+        assert_eq!(texts(tokens[1].trailing_trivia()), [" "]);
+
+        let mut tokens = Vec::new();
+        lexer(TriviaPolicy::Trailing).tokenize(&mut tokens).unwrap();
+        assert_eq!(texts(tokens[0].trailing_trivia()), [" ", "// x", "\n"]);
+        assert_eq!(texts(tokens[1].leading_trivia()), ["// y", "\n"]);
+        assert_eq!(texts(tokens[1].trailing_trivia()), [" "]);
+
+        // The tokens are given once their trivia are known
+        let mut lexer = lexer(TriviaPolicy::Trailing);
+        let events: Vec<TokenEvent<Kind>> = lexer.tokens().collect();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[1], TokenEvent::Token(token) if token.leading_trivia().len() == 2));
+    }
+
+    #[test]
+    fn test_priorities() {
+        let source = "if iffy";
+        let lexer = |keyword_priority| {
+            Lexer::new(StringCharReader::new(source))
+                .with_token(Kind::Name, &range!('a', 'z').at_least(1))
+                .with_token(Kind::Let, &word!("if"))
+                .with_priority(keyword_priority)
+                .with_trivia(&word!(" "))
+        };
+
+        // The longest match wins before the priority
+        let mut tokens = Vec::new();
+        lexer(1).tokenize(&mut tokens).unwrap();
+        assert_eq!(tokens.iter().map(Token::token_type).collect::<Vec<_>>(), [&Kind::Let, &Kind::Name]);
+        assert_eq!(lexer(1).ambiguities().unwrap(), []);
+
+        // Without priority, the first rule wins
+        let mut tokens = Vec::new();
+        lexer(0).tokenize(&mut tokens).unwrap();
+        assert_eq!(tokens.iter().map(Token::token_type).collect::<Vec<_>>(), [&Kind::Name, &Kind::Name]);
+        let ambiguities = lexer(0).ambiguities().unwrap();
+        assert_eq!(ambiguities.len(), 1);
+        assert_eq!(
+            ambiguities[0].to_string(),
+            "The tokens `Name` and `Let` can match the same text with the priority 0 in the state `initial`."
+        );
+
+        // With the token types of `define_tokens!`
+        define_tokens! {
+            StringCharReader,
+            keyword: 1 => word!("if"),
+            name => range!('a', 'z').at_least(1)
+        }
+        let mut lexer = Lexer::new(StringCharReader::new("if")).with_token_types(&tokens::token_types());
+        let mut tokens = Vec::new();
+        lexer.tokenize(&mut tokens).unwrap();
+        assert_eq!(tokens[0].token_type(), &"keyword");
+    }
+
+    #[test]
+    fn test_error_token() {
+        let mut lexer = lexer("let 12 x?").with_error_token(Kind::Error);
+        let mut tokens = Vec::new();
+        lexer.tokenize(&mut tokens).unwrap();
+
+        // The chars up to the next match are in a single error token
+        let types: Vec<&Kind> = tokens.iter().map(Token::token_type).collect();
+        assert_eq!(types, [&Kind::Let, &Kind::Error, &Kind::Name, &Kind::Error]);
+        assert_eq!(spans(&tokens), [(0, 3), (4, 6), (7, 8), (8, 9)]);
+        let errors: Vec<String> = lexer.errors().iter().map(ToString::to_string).collect();
+        assert_eq!(errors, ["1:5: no token matches the input.", "1:9: no token matches the input."]);
+    }
+
+    #[test]
+    fn test_relex() {
+        let lines = |source: &str, policy, interner| {
+            lexer(source).with_trivia(&word!("\n")).with_trivia_policy(policy).with_interner(interner)
+        };
+        let relex = |source: &str, edit: TextEdit, policy| {
+            let mut previous = lines(source, policy, Interner::new());
+            let mut tokens = Vec::new();
+            previous.tokenize(&mut tokens).unwrap();
+
+            // The lexemes are interned with the previous interner, to reuse the symbols of the previous tokens
+            let edited = edit.apply(source);
+            let relexed = lines(&edited, policy, previous.interner().clone()).relex(&tokens, &edit).unwrap();
+
+            // Same tokens as when lexing the whole edited source
+            let mut expected = Vec::new();
+            lines(&edited, policy, previous.interner().clone()).tokenize(&mut expected).unwrap();
+            assert_eq!(relexed.tokens, expected);
+            relexed.relexed
+        };
+
+        let source = "let ab cd\nlet x";
+        assert_eq!(relex(source, TextEdit::new(6..6, "c"), TriviaPolicy::Detached), 1..2);
+        assert_eq!(relex(source, TextEdit::new(4..4, "\nx "), TriviaPolicy::Detached), 1..2);
+        assert_eq!(relex(source, TextEdit::new(3..9, ""), TriviaPolicy::Detached), 0..1);
+        assert_eq!(relex(source, TextEdit::new(15..15, "y z"), TriviaPolicy::Detached), 4..6);
+        assert_eq!(relex(source, TextEdit::new(6..6, "c"), TriviaPolicy::Trailing), 0..2);
+        assert_eq!(relex(source, TextEdit::new(0..1, "n"), TriviaPolicy::Leading), 0..1);
+    }
+
+    #[test]
+    fn test_iterator() {
+        let tokens: Vec<Kind> = lexer("let x y").map(|token| token.unwrap().token_type().clone()).collect();
+        assert_eq!(tokens, [Kind::Let, Kind::Name, Kind::Name]);
+
+        // The error comes after the tokens before it, and ends the iterator
+        let mut tokens = lexer("x 1 y");
+        assert!(tokens.next().unwrap().is_ok());
+        assert_eq!(tokens.next().unwrap().unwrap_err().to_string(), "1:3: no token matches the input.");
+        assert!(tokens.next().is_none());
+    }
+
+    #[test]
+    fn test_stream() {
+        let mut stream = lexer("let a b c");
+
+        // The tokens are lexed when they are read
+        assert_eq!(stream.peek().map(|token| token.token_type().clone()), Some(Kind::Let));
+        assert_eq!(stream.location().index(), 3);
+        assert_eq!(stream.peek_nth(2).map(|token| token.span().start().index()), Some(6));
+        assert_eq!(stream.index(), 0);
+
+        let checkpoint = stream.checkpoint();
+        assert_eq!(stream.consume_nth(1).map(|token| token.span().start().index()), Some(4));
+        assert_eq!(stream.index(), 2);
+        stream.rewind(checkpoint).unwrap();
+        assert_eq!(stream.index(), 0);
+        assert_eq!(stream.consume().map(|token| token.token_type().clone()), Some(Kind::Let));
+        stream.release(checkpoint);
+        stream.rewind(Checkpoint::new(3, 0)).unwrap();
+        assert_eq!(stream.consume().map(|token| token.span().start().index()), Some(8));
+        assert!(stream.is_eof());
+        assert_eq!(stream.consume_nth(0), None);
+        assert!(stream.error().is_none());
+    }
+
+    #[test]
+    fn test_stream_rewind() {
+        let source = "a ".repeat(100);
+        let mut stream = lexer(&source);
+
+        // The tokens consumed since a live checkpoint are kept, even past the capacity of the lookahead
+        stream.consume();
+        let checkpoint = stream.checkpoint();
+        assert!(stream.consume_nth(49).is_some());
+        stream.rewind(checkpoint).unwrap();
+        assert_eq!(stream.index(), 1);
+        assert_eq!(stream.consume().map(|token| token.span().start().index()), Some(2));
+
+        // Once released, they are overwritten by the next tokens
+        stream.release(checkpoint);
+        assert!(stream.consume_nth(97).is_some());
+        assert!(stream.is_eof());
+        assert_eq!(stream.rewind(checkpoint).unwrap_err(), ParserError::Lex(LexError::NoLookBehind(1)));
+        assert_eq!(stream.index(), 100);
+
+        // A checkpoint after the end of the input can't be reached
+        let mut stream = lexer("a b");
+        assert_eq!(stream.rewind(Checkpoint::new(3, 0)).unwrap_err(), ParserError::Lex(LexError::NoLookBehind(3)));
+        assert_eq!(stream.index(), 0);
+        stream.rewind(Checkpoint::new(2, 0)).unwrap();
+        assert!(stream.is_eof());
+
+        // Nor one after an input that can't be lexed
+        let mut stream = lexer("a 1 b");
+        assert_eq!(stream.rewind(Checkpoint::new(2, 0)).unwrap_err().to_string(), "1:3: no token matches the input.");
+        assert_eq!(stream.index(), 0);
+    }
+
+    #[test]
+    fn test_keywords() {
+        let mut lexer = Lexer::new(StringCharReader::new("let letter le"))
+            .with_token(Kind::Name, &range!('a', 'z').at_least(1))
+            .with_trivia(&word!(" "))
+            .with_keywords(Kind::Name, &[("let", Kind::Let)])
+            .with_interner(Interner::new())
+            .with_keywords(Kind::Name, &[("letter", Kind::Text)]);
+        let tokens: Vec<Token<Kind>> = lexer.by_ref().map(Result::unwrap).collect();
+        let types: Vec<&Kind> = tokens.iter().map(Token::token_type).collect();
+        assert_eq!(types, [&Kind::Let, &Kind::Text, &Kind::Name]);
+        // The keywords and the lexemes have the same symbols
+        assert_eq!(lexer.interner().len(), 3);
+        assert_eq!(lexer.interner().resolve(tokens[2].lexeme().unwrap()), Some("le"));
+    }
+
+    #[test]
+    fn test_decoding() {
+        let mut lexer = Lexer::new(StringCharReader::new("12 'ab' 99999999999999999999"))
+            .with_token(Kind::Name, &range!('0', '9').at_least(1))
+            .decoding(|text| text.parse().ok().map(TokenValue::Integer))
+            .with_token(Kind::Text, &seq!(word!("'"), range!('a', 'z').at_least(0), word!("'")))
+            .decoding(|text| Some(TokenValue::String(text[1..text.len() - 1].to_string())))
+            .with_trivia(&word!(" "));
+        let tokens: Vec<Token<Kind>> = lexer.by_ref().map(Result::unwrap).collect();
+        assert_eq!(tokens[0].value(), Some(&TokenValue::Integer(12)));
+        assert_eq!(tokens[1].value(), Some(&TokenValue::String("ab".to_string())));
+        assert_eq!(lexer.interner().resolve(tokens[1].lexeme().unwrap()), Some("'ab'"));
+        // The value is optional
+        assert_eq!(tokens[2].value(), None);
+    }
+}
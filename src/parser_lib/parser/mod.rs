@@ -1,3 +1,9 @@
-mod tokenizer;
+mod dot_export;
+mod ebnf_loader;
+mod grammar_handle;
+mod lexer;
+mod railroad;
 
-pub use tokenizer::Tokenizer;
\ No newline at end of file
+pub use ebnf_loader::GrammarLoadError;
+pub use grammar_handle::{GrammarChange, GrammarHandle};
+pub use lexer::{Lexer, Relexed, TokenAmbiguity, Tokens, TriviaPolicy, INITIAL_STATE};
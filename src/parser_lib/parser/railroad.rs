@@ -0,0 +1,333 @@
+use std::fmt::Write;
+
+use crate::parser_lib::{Grammar, InNotation, MatchStr, MatchToken, MatcherShape, Notation};
+
+// Dimensions of the diagrams, in pixels
+/// Width of a char of the labels (monospace font).
+const CHAR_WIDTH: usize = 8;
+/// Half of the height of a box.
+const BOX_HALF_HEIGHT: usize = 11;
+/// Horizontal padding in the boxes, and length of the lines between the items.
+const GAP: usize = 10;
+/// Radius of the curves, and space taken by the branches on each side of a choice or a loop.
+const ARC: usize = 10;
+/// Vertical space between the branches of a choice, or between an item and its loop. Curves need at least 2 arcs.
+const VERTICAL_GAP: usize = 2 * ARC;
+/// Height taken by the name of a rule above its diagram, and margins around it.
+const TITLE_HEIGHT: usize = 20;
+const MARGIN: usize = 20;
+
+impl<R: 'static + MatchStr> Grammar<R> {
+    /// Draws a railroad (syntax) diagram of each named rule, in a single SVG image.
+    ///
+    /// Terminals are drawn in rounded boxes and references to rules in square boxes. Lookaheads are drawn in dashed
+    /// boxes, labeled `not` or `and`.
+    pub fn to_railroad_svg(&self) -> String {
+        let diagrams: Vec<(&str, Item)> = self.productions().into_iter().map(|(name, rule)| (name, Item::of(rule))).collect();
+
+        let width = diagrams.iter().map(|(_, item)| item.width() + 2 * ARC).max().unwrap_or(0) + 2 * MARGIN;
+        let height = diagrams.iter().map(|(_, item)| TITLE_HEIGHT + item.up() + item.down() + MARGIN).sum::<usize>() + MARGIN;
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">",
+            width, height, width, height
+        );
+        svg.push_str("<style>path, rect { fill: none; stroke: black; stroke-width: 1.5; } ");
+        svg.push_str("text { font-family: monospace; font-size: 12px; } ");
+        svg.push_str("rect.group { stroke-dasharray: 4 3; stroke-width: 1; } text.title { font-weight: bold; }</style>\n");
+
+        let mut top = MARGIN;
+        for (name, item) in &diagrams {
+            let _ = writeln!(svg, "<text class=\"title\" x=\"{}\" y=\"{}\">{}</text>", MARGIN, top + 12, escape(name));
+
+            // The diagram starts and ends with a short vertical bar
+            let y = top + TITLE_HEIGHT + item.up();
+            let x = MARGIN;
+            let _ = writeln!(svg, "<path d=\"M{} {} v20 m0 -10 h{}\"/>", x, y - 10, ARC);
+            item.draw(&mut svg, x + ARC, y);
+            let end = x + ARC + item.width();
+            let _ = writeln!(svg, "<path d=\"M{} {} h{} m0 -10 v20\"/>", end, y, ARC);
+
+            top = y + item.down() + MARGIN;
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+/// Element of a railroad diagram.
+///
+/// Each item is drawn on a horizontal line: it is entered on the left and exited on the right.
+enum Item {
+    /// Matches some text, like a string.
+    Terminal(String),
+    /// Refers to another rule.
+    NonTerminal(String),
+    /// Matches nothing.
+    Skip,
+    Sequence(Vec<Item>),
+    /// The first alternative is on the line, the other ones are below.
+    Choice(Vec<Item>),
+    /// Matches the item once or more, with a loop below it.
+    OneOrMore(Box<Item>),
+    /// Labeled dashed box around an item.
+    Group(&'static str, Box<Item>),
+}
+
+impl Item {
+    /// Converts a matcher tree to a diagram.
+    fn of<R>(matcher: &dyn MatchToken<R>) -> Self {
+        match matcher.shape() {
+            MatcherShape::Terminal
+            | MatcherShape::Literal(_)
+            | MatcherShape::Keywords(_)
+            | MatcherShape::Range { .. } => {
+                Item::Terminal(InNotation(matcher, Notation::Ebnf).to_string())
+            }
+            MatcherShape::Reference(name) => Item::NonTerminal(name.to_string()),
+            MatcherShape::Sequence(children) => Item::Sequence(children.iter().map(|c| Item::of(c.as_ref())).collect()),
+            MatcherShape::Choice(children) => Item::Choice(children.iter().map(|c| Item::of(c.as_ref())).collect()),
+            MatcherShape::Repetition { value, min, max } => Item::repetition(|| Item::of(value.as_ref()), min, max),
+            MatcherShape::Optional(value) => Item::repetition(|| Item::of(value.as_ref()), 0, 1),
+            MatcherShape::Not(value) => Item::Group("not", Box::new(Item::of(value.as_ref()))),
+            MatcherShape::And(value) => Item::Group("and", Box::new(Item::of(value.as_ref()))),
+            MatcherShape::Until { until, min } => Item::until(until.as_ref(), min),
+            MatcherShape::Error(sync) => Item::Group("error", Box::new(Item::until(sync.as_ref(), 1))),
+            MatcherShape::Wrapper(value) | MatcherShape::Label { value, .. } => Item::of(value.as_ref()),
+        }
+    }
+
+    /// Repeats at least min times any char that doesn't start the terminator.
+    fn until<R>(until: &dyn MatchToken<R>, min: usize) -> Self {
+        let any = || {
+            Item::Sequence(vec![Item::Group("not", Box::new(Item::of(until))), Item::Terminal(".".to_string())])
+        };
+        Item::repetition(any, min, 0)
+    }
+
+    /// Repeats an item between min and max times (if max is 0, there is no limit).
+    fn repetition(item: impl Fn() -> Item, min: usize, max: usize) -> Self {
+        let optional = |item| Item::Choice(vec![Item::Skip, item]);
+
+        let mut items: Vec<Item> = (0..min.saturating_sub(1)).map(|_| item()).collect();
+        match (min, max) {
+            (0, 0) => items.push(optional(Item::OneOrMore(Box::new(item())))),
+            (_, 0) => items.push(Item::OneOrMore(Box::new(item()))),
+            _ => {
+                if min > 0 {
+                    items.push(item());
+                }
+                items.extend((min..max).map(|_| optional(item())));
+            }
+        }
+
+        match items.len() {
+            0 => Item::Skip,
+            1 => items.remove(0),
+            _ => Item::Sequence(items),
+        }
+    }
+
+    fn width(&self) -> usize {
+        match self {
+            Item::Terminal(label) | Item::NonTerminal(label) => label.chars().count() * CHAR_WIDTH + 2 * GAP,
+            Item::Skip => 0,
+            Item::Sequence(items) => {
+                items.iter().map(Item::width).sum::<usize>() + GAP * items.len().saturating_sub(1)
+            }
+            Item::Choice(items) => items.iter().map(Item::width).max().unwrap_or(0) + 4 * ARC,
+            Item::OneOrMore(item) => item.width() + 4 * ARC,
+            Item::Group(label, item) => item.width().max(label.len() * CHAR_WIDTH) + 2 * GAP,
+        }
+    }
+
+    /// Height above the line.
+    fn up(&self) -> usize {
+        match self {
+            Item::Terminal(_) | Item::NonTerminal(_) => BOX_HALF_HEIGHT,
+            Item::Skip => 0,
+            Item::Sequence(items) => items.iter().map(Item::up).max().unwrap_or(0),
+            Item::Choice(items) => items.first().map_or(0, Item::up),
+            Item::OneOrMore(item) => item.up(),
+            Item::Group(_, item) => item.up() + GAP + TITLE_HEIGHT,
+        }
+    }
+
+    /// Height below the line.
+    fn down(&self) -> usize {
+        match self {
+            Item::Terminal(_) | Item::NonTerminal(_) => BOX_HALF_HEIGHT,
+            Item::Skip => 0,
+            Item::Sequence(items) => items.iter().map(Item::down).max().unwrap_or(0),
+            Item::Choice(items) => {
+                let first = items.first().map_or(0, Item::down);
+                first + items.iter().skip(1).map(|i| VERTICAL_GAP + i.up() + i.down()).sum::<usize>()
+            }
+            Item::OneOrMore(item) => item.down() + VERTICAL_GAP,
+            Item::Group(_, item) => item.down() + GAP,
+        }
+    }
+
+    /// Draws the item, entering it at (x, y).
+    fn draw(&self, svg: &mut String, x: usize, y: usize) {
+        let width = self.width();
+
+        match self {
+            Item::Terminal(label) | Item::NonTerminal(label) => {
+                let radius = if matches!(self, Item::Terminal(_)) { BOX_HALF_HEIGHT } else { 0 };
+                let _ = writeln!(
+                    svg,
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{}\"/>",
+                    x,
+                    y - BOX_HALF_HEIGHT,
+                    width,
+                    2 * BOX_HALF_HEIGHT,
+                    radius
+                );
+                let _ = writeln!(svg, "<text x=\"{}\" y=\"{}\">{}</text>", x + GAP, y + 4, escape(label));
+            }
+            Item::Skip => {}
+            Item::Sequence(items) => {
+                let mut x = x;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        line(svg, x, y, GAP);
+                        x += GAP;
+                    }
+                    item.draw(svg, x, y);
+                    x += item.width();
+                }
+            }
+            Item::Choice(items) => {
+                let end = x + width;
+                let mut branch_y = y;
+
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        branch_y += items[i - 1].down() + VERTICAL_GAP + item.up();
+
+                        // Curves down from the entry, and back up to the exit
+                        let _ = writeln!(
+                            svg,
+                            "<path d=\"M{x} {y} q{a} 0 {a} {a} V{b} q0 {a} {a} {a}\"/>",
+                            x = x,
+                            y = y,
+                            a = ARC,
+                            b = branch_y - ARC
+                        );
+                        let _ = writeln!(
+                            svg,
+                            "<path d=\"M{x} {b} q{a} 0 {a} -{a} V{y} q0 -{a} {a} -{a}\"/>",
+                            x = end - 2 * ARC,
+                            y = y + ARC,
+                            a = ARC,
+                            b = branch_y
+                        );
+                    } else {
+                        line(svg, x, y, 2 * ARC);
+                        line(svg, end - 2 * ARC, y, 2 * ARC);
+                    }
+
+                    item.draw(svg, x + 2 * ARC, branch_y);
+                    line(svg, x + 2 * ARC + item.width(), branch_y, width - 4 * ARC - item.width());
+                }
+            }
+            Item::OneOrMore(item) => {
+                let end = x + width;
+                let loop_y = y + self.down();
+
+                line(svg, x, y, 2 * ARC);
+                item.draw(svg, x + 2 * ARC, y);
+                line(svg, x + 2 * ARC + item.width(), y, width - 2 * ARC - item.width());
+
+                // Loop back below the item
+                let _ = writeln!(
+                    svg,
+                    "<path d=\"M{s} {y} q{a} 0 {a} {a} V{l} q0 {a} -{a} {a} H{e} q-{a} 0 -{a} -{a} V{t} q0 -{a} {a} -{a}\"/>",
+                    s = end - 2 * ARC,
+                    y = y,
+                    a = ARC,
+                    l = loop_y - ARC,
+                    e = x + 2 * ARC,
+                    t = y + ARC
+                );
+            }
+            Item::Group(label, item) => {
+                let top = y - item.up() - GAP;
+                let _ = writeln!(
+                    svg,
+                    "<rect class=\"group\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/>",
+                    x,
+                    top,
+                    width,
+                    item.up() + item.down() + 2 * GAP
+                );
+                let _ = writeln!(svg, "<text x=\"{}\" y=\"{}\">{}</text>", x, top - 5, label);
+
+                line(svg, x, y, GAP);
+                item.draw(svg, x + GAP, y);
+                line(svg, x + GAP + item.width(), y, width - GAP - item.width());
+            }
+        }
+    }
+}
+
+/// Draws a horizontal line.
+fn line(svg: &mut String, x: usize, y: usize, length: usize) {
+    if length > 0 {
+        let _ = writeln!(svg, "<path d=\"M{} {} h{}\"/>", x, y, length);
+    }
+}
+
+/// Escapes a text for XML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::StringCharReader;
+
+    use super::*;
+
+    #[test]
+    fn test_item_size() {
+        let terminal = || Item::Terminal("\"a\"".to_string());
+        assert_eq!((terminal().width(), terminal().up(), terminal().down()), (44, 11, 11));
+
+        // Alternatives are stacked below the first one
+        let choice = Item::Choice(vec![terminal(), terminal(), Item::NonTerminal("abc".to_string())]);
+        assert_eq!((choice.width(), choice.up(), choice.down()), (84, 11, 11 + 2 * (20 + 22)));
+
+        // Optional items can be skipped on the line
+        let optional = Item::repetition(terminal, 0, 1);
+        assert_eq!((optional.width(), optional.up(), optional.down()), (84, 0, 20 + 22));
+
+        let repetition = Item::repetition(terminal, 2, 0);
+        assert_eq!((repetition.width(), repetition.up(), repetition.down()), (44 + 10 + 84, 11, 31));
+    }
+
+    #[test]
+    fn test_to_railroad_svg() {
+        let grammar = Grammar::<StringCharReader>::from_ebnf("list = \"<\" item* \">\" ; item = !\">\" [a-z]+ | list ;")
+            .unwrap();
+        let svg = grammar.to_railroad_svg();
+
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.ends_with("</svg>\n"));
+
+        // One diagram per rule
+        assert!(svg.contains(">list</text>"));
+        assert!(svg.contains(">item</text>"));
+        assert_eq!(svg.matches("class=\"title\"").count(), 2);
+
+        // The labels are escaped
+        assert!(svg.contains(">&quot;&lt;&quot;</text>"));
+        assert!(svg.contains(">[a-z]</text>"));
+        assert!(svg.contains(">not</text>"));
+        assert!(!svg.contains("<>"));
+    }
+}
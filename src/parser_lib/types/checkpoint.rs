@@ -0,0 +1,24 @@
+/// Position of the cursor of a stream, saved to be able to go back to it later.
+///
+/// See `Rewind::checkpoint` and `Rewind::rewind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Number of elems consumed before the checkpoint.
+    index: usize,
+    /// Position in the underlying input (like a byte offset). Its meaning depends on the stream.
+    position: usize,
+}
+
+impl Checkpoint {
+    pub fn new(index: usize, position: usize) -> Self {
+        Self { index, position }
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    collections::HashSet,
+    fmt::{Display, Formatter},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::{Grammar, Location, ParserError};
+use crate::parser_lib::StringCharReader;
+
+/// Result of a sample file of a corpus.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorpusEntry {
+    pub path: PathBuf,
+    /// Whether the grammar should accept the file (it is in the `valid` directory).
+    pub valid: bool,
+    /// Whether the grammar matched the whole file, or the error it returned.
+    pub result: Result<bool, ParserError>,
+}
+
+impl CorpusEntry {
+    /// Returns true if the grammar accepted the file as expected, or rejected it as expected.
+    pub fn is_ok(&self) -> bool {
+        self.result == Ok(self.valid)
+    }
+}
+
+/// Results of a grammar on a corpus of sample files, with the named rules that none of them matched.
+///
+/// The corpus is a directory containing a `valid` directory, with files the grammar must accept entirely,
+/// and an `invalid` one, with files it must reject. A rule that no file matches is not tested by the corpus.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorpusReport {
+    entries: Vec<CorpusEntry>,
+    /// Named rules matched by no file, in definition order.
+    unused_rules: Vec<String>,
+}
+
+impl CorpusReport {
+    /// Parses every file of the `valid` and `invalid` directories of the corpus (which may be missing) with the grammar.
+    pub fn run(grammar: &Grammar<StringCharReader>, corpus: impl AsRef<Path>) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        let mut used_rules = HashSet::new();
+
+        for (dir, valid) in [("valid", true), ("invalid", false)] {
+            for path in Self::files(&corpus.as_ref().join(dir))? {
+                let source = fs::read_to_string(&path)?;
+                let mut reader = StringCharReader::new(&source);
+                let (result, profile) = grammar.profile(&Location::beginning(), &mut reader);
+
+                used_rules.extend(profile.rules().iter().filter(|rule| rule.calls > rule.failures).map(|rule| rule.name.clone()));
+                let result = result.map(|info| info.is_some_and(|info| info.end().byte_offset() == source.len()));
+                entries.push(CorpusEntry { path, valid, result });
+            }
+        }
+
+        let unused_rules = grammar
+            .rules()
+            .map(|(name, _)| name)
+            .filter(|name| !used_rules.contains(*name))
+            .map(str::to_string)
+            .collect();
+        Ok(Self { entries, unused_rules })
+    }
+
+    /// Returns the files of the directory, sorted by name so that the reports are stable.
+    fn files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Returns the results of the files, the valid ones first.
+    pub fn entries(&self) -> &[CorpusEntry] {
+        &self.entries
+    }
+
+    /// Returns the files that the grammar accepted while they are invalid, or rejected while they are valid.
+    pub fn mismatches(&self) -> impl Iterator<Item = &CorpusEntry> {
+        self.entries.iter().filter(|entry| !entry.is_ok())
+    }
+
+    /// Returns the named rules that no file matched, in definition order.
+    pub fn unused_rules(&self) -> &[String] {
+        &self.unused_rules
+    }
+
+    /// Returns true if every file gave the expected result. Unused rules are not failures.
+    pub fn is_ok(&self) -> bool {
+        self.mismatches().next().is_none()
+    }
+}
+
+impl Display for CorpusReport {
+    /// Writes the mismatches and the unused rules, one per line.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mismatches = self.mismatches().count();
+        writeln!(f, "{} files, {} mismatches", self.entries.len(), mismatches)?;
+        for entry in self.mismatches() {
+            match &entry.result {
+                Ok(_) if entry.valid => writeln!(f, "rejected: {}", entry.path.display())?,
+                Ok(_) => writeln!(f, "accepted: {}", entry.path.display())?,
+                Err(err) => writeln!(f, "error: {}: {}", entry.path.display(), err)?,
+            }
+        }
+        if !self.unused_rules.is_empty() {
+            writeln!(f, "rules never matched: {}", self.unused_rules.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use crate::{
+        choice,
+        parser_lib::{GrammarBuilder, Rule},
+        test_grammars, word,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_corpus_report() {
+        let grammar = test_grammars::grammar("json").unwrap();
+        let report = CorpusReport::run(&grammar, "corpus/json").unwrap();
+
+        assert_eq!(report.is_ok(), true);
+        assert_eq!(report.entries().len(), 4);
+        assert_eq!(report.entries()[0].path, Path::new("corpus/json/valid/package.json"));
+        assert_eq!(report.entries()[3].valid, false);
+
+        // Another grammar rejects the valid files
+        let grammar = test_grammars::grammar("calculator").unwrap();
+        let report = CorpusReport::run(&grammar, "corpus/json").unwrap();
+        assert_eq!(report.is_ok(), false);
+        assert_eq!(report.mismatches().count(), 2);
+        assert_eq!(
+            report.to_string().lines().take(2).collect::<Vec<_>>(),
+            ["4 files, 2 mismatches", "rejected: corpus/json/valid/package.json"]
+        );
+
+        // A missing corpus has no files
+        let report = CorpusReport::run(&grammar, "corpus/missing").unwrap();
+        assert_eq!(report.entries().len(), 0);
+    }
+
+    #[test]
+    fn test_corpus_report_unused_rules() {
+        // Accepts anything, so the invalid files are accepted too
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let keyword = builder.define("keyword", word!("never"));
+        let any = builder.define("any", Rule::range('\0', char::MAX));
+        let grammar = builder.save_root(choice!(keyword, any).at_least(0));
+
+        let report = CorpusReport::run(&grammar, "corpus/calculator").unwrap();
+        assert_eq!(report.mismatches().map(|entry| entry.valid).collect::<Vec<_>>(), [false, false]);
+        assert_eq!(report.unused_rules(), ["keyword"]);
+        assert_eq!(
+            report.to_string(),
+            "4 files, 2 mismatches\n\
+             accepted: corpus/calculator/invalid/dangling_operator.txt\n\
+             accepted: corpus/calculator/invalid/unbalanced.txt\n\
+             rules never matched: keyword\n"
+        );
+    }
+}
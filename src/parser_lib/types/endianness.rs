@@ -0,0 +1,34 @@
+/// Byte order of the integer fields of a binary input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    /// The least significant byte comes first.
+    Little,
+    /// The most significant byte comes first.
+    Big,
+}
+
+impl Endianness {
+    /// Combines the given bytes into an unsigned integer, in this byte order.
+    /// There must be at most 8 bytes.
+    pub fn to_uint(self, bytes: &[u8]) -> u64 {
+        assert!(bytes.len() <= 8, "Integer fields can't be longer than 8 bytes");
+
+        match self {
+            Endianness::Little => bytes.iter().rev().fold(0, |value, b| (value << 8) | *b as u64),
+            Endianness::Big => bytes.iter().fold(0, |value, b| (value << 8) | *b as u64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_uint() {
+        assert_eq!(Endianness::Little.to_uint(&[0x34, 0x12]), 0x1234);
+        assert_eq!(Endianness::Big.to_uint(&[0x12, 0x34]), 0x1234);
+        assert_eq!(Endianness::Big.to_uint(&[0xff; 8]), u64::MAX);
+        assert_eq!(Endianness::Little.to_uint(&[]), 0);
+    }
+}
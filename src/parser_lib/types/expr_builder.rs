@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use crate::parser_lib::{Associativity, ExprMatcher, Fixity, Operator};
+
+use super::{MatchStr, Rule};
+
+/// Builds a rule matching expressions, from an operand rule and operators with their precedence.
+///
+/// ```ignore
+/// let expr = ExprBuilder::new(&number)
+///     .padding(&whitespace)
+///     .infix(&word!("+"), 10, Associativity::Left)
+///     .infix(&word!("*"), 20, Associativity::Left)
+///     .prefix(&word!("-"), 30)
+///     .build();
+/// ```
+///
+/// Operators with a higher precedence bind tighter. Use `build_matcher` to get the tree of the expressions.
+#[derive(Debug)]
+pub struct ExprBuilder<R: MatchStr> {
+    operand: Rule<R>,
+    operators: Vec<Operator<R>>,
+    padding: Option<Rule<R>>,
+}
+
+impl<R: 'static + MatchStr> ExprBuilder<R> {
+    pub fn new(operand: &Rule<R>) -> Self {
+        Self {
+            operand: operand.clone(),
+            operators: Vec::new(),
+            padding: None,
+        }
+    }
+
+    /// Ignores the given rule between operands and operators.
+    pub fn padding(mut self, padding: &Rule<R>) -> Self {
+        self.padding = Some(padding.clone());
+        self
+    }
+
+    /// Adds an operator placed before its operand.
+    pub fn prefix(self, operator: &Rule<R>, precedence: u32) -> Self {
+        self.operator(operator, Fixity::Prefix, precedence)
+    }
+
+    /// Adds an operator placed between its operands.
+    pub fn infix(self, operator: &Rule<R>, precedence: u32, associativity: Associativity) -> Self {
+        self.operator(operator, Fixity::Infix(associativity), precedence)
+    }
+
+    /// Adds an operator placed after its operand.
+    pub fn postfix(self, operator: &Rule<R>, precedence: u32) -> Self {
+        self.operator(operator, Fixity::Postfix, precedence)
+    }
+
+    fn operator(mut self, operator: &Rule<R>, fixity: Fixity, precedence: u32) -> Self {
+        self.operators.push(Operator::new(operator.matcher().clone(), fixity, precedence));
+        self
+    }
+
+    /// Creates the matcher, which can also return the tree of the expressions.
+    pub fn build_matcher(self) -> Arc<ExprMatcher<R>> {
+        Arc::new(ExprMatcher::new(
+            self.operand.matcher().clone(),
+            self.operators,
+            self.padding.map(|padding| padding.matcher().clone()),
+        ))
+    }
+
+    /// Creates the rule.
+    pub fn build(self) -> Rule<R> {
+        Rule::new(self.build_matcher())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::{GrammarBuilder, Location, MatchToken, StringCharReader};
+    use crate::{range, word};
+
+    use super::*;
+
+    #[test]
+    fn test_expr_builder() {
+        let number = range!('0', '9').at_least(1);
+        let expr = ExprBuilder::<StringCharReader>::new(&number)
+            .padding(&word!(" ").at_least(1))
+            .infix(&word!("=="), 5, Associativity::Left)
+            .infix(&word!("="), 1, Associativity::Right)
+            .infix(&word!("+"), 10, Associativity::Left)
+            .prefix(&word!("!"), 20)
+            .postfix(&word!("?"), 30)
+            .build_matcher();
+
+        let source = "12 + !3? == 4";
+        let mut reader = StringCharReader::new(source);
+        let tree = expr.parse(&Location::beginning(), &mut reader).unwrap().unwrap();
+        assert_eq!(tree.to_sexp(source), "(== (+ 12 (! (? 3))) 4)");
+
+        let source = "1 = 2 = 3 + 4";
+        let mut reader = StringCharReader::new(source);
+        let tree = expr.parse(&Location::beginning(), &mut reader).unwrap().unwrap();
+        assert_eq!(tree.to_sexp(source), "(= 1 (= 2 (+ 3 4)))");
+
+        // As a rule in a grammar
+        let grammar = GrammarBuilder::new().save_root(
+            ExprBuilder::new(&number)
+                .infix(&word!("+"), 1, Associativity::Left)
+                .build(),
+        );
+        assert_eq!(grammar.to_ebnf(), "root = [0-9]+, (\"+\", [0-9]+)* ;\n");
+        let mut reader = StringCharReader::new("1+23");
+        assert_eq!(grammar.test(&Location::beginning(), &mut reader).unwrap().unwrap().len(), 4);
+    }
+}
@@ -1,7 +1,19 @@
-use std::fmt::{Display, Error, Formatter};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt::{Display, Error, Formatter},
+    sync::Arc,
+};
 
-use super::{CreateParseResult, Location, MatchStr, MatchToken, ParseResult, ParserError, Rule, Stream};
-use crate::word;
+use super::{
+    incremental::Incremental, optimizer::Optimizer, CreateParseResult, InNotation, IncrementalTree, Location, LocationPolicy,
+    LosslessBuilder, MatchStr, MatchToken, MatcherShape, Notation, ParseArena, ParseContext, ParseEvent, ParseInfo,
+    ParseNode, ParseOptions, ParseProfile, ParseResult, ParserError, Rule, SyntaxError, SyntaxNode, TextEdit, Tracer,
+};
+use crate::{
+    parser_lib::{ReferenceMatcher, StringCharReader},
+    word,
+};
 
 #[derive(Debug)]
 pub struct Grammar<R: MatchStr> {
@@ -9,9 +21,17 @@ pub struct Grammar<R: MatchStr> {
     ///
     /// The intermediate rules are not needed, everything is stored in the root rule.
     root: Option<Rule<R>>,
+    /// Rules defined with a name, in definition order.
+    ///
+    /// They are owned by the grammar, so that the references to them (which are weak) stay valid.
+    rules: Vec<(String, Rule<R>)>,
     /// Keywords that are not allowed for identifiers.
     reserved_words: Vec<String>,
     ignored: Option<Rule<R>>,
+    /// Policy applied to the readers before matching. If None, the policy of the reader is kept.
+    location_policy: Option<LocationPolicy>,
+    /// Options used by `test`.
+    options: ParseOptions,
 }
 
 impl<R: MatchStr> Display for Grammar<R> {
@@ -25,43 +45,368 @@ impl<R: MatchStr> Display for Grammar<R> {
 
 impl<R: MatchStr> MatchToken<R> for Grammar<R> {
     fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
+        self.parse(loc, reader, &self.options)
+    }
+}
+
+impl<R: MatchStr> Grammar<R> {
+    /// Matches the grammar with the given options, instead of the ones of the grammar.
+    pub fn parse(&self, loc: &Location, reader: &mut R, options: &ParseOptions) -> ParseResult {
         match &self.root {
             // Be sure to have a grammar
-            None => ParseResult::error(ParserError::NoGrammarDefined),
-            Some(rule) => rule.test(loc, reader),
+            None => ParseResult::error(SyntaxError::NoGrammarDefined.into()),
+            Some(rule) => {
+                if let Some(policy) = self.location_policy {
+                    reader.set_location_policy(policy);
+                }
+                if let Some(look_ahead) = options.look_ahead() {
+                    reader.reserve_look_ahead(look_ahead);
+                }
+                ParseContext::with_options(options, || rule.test(loc, reader))
+            }
+        }
+    }
+
+    /// Matches the grammar like `test`, and returns the tree of the named rules that matched.
+    ///
+    /// If the root is not a named rule, the tree is a node named `root` containing the nodes of the named rules.
+    pub fn parse_tree(&self, loc: &Location, reader: &mut R) -> Result<Option<ParseNode>, ParserError> {
+        self.tree(loc, reader, &self.options)
+    }
+
+    /// Matches the grammar like `parse_tree`, but keeps the nodes in the arena they were built in.
+    ///
+    /// It avoids allocating each node on its own, which is faster and more compact for large inputs.
+    pub fn parse_arena(&self, loc: &Location, reader: &mut R) -> Result<Option<ParseArena>, ParserError> {
+        self.arena(loc, reader, &self.options)
+    }
+
+    /// Matches the grammar with the given options, and returns the tree of the named rules that matched.
+    fn tree(&self, loc: &Location, reader: &mut R, options: &ParseOptions) -> Result<Option<ParseNode>, ParserError> {
+        let arena = self.arena(loc, reader, options)?;
+        Ok(arena.and_then(|arena| Some(arena.to_node(arena.root()?))))
+    }
+
+    /// Matches the grammar with the given options, and returns the arena containing the tree of the named rules.
+    fn arena(&self, loc: &Location, reader: &mut R, options: &ParseOptions) -> Result<Option<ParseArena>, ParserError> {
+        let (result, mut arena, nodes) = ParseContext::with_nodes(|| self.parse(loc, reader, options));
+        let Some(info) = result? else {
+            return Ok(None);
+        };
+
+        let root_is_named = self.root.as_ref().is_some_and(|root| matches!(root.shape(), MatcherShape::Reference(_)));
+        let root = match nodes[..] {
+            [node] if root_is_named => node,
+            _ => arena.push("root", info.span().clone(), &nodes),
+        };
+        arena.set_root(root);
+        Ok(Some(arena))
+    }
+
+    /// Matches the grammar like `test`, and sends the events of the tree of the named rules to the sink.
+    ///
+    /// A rule can be undone by a backtrack until the whole parse is over, so the events are sent at the end.
+    /// If the parse fails, only an `Error` event is sent.
+    pub fn parse_events(&self, loc: &Location, reader: &mut R, mut sink: impl FnMut(ParseEvent)) -> ParseResult {
+        match self.parse_tree(loc, reader) {
+            Ok(Some(tree)) => {
+                tree.events(&mut sink);
+                let span = tree.span().clone();
+                let len = span.end().index() - span.start().index();
+                Ok(Some(ParseInfo::new(span, len)))
+            }
+            Ok(None) => Ok(None),
+            Err(err) => {
+                sink(ParseEvent::Error(err.clone()));
+                Err(err)
+            }
+        }
+    }
+
+    /// Returns the options used by `test`.
+    pub fn options(&self) -> &ParseOptions {
+        &self.options
+    }
+}
+
+impl Grammar<StringCharReader> {
+    /// Parses the source into a lossless syntax tree, which gives back the source byte for byte.
+    ///
+    /// The text between the named rules is split in trivia, where the ignored rule of the grammar matches, and tokens.
+    /// If the ignored rule is a named rule, its nodes are trivia too. The text around the root is put in a `root` node.
+    pub fn parse_lossless(&self, source: &str) -> Result<Option<SyntaxNode>, ParserError> {
+        let mut reader = StringCharReader::new(source);
+        let Some(tree) = self.parse_tree(&Location::beginning(), &mut reader)? else {
+            return Ok(None);
+        };
+
+        let ignored = self.ignored.as_ref().map(|rule| {
+            let name = match rule.shape() {
+                MatcherShape::Reference(name) => Some(name),
+                _ => None,
+            };
+            (rule as &dyn MatchToken<StringCharReader>, name)
+        });
+        let green = LosslessBuilder::new(source, ignored).build(&tree)?;
+        Ok(Some(SyntaxNode::new_root(Arc::new(green))))
+    }
+
+    /// Parses the source into a tree of the named rules, that `reparse` can update after an edit of the source.
+    pub fn parse_incremental(&self, source: &str) -> Result<IncrementalTree, ParserError> {
+        self.incremental(source.to_string(), Incremental::default())
+    }
+
+    /// Parses the source of the tree after the edit, by reusing the nodes of the tree that the edit can't change.
+    ///
+    /// Panics if the range of the edit is not in the source, or not on char boundaries.
+    pub fn reparse(&self, previous: &IncrementalTree, edit: &TextEdit) -> Result<IncrementalTree, ParserError> {
+        let policy = self.location_policy.unwrap_or_default();
+        self.incremental(edit.apply(previous.source()), Incremental::reparse(previous, edit, policy))
+    }
+
+    fn incremental(&self, source: String, incremental: Incremental) -> Result<IncrementalTree, ParserError> {
+        let mut reader = StringCharReader::new(&source);
+        reader.track_reads();
+        let loc = self.location_policy.unwrap_or_default().beginning();
+        let (tree, incremental) =
+            ParseContext::with_incremental(incremental, || self.tree(&loc, &mut reader, &self.options));
+        Ok(IncrementalTree::new(source, tree?, incremental))
+    }
+}
+
+impl<R: 'static + MatchStr> Grammar<R> {
+    /// Matches the grammar like `test`, and records the statistics of the named rules, to find the slowest ones.
+    pub fn profile(&self, loc: &Location, reader: &mut R) -> (ParseResult, ParseProfile) {
+        ParseContext::profiled(|| self.test(loc, reader))
+    }
+
+    /// Returns the rule defined with the given name, if any.
+    pub fn rule(&self, name: &str) -> Option<&Rule<R>> {
+        self.rules.iter().find(|(n, _)| n == name).map(|(_, rule)| rule)
+    }
+
+    /// Returns the named rules, in definition order.
+    pub fn rules(&self) -> impl Iterator<Item = (&str, &Rule<R>)> {
+        self.rules.iter().map(|(name, rule)| (name.as_str(), rule))
+    }
+
+    /// Returns the keywords that are not allowed for identifiers, in the order they were reserved.
+    pub fn reserved_words(&self) -> &[String] {
+        &self.reserved_words
+    }
+
+    /// Returns the named rules, preceded by the root if it is not one of them (then named `root`).
+    pub(crate) fn productions(&self) -> Vec<(&str, &Rule<R>)> {
+        let mut productions = Vec::new();
+
+        if let Some(root) = &self.root {
+            let is_named = match root.shape() {
+                MatcherShape::Reference(name) => self.rule(name).is_some(),
+                _ => self.rules.iter().any(|(_, rule)| Arc::ptr_eq(rule.matcher(), root.matcher())),
+            };
+            if !is_named {
+                productions.push(("root", root));
+            }
+        }
+        productions.extend(self.rules());
+        productions
+    }
+
+    /// Returns an equivalent grammar with simpler matcher trees, see `Rule::optimize`.
+    ///
+    /// The structurally identical matchers are shared between all the rules of the grammar.
+    pub fn optimize(&self) -> Self {
+        let mut optimizer = Optimizer::with_new_references();
+        let mut optimize = |rule: &Rule<R>| Rule::new(optimizer.optimize(rule.matcher()));
+
+        let root = self.root.as_ref().map(&mut optimize);
+        let ignored = self.ignored.as_ref().map(&mut optimize);
+        let rules: Vec<(String, Rule<R>)> = self
+            .rules
+            .iter()
+            .map(|(name, rule)| (name.clone(), optimize(rule)))
+            .collect();
+
+        // Resolve the new references to the new rules
+        for (name, rule) in &rules {
+            if let Some(reference) = optimizer.reference(name) {
+                reference.resolve(rule.matcher());
+            }
+        }
+
+        Self {
+            root,
+            rules,
+            reserved_words: self.reserved_words.clone(),
+            ignored,
+            location_policy: self.location_policy,
+            options: self.options.clone(),
         }
     }
+
+    /// Writes the grammar in EBNF, with one production per named rule.
+    ///
+    /// If the root is not a named rule, it is written first as `root`.
+    pub fn to_ebnf(&self) -> String {
+        self.to_notation(Notation::Ebnf)
+    }
+
+    /// Writes the grammar as a PEG, with one production per named rule.
+    ///
+    /// If the root is not a named rule, it is written first as `root`.
+    pub fn to_peg(&self) -> String {
+        self.to_notation(Notation::Peg)
+    }
+
+    /// Writes the grammar in the given notation, with one production per line.
+    pub fn to_notation(&self, notation: Notation) -> String {
+        self.productions()
+            .into_iter()
+            .map(|(name, rule)| {
+                format!(
+                    "{}{}{}{}\n",
+                    name,
+                    notation.definition(),
+                    InNotation(rule, notation),
+                    notation.terminator()
+                )
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug)]
 pub struct GrammarBuilder<R: MatchStr> {
     grammar: Grammar<R>,
+    /// References to named rules, resolved when the rule is defined.
+    references: HashMap<String, Arc<ReferenceMatcher<R>>>,
+}
+
+impl<R: 'static + MatchStr> Default for GrammarBuilder<R> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<R: 'static + MatchStr > GrammarBuilder<R> {
     pub fn new() -> Self {
         let grammar = Grammar::<R> {
             root: None,
+            rules: Vec::new(),
             reserved_words: Vec::new(),
             ignored: None,
+            location_policy: None,
+            options: ParseOptions::default(),
         };
-        GrammarBuilder { grammar }
+        GrammarBuilder {
+            grammar,
+            references: HashMap::new(),
+        }
     }
 
-    #[allow(unused)]
-    pub fn reserved(&mut self, word: &'static str) -> Rule<R> {
+    /// Returns a reference to the named rule, which can be defined later with `define`.
+    ///
+    /// That way, rules can be recursive.
+    pub fn rule(&mut self, name: &str) -> Rule<R> {
+        let reference = self
+            .references
+            .entry(name.to_string())
+            .or_insert_with(|| Arc::new(ReferenceMatcher::new(name)));
+        Rule::new(reference.clone())
+    }
+
+    /// Defines a named rule, resolves the references to it, and returns one of them.
+    ///
+    /// A rule can only be defined once: the references keep the first definition.
+    pub fn define(&mut self, name: &str, rule: Rule<R>) -> Rule<R> {
+        // Creating the reference here resolves the ones made later, too
+        let reference = self.rule(name);
+        self.references[name].resolve(rule.matcher());
+
+        self.grammar.rules.push((name.to_string(), rule));
+        reference
+    }
+
+    /// Returns the names of the rules that are referenced but not defined.
+    pub fn undefined_rules(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .references
+            .values()
+            .filter(|r| !r.is_resolved())
+            .map(|r| r.name())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Reserves a keyword, and returns a rule matching it. Use `any_reserved` to exclude the keywords from identifiers.
+    pub fn reserved(&mut self, word: impl Into<Cow<'static, str>>) -> Rule<R> {
+        let word = word.into();
         self.grammar.reserved_words.push(word.to_string());
         word!(word)
     }
 
+    /// Returns a rule matching any of the words reserved so far, the longest ones first.
+    ///
+    /// It doesn't check what follows the word: `let` matches the start of `letter`.
+    pub fn any_reserved(&self) -> Rule<R> {
+        let mut words: Vec<&str> = self.grammar.reserved_words.iter().map(String::as_str).collect();
+        words.sort_by_key(|word| std::cmp::Reverse(word.len()));
+        Rule::keywords(&words)
+    }
+
     pub fn save_root(mut self, root: Rule<R>) -> Grammar<R> {
         self.grammar.root = Some(root);
         self.grammar
     }
 
+    /// Sets the rule matching what can be found between tokens (whitespace, comments...).
+    ///
+    /// It is not added to the rules: use `Rule::padded` or `padded_seq` where it is allowed. It gives the trivia of
+    /// `Grammar::parse_lossless`.
     pub fn ignore(&mut self, ignored: Rule<R>) {
         self.grammar.ignored = Some(ignored);
     }
+
+    /// Matches a sequence of rules, with the ignored rule set so far allowed between them, see `Rule::padded_seq`.
+    ///
+    /// Without ignored rule, it is a plain sequence.
+    pub fn padded_seq(&self, rules: Vec<&Rule<R>>) -> Rule<R> {
+        match &self.grammar.ignored {
+            Some(ignored) => Rule::padded_seq(rules, ignored),
+            None => Rule::seq(rules),
+        }
+    }
+
+    /// Sets the options used when the grammar is matched with `test`.
+    #[allow(unused)]
+    pub fn options(&mut self, options: ParseOptions) {
+        self.grammar.options = options;
+    }
+
+    /// Sets the maximum number of nested rules, see `ParseOptions::with_recursion_limit`.
+    #[allow(unused)]
+    pub fn recursion_limit(&mut self, limit: usize) {
+        self.grammar.options = self.grammar.options.clone().with_recursion_limit(limit);
+    }
+
+    /// Sets the maximum number of steps of a parse, see `ParseOptions::with_fuel`.
+    #[allow(unused)]
+    pub fn fuel(&mut self, fuel: usize) {
+        self.grammar.options = self.grammar.options.clone().with_fuel(fuel);
+    }
+
+    /// Sends the entry and exit of every named rule to the tracer during a parse, see `ParseOptions::with_tracer`.
+    #[allow(unused)]
+    pub fn trace(&mut self, tracer: Tracer) {
+        self.grammar.options = self.grammar.options.clone().with_tracer(tracer);
+    }
+
+    /// Sets how locations are computed when the grammar is used.
+    #[allow(unused)]
+    pub fn location_policy(&mut self, policy: LocationPolicy) {
+        self.grammar.location_policy = Some(policy);
+    }
 }
 
 // Define a macro to make this simpler
@@ -73,11 +418,10 @@ macro_rules! define_grammar {
     ($language:ident, $body:expr) => {
         pub mod $language {
             use super::*;
-            use crate::parser_lib::Grammar;
-            use crate::parser_lib::GrammarBuilder;
-            use crate::parser_lib::MatchStr;
-            use crate::parser_lib::Rule;
-            use crate::parser_lib::Stream;
+            use $crate::parser_lib::Grammar;
+            use $crate::parser_lib::GrammarBuilder;
+            use $crate::parser_lib::MatchStr;
+            use $crate::parser_lib::Rule;
 
             // Create the function
             #[allow(unused)]
@@ -92,12 +436,13 @@ macro_rules! define_grammar {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
     use crate::{
-        choice,
-        parser_lib::{ParseInfo, Span, StringCharReader},
-        range,
+        assert_parses, assert_rejects, assert_span, choice,
+        parser_lib::{ParseInfo, ParserError, Span, StringCharReader, TraceLevel, DEFAULT_RECURSION_LIMIT},
+        range, seq, until,
     };
 
     define_grammar!(my_grammar, |_grammar: &mut GrammarBuilder<R>| {
@@ -127,10 +472,425 @@ mod tests {
 
         let mut reader = StringCharReader::new("22+13");
 
-        // It should match everything
-        let info = ParseInfo::new(Span::new(Location::beginning(), Location::new(1, 6, 5)), 5);
+        // It should match everything, with a repetition for each term
+        let loc = Location::beginning();
+        let terms = vec![Span::new(loc, loc + 2), Span::new(loc + 2, loc + 3), Span::new(loc + 3, loc + 5)];
+        let info = ParseInfo::new(Span::new(loc, Location::new(1, 6, 5)), 5).with_repetitions(terms);
         let loc = Location::beginning();
         assert_eq!(grammar.test(&loc, &mut reader).is_ok(), true);
         assert_eq!(grammar.test(&loc, &mut reader).unwrap(), Some(info));
     }
+
+    define_grammar!(tabbed, |grammar: &mut GrammarBuilder<R>| {
+        grammar.location_policy(LocationPolicy::new().with_tab_width(4).zero_based());
+
+        seq!(word!("\t"), word!("x"))
+    });
+
+    #[test]
+    fn test_grammar_reserved() {
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        builder.reserved("in");
+        // The words can come from runtime data
+        builder.reserved(format!("{}t", "in"));
+        let reserved = builder.any_reserved();
+        assert_span!(reserved, "int", 0..3);
+        assert_span!(reserved, "inside", 0..2);
+
+        // Identifiers can start with a keyword, but not be one
+        let letter = Rule::range('a', 'z');
+        let keyword = reserved.then(&letter.not());
+        let identifier = builder.define("identifier", seq!(keyword.not(), letter.at_least(1)));
+        let grammar = builder.save_root(identifier);
+        assert_parses!(grammar, "inside");
+        assert_rejects!(grammar, "int");
+        assert_rejects!(grammar, "in");
+    }
+
+    #[test]
+    fn test_grammar_padded_seq() {
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let name = range!('a', 'z').at_least(1);
+
+        // Without ignored rule, nothing is allowed between the rules
+        let tight = builder.padded_seq(vec![&name, &word!("="), &name]);
+        assert_parses!(tight, "a=b");
+        assert_rejects!(tight, "a = b");
+
+        builder.ignore(choice!(word!(" "), word!("\n")));
+        let assignment = builder.padded_seq(vec![&name, &word!("="), &name]);
+        let grammar = builder.save_root(assignment);
+        assert_parses!(grammar, "a=b");
+        assert_parses!(grammar, "a = \n b");
+        assert_rejects!(grammar, " a=b");
+    }
+
+    #[test]
+    fn test_grammar_location_policy() {
+        let grammar = tabbed::define_grammar::<StringCharReader>();
+        let mut reader = StringCharReader::new("\tx");
+
+        // The grammar applies its policy to the reader
+        let loc = LocationPolicy::new().zero_based().beginning();
+        let info = ParseInfo::new(Span::new(loc, Location::new(0, 5, 2)), 2);
+        assert_eq!(grammar.test(&loc, &mut reader).unwrap(), Some(info));
+        assert_eq!(reader.location_policy().tab_width(), 4);
+    }
+
+    #[test]
+    fn test_grammar_export() {
+        // Without named rules, the whole grammar is the root
+        let grammar = my_grammar::define_grammar::<StringCharReader>();
+        assert_eq!(grammar.to_ebnf(), "root = ([0-9]+ | \"+\" | \"-\" | \"*\" | \"/\" | \"%\")+ ;\n");
+
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let value = builder.rule("value");
+        let list = builder.define(
+            "list",
+            seq!(word!("["), seq!(value, seq!(word!(","), value).at_least(0)).optional(), word!("]")),
+        );
+        let string = seq!(word!("\""), until!(word!("\""), 0), word!("\""));
+        builder.define("value", choice!(list, string, range!('0', '9').repeat_between(1, 3)));
+        let grammar = builder.save_root(list);
+
+        assert_eq!(
+            grammar.to_ebnf(),
+            concat!(
+                "list = \"[\", (value, (\",\", value)*)?, \"]\" ;\n",
+                "value = list | \"\\\"\", (!\"\\\"\", .)*, \"\\\"\" | [0-9], [0-9]?, [0-9]? ;\n",
+            )
+        );
+        assert_eq!(
+            grammar.to_peg(),
+            concat!(
+                "list <- \"[\" (value (\",\" value)*)? \"]\"\n",
+                "value <- list / \"\\\"\" (!\"\\\"\" .)* \"\\\"\" / [0-9] [0-9]? [0-9]?\n",
+            )
+        );
+
+        // The exported grammars can be loaded back
+        for text in [grammar.to_ebnf(), grammar.to_peg()] {
+            let loaded = Grammar::<StringCharReader>::from_ebnf(&text).unwrap();
+            assert_eq!(loaded.to_ebnf(), grammar.to_ebnf());
+
+            let mut reader = StringCharReader::new("[1,\"a\\\",[]]");
+            assert_eq!(loaded.test(&Location::beginning(), &mut reader).unwrap().unwrap().len(), 11);
+        }
+    }
+
+    #[test]
+    fn test_grammar_optimize() {
+        let grammar = Grammar::<StringCharReader>::from_ebnf(
+            "list = \"(\" , (\"i\" \"t\" \"e\" \"m\" | list | (\"x\" | \"y\"))*, \")\" ; other = (\"x\" | \"y\") ;",
+        )
+        .unwrap();
+        let optimized = grammar.optimize();
+
+        assert_eq!(
+            optimized.to_peg(),
+            concat!(
+                "list <- \"(\" (\"item\" / list / \"x\" / \"y\")* \")\"\n",
+                "other <- \"x\" / \"y\"\n",
+            )
+        );
+
+        // The references point to the optimized rules
+        drop(grammar);
+        let mut reader = StringCharReader::new("(x(item)y)");
+        assert_eq!(optimized.test(&Location::beginning(), &mut reader).unwrap().unwrap().len(), 10);
+    }
+
+    #[test]
+    fn test_grammar_threads() {
+        // Left-recursive, to use the state of the references from several threads
+        let grammar = Grammar::<StringCharReader>::from_ebnf("sum = sum \"+\" [0-9] | [0-9] ;").unwrap();
+
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (1..8)
+                .map(|n| {
+                    let grammar = &grammar;
+                    scope.spawn(move || {
+                        let input = vec!["1"; n].join("+");
+                        let mut reader = StringCharReader::new(&input);
+                        grammar.test(&Location::beginning(), &mut reader).unwrap().unwrap().len()
+                    })
+                })
+                .collect();
+
+            for (n, handle) in (1..8).zip(handles) {
+                assert_eq!(handle.join().unwrap(), 2 * n - 1);
+            }
+        });
+    }
+
+    #[test]
+    fn test_grammar_recursion_limit() {
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let nested = builder.rule("nested");
+        let nested = builder.define("nested", seq!(word!("("), nested.optional(), word!(")")));
+        builder.recursion_limit(10);
+        let grammar = builder.save_root(nested.clone());
+
+        // The rule is tried once more after the last parenthesis
+        let input = "(".repeat(9) + &")".repeat(9);
+        let mut reader = StringCharReader::new(&input);
+        assert_eq!(grammar.test(&Location::beginning(), &mut reader).unwrap().unwrap().len(), 18);
+
+        let input = "(".repeat(100_000);
+        let mut reader = StringCharReader::new(&input);
+        let err = grammar.test(&Location::beginning(), &mut reader).unwrap_err();
+        assert_eq!(err.cause(), &ParserError::Syntax(SyntaxError::RecursionLimit { limit: 10, location: Location::new(1, 11, 10) }));
+        assert_eq!(err.context().len(), 11);
+
+        // Without grammar, the default limit applies. It is meant for the stack of a main thread, which is larger than
+        // the one of the test threads.
+        let result = std::thread::scope(|scope| {
+            let test = || nested.test(&Location::beginning(), &mut StringCharReader::new(&input));
+            std::thread::Builder::new().stack_size(8 * 1024 * 1024).spawn_scoped(scope, test).unwrap().join().unwrap()
+        });
+        let location = Location::new(1, DEFAULT_RECURSION_LIMIT + 1, DEFAULT_RECURSION_LIMIT);
+        assert_eq!(
+            result.map_err(|err| err.cause().clone()),
+            Err(SyntaxError::RecursionLimit { limit: DEFAULT_RECURSION_LIMIT, location }.into())
+        );
+    }
+
+    #[test]
+    fn test_grammar_fuel() {
+        // Without memoization, each failed `a` is tried again by the second alternative: exponential time
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let a = builder.rule("a");
+        let a = builder.define("a", choice!(seq!(word!("("), a, word!(")"), word!("!")), seq!(word!("("), a, word!(")")), word!("x")));
+        builder.fuel(1000);
+        let grammar = builder.save_root(a);
+
+        let mut reader = StringCharReader::new("((x))");
+        assert_eq!(grammar.test(&Location::beginning(), &mut reader).unwrap().unwrap().len(), 5);
+
+        let input = "(".repeat(30) + "x" + &")".repeat(30);
+        let mut reader = StringCharReader::new(&input);
+        assert!(matches!(
+            grammar.test(&Location::beginning(), &mut reader).map_err(|err| err.cause().clone()),
+            Err(ParserError::Syntax(SyntaxError::OutOfFuel { limit: 1000, .. }))
+        ));
+    }
+
+    #[test]
+    fn test_grammar_trace() {
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let digit = builder.define("digit", Rule::range('0', '9'));
+        let sum = builder.define("sum", seq!(digit.clone(), word!("+"), digit));
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        builder.trace(Tracer::new(move |event| sink.lock().unwrap().push(event.to_string())));
+        let grammar = builder.save_root(sum);
+
+        let mut reader = StringCharReader::new("1+x");
+        assert_eq!(grammar.test(&Location::beginning(), &mut reader).unwrap(), None);
+        assert_eq!(
+            events.lock().unwrap().join("\n"),
+            "> sum at 1:1\n  > digit at 1:1\n  < digit at 1:1: 1:1-1:2\n  > digit at 1:3\n  < digit at 1:3: no match\n< sum at 1:1: no match"
+        );
+
+        // Only the rules that didn't match
+        events.lock().unwrap().clear();
+        let options = grammar.options().clone().with_trace_level(TraceLevel::Failures);
+        let mut reader = StringCharReader::new("1+x");
+        assert_eq!(grammar.parse(&Location::beginning(), &mut reader, &options).unwrap(), None);
+        assert_eq!(events.lock().unwrap().join("\n"), "  < digit at 1:3: no match\n< sum at 1:1: no match");
+    }
+
+    #[test]
+    fn test_grammar_profile() {
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let digit = builder.define("digit", Rule::range('0', '9'));
+        let letter = builder.define("letter", Rule::range('a', 'z'));
+        let item = builder.define("item", choice!(digit, letter));
+        let grammar = builder.save_root(item.at_least(1));
+
+        let mut reader = StringCharReader::new("1ab2");
+        let (result, profile) = grammar.profile(&Location::beginning(), &mut reader);
+        assert_eq!(result.unwrap().unwrap().len(), 4);
+
+        let item = profile.rule("item").unwrap();
+        assert_eq!((item.calls, item.failures, item.backtracks), (5, 1, 3));
+        let digit = profile.rule("digit").unwrap();
+        assert_eq!((digit.calls, digit.failures), (5, 3));
+        let letter = profile.rule("letter").unwrap();
+        assert_eq!((letter.calls, letter.failures), (3, 1));
+        assert!(item.total_time >= item.self_time);
+        assert_eq!(profile.rules().len(), 3);
+    }
+
+    #[test]
+    fn test_grammar_parse_options() {
+        // Without memoization, each failed `a` is tried again by the second alternative: exponential time
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let a = builder.rule("a");
+        let a = builder.define("a", choice!(seq!(word!("("), a, word!(")"), word!("!")), seq!(word!("("), a, word!(")")), word!("x")));
+        let grammar = builder.save_root(a);
+
+        let input = "(".repeat(30) + "x" + &")".repeat(30);
+        let options = ParseOptions::new().with_fuel(1000);
+        let mut reader = StringCharReader::new(&input);
+        assert!(matches!(
+            grammar.parse(&Location::beginning(), &mut reader, &options).map_err(|err| err.cause().clone()),
+            Err(ParserError::Syntax(SyntaxError::OutOfFuel { limit: 1000, .. }))
+        ));
+
+        // With memoization, each position is matched once
+        let options = options.with_memoization();
+        let mut reader = StringCharReader::new(&input);
+        assert_eq!(grammar.parse(&Location::beginning(), &mut reader, &options).unwrap().unwrap().len(), 61);
+
+        // Left-recursive rules still grow
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let expr = builder.rule("expr");
+        let num = builder.define("num", Rule::range('0', '9'));
+        let expr = builder.define("expr", choice!(seq!(expr, word!("-"), num.clone()), num));
+        let grammar = builder.save_root(expr);
+        let mut reader = StringCharReader::new("5-3-1+2");
+        let options = ParseOptions::new().with_memoization();
+        assert_eq!(grammar.parse(&Location::beginning(), &mut reader, &options).unwrap().unwrap().len(), 5);
+
+        // The options of the grammar are not used
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let inner = builder.define("inner", word!("x"));
+        let outer = builder.define("outer", seq!(inner));
+        builder.recursion_limit(1);
+        let grammar = builder.save_root(outer);
+        assert_eq!(grammar.options().recursion_limit(), 1);
+        let mut reader = StringCharReader::new("x");
+        let err = grammar.test(&Location::beginning(), &mut reader).unwrap_err();
+        assert!(matches!(err.cause(), ParserError::Syntax(SyntaxError::RecursionLimit { .. })));
+        let mut reader = StringCharReader::new("x");
+        assert_eq!(grammar.parse(&Location::beginning(), &mut reader, &ParseOptions::new()).unwrap().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_grammar_parse_tree() {
+        // The nodes of an alternative that failed are not kept
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let name = builder.define("name", Rule::range('a', 'z'));
+        let num = builder.define("num", Rule::range('0', '9'));
+        let call = builder.define("call", seq!(name.clone(), word!("()")));
+        let assignment = builder.define("assignment", seq!(name, word!("="), num));
+        let grammar = builder.save_root(choice!(assignment, call));
+
+        let mut reader = StringCharReader::new("f()");
+        let tree = grammar.parse_tree(&Location::beginning(), &mut reader).unwrap().unwrap();
+        assert_eq!(tree.rule(), "root");
+        assert_eq!(tree.children().len(), 1);
+        assert_eq!(tree.children()[0].rule(), "call");
+        assert_eq!(tree.children()[0].children().len(), 1);
+        assert_eq!(tree.children()[0].children()[0].text("f()"), "f");
+
+        // Left-recursive rules are nested, with or without memoization
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let expr = builder.rule("expr");
+        let num = builder.define("num", Rule::range('0', '9'));
+        let expr = builder.define("expr", choice!(seq!(expr, word!("-"), num.clone()), num));
+        let grammar = builder.save_root(expr);
+        let outline = |node: &ParseNode| {
+            fn outline(node: &ParseNode, out: &mut String) {
+                out.push_str(node.rule());
+                if !node.children().is_empty() {
+                    out.push('(');
+                    for child in node.children() {
+                        outline(child, out);
+                        out.push(' ');
+                    }
+                    out.pop();
+                    out.push(')');
+                }
+            }
+            let mut out = String::new();
+            outline(node, &mut out);
+            out
+        };
+
+        let mut reader = StringCharReader::new("5-3-1");
+        let tree = grammar.parse_tree(&Location::beginning(), &mut reader).unwrap().unwrap();
+        assert_eq!(outline(&tree), "expr(expr(expr(num) num) num)");
+        assert_eq!(tree.span().end().index(), 5);
+
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let expr = builder.rule("expr");
+        let num = builder.define("num", Rule::range('0', '9'));
+        let expr = builder.define("expr", choice!(seq!(expr, word!("-"), num.clone()), num));
+        builder.options(ParseOptions::new().with_memoization());
+        let grammar = builder.save_root(expr);
+        let mut reader = StringCharReader::new("5-3-1");
+        let tree = grammar.parse_tree(&Location::beginning(), &mut reader).unwrap().unwrap();
+        assert_eq!(outline(&tree), "expr(expr(expr(num) num) num)");
+
+        // No match, no tree
+        let mut reader = StringCharReader::new("-");
+        assert_eq!(grammar.parse_tree(&Location::beginning(), &mut reader), Ok(None));
+    }
+
+    #[test]
+    fn test_grammar_parse_arena() {
+        // The memoized nodes are shared instead of copied
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let num = builder.define("num", Rule::range('0', '9'));
+        let sum = builder.define("sum", seq!(num.clone(), word!("+"), num.clone()));
+        builder.options(ParseOptions::new().with_memoization());
+        let grammar = builder.save_root(choice!(seq!(sum, word!(";")), num));
+
+        let source = "1+2";
+        let mut reader = StringCharReader::new(source);
+        let arena = grammar.parse_arena(&Location::beginning(), &mut reader).unwrap().unwrap();
+        let root = arena.root().unwrap();
+        assert_eq!(arena.rule(root), "root");
+        assert_eq!(arena.to_node(root).to_sexp(source), "(root (num \"1\"))");
+        assert_eq!(arena.len(), 4);
+
+        let mut reader = StringCharReader::new(source);
+        assert_eq!(grammar.parse_tree(&Location::beginning(), &mut reader).unwrap(), Some(arena.to_node(root)));
+
+        let mut reader = StringCharReader::new("+");
+        assert!(grammar.parse_arena(&Location::beginning(), &mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_grammar_parse_events() {
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let num = builder.define("num", Rule::range('0', '9'));
+        let sum = builder.define("sum", seq!(num.clone(), word!("+"), num));
+        let grammar = builder.save_root(sum);
+
+        // Events are enough to rebuild the tree
+        let source = "1+2";
+        let mut reader = StringCharReader::new(source);
+        let mut sexp = String::new();
+        let result = grammar.parse_events(&Location::beginning(), &mut reader, |event| match event {
+            ParseEvent::StartNode(rule) => sexp.push_str(&format!("({}", rule)),
+            ParseEvent::Token(span) => {
+                let text = &source[span.start().byte_offset()..span.end().byte_offset()];
+                sexp.push_str(&format!(" {:?}", text))
+            }
+            ParseEvent::FinishNode => sexp.push(')'),
+            ParseEvent::Error(_) => unreachable!(),
+        });
+        assert_eq!(result.unwrap().unwrap().len(), 3);
+        assert_eq!(sexp, "(sum(num \"1\") \"+\"(num \"2\"))");
+
+        // No match, no event
+        let mut reader = StringCharReader::new("1-2");
+        let mut count = 0;
+        assert_eq!(grammar.parse_events(&Location::beginning(), &mut reader, |_| count += 1), Ok(None));
+        assert_eq!(count, 0);
+
+        // The error is sent too
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let missing = builder.rule("missing");
+        let grammar = builder.save_root(missing);
+        let mut reader = StringCharReader::new("1");
+        let mut events = Vec::new();
+        let result = grammar.parse_events(&Location::beginning(), &mut reader, |event| events.push(format!("{:?}", event)));
+        assert!(result.is_err());
+        assert_eq!(events.len(), 1);
+        assert!(events[0].starts_with("Error("));
+    }
 }
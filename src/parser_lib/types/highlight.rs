@@ -0,0 +1,274 @@
+use std::{fmt::Write, ops::Range};
+
+use super::{Grammar, ParserError, SyntaxKind, SyntaxNode};
+use crate::parser_lib::StringCharReader;
+
+/// Semantic category of a piece of source, used to color it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HighlightCategory {
+    Keyword,
+    String,
+    Number,
+    Comment,
+    Identifier,
+    Operator,
+    /// Punctuation, whitespace, and the text of the rules without a category.
+    Plain,
+}
+
+impl HighlightCategory {
+    /// Returns the category of the text matched by a named rule, guessed from the words of its name, if any.
+    ///
+    /// For example, `string`, `float`, `int_literal`, `identifier`, `type_name` and `sum_op` have a category, while
+    /// `expr` or `block` don't: their text is highlighted by their own rules.
+    pub fn of_rule(name: &str) -> Option<Self> {
+        name.to_lowercase().split(['_', '-', ' ']).rev().find_map(|word| match word {
+            "string" | "str" | "char" | "text" => Some(Self::String),
+            "number" | "integer" | "int" | "float" | "digit" | "digits" | "decimal" => Some(Self::Number),
+            "comment" => Some(Self::Comment),
+            "identifier" | "ident" | "name" | "type" | "path" => Some(Self::Identifier),
+            "keyword" | "boolean" | "bool" => Some(Self::Keyword),
+            "op" | "operator" => Some(Self::Operator),
+            _ => None,
+        })
+    }
+
+    /// Name of the category, used as the class of the HTML spans.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Keyword => "keyword",
+            Self::String => "string",
+            Self::Number => "number",
+            Self::Comment => "comment",
+            Self::Identifier => "identifier",
+            Self::Operator => "operator",
+            Self::Plain => "plain",
+        }
+    }
+
+    /// ANSI escape code setting the color of the category in a terminal, or None for plain text.
+    fn ansi(&self) -> Option<&'static str> {
+        match self {
+            Self::Keyword => Some("\x1b[1;35m"),
+            Self::String => Some("\x1b[32m"),
+            Self::Number => Some("\x1b[36m"),
+            Self::Comment => Some("\x1b[2;37m"),
+            Self::Identifier => Some("\x1b[34m"),
+            Self::Operator => Some("\x1b[33m"),
+            Self::Plain => None,
+        }
+    }
+}
+
+/// Source split in consecutive ranges of bytes, each with its category.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Highlights {
+    source: String,
+    /// Ranges covering the whole source, in order. Two consecutive ranges have different categories.
+    spans: Vec<(Range<usize>, HighlightCategory)>,
+}
+
+impl Highlights {
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn spans(&self) -> &[(Range<usize>, HighlightCategory)] {
+        &self.spans
+    }
+
+    /// Returns the category of the byte at the given offset, if it is in the source.
+    pub fn category_at(&self, offset: usize) -> Option<HighlightCategory> {
+        self.spans.iter().find(|(range, _)| range.contains(&offset)).map(|(_, category)| *category)
+    }
+
+    /// Writes the source with ANSI escape codes, to color it in a terminal.
+    pub fn to_ansi(&self) -> String {
+        let mut output = String::new();
+        for (range, category) in &self.spans {
+            match category.ansi() {
+                Some(code) => write!(output, "{}{}\x1b[0m", code, &self.source[range.clone()]).unwrap(),
+                None => output.push_str(&self.source[range.clone()]),
+            }
+        }
+        output
+    }
+
+    /// Writes the source as an HTML `pre` element, where the text of each category is in a span with its name as class.
+    pub fn to_html(&self) -> String {
+        let mut html = "<pre class=\"highlight\">".to_string();
+        for (range, category) in &self.spans {
+            let text = escape(&self.source[range.clone()]);
+            match category {
+                HighlightCategory::Plain => html.push_str(&text),
+                _ => write!(html, "<span class=\"{}\">{}</span>", category.name(), text).unwrap(),
+            }
+        }
+        html.push_str("</pre>\n");
+        html
+    }
+
+    fn push(&mut self, range: Range<usize>, category: HighlightCategory) {
+        if range.is_empty() {
+            return;
+        }
+        match self.spans.last_mut() {
+            Some((last, last_category)) if *last_category == category && last.end == range.start => {
+                last.end = range.end
+            }
+            _ => self.spans.push((range, category)),
+        }
+    }
+}
+
+impl Grammar<StringCharReader> {
+    /// Splits the source in categories to highlight it, from its lossless syntax tree.
+    ///
+    /// Nothing else than the grammar is needed: the trivia are comments unless they are whitespace, the words of the
+    /// tokens that are reserved are keywords, and the other categories come from the names of the rules
+    /// (see `HighlightCategory::of_rule`), which apply to the text they contain unless an inner rule has its own.
+    /// If the grammar doesn't match the source, it is plain text.
+    pub fn highlight(&self, source: &str) -> Result<Highlights, ParserError> {
+        let mut highlights = Highlights { source: source.to_string(), spans: Vec::new() };
+        match self.parse_lossless(source)? {
+            Some(root) => self.highlight_node(&root, None, &mut highlights),
+            None => highlights.push(0..source.len(), HighlightCategory::Plain),
+        }
+        Ok(highlights)
+    }
+
+    fn highlight_node(&self, node: &SyntaxNode, category: Option<HighlightCategory>, highlights: &mut Highlights) {
+        let range = node.range();
+        match node.kind() {
+            SyntaxKind::Rule(rule) => {
+                let category = HighlightCategory::of_rule(rule).or(category);
+                for child in node.children() {
+                    self.highlight_node(&child, category, highlights);
+                }
+            }
+            SyntaxKind::Trivia if node.text().trim().is_empty() => highlights.push(range, HighlightCategory::Plain),
+            SyntaxKind::Trivia => highlights.push(range, HighlightCategory::Comment),
+            SyntaxKind::Token => match category {
+                Some(category) => highlights.push(range, category),
+                None => self.highlight_keywords(&node.text(), range.start, highlights),
+            },
+        }
+    }
+
+    /// Highlights the reserved words of a token, which can be glued to punctuation like in `return(`.
+    fn highlight_keywords(&self, text: &str, offset: usize, highlights: &mut Highlights) {
+        let mut start = 0;
+        for (i, c) in text.char_indices() {
+            if !c.is_alphanumeric() && c != '_' {
+                self.highlight_word(&text[start..i], offset + start, highlights);
+                highlights.push(offset + i..offset + i + c.len_utf8(), HighlightCategory::Plain);
+                start = i + c.len_utf8();
+            }
+        }
+        self.highlight_word(&text[start..], offset + start, highlights);
+    }
+
+    fn highlight_word(&self, word: &str, offset: usize, highlights: &mut Highlights) {
+        let category = match self.reserved_words().iter().any(|reserved| reserved == word) {
+            true => HighlightCategory::Keyword,
+            false => HighlightCategory::Plain,
+        };
+        highlights.push(offset..offset + word.len(), category);
+    }
+}
+
+/// Escapes the special characters of HTML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        choice,
+        parser_lib::{GrammarBuilder, StringCharReader},
+        range, seq, until, word,
+    };
+
+    /// Statements like `let x = 1 + "a"; // comment`, with the grammar of a tiny language.
+    fn grammar() -> Grammar<StringCharReader> {
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let comment = seq!(word!("//"), until!(word!("\n"), 0), word!("\n"));
+        let ignore = choice![comment, word!(" "), word!("\n")];
+        builder.ignore(ignore.clone());
+        let ws = ignore.at_least(0);
+
+        let let_kw = builder.reserved("let");
+        let identifier = builder.define("identifier", range!('a', 'z').at_least(1));
+        let number = builder.define("number", range!('0', '9').at_least(1));
+        let string = builder.define("string", seq!(word!("\""), until!(word!("\""), 0), word!("\"")));
+        let value = choice![number, string, identifier.clone()];
+        let sum_op = builder.define("sum_op", word!("+"));
+        let expr = builder.define("expr", seq!(value.clone(), seq!(ws.clone(), sum_op, ws.clone(), value).at_least(0)));
+        let statement = builder.define(
+            "statement",
+            seq!(let_kw, ws.clone(), identifier, ws.clone(), word!("="), ws.clone(), expr, word!(";")),
+        );
+        let program = builder.define("program", seq!(ws.clone(), seq!(statement, ws).at_least(0)));
+        builder.save_root(program)
+    }
+
+    #[test]
+    fn test_rule_categories() {
+        assert_eq!(HighlightCategory::of_rule("string"), Some(HighlightCategory::String));
+        assert_eq!(HighlightCategory::of_rule("int_literal"), Some(HighlightCategory::Number));
+        assert_eq!(HighlightCategory::of_rule("TypeName"), None);
+        assert_eq!(HighlightCategory::of_rule("type_name"), Some(HighlightCategory::Identifier));
+        assert_eq!(HighlightCategory::of_rule("comparison_op"), Some(HighlightCategory::Operator));
+        assert_eq!(HighlightCategory::of_rule("expr"), None);
+    }
+
+    #[test]
+    fn test_highlight() {
+        let source = "let ab = 1 + \"<a>\"; // set\n";
+        let highlights = grammar().highlight(source).unwrap();
+        let spans: Vec<(&str, HighlightCategory)> = highlights
+            .spans()
+            .iter()
+            .map(|(range, category)| (&source[range.clone()], *category))
+            .collect();
+        use HighlightCategory::*;
+        assert_eq!(
+            spans,
+            [
+                ("let", Keyword),
+                (" ", Plain),
+                ("ab", Identifier),
+                (" = ", Plain),
+                ("1", Number),
+                (" ", Plain),
+                ("+", Operator),
+                (" ", Plain),
+                ("\"<a>\"", String),
+                ("; ", Plain),
+                ("// set\n", Comment),
+            ]
+        );
+        assert_eq!(highlights.category_at(1), Some(Keyword));
+        assert_eq!(highlights.category_at(source.len()), None);
+
+        assert_eq!(
+            highlights.to_html(),
+            "<pre class=\"highlight\"><span class=\"keyword\">let</span> <span class=\"identifier\">ab</span> = \
+             <span class=\"number\">1</span> <span class=\"operator\">+</span> \
+             <span class=\"string\">&quot;&lt;a&gt;&quot;</span>; <span class=\"comment\">// set\n</span></pre>\n"
+        );
+        assert!(highlights.to_ansi().starts_with("\x1b[1;35mlet\x1b[0m \x1b[34mab\x1b[0m = "));
+    }
+
+    #[test]
+    fn test_highlight_unmatched() {
+        // The text after the match is highlighted from the tokens
+        let highlights = grammar().highlight("let a = 1; let(\n").unwrap();
+        let categories: Vec<HighlightCategory> = highlights.spans().iter().map(|(_, category)| *category).collect();
+        assert_eq!(categories.last(), Some(&HighlightCategory::Plain));
+        assert_eq!(highlights.category_at(12), Some(HighlightCategory::Keyword));
+        assert_eq!(highlights.spans().last().unwrap().0.end, 16);
+    }
+}
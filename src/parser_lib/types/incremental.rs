@@ -0,0 +1,344 @@
+use std::{collections::HashMap, ops::Range};
+
+use super::{Location, LocationDelta, LocationPolicy, ParseNode};
+
+/// Change of a source: the text replacing a range of bytes of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    /// Replaced bytes of the source. It is empty for an insertion.
+    pub range: Range<usize>,
+    /// New text, which is empty for a deletion.
+    pub text: String,
+}
+
+impl TextEdit {
+    pub fn new(range: Range<usize>, text: &str) -> Self {
+        Self {
+            range,
+            text: text.to_string(),
+        }
+    }
+
+    /// Returns the source with the edit applied.
+    ///
+    /// Panics if the range is not in the source, or not on char boundaries.
+    pub fn apply(&self, source: &str) -> String {
+        let mut edited = source.to_string();
+        edited.replace_range(self.range.clone(), &self.text);
+        edited
+    }
+}
+
+/// Rule, end index and end of the input read (exclusive index) of the nodes that can be reused, by start index.
+type ReadEnds = HashMap<usize, Vec<(String, usize, usize)>>;
+
+/// Tree of the named rules of a source, that can be updated after an edit by only matching the rules the edit can change.
+///
+/// A node is reused if it is after the edit, or if its rule didn't read the input up to the edit.
+/// The nodes whose match depends on a left-recursive rule being grown are always matched again.
+#[derive(Debug, Clone)]
+pub struct IncrementalTree {
+    source: String,
+    tree: Option<ParseNode>,
+    read_ends: ReadEnds,
+    /// Number of times a node of the previous tree was reused.
+    reused: usize,
+}
+
+impl IncrementalTree {
+    pub(crate) fn new(source: String, tree: Option<ParseNode>, incremental: Incremental) -> Self {
+        Self {
+            source,
+            tree,
+            read_ends: incremental.read_ends,
+            reused: incremental.reused,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns the tree of the source, or None if the grammar didn't match.
+    pub fn tree(&self) -> Option<&ParseNode> {
+        self.tree.as_ref()
+    }
+
+    /// Returns the number of times a node of the previous tree was reused with its children, instead of matching its rule.
+    pub fn reused_nodes(&self) -> usize {
+        self.reused
+    }
+}
+
+/// State of an incremental parse: the nodes of the previous tree it can reuse, and the input read by the rules it matched.
+#[derive(Debug, Default)]
+pub(crate) struct Incremental {
+    previous: Option<Previous>,
+    read_ends: ReadEnds,
+    /// Number of times a left-recursive seed was used. The rules matched while it changes can't be reused.
+    seeds_used: usize,
+    reused: usize,
+}
+
+/// Previous tree of an incremental parse, and the edit made since.
+#[derive(Debug)]
+struct Previous {
+    tree: ParseNode,
+    read_ends: ReadEnds,
+    /// Index of the start of the edit.
+    start: usize,
+    /// End of the edit, before and after it.
+    old_end: Location,
+    new_end: Location,
+}
+
+impl Incremental {
+    /// Prepares the parse of the edited source of the tree, which is matched with the given location policy.
+    pub fn reparse(previous: &IncrementalTree, edit: &TextEdit, policy: LocationPolicy) -> Self {
+        let previous = previous.tree.as_ref().map(|tree| {
+            let start = previous.source[..edit.range.start].chars().count();
+            let old_end = location_of(&previous.source[..edit.range.end], policy);
+            let new_end = location_of(&(previous.source[..edit.range.start].to_string() + &edit.text), policy);
+            Previous {
+                tree: tree.clone(),
+                read_ends: previous.read_ends.clone(),
+                start,
+                old_end,
+                new_end,
+            }
+        });
+        Self {
+            previous,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the node of the previous tree matched by the rule at the location, if the edit didn't change it,
+    /// with the end of the input its rule read.
+    pub fn reuse(&mut self, rule: &str, loc: &Location) -> Option<(ParseNode, usize)> {
+        let previous = self.previous.as_ref()?;
+        let index = loc.index();
+        let shifted = index >= previous.new_end.index();
+        let old_index = match index {
+            _ if index < previous.start => index,
+            _ if shifted => index - previous.new_end.index() + previous.old_end.index(),
+            _ => return None,
+        };
+
+        let (_, end, read_end) = previous.read_ends.get(&old_index)?.iter().find(|(name, _, _)| name == rule)?;
+        if !shifted && *read_end > previous.start {
+            return None;
+        }
+
+        let mut node = find(&previous.tree, rule, old_index, *end)?.clone();
+        if shifted {
+            node.map_locations(&|loc| previous.shift(loc));
+        }
+
+        // The children can be reused by the next parse too
+        let mut read_ends = Vec::new();
+        previous.read_ends_of(&node, shifted, &mut read_ends);
+        for (start, rule, end, read_end) in read_ends {
+            insert(&mut self.read_ends, start, rule, end, read_end);
+        }
+
+        self.reused += 1;
+        let read_end = if shifted { previous.shift_index(*read_end) } else { *read_end };
+        Some((node, read_end))
+    }
+
+    /// Records the end of the input read by a rule that matched, unless it depends on a seed used since `seeds_used`.
+    pub fn matched(&mut self, rule: &str, start: usize, end: usize, read_end: usize, seeds_used: usize) {
+        if self.seeds_used == seeds_used {
+            insert(&mut self.read_ends, start, rule.to_string(), end, read_end);
+        }
+    }
+
+    pub fn seed_used(&mut self) {
+        self.seeds_used += 1;
+    }
+
+    pub fn seeds_used(&self) -> usize {
+        self.seeds_used
+    }
+}
+
+impl Previous {
+    /// Returns the location in the edited source of a location after the edit.
+    fn shift(&self, loc: &Location) -> Location {
+        let (old, new) = (&self.old_end, &self.new_end);
+        let column = if loc.line() == old.line() {
+            loc.column() - old.column() + new.column()
+        } else {
+            loc.column()
+        };
+        Location::with_byte_offset(
+            loc.line() - old.line() + new.line(),
+            column,
+            self.shift_index(loc.index()),
+            loc.byte_offset() - old.byte_offset() + new.byte_offset(),
+        )
+        .with_source(loc.source())
+    }
+
+    fn shift_index(&self, index: usize) -> usize {
+        index - self.old_end.index() + self.new_end.index()
+    }
+
+    /// Collects the read ends of the node and its descendants (already shifted), in the edited source.
+    fn read_ends_of(&self, node: &ParseNode, shifted: bool, read_ends: &mut Vec<(usize, String, usize, usize)>) {
+        let (start, end) = (node.span().start().index(), node.span().end().index());
+        let old = |index| if shifted { index - self.new_end.index() + self.old_end.index() } else { index };
+        let found = self.read_ends.get(&old(start)).and_then(|nodes| {
+            nodes.iter().find(|(rule, node_end, _)| rule == node.rule() && *node_end == old(end))
+        });
+        if let Some((rule, _, read_end)) = found {
+            let read_end = if shifted { self.shift_index(*read_end) } else { *read_end };
+            read_ends.push((start, rule.clone(), end, read_end));
+        }
+        for child in node.children() {
+            self.read_ends_of(child, shifted, read_ends);
+        }
+    }
+}
+
+/// Records the end of the input read by a node, unless a backtracked match of the same rule already did.
+fn insert(read_ends: &mut ReadEnds, start: usize, rule: String, end: usize, read_end: usize) {
+    let nodes = read_ends.entry(start).or_default();
+    if !nodes.iter().any(|(name, _, _)| *name == rule) {
+        nodes.push((rule, end, read_end));
+    }
+}
+
+/// Returns the node of the rule with the given start and end indexes in the tree.
+fn find<'a>(node: &'a ParseNode, rule: &str, start: usize, end: usize) -> Option<&'a ParseNode> {
+    let span = node.span();
+    if node.rule() == rule && span.start().index() == start && span.end().index() == end {
+        return Some(node);
+    }
+    node.children()
+        .iter()
+        .filter(|child| child.span().start().index() <= start && end <= child.span().end().index())
+        .find_map(|child| find(child, rule, start, end))
+}
+
+/// Returns the location at the end of the text, which starts at the beginning of the source.
+fn location_of(text: &str, policy: LocationPolicy) -> Location {
+    let mut delta = LocationDelta::with_policy(policy);
+    for c in text.chars() {
+        delta.push(c);
+    }
+    delta.apply_to(&policy.beginning())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        choice,
+        parser_lib::{Grammar, GrammarBuilder, ParseOptions, Rule, StringCharReader},
+        seq, word,
+    };
+
+    use super::*;
+
+    /// Statements like `a = 1.5;`, on several lines.
+    fn grammar() -> Grammar<StringCharReader> {
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let space = choice!(word!(" "), word!("\n")).at_least(0);
+        let digits = Rule::range('0', '9').at_least(1);
+        let number = builder.define("number", seq!(digits.clone(), seq!(word!("."), digits).optional()));
+        let name = builder.define("name", Rule::range('a', 'z').at_least(1));
+        let statement = builder.define("statement", seq!(name, word!("="), number, word!(";"), space));
+        let statements = builder.define("statements", statement.at_least(1));
+        builder.save_root(statements)
+    }
+
+    /// Reparses the tree after the edit, and checks that it gives the same tree as a full parse.
+    fn reparse(grammar: &Grammar<StringCharReader>, tree: &IncrementalTree, edit: TextEdit) -> IncrementalTree {
+        let reparsed = grammar.reparse(tree, &edit).unwrap();
+        let mut reader = StringCharReader::new(reparsed.source());
+        let expected = grammar.parse_tree(&Location::beginning(), &mut reader).unwrap();
+        assert_eq!(reparsed.tree(), expected.as_ref(), "{:?}", reparsed.source());
+        reparsed
+    }
+
+    #[test]
+    fn test_reparse() {
+        let grammar = grammar();
+        let tree = grammar.parse_incremental("a=1;\nbc=2;\nd=3;\n").unwrap();
+        assert_eq!(tree.reused_nodes(), 0);
+
+        // The first statement is before the edit, the last one after it (on another line)
+        let tree = reparse(&grammar, &tree, TextEdit::new(8..9, "25"));
+        assert_eq!(tree.source(), "a=1;\nbc=25;\nd=3;\n");
+        assert_eq!(tree.reused_nodes(), 3);
+
+        // The first statement read the name after it, so only its children are reused.
+        // On the line of the edit, the columns are shifted
+        let tree = reparse(&grammar, &tree, TextEdit::new(5..7, "x"));
+        let number = &tree.tree().unwrap().children()[1].children()[1];
+        assert_eq!(number.span().start(), &Location::new(2, 3, 7));
+        assert_eq!(tree.reused_nodes(), 4);
+
+        // The nodes reused by the last parse can be reused again
+        let tree = reparse(&grammar, &tree, TextEdit::new(0..0, "z=0;"));
+        assert_eq!(tree.reused_nodes(), 3);
+
+        // Nothing to reuse after a failure
+        let tree = reparse(&grammar, &tree, TextEdit::new(0..1, "?"));
+        assert_eq!(tree.tree(), None);
+        let tree = reparse(&grammar, &tree, TextEdit::new(0..1, "z"));
+        assert_eq!(tree.reused_nodes(), 0);
+    }
+
+    #[test]
+    fn test_reparse_look_ahead() {
+        let grammar = grammar();
+        let tree = grammar.parse_incremental("a=12;").unwrap();
+
+        // The number read the char after it to look for a dot, so only the name is reused
+        let tree = reparse(&grammar, &tree, TextEdit::new(4..4, ".5"));
+        assert_eq!(tree.reused_nodes(), 1);
+        assert_eq!(tree.tree().unwrap().children()[0].children()[1].text(tree.source()), "12.5");
+    }
+
+    #[test]
+    fn test_text_edit() {
+        assert_eq!(TextEdit::new(2..3, "é!").apply("abcd"), "abé!d");
+        assert_eq!(TextEdit::new(4..4, "e").apply("abcd"), "abcde");
+        assert_eq!(TextEdit::new(0..2, "").apply("abcd"), "cd");
+    }
+
+    #[test]
+    fn test_reparse_left_recursion() {
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let expr = builder.rule("expr");
+        let number = builder.define("number", Rule::range('0', '9'));
+        let expr = builder.define("expr", choice!(seq!(expr, word!("-"), number.clone()), number));
+        let grammar = builder.save_root(expr);
+
+        // The numbers are reused (the first one twice, since the last iteration of the growth backtracks to it),
+        // but not the expressions grown from a seed
+        let tree = grammar.parse_incremental("1-2-3").unwrap();
+        let tree = reparse(&grammar, &tree, TextEdit::new(2..3, "5"));
+        assert_eq!(tree.reused_nodes(), 3);
+    }
+
+    #[test]
+    fn test_reparse_memoized() {
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        builder.options(ParseOptions::new().with_memoization());
+        let name = builder.define("name", Rule::range('a', 'z').at_least(1));
+        let call = builder.define("call", seq!(name.clone(), word!("()")));
+        // The name is matched twice at the same position when the value is not a call: the second time is cached
+        let value = choice!(call, name);
+        let statement = builder.define("statement", seq!(value, word!(";")));
+        let grammar = builder.save_root(statement.at_least(1));
+
+        let tree = grammar.parse_incremental("ab;cd();").unwrap();
+        let tree = reparse(&grammar, &tree, TextEdit::new(2..2, "()"));
+        assert_eq!(tree.source(), "ab();cd();");
+        let tree = reparse(&grammar, &tree, TextEdit::new(1..2, "bc"));
+        assert_eq!(tree.reused_nodes(), 1);
+    }
+}
@@ -0,0 +1,73 @@
+use std::{collections::HashMap, sync::Arc};
+
+/// Identifier of a string stored in an `Interner`, which is compared and hashed in constant time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Symbol(u32);
+
+/// Stores each distinct string once, and gives the same `Symbol` for the same string.
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    symbols: HashMap<Arc<str>, Symbol>,
+    strings: Vec<Arc<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the symbol of the string, storing it if it is new.
+    pub fn intern(&mut self, string: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(string) {
+            return *symbol;
+        }
+
+        let symbol = Symbol(self.strings.len() as u32);
+        let string: Arc<str> = Arc::from(string);
+        self.strings.push(string.clone());
+        self.symbols.insert(string, symbol);
+        symbol
+    }
+
+    /// Returns the symbol of the string if it is stored, without storing it.
+    pub fn get(&self, string: &str) -> Option<Symbol> {
+        self.symbols.get(string).copied()
+    }
+
+    /// Returns the string of a symbol, or None if it comes from another interner.
+    pub fn resolve(&self, symbol: Symbol) -> Option<&str> {
+        self.strings.get(symbol.0 as usize).map(|string| string.as_ref())
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interner() {
+        let mut interner = Interner::new();
+        let let_symbol = interner.intern("let");
+        let fn_symbol = interner.intern("fn");
+
+        assert_eq!(interner.intern("let"), let_symbol);
+        assert_ne!(let_symbol, fn_symbol);
+        assert_eq!(interner.len(), 2);
+        assert_eq!(interner.resolve(fn_symbol), Some("fn"));
+        assert_eq!(interner.get("fn"), Some(fn_symbol));
+
+        // Looking up a string doesn't store it
+        assert_eq!(interner.get("x"), None);
+        assert_eq!(interner.len(), 2);
+        assert_eq!(Interner::new().resolve(let_symbol), None);
+    }
+}
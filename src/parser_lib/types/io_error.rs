@@ -0,0 +1,77 @@
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+    io,
+    sync::Arc,
+};
+
+/// Error while reading the input.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IoError {
+    /// The input could not be read. The error is shared so that the parser error stays cheap to clone.
+    ///
+    /// It is serialized as its message, and deserialized as an error of kind `Other`.
+    #[cfg_attr(feature = "serde", serde(with = "io_error_message"))]
+    Read(Arc<io::Error>),
+    /// The input contains bytes that are not valid UTF-8, starting at the given byte offset
+    InvalidUtf8 { byte_offset: usize },
+    /// The input has no bytes for now after the given byte offset (it returned `WouldBlock`), but more may arrive later.
+    NeedMoreInput { byte_offset: usize },
+}
+
+#[cfg(feature = "serde")]
+mod io_error_message {
+    use std::{io, sync::Arc};
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(err: &Arc<io::Error>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(err)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Arc<io::Error>, D::Error> {
+        let message = String::deserialize(deserializer)?;
+        Ok(Arc::new(io::Error::other(message)))
+    }
+}
+
+impl PartialEq for IoError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::InvalidUtf8 { byte_offset: a }, Self::InvalidUtf8 { byte_offset: b }) => a == b,
+            (Self::NeedMoreInput { byte_offset: a }, Self::NeedMoreInput { byte_offset: b }) => a == b,
+            // I/O errors can't be compared, their kind is the closest
+            (Self::Read(a), Self::Read(b)) => a.kind() == b.kind(),
+            _ => false,
+        }
+    }
+}
+
+impl From<io::Error> for IoError {
+    fn from(err: io::Error) -> Self {
+        Self::Read(Arc::new(err))
+    }
+}
+
+impl Display for IoError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            IoError::Read(err)
+                => write!(f, "Could not read the input: {}", err),
+            IoError::InvalidUtf8 { byte_offset }
+                => write!(f, "Invalid UTF-8 sequence at byte {}.", byte_offset),
+            IoError::NeedMoreInput { byte_offset }
+                => write!(f, "The input has no more bytes available after byte {} for now.", byte_offset),
+        }
+    }
+}
+
+impl Error for IoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            IoError::Read(err) => Some(err.as_ref()),
+            IoError::InvalidUtf8 { .. } | IoError::NeedMoreInput { .. } => None,
+        }
+    }
+}
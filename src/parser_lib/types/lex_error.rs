@@ -0,0 +1,43 @@
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+};
+
+use crate::utils::RingBufferError;
+
+/// Error of the readers while giving the chars of the input to the matchers.
+///
+/// They are about the buffer of the readers, not about the input: a bigger buffer or another reader avoids them.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LexError {
+    /// Tried to peek a char which is before the cursor and thus not accessible anymore
+    NoLookBehind(usize),
+    /// Tried to peek a char which is too far away from the cursor and wouldn't fit in the buffer
+    LookAheadBufferOverflow(usize),
+    /// Tried to push a value in a buffer that is full
+    BufferFull,
+}
+
+impl<T> From<RingBufferError<T>> for LexError {
+    fn from(err: RingBufferError<T>) -> Self {
+        match err {
+            RingBufferError::NotEnoughSpace(_) => Self::BufferFull,
+        }
+    }
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            LexError::NoLookBehind(index)
+                => write!(f, "Invalid search index: {}. Unable to look behind cursor.", index),
+            LexError::LookAheadBufferOverflow(index)
+                => write!(f, "Could not look ahead char at relative index {}: char read buffer capacity is too small.", index),
+            LexError::BufferFull
+                => write!(f, "Could not push a value in the read buffer: it is full."),
+        }
+    }
+}
+
+impl Error for LexError {}
@@ -1,28 +1,54 @@
 use std::{fmt::Display, ops::Add};
 
+use super::{LocationDelta, LocationPolicy, SourceId};
+
 /// Location of a point in a source file.
 ///
 /// The location is defined by the line and column number.
 ///
 /// Both numbers are 1-based, so the start of the file is (1, 1).
 ///
-/// - Adding a ``usize`` to a ``Location`` increments the column number.
+/// It also stores two 0-based offsets from the start of the input:
+/// - the index, counted in chars
+/// - the byte offset, counted in bytes of the UTF-8 encoded input
+///
+/// When several inputs are parsed together, it also stores the id of the input it belongs to.
+///
+/// - Adding a ``usize`` to a ``Location`` increments the column number, the index and the byte offset by the same
+///   amount. It is only right for ASCII chars without new line: use a ``LocationDelta`` for any other text.
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Location {
     line: usize,
     column: usize,
     index: usize,
+    byte_offset: usize,
+    source: SourceId,
 }
 
 impl Location {
+    /// Creates a location in an ASCII input, where the byte offset is the same as the char index.
     pub fn new(line: usize, column: usize, index: usize) -> Self {
+        Self::with_byte_offset(line, column, index, index)
+    }
+
+    /// Creates a location with a byte offset that differs from the char index.
+    pub fn with_byte_offset(line: usize, column: usize, index: usize, byte_offset: usize) -> Self {
         Self {
             line,
             column,
             index,
+            byte_offset,
+            source: SourceId::default(),
         }
     }
 
+    /// Returns the same location, in the given input.
+    pub fn with_source(mut self, source: SourceId) -> Self {
+        self.source = source;
+        self
+    }
+
     /// Returns a position which is the beginning of a file
     #[allow(unused)]
     pub fn beginning() -> Self {
@@ -39,50 +65,102 @@ impl Location {
         self.column
     }
 
+    /// Index of the char in the input, counted in chars.
     pub fn index(&self) -> usize {
         self.index
     }
 
+    /// Offset of the char in the UTF-8 encoded input, counted in bytes.
     #[allow(unused)]
-    pub fn add_line(&self) -> Self {
-        Self {
-            line: self.line + 1,
-            column: 1, // Columns are still 1-based
-            index: self.index + 1,
-        }
+    pub fn byte_offset(&self) -> usize {
+        self.byte_offset
+    }
+
+    /// Id of the input the location belongs to.
+    pub fn source(&self) -> SourceId {
+        self.source
+    }
+
+    /// Returns the location after a new line, at the first column of the policy.
+    #[allow(unused)]
+    pub fn add_line(&self, policy: LocationPolicy) -> Self {
+        self.add_delta(1, 0, 1, 1, policy)
     }
 
-    /// Increments the location according to the given char.
+    /// Increments the location according to the given char, counting the columns like the policy.
     ///
     /// The increment is done **in place**.
     #[allow(unused)]
-    pub fn increment_for(&mut self, c: char) {
-        match c {
-            '\n' => {
-                self.line += 1;
-                self.index += 1;
-                self.column = 1;
-            }
-            _ => {
-                self.column += 1;
-                self.index += 1;
-            }
-        }
+    pub fn increment_for(&mut self, c: char, policy: LocationPolicy) {
+        let mut delta = LocationDelta::with_policy(policy);
+        delta.push(c);
+        *self = delta.apply_to(self);
     }
 
-    pub fn add_delta(&self, delta_lines: usize, delta_columns: usize, delta_index: usize) -> Self {
+    /// Moves the location by the given numbers of lines, columns, chars and bytes.
+    ///
+    /// The policy is the one the location was computed with: after a new line, the column restarts at its base.
+    pub fn add_delta(
+        &self,
+        delta_lines: usize,
+        delta_columns: usize,
+        delta_index: usize,
+        delta_bytes: usize,
+        policy: LocationPolicy,
+    ) -> Self {
         let index = self.index + delta_index;
+        let byte_offset = self.byte_offset + delta_bytes;
         let line = self.line + delta_lines;
 
-        // If there is a new line, the column is reset to 1
-        let column = if delta_lines > 0 { 1 } else { self.column } + delta_columns;
+        // If there is a new line, the column is reset to the first one
+        let column = if delta_lines > 0 { policy.base() } else { self.column } + delta_columns;
 
-        Self::new(line, column, index)
+        Self::with_byte_offset(line, column, index, byte_offset).with_source(self.source)
     }
 }
 
+/// Position as used by editors and LSP clients.
+///
+/// Both numbers are 0-based, and the column is counted in UTF-16 code units.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Utf16Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+impl Location {
+    /// Converts the location to a position in UTF-16 code units.
+    ///
+    /// The source is the input the location was computed on. It is needed because the
+    /// width of the chars before the location, on the same line, is not stored.
+    /// The policy is the one the location was computed with, to know the number of the first line.
+    ///
+    /// Returns None if the location is not in the source, like a location computed before an edit of the source.
+    pub fn to_utf16_position(&self, source: &str, policy: LocationPolicy) -> Option<Utf16Position> {
+        let before = source.get(..self.byte_offset)?;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+        Some(Utf16Position {
+            line: self.line.checked_sub(policy.base())?,
+            character: utf16_len(&before[line_start..]),
+        })
+    }
+
+    /// Returns the offset of the location from the start of the source, in UTF-16 code units.
+    ///
+    /// Returns None if the location is not in the source.
+    pub fn to_utf16_offset(&self, source: &str) -> Option<usize> {
+        source.get(..self.byte_offset).map(utf16_len)
+    }
+}
+
+fn utf16_len(s: &str) -> usize {
+    s.chars().map(char::len_utf16).sum()
+}
+
 // Operator overloading for convenience
 // Add a usize to a location: we don't have any new line, so add just columns
+// The chars are assumed to be ASCII, so one byte per char. The matchers use a `LocationDelta` instead.
 impl Add<usize> for Location {
     type Output = Self;
 
@@ -91,6 +169,8 @@ impl Add<usize> for Location {
             line: self.line,
             column: self.column + nb,
             index: self.index + nb,
+            byte_offset: self.byte_offset + nb,
+            source: self.source,
         }
     }
 }
@@ -117,19 +197,107 @@ mod tests {
         assert_eq!(loc2.column(), 5);
         assert_eq!(loc2.index(), 4);
 
-        let loc4 = loc.add_line();
+        let policy = LocationPolicy::default();
+        let loc4 = loc.add_line(policy);
         assert_eq!(loc4.line(), 2);
         assert_eq!(loc4.column(), 1);
         assert_eq!(loc4.index(), 2);
 
-        loc.increment_for('\n');
+        loc.increment_for('\n', policy);
         assert_eq!(loc.line(), 2);
         assert_eq!(loc.column(), 1);
         assert_eq!(loc.index(), 2);
 
-        loc.increment_for('a');
+        loc.increment_for('a', policy);
         assert_eq!(loc.line(), 2);
         assert_eq!(loc.column(), 2);
         assert_eq!(loc.index(), 3);
+        assert_eq!(loc.byte_offset(), 3);
+
+        // Multi-byte chars only count as one char, but several bytes
+        loc.increment_for('é', policy);
+        assert_eq!(loc.column(), 3);
+        assert_eq!(loc.index(), 4);
+        assert_eq!(loc.byte_offset(), 5);
+
+        let loc5 = loc.add_delta(1, 2, 3, 6, LocationPolicy::default());
+        assert_eq!(loc5, Location::with_byte_offset(3, 3, 7, 11));
+        // The column restarts at the base of the policy
+        let loc6 = loc.add_delta(1, 2, 3, 6, LocationPolicy::new().zero_based());
+        assert_eq!(loc6, Location::with_byte_offset(3, 2, 7, 11));
+    }
+
+    #[test]
+    fn test_zero_based_policy() {
+        // The three ways of moving a location agree on the column after a new line
+        let policy = LocationPolicy::new().zero_based().with_tab_width(4);
+        let start = policy.beginning();
+        let mut loc = start;
+        for c in "a\n\tb".chars() {
+            loc.increment_for(c, policy);
+        }
+        assert_eq!(loc, Location::new(1, 5, 4));
+        assert_eq!(start.add_line(policy), Location::new(1, 0, 1));
+        assert_eq!(start.add_delta(1, 5, 4, 4, policy), loc);
+    }
+
+    #[test]
+    fn test_source() {
+        let loc = Location::beginning();
+        assert_eq!(loc.source(), SourceId::default());
+
+        // The source is kept when the location moves
+        let loc = loc.with_source(SourceId::new(2));
+        assert_eq!((loc + 2).source(), SourceId::new(2));
+        assert_eq!(loc.add_delta(1, 0, 1, 1, LocationPolicy::default()).source(), SourceId::new(2));
+    }
+
+    #[test]
+    fn test_utf16_position() {
+        let source = "a😎\néb😎c";
+
+        // Compute the location of 'c' by walking the source
+        let mut loc = Location::beginning();
+        for c in source.chars().take(6) {
+            loc.increment_for(c, LocationPolicy::default());
+        }
+        assert_eq!(loc, Location::with_byte_offset(2, 4, 6, 13));
+
+        // The emoji takes 2 UTF-16 code units, 'é' only one
+        let policy = LocationPolicy::default();
+        let pos = loc.to_utf16_position(source, policy);
+        assert_eq!(pos, Some(Utf16Position { line: 1, character: 4 }));
+        assert_eq!(loc.to_utf16_offset(source), Some(8));
+
+        // The first line is not affected by the second one
+        let pos = Location::beginning().to_utf16_position(source, policy);
+        assert_eq!(pos, Some(Utf16Position { line: 0, character: 0 }));
+
+        // Zero-based lines are already the ones of the position
+        let policy = LocationPolicy::new().zero_based();
+        let pos = policy.beginning().to_utf16_position(source, policy);
+        assert_eq!(pos, Some(Utf16Position { line: 0, character: 0 }));
+
+        // A location that is not in the source, like after an edit, has no position
+        let stale = Location::with_byte_offset(2, 3, 4, 2);
+        assert_eq!(stale.to_utf16_position(source, LocationPolicy::default()), None);
+        assert_eq!(stale.to_utf16_offset(source), None);
+        let stale = Location::with_byte_offset(3, 1, 20, 40);
+        assert_eq!(stale.to_utf16_position(source, LocationPolicy::default()), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        use crate::parser_lib::Span;
+
+        let span = Span::new(Location::new(1, 1, 0), Location::new(1, 6, 5).with_source(SourceId::new(2)));
+        let json = serde_json::to_string(&span).unwrap();
+        assert_eq!(
+            json,
+            "{\"start\":{\"line\":1,\"column\":1,\"index\":0,\"byte_offset\":0,\"source\":0},\
+             \"end\":{\"line\":1,\"column\":6,\"index\":5,\"byte_offset\":5,\"source\":2}}"
+        );
+        assert_eq!(serde_json::from_str::<Span>(&json).unwrap(), span);
     }
 }
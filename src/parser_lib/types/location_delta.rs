@@ -0,0 +1,132 @@
+use super::{Location, LocationPolicy};
+
+/// Difference between the location before and after a group of matched chars.
+///
+/// Readers build it while matching, so that matchers can compute the end location
+/// even when the matched chars contain new lines.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct LocationDelta {
+    /// Number of new lines crossed.
+    lines: usize,
+    /// Number of columns after the last new line, or since the start if there is none.
+    columns: usize,
+    /// Total number of chars.
+    len: usize,
+    /// Total number of bytes of the UTF-8 encoded chars.
+    bytes: usize,
+    /// How columns are counted.
+    policy: LocationPolicy,
+}
+
+impl LocationDelta {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty delta which counts columns according to the given policy.
+    pub fn with_policy(policy: LocationPolicy) -> Self {
+        Self {
+            policy,
+            ..Self::default()
+        }
+    }
+
+    /// Creates the delta of `count` bytes of a binary input. It has no lines, so each byte is a column.
+    pub fn of_bytes(count: usize) -> Self {
+        Self {
+            columns: count,
+            len: count,
+            bytes: count,
+            ..Self::default()
+        }
+    }
+
+    /// Adds a char to the delta.
+    pub fn push(&mut self, c: char) {
+        if c == '\n' {
+            self.lines += 1;
+            self.columns = 0;
+        } else {
+            self.columns += self.policy.width_of(c);
+        }
+        self.len += 1;
+        self.bytes += c.len_utf8();
+    }
+
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the location reached after applying the delta to the given location.
+    pub fn apply_to(&self, loc: &Location) -> Location {
+        loc.add_delta(self.lines, self.columns, self.len, self.bytes, self.policy)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_location_delta() {
+        let mut delta = LocationDelta::new();
+        assert_eq!(delta.is_empty(), true);
+
+        for c in "ab\ncde".chars() {
+            delta.push(c);
+        }
+        assert_eq!(delta.lines(), 1);
+        assert_eq!(delta.columns(), 3);
+        assert_eq!(delta.len(), 6);
+        assert_eq!(delta.bytes(), 6);
+
+        // The column is reset by the new line
+        let loc = Location::new(3, 5, 20);
+        assert_eq!(delta.apply_to(&loc), Location::new(4, 4, 26));
+
+        // Without new line, the column is simply incremented
+        let mut delta = LocationDelta::new();
+        delta.push('é');
+        assert_eq!(delta.len(), 1);
+        assert_eq!(delta.bytes(), 2);
+        assert_eq!(delta.apply_to(&loc), Location::with_byte_offset(3, 6, 21, 22));
+
+        // In a binary input, the bytes are columns
+        assert_eq!(LocationDelta::of_bytes(4).apply_to(&loc), Location::new(3, 9, 24));
+    }
+
+    #[test]
+    fn test_location_delta_with_policy() {
+        let policy = LocationPolicy::new().with_tab_width(4).zero_based();
+        let mut delta = LocationDelta::with_policy(policy);
+
+        for c in "\tab".chars() {
+            delta.push(c);
+        }
+        assert_eq!(delta.columns(), 6);
+        assert_eq!(delta.apply_to(&policy.beginning()), Location::new(0, 6, 3));
+
+        // After a new line, the columns restart at 0
+        for c in "\n\tc".chars() {
+            delta.push(c);
+        }
+        assert_eq!(delta.apply_to(&policy.beginning()), Location::new(1, 5, 6));
+    }
+}
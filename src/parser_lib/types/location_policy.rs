@@ -0,0 +1,138 @@
+use super::Location;
+
+/// Unit in which columns are counted.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum ColumnUnit {
+    /// One column per char.
+    #[default]
+    Chars,
+    /// One column per UTF-16 code unit, like most editors and LSP clients.
+    Utf16,
+}
+
+/// Tells how locations are computed when chars are matched.
+///
+/// The default policy counts chars, with tabs taking 1 column, and 1-based lines and columns.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LocationPolicy {
+    /// Number of columns a tab advances by.
+    ///
+    /// It is a fixed width and not a tab stop, so that deltas stay independent of the start column.
+    tab_width: usize,
+    column_unit: ColumnUnit,
+    /// First line and column number: 0 or 1.
+    base: usize,
+    /// If true, `\r` takes no column, so that `\r\n` is a single logical new line.
+    crlf_newlines: bool,
+}
+
+impl Default for LocationPolicy {
+    fn default() -> Self {
+        Self {
+            tab_width: 1,
+            column_unit: ColumnUnit::Chars,
+            base: 1,
+            crlf_newlines: false,
+        }
+    }
+}
+
+impl LocationPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = tab_width;
+        self
+    }
+
+    pub fn with_column_unit(mut self, column_unit: ColumnUnit) -> Self {
+        self.column_unit = column_unit;
+        self
+    }
+
+    /// Makes lines and columns start at 0 instead of 1.
+    pub fn zero_based(mut self) -> Self {
+        self.base = 0;
+        self
+    }
+
+    /// Treats `\r\n` as a single new line: the `\r` doesn't take any column,
+    /// so Windows-authored files get the same columns as Unix ones.
+    pub fn with_crlf_newlines(mut self) -> Self {
+        self.crlf_newlines = true;
+        self
+    }
+
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    pub fn column_unit(&self) -> ColumnUnit {
+        self.column_unit
+    }
+
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    pub fn crlf_newlines(&self) -> bool {
+        self.crlf_newlines
+    }
+
+    /// Returns the location of the beginning of an input.
+    pub fn beginning(&self) -> Location {
+        Location::new(self.base, self.base, 0)
+    }
+
+    /// Returns the number of columns the given char (which is not a new line) takes.
+    pub fn width_of(&self, c: char) -> usize {
+        match (c, self.column_unit) {
+            ('\t', _) => self.tab_width,
+            ('\r', _) if self.crlf_newlines => 0,
+            (_, ColumnUnit::Chars) => 1,
+            (_, ColumnUnit::Utf16) => c.len_utf16(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::LocationDelta;
+
+    use super::*;
+
+    #[test]
+    fn test_location_policy() {
+        let policy = LocationPolicy::new();
+        assert_eq!(policy.beginning(), Location::beginning());
+        assert_eq!(policy.width_of('\t'), 1);
+        assert_eq!(policy.width_of('😎'), 1);
+
+        let policy = LocationPolicy::new()
+            .with_tab_width(4)
+            .with_column_unit(ColumnUnit::Utf16)
+            .zero_based();
+        assert_eq!(policy.beginning(), Location::new(0, 0, 0));
+        assert_eq!(policy.width_of('\t'), 4);
+        assert_eq!(policy.width_of('😎'), 2);
+        assert_eq!(policy.width_of('a'), 1);
+    }
+
+    #[test]
+    fn test_crlf_newlines() {
+        let policy = LocationPolicy::new();
+        assert_eq!(policy.width_of('\r'), 1);
+
+        let policy = LocationPolicy::new().with_crlf_newlines();
+        assert_eq!(policy.width_of('\r'), 0);
+
+        // The line ends the same way as with a single \n
+        let mut delta = LocationDelta::with_policy(policy);
+        for c in "ab\r\ncd\r\n".chars() {
+            delta.push(c);
+        }
+        assert_eq!(delta.apply_to(&Location::beginning()), Location::new(3, 1, 8));
+    }
+}
@@ -0,0 +1,88 @@
+use std::fmt::Debug;
+
+use super::{Endianness, LexError, ParserError, Stream};
+
+/// Matching functions used by the byte matchers, to describe binary formats.
+///
+/// It is the byte counterpart of `MatchStr`: positions are absolute offsets in bytes from the start of the input,
+/// and the default implementations are based on `Stream::peek_nth`.
+pub trait MatchBytes: Debug + Stream<u8> {
+    /// Returns the byte at the given position, or None at the end of the input.
+    ///
+    /// By default, the bytes behind the cursor are not accessible anymore (`NoLookBehind` error).
+    fn byte_at(&mut self, pos: usize) -> Result<Option<u8>, ParserError> {
+        let cursor = self.index();
+        if pos < cursor {
+            return Err(LexError::NoLookBehind(pos).into());
+        }
+
+        Ok(self.peek_nth(pos - cursor))
+    }
+
+    /// Returns the error that stopped the reading of the input, if any.
+    ///
+    /// It is checked when no more bytes are available, to tell a failure apart from the end of the input.
+    fn check_error(&self) -> Result<(), ParserError> {
+        Ok(())
+    }
+
+    /// Returns `true` if the given bytes are in the input at the position `pos`, `false` otherwise.
+    fn match_bytes(&mut self, pos: usize, bytes: &[u8]) -> Result<bool, ParserError> {
+        for (i, expected) in (pos..).zip(bytes) {
+            match self.byte_at(i)? {
+                Some(b) if b == *expected => {}
+                Some(_) => return Ok(false),
+                None => return self.check_error().map(|_| false),
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Returns the number of consecutive bytes in the given range, starting at `pos`.
+    ///
+    /// start: inclusive start of the range
+    /// end: inclusive end of the range
+    ///
+    /// max: if 0, repeat until in doesn't match. If > 0, repeat max times.
+    fn match_byte_range(&mut self, pos: usize, start: u8, end: u8, max: usize) -> Result<usize, ParserError> {
+        let mut count = 0;
+
+        while max == 0 || count < max {
+            match self.byte_at(pos + count)? {
+                Some(b) if (start..=end).contains(&b) => count += 1,
+                _ => break,
+            }
+        }
+
+        // The end of the bytes may also be caused by a read error
+        self.check_error()?;
+
+        Ok(count)
+    }
+
+    /// Reads an unsigned integer field of `size` bytes at the position `pos`, in the given byte order.
+    ///
+    /// Returns None if the input ends before the end of the field.
+    fn read_uint(&mut self, pos: usize, size: usize, endianness: Endianness) -> Result<Option<u64>, ParserError> {
+        let mut bytes = Vec::with_capacity(size);
+
+        for i in pos..pos + size {
+            match self.byte_at(i)? {
+                Some(b) => bytes.push(b),
+                None => return self.check_error().map(|_| None),
+            }
+        }
+
+        Ok(Some(endianness.to_uint(&bytes)))
+    }
+
+    /// Returns true if the byte is the end of the input.
+    fn is_end_of_input(&mut self, pos: usize) -> Result<bool, ParserError> {
+        match self.byte_at(pos)? {
+            Some(_) => Ok(false),
+            // The end of the bytes may also be caused by a read error
+            None => self.check_error().map(|_| true),
+        }
+    }
+}
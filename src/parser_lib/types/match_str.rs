@@ -1,18 +1,71 @@
 use std::fmt::Debug;
 
-use super::{ParserError, Stream};
+use super::{LexError, LocationDelta, LocationPolicy, ParserError, Stream};
 
+/// Matching functions used by the matchers.
+///
+/// They all have default implementations based on `Stream::peek_nth`, so a new input source
+/// only needs to implement `Stream<char>`.
+/// Readers can override them when they can do better (like comparing whole slices at once).
 pub trait MatchStr: Debug + Stream<char> {
+    /// Returns the char at the given position, or None at the end of the input.
+    ///
+    /// The position is an absolute index from the start of the input.
+    ///
+    /// By default, the chars behind the cursor are not accessible anymore (`NoLookBehind` error).
+    fn char_at(&mut self, pos: usize) -> Result<Option<char>, ParserError> {
+        let cursor = self.index();
+        if pos < cursor {
+            return Err(LexError::NoLookBehind(pos).into());
+        }
+
+        Ok(self.peek_nth(pos - cursor))
+    }
+
+    /// Returns the error that stopped the reading of the input, if any.
+    ///
+    /// It is checked when no more chars are available, to tell a failure apart from the end of the input.
+    fn check_error(&self) -> Result<(), ParserError> {
+        Ok(())
+    }
+
     /// Compares the given string `s` with the input at the position `pos`.
     ///
     /// The position is an absolute index from the start of the input.
     ///
-    /// Returns `false` if s is a substring of the input starting at pos `pos`, `false` otherwise.
+    /// Returns `true` if s is a substring of the input starting at pos `pos`, `false` otherwise.
     ///
     /// Can return an error if:
     /// - The given pos is behind the cursor (no look behind)
     /// - The given pos + the size of the string falls outside of the size of the buffer (look ahead overflow)
-    fn match_str(&mut self, pos: usize, s: &str) -> Result<bool, ParserError>;
+    fn match_str(&mut self, pos: usize, s: &str) -> Result<bool, ParserError> {
+        // Check that the whole string can be reached before comparing anything:
+        // its start may be behind the cursor, and its end too far ahead
+        if let Some(last) = s.chars().count().checked_sub(1) {
+            if pos < self.index() {
+                self.char_at(pos)?;
+            }
+            self.char_at(pos + last)?;
+        }
+
+        // Compare each char
+        for (i, str_c) in (pos..).zip(s.chars()) {
+            if let Some(input_c) = self.char_at(i)? {
+                if input_c != str_c {
+                    // If a difference is found, it's not equal
+                    return Ok(false);
+                }
+            } else {
+                // The end of the chars may also be caused by a read error
+                self.check_error()?;
+
+                // If EOF is reached before the end of the string to compare, it's not equal
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
 
     /// Checks if the next char is in the given char range.
     /// Avoids to check individually every possibility if the binary range is continuous.
@@ -22,18 +75,174 @@ pub trait MatchStr: Debug + Stream<char> {
     ///
     /// max: if 0, repeat until in doesn't match. If > 0, repeat max times.
     ///
-    /// If Ok, returns the number of chars matched.
+    /// If Ok, returns the delta covered by the matched chars.
+    /// Its length is the number of chars matched, and it accounts for new lines in the range.
     fn match_range(
         &mut self,
         pos: usize,
         start: char,
         end: char,
-        max: u8,
-    ) -> Result<u32, ParserError>;
+        max: usize,
+    ) -> Result<LocationDelta, ParserError> {
+        let mut matched = LocationDelta::with_policy(self.location_policy());
+
+        let mut i = pos;
+        while let Some(c) = self.char_at(i)? {
+            // If a difference is found, or if we already have matched the max, we stop here
+            if c < start || c > end {
+                break;
+            }
+
+            // If there is a max and it is reached, we stop here
+            if max != 0 && matched.len() >= max {
+                break;
+            }
+
+            matched.push(c);
+            i += 1;
+        }
+
+        // The end of the chars may also be caused by a read error
+        self.check_error()?;
+
+        Ok(matched)
+    }
+
+    /// Returns the position of the first `c` found at `pos` or after it, or None if the input ends before.
+    ///
+    /// It is used to skip the chars that can't start a terminator: readers can search their buffer at once.
+    fn find_char(&mut self, pos: usize, c: char) -> Result<Option<usize>, ParserError> {
+        let mut i = pos;
+        while let Some(input_c) = self.char_at(i)? {
+            if input_c == c {
+                return Ok(Some(i));
+            }
+            i += 1;
+        }
+
+        // The end of the chars may also be caused by a read error
+        self.check_error()?;
+        Ok(None)
+    }
 
     /// Returns true if the char is a newline.
-    fn is_newline(&mut self, pos: usize) -> Result<bool, ParserError>;
+    fn is_newline(&mut self, pos: usize) -> Result<bool, ParserError> {
+        match self.char_at(pos)? {
+            Some(c) => Ok(c == '\n'),
+            // The end of the chars may also be caused by a read error
+            None => self.check_error().map(|_| false),
+        }
+    }
 
     /// Returns true if the char is the end of the input.
-    fn is_end_of_input(&mut self, pos: usize) -> Result<bool, ParserError>;
+    fn is_end_of_input(&mut self, pos: usize) -> Result<bool, ParserError> {
+        match self.char_at(pos)? {
+            Some(_) => Ok(false),
+            // The end of the chars may also be caused by a read error
+            None => self.check_error().map(|_| true),
+        }
+    }
+
+    /// Returns the number of times the reader filled its buffer from its input.
+    ///
+    /// Readers that don't use a buffer always return 0.
+    fn refills(&self) -> usize {
+        0
+    }
+
+    /// Makes sure that the next `n` chars from the cursor can be matched, without a `LookAheadBufferOverflow` error.
+    ///
+    /// Readers that don't use a buffer can already look as far as needed, so they do nothing by default.
+    fn reserve_look_ahead(&mut self, _n: usize) {}
+
+    /// Returns the policy used to compute locations of the matched chars.
+    ///
+    /// Readers that don't store a policy always use the default one.
+    fn location_policy(&self) -> LocationPolicy {
+        LocationPolicy::default()
+    }
+
+    /// Changes the policy used to compute locations of the matched chars.
+    ///
+    /// Readers that don't store a policy don't support changing it, and ignore the new one by default.
+    fn set_location_policy(&mut self, _policy: LocationPolicy) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::{Checkpoint, Location, Rewind};
+
+    use super::*;
+
+    /// Minimal input source, which only implements the stream
+    #[derive(Debug)]
+    struct VecReader {
+        chars: Vec<char>,
+        cursor: usize,
+    }
+
+    impl Stream<char> for VecReader {
+        fn peek(&mut self) -> Option<char> {
+            self.peek_nth(0)
+        }
+
+        fn peek_nth(&mut self, n: usize) -> Option<char> {
+            self.chars.get(self.cursor + n).copied()
+        }
+
+        fn consume(&mut self) -> Option<char> {
+            self.consume_nth(0)
+        }
+
+        fn consume_nth(&mut self, n: usize) -> Option<char> {
+            let c = self.peek_nth(n)?;
+            self.cursor += n + 1;
+            Some(c)
+        }
+
+        fn is_eof(&mut self) -> bool {
+            self.peek().is_none()
+        }
+
+        fn index(&self) -> usize {
+            self.cursor
+        }
+    }
+
+    impl Rewind for VecReader {
+        fn checkpoint(&mut self) -> Checkpoint {
+            Checkpoint::new(self.cursor, 0)
+        }
+
+        fn rewind(&mut self, checkpoint: Checkpoint) -> Result<(), ParserError> {
+            self.cursor = checkpoint.index();
+            Ok(())
+        }
+    }
+
+    impl MatchStr for VecReader {}
+
+    #[test]
+    fn test_default_implementations() {
+        let mut reader = VecReader {
+            chars: "ab\ncd".chars().collect(),
+            cursor: 0,
+        };
+
+        assert_eq!(reader.match_str(0, "ab"), Ok(true));
+        assert_eq!(reader.match_str(1, "ab"), Ok(false));
+        assert_eq!(reader.is_newline(2), Ok(true));
+        assert_eq!(reader.is_end_of_input(5), Ok(true));
+
+        let delta = reader.match_range(0, '\0', 'z', 0).unwrap();
+        assert_eq!(delta.apply_to(&Location::beginning()), Location::new(2, 3, 5));
+
+        // The policy can't be changed
+        reader.set_location_policy(LocationPolicy::new().zero_based());
+        assert_eq!(reader.location_policy(), LocationPolicy::default());
+
+        // Consumed chars can't be read anymore
+        reader.consume();
+        assert_eq!(reader.match_str(0, "ab"), Err(LexError::NoLookBehind(0).into()));
+    }
 }
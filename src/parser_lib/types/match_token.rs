@@ -1,15 +1,31 @@
-use std::fmt::{Debug, Display};
+use std::fmt::{Debug, Display, Formatter};
 
-use super::{Location, MatchStr, ParseResult};
+use super::{Location, MatcherShape, Nesting, Notation, ParseResult};
 
 /// A matcher (or parser) tells how to analyse a specific part of the source code.
 ///
 /// For example, a "StringMatcher" will try to match an exact string.
-pub trait MatchToken<R: MatchStr>: Display + Debug {
+///
+/// The reader is usually a `MatchStr` for text, or a `MatchBytes` for binary formats.
+///
+/// Matchers are `Send + Sync`, so that a grammar can be shared between threads to parse several inputs at once.
+pub trait MatchToken<R>: Display + Debug + Send + Sync {
     /// Compares this token to the input at the given location in the reader.
     ///
     /// Returns true if the token matches, false otherwise.
     ///
     /// Propagates errors returned by the reader.
     fn test(&self, loc: &Location, reader: &mut R) -> ParseResult;
+
+    /// Writes the matcher in the given grammar notation, adding parentheses if needed where it is nested.
+    ///
+    /// By default, it is written like `Display`.
+    fn fmt_notation(&self, f: &mut Formatter, _notation: Notation, _nesting: Nesting) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+
+    /// Returns the structure of the matcher. By default, it is a terminal.
+    fn shape(&self) -> MatcherShape<'_, R> {
+        MatcherShape::Terminal
+    }
 }
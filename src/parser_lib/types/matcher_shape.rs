@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use super::MatchToken;
+
+/// Structure of a matcher, to walk through the matcher tree (to draw diagrams, for example).
+pub enum MatcherShape<'a, R> {
+    /// Matches the input by itself (string, range...). It is described by its notation.
+    Terminal,
+    /// Matches exactly the given string.
+    Literal(&'a str),
+    /// Matches the first of the given strings that matches, like a choice between literals.
+    Keywords(&'a [String]),
+    /// Matches between min and max chars of the range (inclusive). If max is 0, there is no limit.
+    Range { start: char, end: char, min: usize, max: usize },
+    /// Matches the named rule.
+    Reference(&'a str),
+    /// Matches the children one after another.
+    Sequence(&'a [Arc<dyn MatchToken<R>>]),
+    /// Matches the first child that matches.
+    Choice(&'a [Arc<dyn MatchToken<R>>]),
+    /// Matches the value between min and max times. If max is 0, there is no limit.
+    Repetition {
+        value: &'a Arc<dyn MatchToken<R>>,
+        min: usize,
+        max: usize,
+    },
+    /// Matches the value, or nothing.
+    Optional(&'a Arc<dyn MatchToken<R>>),
+    /// Matches nothing, if the value doesn't match (negative lookahead).
+    Not(&'a Arc<dyn MatchToken<R>>),
+    /// Matches nothing, if the value matches (positive lookahead).
+    And(&'a Arc<dyn MatchToken<R>>),
+    /// Matches at least min chars, until the value matches.
+    Until { until: &'a Arc<dyn MatchToken<R>>, min: usize },
+    /// Matches at least one char until the sync point, as an error node.
+    Error(&'a Arc<dyn MatchToken<R>>),
+    /// Matches the value, with some side effect (like finishing a token).
+    Wrapper(&'a Arc<dyn MatchToken<R>>),
+    /// Matches the value, named in the result of the parent sequence.
+    Label { label: &'a Arc<str>, value: &'a Arc<dyn MatchToken<R>> },
+}
@@ -1,33 +1,92 @@
+mod checkpoint;
+mod corpus_report;
+mod endianness;
+mod expr_builder;
 mod grammar;
+mod highlight;
+mod incremental;
+mod interner;
+mod io_error;
+mod lex_error;
 mod location;
+mod location_delta;
+mod location_policy;
+mod match_bytes;
 mod match_str;
 mod match_token;
+mod matcher_shape;
+mod notation;
+mod optimizer;
+mod parse_arena;
+mod parse_context;
+mod parse_event;
 mod parse_info;
+mod parse_node;
+mod parse_options;
 mod parse_result;
 mod parser_error;
+mod profile;
+mod reader_stats;
+mod resumable_parse;
 mod rule;
 mod rule_macros;
+mod source_id;
 mod span;
 mod stream;
+mod syntax_error;
+mod syntax_tree;
+mod test_macros;
 mod token;
+mod token_sink;
+mod trace;
 
 // Traits
+pub use match_bytes::MatchBytes;
 pub use match_str::MatchStr;
 pub use match_token::MatchToken;
+pub use matcher_shape::MatcherShape;
 pub use parse_result::CreateParseResult;
-pub use stream::Stream;
+pub use stream::{Rewind, Stream};
 pub use token::TokenType;
+pub use token_sink::TokenSink;
 
 // Structs
+pub use checkpoint::Checkpoint;
+pub use corpus_report::{CorpusEntry, CorpusReport};
+pub use endianness::Endianness;
+pub use expr_builder::ExprBuilder;
 pub use grammar::Grammar;
 pub use grammar::GrammarBuilder;
+pub use highlight::{HighlightCategory, Highlights};
+pub use incremental::{IncrementalTree, TextEdit};
+pub use interner::{Interner, Symbol};
+pub use io_error::IoError;
+pub use lex_error::LexError;
 pub use location::Location;
-pub use parse_info::ParseInfo;
-pub use parser_error::ParserError;
+pub use location::Utf16Position;
+pub use location_delta::LocationDelta;
+pub use location_policy::{ColumnUnit, LocationPolicy};
+pub use notation::{InNotation, Nesting, Notation};
+pub use parse_arena::{NodeId, ParseArena};
+pub use parse_event::ParseEvent;
+pub use parse_info::{Field, ParseInfo};
+pub use parse_node::ParseNode;
+pub use parse_options::{ParseOptions, Recovery};
+pub use parser_error::{ErrorContext, ParserError};
+pub use profile::{ParseProfile, RuleProfile};
+pub use reader_stats::ReaderStats;
+pub use resumable_parse::{ParseStatus, ResumableParse};
 pub use rule::Rule;
+pub use source_id::SourceId;
 pub use span::Span;
-pub use token::Token;
+pub use syntax_error::SyntaxError;
+pub use syntax_tree::{GreenNode, SyntaxKind, SyntaxNode};
+pub use token::{Token, TokenValue};
+pub use token_sink::TokenEvent;
+pub use trace::{TraceEvent, TraceLevel, TraceOutcome, Tracer};
 
 // Other
+pub use parse_context::DEFAULT_RECURSION_LIMIT;
+pub(crate) use parse_context::{Emitter, Mark, ParseContext, PendingToken};
 pub use parse_result::ParseResult;
-pub use rule_macros::*;
+pub(crate) use syntax_tree::LosslessBuilder;
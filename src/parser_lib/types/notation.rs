@@ -0,0 +1,227 @@
+use std::fmt::{Display, Formatter, Result};
+
+use super::MatchToken;
+
+/// Text notation used to export grammars.
+///
+/// Both notations can be loaded back with `Grammar::from_ebnf`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+    /// Extended Backus-Naur form: `name = a, (b | c)* ;`
+    ///
+    /// EBNF has no lookahead, so the PEG operators `!` and `&` are used for it.
+    Ebnf,
+    /// Parsing expression grammar: `name <- a (b / c)*`
+    Peg,
+}
+
+/// Where an expression is written, to know if it needs parentheses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Nesting {
+    /// Whole body of a rule, or alternative of a choice.
+    Alternative,
+    /// Item of a sequence.
+    Item,
+    /// Operand of a prefix or postfix operator.
+    Operand,
+}
+
+impl Notation {
+    /// Operator between the name of a rule and its body.
+    pub fn definition(self) -> &'static str {
+        match self {
+            Notation::Ebnf => " = ",
+            Notation::Peg => " <- ",
+        }
+    }
+
+    /// End of a rule.
+    pub fn terminator(self) -> &'static str {
+        match self {
+            Notation::Ebnf => " ;",
+            Notation::Peg => "",
+        }
+    }
+
+    /// Separator between the items of a sequence.
+    pub fn sequence_separator(self) -> &'static str {
+        match self {
+            Notation::Ebnf => ", ",
+            Notation::Peg => " ",
+        }
+    }
+
+    /// Separator between the alternatives of a choice.
+    pub fn choice_separator(self) -> &'static str {
+        match self {
+            Notation::Ebnf => " | ",
+            Notation::Peg => " / ",
+        }
+    }
+
+    /// Writes the body between parentheses if needed.
+    pub fn write_group(f: &mut Formatter, parentheses: bool, body: impl FnOnce(&mut Formatter) -> Result) -> Result {
+        if parentheses {
+            write!(f, "(")?;
+            body(f)?;
+            write!(f, ")")
+        } else {
+            body(f)
+        }
+    }
+
+    /// Writes a quoted string.
+    pub fn write_str(f: &mut Formatter, value: &str) -> Result {
+        write!(f, "\"")?;
+        for c in value.chars() {
+            write!(f, "{}", escape(c, false))?;
+        }
+        write!(f, "\"")
+    }
+
+    /// Writes a class containing the chars between start and end (inclusive).
+    pub fn write_range(f: &mut Formatter, start: char, end: char) -> Result {
+        match (start, end) {
+            ('\0', char::MAX) => write!(f, "."),
+            _ if start == end => write!(f, "[{}]", escape(start, true)),
+            _ => write!(f, "[{}-{}]", escape(start, true), escape(end, true)),
+        }
+    }
+
+    /// Writes an item repeated between min and max times (if max is 0, there is no limit).
+    ///
+    /// The usual notations have no bounded repetition, so the item is written several times instead:
+    /// `a{2,3}` is written `a a a?`.
+    pub fn write_repetition(
+        self,
+        f: &mut Formatter,
+        nesting: Nesting,
+        min: usize,
+        max: usize,
+        item: &dyn Fn(&mut Formatter, Nesting) -> Result,
+    ) -> Result {
+        let suffix = match (min, max) {
+            (0, 0) => Some("*"),
+            (_, 0) => Some("+"),
+            (0, 1) => Some("?"),
+            _ => None,
+        };
+        let copies = match (min, max) {
+            (0, 0) => 0,
+            (_, 0) => min - 1,
+            _ => min,
+        };
+        let optionals = if max == 0 { 0 } else { max - min };
+
+        // The simple cases are a single postfix operation
+        if let (Some(suffix), 0) = (suffix, copies) {
+            Self::write_group(f, nesting >= Nesting::Operand, |f| {
+                item(f, Nesting::Operand)?;
+                write!(f, "{}", suffix)
+            })?;
+            return Ok(());
+        }
+        if copies + optionals == 0 {
+            return write!(f, "\"\"");
+        }
+
+        let mut parts: Vec<(Nesting, &str)> = vec![(Nesting::Item, ""); copies];
+        match suffix {
+            Some("+") => parts.push((Nesting::Operand, "+")),
+            _ => parts.extend(vec![(Nesting::Operand, "?"); optionals]),
+        }
+
+        if parts.len() == 1 {
+            return item(f, nesting);
+        }
+        Self::write_group(f, nesting >= Nesting::Operand, |f| {
+            for (i, (item_nesting, suffix)) in parts.iter().enumerate() {
+                if i > 0 {
+                    write!(f, "{}", self.sequence_separator())?;
+                }
+                item(f, *item_nesting)?;
+                write!(f, "{}", suffix)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Displays a matcher in the given notation.
+pub struct InNotation<'a, R>(pub &'a dyn MatchToken<R>, pub Notation);
+
+impl<R> Display for InNotation<'_, R> {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        self.0.fmt_notation(f, self.1, Nesting::Alternative)
+    }
+}
+
+/// Escapes a char of a string (or of a class, where the brackets are special too).
+fn escape(c: char, in_class: bool) -> String {
+    match c {
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        '\0' => "\\0".to_string(),
+        '\\' => "\\\\".to_string(),
+        '"' if !in_class => "\\\"".to_string(),
+        ']' | '[' | '-' | '^' if in_class => format!("\\{}", c),
+        c if c.is_control() => format!("\\u{{{:x}}}", c as u32),
+        c => c.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a repetition of "a".
+    struct Repetition(Notation, Nesting, usize, usize);
+
+    impl Display for Repetition {
+        fn fmt(&self, f: &mut Formatter) -> Result {
+            self.0.write_repetition(f, self.1, self.2, self.3, &|f, _| write!(f, "a"))
+        }
+    }
+
+    #[test]
+    fn test_write_repetition() {
+        let (ebnf, peg, top, operand) = (Notation::Ebnf, Notation::Peg, Nesting::Alternative, Nesting::Operand);
+
+        assert_eq!(Repetition(ebnf, top, 0, 0).to_string(), "a*");
+        assert_eq!(Repetition(ebnf, top, 1, 0).to_string(), "a+");
+        assert_eq!(Repetition(ebnf, top, 0, 1).to_string(), "a?");
+        assert_eq!(Repetition(ebnf, top, 1, 1).to_string(), "a");
+        assert_eq!(Repetition(ebnf, top, 3, 0).to_string(), "a, a, a+");
+        assert_eq!(Repetition(peg, top, 2, 4).to_string(), "a a a? a?");
+        assert_eq!(Repetition(peg, top, 0, 0).to_string(), "a*");
+        assert_eq!(Repetition(peg, top, 0, 2).to_string(), "a? a?");
+
+        // Parentheses are needed as operands
+        assert_eq!(Repetition(peg, operand, 0, 0).to_string(), "(a*)");
+        assert_eq!(Repetition(peg, operand, 2, 2).to_string(), "(a a)");
+    }
+
+    #[test]
+    fn test_escape() {
+        struct Quoted(&'static str);
+        impl Display for Quoted {
+            fn fmt(&self, f: &mut Formatter) -> Result {
+                Notation::write_str(f, self.0)
+            }
+        }
+        struct Class(char, char);
+        impl Display for Class {
+            fn fmt(&self, f: &mut Formatter) -> Result {
+                Notation::write_range(f, self.0, self.1)
+            }
+        }
+
+        assert_eq!(Quoted("say \"hi\"\n").to_string(), r#""say \"hi\"\n""#);
+        assert_eq!(Quoted("C:\\é\u{7}").to_string(), r#""C:\\é\u{7}""#);
+        assert_eq!(Class('a', 'z').to_string(), "[a-z]");
+        assert_eq!(Class('-', ']').to_string(), r"[\--\]]");
+        assert_eq!(Class('"', '"').to_string(), "[\"]");
+        assert_eq!(Class('\0', char::MAX).to_string(), ".");
+    }
+}
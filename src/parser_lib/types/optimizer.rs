@@ -0,0 +1,247 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::parser_lib::{
+    AndMatcher, ChoiceMatcher, ErrorNodeMatcher, KeywordSetMatcher, LabelMatcher, NotMatcher, OptionalMatcher, ReferenceMatcher, RepetitionMatcher, SequentialMatcher,
+    StrMatcher, UntilMatcher,
+};
+
+use super::{MatchStr, MatchToken, MatcherShape};
+
+/// Simplifies a matcher tree without changing what it matches:
+/// - nested sequences and nested choices are flattened, and those with a single child are replaced by it
+/// - adjacent strings of a sequence are merged into one
+/// - consecutive strings of a choice are matched with a trie, see `KeywordSetMatcher`
+/// - structurally identical matchers are shared (hash-consing)
+///
+/// Terminals are compared with their debug representation. Wrappers (like tokens) are kept as they are.
+pub(crate) struct Optimizer<R: MatchStr> {
+    /// Unique matchers, by structure.
+    unique: HashMap<String, Arc<dyn MatchToken<R>>>,
+    /// Optimized matchers, by address of the original matcher.
+    done: HashMap<*const u8, Arc<dyn MatchToken<R>>>,
+    /// New references to the named rules. If None, the references are kept.
+    references: Option<HashMap<String, Arc<ReferenceMatcher<R>>>>,
+}
+
+impl<R: 'static + MatchStr> Optimizer<R> {
+    /// Creates an optimizer that keeps the references to named rules.
+    pub fn new() -> Self {
+        Self {
+            unique: HashMap::new(),
+            done: HashMap::new(),
+            references: None,
+        }
+    }
+
+    /// Creates an optimizer that replaces the references to named rules by new ones, to optimize a whole grammar.
+    pub fn with_new_references() -> Self {
+        Self {
+            references: Some(HashMap::new()),
+            ..Self::new()
+        }
+    }
+
+    /// Returns the new reference to the named rule, if the references are replaced.
+    pub fn reference(&mut self, name: &str) -> Option<Arc<ReferenceMatcher<R>>> {
+        let references = self.references.as_mut()?;
+        let reference = references
+            .entry(name.to_string())
+            .or_insert_with(|| Arc::new(ReferenceMatcher::new(name)));
+        Some(reference.clone())
+    }
+
+    /// Returns the optimized version of the matcher.
+    pub fn optimize(&mut self, matcher: &Arc<dyn MatchToken<R>>) -> Arc<dyn MatchToken<R>> {
+        let address = Arc::as_ptr(matcher) as *const u8;
+        if let Some(optimized) = self.done.get(&address) {
+            return optimized.clone();
+        }
+
+        let optimized = match matcher.shape() {
+            MatcherShape::Terminal | MatcherShape::Range { .. } | MatcherShape::Wrapper(_) => self.intern(format!("{:?}", matcher), || matcher.clone()),
+            MatcherShape::Literal(value) => self.intern(format!("str {:?}", value), || matcher.clone()),
+            MatcherShape::Keywords(words) => self.intern(format!("keywords {:?}", words), || matcher.clone()),
+            MatcherShape::Reference(name) => match self.reference(name) {
+                Some(reference) => reference,
+                None => self.intern(format!("ref {:p}", address), || matcher.clone()),
+            },
+            MatcherShape::Sequence(children) => self.sequence(children),
+            MatcherShape::Choice(children) => self.choice(children),
+            MatcherShape::Repetition { value, min, max } => {
+                let value = self.optimize(value);
+                self.intern(format!("rep {:p} {} {}", value, min, max), || {
+                    Arc::new(RepetitionMatcher::between(value.clone(), min, max))
+                })
+            }
+            MatcherShape::Optional(value) => {
+                let value = self.optimize(value);
+                self.intern(format!("opt {:p}", value), || Arc::new(OptionalMatcher::new(value.clone())))
+            }
+            MatcherShape::Not(value) => {
+                let value = self.optimize(value);
+                self.intern(format!("not {:p}", value), || Arc::new(NotMatcher::new(value.clone())))
+            }
+            MatcherShape::And(value) => {
+                let value = self.optimize(value);
+                self.intern(format!("and {:p}", value), || Arc::new(AndMatcher::new(value.clone())))
+            }
+            MatcherShape::Until { until, min } => {
+                let until = self.optimize(until);
+                self.intern(format!("until {:p} {}", until, min), || Arc::new(UntilMatcher::new(until.clone(), min)))
+            }
+            MatcherShape::Error(sync) => {
+                let sync = self.optimize(sync);
+                self.intern(format!("error {:p}", sync), || Arc::new(ErrorNodeMatcher::new(sync.clone())))
+            }
+            MatcherShape::Label { label, value } => {
+                let value = self.optimize(value);
+                self.intern(format!("label {:?} {:p}", label, value), || {
+                    Arc::new(LabelMatcher::new(label, value.clone()))
+                })
+            }
+        };
+
+        self.done.insert(address, optimized.clone());
+        optimized
+    }
+
+    /// Returns the matcher with the given structure if there is one, or creates it.
+    fn intern(&mut self, key: String, create: impl FnOnce() -> Arc<dyn MatchToken<R>>) -> Arc<dyn MatchToken<R>> {
+        self.unique.entry(key).or_insert_with(create).clone()
+    }
+
+    fn sequence(&mut self, children: &[Arc<dyn MatchToken<R>>]) -> Arc<dyn MatchToken<R>> {
+        let mut items: Vec<Arc<dyn MatchToken<R>>> = Vec::new();
+        for child in children {
+            let child = self.optimize(child);
+            match child.shape() {
+                MatcherShape::Sequence(grandchildren) => items.extend(grandchildren.iter().cloned()),
+                _ => items.push(child.clone()),
+            }
+        }
+
+        // Merge adjacent strings
+        let mut merged: Vec<Arc<dyn MatchToken<R>>> = Vec::new();
+        for item in items {
+            let previous = merged.last().map(|last| last.shape());
+            if let (Some(MatcherShape::Literal(first)), MatcherShape::Literal(second)) = (previous, item.shape()) {
+                let value = format!("{}{}", first, second);
+                merged.pop();
+                let literal = self.intern(format!("str {:?}", value), || Arc::new(StrMatcher::new(value.clone())));
+                merged.push(literal);
+            } else {
+                merged.push(item);
+            }
+        }
+
+        if merged.len() == 1 {
+            return merged.remove(0);
+        }
+        let key = Self::key("seq", &merged);
+        self.intern(key, || Arc::new(SequentialMatcher::new(merged)))
+    }
+
+    fn choice(&mut self, children: &[Arc<dyn MatchToken<R>>]) -> Arc<dyn MatchToken<R>> {
+        let mut alternatives: Vec<Arc<dyn MatchToken<R>>> = Vec::new();
+        for child in children {
+            let child = self.optimize(child);
+            match child.shape() {
+                MatcherShape::Choice(grandchildren) => alternatives.extend(grandchildren.iter().cloned()),
+                _ => alternatives.push(child.clone()),
+            }
+        }
+
+        let mut alternatives = self.keyword_sets(alternatives);
+        if alternatives.len() == 1 {
+            return alternatives.remove(0);
+        }
+        let key = Self::key("choice", &alternatives);
+        self.intern(key, || Arc::new(ChoiceMatcher::new(alternatives)))
+    }
+
+    /// Replaces the consecutive strings of a choice by keyword sets, which are matched in a single pass.
+    fn keyword_sets(&mut self, alternatives: Vec<Arc<dyn MatchToken<R>>>) -> Vec<Arc<dyn MatchToken<R>>> {
+        let mut result: Vec<Arc<dyn MatchToken<R>>> = Vec::new();
+        let mut words: Vec<Arc<dyn MatchToken<R>>> = Vec::new();
+
+        for alternative in alternatives.into_iter().map(Some).chain([None]) {
+            if let Some(alternative) = &alternative {
+                if let MatcherShape::Literal(_) = alternative.shape() {
+                    words.push(alternative.clone());
+                    continue;
+                }
+            }
+
+            // End of a run of strings
+            if words.len() >= 2 {
+                let values: Vec<&str> = words
+                    .iter()
+                    .filter_map(|word| match word.shape() {
+                        MatcherShape::Literal(value) => Some(value),
+                        _ => None,
+                    })
+                    .collect();
+                let key = format!("keywords {:?}", values);
+                let set = KeywordSetMatcher::new(&values);
+                result.push(self.intern(key, || Arc::new(set)));
+            } else {
+                result.append(&mut words);
+            }
+            words.clear();
+            result.extend(alternative);
+        }
+        result
+    }
+
+    /// Identifies a combination of unique matchers.
+    fn key(kind: &str, children: &[Arc<dyn MatchToken<R>>]) -> String {
+        let addresses: Vec<String> = children.iter().map(|child| format!("{:p}", Arc::as_ptr(child) as *const u8)).collect();
+        format!("{} {}", kind, addresses.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::{InNotation, Location, Notation, Rule, StringCharReader};
+    use crate::{choice, seq, word};
+
+    use super::*;
+
+    #[test]
+    fn test_optimizer() {
+        // Nested sequences and choices, and duplicate nodes
+        let rule: Rule<StringCharReader> = choice!(
+            seq!(seq!(word!("a"), word!("b")), word!("c"), Rule::range('0', '9')),
+            choice!(word!("x").at_least(1), seq!(word!("x")).at_least(1)),
+            word!("a") * 2
+        );
+
+        let optimized = Optimizer::new().optimize(rule.matcher());
+        assert_eq!(
+            InNotation(optimized.as_ref(), Notation::Peg).to_string(),
+            "\"abc\" [0-9] / \"x\"+ / \"x\"+ / \"aa\""
+        );
+        let MatcherShape::Choice(alternatives) = optimized.shape() else {
+            panic!("expected a choice");
+        };
+        assert!(Arc::ptr_eq(&alternatives[1], &alternatives[2]));
+
+        let mut reader = StringCharReader::new("abc7");
+        assert_eq!(optimized.test(&Location::beginning(), &mut reader).unwrap().unwrap().len(), 4);
+        let mut reader = StringCharReader::new("aa");
+        assert_eq!(optimized.test(&Location::beginning(), &mut reader).unwrap().unwrap().len(), 2);
+
+        // Keywords
+        let rule: Rule<StringCharReader> =
+            choice!(word!("in"), word!("int"), Rule::range('0', '9'), word!("for"), word!("fn"), word!("x"));
+        let optimized = Optimizer::new().optimize(rule.matcher());
+        let MatcherShape::Choice(alternatives) = optimized.shape() else {
+            panic!("expected a choice");
+        };
+        assert_eq!(alternatives.len(), 3);
+        assert_eq!(alternatives[2].to_string(), "(\"for\" | \"fn\" | \"x\")");
+
+        let mut reader = StringCharReader::new("int");
+        assert_eq!(optimized.test(&Location::beginning(), &mut reader).unwrap().unwrap().len(), 2);
+    }
+}
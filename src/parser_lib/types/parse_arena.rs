@@ -0,0 +1,135 @@
+use std::{collections::HashMap, ops::Range};
+
+use super::{ParseNode, Span};
+
+/// Index of a node in a `ParseArena`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+#[derive(Debug, Clone)]
+struct ArenaNode {
+    /// Index of the name of the rule.
+    rule: usize,
+    span: Span,
+    /// Indexes of the children in the children of the arena.
+    children: Range<usize>,
+}
+
+/// Storage of the nodes of the parse trees built during a parse, see `Grammar::parse_arena`.
+///
+/// The nodes are appended to a few vectors instead of being allocated one by one, and the names of the rules are stored
+/// once. They are never removed: the nodes of the rules undone by a backtrack stay in the arena, unreachable from the root.
+/// Since they can't change, a node can be shared by several parents, like the results of memoized rules.
+#[derive(Debug, Clone, Default)]
+pub struct ParseArena {
+    /// Names of the rules, and their indexes.
+    rules: Vec<String>,
+    rule_indexes: HashMap<String, usize>,
+    nodes: Vec<ArenaNode>,
+    /// Children of all the nodes, those of a node being contiguous.
+    children: Vec<NodeId>,
+    root: Option<NodeId>,
+}
+
+impl ParseArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a node of the rule containing the given nodes.
+    pub fn push(&mut self, rule: &str, span: Span, children: &[NodeId]) -> NodeId {
+        let rule = match self.rule_indexes.get(rule) {
+            Some(index) => *index,
+            None => {
+                self.rules.push(rule.to_string());
+                self.rule_indexes.insert(rule.to_string(), self.rules.len() - 1);
+                self.rules.len() - 1
+            }
+        };
+
+        let start = self.children.len();
+        self.children.extend_from_slice(children);
+        self.nodes.push(ArenaNode { rule, span, children: start..self.children.len() });
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Adds the nodes of the tree, and returns the node of its root.
+    pub fn insert(&mut self, node: &ParseNode) -> NodeId {
+        let children: Vec<NodeId> = node.children().iter().map(|child| self.insert(child)).collect();
+        self.push(node.rule(), node.span().clone(), &children)
+    }
+
+    /// Returns the root of the tree built by the parse, if it matched.
+    pub fn root(&self) -> Option<NodeId> {
+        self.root
+    }
+
+    pub(crate) fn set_root(&mut self, root: NodeId) {
+        self.root = Some(root);
+    }
+
+    /// Returns the name of the rule of the node.
+    ///
+    /// Panics if the node is not in the arena, like the other accessors.
+    pub fn rule(&self, node: NodeId) -> &str {
+        &self.rules[self.nodes[node.0].rule]
+    }
+
+    pub fn span(&self, node: NodeId) -> &Span {
+        &self.nodes[node.0].span
+    }
+
+    pub fn children(&self, node: NodeId) -> &[NodeId] {
+        &self.children[self.nodes[node.0].children.clone()]
+    }
+
+    /// Returns the text matched by the node in the given source.
+    pub fn text<'a>(&self, node: NodeId, source: &'a str) -> &'a str {
+        let span = self.span(node);
+        source.get(span.start().byte_offset()..span.end().byte_offset()).unwrap_or_default()
+    }
+
+    /// Returns the number of nodes in the arena, including the ones that are not in the tree.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Copies the node and its descendants out of the arena.
+    pub fn to_node(&self, node: NodeId) -> ParseNode {
+        let children = self.children(node).iter().map(|child| self.to_node(*child)).collect();
+        ParseNode::new(self.rule(node), self.span(node).clone(), children)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::Location;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_arena() {
+        let source = "ab";
+        let mut arena = ParseArena::new();
+        let a = arena.push("letter", Span::new(Location::beginning(), Location::new(1, 2, 1)), &[]);
+        let b = arena.push("letter", Span::new(Location::new(1, 2, 1), Location::new(1, 3, 2)), &[]);
+        let word = arena.push("word", Span::new(Location::beginning(), Location::new(1, 3, 2)), &[a, b]);
+
+        assert_eq!(arena.len(), 3);
+        assert_eq!(arena.rules.len(), 2);
+        assert_eq!(arena.children(word), [a, b]);
+        assert_eq!(arena.rule(b), "letter");
+        assert_eq!(arena.text(b, source), "b");
+        assert_eq!(arena.root(), None);
+
+        // Copying a tree out of the arena and back gives the same tree
+        let node = arena.to_node(word);
+        assert_eq!(node.to_sexp(source), "(word (letter \"a\") (letter \"b\"))");
+        let copy = arena.insert(&node);
+        assert_eq!(arena.to_node(copy), node);
+    }
+}
@@ -0,0 +1,634 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt::{Debug, Formatter},
+    sync::Arc,
+    time::Instant,
+};
+
+use super::{
+    incremental::Incremental, profile::Profiler, Checkpoint, Location, NodeId, ParseArena, ParseInfo, ParseOptions,
+    ParseProfile, ParseResult, ParserError, Recovery, Rewind, SourceId, Span, SyntaxError, TraceEvent, TraceLevel,
+    TraceOutcome, Tracer,
+};
+
+/// Default maximum number of nested rules, low enough to fit in the stack of a new thread (2 MiB), even in debug builds.
+pub const DEFAULT_RECURSION_LIMIT: usize = 256;
+
+thread_local! {
+    /// State of the parse running on the current thread.
+    static CONTEXT: Cell<ParseContext> = const { Cell::new(ParseContext::new()) };
+    /// Tracer of the parse running on the current thread, if any. It isn't `Copy`, so it is kept apart.
+    static TRACER: RefCell<Option<Tracer>> = const { RefCell::new(None) };
+    /// Statistics of the rules of the parse running on the current thread, if it is profiled.
+    static PROFILER: RefCell<Option<Profiler>> = const { RefCell::new(None) };
+    /// Results of the named rules of the parse running on the current thread, if it is memoized.
+    static MEMO: RefCell<Option<Memo>> = const { RefCell::new(None) };
+    /// Nodes of the rules matched by the parse running on the current thread, if it builds a tree.
+    static NODES: RefCell<Option<Nodes>> = const { RefCell::new(None) };
+    /// Tokens finished by the parse running on the current thread. None outside of a parse.
+    static TOKENS: RefCell<Option<Vec<PendingToken>>> = const { RefCell::new(None) };
+    /// Nodes of a previous tree that the parse running on the current thread can reuse, if it is incremental.
+    static INCREMENTAL: RefCell<Option<Incremental>> = const { RefCell::new(None) };
+    /// End (exclusive index) of the input read by the rules being matched, if the parse is incremental.
+    static READ_END: Cell<usize> = const { Cell::new(0) };
+    /// Spans of the labeled children matched by the sequences of the rules being matched, with their label.
+    static CAPTURES: RefCell<Vec<(Arc<str>, Span)>> = const { RefCell::new(Vec::new()) };
+    /// Index of the first capture of the rule being matched, since a rule doesn't see the captures of its parents.
+    static CAPTURES_START: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Rule (the address of its matcher) and position of a cached result.
+type MemoKey = (usize, SourceId, usize);
+
+/// Cached results of the named rules, by rule and position.
+#[derive(Debug, Default)]
+struct Memo {
+    results: HashMap<MemoKey, Cached>,
+    /// Number of times a left-recursive seed was used. The results computed while it changes depend on the seed.
+    seeds_used: usize,
+}
+
+/// Result of a rule, with what matching it produced.
+#[derive(Debug, Clone)]
+struct Cached {
+    result: Option<ParseInfo>,
+    /// Nodes pushed by the rule, if a tree is built.
+    nodes: Vec<NodeId>,
+    /// Tokens finished by the rule.
+    tokens: Vec<PendingToken>,
+    /// End (exclusive index) of the input read by the rule.
+    read_end: usize,
+}
+
+/// Nodes of the tree being built.
+#[derive(Debug, Default)]
+struct Nodes {
+    arena: ParseArena,
+    /// Nodes that are not in a parent yet, in input order. The nodes pushed by a failed match are removed.
+    pending: Vec<NodeId>,
+}
+
+/// Number of nodes and tokens pushed, to remove the ones pushed after it with `rollback`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Mark {
+    nodes: usize,
+    tokens: usize,
+}
+
+/// Gives a finished token to its sink, with its span.
+pub(crate) type Emitter = Arc<dyn Fn(&Span) + Send + Sync>;
+
+/// Token finished by a `TokenMatcher`.
+///
+/// It is only given to its sink when the parse matches: until then, a backtrack can still undo it.
+#[derive(Clone)]
+pub(crate) struct PendingToken {
+    span: Span,
+    /// Position of the reader before the token consumed its input. None if it didn't consume anything, like the
+    /// tokens of a cached result.
+    checkpoint: Option<Checkpoint>,
+    emit: Option<Emitter>,
+}
+
+/// The emitter is written as its address, like in `TokenMatcher`.
+impl Debug for PendingToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let emit = self.emit.as_ref().map(|emit| format!("{:p}", Arc::as_ptr(emit)));
+        f.debug_struct("PendingToken")
+            .field("span", &self.span)
+            .field("checkpoint", &self.checkpoint)
+            .field("emit", &emit)
+            .finish()
+    }
+}
+
+/// State shared by the matchers during a parse, like the number of nested rules.
+///
+/// It is stored per thread, so that a grammar can be used by several threads at once.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ParseContext {
+    depth: usize,
+    recursion_limit: usize,
+    /// Steps that can still be done, and the initial number of steps. If None, there is no limit.
+    fuel: Option<(usize, usize)>,
+    /// Maximum number of error nodes. If None, there is no limit.
+    max_errors: Option<usize>,
+    /// Number of error nodes, and the index of the last one.
+    errors: (usize, Option<usize>),
+    recovery: Recovery,
+    trace_level: TraceLevel,
+}
+
+impl ParseContext {
+    const fn new() -> Self {
+        Self {
+            depth: 0,
+            recursion_limit: DEFAULT_RECURSION_LIMIT,
+            fuel: None,
+            max_errors: None,
+            errors: (0, None),
+            recovery: Recovery::Skip,
+            trace_level: TraceLevel::Rules,
+        }
+    }
+
+    /// Runs the parse with the given options, then restores the previous ones.
+    ///
+    /// A step is matching a named rule, or trying another alternative of a choice after a failure (a backtrack).
+    /// When no step is left, the matchers return an `OutOfFuel` error, so that a pathological input can't hang the parser.
+    ///
+    /// The tokens finished by the parse are given to their sinks at the end, if it matches.
+    pub fn with_options(options: &ParseOptions, f: impl FnOnce() -> ParseResult) -> ParseResult {
+        let previous = CONTEXT.get();
+        let mut previous_tracer = TRACER.replace(options.tracer().cloned());
+        let mut previous_memo = MEMO.replace(options.memoize().then(Memo::default));
+        let mut previous_tokens = TOKENS.replace(Some(Vec::new()));
+        // The captures of the root are released at the end of the parse, like the ones of a rule
+        let captures = Self::enter_captures();
+        let _restore = Restore(move |context: &mut ParseContext| {
+            context.recursion_limit = previous.recursion_limit;
+            context.fuel = previous.fuel;
+            context.max_errors = previous.max_errors;
+            context.errors = previous.errors;
+            context.recovery = previous.recovery;
+            context.trace_level = previous.trace_level;
+            TRACER.set(previous_tracer.take());
+            MEMO.set(previous_memo.take());
+            TOKENS.set(previous_tokens.take());
+            Self::exit_captures(captures);
+        });
+
+        update(|context| {
+            context.recursion_limit = options.recursion_limit();
+            context.fuel = options.fuel().map(|fuel| (fuel, fuel));
+            context.max_errors = options.max_errors();
+            context.errors = (0, None);
+            context.recovery = options.recovery();
+            context.trace_level = options.trace_level();
+        });
+        let result = f();
+        if let Ok(Some(_)) = result {
+            for token in TOKENS.take().unwrap_or_default() {
+                if let Some(emit) = token.emit {
+                    emit(&token.span);
+                }
+            }
+        }
+        result
+    }
+
+    /// Uses a step, or returns an `OutOfFuel` error if there is none left.
+    pub fn step(loc: &Location) -> Result<(), ParserError> {
+        let mut context = CONTEXT.get();
+        match &mut context.fuel {
+            None => Ok(()),
+            Some((0, limit)) => Err(SyntaxError::OutOfFuel { limit: *limit, location: *loc }.into()),
+            Some((left, _)) => {
+                *left -= 1;
+                CONTEXT.set(context);
+                Ok(())
+            }
+        }
+    }
+
+    /// Counts an error node, or returns a `TooManyErrors` error if there are more than allowed.
+    ///
+    /// An error is only counted if it is after the last one: when the parse backtracks before an error and skips it
+    /// again, it is the same error.
+    pub fn error(loc: &Location) -> Result<(), ParserError> {
+        let mut context = CONTEXT.get();
+        let (count, last) = context.errors;
+        if last.is_some_and(|last| loc.index() <= last) {
+            return Ok(());
+        }
+        context.errors = (count + 1, Some(loc.index()));
+        CONTEXT.set(context);
+        match context.max_errors {
+            Some(limit) if count + 1 > limit => Err(SyntaxError::TooManyErrors { limit, location: *loc }.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns whether the error productions skip the garbled input, see `ParseOptions::with_recovery`.
+    pub fn recovers() -> bool {
+        CONTEXT.get().recovery == Recovery::Skip
+    }
+
+    /// Goes back to try another alternative after a failure. It uses a step, and is recorded by the profiler.
+    pub fn backtrack(loc: &Location) -> Result<(), ParserError> {
+        PROFILER.with_borrow_mut(|profiler| {
+            if let Some(profiler) = profiler {
+                profiler.backtrack();
+            }
+        });
+        Self::step(loc)
+    }
+
+    /// Runs the function while recording the statistics of the named rules.
+    pub fn profiled<T>(f: impl FnOnce() -> T) -> (T, ParseProfile) {
+        let mut previous = PROFILER.replace(Some(Profiler::default()));
+        let restore = Restore(move |_: &mut ParseContext| {
+            PROFILER.set(previous.take());
+        });
+        let result = f();
+        let profiler = PROFILER.take().unwrap_or_default();
+        drop(restore);
+        (result, profiler.finish())
+    }
+
+    /// Returns the cached result of the rule at the given location, or matches it and caches the result if memoized.
+    ///
+    /// The rule is identified by the address of its matcher. Errors are not cached.
+    pub fn memoized(
+        rule: usize,
+        loc: &Location,
+        f: impl FnOnce() -> Result<Option<ParseInfo>, ParserError>,
+    ) -> Result<Option<ParseInfo>, ParserError> {
+        let key = (rule, loc.source(), loc.index());
+        let cached = MEMO.with_borrow(|memo| memo.as_ref().map(|memo| (memo.results.get(&key).cloned(), memo.seeds_used)));
+        let seeds_used = match cached {
+            None => return f(),
+            Some((Some(Cached { result, nodes, tokens, read_end }), _)) => {
+                Self::replay(nodes);
+                Self::replay_tokens(tokens);
+                Self::read(read_end);
+                return Ok(result);
+            }
+            Some((None, seeds_used)) => seeds_used,
+        };
+
+        // The input read by the rule is cached too: in an incremental parse, using the result reads the same input
+        let mark = Self::mark();
+        let previous_read_end = READ_END.replace(loc.index());
+        let result = f();
+        let read_end = READ_END.get();
+        READ_END.set(read_end.max(previous_read_end));
+
+        let result = result?;
+        Self::cache(key, seeds_used, &result, mark, read_end);
+        Ok(result)
+    }
+
+    /// Caches the result of a rule and the nodes and tokens pushed since the mark, unless a seed was used since
+    /// `seeds_used`.
+    fn cache(key: MemoKey, seeds_used: usize, result: &Option<ParseInfo>, mark: Mark, read_end: usize) {
+        let nodes = Self::nodes_since(mark).unwrap_or_default();
+        let tokens = Self::tokens_since(mark);
+        MEMO.with_borrow_mut(|memo| {
+            if let Some(memo) = memo.as_mut().filter(|memo| memo.seeds_used == seeds_used) {
+                memo.results.insert(key, Cached { result: result.clone(), nodes, tokens, read_end });
+            }
+        });
+    }
+
+    /// Tells that the result of a left-recursive rule being grown was used, so the current results can't be cached.
+    pub fn seed_used() {
+        MEMO.with_borrow_mut(|memo| {
+            if let Some(memo) = memo {
+                memo.seeds_used += 1;
+            }
+        });
+        INCREMENTAL.with_borrow_mut(|incremental| {
+            if let Some(incremental) = incremental {
+                incremental.seed_used();
+            }
+        });
+    }
+
+    /// Matches a named rule, and reports it to the profiler and the tracer, if any.
+    ///
+    /// If a tree is built, the nodes pushed by the rule are put in a node of the rule when it matches.
+    pub fn rule(
+        rule: &str,
+        loc: &Location,
+        f: impl FnOnce() -> Result<Option<ParseInfo>, ParserError>,
+    ) -> Result<Option<ParseInfo>, ParserError> {
+        if let Some(info) = Self::reuse(rule, loc) {
+            return Ok(Some(info));
+        }
+
+        let mark = Self::mark();
+        let reads = Self::enter_reads(loc);
+        let captures = Self::enter_captures();
+        let profiled = PROFILER.with_borrow_mut(|profiler| {
+            profiler.as_mut().map(|profiler| profiler.enter(rule)).is_some()
+        });
+
+        let start = profiled.then(Instant::now);
+        let result = Self::traced(rule, loc, f);
+        if let Some(start) = start {
+            PROFILER.with_borrow_mut(|profiler| {
+                if let Some(profiler) = profiler {
+                    profiler.exit(start, matches!(result, Ok(Some(_))));
+                }
+            });
+        }
+
+        Self::exit_reads(rule, reads, &result);
+        Self::exit_captures(captures);
+        match &result {
+            Ok(Some(info)) => Self::wrap(rule, info, mark),
+            _ => Self::truncate(mark),
+        }
+        result
+    }
+
+    /// Runs the function as an incremental parse, which reuses the nodes of a previous tree and records what it reads.
+    pub fn with_incremental<T>(incremental: Incremental, f: impl FnOnce() -> T) -> (T, Incremental) {
+        let mut previous = INCREMENTAL.replace(Some(incremental));
+        let previous_read_end = READ_END.replace(0);
+        let restore = Restore(move |_: &mut ParseContext| {
+            INCREMENTAL.set(previous.take());
+            READ_END.set(previous_read_end);
+        });
+        let result = f();
+        let incremental = INCREMENTAL.take().unwrap_or_default();
+        drop(restore);
+        (result, incremental)
+    }
+
+    /// Tells that the input was read up to the given index (exclusive), for the incremental parse.
+    pub fn read(end: usize) {
+        READ_END.set(READ_END.get().max(end));
+    }
+
+    /// Pushes the node of the previous tree matched by the rule at the location if it can be reused, and returns its result.
+    fn reuse(rule: &str, loc: &Location) -> Option<ParseInfo> {
+        let (node, read_end) = INCREMENTAL.with_borrow_mut(|incremental| incremental.as_mut()?.reuse(rule, loc))?;
+        Self::read(read_end);
+        let len = node.span().end().index() - node.span().start().index();
+        let info = ParseInfo::new(node.span().clone(), len);
+        NODES.with_borrow_mut(|nodes| {
+            if let Some(nodes) = nodes {
+                let id = nodes.arena.insert(&node);
+                nodes.pending.push(id);
+            }
+        });
+        Some(info)
+    }
+
+    /// Starts recording the input read by a rule, if the parse is incremental.
+    ///
+    /// Returns the end of the input read before the rule, and the number of seeds used.
+    fn enter_reads(loc: &Location) -> Option<(usize, usize)> {
+        let seeds_used = INCREMENTAL.with_borrow(|incremental| incremental.as_ref().map(Incremental::seeds_used))?;
+        Some((READ_END.replace(loc.index()), seeds_used))
+    }
+
+    /// Records the input read by a rule since `enter_reads`, if the parse is incremental.
+    fn exit_reads(rule: &str, reads: Option<(usize, usize)>, result: &Result<Option<ParseInfo>, ParserError>) {
+        let Some((previous_read_end, seeds_used)) = reads else {
+            return;
+        };
+
+        let read_end = READ_END.get();
+        if let Ok(Some(info)) = result {
+            INCREMENTAL.with_borrow_mut(|incremental| {
+                if let Some(incremental) = incremental {
+                    let (start, end) = (info.start().index(), info.end().index());
+                    incremental.matched(rule, start, end, read_end, seeds_used);
+                }
+            });
+        }
+        READ_END.set(read_end.max(previous_read_end));
+    }
+
+    /// Starts the captures of a rule, hiding the ones of its parents so that its result only depends on its position.
+    ///
+    /// Returns the number of captures and the first capture of the parent.
+    fn enter_captures() -> (usize, usize) {
+        let len = Self::captures_mark();
+        (len, CAPTURES_START.replace(len))
+    }
+
+    /// Removes the captures of a rule since `enter_captures`, and restores the ones of its parent.
+    fn exit_captures((len, start): (usize, usize)) {
+        Self::release_captures(len);
+        CAPTURES_START.set(start);
+    }
+
+    /// Returns the number of captures, to remove the ones added after it with `release_captures`.
+    pub fn captures_mark() -> usize {
+        CAPTURES.with_borrow(Vec::len)
+    }
+
+    /// Removes the captures added since the mark, because what captured them is not part of the match.
+    pub fn release_captures(mark: usize) {
+        CAPTURES.with_borrow_mut(|captures| captures.truncate(mark));
+    }
+
+    /// Records the span of a labeled child of a sequence, so that the next matchers of the rule can use it.
+    pub fn capture(label: &Arc<str>, span: &Span) {
+        CAPTURES.with_borrow_mut(|captures| captures.push((label.clone(), span.clone())));
+    }
+
+    /// Returns the last span captured with the label in the rule being matched, if any.
+    pub fn captured(label: &str) -> Option<Span> {
+        let start = CAPTURES_START.get();
+        CAPTURES.with_borrow(|captures| {
+            let captures = captures.get(start..)?;
+            captures.iter().rev().find(|(captured, _)| **captured == *label).map(|(_, span)| span.clone())
+        })
+    }
+
+    /// Matches a named rule, and sends its entry and exit to the tracer, if any and if the trace level allows it.
+    fn traced(
+        rule: &str,
+        loc: &Location,
+        f: impl FnOnce() -> Result<Option<ParseInfo>, ParserError>,
+    ) -> Result<Option<ParseInfo>, ParserError> {
+        let Some(tracer) = TRACER.with_borrow(Clone::clone) else {
+            return f();
+        };
+
+        let ParseContext { depth, trace_level, .. } = CONTEXT.get();
+        let send = |event: TraceEvent| {
+            if event.level() <= trace_level {
+                tracer.send(&event);
+            }
+        };
+        send(TraceEvent::Enter { rule, location: *loc, depth });
+        let result = f();
+        let outcome = match &result {
+            Ok(Some(info)) => TraceOutcome::Matched(info.span().clone()),
+            Ok(None) => TraceOutcome::NoMatch,
+            Err(err) => TraceOutcome::Error(err.clone()),
+        };
+        send(TraceEvent::Exit { rule, location: *loc, depth, outcome });
+        result
+    }
+
+    /// Replaces the nodes pushed since the mark by a node of the rule containing them, if a tree is built.
+    fn wrap(rule: &str, info: &ParseInfo, mark: Mark) {
+        NODES.with_borrow_mut(|nodes| {
+            if let Some(nodes) = nodes {
+                let id = nodes.arena.push(rule, info.span().clone(), &nodes.pending[mark.nodes..]);
+                nodes.pending.truncate(mark.nodes);
+                nodes.pending.push(id);
+            }
+        });
+    }
+
+    /// Runs the function while building the tree of the named rules.
+    ///
+    /// Returns the arena containing the nodes, and the nodes that have no parent.
+    pub fn with_nodes<T>(f: impl FnOnce() -> T) -> (T, ParseArena, Vec<NodeId>) {
+        let mut previous = NODES.replace(Some(Nodes::default()));
+        let restore = Restore(move |_: &mut ParseContext| {
+            NODES.set(previous.take());
+        });
+        let result = f();
+        let nodes = NODES.take().unwrap_or_default();
+        drop(restore);
+        (result, nodes.arena, nodes.pending)
+    }
+
+    /// Returns the number of nodes without parent and of tokens, to remove the ones pushed after it with `rollback`.
+    pub fn mark() -> Mark {
+        Mark {
+            nodes: NODES.with_borrow(|nodes| nodes.as_ref().map_or(0, |nodes| nodes.pending.len())),
+            tokens: TOKENS.with_borrow(|tokens| tokens.as_ref().map_or(0, Vec::len)),
+        }
+    }
+
+    /// Removes the nodes and tokens pushed since the mark, because what produced them is not part of the match.
+    ///
+    /// The input consumed by the removed tokens is given back: the reader is rewound to before the first one.
+    pub fn rollback(mark: Mark, reader: &mut impl Rewind) -> Result<(), ParserError> {
+        Self::rewind(mark, reader)?;
+        Self::truncate(mark);
+        Ok(())
+    }
+
+    /// Removes the nodes and tokens pushed since the mark, without rewinding the reader.
+    fn truncate(mark: Mark) {
+        NODES.with_borrow_mut(|nodes| {
+            if let Some(nodes) = nodes {
+                nodes.pending.truncate(mark.nodes);
+            }
+        });
+        TOKENS.with_borrow_mut(|tokens| {
+            if let Some(tokens) = tokens {
+                tokens.truncate(mark.tokens);
+            }
+        });
+    }
+
+    /// Moves the reader back to before the first token pushed since the mark, without removing anything.
+    ///
+    /// It is used to match again from the same position, like when growing a left-recursive rule.
+    pub fn rewind(mark: Mark, reader: &mut impl Rewind) -> Result<(), ParserError> {
+        let checkpoint = TOKENS.with_borrow(|tokens| {
+            let tokens = tokens.as_ref()?.get(mark.tokens..)?;
+            tokens.iter().find_map(|token| token.checkpoint)
+        });
+        match checkpoint {
+            Some(checkpoint) => reader.rewind(checkpoint),
+            None => Ok(()),
+        }
+    }
+
+    /// Removes the nodes and tokens pushed between the two marks, because they were replaced by the ones pushed after
+    /// them.
+    pub fn discard(from: Mark, to: Mark) {
+        NODES.with_borrow_mut(|nodes| {
+            if let Some(nodes) = nodes {
+                nodes.pending.drain(from.nodes..to.nodes);
+            }
+        });
+        TOKENS.with_borrow_mut(|tokens| {
+            if let Some(tokens) = tokens {
+                tokens.drain(from.tokens..to.tokens);
+            }
+        });
+    }
+
+    /// Returns the nodes pushed since the mark, or None if no tree is built.
+    pub fn nodes_since(mark: Mark) -> Option<Vec<NodeId>> {
+        NODES.with_borrow(|nodes| nodes.as_ref().map(|nodes| nodes.pending[mark.nodes..].to_vec()))
+    }
+
+    /// Adds a node of the rule containing the nodes pushed since the mark, without pushing it, if a tree is built.
+    pub fn node_since(rule: &str, info: &ParseInfo, mark: Mark) -> Option<NodeId> {
+        NODES.with_borrow_mut(|nodes| {
+            let nodes = nodes.as_mut()?;
+            Some(nodes.arena.push(rule, info.span().clone(), &nodes.pending[mark.nodes..]))
+        })
+    }
+
+    /// Pushes nodes produced by an earlier match of the same rules, if a tree is built.
+    pub fn replay(replayed: impl IntoIterator<Item = NodeId>) {
+        NODES.with_borrow_mut(|nodes| {
+            if let Some(nodes) = nodes {
+                nodes.pending.extend(replayed);
+            }
+        });
+    }
+
+    /// Adds a token finished by a `TokenMatcher`, with the position of the reader before it consumed its input, if it
+    /// did.
+    ///
+    /// Outside of a parse, nothing can undo the token, so it is given to its sink right away.
+    pub fn finish_token(span: &Span, checkpoint: Option<Checkpoint>, emit: Option<&Emitter>) {
+        let token = PendingToken { span: span.clone(), checkpoint, emit: emit.cloned() };
+        let token = TOKENS.with_borrow_mut(|tokens| match tokens {
+            Some(tokens) => {
+                tokens.push(token);
+                None
+            }
+            None => Some(token),
+        });
+        if let Some(PendingToken { span, emit: Some(emit), .. }) = token {
+            emit(&span);
+        }
+    }
+
+    /// Returns the tokens pushed since the mark.
+    pub fn tokens_since(mark: Mark) -> Vec<PendingToken> {
+        TOKENS.with_borrow(|tokens| {
+            tokens.as_ref().and_then(|tokens| tokens.get(mark.tokens..)).map(<[_]>::to_vec).unwrap_or_default()
+        })
+    }
+
+    /// Pushes tokens finished by an earlier match of the same rules. Their input is not consumed again.
+    pub fn replay_tokens(replayed: impl IntoIterator<Item = PendingToken>) {
+        TOKENS.with_borrow_mut(|tokens| {
+            if let Some(tokens) = tokens {
+                tokens.extend(replayed.into_iter().map(|token| PendingToken { checkpoint: None, ..token }));
+            }
+        });
+    }
+
+    /// Matches a nested rule, or returns a `RecursionLimit` error if there are too many of them.
+    ///
+    /// Without limit, deeply nested inputs would overflow the stack, which can't be recovered from.
+    pub fn nested<T>(loc: &Location, f: impl FnOnce() -> Result<T, ParserError>) -> Result<T, ParserError> {
+        let context = CONTEXT.get();
+        if context.depth >= context.recursion_limit {
+            return Err(SyntaxError::RecursionLimit {
+                limit: context.recursion_limit,
+                location: *loc,
+            }.into());
+        }
+
+        let _restore = Restore(|context: &mut ParseContext| context.depth -= 1);
+        update(|context| context.depth += 1);
+        f()
+    }
+}
+
+fn update(f: impl FnOnce(&mut ParseContext)) {
+    let mut context = CONTEXT.get();
+    f(&mut context);
+    CONTEXT.set(context);
+}
+
+/// Updates the context when dropped, even if the matcher returns early or panics.
+struct Restore<F: FnMut(&mut ParseContext)>(F);
+
+impl<F: FnMut(&mut ParseContext)> Drop for Restore<F> {
+    fn drop(&mut self) {
+        update(&mut self.0);
+    }
+}
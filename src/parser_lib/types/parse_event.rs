@@ -0,0 +1,69 @@
+use super::{ParseNode, ParserError, Span};
+
+/// Event of the stream of a parse, to build a custom tree or to process the result without building one.
+///
+/// The events of a node are a `StartNode`, the events of its content in input order, then a `FinishNode`.
+/// The text matched by a node outside of its nested nodes is given as `Token`s.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseEvent<'a> {
+    /// A named rule matched, its content follows.
+    StartNode(&'a str),
+    /// Text matched directly by the current rule.
+    Token(Span),
+    /// The content of the current rule is over.
+    FinishNode,
+    /// The parse failed: no more event follows.
+    Error(ParserError),
+}
+
+impl ParseNode {
+    /// Sends the events of the node and its children, see `ParseEvent`.
+    pub fn events<'a>(&'a self, sink: &mut impl FnMut(ParseEvent<'a>)) {
+        sink(ParseEvent::StartNode(self.rule()));
+        let mut start = *self.span().start();
+        for child in self.children() {
+            if child.span().start().index() > start.index() {
+                sink(ParseEvent::Token(Span::new(start, *child.span().start())));
+            }
+            child.events(sink);
+            start = *child.span().end();
+        }
+        if self.span().end().index() > start.index() {
+            sink(ParseEvent::Token(Span::new(start, *self.span().end())));
+        }
+        sink(ParseEvent::FinishNode);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_lib::Location;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_node_events() {
+        // "f(1)": a call containing a name and a number
+        let name = ParseNode::new("name", Span::new(Location::beginning(), Location::new(1, 2, 1)), Vec::new());
+        let number = ParseNode::new("number", Span::new(Location::new(1, 3, 2), Location::new(1, 4, 3)), Vec::new());
+        let call = ParseNode::new("call", Span::new(Location::beginning(), Location::new(1, 5, 4)), vec![name, number]);
+
+        let mut events = Vec::new();
+        call.events(&mut |event| events.push(event));
+        assert_eq!(
+            events,
+            [
+                ParseEvent::StartNode("call"),
+                ParseEvent::StartNode("name"),
+                ParseEvent::Token(Span::new(Location::beginning(), Location::new(1, 2, 1))),
+                ParseEvent::FinishNode,
+                ParseEvent::Token(Span::new(Location::new(1, 2, 1), Location::new(1, 3, 2))),
+                ParseEvent::StartNode("number"),
+                ParseEvent::Token(Span::new(Location::new(1, 3, 2), Location::new(1, 4, 3))),
+                ParseEvent::FinishNode,
+                ParseEvent::Token(Span::new(Location::new(1, 4, 3), Location::new(1, 5, 4))),
+                ParseEvent::FinishNode,
+            ]
+        );
+    }
+}
@@ -1,17 +1,48 @@
-use std::fmt::Display;
+use std::{fmt::Display, sync::Arc};
 
 use super::{Location, Span};
 
-#[derive(Debug, PartialEq)]
+/// Label and span of a labeled child of a sequence.
+pub type Field = (Arc<str>, Span);
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Information about a successful parse
 pub struct ParseInfo {
     span: Span,
     len: usize,
+    /// Index of the alternative that matched, if the matcher is a choice.
+    alternative: Option<usize>,
+    /// Span of each match of the repeated matcher, if the matcher is a repetition.
+    repetitions: Option<Box<[Span]>>,
+    /// Span of each labeled child, if the matcher is a sequence with labels.
+    fields: Option<Box<[Field]>>,
 }
 
 impl ParseInfo {
     pub fn new(span: Span, len: usize) -> Self {
-        Self { span, len }
+        Self {
+            span,
+            len,
+            alternative: None,
+            repetitions: None,
+            fields: None,
+        }
+    }
+
+    pub fn with_alternative(mut self, alternative: usize) -> Self {
+        self.alternative = Some(alternative);
+        self
+    }
+
+    pub fn with_repetitions(mut self, repetitions: Vec<Span>) -> Self {
+        self.repetitions = Some(repetitions.into_boxed_slice());
+        self
+    }
+
+    pub fn with_fields(mut self, fields: Vec<Field>) -> Self {
+        self.fields = Some(fields.into_boxed_slice());
+        self
     }
 
     pub fn span(&self) -> &Span {
@@ -23,6 +54,11 @@ impl ParseInfo {
         self.len
     }
 
+    #[allow(unused)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
     #[allow(unused)]
     pub fn start(&self) -> &Location {
         self.span.start()
@@ -31,6 +67,29 @@ impl ParseInfo {
     pub fn end(&self) -> &Location {
         self.span.end()
     }
+
+    /// Returns the index of the alternative that matched, if the matcher is a choice.
+    ///
+    /// The indices are those of the matcher that was tested: an optimized rule can have merged some alternatives.
+    pub fn alternative(&self) -> Option<usize> {
+        self.alternative
+    }
+
+    /// Returns the span of each repetition, in order, if the matcher is a repetition. Their number is the repetition
+    /// count.
+    pub fn repetitions(&self) -> Option<&[Span]> {
+        self.repetitions.as_deref()
+    }
+
+    /// Returns the label and the span of each labeled child, in order, if the matcher is a sequence with labels.
+    pub fn fields(&self) -> Option<&[Field]> {
+        self.fields.as_deref()
+    }
+
+    /// Returns the span of the child with the given label, if the matcher is a sequence with this label.
+    pub fn field(&self, label: &str) -> Option<&Span> {
+        self.fields()?.iter().find(|(name, _)| **name == *label).map(|(_, span)| span)
+    }
 }
 
 impl Display for ParseInfo {
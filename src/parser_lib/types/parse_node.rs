@@ -0,0 +1,223 @@
+use std::fmt::Write;
+
+use super::{Location, Span};
+use crate::parser_lib::ERROR_RULE;
+
+/// Node of a parse tree: a named rule that matched, and the named rules it contains.
+///
+/// The nodes don't store the text they matched, it is taken from the source when needed.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseNode {
+    rule: String,
+    span: Span,
+    children: Vec<ParseNode>,
+}
+
+impl ParseNode {
+    pub fn new(rule: &str, span: Span, children: Vec<ParseNode>) -> Self {
+        Self {
+            rule: rule.to_string(),
+            span,
+            children,
+        }
+    }
+
+    /// Returns the name of the rule that matched.
+    pub fn rule(&self) -> &str {
+        &self.rule
+    }
+
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// Returns true if the node is garbled input skipped by an error production, see `Rule::recover_with`.
+    pub fn is_error(&self) -> bool {
+        self.rule == ERROR_RULE
+    }
+
+    /// Returns the error nodes of the tree, in input order.
+    pub fn errors(&self) -> Vec<&ParseNode> {
+        let mut errors = Vec::new();
+        self.collect_errors(&mut errors);
+        errors
+    }
+
+    fn collect_errors<'a>(&'a self, errors: &mut Vec<&'a ParseNode>) {
+        match self.is_error() {
+            true => errors.push(self),
+            false => self.children.iter().for_each(|child| child.collect_errors(errors)),
+        }
+    }
+
+    /// Returns the nodes of the named rules matched directly in this one, in order.
+    pub fn children(&self) -> &[ParseNode] {
+        &self.children
+    }
+
+    /// Returns the text matched by the node in the given source.
+    pub fn text<'a>(&self, source: &'a str) -> &'a str {
+        source
+            .get(self.span.start().byte_offset()..self.span.end().byte_offset())
+            .unwrap_or_default()
+    }
+
+    /// Replaces the locations of the spans of the node and its descendants by the ones given by the function.
+    pub(crate) fn map_locations(&mut self, f: &impl Fn(&Location) -> Location) {
+        self.span = Span::new(f(self.span.start()), f(self.span.end()));
+        for child in &mut self.children {
+            child.map_locations(f);
+        }
+    }
+
+    /// Writes the tree in JSON,with the rule name, span, text and children of each node, like
+    /// `{"rule":"number","span":{"start":{...},"end":{...}},"text":"12","children":[]}`.
+    pub fn to_json(&self, source: &str) -> String {
+        let mut json = String::new();
+        self.write_json(&mut json, source);
+        json
+    }
+
+    /// Writes the tree as a compact s-expression, like tree-sitter: `(call (ident "foo") (args (number "1")))`.
+    ///
+    /// The nodes without children are followed by the text they matched, which is enough to compare trees in tests.
+    pub fn to_sexp(&self, source: &str) -> String {
+        let mut sexp = String::new();
+        self.write_sexp(&mut sexp, source);
+        sexp
+    }
+
+    fn write_sexp(&self, sexp: &mut String, source: &str) {
+        sexp.push('(');
+        sexp.push_str(&self.rule);
+        if self.children.is_empty() {
+            sexp.push(' ');
+            write_quoted(sexp, self.text(source));
+        }
+        for child in &self.children {
+            sexp.push(' ');
+            child.write_sexp(sexp, source);
+        }
+        sexp.push(')');
+    }
+
+    /// Formats an s-expression written by hand like `to_sexp`, so that expected trees can be indented on several lines.
+    pub fn normalize_sexp(sexp: &str) -> String {
+        let mut normalized = String::new();
+        let mut chars = sexp.chars();
+        let mut space = false;
+        while let Some(c) = chars.next() {
+            match c {
+                c if c.is_whitespace() => space = true,
+                _ => {
+                    // Spaces are only kept between two items
+                    if space && c != ')' && !normalized.is_empty() && !normalized.ends_with('(') {
+                        normalized.push(' ');
+                    }
+                    space = false;
+                    normalized.push(c);
+
+                    // Strings are kept as they are
+                    if c == '"' {
+                        while let Some(c) = chars.next() {
+                            normalized.push(c);
+                            match c {
+                                '\\' => normalized.extend(chars.next()),
+                                '"' => break,
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        normalized
+    }
+
+    fn write_json(&self, json: &mut String, source: &str) {
+        json.push_str("{\"rule\":");
+        write_quoted(json, &self.rule);
+        json.push_str(",\"span\":{\"start\":");
+        write_json_location(json, self.span.start());
+        json.push_str(",\"end\":");
+        write_json_location(json, self.span.end());
+        json.push_str("},\"text\":");
+        write_quoted(json, self.text(source));
+        json.push_str(",\"children\":[");
+        for (i, child) in self.children.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            child.write_json(json, source);
+        }
+        json.push_str("]}");
+    }
+}
+
+fn write_json_location(json: &mut String, location: &Location) {
+    // Writing to a string can't fail
+    let _ = write!(
+        json,
+        "{{\"line\":{},\"column\":{},\"index\":{},\"byte_offset\":{}}}",
+        location.line(),
+        location.column(),
+        location.index(),
+        location.byte_offset()
+    );
+}
+
+/// Writes the string between quotes, escaped like in JSON (which s-expressions read too).
+fn write_quoted(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_node_to_json() {
+        let source = "x = \"a\"";
+        let value = ParseNode::new("string", Span::new(Location::new(1, 5, 4), Location::new(1, 8, 7)), Vec::new());
+        let node = ParseNode::new("assignment", Span::new(Location::beginning(), Location::new(1, 8, 7)), vec![value]);
+
+        assert_eq!(node.children()[0].text(source), "\"a\"");
+        assert_eq!(
+            node.to_json(source),
+            "{\"rule\":\"assignment\",\"span\":{\"start\":{\"line\":1,\"column\":1,\"index\":0,\"byte_offset\":0},\
+             \"end\":{\"line\":1,\"column\":8,\"index\":7,\"byte_offset\":7}},\"text\":\"x = \\\"a\\\"\",\"children\":[\
+             {\"rule\":\"string\",\"span\":{\"start\":{\"line\":1,\"column\":5,\"index\":4,\"byte_offset\":4},\
+             \"end\":{\"line\":1,\"column\":8,\"index\":7,\"byte_offset\":7}},\"text\":\"\\\"a\\\"\",\"children\":[]}]}"
+        );
+    }
+
+    #[test]
+    fn test_parse_node_to_sexp() {
+        let source = "f(\"a\")";
+        let name = ParseNode::new("ident", Span::new(Location::beginning(), Location::new(1, 2, 1)), Vec::new());
+        let arg = ParseNode::new("string", Span::new(Location::new(1, 3, 2), Location::new(1, 6, 5)), Vec::new());
+        let args = ParseNode::new("args", Span::new(Location::new(1, 2, 1), Location::new(1, 7, 6)), vec![arg]);
+        let call = ParseNode::new("call", Span::new(Location::beginning(), Location::new(1, 7, 6)), vec![name, args]);
+
+        assert_eq!(call.to_sexp(source), "(call (ident \"f\") (args (string \"\\\"a\\\"\")))");
+        assert_eq!(
+            ParseNode::normalize_sexp("\n  (call\n    (ident \"f\")\n    ( args (string \"\\\" a  \")  ) )\n"),
+            "(call (ident \"f\") (args (string \"\\\" a  \")))"
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use super::{TraceLevel, Tracer, DEFAULT_RECURSION_LIMIT};
+
+/// What the error productions do with garbled input, see `Rule::recover_with`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Recovery {
+    /// The garbled input is skipped as an error node, and the parse goes on.
+    #[default]
+    Skip,
+    /// The error productions don't match, so the parse stops at the first error, like without them.
+    Disabled,
+}
+
+/// Tells how a grammar parses an input, see `Grammar::parse`.
+///
+/// The default options keep the buffers of the readers, don't memoize, allow `DEFAULT_RECURSION_LIMIT` nested rules,
+/// don't limit the number of steps nor of errors, recover from the errors, and don't trace.
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// Number of chars the buffered readers must be able to look ahead. If None, their buffer is kept.
+    look_ahead: Option<usize>,
+    /// If true, the results of the named rules are cached by position (packrat parsing).
+    memoize: bool,
+    /// Maximum number of nested rules.
+    recursion_limit: usize,
+    /// Maximum number of steps. If None, there is no limit.
+    fuel: Option<usize>,
+    /// Maximum number of error nodes. If None, there is no limit.
+    max_errors: Option<usize>,
+    recovery: Recovery,
+    tracer: Option<Tracer>,
+    trace_level: TraceLevel,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            look_ahead: None,
+            memoize: false,
+            recursion_limit: DEFAULT_RECURSION_LIMIT,
+            fuel: None,
+            max_errors: None,
+            recovery: Recovery::default(),
+            tracer: None,
+            trace_level: TraceLevel::default(),
+        }
+    }
+}
+
+impl ParseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Grows the buffer of the readers, so that the matchers can look at least `look_ahead` chars ahead of the cursor
+    /// without a `LookAheadBufferOverflow` error. Readers holding the whole input are not affected.
+    pub fn with_look_ahead(mut self, look_ahead: usize) -> Self {
+        self.look_ahead = Some(look_ahead);
+        self
+    }
+
+    /// Caches the result of each named rule at each position, so that it is matched only once per position.
+    ///
+    /// It bounds the parse time of grammars that backtrack a lot, at the cost of memory.
+    /// Results that depend on a left-recursive rule being grown are not cached.
+    pub fn with_memoization(mut self) -> Self {
+        self.memoize = true;
+        self
+    }
+
+    /// Sets the maximum number of nested rules, see `SyntaxError::RecursionLimit`.
+    ///
+    /// Matching deeply nested rules uses a lot of stack: the parsing thread may need a bigger stack to raise it.
+    pub fn with_recursion_limit(mut self, limit: usize) -> Self {
+        self.recursion_limit = limit;
+        self
+    }
+
+    /// Sets the maximum number of steps (matched rules and backtracks), see `SyntaxError::OutOfFuel`.
+    ///
+    /// It bounds the time taken by pathological inputs, for example in a service parsing untrusted inputs.
+    pub fn with_fuel(mut self, fuel: usize) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// Sets the maximum number of error nodes that the error productions can skip (see `Rule::recover_with`) before the
+    /// parse stops with a `SyntaxError::TooManyErrors`.
+    ///
+    /// Past some errors, the input is probably not in the language at all, and the other errors are mostly noise.
+    pub fn with_max_errors(mut self, max_errors: usize) -> Self {
+        self.max_errors = Some(max_errors);
+        self
+    }
+
+    /// Sets what the error productions do, see `Recovery`.
+    ///
+    /// Disabling them makes a parse fail fast, like when checking that an input is valid.
+    pub fn with_recovery(mut self, recovery: Recovery) -> Self {
+        self.recovery = recovery;
+        self
+    }
+
+    /// Sends the entry and exit of every named rule to the tracer, to debug the grammar.
+    pub fn with_tracer(mut self, tracer: Tracer) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    /// Sets which events are sent to the tracer. By default, they all are.
+    pub fn with_trace_level(mut self, level: TraceLevel) -> Self {
+        self.trace_level = level;
+        self
+    }
+
+    pub fn look_ahead(&self) -> Option<usize> {
+        self.look_ahead
+    }
+
+    pub fn memoize(&self) -> bool {
+        self.memoize
+    }
+
+    pub fn recursion_limit(&self) -> usize {
+        self.recursion_limit
+    }
+
+    pub fn fuel(&self) -> Option<usize> {
+        self.fuel
+    }
+
+    pub fn max_errors(&self) -> Option<usize> {
+        self.max_errors
+    }
+
+    pub fn recovery(&self) -> Recovery {
+        self.recovery
+    }
+
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
+    pub fn trace_level(&self) -> TraceLevel {
+        self.trace_level
+    }
+}
@@ -1,29 +1,200 @@
 use std::{
     error::Error,
     fmt::{Display, Formatter},
+    io,
 };
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+use crate::utils::RingBufferError;
+
+use super::{IoError, LexError, Location, SyntaxError};
+
+/// Named rule that was being matched when an error happened.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ErrorContext {
+    pub rule: String,
+    /// Location where the rule started to be matched.
+    pub location: Location,
+}
+
+/// Error returned by the matchers, by category, so that the ones that matter can be matched.
+///
+/// Use `cause` to get the category of an error that has a context.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ParserError {
-    /// Tried to peek a char which is before the cursor and thus not accessible anymore
-    NoLookBehind(usize),
-    /// Tried to peek a char which is too far away from the cursor and wouldn't fit in the buffer
-    LookAheadBufferOverflow(usize),
-    /// Tried to use a grammar that is not defined
-    NoGrammarDefined,
+    /// The readers could not give the chars to the matchers
+    Lex(LexError),
+    /// The input could not be read
+    Io(IoError),
+    /// The grammar is invalid, or the input made the parse go beyond its limits
+    Syntax(SyntaxError),
+    /// The error happened while matching the given rules, from the innermost to the outermost one
+    Context { error: Box<ParserError>, context: Vec<ErrorContext> },
+}
+
+impl ParserError {
+    /// Adds a rule that was being matched when the error happened, outside the ones already known.
+    pub fn in_rule(self, rule: &str, location: Location) -> Self {
+        let frame = ErrorContext { rule: rule.to_string(), location };
+        match self {
+            ParserError::Context { error, mut context } => {
+                context.push(frame);
+                ParserError::Context { error, context }
+            }
+            error => ParserError::Context { error: Box::new(error), context: vec![frame] },
+        }
+    }
+
+    /// Returns the error without its context.
+    pub fn cause(&self) -> &ParserError {
+        match self {
+            ParserError::Context { error, .. } => error,
+            error => error,
+        }
+    }
+
+    /// Returns the rules that were being matched when the error happened, from the innermost to the outermost one.
+    pub fn context(&self) -> &[ErrorContext] {
+        match self {
+            ParserError::Context { context, .. } => context,
+            _ => &[],
+        }
+    }
+
+    /// Returns the location of the error if it is known, or else the location of the innermost rule being matched.
+    pub fn location(&self) -> Option<Location> {
+        match self.cause() {
+            ParserError::Syntax(
+                SyntaxError::RecursionLimit { location, .. }
+                | SyntaxError::OutOfFuel { location, .. }
+                | SyntaxError::TooManyErrors { location, .. }
+                | SyntaxError::Fatal { location, .. }
+                | SyntaxError::NoToken { location },
+            ) => Some(*location),
+            _ => self.context().first().map(|frame| frame.location),
+        }
+    }
+}
+
+impl From<LexError> for ParserError {
+    fn from(err: LexError) -> Self {
+        Self::Lex(err)
+    }
+}
+
+impl From<IoError> for ParserError {
+    fn from(err: IoError) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<SyntaxError> for ParserError {
+    fn from(err: SyntaxError) -> Self {
+        Self::Syntax(err)
+    }
+}
+
+impl From<io::Error> for ParserError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err.into())
+    }
+}
+
+impl<T> From<RingBufferError<T>> for ParserError {
+    fn from(err: RingBufferError<T>) -> Self {
+        Self::Lex(err.into())
+    }
 }
 
 impl Display for ParserError {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         match self {
-            ParserError::NoLookBehind(index)
-                => write!(f, "Invalid search index: {}. Unable to look behind cursor.", index),
-            ParserError::LookAheadBufferOverflow(index)
-                => write!(f, "Could not look ahead char at relative index {}: char read buffer capacity is too small.", index),
-            ParserError::NoGrammarDefined
-                => write!(f, "No grammar defined. Use `define_grammar!` macro."),
+            ParserError::Lex(err) => write!(f, "{}", err),
+            ParserError::Io(err) => write!(f, "{}", err),
+            ParserError::Syntax(err) => write!(f, "{}", err),
+            ParserError::Context { error, context } => {
+                write!(f, "{}", error)?;
+                for frame in context {
+                    write!(f, "\n    while parsing `{}` at {}", frame.rule, frame.location)?;
+                }
+                Ok(())
+            }
         }
     }
 }
 
-impl Error for ParserError {}
+impl Error for ParserError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        // The categories are displayed as they are, so their source is the one of the error
+        match self {
+            ParserError::Lex(err) => err.source(),
+            ParserError::Io(err) => err.source(),
+            ParserError::Syntax(err) => err.source(),
+            ParserError::Context { error, .. } => Some(error.as_ref()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::RingBuffer;
+
+    use super::*;
+
+    #[test]
+    fn test_from_ring_buffer_error() {
+        let mut buffer = RingBuffer::new(1);
+        buffer.push_back('a').unwrap();
+
+        let err: ParserError = buffer.push_back('b').unwrap_err().into();
+        assert_eq!(err, ParserError::Lex(LexError::BufferFull));
+    }
+
+    #[test]
+    fn test_error_categories() {
+        let err: ParserError = io::Error::new(io::ErrorKind::UnexpectedEof, "closed").into();
+        assert!(matches!(err, ParserError::Io(IoError::Read(_))));
+        assert_eq!(err.to_string(), "Could not read the input: closed");
+        assert_eq!(err.source().unwrap().to_string(), "closed");
+
+        let err: ParserError = SyntaxError::UnresolvedRule("expr".to_string()).into();
+        assert_eq!(err.to_string(), "The rule `expr` is not defined.");
+        assert!(err.source().is_none());
+    }
+
+    #[test]
+    fn test_error_context() {
+        let err = ParserError::Lex(LexError::LookAheadBufferOverflow(3))
+            .in_rule("expr", Location::new(2, 5, 12))
+            .in_rule("body", Location::new(1, 1, 0));
+
+        assert_eq!(err.cause(), &ParserError::Lex(LexError::LookAheadBufferOverflow(3)));
+        assert_eq!(err.context().len(), 2);
+        assert_eq!(err.context()[1].rule, "body");
+        assert_eq!(err.location(), Some(Location::new(2, 5, 12)));
+        assert_eq!(
+            err.to_string(),
+            "Could not look ahead char at relative index 3: char read buffer capacity is too small.\n    \
+             while parsing `expr` at 2:5\n    while parsing `body` at 1:1"
+        );
+        assert!(err.source().is_some());
+        assert_eq!(ParserError::Lex(LexError::BufferFull).location(), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let err = ParserError::Syntax(SyntaxError::OutOfFuel { limit: 10, location: Location::new(1, 3, 2) })
+            .in_rule("expr", Location::new(1, 1, 0));
+        let json = serde_json::to_string(&err).unwrap();
+        assert_eq!(serde_json::from_str::<ParserError>(&json).unwrap(), err);
+
+        // I/O errors are sent as their message
+        let err: ParserError = io::Error::new(io::ErrorKind::UnexpectedEof, "closed").into();
+        let json = serde_json::to_string(&err).unwrap();
+        assert_eq!(json, "{\"Io\":{\"Read\":\"closed\"}}");
+        let err: ParserError = serde_json::from_str(&json).unwrap();
+        assert_eq!(err.to_string(), "Could not read the input: closed");
+    }
+}
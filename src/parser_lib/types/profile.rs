@@ -0,0 +1,142 @@
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    time::{Duration, Instant},
+};
+
+/// Statistics of a named rule during a parse.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RuleProfile {
+    pub name: String,
+    /// Number of times the rule was matched, successfully or not.
+    pub calls: usize,
+    /// Number of calls that didn't match, after which the parser backtracked.
+    pub failures: usize,
+    /// Number of alternatives tried again after a failure, directly in the rule (not in the nested rules).
+    pub backtracks: usize,
+    /// Time spent in the rule, including the nested rules. Recursive calls are counted several times.
+    pub total_time: Duration,
+    /// Time spent in the rule, without the nested rules.
+    pub self_time: Duration,
+}
+
+/// Statistics of the named rules during a parse, to find the rules dominating the parse time.
+///
+/// The rules are sorted by cost: the rule with the highest self time comes first.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ParseProfile {
+    rules: Vec<RuleProfile>,
+}
+
+impl ParseProfile {
+    /// Returns the statistics of the rules, by decreasing self time.
+    pub fn rules(&self) -> &[RuleProfile] {
+        &self.rules
+    }
+
+    /// Returns the statistics of the given rule, if it was matched.
+    pub fn rule(&self, name: &str) -> Option<&RuleProfile> {
+        self.rules.iter().find(|rule| rule.name == name)
+    }
+}
+
+impl Display for ParseProfile {
+    /// Writes the report as a table, one rule per line.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let width = self.rules.iter().map(|rule| rule.name.len()).chain(["rule".len()]).max().unwrap_or_default();
+        writeln!(f, "{:width$} {:>8} {:>8} {:>10} {:>12} {:>12}", "rule", "calls", "failures", "backtracks", "self", "total")?;
+        for rule in &self.rules {
+            writeln!(
+                f,
+                "{:width$} {:>8} {:>8} {:>10} {:>12} {:>12}",
+                rule.name,
+                rule.calls,
+                rule.failures,
+                rule.backtracks,
+                format!("{:.2?}", rule.self_time),
+                format!("{:.2?}", rule.total_time),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Records the statistics of the rules while parsing.
+#[derive(Debug, Default)]
+pub(crate) struct Profiler {
+    rules: HashMap<String, RuleProfile>,
+    /// Rules being matched, with the time spent in their nested rules so far.
+    stack: Vec<(String, Duration)>,
+}
+
+impl Profiler {
+    pub fn enter(&mut self, rule: &str) {
+        self.stack.push((rule.to_string(), Duration::ZERO));
+    }
+
+    /// Records the end of the innermost rule, which started at the given instant.
+    pub fn exit(&mut self, start: Instant, matched: bool) {
+        let Some((name, nested_time)) = self.stack.pop() else {
+            return;
+        };
+        let elapsed = start.elapsed();
+        if let Some((_, parent_nested_time)) = self.stack.last_mut() {
+            *parent_nested_time += elapsed;
+        }
+
+        let profile = self.rules.entry(name).or_insert_with_key(|name| RuleProfile {
+            name: name.clone(),
+            ..RuleProfile::default()
+        });
+        profile.calls += 1;
+        profile.failures += usize::from(!matched);
+        profile.total_time += elapsed;
+        profile.self_time += elapsed.saturating_sub(nested_time);
+    }
+
+    /// Records a backtrack in the innermost rule.
+    pub fn backtrack(&mut self) {
+        let Some((name, _)) = self.stack.last() else {
+            return;
+        };
+        let profile = self.rules.entry(name.clone()).or_insert_with_key(|name| RuleProfile {
+            name: name.clone(),
+            ..RuleProfile::default()
+        });
+        profile.backtracks += 1;
+    }
+
+    pub fn finish(self) -> ParseProfile {
+        let mut rules: Vec<RuleProfile> = self.rules.into_values().collect();
+        rules.sort_by(|a, b| b.self_time.cmp(&a.self_time).then_with(|| a.name.cmp(&b.name)));
+        ParseProfile { rules }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiler() {
+        let mut profiler = Profiler::default();
+        let start = Instant::now();
+        profiler.enter("expr");
+        profiler.backtrack();
+        let nested_start = Instant::now();
+        profiler.enter("number");
+        profiler.exit(nested_start, false);
+        profiler.exit(start, true);
+        // Outside of any rule
+        profiler.backtrack();
+
+        let profile = profiler.finish();
+        assert_eq!(profile.rules().len(), 2);
+        let expr = profile.rule("expr").unwrap();
+        assert_eq!((expr.calls, expr.failures, expr.backtracks), (1, 0, 1));
+        let number = profile.rule("number").unwrap();
+        assert_eq!((number.calls, number.failures, number.backtracks), (1, 1, 0));
+        assert!(expr.total_time >= number.total_time);
+        assert!(profile.to_string().starts_with("rule   "));
+    }
+}
@@ -0,0 +1,27 @@
+use std::fmt::Display;
+
+/// Counters about how a reader was used during a parse.
+///
+/// They help to choose buffer sizes (the peak lookahead must fit in the buffer of streaming readers),
+/// and to spot grammars that backtrack a lot.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReaderStats {
+    /// Number of consumed chars.
+    pub consumed: usize,
+    /// Longest distance between the cursor and a char read by the matchers, in chars.
+    pub peak_lookahead: usize,
+    /// Number of times the buffer of the reader was filled from its input.
+    pub refills: usize,
+    /// Number of times the matching went back before the end of an already matched part of the input.
+    pub backtracks: usize,
+}
+
+impl Display for ReaderStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} chars consumed, peak lookahead of {} chars, {} buffer refills, {} backtracks",
+            self.consumed, self.peak_lookahead, self.refills, self.backtracks
+        )
+    }
+}
@@ -0,0 +1,147 @@
+use std::{fmt::Debug, io::Read};
+
+use super::{Checkpoint, Grammar, Location, MatchToken, ParseInfo, ParserError, Rewind};
+use crate::parser_lib::ReadCharReader;
+
+/// Outcome of `ResumableParse::parse`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseStatus {
+    /// The grammar matched the input at the location of the parse, or didn't (None).
+    Done(Option<ParseInfo>),
+    /// The grammar needs bytes that didn't arrive yet: the parse must be resumed when they do.
+    NeedMoreInput,
+}
+
+/// Parse of an input whose bytes arrive over time, like a non-blocking socket or pipe that returns `WouldBlock`
+/// when it has no bytes yet.
+///
+/// Instead of failing when it needs these bytes, `parse` returns `NeedMoreInput`, and can be called again later.
+/// The bytes read are kept, but not the state of the rules: the grammar is matched again from the same location.
+/// After a match, the matched input is consumed, so that the next call matches what follows (like the next message
+/// of a protocol).
+#[derive(Debug)]
+pub struct ResumableParse<R: Read> {
+    reader: ReadCharReader<R>,
+    /// Where the next match starts.
+    loc: Location,
+}
+
+impl<R: Read + Debug> ResumableParse<R> {
+    /// Creates a parse of the input of the reader, whose buffer must be able to hold a whole match.
+    pub fn new(reader: ReadCharReader<R>) -> Self {
+        Self {
+            reader,
+            loc: Location::beginning(),
+        }
+    }
+
+    /// Returns where the next match starts.
+    pub fn location(&self) -> &Location {
+        &self.loc
+    }
+
+    pub fn reader(&self) -> &ReadCharReader<R> {
+        &self.reader
+    }
+
+    pub fn reader_mut(&mut self) -> &mut ReadCharReader<R> {
+        &mut self.reader
+    }
+
+    /// Matches the grammar at the location of the parse, with the bytes arrived so far.
+    ///
+    /// If the grammar read all of them and needed more, the result could change with them, so `NeedMoreInput` is returned.
+    pub fn parse(&mut self, grammar: &Grammar<ReadCharReader<R>>) -> Result<ParseStatus, ParserError> {
+        self.reader.resume();
+        let result = grammar.test(&self.loc, &mut self.reader);
+        if self.reader.needs_more_input() {
+            return Ok(ParseStatus::NeedMoreInput);
+        }
+
+        let Some(info) = result? else {
+            return Ok(ParseStatus::Done(None));
+        };
+        // The matched chars are not needed anymore, they make room in the buffer for the next match
+        self.reader.rewind(Checkpoint::new(info.end().index(), 0))?;
+        self.loc = *info.end();
+        Ok(ParseStatus::Done(Some(info)))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        collections::VecDeque,
+        io::{self, ErrorKind},
+        rc::Rc,
+    };
+
+    use crate::{
+        parser_lib::{GrammarBuilder, Rule, Span},
+        seq, word,
+    };
+
+    use super::*;
+
+    /// Non-blocking pipe: it gives the bytes written to it, then `WouldBlock` until more are written or it is closed.
+    #[derive(Debug, Clone, Default)]
+    struct Pipe(Rc<RefCell<(VecDeque<u8>, bool)>>);
+
+    impl Pipe {
+        fn write(&self, bytes: &[u8]) {
+            self.0.borrow_mut().0.extend(bytes);
+        }
+
+        fn close(&self) {
+            self.0.borrow_mut().1 = true;
+        }
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let (bytes, closed) = &mut *self.0.borrow_mut();
+            if bytes.is_empty() && !*closed {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            let len = bytes.len().min(buf.len());
+            for (i, byte) in bytes.drain(..len).enumerate() {
+                buf[i] = byte;
+            }
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_resumable_parse() {
+        // Messages like "(abc)"
+        let builder = GrammarBuilder::<ReadCharReader<Pipe>>::new();
+        let grammar = builder.save_root(seq!(word!("("), Rule::range('a', 'é').at_least(0), word!(")")));
+
+        let pipe = Pipe::default();
+        let mut parse = ResumableParse::new(ReadCharReader::new(pipe.clone(), 16));
+        assert_eq!(parse.parse(&grammar), Ok(ParseStatus::NeedMoreInput));
+
+        pipe.write(b"(ab");
+        assert_eq!(parse.parse(&grammar), Ok(ParseStatus::NeedMoreInput));
+
+        // The end of a message is enough, even if the next one is incomplete (here, in the middle of a char)
+        pipe.write(b"c)(\xc3");
+        let span = Span::new(Location::beginning(), Location::new(1, 6, 5));
+        assert_eq!(parse.parse(&grammar), Ok(ParseStatus::Done(Some(ParseInfo::new(span, 5)))));
+        assert_eq!(parse.location(), &Location::new(1, 6, 5));
+        assert_eq!(parse.parse(&grammar), Ok(ParseStatus::NeedMoreInput));
+
+        pipe.write(b"\xa9)");
+        let span = Span::new(Location::new(1, 6, 5), Location::with_byte_offset(1, 9, 8, 9));
+        assert_eq!(parse.parse(&grammar), Ok(ParseStatus::Done(Some(ParseInfo::new(span, 3)))));
+
+        // Once the input is closed, its end is known
+        pipe.write(b"?");
+        assert_eq!(parse.parse(&grammar), Ok(ParseStatus::Done(None)));
+        pipe.close();
+        assert_eq!(parse.parse(&grammar), Ok(ParseStatus::Done(None)));
+        assert_eq!(parse.reader().needs_more_input(), false);
+    }
+}
@@ -1,15 +1,48 @@
-use std::{fmt::Display, rc::Rc};
+use std::{
+    borrow::Cow,
+    fmt::{Display, Formatter},
+    ops::{Add, BitOr, Mul, RangeFrom},
+    sync::Arc,
+};
 
 use crate::parser_lib::{
-    ChoiceMatcher, OptionalMatcher, RangeMatcher, RepetitionMatcher, SequentialMatcher, StrMatcher, NotMatcher, UntilMatcher, TokenMatcher,
+    AndMatcher, BackReferenceMatcher, CharMatcher, ChoiceMatcher, DfaError, DfaMatcher, ErrorNodeMatcher, FatalMatcher, KeywordSetMatcher, LabelMatcher, OptionalMatcher, RangeMatcher, RepetitionMatcher, SequentialMatcher, StrMatcher, NotMatcher, UntilMatcher, TokenMatcher,
 };
 
-use super::{Location, MatchStr, MatchToken, ParseResult, Stream};
+use super::{optimizer::Optimizer, Location, MatchStr, MatchToken, MatcherShape, Nesting, Notation, ParseResult, Token};
 
 /// A "Rule" wraps a Matcher and gives it helper functions for clearer grammar definition.
+///
+/// Rules can also be combined with operators:
+/// - `a + b` matches a sequence (like `seq!(a, b)`)
+/// - `a | b` chooses between rules (like `choice!(a, b)`)
+/// - `a * n` repeats a rule exactly n times, and `a * (n..)` at least n times
 #[derive(Debug)]
 pub struct Rule<R: MatchStr> {
-    matcher: Rc<dyn MatchToken<R>>,
+    matcher: Arc<dyn MatchToken<R>>,
+    /// If the rule is a sequence or a choice, its children.
+    /// They are kept so that chained operators build a single flat matcher: `a + b + c` is `(a b c)`, not `((a b) c)`.
+    operands: Option<(Combinator, Matchers<R>)>,
+}
+
+/// Children of a combined rule.
+type Matchers<R> = Vec<Arc<dyn MatchToken<R>>>;
+
+/// Kind of rule that can be extended with more children.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Combinator {
+    Seq,
+    Choice,
+}
+
+// Cloning a rule shares the same matcher
+impl<R: MatchStr> Clone for Rule<R> {
+    fn clone(&self) -> Self {
+        Self {
+            matcher: Arc::clone(&self.matcher),
+            operands: self.operands.clone(),
+        }
+    }
 }
 
 impl<R: MatchStr> Display for Rule<R> {
@@ -23,29 +56,67 @@ impl<R: MatchStr> MatchToken<R> for Rule<R> {
     fn test(&self, loc: &Location, reader: &mut R) -> ParseResult {
         self.matcher.test(loc, reader)
     }
+
+    fn fmt_notation(&self, f: &mut Formatter, notation: Notation, nesting: Nesting) -> std::fmt::Result {
+        self.matcher.fmt_notation(f, notation, nesting)
+    }
+
+    fn shape(&self) -> MatcherShape<'_, R> {
+        self.matcher.shape()
+    }
 }
 
 impl<R: 'static + MatchStr > Rule<R> {
     /// Creates a new Rule from a Matcher.
-    pub fn new(matcher: Rc<dyn MatchToken<R>>) -> Self {
-        Self { matcher }
+    pub fn new(matcher: Arc<dyn MatchToken<R>>) -> Self {
+        Self { matcher, operands: None }
+    }
+
+    /// Returns the underlying matcher.
+    pub(crate) fn matcher(&self) -> &Arc<dyn MatchToken<R>> {
+        &self.matcher
+    }
+
+    /// Matches an exact string, which can be owned (see `StrMatcher`). A single char is matched with a `CharMatcher`.
+    pub fn word(word: impl Into<Cow<'static, str>>) -> Self {
+        let word = word.into();
+        let mut chars = word.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return Self::new(Arc::new(CharMatcher::new(c)));
+        }
+        Self::new(Arc::new(StrMatcher::new(word)))
     }
 
-    /// Matches an exact string.
-    pub fn word(word: &'static str) -> Self {
-        Self::new(Rc::new(StrMatcher::new(word)))
+    /// Matches the first of the given words found in the input, like a choice between them, but in a single pass.
+    pub fn keywords(words: &[&str]) -> Self {
+        Self::new(Arc::new(KeywordSetMatcher::new(words)))
     }
 
     /// Matches characters within a range.
     #[allow(unused)]
     pub fn range(start: char, end: char) -> Self {
-        Self::new(Rc::new(RangeMatcher::new(start, end)))
+        Self::new(Arc::new(RangeMatcher::new(start, end)))
+    }
+
+    /// Matches characters within a range, or their other case (for case-insensitive languages).
+    pub fn range_ignore_case(start: char, end: char) -> Self {
+        Self::new(Arc::new(RangeMatcher::new(start, end).with_ignore_case()))
     }
 
     /// Matches any character that doesn't match the condition, at least `min` times.
     #[allow(unused)]
     pub fn until(until: &Self, min: usize) -> Self {
-        Self::new(Rc::new(UntilMatcher::new(Rc::clone(&until.matcher), min)))
+        Self::new(Arc::new(UntilMatcher::new(Arc::clone(&until.matcher), min)))
+    }
+
+    /// Matches at least one char until the sync point, as an error node. See `ErrorNodeMatcher`.
+    pub fn error(sync: &Self) -> Self {
+        Self::new(Arc::new(ErrorNodeMatcher::new(Arc::clone(&sync.matcher))))
+    }
+
+    /// Stops the parse with a `SyntaxError::Fatal` error with the given message when reached. See `FatalMatcher`.
+    pub fn fatal(message: &str) -> Self {
+        Self::new(Arc::new(FatalMatcher::new(message)))
     }
 
     /// Matches a sequence of rules.
@@ -54,8 +125,7 @@ impl<R: 'static + MatchStr > Rule<R> {
         // Get all underlying matchers
         let matchers = rules.into_iter().map(|r| r.matcher.clone()).collect();
 
-        // Create a sequential matcher
-        Self::new(Rc::new(SequentialMatcher::new(matchers)))
+        Self::combine(Combinator::Seq, matchers)
     }
 
     /// Chooses between several rules.
@@ -63,50 +133,250 @@ impl<R: 'static + MatchStr > Rule<R> {
         // Get all underlying matchers
         let matchers = rules.into_iter().map(|r| r.matcher.clone()).collect();
 
-        // Create a sequential matcher
-        Self::new(Rc::new(ChoiceMatcher::new(matchers)))
+        Self::combine(Combinator::Choice, matchers)
+    }
+
+    /// Matches this rule, then the other one. Same as `self + other`.
+    pub fn then(&self, other: &Self) -> Self {
+        let mut matchers = self.operands_of(Combinator::Seq);
+        matchers.extend(other.operands_of(Combinator::Seq));
+        Self::combine(Combinator::Seq, matchers)
+    }
+
+    /// Matches this rule, or the other one if it fails. Same as `self | other`.
+    pub fn or(&self, other: &Self) -> Self {
+        let mut matchers = self.operands_of(Combinator::Choice);
+        matchers.extend(other.operands_of(Combinator::Choice));
+        Self::combine(Combinator::Choice, matchers)
+    }
+
+    /// Repeats the rule exactly n times. Same as `self * n`.
+    pub fn repeat(&self, n: usize) -> Self {
+        Self::combine(Combinator::Seq, vec![self.matcher.clone(); n])
+    }
+
+    /// Creates a sequence or a choice of the given matchers.
+    fn combine(combinator: Combinator, matchers: Matchers<R>) -> Self {
+        let matcher: Arc<dyn MatchToken<R>> = match combinator {
+            Combinator::Seq => Arc::new(SequentialMatcher::new(matchers.clone())),
+            Combinator::Choice => Arc::new(ChoiceMatcher::new(matchers.clone())),
+        };
+
+        Self {
+            matcher,
+            operands: Some((combinator, matchers)),
+        }
+    }
+
+    /// Returns the children of the rule if it is of the given kind, or the rule itself otherwise.
+    fn operands_of(&self, combinator: Combinator) -> Matchers<R> {
+        match &self.operands {
+            Some((kind, matchers)) if *kind == combinator => matchers.clone(),
+            _ => vec![self.matcher.clone()],
+        }
     }
 
     /// Repeats the rule at least n time.
     #[allow(unused)]
-    pub fn at_least(&self, n: u8) -> Self {
+    pub fn at_least(&self, n: usize) -> Self {
         let repeat = RepetitionMatcher::new(self.matcher.clone(), n);
-        Self {
-            matcher: Rc::new(repeat),
+        Self::new(Arc::new(repeat))
+    }
+
+    /// Repeats the rule between min and max times (max included).
+    pub fn repeat_between(&self, min: usize, max: usize) -> Self {
+        Self::new(Arc::new(RepetitionMatcher::between(self.matcher.clone(), min, max)))
+    }
+
+    /// Repeats the rule at most n times (possibly 0).
+    pub fn at_most(&self, n: usize) -> Self {
+        self.repeat_between(0, n)
+    }
+
+    /// Repeats the rule exactly n times.
+    pub fn exactly(&self, n: usize) -> Self {
+        self.repeat_between(n, n)
+    }
+
+    /// Matches the inner rule, surrounded by the open and close rules (like parentheses).
+    pub fn between(open: &Self, inner: &Self, close: &Self) -> Self {
+        Self::seq(vec![open, inner, close])
+    }
+
+    /// Matches the rule, surrounded by the open and close rules. Same as `Rule::between(open, self, close)`.
+    pub fn surrounded_by(&self, open: &Self, close: &Self) -> Self {
+        Self::between(open, self, close)
+    }
+
+    /// Matches the rule, with any number of `ignored` matches before and after it (like whitespace or comments).
+    pub fn padded(&self, ignored: &Self) -> Self {
+        let padding = ignored.at_least(0);
+        Self::between(&padding, self, &padding)
+    }
+
+    /// Matches a sequence of rules, with any number of `ignored` matches between them (but not around them).
+    pub fn padded_seq(rules: Vec<&Self>, ignored: &Self) -> Self {
+        let padding = ignored.at_least(0);
+        let mut items = Vec::new();
+        for (i, rule) in rules.into_iter().enumerate() {
+            if i > 0 {
+                items.push(&padding);
+            }
+            items.push(rule);
         }
+        Self::seq(items)
+    }
+
+    /// Matches the rule, or else takes the input up to the sync point as an error node, so that the parse goes on.
+    ///
+    /// The sync point is not consumed: the rule after this one usually matches it, like the `;` ending a statement.
+    pub fn recover_with(&self, sync: &Self) -> Self {
+        self.or(&Self::error(sync))
     }
 
     /// Makes the rule optional.
     #[allow(unused)]
     pub fn optional(&self) -> Self {
         let optional = OptionalMatcher::new(self.matcher.clone());
-        Self {
-            matcher: Rc::new(optional),
-        }
+        Self::new(Arc::new(optional))
     }
 
     /// Negates the rule.
     #[allow(unused)]
     pub fn not(&self) -> Self {
         let not = NotMatcher::new(self.matcher.clone());
-        Self {
-            matcher: Rc::new(not),
-        }
+        Self::new(Arc::new(not))
+    }
+
+    /// Checks that the rule matches, without taking the input (positive lookahead).
+    pub fn and(&self) -> Self {
+        Self::new(Arc::new(AndMatcher::new(self.matcher.clone())))
+    }
+
+    /// Names the rule in the sequences containing it, which give its span as a field. See `ParseInfo::field`.
+    pub fn labeled(&self, label: &str) -> Self {
+        Self::new(Arc::new(LabelMatcher::new(label, self.matcher.clone())))
+    }
+
+    /// Matches the exact text of the last child labeled with the given name, earlier in the same rule.
+    /// See `BackReferenceMatcher`.
+    pub fn back_reference(label: &str) -> Self {
+        Self::new(Arc::new(BackReferenceMatcher::new(label)))
     }
 
     /// Finishes a token (consumes the input it takes, it won't be accessible again).
     #[allow(unused)]
-    pub fn finish_token(self) -> Self {
+    pub fn finish_token(&self) -> Self {
         let finish = TokenMatcher::new(self.matcher.clone());
-        Self {
-            matcher: Rc::new(finish),
+        Self::new(Arc::new(finish))
+    }
+
+    /// Finishes a token, and gives it to the sink with the given type and the span of the match.
+    /// During a parse, it is given at the end, once the parse matched. See `TokenMatcher`.
+    pub fn emit_token<T>(&self, token_type: T, sink: impl Fn(Token<T>) + Send + Sync + 'static) -> Self
+    where
+        T: Clone + PartialEq + Send + Sync + 'static,
+    {
+        Self::new(Arc::new(TokenMatcher::emitting(self.matcher.clone(), token_type, sink)))
+    }
+
+    /// Compiles the rule to a DFA, which reads each char once. The rule must be regular (no references or lookaheads).
+    ///
+    /// The compiled rule finds the longest match, like a lexer generator, see `Dfa`.
+    pub fn compile(&self) -> Result<Self, DfaError> {
+        Ok(Self::new(Arc::new(DfaMatcher::new(self.matcher.clone())?)))
+    }
+
+    /// Returns an equivalent rule with a simpler matcher tree: nested sequences and choices are flattened,
+    /// adjacent strings are merged and identical sub-matchers are shared.
+    ///
+    /// References to named rules are kept. Use `Grammar::optimize` to also optimize the named rules.
+    pub fn optimize(&self) -> Self {
+        Self::new(Optimizer::new().optimize(&self.matcher))
+    }
+}
+
+// Operators, for both owned rules and references, so that rules can be reused without cloning them
+
+/// Implements a binary operator between rules with the given method.
+macro_rules! impl_rule_operator {
+    ($op:ident, $fn:ident, $method:ident) => {
+        impl<R: 'static + MatchStr> $op<Rule<R>> for Rule<R> {
+            type Output = Rule<R>;
+
+            fn $fn(self, rhs: Rule<R>) -> Rule<R> {
+                self.$method(&rhs)
+            }
+        }
+
+        impl<R: 'static + MatchStr> $op<&Rule<R>> for Rule<R> {
+            type Output = Rule<R>;
+
+            fn $fn(self, rhs: &Rule<R>) -> Rule<R> {
+                self.$method(rhs)
+            }
         }
+
+        impl<R: 'static + MatchStr> $op<Rule<R>> for &Rule<R> {
+            type Output = Rule<R>;
+
+            fn $fn(self, rhs: Rule<R>) -> Rule<R> {
+                self.$method(&rhs)
+            }
+        }
+
+        impl<R: 'static + MatchStr> $op<&Rule<R>> for &Rule<R> {
+            type Output = Rule<R>;
+
+            fn $fn(self, rhs: &Rule<R>) -> Rule<R> {
+                self.$method(rhs)
+            }
+        }
+    };
+}
+
+impl_rule_operator!(Add, add, then);
+impl_rule_operator!(BitOr, bitor, or);
+
+impl<R: 'static + MatchStr> Mul<usize> for Rule<R> {
+    type Output = Rule<R>;
+
+    fn mul(self, n: usize) -> Rule<R> {
+        self.repeat(n)
+    }
+}
+
+impl<R: 'static + MatchStr> Mul<usize> for &Rule<R> {
+    type Output = Rule<R>;
+
+    fn mul(self, n: usize) -> Rule<R> {
+        self.repeat(n)
+    }
+}
+
+impl<R: 'static + MatchStr> Mul<RangeFrom<usize>> for Rule<R> {
+    type Output = Rule<R>;
+
+    fn mul(self, range: RangeFrom<usize>) -> Rule<R> {
+        self.at_least(range.start)
+    }
+}
+
+impl<R: 'static + MatchStr> Mul<RangeFrom<usize>> for &Rule<R> {
+    type Output = Rule<R>;
+
+    fn mul(self, range: RangeFrom<usize>) -> Rule<R> {
+        self.at_least(range.start)
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
-    use crate::parser_lib::{Location, ParseInfo, Span, StringCharReader};
+    use crate::{
+        parser_lib::{Location, ParseInfo, Span, StringCharReader},
+        seq,
+    };
 
     use super::*;
 
@@ -171,4 +441,86 @@ mod tests {
         assert_eq!(params.test(&loc2, &mut reader).is_ok(), true);
         assert_eq!(params.test(&loc2, &mut reader).unwrap(), Some(info2));
     }
+
+    #[test]
+    fn test_operators() {
+        let x: Rule<StringCharReader> = Rule::word("X");
+        let y = Rule::word("Y");
+        let ws = Rule::word(" ") * (0..);
+
+        // Chained operators give flat rules
+        let val = &x | &y | Rule::word("Z");
+        assert_eq!(val.to_string(), "(\"X\" | \"Y\" | \"Z\")");
+        let pair = &val + &ws + Rule::word(",") + &ws + &val;
+        assert_eq!(pair.to_string(), "((\"X\" | \"Y\" | \"Z\") \" \"* \",\" \" \"* (\"X\" | \"Y\" | \"Z\"))");
+
+        // They are the same as the macros
+        assert_eq!((&x + &y).to_string(), Rule::seq(vec![&x, &y]).to_string());
+        assert_eq!((&x | &y).to_string(), Rule::choice(vec![&x, &y]).to_string());
+
+        let loc = Location::beginning();
+        let mut reader = StringCharReader::new("Y ,Z");
+        let info = ParseInfo::new(Span::new(loc, Location::new(1, 5, 4)), 4);
+        assert_eq!(pair.test(&loc, &mut reader).unwrap(), Some(info));
+
+        // Repetitions
+        let three = &x * 3;
+        assert_eq!(three.to_string(), "(\"X\" \"X\" \"X\")");
+        let mut reader = StringCharReader::new("XXXX");
+        assert_eq!(three.test(&loc, &mut reader).unwrap().unwrap().len(), 3);
+        assert_eq!((x * (2..)).test(&loc, &mut reader).unwrap().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_fluent_builder() {
+        let ws: Rule<StringCharReader> = Rule::word(" ");
+        let digit = Rule::range('0', '9');
+
+        // A list of 1 to 3 digits, between brackets, with spaces around
+        let list = digit
+            .then(&digit.at_most(2))
+            .surrounded_by(&Rule::word("["), &Rule::word("]"))
+            .padded(&ws);
+        assert_eq!(list.to_string(), "(\" \"* (\"[\" ([0-9] [0-9]{0,2}) \"]\") \" \"*)");
+
+        let loc = Location::beginning();
+        let mut reader = StringCharReader::new("  [123] ");
+        assert_eq!(list.test(&loc, &mut reader).unwrap().unwrap().len(), 8);
+        let mut reader = StringCharReader::new("[1234]");
+        assert_eq!(list.test(&loc, &mut reader).unwrap(), None);
+
+        let pair = digit.exactly(2).or(&Rule::word("none"));
+        assert_eq!(pair.to_string(), "([0-9]{2} | \"none\")");
+    }
+
+    #[test]
+    fn test_padded_seq() {
+        let ws: Rule<StringCharReader> = Rule::word(" ");
+        let call = Rule::padded_seq(vec![&Rule::word("f"), &Rule::word("("), &Rule::word(")")], &ws);
+        assert_eq!(call.to_string(), "(\"f\" \" \"* \"(\" \" \"* \")\")");
+
+        // The padding is only between the rules
+        let loc = Location::beginning();
+        let mut reader = StringCharReader::new("f  ( ) ");
+        assert_eq!(call.test(&loc, &mut reader).unwrap().unwrap().len(), 6);
+        let mut reader = StringCharReader::new("f()");
+        assert_eq!(call.test(&loc, &mut reader).unwrap().unwrap().len(), 3);
+        let mut reader = StringCharReader::new(" f()");
+        assert_eq!(call.test(&loc, &mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_labeled() {
+        let x: Rule<StringCharReader> = Rule::word("x");
+
+        // The fields are kept when the sequences are extended or optimized
+        let assignment = seq!(name: x, Rule::word(" ")) + seq!(Rule::word("="), value: Rule::range('0', '9'));
+        let loc = Location::beginning();
+        for rule in [assignment.clone(), assignment.optimize()] {
+            let mut reader = StringCharReader::new("x =4");
+            let info = rule.test(&loc, &mut reader).unwrap().unwrap();
+            assert_eq!(info.field("name"), Some(&Span::new(loc, loc + 1)));
+            assert_eq!(info.field("value"), Some(&Span::new(loc + 3, loc + 4)));
+        }
+    }
 }
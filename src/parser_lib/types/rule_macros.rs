@@ -4,10 +4,25 @@
 use crate::parser_lib::{Rule};
 
 /// Matches a sequence of rules
-#[macro_export(rule_macros)]
+///
+/// The rules can be labeled, like `seq!(name: ident, word!("="), value: expr)`, see `Rule::labeled`.
+#[macro_export]
 macro_rules! seq {
+    // Labels are read one rule at a time
+    (@labeled [$($rules:expr),*]) => {
+        $crate::parser_lib::Rule::seq(vec![$(&$rules),*])
+    };
+    (@labeled [$($rules:expr),*] $label:ident : $rule:expr $(, $($rest:tt)*)?) => {
+        $crate::seq!(@labeled [$($rules,)* $rule.labeled(stringify!($label))] $($($rest)*)?)
+    };
+    (@labeled [$($rules:expr),*] $rule:expr $(, $($rest:tt)*)?) => {
+        $crate::seq!(@labeled [$($rules,)* $rule] $($($rest)*)?)
+    };
     ($($rule:expr),*) => {
-        Rule::seq(vec![$(&$rule),*])
+        $crate::parser_lib::Rule::seq(vec![$(&$rule),*])
+    };
+    ($($tokens:tt)*) => {
+        $crate::seq!(@labeled [] $($tokens)*)
     };
 }
 
@@ -15,7 +30,7 @@ macro_rules! seq {
 #[macro_export]
 macro_rules! choice {
     ($($rule:expr),*) => {
-        Rule::choice(vec![$(&$rule),*])
+        $crate::parser_lib::Rule::choice(vec![$(&$rule),*])
     };
 }
 
@@ -31,7 +46,7 @@ macro_rules! opt {
 #[macro_export]
 macro_rules! range {
     ($start:expr, $end:expr) => {
-        Rule::range($start, $end)
+        $crate::parser_lib::Rule::range($start, $end)
     };
 }
 
@@ -39,7 +54,7 @@ macro_rules! range {
 #[macro_export]
 macro_rules! word {
     ($word:expr) => {
-        Rule::word($word)
+        $crate::parser_lib::Rule::word($word)
     };
 }
 
@@ -51,11 +66,46 @@ macro_rules! not {
     };
 }
 
+/// Checks that the rule matches, without taking the input
+#[macro_export]
+macro_rules! and {
+    ($rule:expr) => {
+        $rule.and()
+    };
+}
+
+/// Consumes the input matched by the rule, to finish a token
+#[macro_export]
+macro_rules! tok {
+    ($rule:expr) => {
+        $rule.finish_token()
+    };
+}
+
+/// Repeats the rule exactly `n` times, or between `min` and `max` times
+#[macro_export]
+macro_rules! repeat {
+    ($rule:expr, $n:expr) => {
+        $rule.repeat($n)
+    };
+    ($rule:expr, $min:expr, $max:expr) => {
+        $rule.repeat_between($min, $max)
+    };
+}
+
+/// Matches a rule surrounded by two others, like an expression between parentheses
+#[macro_export]
+macro_rules! between {
+    ($open:expr, $inner:expr, $close:expr) => {
+        $crate::parser_lib::Rule::between(&$open, &$inner, &$close)
+    };
+}
+
 /// Matches anything that doesn't match a rule, at least `min` times
 #[macro_export]
 macro_rules! until {
     ($rule:expr, $min:expr) => {
-        Rule::until(&$rule, $min)
+        $crate::parser_lib::Rule::until(&$rule, $min)
     };
 }
 
@@ -71,6 +121,10 @@ mod tests {
         let y = word!("Y");
         let val = seq![x, y];
         assert_eq!(val.to_string(), "(\"X\" \"Y\")");
+
+        // Some rules can be labeled
+        let val = seq!(first: x, word!("="), second: y.at_least(1),);
+        assert_eq!(val.to_string(), "(first:\"X\" \"=\" second:\"Y\"+)");
     }
 
     #[test]
@@ -98,6 +152,11 @@ mod tests {
     fn test_word() {
         let val: Rule<StringCharReader> = word!("X");
         assert_eq!(val.to_string(), "\"X\"");
+
+        // A single char doesn't need a string matcher
+        assert!(format!("{:?}", val.matcher()).starts_with("CharMatcher"));
+        let val: Rule<StringCharReader> = word!("XY");
+        assert!(format!("{:?}", val.matcher()).starts_with("StrMatcher"));
     }
 
     #[test]
@@ -119,4 +178,32 @@ mod tests {
         let val = until!(x, 2);
         assert_eq!(val.to_string(), "(!\"X\"){2,...}");
     }
+
+    #[test]
+    fn test_and() {
+        let x: Rule<StringCharReader> = word!("X");
+        let val = and!(x);
+        assert_eq!(val.to_string(), "(&\"X\")");
+    }
+
+    #[test]
+    fn test_tok() {
+        let x: Rule<StringCharReader> = word!("X");
+        let val = tok!(x);
+        assert_eq!(val.to_string(), "\"X\"");
+    }
+
+    #[test]
+    fn test_repeat() {
+        let x: Rule<StringCharReader> = word!("X");
+        assert_eq!(repeat!(x, 2).to_string(), "(\"X\" \"X\")");
+        assert_eq!(repeat!(x, 1, 3).to_string(), "\"X\"{1,3}");
+    }
+
+    #[test]
+    fn test_between() {
+        let inner: Rule<StringCharReader> = range!('a', 'z');
+        let val = between!(word!("("), inner, word!(")"));
+        assert_eq!(val.to_string(), "(\"(\" [a-z] \")\")");
+    }
 }
@@ -0,0 +1,16 @@
+/// Identifies the input a location belongs to, when several inputs are parsed together.
+///
+/// Single inputs use the default id, 0.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourceId(usize);
+
+impl SourceId {
+    pub fn new(id: usize) -> Self {
+        Self(id)
+    }
+
+    pub fn id(&self) -> usize {
+        self.0
+    }
+}
@@ -12,6 +12,7 @@ use super::Location;
 /// - start: (1, 1)
 /// - end: (1, 6), which is the char just after "hello", where we would read next
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Span {
     start: Location,
     end: Location,
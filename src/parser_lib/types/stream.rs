@@ -1,4 +1,6 @@
-pub trait Stream<T> {
+use super::{Checkpoint, ParserError};
+
+pub trait Stream<T>: Rewind {
     /// Returns the next elem in the input
     fn peek(&mut self) -> Option<T>;
 
@@ -21,4 +23,27 @@ pub trait Stream<T> {
 
     /// Checks whether the end of the input has been reached
     fn is_eof(&mut self) -> bool;
+
+    /// Returns the number of elems consumed since the start of the input,
+    /// which is also the absolute index of the next elem.
+    fn index(&self) -> usize;
+}
+
+/// Going back to an earlier position of a stream.
+///
+/// It doesn't depend on the type of the elems, so that the matchers shared by all the inputs (like sequences) can
+/// give back the input consumed by a failed match.
+pub trait Rewind {
+    /// Saves the current position of the cursor, so that it can be restored with `rewind`.
+    fn checkpoint(&mut self) -> Checkpoint;
+
+    /// Moves the cursor back (or forward) to the given checkpoint, so that consumed elems can be read again.
+    ///
+    /// Streams that don't keep the whole input may have already dropped the elems after the checkpoint.
+    /// In that case, a `NoLookBehind` error is returned and the cursor doesn't move.
+    fn rewind(&mut self, checkpoint: Checkpoint) -> Result<(), ParserError>;
+
+    /// Tells the stream that it won't be rewound to the checkpoint anymore, so that it can drop the elems consumed
+    /// since it. Streams that don't keep elems for their checkpoints have nothing to do.
+    fn release(&mut self, _checkpoint: Checkpoint) {}
 }
@@ -0,0 +1,49 @@
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+};
+
+use super::Location;
+
+/// Error of the grammar, or of the parse of an input with it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SyntaxError {
+    /// Tried to use a grammar that is not defined
+    NoGrammarDefined,
+    /// Tried to match a reference to a rule that is not defined, or whose grammar was dropped
+    UnresolvedRule(String),
+    /// Too many rules are nested at the given location (like in a deeply nested input)
+    RecursionLimit { limit: usize, location: Location },
+    /// The parse used all its steps (matched rules and backtracks) at the given location
+    OutOfFuel { limit: usize, location: Location },
+    /// The parse skipped more error nodes than allowed, the last one at the given location
+    TooManyErrors { limit: usize, location: Location },
+    /// The parse reached a fatal rule at the given location, which stops it immediately
+    Fatal { message: String, location: Location },
+    /// No token rule of a lexer matches the input at the given location
+    NoToken { location: Location },
+}
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            SyntaxError::NoGrammarDefined
+                => write!(f, "No grammar defined. Use `define_grammar!` macro."),
+            SyntaxError::UnresolvedRule(name)
+                => write!(f, "The rule `{}` is not defined.", name),
+            SyntaxError::RecursionLimit { limit, location }
+                => write!(f, "{}: more than {} rules are nested.", location, limit),
+            SyntaxError::OutOfFuel { limit, location }
+                => write!(f, "{}: the parse took more than {} steps.", location, limit),
+            SyntaxError::TooManyErrors { limit, location }
+                => write!(f, "{}: more than {} errors, the parse stopped.", location, limit),
+            SyntaxError::Fatal { message, location }
+                => write!(f, "{}: {}.", location, message),
+            SyntaxError::NoToken { location }
+                => write!(f, "{}: no token matches the input.", location),
+        }
+    }
+}
+
+impl Error for SyntaxError {}
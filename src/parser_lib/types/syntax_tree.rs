@@ -0,0 +1,294 @@
+use std::{
+    fmt::{Display, Formatter},
+    ops::Range,
+    sync::Arc,
+};
+
+use super::{Location, MatchStr, MatchToken, ParseNode, ParserError};
+use crate::parser_lib::StringCharReader;
+
+/// Kind of an element of a lossless syntax tree.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SyntaxKind {
+    /// A named rule, containing the elements it matched.
+    Rule(String),
+    /// Text matched directly by a rule, outside of its named rules (keywords, punctuation...).
+    Token,
+    /// Text matched by the ignored rule of the grammar (whitespace, comments...).
+    Trivia,
+}
+
+/// Immutable element of a lossless syntax tree (the "green" tree).
+///
+/// It only knows its length, not its position, so that identical subtrees can be shared.
+/// The leaves hold their text: writing the tree gives back the parsed source, byte for byte.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GreenNode {
+    kind: SyntaxKind,
+    /// Length in bytes.
+    len: usize,
+    /// Text of a leaf, empty for a rule.
+    text: String,
+    children: Vec<Arc<GreenNode>>,
+}
+
+impl GreenNode {
+    /// Creates a node of a rule containing the given elements.
+    pub fn rule(rule: &str, children: Vec<Arc<GreenNode>>) -> Self {
+        Self {
+            kind: SyntaxKind::Rule(rule.to_string()),
+            len: children.iter().map(|child| child.len).sum(),
+            text: String::new(),
+            children,
+        }
+    }
+
+    /// Creates a token or a trivia with the given text.
+    pub fn leaf(kind: SyntaxKind, text: &str) -> Self {
+        Self {
+            kind,
+            len: text.len(),
+            text: text.to_string(),
+            children: Vec::new(),
+        }
+    }
+
+    pub fn kind(&self) -> &SyntaxKind {
+        &self.kind
+    }
+
+    /// Returns the length of the text of the element, in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn children(&self) -> &[Arc<GreenNode>] {
+        &self.children
+    }
+}
+
+impl Display for GreenNode {
+    /// Writes the text of the element, which is the part of the source it covers.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)?;
+        for child in &self.children {
+            write!(f, "{}", child)?;
+        }
+        Ok(())
+    }
+}
+
+/// Element of a lossless syntax tree with its position and its parent (the "red" tree), built on demand.
+#[derive(Debug, Clone)]
+pub struct SyntaxNode {
+    green: Arc<GreenNode>,
+    /// Byte offset of the element in the source.
+    offset: usize,
+    parent: Option<Arc<SyntaxNode>>,
+}
+
+impl SyntaxNode {
+    /// Creates the root of a tree, at the beginning of the source.
+    pub fn new_root(green: Arc<GreenNode>) -> Self {
+        Self { green, offset: 0, parent: None }
+    }
+
+    pub fn green(&self) -> &Arc<GreenNode> {
+        &self.green
+    }
+
+    pub fn kind(&self) -> &SyntaxKind {
+        self.green.kind()
+    }
+
+    /// Returns the bytes of the source covered by the element.
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.len()
+    }
+
+    pub fn parent(&self) -> Option<&SyntaxNode> {
+        self.parent.as_deref()
+    }
+
+    /// Returns the elements of the node, in source order, trivia included.
+    pub fn children(&self) -> Vec<SyntaxNode> {
+        let parent = Arc::new(self.clone());
+        let mut offset = self.offset;
+        self.green
+            .children()
+            .iter()
+            .map(|green| {
+                let child = SyntaxNode { green: green.clone(), offset, parent: Some(parent.clone()) };
+                offset += green.len();
+                child
+            })
+            .collect()
+    }
+
+    /// Returns the text of the element, trivia included.
+    pub fn text(&self) -> String {
+        self.green.to_string()
+    }
+}
+
+impl Display for SyntaxNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.green)
+    }
+}
+
+/// Builds a lossless syntax tree from the tree of the named rules, by filling the gaps between them with tokens and trivia.
+pub(crate) struct LosslessBuilder<'a> {
+    source: &'a str,
+    reader: StringCharReader,
+    /// Matcher and name of the ignored rule, if any.
+    ignored: Option<(&'a dyn MatchToken<StringCharReader>, Option<&'a str>)>,
+}
+
+impl<'a> LosslessBuilder<'a> {
+    pub fn new(source: &'a str, ignored: Option<(&'a dyn MatchToken<StringCharReader>, Option<&'a str>)>) -> Self {
+        Self { source, reader: StringCharReader::new(source), ignored }
+    }
+
+    /// Builds the tree of the root, extended to the whole source.
+    pub fn build(mut self, root: &ParseNode) -> Result<GreenNode, ParserError> {
+        let start = *root.span().start();
+        let end = *root.span().end();
+        let green = self.node(root)?;
+        if start.byte_offset() == 0 && end.byte_offset() == self.source.len() {
+            return Ok(green);
+        }
+
+        // The text around the root is kept in a node containing it
+        let mut children = Vec::new();
+        self.gap(Location::beginning(), start, &mut children)?;
+        children.push(Arc::new(green));
+        let mut source_end = end;
+        for c in self.source[end.byte_offset()..].chars() {
+            source_end.increment_for(c, self.reader.location_policy());
+        }
+        self.gap(end, source_end, &mut children)?;
+        Ok(GreenNode::rule("root", children))
+    }
+
+    fn node(&mut self, node: &ParseNode) -> Result<GreenNode, ParserError> {
+        let mut children = Vec::new();
+        let mut loc = *node.span().start();
+        for child in node.children() {
+            self.gap(loc, *child.span().start(), &mut children)?;
+            let is_trivia = self.ignored.is_some_and(|(_, name)| name == Some(child.rule()));
+            children.push(Arc::new(if is_trivia {
+                GreenNode::leaf(SyntaxKind::Trivia, child.text(self.source))
+            } else {
+                self.node(child)?
+            }));
+            loc = *child.span().end();
+        }
+        self.gap(loc, *node.span().end(), &mut children)?;
+        Ok(GreenNode::rule(node.rule(), children))
+    }
+
+    /// Splits the text between two locations in trivia, where the ignored rule matches, and tokens.
+    fn gap(&mut self, start: Location, end: Location, children: &mut Vec<Arc<GreenNode>>) -> Result<(), ParserError> {
+        let mut loc = start;
+        let mut token_start = start.byte_offset();
+        while loc.byte_offset() < end.byte_offset() {
+            let trivia = match self.ignored {
+                Some((ignored, _)) => ignored
+                    .test(&loc, &mut self.reader)?
+                    .filter(|info| !info.is_empty() && info.end().byte_offset() <= end.byte_offset()),
+                None => None,
+            };
+
+            match trivia {
+                Some(info) => {
+                    self.leaf(SyntaxKind::Token, token_start..loc.byte_offset(), children);
+                    self.leaf(SyntaxKind::Trivia, loc.byte_offset()..info.end().byte_offset(), children);
+                    loc = *info.end();
+                    token_start = loc.byte_offset();
+                }
+                None => {
+                    let c = self.source[loc.byte_offset()..].chars().next().unwrap_or_default();
+                    loc.increment_for(c, self.reader.location_policy());
+                }
+            }
+        }
+        self.leaf(SyntaxKind::Token, token_start..end.byte_offset(), children);
+        Ok(())
+    }
+
+    fn leaf(&self, kind: SyntaxKind, range: Range<usize>, children: &mut Vec<Arc<GreenNode>>) {
+        if !range.is_empty() {
+            children.push(Arc::new(GreenNode::leaf(kind, &self.source[range])));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        choice,
+        parser_lib::{GrammarBuilder, Rule},
+        seq, until, word,
+    };
+
+    use super::*;
+
+    /// Writes the kinds of the elements of the node, with the text of the leaves.
+    fn outline(node: &SyntaxNode) -> String {
+        match node.kind() {
+            SyntaxKind::Rule(rule) => {
+                let children: Vec<String> = node.children().iter().map(outline).collect();
+                format!("{}({})", rule, children.join(" "))
+            }
+            SyntaxKind::Token => format!("{:?}", node.text()),
+            SyntaxKind::Trivia => format!("~{:?}", node.text()),
+        }
+    }
+
+    #[test]
+    fn test_parse_lossless() {
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let comment = seq!(word!("#"), until!(word!("\n"), 0));
+        let ignored = choice!(word!(" "), word!("\n"), comment).at_least(1);
+        let number = builder.define("number", Rule::range('0', '9').at_least(1));
+        let plus = word!("+").padded(&ignored);
+        let sum = builder.define("sum", seq!(number, seq!(plus, number).at_least(0)).padded(&ignored));
+        builder.ignore(ignored);
+        let grammar = builder.save_root(sum);
+
+        let source = " 1 + 22 # two\n+3\n";
+        let tree = grammar.parse_lossless(source).unwrap().unwrap();
+        assert_eq!(tree.to_string(), source);
+        assert_eq!(
+            outline(&tree),
+            "sum(~\" \" number(\"1\") ~\" \" \"+\" ~\" \" number(\"22\") ~\" # two\\n\" \"+\" number(\"3\") ~\"\\n\")"
+        );
+
+        let number = &tree.children()[5];
+        assert_eq!(number.range(), 5..7);
+        assert_eq!(number.parent().unwrap().kind(), &SyntaxKind::Rule("sum".to_string()));
+
+        // The text after the root is kept
+        let tree = grammar.parse_lossless("1+2 ?").unwrap().unwrap();
+        assert_eq!(outline(&tree), "root(sum(number(\"1\") \"+\" number(\"2\") ~\" \") \"?\")");
+        assert!(matches!(grammar.parse_lossless("?"), Ok(None)));
+    }
+
+    #[test]
+    fn test_parse_lossless_named_ignored_rule() {
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let space = builder.define("space", word!(" ").at_least(1));
+        let word = builder.define("word", Rule::range('a', 'z').at_least(1));
+        let words = builder.define("words", seq!(word, seq!(space, word).at_least(0)));
+        builder.ignore(space);
+        let grammar = builder.save_root(words);
+
+        let tree = grammar.parse_lossless("ab  c").unwrap().unwrap();
+        assert_eq!(outline(&tree), "words(word(\"ab\") ~\"  \" word(\"c\"))");
+    }
+}
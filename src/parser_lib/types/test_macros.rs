@@ -0,0 +1,158 @@
+// Define assertion macros to test grammars and matchers, in this crate and in the ones using it
+
+#[allow(unused)]
+use crate::parser_lib::{MatchToken, ParseNode};
+
+/// Matches the input with the grammar (or any matcher), and checks that the whole input matches.
+#[macro_export]
+macro_rules! assert_parses {
+    ($grammar:expr, $input:expr $(,)?) => {{
+        use $crate::parser_lib::MatchToken as _;
+
+        let input: &str = $input;
+        let mut reader = $crate::parser_lib::StringCharReader::new(input);
+        match $grammar.test(&$crate::parser_lib::Location::beginning(), &mut reader) {
+            Ok(Some(info)) => assert_eq!(
+                info.end().byte_offset(),
+                input.len(),
+                "only {:?} was matched in {:?}",
+                &input[..info.end().byte_offset()],
+                input
+            ),
+            Ok(None) => panic!("{:?} was not matched", input),
+            Err(err) => panic!("{:?} could not be parsed: {}", input, err),
+        }
+    }};
+}
+
+/// Matches the input with the grammar (or any matcher), and checks that the input doesn't match, or only partly.
+///
+/// Errors are not rejections: they make the assertion fail.
+#[macro_export]
+macro_rules! assert_rejects {
+    ($grammar:expr, $input:expr $(,)?) => {{
+        use $crate::parser_lib::MatchToken as _;
+
+        let input: &str = $input;
+        let mut reader = $crate::parser_lib::StringCharReader::new(input);
+        match $grammar.test(&$crate::parser_lib::Location::beginning(), &mut reader) {
+            Ok(Some(info)) if info.end().byte_offset() == input.len() => panic!("{:?} was matched", input),
+            Ok(_) => {}
+            Err(err) => panic!("{:?} could not be parsed: {}", input, err),
+        }
+    }};
+}
+
+/// Matches the input with the rule (or any matcher), and checks the range of char indexes that matches, like `0..5`.
+#[macro_export]
+macro_rules! assert_span {
+    ($rule:expr, $input:expr, $expected:expr $(,)?) => {{
+        use $crate::parser_lib::MatchToken as _;
+
+        let input: &str = $input;
+        let mut reader = $crate::parser_lib::StringCharReader::new(input);
+        match $rule.test(&$crate::parser_lib::Location::beginning(), &mut reader) {
+            Ok(Some(info)) => assert_eq!(info.start().index()..info.end().index(), $expected, "wrong span in {:?}", input),
+            Ok(None) => panic!("{:?} was not matched", input),
+            Err(err) => panic!("{:?} could not be parsed: {}", input, err),
+        }
+    }};
+}
+
+/// Parses the input with the grammar, and checks that the whole input matches with the given tree, like `ParseNode::to_sexp`.
+///
+/// The expected tree can be indented on several lines, which makes golden files of grammars easy to write:
+///
+/// ```ignore
+/// assert_parse_tree!(grammar, "f(1)", r#"
+///     (call
+///         (ident "f")
+///         (args (number "1")))
+/// "#);
+/// ```
+#[macro_export]
+macro_rules! assert_parse_tree {
+    ($grammar:expr, $input:expr, $expected:expr $(,)?) => {{
+        let input: &str = $input;
+        let mut reader = $crate::parser_lib::StringCharReader::new(input);
+        match $grammar.parse_tree(&$crate::parser_lib::Location::beginning(), &mut reader) {
+            Ok(Some(tree)) => {
+                assert_eq!(
+                    tree.span().end().byte_offset(),
+                    input.len(),
+                    "only {:?} was matched in {:?}",
+                    tree.text(input),
+                    input
+                );
+                assert_eq!(tree.to_sexp(input), $crate::parser_lib::ParseNode::normalize_sexp($expected));
+            }
+            Ok(None) => panic!("{:?} was not matched", input),
+            Err(err) => panic!("{:?} could not be parsed: {}", input, err),
+        }
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser_lib::{GrammarBuilder, Rule, StrMatcher, StringCharReader},
+        seq, word,
+    };
+
+    #[test]
+    fn test_assert_parses() {
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let digit = builder.define("digit", Rule::range('0', '9'));
+        let grammar = builder.save_root(digit.at_least(1));
+
+        assert_parses!(grammar, "42");
+        assert_rejects!(grammar, "");
+        assert_rejects!(grammar, "4a");
+        assert_span!(grammar, "42a", 0..2);
+        assert_span!(digit, "42", 0..1);
+        assert_span!(StrMatcher::new("hé"), "héllo", 0..2);
+    }
+
+    #[test]
+    #[should_panic(expected = "only \"4\" was matched in \"4a\"")]
+    fn test_assert_parses_partial() {
+        assert_parses!(Rule::<StringCharReader>::range('0', '9'), "4a");
+    }
+
+    #[test]
+    #[should_panic(expected = "\"4\" was matched")]
+    fn test_assert_rejects_match() {
+        assert_rejects!(Rule::<StringCharReader>::range('0', '9'), "4");
+    }
+
+    #[test]
+    fn test_assert_parse_tree() {
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let ident = builder.define("ident", Rule::range('a', 'z'));
+        let number = builder.define("number", Rule::range('0', '9'));
+        let args = builder.define("args", seq!(word!("("), number, word!(")")));
+        let call = builder.define("call", seq!(ident, args));
+        let grammar = builder.save_root(call);
+
+        assert_parse_tree!(
+            grammar,
+            "f(1)",
+            r#"
+            (call
+                (ident "f")
+                (args (number "1")))
+            "#
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "only \"f(1)\" was matched")]
+    fn test_assert_parse_tree_partial() {
+        let mut builder = GrammarBuilder::<StringCharReader>::new();
+        let number = builder.define("number", Rule::range('0', '9'));
+        let call = builder.define("call", seq!(word!("f("), number, word!(")")));
+        let grammar = builder.save_root(call);
+
+        assert_parse_tree!(grammar, "f(1)f(2)", "(call (number \"1\"))");
+    }
+}
@@ -1,16 +1,97 @@
-use std::rc::Rc;
-
-use super::{MatchStr, MatchToken, Span};
+use std::sync::Arc;
+
+use super::{Location, MatchStr, MatchToken, Span, Symbol};
+
+/// Value of a token decoded from its text, like the number of an integer literal.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TokenValue {
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    String(String),
+}
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Token<T: PartialEq> {
     span: Span,
     token_type: T,
+    /// Trivia before the token, see `TriviaPolicy`.
+    leading_trivia: Vec<Span>,
+    /// Trivia after the token.
+    trailing_trivia: Vec<Span>,
+    /// Text of the token, interned by the lexer.
+    lexeme: Option<Symbol>,
+    value: Option<TokenValue>,
 }
 
 impl<T: PartialEq> Token<T> {
     pub fn new(span: Span, token_type: T) -> Self {
-        Self { span, token_type }
+        Self {
+            span,
+            token_type,
+            leading_trivia: Vec::new(),
+            trailing_trivia: Vec::new(),
+            lexeme: None,
+            value: None,
+        }
+    }
+
+    pub fn with_lexeme(mut self, lexeme: Symbol) -> Self {
+        self.lexeme = Some(lexeme);
+        self
+    }
+
+    pub fn with_value(mut self, value: TokenValue) -> Self {
+        self.value = Some(value);
+        self
+    }
+
+    /// Returns the symbol of the text of the token, which the interner of its lexer resolves.
+    pub fn lexeme(&self) -> Option<Symbol> {
+        self.lexeme
+    }
+
+    pub fn value(&self) -> Option<&TokenValue> {
+        self.value.as_ref()
+    }
+
+    /// Attaches the spans of the trivia before and after the token.
+    pub fn with_trivia(mut self, leading: Vec<Span>, trailing: Vec<Span>) -> Self {
+        self.leading_trivia = leading;
+        self.trailing_trivia = trailing;
+        self
+    }
+
+    pub(crate) fn add_trailing_trivia(&mut self, span: Span) {
+        self.trailing_trivia.push(span);
+    }
+
+    /// Returns the location of the start of the token, or of its leading trivia.
+    pub fn full_start(&self) -> &Location {
+        self.leading_trivia.first().map_or(self.span.start(), Span::start)
+    }
+
+    /// Returns the location of the end of the token, or of its trailing trivia.
+    pub fn full_end(&self) -> &Location {
+        self.trailing_trivia.last().map_or(self.span.end(), Span::end)
+    }
+
+    pub(crate) fn map_locations(&mut self, f: &impl Fn(&Location) -> Location) {
+        let map = |span: &Span| Span::new(f(span.start()), f(span.end()));
+        self.span = map(&self.span);
+        self.leading_trivia.iter_mut().for_each(|span| *span = map(span));
+        self.trailing_trivia.iter_mut().for_each(|span| *span = map(span));
+    }
+
+    pub fn leading_trivia(&self) -> &[Span] {
+        &self.leading_trivia
+    }
+
+    pub fn trailing_trivia(&self) -> &[Span] {
+        &self.trailing_trivia
     }
 
     pub fn span(&self) -> &Span {
@@ -25,31 +106,57 @@ impl<T: PartialEq> Token<T> {
 #[derive(Debug)]
 pub struct TokenType<R: MatchStr> {
     name: &'static str,
-    matcher: Rc<dyn MatchToken<R>>,
+    matcher: Arc<dyn MatchToken<R>>,
+    /// When several token types match the longest text, the one with the highest priority wins.
+    priority: i32,
 }
 impl<R: MatchStr> TokenType<R> {
-    pub fn new(name: &'static str, matcher: Rc<dyn MatchToken<R>>) -> Self {
-        Self { name, matcher }
+    pub fn new(name: &'static str, matcher: Arc<dyn MatchToken<R>>) -> Self {
+        Self { name, matcher, priority: 0 }
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn priority(&self) -> i32 {
+        self.priority
     }
 
     pub fn name(&self) -> &'static str {
         self.name
     }
 
-    pub fn matcher(&self) -> &Rc<dyn MatchToken<R>> {
+    pub fn matcher(&self) -> &Arc<dyn MatchToken<R>> {
         &self.matcher
     }
 }
 
+/// Defines the token types of a lexer, returned by the function `tokens::token_types`, in their order.
+///
+/// A token type can have a priority (0 by default), which wins the ties with the ones matching as much text:
+///
+/// ```ignore
+/// define_tokens! {
+///     StringCharReader,
+///     keyword: 1 => Rule::keywords(&["if", "else"]),
+///     name => range!('a', 'z').at_least(1)
+/// }
+/// let lexer = Lexer::new(reader).with_token_types(&tokens::token_types());
+/// ```
+#[macro_export]
 macro_rules! define_tokens {
-    ($R: ident, $($name: ident => $matcher: expr),*) => {
+    ($R: ty, $($name: ident $(: $priority: expr)? => $matcher: expr),* $(,)?) => {
         mod tokens {
             use super::*;
-            use crate::parser_lib::TokenType;
-            use std::rc::Rc;
+            use std::sync::Arc;
+            use $crate::parser_lib::TokenType;
 
             // Aggregate all the token types in a vector for easy iteration
-            pub const tokens: [TokenType<$R>; 2] = [$(TokenType::new(stringify!($name), Rc::new($matcher))),*];
+            pub fn token_types() -> Vec<TokenType<$R>> {
+                vec![$(TokenType::new(stringify!($name), Arc::new($matcher))$(.with_priority($priority))?),*]
+            }
         }
     };
 }
@@ -63,11 +170,10 @@ macro_rules! define_grammar_2 {
     ($language:ident, $body:expr) => {
         pub mod $language {
             use super::*;
-            use crate::parser_lib::Grammar;
-            use crate::parser_lib::GrammarBuilder;
-            use crate::parser_lib::MatchStr;
-            use crate::parser_lib::Rule;
-            use crate::parser_lib::Stream;
+            use $crate::parser_lib::Grammar;
+            use $crate::parser_lib::GrammarBuilder;
+            use $crate::parser_lib::MatchStr;
+            use $crate::parser_lib::Rule;
 
             // Create the function
             #[allow(unused)]
@@ -81,6 +187,7 @@ macro_rules! define_grammar_2 {
     };
 }
 
+#[allow(unused)]
 macro_rules! separation {
     ($lang_name: ident, {
         tokens => { $($tok_name:  ident => $tok_matcher:  expr),* }
@@ -93,8 +200,8 @@ macro_rules! separation {
 #[cfg(test)]
 mod tests {
     use crate::{
-        parser_lib::{Location, Rule, StrMatcher, StringCharReader},
-        word,
+        parser_lib::{Rule, StringCharReader},
+        range,
     };
 
     use super::*;
@@ -122,19 +229,21 @@ mod tests {
 
     #[test]
     fn test_define() {
-        // define_tokens! {
-        //     StringCharReader,
-        //     token1 => Rule::word("hello"),
-        //     token2 => Rule::word("world")
-        // }
+        define_tokens! {
+            StringCharReader,
+            keyword: 1 => Rule::word("hello"),
+            name => range!('a', 'z').at_least(1),
+        }
 
-        // println!("{:?}", tokens::tokens);
+        let token_types = tokens::token_types();
+        let names: Vec<(&str, i32)> = token_types.iter().map(|t| (t.name(), t.priority())).collect();
+        assert_eq!(names, [("keyword", 1), ("name", 0)]);
 
         separation! {
             almora, {
                 tokens => {
                     token1 => word("hello"),
-                    token2 => word("world"),
+                    token2 => word("world")
                 }
                 rules => {
                     rule1 => seq!(token1, token2)
@@ -0,0 +1,63 @@
+use std::{collections::VecDeque, sync::mpsc::Sender};
+
+use super::{ParserError, Span, Token};
+
+/// Output of a lexer, given in the order of the input.
+///
+/// Only the tokens are required: the trivia (like whitespace or comments) and the errors are dropped by default.
+pub trait TokenSink<T: PartialEq> {
+    fn push_token(&mut self, token: Token<T>);
+
+    /// Receives the span of some trivia, which is not a token but is still a part of the input.
+    fn push_trivia(&mut self, _span: Span) {}
+
+    /// Receives the error that stopped the lexer. It is also returned by the lexer.
+    ///
+    /// The errors of the chars put in error tokens are not given, since the lexer goes on, see `Lexer::errors`.
+    fn push_error(&mut self, _error: &ParserError) {}
+}
+
+/// Item written by a lexer, for the sinks that keep everything.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenEvent<T: PartialEq> {
+    Token(Token<T>),
+    Trivia(Span),
+    Error(ParserError),
+}
+
+/// Collects the tokens, to tokenize a whole input at once.
+impl<T: PartialEq> TokenSink<T> for Vec<Token<T>> {
+    fn push_token(&mut self, token: Token<T>) {
+        self.push(token);
+    }
+}
+
+/// Buffers everything, to read the items one by one as they are lexed.
+impl<T: PartialEq> TokenSink<T> for VecDeque<TokenEvent<T>> {
+    fn push_token(&mut self, token: Token<T>) {
+        self.push_back(TokenEvent::Token(token));
+    }
+
+    fn push_trivia(&mut self, span: Span) {
+        self.push_back(TokenEvent::Trivia(span));
+    }
+
+    fn push_error(&mut self, error: &ParserError) {
+        self.push_back(TokenEvent::Error(error.clone()));
+    }
+}
+
+/// Sends everything to another thread. If the receiver is gone, the items are dropped.
+impl<T: PartialEq> TokenSink<T> for Sender<TokenEvent<T>> {
+    fn push_token(&mut self, token: Token<T>) {
+        let _ = self.send(TokenEvent::Token(token));
+    }
+
+    fn push_trivia(&mut self, span: Span) {
+        let _ = self.send(TokenEvent::Trivia(span));
+    }
+
+    fn push_error(&mut self, error: &ParserError) {
+        let _ = self.send(TokenEvent::Error(error.clone()));
+    }
+}
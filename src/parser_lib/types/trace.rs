@@ -0,0 +1,120 @@
+use std::{
+    fmt::{Debug, Display, Formatter},
+    sync::Arc,
+};
+
+use super::{Location, ParserError, Span};
+
+/// Outcome of a named rule, in a trace.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceOutcome {
+    Matched(Span),
+    NoMatch,
+    Error(ParserError),
+}
+
+/// Which events are sent to a tracer, from the fewest to the most. See `ParseOptions::with_trace_level`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum TraceLevel {
+    /// Only the exits of the rules that returned an error.
+    Errors,
+    /// The exits of the rules that didn't match, or returned an error.
+    Failures,
+    /// The entry and exit of every named rule.
+    #[default]
+    Rules,
+}
+
+/// Event sent to a tracer when a named rule is entered or exited.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceEvent<'a> {
+    /// The rule is about to be matched at the given location.
+    Enter { rule: &'a str, location: Location, depth: usize },
+    /// The rule was matched at the given location, with the given outcome.
+    Exit { rule: &'a str, location: Location, depth: usize, outcome: TraceOutcome },
+}
+
+impl TraceEvent<'_> {
+    /// Returns the lowest trace level that sends the event.
+    pub fn level(&self) -> TraceLevel {
+        match self {
+            TraceEvent::Exit { outcome: TraceOutcome::Error(_), .. } => TraceLevel::Errors,
+            TraceEvent::Exit { outcome: TraceOutcome::NoMatch, .. } => TraceLevel::Failures,
+            _ => TraceLevel::Rules,
+        }
+    }
+
+    /// Returns the number of rules that contain this one.
+    pub fn depth(&self) -> usize {
+        match self {
+            TraceEvent::Enter { depth, .. } | TraceEvent::Exit { depth, .. } => *depth,
+        }
+    }
+}
+
+impl Display for TraceEvent<'_> {
+    /// Writes the event on a single line, indented by its depth, like `  > expr at 1:3` or `  < expr at 1:3: 1:3-1:5`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:indent$}", "", indent = 2 * self.depth())?;
+        match self {
+            TraceEvent::Enter { rule, location, .. } => write!(f, "> {} at {}", rule, location),
+            TraceEvent::Exit { rule, location, outcome, .. } => {
+                write!(f, "< {} at {}: ", rule, location)?;
+                match outcome {
+                    TraceOutcome::Matched(span) => write!(f, "{}", span),
+                    TraceOutcome::NoMatch => write!(f, "no match"),
+                    TraceOutcome::Error(err) => write!(f, "error: {}", err),
+                }
+            }
+        }
+    }
+}
+
+/// Callback receiving the trace of a parse, to debug why a rule didn't match.
+///
+/// ```ignore
+/// builder.trace(Tracer::new(|event| eprintln!("{}", event)));
+/// ```
+#[derive(Clone)]
+pub struct Tracer(Arc<dyn Fn(&TraceEvent) + Send + Sync>);
+
+impl Tracer {
+    pub fn new(f: impl Fn(&TraceEvent) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    /// Creates a tracer that writes the events to the standard error.
+    pub fn stderr() -> Self {
+        Self::new(|event| eprintln!("{}", event))
+    }
+
+    pub(crate) fn send(&self, event: &TraceEvent) {
+        (self.0)(event)
+    }
+}
+
+impl Debug for Tracer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Tracer")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_event_display() {
+        let location = Location::new(1, 3, 2);
+        let event = TraceEvent::Enter { rule: "expr", location, depth: 1 };
+        assert_eq!(event.to_string(), "  > expr at 1:3");
+
+        let outcome = TraceOutcome::Matched(Span::new(location, Location::new(1, 5, 4)));
+        let event = TraceEvent::Exit { rule: "expr", location, depth: 0, outcome };
+        assert_eq!(event.to_string(), "< expr at 1:3: 1:3-1:5");
+
+        let event = TraceEvent::Exit { rule: "expr", location, depth: 2, outcome: TraceOutcome::NoMatch };
+        assert_eq!(event.to_string(), "    < expr at 1:3: no match");
+        assert_eq!(event.level(), TraceLevel::Failures);
+    }
+}
@@ -0,0 +1,42 @@
+use crate::{choice, define_grammar, opt, range, seq, word};
+
+define_grammar!(calculator, |grammar: &mut GrammarBuilder<R>| {
+    // ===== Tokens =====
+    let ws = choice![word!(" "), word!("\t"), word!("\n"), word!("\r")].at_least(0);
+    let digits = range!('0', '9').at_least(1);
+    let number = seq!(digits, opt!(seq!(word!("."), digits)));
+    let additive = choice![word!("+"), word!("-")];
+    let multiplicative = choice![word!("*"), word!("/"), word!("%")];
+
+    // ===== Expressions =====
+    // Any expression can be wrapped in parentheses
+    let expr = grammar.rule("expr");
+    let primary = choice![number, seq!(word!("("), ws, expr, ws, word!(")"))];
+    let factor = seq!(opt!(seq!(word!("-"), ws)), primary);
+    let term = seq!(factor, seq!(ws, multiplicative, ws, factor).at_least(0));
+    let expr = grammar.define("expr", seq!(term, seq!(ws, additive, ws, term).at_least(0)));
+
+    // Save the root rule.
+    seq!(ws, expr, ws)
+});
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser_lib::StringCharReader, test_grammars::matches_fully};
+
+    use super::*;
+
+    #[test]
+    fn test_calculator() {
+        let grammar = calculator::define_grammar::<StringCharReader>();
+
+        assert_eq!(matches_fully(&grammar, "1"), Ok(true));
+        assert_eq!(matches_fully(&grammar, " 1 + 2 * 3.5 "), Ok(true));
+        assert_eq!(matches_fully(&grammar, "-(1 + 2) % (4 / (2 - -1))"), Ok(true));
+        assert_eq!(matches_fully(&grammar, &format!("{}1{}", "(".repeat(20), ")".repeat(20))), Ok(true));
+
+        assert_eq!(matches_fully(&grammar, "1 +"), Ok(false));
+        assert_eq!(matches_fully(&grammar, "(1 + 2"), Ok(false));
+        assert_eq!(matches_fully(&grammar, "1 2"), Ok(false));
+    }
+}
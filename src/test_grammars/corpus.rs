@@ -0,0 +1,87 @@
+/// Sample input of an example grammar.
+#[derive(Debug)]
+pub struct CorpusFile {
+    /// Name of the grammar the file is written for.
+    pub grammar: &'static str,
+    /// Name of the file in the corpus directory.
+    pub name: &'static str,
+    /// Whether the grammar should accept the file.
+    pub valid: bool,
+    pub source: &'static str,
+}
+
+// The files are embedded so that the corpus is available wherever the crate is used
+macro_rules! corpus_file {
+    ($grammar:literal, valid, $name:literal) => {
+        corpus_file!($grammar, "valid", $name, true)
+    };
+    ($grammar:literal, invalid, $name:literal) => {
+        corpus_file!($grammar, "invalid", $name, false)
+    };
+    ($grammar:literal, $dir:literal, $name:literal, $valid:expr) => {
+        CorpusFile {
+            grammar: $grammar,
+            name: $name,
+            valid: $valid,
+            source: include_str!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/corpus/",
+                $grammar,
+                "/",
+                $dir,
+                "/",
+                $name
+            )),
+        }
+    };
+}
+
+const CORPUS: &[CorpusFile] = &[
+    corpus_file!("calculator", valid, "arithmetic.txt"),
+    corpus_file!("calculator", valid, "nested.txt"),
+    corpus_file!("calculator", invalid, "dangling_operator.txt"),
+    corpus_file!("calculator", invalid, "unbalanced.txt"),
+    corpus_file!("ini", valid, "config.ini"),
+    corpus_file!("ini", valid, "comments.ini"),
+    corpus_file!("ini", invalid, "unclosed_section.ini"),
+    corpus_file!("json", valid, "package.json"),
+    corpus_file!("json", valid, "scalars.json"),
+    corpus_file!("json", invalid, "trailing_comma.json"),
+    corpus_file!("json", invalid, "single_quotes.json"),
+    corpus_file!("mini", valid, "countdown.mini"),
+    corpus_file!("mini", valid, "fizzbuzz.mini"),
+    corpus_file!("mini", invalid, "missing_semicolon.mini"),
+];
+
+/// Returns every file of the corpus.
+pub fn corpus() -> &'static [CorpusFile] {
+    CORPUS
+}
+
+/// Returns the files of the corpus written for the given grammar.
+pub fn corpus_for(grammar: &str) -> impl Iterator<Item = &'static CorpusFile> + '_ {
+    CORPUS.iter().filter(move |file| file.grammar == grammar)
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use crate::test_grammars::{grammar, matches_fully, GRAMMARS};
+
+    use super::*;
+
+    #[test]
+    fn test_corpus() {
+        for name in GRAMMARS {
+            let grammar = grammar(name).unwrap();
+
+            // Every grammar has at least one valid and one invalid file
+            assert_eq!(corpus_for(name).any(|file| file.valid), true);
+            assert_eq!(corpus_for(name).any(|file| !file.valid), true);
+
+            for file in corpus_for(name) {
+                assert_eq!(matches_fully(&grammar, file.source), Ok(file.valid), "{}/{}", name, file.name);
+            }
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use crate::{choice, define_grammar, opt, range, seq, until, word};
+
+define_grammar!(ini, |_grammar: &mut GrammarBuilder<R>| {
+    // ===== Tokens =====
+    let spaces = choice![word!(" "), word!("\t")].at_least(0);
+    let line_end = choice![word!("\n"), word!("\r\n")];
+    let name = choice![
+        range!('a', 'z'),
+        range!('A', 'Z'),
+        range!('0', '9'),
+        word!("_"),
+        word!("-"),
+        word!(".")
+    ]
+    .at_least(1);
+
+    // ===== Lines =====
+    let comment = seq!(choice![word!(";"), word!("#")], until!(line_end, 0));
+    let section = seq!(word!("["), spaces, name, spaces, word!("]"), spaces);
+    let value = until!(line_end, 0);
+    let pair = seq!(name, spaces, word!("="), spaces, value);
+
+    // Blank lines are allowed anywhere
+    let line = seq!(spaces, opt!(choice![section, pair, comment]));
+
+    // Save the root rule.
+    seq!(line, seq!(line_end, line).at_least(0))
+});
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser_lib::StringCharReader, test_grammars::matches_fully};
+
+    use super::*;
+
+    #[test]
+    fn test_ini() {
+        let grammar = ini::define_grammar::<StringCharReader>();
+
+        assert_eq!(matches_fully(&grammar, "[section]\nkey = value"), Ok(true));
+        assert_eq!(matches_fully(&grammar, "; comment\r\n\r\n  a.b=c # not a comment\n"), Ok(true));
+
+        // Unclosed section and missing "="
+        assert_eq!(matches_fully(&grammar, "[section\nkey = value"), Ok(false));
+        assert_eq!(matches_fully(&grammar, "[section]\nkey value"), Ok(false));
+    }
+}
@@ -0,0 +1,83 @@
+use crate::{choice, define_grammar, not, opt, range, seq, word};
+
+define_grammar!(json, |grammar: &mut GrammarBuilder<R>| {
+    // ===== Tokens =====
+    let ws = choice![word!(" "), word!("\t"), word!("\n"), word!("\r")].at_least(0);
+    let quote = word!("\"");
+    let backslash = word!("\\");
+    let comma = seq!(ws, word!(","), ws);
+    let colon = seq!(ws, word!(":"), ws);
+
+    // Strings
+    let hex = choice![range!('0', '9'), range!('a', 'f'), range!('A', 'F')];
+    let escaped = choice![
+        word!("\""),
+        word!("\\"),
+        word!("/"),
+        word!("b"),
+        word!("f"),
+        word!("n"),
+        word!("r"),
+        word!("t"),
+        seq!(word!("u"), hex, hex, hex, hex)
+    ];
+    let escape = seq!(backslash, escaped);
+    // Any char except control chars, quotes and backslashes
+    let plain = seq!(not!(choice![quote, backslash]), range!('\u{20}', '\u{10FFFF}'));
+    let string = seq!(quote, choice![escape, plain].at_least(0), quote);
+
+    // Numbers
+    let digits = range!('0', '9').at_least(1);
+    let integer = choice![word!("0"), seq!(range!('1', '9'), range!('0', '9').at_least(0))];
+    let fraction = seq!(word!("."), digits);
+    let exponent = seq!(
+        choice![word!("e"), word!("E")],
+        opt!(choice![word!("+"), word!("-")]),
+        digits
+    );
+    let number = seq!(opt!(word!("-")), integer, opt!(fraction), opt!(exponent));
+
+    let scalar = choice![string, number, word!("true"), word!("false"), word!("null")];
+
+    // ===== Containers =====
+    // The containers can contain any value, including other containers
+    let value = grammar.rule("value");
+    let elements = seq!(value, seq!(comma, value).at_least(0));
+    let array = grammar.define("array", seq!(word!("["), ws, opt!(elements), ws, word!("]")));
+
+    let member = seq!(string, colon, value);
+    let members = seq!(member, seq!(comma, member).at_least(0));
+    let object = grammar.define("object", seq!(word!("{"), ws, opt!(members), ws, word!("}")));
+
+    let value = grammar.define("value", choice![object, array, scalar]);
+
+    // Save the root rule.
+    seq!(ws, value, ws)
+});
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser_lib::StringCharReader, test_grammars::matches_fully};
+
+    use super::*;
+
+    #[test]
+    fn test_json() {
+        let grammar = json::define_grammar::<StringCharReader>();
+
+        assert_eq!(matches_fully(&grammar, "null"), Ok(true));
+        assert_eq!(matches_fully(&grammar, "-12.5e+3"), Ok(true));
+        assert_eq!(matches_fully(&grammar, "\"a \\\"quoted\\\" \\u00e9\""), Ok(true));
+        assert_eq!(matches_fully(&grammar, "[1, [2, {\"a\": []}], {}]"), Ok(true));
+
+        // Leading zeros, trailing commas and bare words are rejected
+        assert_eq!(matches_fully(&grammar, "012"), Ok(false));
+        assert_eq!(matches_fully(&grammar, "[1, 2,]"), Ok(false));
+        assert_eq!(matches_fully(&grammar, "{a: 1}"), Ok(false));
+
+        // The containers can be nested at any depth
+        assert_eq!(matches_fully(&grammar, "[[[[[1]]]]]"), Ok(true));
+        assert_eq!(matches_fully(&grammar, &format!("{}{}", "[{\"a\": ".repeat(20), "}]".repeat(20))), Ok(false));
+        assert_eq!(matches_fully(&grammar, &format!("{}1{}", "[{\"a\": ".repeat(20), "}]".repeat(20))), Ok(true));
+    }
+}
@@ -0,0 +1,81 @@
+use crate::{choice, define_grammar, opt, range, seq, until, word};
+
+define_grammar!(mini, |grammar: &mut GrammarBuilder<R>| {
+    // ===== Ignored =====
+    let whitespace = choice![word!(" "), word!("\t"), word!("\n"), word!("\r")];
+    let line_comment = seq!(word!("//"), until!(word!("\n"), 0));
+    let ws = choice![whitespace, line_comment].at_least(0);
+    // Mandatory separation after keywords
+    let sep = seq!(whitespace, ws);
+
+    // ===== Tokens =====
+    let letter = choice![range!('a', 'z'), range!('A', 'Z'), word!("_")];
+    let identifier = seq!(letter, choice![letter, range!('0', '9')].at_least(0));
+    let number = range!('0', '9').at_least(1);
+    let string = seq!(word!("\""), until!(word!("\""), 0), word!("\""));
+    let semicolon = seq!(ws, word!(";"));
+
+    // ===== Expressions =====
+    let comparison = choice![
+        word!("=="),
+        word!("!="),
+        word!("<="),
+        word!(">="),
+        word!("<"),
+        word!(">")
+    ];
+    let additive = choice![word!("+"), word!("-")];
+    let multiplicative = choice![word!("*"), word!("/")];
+
+    let expr = grammar.rule("expr");
+    let primary = choice![number, string, identifier, seq!(word!("("), ws, expr, ws, word!(")"))];
+    let term = seq!(primary, seq!(ws, multiplicative, ws, primary).at_least(0));
+    let sum = seq!(term, seq!(ws, additive, ws, term).at_least(0));
+    let expr = grammar.define("expr", seq!(sum, opt!(seq!(ws, comparison, ws, sum))));
+
+    // ===== Statements =====
+    let let_stmt = seq!(word!("let"), sep, identifier, ws, word!("="), ws, expr, semicolon);
+    let assign_stmt = seq!(identifier, ws, word!("="), ws, expr, semicolon);
+    let print_stmt = seq!(word!("print"), sep, expr, semicolon);
+
+    // Blocks of statements can be nested in control flow statements
+    let statement = grammar.rule("statement");
+    let block = seq!(word!("{"), ws, seq!(statement, ws).at_least(0), word!("}"));
+    let while_stmt = seq!(word!("while"), sep, expr, ws, block);
+    let else_branch = seq!(ws, word!("else"), ws, block);
+    let if_stmt = seq!(word!("if"), sep, expr, ws, block, opt!(else_branch));
+    let statement = grammar.define("statement", choice![while_stmt, if_stmt, let_stmt, print_stmt, assign_stmt]);
+
+    // Save the root rule.
+    seq!(ws, seq!(statement, ws).at_least(0))
+});
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser_lib::StringCharReader, test_grammars::matches_fully};
+
+    use super::*;
+
+    #[test]
+    fn test_mini() {
+        let grammar = mini::define_grammar::<StringCharReader>();
+
+        assert_eq!(matches_fully(&grammar, ""), Ok(true));
+        assert_eq!(matches_fully(&grammar, "let x = (1 + 2) * y;"), Ok(true));
+        assert_eq!(matches_fully(&grammar, "// hello\nprint \"hello\";\n"), Ok(true));
+        assert_eq!(
+            matches_fully(&grammar, "while x > 0 {\n    x = x - 1;\n    if x == 2 { print x; } else { }\n}"),
+            Ok(true)
+        );
+
+        assert_eq!(
+            matches_fully(&grammar, &format!("{}let x = ((((1))));{}", "if x { while y { ".repeat(5), "} }".repeat(5))),
+            Ok(true)
+        );
+
+        assert_eq!(matches_fully(&grammar, "let x = 1"), Ok(false));
+        assert_eq!(matches_fully(&grammar, "letx = 1;"), Ok(true));
+        assert_eq!(matches_fully(&grammar, "let 1 = x;"), Ok(false));
+        assert_eq!(matches_fully(&grammar, "while x { print x; "), Ok(false));
+    }
+}
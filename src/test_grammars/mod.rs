@@ -0,0 +1,42 @@
+//! Example grammars with a corpus of sample inputs.
+//!
+//! They are used as integration tests of the matchers and as benchmark subjects,
+//! and are exposed behind the `test-grammars` feature so that downstream users can test against them.
+
+mod calculator_grammar;
+mod corpus;
+mod ini_grammar;
+mod json_grammar;
+mod mini_grammar;
+
+pub use calculator_grammar::calculator;
+pub use corpus::{corpus, corpus_for, CorpusFile};
+pub use ini_grammar::ini;
+pub use json_grammar::json;
+pub use mini_grammar::mini;
+
+use crate::parser_lib::{Grammar, Location, MatchToken, ParserError, StringCharReader};
+
+/// Names of the grammars that have a corpus.
+pub const GRAMMARS: [&str; 4] = ["calculator", "ini", "json", "mini"];
+
+/// Defines the example grammar with the given name, if it exists.
+pub fn grammar(name: &str) -> Option<Grammar<StringCharReader>> {
+    match name {
+        "calculator" => Some(calculator::define_grammar()),
+        "ini" => Some(ini::define_grammar()),
+        "json" => Some(json::define_grammar()),
+        "mini" => Some(mini::define_grammar()),
+        _ => None,
+    }
+}
+
+/// Returns true if the grammar matches the whole source, and not only the beginning of it.
+pub fn matches_fully(grammar: &Grammar<StringCharReader>, source: &str) -> Result<bool, ParserError> {
+    let mut reader = StringCharReader::new(source);
+
+    match grammar.test(&Location::beginning(), &mut reader)? {
+        Some(info) => Ok(info.len() == source.chars().count()),
+        None => Ok(false),
+    }
+}
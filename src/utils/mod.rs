@@ -1,3 +1,5 @@
 mod ring_buffer;
+mod utf8_indices;
 
-pub use ring_buffer::RingBuffer;
+pub use ring_buffer::{RingBuffer, RingBufferError};
+pub use utf8_indices::Utf8Indices;
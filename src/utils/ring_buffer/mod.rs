@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 mod ring_buffer;
 mod ring_buffer_error;
 
@@ -1,9 +1,10 @@
 use super::RingBufferError;
-use std::fmt::{Debug, Display};
 
-/// Ring buffer for storing values.
+/// Ring buffer for storing values, like the chars loaded by a reader or the tokens of a tokenizer.
+///
+/// Its capacity is fixed, unless it is explicitly grown with `grow`.
 #[derive(Debug)]
-pub struct RingBuffer<T: Copy + Clone + Debug + Display> {
+pub struct RingBuffer<T: Clone> {
     buf: Vec<Option<T>>,
     /// Where to read from next
     read_pos: usize,
@@ -11,15 +12,18 @@ pub struct RingBuffer<T: Copy + Clone + Debug + Display> {
     write_pos: usize,
     /// Number of chars in the buffer.
     size: usize,
+    /// Number of popped chars that were not overwritten yet, just before read_pos.
+    behind: usize,
 }
 
-impl<T: Copy + Clone + Debug + Display> RingBuffer<T> {
+impl<T: Clone> RingBuffer<T> {
     pub fn new(capacity: usize) -> Self {
         RingBuffer {
             buf: vec![None; capacity],
             read_pos: 0,
             write_pos: 0,
             size: 0,
+            behind: 0,
         }
     }
 
@@ -27,7 +31,8 @@ impl<T: Copy + Clone + Debug + Display> RingBuffer<T> {
 
     #[inline]
     pub fn capacity(&self) -> usize {
-        self.buf.capacity()
+        // The vec is never resized, its len is the capacity of the buffer
+        self.buf.len()
     }
 
     #[inline]
@@ -36,11 +41,16 @@ impl<T: Copy + Clone + Debug + Display> RingBuffer<T> {
     }
 
     // Methods
-    pub fn push(&mut self, c: T) -> Result<(), RingBufferError<T>> {
+    pub fn push_back(&mut self, c: T) -> Result<(), RingBufferError<T>> {
         if self.size() == self.capacity() {
             return Err(RingBufferError::NotEnoughSpace(c));
         }
 
+        // If all the free slots hold popped chars, the oldest one is overwritten
+        if self.behind == self.capacity() - self.size() {
+            self.behind -= 1;
+        }
+
         self.buf[self.write_pos] = Some(c);
         // Increase write_pos and size and wrap around if necessary
         self.write_pos += 1;
@@ -53,12 +63,12 @@ impl<T: Copy + Clone + Debug + Display> RingBuffer<T> {
         Ok(())
     }
 
-    pub fn pop(&mut self) -> Option<T> {
+    pub fn pop_front(&mut self) -> Option<T> {
         if self.size() == 0 {
             return None;
         }
 
-        let c = self.buf[self.read_pos];
+        let c = self.buf[self.read_pos].clone();
         // Increase read_pos and size and wrap around if necessary
         self.read_pos += 1;
         if self.read_pos == self.capacity() {
@@ -66,16 +76,58 @@ impl<T: Copy + Clone + Debug + Display> RingBuffer<T> {
         }
         // Decrease size
         self.size -= 1;
+        // The char stays in the buffer until it is overwritten
+        self.behind += 1;
 
         c
     }
 
+    /// Returns the chars of the buffer, from the next one to pop to the last pushed one.
+    /// Since the buffer wraps around, they are split in two contiguous slices. The second one may be empty.
+    pub fn as_slices(&self) -> (&[Option<T>], &[Option<T>]) {
+        let end = self.read_pos + self.size;
+        if end <= self.capacity() {
+            (&self.buf[self.read_pos..end], &[])
+        } else {
+            (&self.buf[self.read_pos..], &self.buf[..end - self.capacity()])
+        }
+    }
+
+    /// Returns the number of popped chars that are still in the buffer.
+    #[inline]
+    pub fn behind(&self) -> usize {
+        self.behind
+    }
+
+    /// Returns the nth popped char, starting from the last one (n = 1), if it was not overwritten yet.
+    pub fn peek_back(&self, n: usize) -> Option<T> {
+        if n == 0 || n > self.behind {
+            return None;
+        }
+
+        let pos = (self.read_pos + self.capacity() - n) % self.capacity();
+        self.buf[pos].clone()
+    }
+
+    /// Puts back the last n popped chars, if they were not overwritten yet.
+    /// Returns false if it is not possible, in which case nothing is changed.
+    pub fn unpop(&mut self, n: usize) -> bool {
+        if n > self.behind {
+            return false;
+        }
+
+        self.read_pos = (self.read_pos + self.capacity() - n) % self.capacity();
+        self.size += n;
+        self.behind -= n;
+        true
+    }
+
     pub fn peek(&self) -> Option<T> {
         if self.size() == 0 {
             return None;
         }
 
-        self.buf[self.read_pos]
+        self.buf[self.read_pos].clone()
     }
 
     pub fn peek_nth(&self, n: usize) -> Option<T> {
@@ -88,11 +140,29 @@ impl<T: Copy + Clone + Debug + Display> RingBuffer<T> {
         }
 
         let pos = (self.read_pos + n) % self.capacity();
-        self.buf[pos]
+        self.buf[pos].clone()
+    }
+
+    /// Increases the capacity of the buffer by `additional` values.
+    ///
+    /// The values are kept in order, including the popped ones that were not overwritten yet.
+    pub fn grow(&mut self, additional: usize) {
+        let capacity = self.capacity();
+        let kept = self.behind + self.size;
+        let first = (self.read_pos + capacity - self.behind) % capacity.max(1);
+
+        let mut buf = Vec::with_capacity(capacity + additional);
+        buf.extend((0..kept).map(|i| self.buf[(first + i) % capacity].take()));
+        buf.resize(capacity + additional, None);
+
+        self.buf = buf;
+        self.read_pos = self.behind;
+        self.write_pos = kept % self.capacity().max(1);
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
@@ -118,33 +188,33 @@ mod tests {
 
         assert_eq!(cb.size(), 0);
 
-        assert_eq!(cb.push('h').is_ok(), true);
+        assert_eq!(cb.push_back('h').is_ok(), true);
         assert_eq!(cb.size(), 1);
         assert_eq!(cb.write_pos, 3);
         assert_eq!(cb.buf[2], Some('h'));
 
-        assert_eq!(cb.push('e').is_ok(), true);
+        assert_eq!(cb.push_back('e').is_ok(), true);
         assert_eq!(cb.size(), 2);
         assert_eq!(cb.write_pos, 4);
         assert_eq!(cb.buf[3], Some('e'));
 
-        assert_eq!(cb.push('l').is_ok(), true);
+        assert_eq!(cb.push_back('l').is_ok(), true);
         assert_eq!(cb.size(), 3);
         assert_eq!(cb.write_pos, 0);
         assert_eq!(cb.buf[4], Some('l'));
 
-        assert_eq!(cb.push('l').is_ok(), true);
+        assert_eq!(cb.push_back('l').is_ok(), true);
         assert_eq!(cb.size(), 4);
         assert_eq!(cb.write_pos, 1);
         assert_eq!(cb.buf[0], Some('l'));
 
-        assert_eq!(cb.push('o').is_ok(), true);
+        assert_eq!(cb.push_back('o').is_ok(), true);
         assert_eq!(cb.size(), 5);
         assert_eq!(cb.write_pos, 2);
         assert_eq!(cb.buf[1], Some('o'));
 
         // Now we should be full
-        assert_eq!(cb.push('!').is_ok(), false);
+        assert_eq!(cb.push_back('!').is_ok(), false);
     }
 
     #[test]
@@ -157,45 +227,45 @@ mod tests {
 
         // First, its empty
         assert_eq!(cb.size(), 0);
-        assert_eq!(cb.pop().is_none(), true);
+        assert_eq!(cb.pop_front().is_none(), true);
 
         // Now we push some chars
-        assert_eq!(cb.push('h').is_ok(), true);
-        assert_eq!(cb.push('e').is_ok(), true);
+        assert_eq!(cb.push_back('h').is_ok(), true);
+        assert_eq!(cb.push_back('e').is_ok(), true);
 
         // Now we should have 2 chars
         assert_eq!(cb.size(), 2);
 
         // Pop the first char
-        assert_eq!(cb.pop().unwrap(), 'h');
+        assert_eq!(cb.pop_front().unwrap(), 'h');
         assert_eq!(cb.size(), 1);
         assert_eq!(cb.read_pos, 3);
 
         // Pop the second char
-        assert_eq!(cb.pop().unwrap(), 'e');
+        assert_eq!(cb.pop_front().unwrap(), 'e');
         assert_eq!(cb.size(), 0);
         assert_eq!(cb.read_pos, 4);
 
         // Now we should be empty
-        assert_eq!(cb.pop().is_none(), true);
+        assert_eq!(cb.pop_front().is_none(), true);
 
         // Now we push some more chars
-        assert_eq!(cb.push('h').is_ok(), true);
-        assert_eq!(cb.push('e').is_ok(), true);
-        assert_eq!(cb.push('l').is_ok(), true);
-        assert_eq!(cb.push('l').is_ok(), true);
-        assert_eq!(cb.push('o').is_ok(), true);
+        assert_eq!(cb.push_back('h').is_ok(), true);
+        assert_eq!(cb.push_back('e').is_ok(), true);
+        assert_eq!(cb.push_back('l').is_ok(), true);
+        assert_eq!(cb.push_back('l').is_ok(), true);
+        assert_eq!(cb.push_back('o').is_ok(), true);
 
         // Now we should have 5 chars
         assert_eq!(cb.size(), 5);
 
         // Pop the first char
-        assert_eq!(cb.pop().unwrap(), 'h');
+        assert_eq!(cb.pop_front().unwrap(), 'h');
         assert_eq!(cb.size(), 4);
         assert_eq!(cb.read_pos, 0);
 
         // Pop the second char
-        assert_eq!(cb.pop().unwrap(), 'e');
+        assert_eq!(cb.pop_front().unwrap(), 'e');
         assert_eq!(cb.size(), 3);
         assert_eq!(cb.read_pos, 1);
     }
@@ -213,8 +283,8 @@ mod tests {
         assert_eq!(cb.peek().is_none(), true);
 
         // Now we push some chars
-        assert_eq!(cb.push('h').is_ok(), true);
-        assert_eq!(cb.push('e').is_ok(), true);
+        assert_eq!(cb.push_back('h').is_ok(), true);
+        assert_eq!(cb.push_back('e').is_ok(), true);
 
         // Now we should have 2 chars
         assert_eq!(cb.size(), 2);
@@ -235,4 +305,78 @@ mod tests {
 
         assert_eq!(cb.peek_nth(2).is_none(), true);
     }
+
+    #[test]
+    fn test_unpop() {
+        let mut cb = RingBuffer::new(4);
+        for c in "hey".chars() {
+            cb.push_back(c).unwrap();
+        }
+
+        // Popped chars can be put back
+        assert_eq!(cb.pop_front(), Some('h'));
+        assert_eq!(cb.pop_front(), Some('e'));
+        assert_eq!(cb.unpop(2), true);
+        assert_eq!(cb.peek(), Some('h'));
+        assert_eq!(cb.size(), 3);
+
+        // Until they are overwritten
+        assert_eq!(cb.pop_front(), Some('h'));
+        assert_eq!(cb.pop_front(), Some('e'));
+        cb.push_back('!').unwrap();
+        cb.push_back('?').unwrap();
+        assert_eq!(cb.unpop(2), false);
+        assert_eq!(cb.unpop(1), true);
+        assert_eq!(cb.peek(), Some('e'));
+
+        // They can also be peeked without being put back
+        assert_eq!(cb.pop_front(), Some('e'));
+        assert_eq!(cb.behind(), 1);
+        assert_eq!(cb.peek_back(1), Some('e'));
+        assert_eq!(cb.peek_back(2), None);
+    }
+
+    #[test]
+    fn test_grow() {
+        let mut cb = RingBuffer::new(4);
+        for c in "abcd".chars() {
+            cb.push_back(c).unwrap();
+        }
+        cb.pop_front();
+        cb.pop_front();
+        cb.push_back('e').unwrap();
+
+        // The values are kept in order, the popped one too
+        cb.grow(2);
+        assert_eq!(cb.capacity(), 6);
+        assert_eq!(cb.as_slices(), (&[Some('c'), Some('d'), Some('e')][..], &[][..]));
+        assert_eq!(cb.peek_back(1), Some('b'));
+
+        cb.push_back('f').unwrap();
+        assert_eq!(cb.size(), 4);
+        assert_eq!(cb.peek_nth(3), Some('f'));
+
+        // Any value can be stored
+        let mut cb = RingBuffer::new(1);
+        cb.push_back(String::from("token")).unwrap();
+        assert_eq!(cb.pop_front().as_deref(), Some("token"));
+    }
+
+    #[test]
+    fn test_as_slices() {
+        let mut cb = RingBuffer::new(4);
+        assert_eq!(cb.as_slices(), (&[][..], &[][..]));
+
+        for c in "hey".chars() {
+            cb.push_back(c).unwrap();
+        }
+        assert_eq!(cb.as_slices(), (&[Some('h'), Some('e'), Some('y')][..], &[][..]));
+
+        // Once it wraps around, the chars are split
+        cb.pop_front();
+        cb.pop_front();
+        cb.push_back('!').unwrap();
+        cb.push_back('?').unwrap();
+        assert_eq!(cb.as_slices(), (&[Some('y'), Some('!')][..], &[Some('?')][..]));
+    }
 }
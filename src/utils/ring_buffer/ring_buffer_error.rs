@@ -4,19 +4,20 @@ use std::{
 };
 
 #[derive(Debug)]
-pub enum RingBufferError<T: Copy + Clone + Debug + Display> {
+pub enum RingBufferError<T> {
+    /// The buffer is full, the value that couldn't be pushed is given back
     NotEnoughSpace(T),
 }
 
-impl<T: Copy + Clone + Debug + Display> Display for RingBufferError<T> {
+impl<T: Debug> Display for RingBufferError<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // Only create messages when we want to print them
         match self {
-            Self::NotEnoughSpace(c) => {
-                write!(f, "Not enough space in the buffer to push the char {}", c)
+            Self::NotEnoughSpace(value) => {
+                write!(f, "Not enough space in the buffer to push the value {:?}", value)
             }
         }
     }
 }
 
-impl<T: Copy + Clone + Debug + Display> Error for RingBufferError<T> {}
+impl<T: Debug> Error for RingBufferError<T> {}
@@ -0,0 +1,31 @@
+/// Iteration over the chars of a string with their position in bytes.
+pub trait Utf8Indices {
+    /// Returns an iterator over the chars of the string, as `(char_str, byte_offset, byte_len)`.
+    ///
+    /// `char_str` is the slice of the string containing the char, so it can be printed or compared as is.
+    fn utf8_indices(&self) -> impl Iterator<Item = (&str, usize, usize)>;
+}
+
+impl Utf8Indices for str {
+    fn utf8_indices(&self) -> impl Iterator<Item = (&str, usize, usize)> {
+        self.char_indices().map(|(offset, c)| {
+            let len = c.len_utf8();
+            (&self[offset..offset + len], offset, len)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utf8_indices() {
+        let chars: Vec<_> = "aé😎".utf8_indices().collect();
+        assert_eq!(chars, vec![("a", 0, 1), ("é", 1, 2), ("😎", 3, 4)]);
+
+        // Works on Strings too
+        let s = String::from("");
+        assert_eq!(s.utf8_indices().count(), 0);
+    }
+}
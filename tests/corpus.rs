@@ -0,0 +1,67 @@
+use almora::{
+    parser_lib::{CorpusReport, FileCharReader, Location, MatchToken, StringCharReader},
+    test_grammars::{self, corpus, corpus_for, matches_fully, GRAMMARS},
+};
+
+#[test]
+fn test_corpus_with_string_reader() {
+    for name in GRAMMARS {
+        let grammar = test_grammars::grammar(name).unwrap();
+
+        for file in corpus_for(name) {
+            assert_eq!(
+                matches_fully(&grammar, file.source),
+                Ok(file.valid),
+                "{}/{}",
+                name,
+                file.name
+            );
+        }
+    }
+}
+
+#[test]
+fn test_corpus_report() {
+    // The harness reads the corpus directories, and gives the same results as the embedded files
+    for name in GRAMMARS {
+        let grammar = test_grammars::grammar(name).unwrap();
+        let report = CorpusReport::run(&grammar, format!("corpus/{}", name)).unwrap();
+
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.entries().len(), corpus_for(name).count());
+    }
+}
+
+#[test]
+fn test_corpus_with_file_reader() {
+    // The file reader should give the exact same results as the string reader
+    for file in corpus() {
+        let path = format!(
+            "corpus/{}/{}/{}",
+            file.grammar,
+            if file.valid { "valid" } else { "invalid" },
+            file.name
+        );
+        // Nothing is consumed, so the buffer must be able to hold the whole file
+        let direct = FileCharReader::new(&path, 4096).unwrap();
+        let prefetched = FileCharReader::with_prefetch(&path, 4096, 64).unwrap();
+
+        let mut string_reader = StringCharReader::new(file.source);
+        let loc = Location::beginning();
+        let expected = test_grammars::grammar(file.grammar)
+            .unwrap()
+            .test(&loc, &mut string_reader);
+
+        for mut reader in [direct, prefetched] {
+            let result = match file.grammar {
+                "calculator" => test_grammars::calculator::define_grammar().test(&loc, &mut reader),
+                "ini" => test_grammars::ini::define_grammar().test(&loc, &mut reader),
+                "json" => test_grammars::json::define_grammar().test(&loc, &mut reader),
+                "mini" => test_grammars::mini::define_grammar().test(&loc, &mut reader),
+                _ => unreachable!(),
+            };
+
+            assert_eq!(result, expected, "{}", path);
+        }
+    }
+}